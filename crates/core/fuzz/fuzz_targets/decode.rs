@@ -0,0 +1,16 @@
+//! Opcode decoder and disassembler over arbitrary instruction words.
+#![no_main]
+
+use arduboy_core::{disasm, opcodes};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for w in data.chunks(6) {
+        if w.len() < 6 { break; }
+        let op = u16::from_le_bytes([w[0], w[1]]);
+        let next = u16::from_le_bytes([w[2], w[3]]);
+        let pc = u16::from_le_bytes([w[4], w[5]]) as u32 * 4;
+        let (inst, _) = opcodes::decode(op, next);
+        let _ = disasm::disassemble(inst, pc);
+    }
+});
@@ -0,0 +1,18 @@
+//! File parsers: HEX, ELF, `.arduboy`, IPS/BPS patches and save states.
+#![no_main]
+
+use arduboy_core::{arduboy_file, elf, hex, romhack, savestate, Arduboy};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut flash = vec![0u8; 32768];
+    let _ = hex::parse_hex(&String::from_utf8_lossy(data), &mut flash);
+    let _ = elf::parse_elf(data);
+    let _ = arduboy_file::parse_arduboy(data);
+    let _ = romhack::apply(&flash, data);
+    if let Ok(state) = savestate::decode(data, 0) {
+        let mut a = Arduboy::new();
+        a.load_full_state(&state);
+        a.run_frame();
+    }
+});
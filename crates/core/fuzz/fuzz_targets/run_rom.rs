@@ -0,0 +1,19 @@
+//! Arbitrary flash images executed for a few frames on every CPU type.
+#![no_main]
+
+use arduboy_core::{Arduboy, CpuType};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for cpu in [CpuType::Atmega32u4, CpuType::Atmega328p, CpuType::Atmega2560] {
+        let mut a = Arduboy::new_with_cpu(cpu);
+        let len = data.len().min(a.mem.flash.len());
+        a.mem.flash[..len].copy_from_slice(&data[..len]);
+        a.program_size = len;
+        a.reset();
+        for _ in 0..2 {
+            a.run_frame();
+        }
+        let _ = a.step_one();
+    }
+});
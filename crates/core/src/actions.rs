@@ -0,0 +1,261 @@
+//! Frontend hotkeys as named actions with rebindable keys.
+//!
+//! Every [`Action`] (fullscreen, mute, quick save, ...) has a name and a
+//! list of bindings. [`Actions::update`] is called once per host frame with
+//! a callback that tells whether a key is down, and afterwards
+//! [`Actions::pressed`] reports the actions whose key went down in this
+//! update and [`Actions::held`] those whose key is down. The callback
+//! translates key names to the frontend's own key type, so minifb, egui or
+//! a terminal frontend share the bindings and the edge detection.
+//!
+//! | Key names | |
+//! |-----------|---|
+//! | `A`-`Z`, `0`-`9`, `F1`-`F12` | Letters, digits and function keys |
+//! | `Tab`, `Enter`, `Backspace`, `Space`, `Escape`, `Left`, `Right`, `Up`, `Down` | Named keys |
+//! | `Pad.<button>` | Gamepad buttons: `South`, `East`, `North`, `West`, `Start`, `Select`, `Mode`, `LeftTrigger`, `RightTrigger`, `LeftTrigger2`, `RightTrigger2`, `LeftThumb`, `RightThumb` |
+//!
+//! A `Shift+` prefix makes a binding need Shift (the callback is asked for
+//! `Shift`). A binding without it does not fire while Shift is down when
+//! the same key also has a `Shift+` binding, so `M` and `Shift+M` can mean
+//! different things. Bindings are changed with [`Actions::bind`] from
+//! `action=key,key` specs (`--bind mute=F8,Pad.Select`).
+
+/// Something the user can ask the frontend to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Window scale 1×-6×
+    Scale(u8),
+    Fullscreen,
+    /// Run unthrottled
+    Unlimited,
+    Blur,
+    LcdEffect,
+    Profiler,
+    Mute,
+    AudioDevice,
+    AudioFilter,
+    Speaker,
+    Rotate,
+    Screenshot,
+    GifRecord,
+    InputDisplay,
+    SramMap,
+    /// Hold to rewind
+    Rewind,
+    Timeline,
+    TimelineBack,
+    TimelineForward,
+    TimelineResume,
+    Reload,
+    ListGames,
+    NextGame,
+    PrevGame,
+    DumpRegs,
+    QuickSave,
+    QuickLoad,
+    BugReport,
+}
+
+/// Every action with its name and default keys.
+const DEFAULTS: &[(Action, &str, &[&str])] = &[
+    (Action::Scale(1), "scale1", &["1"]),
+    (Action::Scale(2), "scale2", &["2"]),
+    (Action::Scale(3), "scale3", &["3"]),
+    (Action::Scale(4), "scale4", &["4"]),
+    (Action::Scale(5), "scale5", &["5"]),
+    (Action::Scale(6), "scale6", &["6"]),
+    (Action::Fullscreen, "fullscreen", &["F11"]),
+    (Action::Unlimited, "unlimited", &["F"]),
+    (Action::Blur, "blur", &["B"]),
+    (Action::LcdEffect, "lcd", &["L"]),
+    (Action::Profiler, "profiler", &["T"]),
+    (Action::Mute, "mute", &["M"]),
+    (Action::AudioDevice, "audio-device", &["Shift+M"]),
+    (Action::AudioFilter, "audio-filter", &["A"]),
+    (Action::Speaker, "speaker", &["Shift+A"]),
+    (Action::Rotate, "rotate", &["V"]),
+    (Action::Screenshot, "screenshot", &["S"]),
+    (Action::GifRecord, "gif", &["G"]),
+    (Action::InputDisplay, "inputs", &["I"]),
+    (Action::SramMap, "sram-map", &["H"]),
+    (Action::Rewind, "rewind", &["Backspace"]),
+    (Action::Timeline, "timeline", &["Tab"]),
+    (Action::TimelineBack, "timeline-back", &["Left"]),
+    (Action::TimelineForward, "timeline-forward", &["Right"]),
+    (Action::TimelineResume, "timeline-resume", &["Enter"]),
+    (Action::Reload, "reload", &["R"]),
+    (Action::ListGames, "games", &["O"]),
+    (Action::NextGame, "next-game", &["N"]),
+    (Action::PrevGame, "prev-game", &["P"]),
+    (Action::DumpRegs, "regs", &["D"]),
+    (Action::QuickSave, "save-state", &["F5"]),
+    (Action::QuickLoad, "load-state", &["F9"]),
+    (Action::BugReport, "bug-report", &["F12"]),
+];
+
+const NAMED_KEYS: &[&str] = &["Tab", "Enter", "Backspace", "Space", "Escape", "Left", "Right", "Up", "Down"];
+const PAD_BUTTONS: &[&str] = &[
+    "South", "East", "North", "West", "Start", "Select", "Mode", "LeftTrigger", "RightTrigger",
+    "LeftTrigger2", "RightTrigger2", "LeftThumb", "RightThumb",
+];
+
+impl Action {
+    /// Name used in `--bind` specs.
+    pub fn name(self) -> &'static str {
+        DEFAULTS.iter().find(|d| d.0 == self).map_or("?", |d| d.1)
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        DEFAULTS.iter().find(|d| d.1 == name).map(|d| d.0)
+    }
+}
+
+/// A key, gamepad button or Shift combination bound to an action.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub key: String,
+    pub shift: bool,
+}
+
+impl Binding {
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (shift, key) = match spec.strip_prefix("Shift+") {
+            Some(k) => (true, k),
+            None => (false, spec),
+        };
+        let known = match key.strip_prefix("Pad.") {
+            Some(b) => PAD_BUTTONS.contains(&b),
+            None => {
+                let fkey = key.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()).is_some_and(|n| (1..=12).contains(&n));
+                (key.len() == 1 && key.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()))
+                    || fkey || NAMED_KEYS.contains(&key)
+            }
+        };
+        if !known {
+            return Err(format!("unknown key '{}'", spec));
+        }
+        Ok(Binding { key: key.to_string(), shift })
+    }
+}
+
+/// Bindings and key state; see the module docs.
+#[derive(Debug, Clone)]
+pub struct Actions {
+    bindings: Vec<(Action, Binding)>,
+    /// Key of each binding down at the last update
+    down: Vec<bool>,
+    pressed: Vec<Action>,
+    held: Vec<Action>,
+}
+
+impl Actions {
+    /// The default bindings.
+    pub fn new() -> Self {
+        let bindings: Vec<(Action, Binding)> = DEFAULTS.iter()
+            .flat_map(|&(a, _, keys)| keys.iter().map(move |k| (a, Binding::parse(k).expect("default key"))))
+            .collect();
+        let down = vec![false; bindings.len()];
+        Actions { bindings, down, pressed: Vec::new(), held: Vec::new() }
+    }
+
+    /// Replace the bindings of one action from `action=key,key` (an empty
+    /// list unbinds it).
+    pub fn bind(&mut self, spec: &str) -> Result<(), String> {
+        let (name, keys) = spec.split_once('=').ok_or_else(|| format!("'{}': expected action=key,...", spec))?;
+        let action = Action::from_name(name.trim()).ok_or_else(|| format!("unknown action '{}'", name.trim()))?;
+        let keys = keys.split(',').map(str::trim).filter(|k| !k.is_empty())
+            .map(Binding::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.bindings.retain(|(a, _)| *a != action);
+        self.bindings.extend(keys.into_iter().map(|b| (action, b)));
+        self.down = vec![false; self.bindings.len()];
+        Ok(())
+    }
+
+    /// Keys bound to `action`, as written in specs.
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        self.bindings.iter()
+            .filter(|(a, _)| *a == action)
+            .map(|(_, b)| if b.shift { format!("Shift+{}", b.key) } else { b.key.clone() })
+            .collect()
+    }
+
+    /// Sample the keys; `is_down` answers for a key name or `Shift`.
+    pub fn update(&mut self, mut is_down: impl FnMut(&str) -> bool) {
+        let shift = is_down("Shift");
+        self.pressed.clear();
+        self.held.clear();
+        for (i, (action, b)) in self.bindings.iter().enumerate() {
+            let key_down = is_down(&b.key);
+            let mods = if b.shift {
+                shift
+            } else {
+                !shift || !self.bindings.iter().any(|(_, o)| o.shift && o.key == b.key)
+            };
+            if key_down && mods {
+                self.held.push(*action);
+                if !self.down[i] {
+                    self.pressed.push(*action);
+                }
+            }
+            self.down[i] = key_down;
+        }
+    }
+
+    /// Whether a key of `action` went down in the last update.
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    /// Whether a key of `action` is down.
+    pub fn held(&self, action: Action) -> bool {
+        self.held.contains(&action)
+    }
+}
+
+impl Default for Actions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edges_and_shift() {
+        let mut a = Actions::new();
+        let mut keys: Vec<&str> = vec!["M"];
+        a.update(|k| keys.contains(&k));
+        assert!(a.pressed(Action::Mute) && !a.pressed(Action::AudioDevice));
+        a.update(|k| keys.contains(&k));
+        assert!(!a.pressed(Action::Mute), "held, not pressed again");
+        keys = vec!["Shift", "M"];
+        a.update(|k| keys.contains(&k));
+        assert!(!a.pressed(Action::AudioDevice), "M was already down");
+        keys = vec!["Shift"];
+        a.update(|k| keys.contains(&k));
+        keys = vec!["Shift", "M", "B", "Backspace"];
+        a.update(|k| keys.contains(&k));
+        assert!(a.pressed(Action::AudioDevice) && !a.pressed(Action::Mute));
+        assert!(a.pressed(Action::Blur), "no Shift+B binding: Shift is ignored");
+        assert!(a.held(Action::Rewind));
+    }
+
+    #[test]
+    fn test_bind() {
+        let mut a = Actions::new();
+        a.bind("mute=F8, Pad.Select").unwrap();
+        assert_eq!(a.keys(Action::Mute), vec!["F8", "Pad.Select"]);
+        a.update(|k| k == "M");
+        assert!(!a.pressed(Action::Mute));
+        a.update(|k| k == "Pad.Select");
+        assert!(a.pressed(Action::Mute));
+        a.bind("blur=").unwrap();
+        assert!(a.keys(Action::Blur).is_empty());
+        assert_eq!(Action::from_name("scale3"), Some(Action::Scale(3)));
+        assert!(a.bind("mute=F13").unwrap_err().contains("unknown key"));
+        assert!(a.bind("warp=F1").unwrap_err().contains("unknown action"));
+    }
+}
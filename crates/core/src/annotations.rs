@@ -0,0 +1,214 @@
+//! User symbol and comment files for games without ELF debug info.
+//!
+//! A HEX-only game has no symbol table, so the disassembler, profiler and
+//! debugger show raw addresses. An annotation file names addresses and
+//! attaches comments by hand, and is kept next to the game as `<game>.sym`:
+//!
+//! ```text
+//! # Flash addresses are byte addresses, as the disassembler shows them
+//! 0x0A3C drawPlayer 96       ; size is optional
+//! 0x0B20 -                   ; "-" adds a comment without a name
+//! ram:0x0110 player_x 2
+//! ```
+//!
+//! | Field | Form |
+//! |-------|------|
+//! | address | `0x…` flash byte address; `ram:0x…` (or `0x800000`+) for data |
+//! | name | Symbol name without spaces, or `-` |
+//! | size | Byte count (decimal or `0x…`), optional |
+//! | comment | Everything after `;` |
+//!
+//! [`Annotations::apply`] merges the entries into an [`ElfFile`], where they
+//! replace ELF names at the same address, and [`Annotations::to_elf`] builds
+//! one for HEX games, so everything that takes ELF symbols (disassembly,
+//! profiler reports, `describe_pc`, debugger expressions) uses them.
+//! Frontends that let the user add names write the file back with
+//! [`Annotations::save`].
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::elf::{ElfFile, DATA_OFFSET};
+use crate::error::Error;
+
+/// Name, size and comment for one address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotation {
+    pub name: Option<String>,
+    /// Bytes covered (0 = up to the next symbol)
+    pub size: u32,
+    pub comment: Option<String>,
+}
+
+/// Annotations by byte address (data at 0x800000+).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations {
+    pub entries: BTreeMap<u32, Annotation>,
+}
+
+fn parse_num(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(h) => u32::from_str_radix(h, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parse `0x…`, `ram:0x…` or a plain number into a stored address.
+pub fn parse_address(s: &str) -> Result<u32, String> {
+    let (ram, num) = match s.strip_prefix("ram:") {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let addr = parse_num(num).ok_or_else(|| format!("bad address '{}'", s))?;
+    Ok(if ram && addr < DATA_OFFSET { addr + DATA_OFFSET } else { addr })
+}
+
+impl Annotations {
+    /// The annotation file kept next to `game` (`<game>.sym`).
+    pub fn path_for(game: &Path) -> PathBuf {
+        game.with_extension("sym")
+    }
+
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut out = Annotations::default();
+        for (n, line) in text.lines().enumerate() {
+            let (fields, comment) = match line.split_once(';') {
+                Some((f, c)) => (f, Some(c.trim().to_string()).filter(|c| !c.is_empty())),
+                None => (line, None),
+            };
+            let fields = fields.split('#').next().unwrap_or("");
+            let parts: Vec<&str> = fields.split_whitespace().collect();
+            if parts.is_empty() {
+                if comment.is_some() {
+                    return Err(Error::Symbols { line: n + 1, detail: "comment without an address".into() });
+                }
+                continue;
+            }
+            let err = |detail: String| Error::Symbols { line: n + 1, detail };
+            let addr = parse_address(parts[0]).map_err(err)?;
+            let name = parts.get(1).filter(|&&s| s != "-").map(|s| s.to_string());
+            let size = match parts.get(2) {
+                Some(s) => parse_num(s).ok_or_else(|| err(format!("bad size '{}'", s)))?,
+                None => 0,
+            };
+            if parts.len() > 3 {
+                return Err(err(format!("unexpected '{}' (comments start with ';')", parts[3])));
+            }
+            out.entries.insert(addr, Annotation { name, size, comment });
+        }
+        Ok(out)
+    }
+
+    /// File form, one entry per line in address order.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for (&addr, a) in &self.entries {
+            let mut line = if addr >= DATA_OFFSET {
+                format!("ram:0x{:04X}", addr - DATA_OFFSET)
+            } else {
+                format!("0x{:04X}", addr)
+            };
+            line.push(' ');
+            line.push_str(a.name.as_deref().unwrap_or("-"));
+            if a.size > 0 {
+                line.push_str(&format!(" {}", a.size));
+            }
+            if let Some(c) = &a.comment {
+                line.push_str(&format!(" ; {}", c));
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path).map_err(|source| Error::Io { op: "Read", source })?;
+        Self::parse(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        std::fs::write(path, self.to_text()).map_err(|source| Error::Io { op: "Write", source })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Name `addr` (None removes the name).
+    pub fn set_name(&mut self, addr: u32, name: Option<&str>) {
+        self.entries.entry(addr).or_default().name = name.map(str::to_string);
+        self.prune(addr);
+    }
+
+    /// Comment on `addr` (None removes the comment).
+    pub fn set_comment(&mut self, addr: u32, comment: Option<&str>) {
+        self.entries.entry(addr).or_default().comment = comment.map(str::to_string);
+        self.prune(addr);
+    }
+
+    fn prune(&mut self, addr: u32) {
+        if self.entries.get(&addr).is_some_and(|a| *a == Annotation::default()) {
+            self.entries.remove(&addr);
+        }
+    }
+
+    /// Add the names, sizes and comments to `elf`.
+    pub fn apply(&self, elf: &mut ElfFile) {
+        for (&addr, a) in &self.entries {
+            if let Some(name) = &a.name {
+                elf.symbols.insert(addr, name.clone());
+            }
+            if a.size > 0 {
+                elf.sizes.insert(addr, a.size);
+            }
+            if let Some(c) = &a.comment {
+                elf.comments.insert(addr, c.clone());
+            }
+        }
+        elf.reindex();
+    }
+
+    /// Symbol tables holding only these annotations, for HEX games.
+    pub fn to_elf(&self) -> ElfFile {
+        let mut elf = ElfFile::default();
+        self.apply(&mut elf);
+        elf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_round_trip_and_apply() {
+        let text = "# player code\n\
+                    0x0A3C drawPlayer 96 ; draws the sprite\n\
+                    0x0B20 - ; loop starts here\n\
+                    \n\
+                    ram:0x0110 player_x 2\n";
+        let mut ann = Annotations::parse(text).unwrap();
+        assert_eq!(ann.len(), 3);
+        assert_eq!(ann.entries[&0x800110].name.as_deref(), Some("player_x"));
+        assert_eq!(Annotations::parse(&ann.to_text()).unwrap(), ann);
+        assert!(matches!(Annotations::parse("0x10 a 4 extra"), Err(Error::Symbols { line: 1, .. })));
+        assert!(Annotations::parse("zz name").is_err());
+        assert!(matches!(Annotations::load(Path::new("/nonexistent/game.sym")), Err(Error::Io { .. })));
+
+        ann.set_name(0x0C00, Some("update"));
+        ann.set_comment(0x0B20, None);
+        assert_eq!(ann.len(), 3);
+
+        let elf = ann.to_elf();
+        assert_eq!(elf.find_function(0x0A40), Some(("drawPlayer", 4)));
+        assert_eq!(elf.describe_pc(0x0A3C / 2), "<drawPlayer> ; draws the sprite");
+        assert_eq!(elf.symbol_range(0x0A3C), (0x0A3C, 0x0A3C + 96));
+        assert_eq!(elf.find_symbols("player_x"), vec![0x800110]);
+        assert_eq!(elf.find_function(0x0C10), Some(("update", 16)));
+    }
+}
@@ -1,13 +1,15 @@
 //! `.arduboy` file parser.
 //!
 //! An `.arduboy` file is a ZIP archive containing:
-//! - `info.json` — metadata (title, author, description)
+//! - `info.json` — metadata (title, author, description, screen rotation)
 //! - `*.hex` — Intel HEX game binary
 //! - `*-fx.bin` or `*.bin` — optional FX flash data
 //!
 //! This module provides a minimal ZIP reader (stored + deflate via miniz_oxide)
 //! to extract these files.
 
+use crate::error::{ArchiveError, Error};
+use crate::rotation::Rotation;
 use std::collections::HashMap;
 
 /// Parsed contents of an .arduboy file.
@@ -23,12 +25,15 @@ pub struct ArduboyFile {
     pub fx_data: Option<Vec<u8>>,
     /// FX flash save data.
     pub fx_save: Option<Vec<u8>>,
+    /// Screen rotation from info.json `rotation` (degrees clockwise, as a
+    /// number or string); unrotated if absent or not a multiple of 90.
+    pub rotation: Rotation,
     /// All files in the archive: name → data.
     pub files: HashMap<String, Vec<u8>>,
 }
 
 /// Parse a .arduboy (ZIP) file from raw bytes.
-pub fn parse_arduboy(data: &[u8]) -> Result<ArduboyFile, String> {
+pub fn parse_arduboy(data: &[u8]) -> Result<ArduboyFile, Error> {
     let files = read_zip(data)?;
     let mut result = ArduboyFile::default();
     result.files = files.clone();
@@ -50,6 +55,9 @@ pub fn parse_arduboy(data: &[u8]) -> Result<ArduboyFile, String> {
         result.author = extract_json_string(&info_str, "author")
             .or_else(|| extract_json_string(&info_str, "developer"))
             .unwrap_or_default();
+        result.rotation = extract_json_number(&info_str, "rotation")
+            .and_then(Rotation::from_degrees)
+            .unwrap_or_default();
 
         // Use info.json field names to find exact files
         if let Some(data_name) = extract_json_string(&info_str, "flashdata") {
@@ -105,7 +113,7 @@ pub fn parse_arduboy(data: &[u8]) -> Result<ArduboyFile, String> {
     }
 
     if result.hex.is_none() {
-        return Err("No .hex file found in .arduboy archive".into());
+        return Err(ArchiveError::NoHex.into());
     }
 
     Ok(result)
@@ -125,17 +133,33 @@ fn extract_json_string(json: &str, key: &str) -> Option<String> {
     Some(rest[..end].to_string())
 }
 
+/// JSON number value, also accepted as a string (`90` or `"90"`).
+fn extract_json_number(json: &str, key: &str) -> Option<i32> {
+    if let Some(s) = extract_json_string(json, key) {
+        return s.trim().parse().ok();
+    }
+    let pattern = format!("\"{}\"", key);
+    let idx = json.find(&pattern)?;
+    let rest = json[idx + pattern.len()..].trim_start().strip_prefix(':')?.trim_start();
+    let end = rest.find(|c: char| !(c == '-' || c.is_ascii_digit())).unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
 // ─── Minimal ZIP Reader ─────────────────────────────────────────────────────
 
-fn read_zip(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, String> {
+/// Largest inflated entry accepted: the 16 MB FX chip plus headroom, so a
+/// crafted archive cannot exhaust memory.
+const MAX_ENTRY_SIZE: usize = 32 << 20;
+
+fn read_zip(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, ArchiveError> {
     let mut files = HashMap::new();
 
     // Find End of Central Directory record (scan backwards)
     let eocd_sig: [u8; 4] = [0x50, 0x4B, 0x05, 0x06];
     let eocd_pos = data.windows(4).rposition(|w| w == eocd_sig)
-        .ok_or("No End of Central Directory found — not a valid ZIP")?;
+        .ok_or(ArchiveError::NotZip)?;
 
-    if eocd_pos + 22 > data.len() { return Err("EOCD truncated".into()); }
+    if eocd_pos + 22 > data.len() { return Err(ArchiveError::Truncated); }
     let cd_count = u16_le(data, eocd_pos + 10) as usize;
     let cd_offset = u32_le(data, eocd_pos + 16) as usize;
 
@@ -176,8 +200,8 @@ fn read_zip(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, String> {
         let file_data = match method {
             0 => compressed.to_vec(),
             8 => {
-                miniz_oxide::inflate::decompress_to_vec(compressed)
-                    .map_err(|e| format!("Inflate error for {}: {:?}", name, e))?
+                miniz_oxide::inflate::decompress_to_vec_with_limit(compressed, MAX_ENTRY_SIZE)
+                    .map_err(|e| ArchiveError::Inflate { name: name.clone(), detail: format!("{:?}", e) })?
             }
             _ => continue,
         };
@@ -191,7 +215,7 @@ fn read_zip(data: &[u8]) -> Result<HashMap<String, Vec<u8>>, String> {
     }
 
     if files.is_empty() {
-        return Err("No files found in ZIP archive".into());
+        return Err(ArchiveError::Empty);
     }
     Ok(files)
 }
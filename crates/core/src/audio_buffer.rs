@@ -11,9 +11,10 @@
 //!    each sample period instead of snapping to the nearest edge. Eliminates
 //!    aliasing artifacts from sub-sample transitions.
 //!
-//! 2. **Low-pass filter** — 2nd-order Butterworth at 8 kHz simulates the
-//!    bandwidth limitation of the Arduboy's piezo speaker, rounding off harsh
-//!    upper harmonics from the raw square wave.
+//! 2. **Speaker model** — the frequency response of the selected
+//!    [`SpeakerModel`]. The default is a 2nd-order Butterworth at 8 kHz that
+//!    rounds off harsh upper harmonics from the raw square wave; `Piezo`
+//!    adds the real buzzer's resonant peak near 4 kHz and its weak bass.
 //!
 //! 3. **DC-blocking high-pass** — 2nd-order Butterworth at 20 Hz removes any
 //!    DC offset that accumulates through the LPF.
@@ -29,8 +30,19 @@ use std::f32::consts::{PI, SQRT_2};
 
 // ─── Constants ──────────────────────────────────────────────────────────────
 
-/// Low-pass filter cutoff (Hz). Models piezo speaker bandwidth rolloff.
+/// Low-pass filter cutoff (Hz) of the default speaker model.
 const LPF_CUTOFF: f32 = 8000.0;
+/// Piezo model: resonance (Hz), its Q and peak gain (dB).
+const PIEZO_RESONANCE: f32 = 4000.0;
+const PIEZO_Q: f32 = 2.0;
+const PIEZO_PEAK_DB: f32 = 9.0;
+/// Piezo model: roll-off below (Hz) and above (Hz) the resonance.
+const PIEZO_LOW_CUT: f32 = 600.0;
+const PIEZO_HIGH_CUT: f32 = 9000.0;
+/// Piezo model: output gain keeping the resonant peak from clipping.
+const PIEZO_GAIN: f32 = 0.5;
+/// Headphone model: only the content above hearing range is removed (Hz).
+const HEADPHONE_CUTOFF: f32 = 16000.0;
 /// DC-blocking high-pass cutoff (Hz). Removes sub-audible drift.
 const HPF_CUTOFF: f32 = 20.0;
 /// Default crossfeed: 20% of opposite channel mixed in.
@@ -123,6 +135,24 @@ impl Biquad {
         }
     }
 
+    /// Peaking EQ (RBJ cookbook): `gain_db` at `freq`, width set by `q`.
+    fn peaking(freq: f32, q: f32, gain_db: f32, sample_rate: f32) -> Self {
+        let a = 10f32.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * freq / sample_rate;
+        let (sin_w, cos_w) = (w0.sin(), w0.cos());
+        let alpha = sin_w / (2.0 * q);
+        let a0_inv = 1.0 / (1.0 + alpha / a);
+        Biquad {
+            b0: (1.0 + alpha * a) * a0_inv,
+            b1: (-2.0 * cos_w) * a0_inv,
+            b2: (1.0 - alpha * a) * a0_inv,
+            a1: (-2.0 * cos_w) * a0_inv,
+            a2: (1.0 - alpha / a) * a0_inv,
+            z1: 0.0,
+            z2: 0.0,
+        }
+    }
+
     /// 2nd-order Butterworth high-pass filter.
     fn highpass(cutoff: f32, sample_rate: f32) -> Self {
         let w0 = 2.0 * PI * cutoff / sample_rate;
@@ -150,6 +180,92 @@ impl Biquad {
     }
 }
 
+// ─── Speaker models ─────────────────────────────────────────────────────────
+
+/// Frequency response of the speaker the output is shaped like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpeakerModel {
+    /// Unfiltered square wave (only DC blocking and click suppression)
+    Ideal,
+    /// 8 kHz Butterworth roll-off (the long-standing default)
+    #[default]
+    Soft,
+    /// Arduboy piezo buzzer: resonant peak near 4 kHz, thin below 600 Hz,
+    /// rolling off above 9 kHz
+    Piezo,
+    /// Flat headphones: only content above 16 kHz removed
+    Headphone,
+}
+
+impl SpeakerModel {
+    /// Every model, in the order frontends cycle through them.
+    pub const ALL: [SpeakerModel; 4] =
+        [SpeakerModel::Soft, SpeakerModel::Piezo, SpeakerModel::Headphone, SpeakerModel::Ideal];
+
+    /// Parse `ideal`, `soft`, `piezo` or `headphone`.
+    pub fn parse(s: &str) -> Result<SpeakerModel, String> {
+        match s.to_ascii_lowercase().as_str() {
+            "ideal" | "square" => Ok(SpeakerModel::Ideal),
+            "soft" | "default" => Ok(SpeakerModel::Soft),
+            "piezo" => Ok(SpeakerModel::Piezo),
+            "headphone" | "headphones" | "flat" => Ok(SpeakerModel::Headphone),
+            _ => Err(format!("speaker: expected ideal, soft, piezo or headphone, got '{}'", s)),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SpeakerModel::Ideal => "ideal",
+            SpeakerModel::Soft => "soft",
+            SpeakerModel::Piezo => "piezo",
+            SpeakerModel::Headphone => "headphone",
+        }
+    }
+
+    /// The model after this one in [`ALL`](Self::ALL).
+    pub fn next(self) -> SpeakerModel {
+        let i = Self::ALL.iter().position(|&m| m == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// Filter stages and output gain of this model.
+    fn chain(self, sample_rate: f32) -> (Vec<Biquad>, f32) {
+        match self {
+            SpeakerModel::Ideal => (Vec::new(), 1.0),
+            SpeakerModel::Soft => (vec![Biquad::lowpass(LPF_CUTOFF, sample_rate)], 1.0),
+            SpeakerModel::Piezo => (vec![
+                Biquad::highpass(PIEZO_LOW_CUT, sample_rate),
+                Biquad::peaking(PIEZO_RESONANCE, PIEZO_Q, PIEZO_PEAK_DB, sample_rate),
+                Biquad::lowpass(PIEZO_HIGH_CUT, sample_rate),
+            ], PIEZO_GAIN),
+            SpeakerModel::Headphone => {
+                // Above Nyquist at low rates: nothing left to remove
+                let cutoff = HEADPHONE_CUTOFF.min(sample_rate * 0.45);
+                (vec![Biquad::lowpass(cutoff, sample_rate)], 1.0)
+            }
+        }
+    }
+}
+
+/// One channel's speaker filter stages.
+#[derive(Debug, Clone)]
+struct SpeakerChain {
+    stages: Vec<Biquad>,
+    gain: f32,
+}
+
+impl SpeakerChain {
+    fn new(model: SpeakerModel, sample_rate: f32) -> Self {
+        let (stages, gain) = model.chain(sample_rate);
+        SpeakerChain { stages, gain }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f32) -> f32 {
+        self.stages.iter_mut().fold(x, |y, b| b.process(y)) * self.gain
+    }
+}
+
 // ─── Audio buffer with post-processing ──────────────────────────────────────
 
 /// Stereo audio buffer with optional post-processing pipeline.
@@ -175,8 +291,9 @@ pub struct AudioBuffer {
     pwm_level: f32,
 
     // ── Post-processing state (persists across frames) ──
-    lpf_l: Biquad,
-    lpf_r: Biquad,
+    speaker_l: SpeakerChain,
+    speaker_r: SpeakerChain,
+    speaker: SpeakerModel,
     hpf_l: Biquad,
     hpf_r: Biquad,
     envelope_l: f32,
@@ -200,8 +317,9 @@ impl AudioBuffer {
             frame_end: 0,
             pwm_samples: Vec::with_capacity(4096),
             pwm_level: 0.0,
-            lpf_l: Biquad::lowpass(LPF_CUTOFF, sr),
-            lpf_r: Biquad::lowpass(LPF_CUTOFF, sr),
+            speaker_l: SpeakerChain::new(SpeakerModel::Soft, sr),
+            speaker_r: SpeakerChain::new(SpeakerModel::Soft, sr),
+            speaker: SpeakerModel::Soft,
             hpf_l: Biquad::highpass(HPF_CUTOFF, sr),
             hpf_r: Biquad::highpass(HPF_CUTOFF, sr),
             envelope_l: 0.0,
@@ -215,8 +333,8 @@ impl AudioBuffer {
     /// Recalculate filter coefficients for a new sample rate.
     fn configure_filters(&mut self, sample_rate: u32) {
         let sr = sample_rate as f32;
-        self.lpf_l = Biquad::lowpass(LPF_CUTOFF, sr);
-        self.lpf_r = Biquad::lowpass(LPF_CUTOFF, sr);
+        self.speaker_l = SpeakerChain::new(self.speaker, sr);
+        self.speaker_r = SpeakerChain::new(self.speaker, sr);
         self.hpf_l = Biquad::highpass(HPF_CUTOFF, sr);
         self.hpf_r = Biquad::highpass(HPF_CUTOFF, sr);
         self.configured_rate = sample_rate;
@@ -258,6 +376,17 @@ impl AudioBuffer {
         self.pwm_samples.push((tick, level));
     }
 
+    /// Speaker model shaping the output (post-processing stage 2).
+    pub fn speaker(&self) -> SpeakerModel {
+        self.speaker
+    }
+
+    /// Select the speaker model; filters are rebuilt at the next render.
+    pub fn set_speaker(&mut self, model: SpeakerModel) {
+        self.speaker = model;
+        self.configured_rate = 0;
+    }
+
     /// Toggle the post-processing filter pipeline on/off.
     pub fn toggle_filters(&mut self) {
         self.filters_enabled = !self.filters_enabled;
@@ -363,9 +492,9 @@ impl AudioBuffer {
                 let l_env = l_raw * self.envelope_l;
                 let r_env = r_raw * self.envelope_r;
 
-                // (2) Low-pass filter (speaker bandwidth simulation)
-                let l_lp = self.lpf_l.process(l_env);
-                let r_lp = self.lpf_r.process(r_env);
+                // (2) Speaker frequency response
+                let l_lp = self.speaker_l.process(l_env);
+                let r_lp = self.speaker_r.process(r_env);
 
                 // (3) DC-blocking high-pass
                 let l_hp = self.hpf_l.process(l_lp);
@@ -463,3 +592,34 @@ impl AudioBuffer {
         (accum / tps) as f32 * volume
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Steady-state peak of the chain's response to a sine at `freq`.
+    fn response(model: SpeakerModel, freq: f32) -> f32 {
+        let sr = 44100.0;
+        let mut chain = SpeakerChain::new(model, sr);
+        let mut peak = 0.0f32;
+        for i in 0..8820 {
+            let y = chain.process((2.0 * PI * freq * i as f32 / sr).sin());
+            if i >= 4410 { peak = peak.max(y.abs()); }
+        }
+        peak
+    }
+
+    #[test]
+    fn test_speaker_models() {
+        assert!((response(SpeakerModel::Ideal, 1000.0) - 1.0).abs() < 0.01);
+        assert!(response(SpeakerModel::Soft, 12000.0) < 0.5);
+        // Piezo: resonance well above both the bass and the top end
+        let peak = response(SpeakerModel::Piezo, 4000.0);
+        assert!(peak > 2.0 * response(SpeakerModel::Piezo, 400.0));
+        assert!(peak > 2.0 * response(SpeakerModel::Piezo, 14000.0));
+        assert!(response(SpeakerModel::Headphone, 10000.0) > 0.9);
+        assert_eq!(SpeakerModel::parse("Piezo"), Ok(SpeakerModel::Piezo));
+        assert_eq!(SpeakerModel::Ideal.next(), SpeakerModel::Soft);
+        assert!(SpeakerModel::parse("tweeter").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! GPIO speaker routing for bit-banged audio.
+//!
+//! Games that toggle a speaker pin with `digitalWrite` (instead of a timer
+//! compare output) are heard through edge detection on PORTx writes. The
+//! [`AudioRouter`] owns that logic: it maps one pin to each stereo channel,
+//! records every edge into the sample-accurate [`AudioBuffer`] and measures
+//! the toggle half-period for the tone-frequency readout.
+//!
+//! Default mappings:
+//!
+//! | Board                      | Left  | Right |
+//! |----------------------------|-------|-------|
+//! | Arduboy (ATmega32u4)       | `PC6` | `PB5` |
+//! | Gamebuino Classic (328P)   | `PD3` | —     |
+//! | Arduino Mega (2560)        | —     | —     |
+//!
+//! Homemade units with the speaker elsewhere can remap the channels with
+//! [`AudioRouter::set_pins`] (e.g. `--speaker-pins PB6,PB7`) or through the
+//! `speaker` entry of a [`BoardProfile`](crate::board::BoardProfile).
+
+use crate::board::GpioPin;
+use crate::{AudioBuffer, CpuType};
+
+/// Shortest half-period accepted as a tone (16 MHz / (2 × 20 kHz))
+const MIN_HALF_PERIOD: u64 = 400;
+/// Longest half-period accepted as a tone (~30 Hz)
+const MAX_HALF_PERIOD: u64 = 270_000;
+/// A tone is reported silent this many ticks after its last valid edge
+const TONE_TIMEOUT: u64 = 250_000;
+
+/// Edge-tracking state for one speaker channel.
+#[derive(Debug, Clone, Default)]
+pub struct SpeakerChannel {
+    /// Pin routed to this channel (None = channel unused)
+    pub pin: Option<GpioPin>,
+    /// Previous pin level for edge detection
+    pub(crate) prev: bool,
+    /// Tick of the last edge
+    pub(crate) last_edge: u64,
+    /// Measured half-period in ticks
+    pub(crate) half_period: u64,
+    /// Tick when a valid tone edge was last seen
+    pub(crate) last_active: u64,
+}
+
+impl SpeakerChannel {
+    fn new(pin: Option<GpioPin>) -> Self {
+        SpeakerChannel { pin, ..Default::default() }
+    }
+
+    /// Returns the new level when this write produced an edge on the pin.
+    fn edge(&mut self, port_addr: u16, value: u8, tick: u64) -> Option<bool> {
+        let pin = self.pin?;
+        if pin.port_addr != port_addr {
+            return None;
+        }
+        let level = value & (1 << pin.bit) != 0;
+        if level == self.prev {
+            return None;
+        }
+        if self.last_edge > 0 {
+            let half = tick.saturating_sub(self.last_edge);
+            if (MIN_HALF_PERIOD..=MAX_HALF_PERIOD).contains(&half) {
+                self.half_period = half;
+                self.last_active = tick;
+            }
+        }
+        self.last_edge = tick;
+        self.prev = level;
+        Some(level)
+    }
+
+    /// Tone frequency derived from the toggle rate at `clock_hz` ticks per
+    /// second, 0.0 when silent.
+    pub fn tone_hz(&self, tick: u64, clock_hz: u32) -> f32 {
+        if self.half_period == 0 || tick.saturating_sub(self.last_active) >= TONE_TIMEOUT {
+            return 0.0;
+        }
+        clock_hz as f32 / (2.0 * self.half_period as f32)
+    }
+
+    fn clear(&mut self) {
+        *self = SpeakerChannel::new(self.pin);
+    }
+}
+
+/// Routes GPIO speaker pins to the left/right audio channels.
+#[derive(Debug, Clone)]
+pub struct AudioRouter {
+    pub left: SpeakerChannel,
+    pub right: SpeakerChannel,
+}
+
+impl AudioRouter {
+    /// Default speaker wiring for a CPU type (see module docs).
+    pub fn for_cpu(cpu: CpuType) -> Self {
+        let (left, right) = match cpu {
+            CpuType::Atmega32u4 => (Some(GpioPin::new('C', 6)), Some(GpioPin::new('B', 5))),
+            CpuType::Atmega328p => (Some(GpioPin::new('D', 3)), None),
+            CpuType::Atmega2560 => (None, None),
+        };
+        AudioRouter { left: SpeakerChannel::new(left), right: SpeakerChannel::new(right) }
+    }
+
+    /// Remap the speaker pins. Edge state is cleared.
+    pub fn set_pins(&mut self, left: Option<GpioPin>, right: Option<GpioPin>) {
+        self.left = SpeakerChannel::new(left);
+        self.right = SpeakerChannel::new(right);
+    }
+
+    /// Parse `<left>[,<right>]`, e.g. `PC6,PB5`, `PD3` or `none,PB5`.
+    pub fn parse_pins(spec: &str) -> Result<(Option<GpioPin>, Option<GpioPin>), String> {
+        let mut it = spec.split(',');
+        let pin = |s: Option<&str>| match s.map(str::trim) {
+            None | Some("none") | Some("") => Ok(None),
+            Some(p) => GpioPin::parse(p).map(Some),
+        };
+        let left = pin(it.next())?;
+        let right = pin(it.next())?;
+        if it.next().is_some() {
+            return Err(format!("too many speaker pins in '{}'", spec));
+        }
+        Ok((left, right))
+    }
+
+    /// Observe a PORTx write; routed pins that change level are recorded
+    /// as edges in `buf`.
+    pub fn port_write(&mut self, port_addr: u16, value: u8, tick: u64, buf: &mut AudioBuffer) {
+        if let Some(level) = self.left.edge(port_addr, value, tick) {
+            buf.left.push(tick, level);
+        }
+        if let Some(level) = self.right.edge(port_addr, value, tick) {
+            buf.right.push(tick, level);
+        }
+    }
+
+    /// Forget edge history, keeping the pin mapping.
+    pub fn reset(&mut self) {
+        self.left.clear();
+        self.right.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_parse_roundtrip() {
+        let p = GpioPin::parse("pc6").unwrap();
+        assert_eq!(p, GpioPin { port_addr: 0x28, bit: 6 });
+        assert_eq!(p.name(), "PC6");
+        assert!(GpioPin::parse("PA1").is_err());
+        assert!(GpioPin::parse("PB8").is_err());
+        let (l, r) = AudioRouter::parse_pins("none,PB5").unwrap();
+        assert_eq!(l, None);
+        assert_eq!(r, Some(GpioPin::new('B', 5)));
+    }
+
+    #[test]
+    fn test_router_measures_tone() {
+        let mut router = AudioRouter::for_cpu(CpuType::Atmega32u4);
+        router.set_pins(Some(GpioPin::new('B', 6)), None);
+        let mut buf = AudioBuffer::new();
+        let mut tick = 1000;
+        for i in 0..4 {
+            router.port_write(0x25, if i % 2 == 0 { 0x40 } else { 0 }, tick, &mut buf);
+            router.port_write(0x28, 0x40, tick, &mut buf); // PC6 no longer routed
+            tick += 8000;
+        }
+        assert_eq!(router.left.tone_hz(tick - 8000, crate::CLOCK_HZ), 1000.0);
+        assert_eq!(router.right.tone_hz(tick, crate::CLOCK_HZ), 0.0);
+    }
+}
@@ -0,0 +1,336 @@
+//! RAM-condition autosplitter with LiveSplit Server output.
+//!
+//! A TOML file describes when a run starts, splits and resets, as
+//! [`expr`](crate::expr) conditions over registers, RAM and ELF symbols:
+//!
+//! ```toml
+//! start = "state == 2"          # title screen → playing
+//! reset = "state == 0"
+//! game_time = true              # report emulated time, not wall-clock
+//!
+//! [[split]]
+//! name = "World 1"
+//! when = "level"
+//! edge = "change"               # rise (default), fall or change
+//!
+//! [[split]]
+//! name = "Boss"
+//! when = "[0x0312] == 0"
+//! ```
+//!
+//! Conditions are checked once per frame. `rise` fires when a condition
+//! goes from false (0) to true, `fall` the other way and `change` when its
+//! value differs from the previous frame. Only the next split in order is
+//! armed, so one condition can be reused for several splits. `start` and
+//! `reset` always trigger on a rising edge.
+//!
+//! [`LiveSplitClient`] sends the resulting [`Event`]s to LiveSplit's
+//! Server component (TCP, port 16834 by default). With `game_time` the
+//! client pauses LiveSplit's game timer at the start and sets it to the
+//! emulated time since the start on every split, so runs timed on a slow or
+//! fast-forwarded emulator still compare fairly.
+
+use std::io::Write;
+use std::net::TcpStream;
+use std::path::Path;
+
+use crate::expr::{Expr, Symbols};
+use crate::{toml_lite, Arduboy};
+
+/// Default LiveSplit Server address.
+pub const LIVESPLIT_ADDR: &str = "127.0.0.1:16834";
+
+/// When a condition counts as triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// False → true
+    Rise,
+    /// True → false
+    Fall,
+    /// Any change of value
+    Change,
+}
+
+impl Edge {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "rise" => Ok(Edge::Rise),
+            "fall" => Ok(Edge::Fall),
+            "change" => Ok(Edge::Change),
+            _ => Err(format!("unknown edge '{}' (rise, fall, change)", s)),
+        }
+    }
+}
+
+/// A condition with its value on the previous frame.
+#[derive(Debug, Clone)]
+pub struct Trigger {
+    pub source: String,
+    pub edge: Edge,
+    expr: Expr,
+    prev: Option<i64>,
+}
+
+impl Trigger {
+    pub fn new(source: &str, edge: Edge, symbols: &Symbols) -> Result<Self, String> {
+        let expr = Expr::parse(source, symbols).map_err(|e| format!("'{}': {}", source, e))?;
+        Ok(Trigger { source: source.to_string(), edge, expr, prev: None })
+    }
+
+    /// Evaluate for this frame; true on the configured edge. The first
+    /// evaluation only records the value.
+    fn check(&mut self, arduboy: &Arduboy) -> bool {
+        let Ok(now) = self.expr.eval(arduboy) else { return false };
+        let fired = match (self.prev, self.edge) {
+            (None, _) => false,
+            (Some(p), Edge::Rise) => p == 0 && now != 0,
+            (Some(p), Edge::Fall) => p != 0 && now == 0,
+            (Some(p), Edge::Change) => p != now,
+        };
+        self.prev = Some(now);
+        fired
+    }
+}
+
+/// One named split.
+#[derive(Debug, Clone)]
+pub struct Split {
+    pub name: String,
+    pub trigger: Trigger,
+}
+
+/// Something that happened to the run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Start,
+    /// Split index and emulated seconds since the start
+    Split(usize, f64),
+    Reset,
+}
+
+/// Autosplitter state.
+#[derive(Debug, Clone)]
+pub struct Autosplitter {
+    pub start: Trigger,
+    pub reset: Option<Trigger>,
+    pub splits: Vec<Split>,
+    /// Report emulated time to LiveSplit as game time
+    pub game_time: bool,
+    /// Tick of the start, while a run is in progress
+    started_at: Option<u64>,
+    /// Index of the next split
+    next: usize,
+}
+
+impl Autosplitter {
+    /// Parse a config (see the module docs).
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Self, String> {
+        let doc = toml_lite::parse(text)?;
+        let mut start = None;
+        let mut reset = None;
+        let mut game_time = false;
+        for (key, value) in &doc.root {
+            match key.as_str() {
+                "start" => start = Some(Trigger::new(value.as_str(key)?, Edge::Rise, symbols)?),
+                "reset" => reset = Some(Trigger::new(value.as_str(key)?, Edge::Rise, symbols)?),
+                "game_time" => game_time = value.as_bool(key)?,
+                _ => return Err(format!("unknown key '{}'", key)),
+            }
+        }
+        let mut splits = Vec::new();
+        for (table, t) in &doc.tables {
+            if table != "split" {
+                return Err(format!("[[{}]]: unknown table (expected [[split]])", table));
+            }
+            let name = match t.get("name") {
+                Some(v) => v.as_str("name")?.to_string(),
+                None => format!("split {}", splits.len() + 1),
+            };
+            let err = |e: String| format!("split \"{}\": {}", name, e);
+            for (key, _) in &t.0 {
+                if !matches!(key.as_str(), "name" | "when" | "edge") {
+                    return Err(err(format!("unknown key '{}'", key)));
+                }
+            }
+            let when = t.get("when").ok_or_else(|| err("missing when".into()))?.as_str("when").map_err(err)?;
+            let edge = match t.get("edge") {
+                Some(v) => Edge::parse(v.as_str("edge").map_err(err)?).map_err(err)?,
+                None => Edge::Rise,
+            };
+            let trigger = Trigger::new(when, edge, symbols).map_err(err)?;
+            splits.push(Split { name, trigger });
+        }
+        let start = start.ok_or("missing start condition")?;
+        if splits.is_empty() {
+            return Err("no [[split]] tables".into());
+        }
+        Ok(Autosplitter { start, reset, splits, game_time, started_at: None, next: 0 })
+    }
+
+    pub fn load(path: &Path, symbols: &Symbols) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text, symbols).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// True between the start and a reset (also after the last split).
+    pub fn running(&self) -> bool {
+        self.started_at.is_some()
+    }
+
+    /// Index of the next split to trigger.
+    pub fn next_split(&self) -> usize {
+        self.next
+    }
+
+    /// Check the conditions after a frame. Every armed trigger is evaluated
+    /// each frame so edges are measured against the previous frame.
+    pub fn update(&mut self, arduboy: &Arduboy) -> Vec<Event> {
+        let mut events = Vec::new();
+        let started = self.start.check(arduboy);
+        let reset = self.reset.as_mut().is_some_and(|r| r.check(arduboy));
+        let split = match self.splits.get_mut(self.next) {
+            Some(s) => s.trigger.check(arduboy),
+            None => false,
+        };
+        match self.started_at {
+            Some(_) if reset => {
+                self.started_at = None;
+                events.push(Event::Reset);
+            }
+            Some(t0) if split => {
+                let secs = arduboy.cpu.tick.saturating_sub(t0) as f64 / arduboy.clock_hz as f64;
+                events.push(Event::Split(self.next, secs));
+                self.next += 1;
+                // Re-arm the following split from this frame's state
+                if let Some(s) = self.splits.get_mut(self.next) {
+                    s.trigger.prev = None;
+                    s.trigger.check(arduboy);
+                }
+            }
+            _ => {}
+        }
+        if self.started_at.is_none() && started {
+            self.started_at = Some(arduboy.cpu.tick);
+            self.next = 0;
+            for s in &mut self.splits {
+                s.trigger.prev = None;
+            }
+            self.splits[0].trigger.check(arduboy);
+            events.push(Event::Start);
+        }
+        events
+    }
+
+    /// One-line description of an event for logs.
+    pub fn describe(&self, event: &Event) -> String {
+        match event {
+            Event::Start => "start".to_string(),
+            Event::Split(i, secs) => format!("split {}/{} \"{}\" at {}",
+                i + 1, self.splits.len(), self.splits[*i].name, format_time(*secs)),
+            Event::Reset => "reset".to_string(),
+        }
+    }
+}
+
+/// `h:mm:ss.fff`, the time format LiveSplit parses.
+pub fn format_time(secs: f64) -> String {
+    let ms = (secs * 1000.0).round() as u64;
+    format!("{}:{:02}:{:02}.{:03}", ms / 3_600_000, ms / 60_000 % 60, ms / 1000 % 60, ms % 1000)
+}
+
+/// LiveSplit Server commands for `event`, one per line.
+pub fn commands(event: &Event, game_time: bool) -> Vec<String> {
+    match event {
+        Event::Start if game_time => vec!["starttimer".into(), "initgametime".into(), "pausegametime".into()],
+        Event::Start => vec!["starttimer".into()],
+        Event::Split(_, secs) if game_time => vec![format!("setgametime {}", format_time(*secs)), "split".into()],
+        Event::Split(..) => vec!["split".into()],
+        Event::Reset => vec!["reset".into()],
+    }
+}
+
+/// Connection to LiveSplit's Server component.
+pub struct LiveSplitClient {
+    stream: TcpStream,
+}
+
+impl LiveSplitClient {
+    pub fn connect(addr: &str) -> std::io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(LiveSplitClient { stream })
+    }
+
+    /// Send the commands for `event`.
+    pub fn send(&mut self, event: &Event, game_time: bool) -> std::io::Result<()> {
+        for cmd in commands(event, game_time) {
+            self.stream.write_all(cmd.as_bytes())?;
+            self.stream.write_all(b"\r\n")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CLOCK_HZ;
+
+    const CONFIG: &str = r#"
+start = "[0x100] == 1"
+reset = "[0x100] == 0"
+game_time = true
+
+[[split]]
+name = "Level"
+when = "[0x101]"
+edge = "change"
+
+[[split]]
+when = "[0x102] == 3"
+"#;
+
+    fn frame(a: &mut Arduboy, state: u8, level: u8, boss: u8, ticks: u64) {
+        a.mem.data[0x100] = state;
+        a.mem.data[0x101] = level;
+        a.mem.data[0x102] = boss;
+        a.cpu.tick += ticks;
+    }
+
+    #[test]
+    fn test_start_split_reset() {
+        let mut a = Arduboy::new();
+        let mut s = Autosplitter::parse(CONFIG, &Symbols::default()).unwrap();
+        assert_eq!(s.splits[1].name, "split 2");
+        assert!(s.update(&a).is_empty());
+        frame(&mut a, 1, 0, 0, 0);
+        assert_eq!(s.update(&a), vec![Event::Start]);
+        // No change yet; then level changes one second later
+        frame(&mut a, 1, 0, 0, CLOCK_HZ as u64);
+        assert!(s.update(&a).is_empty());
+        frame(&mut a, 1, 1, 3, CLOCK_HZ as u64 / 2);
+        assert_eq!(s.update(&a), vec![Event::Split(0, 1.5)]);
+        // The boss condition is already true: it needs a fresh rising edge
+        assert!(s.update(&a).is_empty());
+        frame(&mut a, 1, 1, 0, 0);
+        assert!(s.update(&a).is_empty());
+        frame(&mut a, 1, 1, 3, 0);
+        let ev = s.update(&a);
+        assert_eq!(s.describe(&ev[0]), "split 2/2 \"split 2\" at 0:00:01.500");
+        assert!(s.running());
+        frame(&mut a, 0, 0, 0, 0);
+        assert_eq!(s.update(&a), vec![Event::Reset]);
+        assert!(!s.running());
+    }
+
+    #[test]
+    fn test_commands_and_errors() {
+        assert_eq!(commands(&Event::Split(0, 3725.25), true), vec!["setgametime 1:02:05.250", "split"]);
+        assert_eq!(commands(&Event::Start, false), vec!["starttimer"]);
+        let syms = Symbols::default();
+        assert!(Autosplitter::parse("start = \"1\"", &syms).unwrap_err().contains("no [[split]]"));
+        assert!(Autosplitter::parse("[[split]]\nwhen = \"1\"", &syms).unwrap_err().contains("start"));
+        assert!(Autosplitter::parse("start = \"1\"\n[[split]]\nwhen = \"1\"\nedge = \"up\"", &syms)
+            .unwrap_err().contains("unknown edge"));
+    }
+}
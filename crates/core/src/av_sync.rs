@@ -0,0 +1,170 @@
+//! Audio/video sync statistics and drift correction.
+//!
+//! A frontend that plays [`AudioBuffer`](crate::audio_buffer::AudioBuffer)
+//! output through a ring buffer reports how much audio is still queued after
+//! each presented frame. That backlog is how far the sound trails the
+//! picture, so [`AvSync`] turns it into an A/V offset in milliseconds and
+//! keeps statistics for [`AvSync::report`].
+//!
+//! The host's audio clock and its display clock never agree exactly, so the
+//! offset drifts over a long session until the ring runs dry or overflows.
+//! With [`AvSync::correct`] set, the cycles run per frame
+//! ([`Arduboy::frame_cycles`](crate::Arduboy::frame_cycles)) are scaled to
+//! hold the offset at [`AvSync::target_ms`]:
+//!
+//! | Offset | Cycles per frame |
+//! |--------|------------------|
+//! | Above target (audio late) | Fewer, so each frame queues less audio |
+//! | Below target | More |
+//! | Any | Within ±0.5% of [`FRAME_CYCLES`], changing by at most 0.01% per frame |
+//!
+//! The limits keep the change far below audible pitch or visible speed
+//! differences. Frames without sample-accurate audio call [`AvSync::idle`],
+//! which eases the scale back to nominal.
+
+use crate::FRAME_CYCLES;
+
+/// Largest correction, as a fraction of [`FRAME_CYCLES`]
+pub const MAX_ADJUST: f64 = 0.005;
+/// Largest change of the correction per frame
+const SLEW: f64 = 0.0001;
+/// Correction per millisecond of smoothed offset error
+const GAIN: f64 = 0.0005;
+/// Weight of the newest measurement in the smoothed offset
+const SMOOTHING: f64 = 0.05;
+
+/// Offset measurements and the current correction.
+#[derive(Debug, Clone)]
+pub struct AvSync {
+    /// Adjust cycles per frame (statistics are kept either way)
+    pub correct: bool,
+    /// Offset the correction aims for
+    pub target_ms: f64,
+    smoothed_ms: f64,
+    last_ms: f64,
+    /// Current correction, within ±[`MAX_ADJUST`]
+    adjust: f64,
+    frames: u64,
+    sum_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+    /// Frames that found the ring empty
+    underruns: u64,
+    /// Frames with the correction at its limit
+    saturated: u64,
+}
+
+impl AvSync {
+    pub fn new(target_ms: f64) -> Self {
+        AvSync {
+            correct: true,
+            target_ms,
+            smoothed_ms: target_ms,
+            last_ms: 0.0,
+            adjust: 0.0,
+            frames: 0,
+            sum_ms: 0.0,
+            min_ms: f64::MAX,
+            max_ms: 0.0,
+            underruns: 0,
+            saturated: 0,
+        }
+    }
+
+    /// Record `queued` stereo frames of audio waiting at `sample_rate` after
+    /// a presented frame; returns the cycles to run per emulated frame.
+    pub fn observe(&mut self, queued: usize, sample_rate: u32) -> u64 {
+        let ms = queued as f64 * 1000.0 / sample_rate.max(1) as f64;
+        self.last_ms = ms;
+        self.frames += 1;
+        self.sum_ms += ms;
+        self.min_ms = self.min_ms.min(ms);
+        self.max_ms = self.max_ms.max(ms);
+        if queued == 0 {
+            self.underruns += 1;
+        }
+        self.smoothed_ms += (ms - self.smoothed_ms) * SMOOTHING;
+        let want = if self.correct {
+            ((self.target_ms - self.smoothed_ms) * GAIN).clamp(-MAX_ADJUST, MAX_ADJUST)
+        } else {
+            0.0
+        };
+        self.adjust += (want - self.adjust).clamp(-SLEW, SLEW);
+        if self.adjust.abs() >= MAX_ADJUST - 1e-9 {
+            self.saturated += 1;
+        }
+        self.frame_cycles()
+    }
+
+    /// A frame without sample-accurate audio: relax toward nominal speed.
+    pub fn idle(&mut self) -> u64 {
+        self.adjust -= self.adjust.clamp(-SLEW, SLEW);
+        self.frame_cycles()
+    }
+
+    /// Cycles per frame with the current correction.
+    pub fn frame_cycles(&self) -> u64 {
+        (FRAME_CYCLES as f64 * (1.0 + self.adjust)).round() as u64
+    }
+
+    /// Latest measured offset in milliseconds.
+    pub fn offset_ms(&self) -> f64 {
+        self.last_ms
+    }
+
+    /// Current correction as a fraction (+0.001 = 0.1% more cycles).
+    pub fn adjustment(&self) -> f64 {
+        self.adjust
+    }
+
+    /// Offset statistics and correction summary.
+    pub fn report(&self) -> String {
+        if self.frames == 0 {
+            return "A/V sync: no sample-accurate audio played".to_string();
+        }
+        let pct = |n: u64| n as f64 * 100.0 / self.frames as f64;
+        let mut s = format!(
+            "A/V sync: {} frames, offset avg {:.1} ms (min {:.1}, max {:.1}, now {:.1}), target {:.0} ms\n",
+            self.frames, self.sum_ms / self.frames as f64, self.min_ms, self.max_ms, self.last_ms, self.target_ms);
+        s.push_str(&format!("  Underruns: {} frames ({:.1}%) found the audio ring empty\n",
+            self.underruns, pct(self.underruns)));
+        if self.correct {
+            s.push_str(&format!("  Correction: {:+.3}% cycles per frame now, at the ±{}% limit in {:.1}% of frames",
+                self.adjust * 100.0, MAX_ADJUST * 100.0, pct(self.saturated)));
+        } else {
+            s.push_str("  Correction: off");
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correction_bounded_and_slewed() {
+        let mut av = AvSync::new(40.0);
+        // Audio far behind the picture: run fewer cycles, down to the limit
+        let first = av.observe(4410, 44100);
+        assert_eq!(first, (FRAME_CYCLES as f64 * (1.0 - SLEW)).round() as u64);
+        for _ in 0..500 {
+            av.observe(4410, 44100);
+        }
+        assert_eq!(av.frame_cycles(), (FRAME_CYCLES as f64 * (1.0 - MAX_ADJUST)).round() as u64);
+        assert_eq!(av.offset_ms(), 100.0);
+
+        // Ring running dry: back up past nominal
+        for _ in 0..500 {
+            av.observe(0, 44100);
+        }
+        assert!(av.adjustment() > 0.0 && av.adjustment() <= MAX_ADJUST);
+        let report = av.report();
+        assert!(report.contains("1001 frames") && report.contains("Underruns: 500 frames"), "{}", report);
+
+        for _ in 0..100 {
+            av.idle();
+        }
+        assert_eq!(av.frame_cycles(), FRAME_CYCLES);
+    }
+}
@@ -0,0 +1,293 @@
+//! Board profiles: named hardware configurations beyond [`CpuType`].
+//!
+//! A [`BoardProfile`] bundles everything that differs between Arduboy-style
+//! handhelds: CPU, display controller and bus, the chip-select / data-command
+//! pins, button and speaker wiring, and whether an FX flash chip is fitted.
+//!
+//! Built-in profiles:
+//!
+//! | Name              | CPU   | Display                 | Notes                          |
+//! |-------------------|-------|-------------------------|--------------------------------|
+//! | `arduboy`         | 32u4  | SSD1306 (SPI)           | Original Arduboy               |
+//! | `arduboy-fx`      | 32u4  | SSD1306 (SPI)           | Arduboy FX (flash on PD1)      |
+//! | `leonardo-sh1106` | 32u4  | SH1106 (SPI)            | Homemade Leonardo/Pro Micro    |
+//! | `gamebuino`       | 328P  | PCD8544 (SPI)           | Gamebuino Classic              |
+//! | `uno-i2c-oled`    | 328P  | SSD1306 (I2C)           | Uno + I2C OLED (TWI not emulated yet) |
+//!
+//! Custom boards are described in a small TOML file (see [`BoardProfile::parse`]):
+//!
+//! ```toml
+//! base = "arduboy"                 # built-in profile to start from
+//! name = "My handheld"
+//! cpu = "32u4"                     # 32u4 | 328p | 2560
+//! display = "ssd1306"              # ssd1306 | pcd8544
+//! controller = "sh1106"            # --display-controller syntax
+//! bus = "spi"                      # spi | i2c
+//! display_cs = "PD6"
+//! display_dc = "PD4"
+//! buttons = ["PF7", "PF4", "PF5", "PF6", "PE6", "PB4"]   # up, down, left, right, A, B
+//! speaker = ["PC6", "none"]        # left, right
+//! fx = false
+//! ```
+
+use crate::display::{DisplayController, DisplayQuirks};
+use crate::toml_lite;
+use crate::{CpuType, DisplayType};
+
+// ─── Pins ───────────────────────────────────────────────────────────────────
+
+/// A single GPIO pin, identified by its PORTx register and bit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpioPin {
+    /// Data-space address of the PORTx register (0x25 = PORTB ... 0x31 = PORTF)
+    pub port_addr: u16,
+    /// Bit within the port (0..7)
+    pub bit: u8,
+}
+
+impl GpioPin {
+    pub const fn new(port: char, bit: u8) -> Self {
+        let idx = (port as u8 - b'B') as u16;
+        GpioPin { port_addr: 0x25 + idx * 3, bit }
+    }
+
+    /// Parse a pin name such as `PC6` or `pb5`.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let up = name.trim().to_ascii_uppercase();
+        let b = up.as_bytes();
+        if b.len() != 3 || b[0] != b'P' || !(b'B'..=b'F').contains(&b[1]) || !(b'0'..=b'7').contains(&b[2]) {
+            return Err(format!("bad pin '{}' (expected PB0..PF7)", name));
+        }
+        Ok(GpioPin::new(b[1] as char, b[2] - b'0'))
+    }
+
+    /// Pin name, e.g. `PC6`.
+    pub fn name(&self) -> String {
+        format!("P{}{}", (b'B' + self.port_index() as u8) as char, self.bit)
+    }
+
+    /// Port index: 0 = B ... 4 = F.
+    pub fn port_index(&self) -> usize {
+        ((self.port_addr - 0x25) / 3) as usize
+    }
+
+    /// Bit mask within the port.
+    pub fn mask(&self) -> u8 {
+        1 << self.bit
+    }
+}
+
+// ─── Profile ────────────────────────────────────────────────────────────────
+
+/// How the display controller is attached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayBus {
+    Spi,
+    /// Two-wire interface; the emulator has no TWI peripheral yet, so such
+    /// boards run but show a blank screen.
+    I2c,
+}
+
+/// A named hardware configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoardProfile {
+    pub name: String,
+    pub cpu: CpuType,
+    /// Ssd1306 (any OLED controller, see `controller`) or Pcd8544
+    pub display: DisplayType,
+    /// OLED controller variant and quirks
+    pub controller: DisplayQuirks,
+    pub bus: DisplayBus,
+    /// Display chip select (active low)
+    pub display_cs: GpioPin,
+    /// Display data/command select (high = data)
+    pub display_dc: GpioPin,
+    /// Button pins in [`input_overlay::BUTTONS`](crate::input_overlay::BUTTONS)
+    /// order (up, down, left, right, A, B); active low
+    pub buttons: [GpioPin; 6],
+    /// Speaker pins for the left / right audio channel
+    pub speaker: [Option<GpioPin>; 2],
+    /// FX flash chip fitted (CS on PD1)
+    pub fx: bool,
+}
+
+/// Names accepted by [`BoardProfile::builtin`].
+pub const BUILTIN_BOARDS: [&str; 5] =
+    ["arduboy", "arduboy-fx", "leonardo-sh1106", "gamebuino", "uno-i2c-oled"];
+
+impl BoardProfile {
+    /// A built-in profile by name (see module docs).
+    pub fn builtin(name: &str) -> Option<Self> {
+        let p = GpioPin::new;
+        let arduboy = BoardProfile {
+            name: "arduboy".into(),
+            cpu: CpuType::Atmega32u4,
+            display: DisplayType::Ssd1306,
+            controller: DisplayQuirks::for_controller(DisplayController::Ssd1306),
+            bus: DisplayBus::Spi,
+            display_cs: p('D', 6),
+            display_dc: p('D', 4),
+            buttons: [p('F', 7), p('F', 4), p('F', 5), p('F', 6), p('E', 6), p('B', 4)],
+            speaker: [Some(p('C', 6)), Some(p('B', 5))],
+            fx: false,
+        };
+        let gamebuino = BoardProfile {
+            name: "gamebuino".into(),
+            cpu: CpuType::Atmega328p,
+            display: DisplayType::Pcd8544,
+            display_cs: p('C', 1),
+            display_dc: p('C', 2),
+            buttons: [p('B', 1), p('D', 6), p('B', 0), p('D', 7), p('D', 4), p('D', 2)],
+            speaker: [Some(p('D', 3)), None],
+            ..arduboy.clone()
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "arduboy" => Some(arduboy),
+            "arduboy-fx" => Some(BoardProfile { name: "arduboy-fx".into(), fx: true, ..arduboy }),
+            "leonardo-sh1106" => Some(BoardProfile {
+                name: "leonardo-sh1106".into(),
+                controller: DisplayQuirks::for_controller(DisplayController::Sh1106),
+                ..arduboy
+            }),
+            "gamebuino" => Some(gamebuino),
+            // Buttons and speaker follow the Gamebuino layout; override in TOML
+            "uno-i2c-oled" => Some(BoardProfile {
+                name: "uno-i2c-oled".into(),
+                display: DisplayType::Ssd1306,
+                bus: DisplayBus::I2c,
+                ..gamebuino
+            }),
+            _ => None,
+        }
+    }
+
+    /// Parse a profile from TOML text (format in the module docs).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let doc = toml_lite::parse(text)?;
+        let base = match doc.root.iter().find(|(k, _)| k == "base") {
+            Some((_, v)) => v.as_str("base")?.to_string(),
+            None => "arduboy".to_string(),
+        };
+        let mut b = Self::builtin(&base).ok_or_else(|| format!("base: unknown board \"{}\"", base))?;
+        b.name = "custom".into();
+
+        let pin = |key: &str, v: &toml_lite::Value| -> Result<GpioPin, String> {
+            GpioPin::parse(v.as_str(key)?).map_err(|e| format!("{}: {}", key, e))
+        };
+        for (key, val) in &doc.root {
+            match key.as_str() {
+                "base" => {}
+                "name" => b.name = val.as_str(key)?.to_string(),
+                "cpu" => b.cpu = match val.as_str(key)?.to_lowercase().as_str() {
+                    "32u4" | "atmega32u4" => CpuType::Atmega32u4,
+                    "328p" | "atmega328p" => CpuType::Atmega328p,
+                    "2560" | "atmega2560" => CpuType::Atmega2560,
+                    other => return Err(format!("cpu: unknown type \"{}\"", other)),
+                },
+                "display" => b.display = match val.as_str(key)?.to_lowercase().as_str() {
+                    "ssd1306" | "oled" => DisplayType::Ssd1306,
+                    "pcd8544" | "lcd" => DisplayType::Pcd8544,
+                    other => return Err(format!("display: unknown type \"{}\"", other)),
+                },
+                "controller" => b.controller = DisplayQuirks::parse(val.as_str(key)?)
+                    .map_err(|e| format!("controller: {}", e))?,
+                "bus" => b.bus = match val.as_str(key)?.to_lowercase().as_str() {
+                    "spi" => DisplayBus::Spi,
+                    "i2c" | "twi" => DisplayBus::I2c,
+                    other => return Err(format!("bus: unknown bus \"{}\"", other)),
+                },
+                "display_cs" => b.display_cs = pin(key, val)?,
+                "display_dc" => b.display_dc = pin(key, val)?,
+                "buttons" => {
+                    let list = val.as_array(key)?;
+                    if list.len() != 6 {
+                        return Err("buttons: expected 6 pins (up, down, left, right, A, B)".into());
+                    }
+                    for (slot, v) in b.buttons.iter_mut().zip(list) {
+                        *slot = pin(key, v)?;
+                    }
+                }
+                "speaker" => {
+                    let list = val.as_array(key)?;
+                    if list.is_empty() || list.len() > 2 {
+                        return Err("speaker: expected [left] or [left, right]".into());
+                    }
+                    b.speaker = [None, None];
+                    for (slot, v) in b.speaker.iter_mut().zip(list) {
+                        *slot = match v.as_str(key)? {
+                            "none" => None,
+                            _ => Some(pin(key, v)?),
+                        };
+                    }
+                }
+                "fx" => b.fx = val.as_bool(key)?,
+                _ => return Err(format!("unknown key \"{}\"", key)),
+            }
+        }
+        if !doc.tables.is_empty() {
+            return Err("board profiles take no [[tables]]".into());
+        }
+        Ok(b)
+    }
+
+    /// Resolve `--board` argument: a built-in name or a path to a TOML file.
+    pub fn load(spec: &str) -> Result<Self, String> {
+        if let Some(b) = Self::builtin(spec) {
+            return Ok(b);
+        }
+        let text = std::fs::read_to_string(spec).map_err(|e| {
+            format!("{}: {} (built-in boards: {})", spec, e, BUILTIN_BOARDS.join(", "))
+        })?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", spec, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_profiles() {
+        for name in BUILTIN_BOARDS {
+            let b = BoardProfile::builtin(name).unwrap();
+            assert_eq!(b.name, name);
+        }
+        let gb = BoardProfile::builtin("gamebuino").unwrap();
+        assert_eq!(gb.cpu, CpuType::Atmega328p);
+        assert_eq!(gb.display_cs, GpioPin::new('C', 1));
+        assert!(BoardProfile::builtin("arduboy-fx").unwrap().fx);
+        assert_eq!(BoardProfile::builtin("leonardo-sh1106").unwrap().controller.controller,
+            DisplayController::Sh1106);
+    }
+
+    #[test]
+    fn test_parse_custom_profile() {
+        let b = BoardProfile::parse(r#"
+base = "arduboy"
+name = "pocket"
+controller = "ssd1309"
+buttons = ["PB0", "PB1", "PB2", "PB3", "PD0", "PD1"]
+speaker = ["PB6"]
+"#).unwrap();
+        assert_eq!(b.name, "pocket");
+        assert_eq!(b.controller.controller, DisplayController::Ssd1309);
+        assert_eq!(b.buttons[4], GpioPin::new('D', 0));
+        assert_eq!(b.speaker, [Some(GpioPin::new('B', 6)), None]);
+        assert_eq!(b.display_cs, GpioPin::new('D', 6), "inherited from base");
+
+        assert!(BoardProfile::parse("buttons = [\"PB0\"]").is_err());
+        assert!(BoardProfile::parse("base = \"nes\"").is_err());
+        assert!(BoardProfile::parse("display_dc = \"PA1\"").is_err());
+        assert!(BoardProfile::parse("colour = \"red\"").is_err());
+    }
+
+    #[test]
+    fn test_gpio_pin_names() {
+        let p = GpioPin::parse("pc6").unwrap();
+        assert_eq!(p, GpioPin { port_addr: 0x28, bit: 6 });
+        assert_eq!(p.name(), "PC6");
+        assert_eq!(p.port_index(), 1);
+        assert!(GpioPin::parse("PA1").is_err());
+        assert!(GpioPin::parse("PB8").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+//! Built-in "no game loaded" ROM.
+//!
+//! [`build`] assembles a small ATmega32u4 program that brings up the SSD1306
+//! the way Arduboy2 does, draws a pre-rendered screen naming the emulator
+//! and the game browser keys, and then polls the A button, inverting (and
+//! redrawing) the display while it is held. Frontends boot it when started
+//! without a game, so the window shows something that reacts to input
+//! instead of exiting with usage text. Like the [`selftest`](crate::selftest)
+//! ROM it is plain AVR code with no interrupts, so it runs unchanged on real
+//! hardware.
+//!
+//! | Key | Shown as |
+//! |-----|----------|
+//! | N / P | Next / previous game in the current directory |
+//! | O | List the games found there |
+//! | A | Invert the screen while held |
+
+use crate::selftest::{brne, io, ldi, mov, rjmp, two_reg, RET};
+
+// I/O addresses
+const PINE: u8 = 0x0C;
+const PORTE: u8 = 0x0E;
+const DDRB: u8 = 0x04;
+const DDRD: u8 = 0x0A;
+const PORTD: u8 = 0x0B;
+const SPCR: u8 = 0x2C;
+const SPSR: u8 = 0x2D;
+const SPDR: u8 = 0x2E;
+
+/// Display set-up sent before the image: clock, charge pump, segment and
+/// COM remap, contrast, precharge, display on, horizontal addressing
+const INIT: [u8; 13] = [0xD5, 0xF0, 0x8D, 0x14, 0xA1, 0xC8, 0x81, 0xCF, 0xD9, 0xF1, 0xAF, 0x20, 0x00];
+
+/// Screen text as (page, column, text); a column of `None` centers the line
+const TEXT: [(usize, Option<usize>, &str); 5] = [
+    (1, None, "ARDUBOY EMULATOR"),
+    (3, None, "NO GAME LOADED"),
+    (5, Some(8), "N/P  NEXT/PREV GAME"),
+    (6, Some(8), "O    LIST GAMES"),
+    (7, Some(8), "A    INVERT SCREEN"),
+];
+
+/// 5x7 glyphs, one byte per column with bit 0 at the top
+const FONT: &[(char, [u8; 5])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('/', [0x20, 0x10, 0x08, 0x04, 0x02]),
+    ('A', [0x7C, 0x12, 0x11, 0x12, 0x7C]),
+    ('B', [0x7F, 0x49, 0x49, 0x49, 0x36]),
+    ('C', [0x3E, 0x41, 0x41, 0x41, 0x22]),
+    ('D', [0x7F, 0x41, 0x41, 0x22, 0x1C]),
+    ('E', [0x7F, 0x49, 0x49, 0x49, 0x41]),
+    ('G', [0x3E, 0x41, 0x49, 0x49, 0x7A]),
+    ('I', [0x00, 0x41, 0x7F, 0x41, 0x00]),
+    ('L', [0x7F, 0x40, 0x40, 0x40, 0x40]),
+    ('M', [0x7F, 0x02, 0x0C, 0x02, 0x7F]),
+    ('N', [0x7F, 0x04, 0x08, 0x10, 0x7F]),
+    ('O', [0x3E, 0x41, 0x41, 0x41, 0x3E]),
+    ('P', [0x7F, 0x09, 0x09, 0x09, 0x06]),
+    ('R', [0x7F, 0x09, 0x19, 0x29, 0x46]),
+    ('S', [0x46, 0x49, 0x49, 0x49, 0x31]),
+    ('T', [0x01, 0x01, 0x7F, 0x01, 0x01]),
+    ('U', [0x3F, 0x40, 0x40, 0x40, 0x3F]),
+    ('V', [0x1F, 0x20, 0x40, 0x20, 0x1F]),
+    ('X', [0x63, 0x14, 0x08, 0x14, 0x63]),
+    ('Y', [0x07, 0x08, 0x70, 0x08, 0x07]),
+];
+
+fn sbrs(r: u8, b: u8) -> u16 { 0xFE00 | ((r as u16) << 4) | b as u16 }
+fn sbi(a: u8, b: u8) -> u16 { 0x9A00 | ((a as u16) << 3) | b as u16 }
+fn cbi(a: u8, b: u8) -> u16 { 0x9800 | ((a as u16) << 3) | b as u16 }
+fn dec(d: u8) -> u16 { 0x940A | ((d as u16) << 4) }
+fn breq(k: i16) -> u16 { 0xF001 | (((k as u16) & 0x7F) << 3) }
+fn rcall(k: i16) -> u16 { 0xD000 | ((k as u16) & 0x0FFF) }
+/// `lpm r24, Z+`
+const LPM_R24_ZP: u16 = 0x9185;
+/// `sbiw r26, 1`
+const SBIW_X_1: u16 = 0x9711;
+
+/// The screen as 1024 bytes of SSD1306 pages (128 columns per page).
+pub fn screen() -> Vec<u8> {
+    let mut buf = vec![0u8; 1024];
+    for (page, col, text) in TEXT {
+        let x0 = col.unwrap_or((129 - text.len() * 6) / 2);
+        for (i, c) in text.chars().enumerate() {
+            let glyph = FONT.iter().find(|(g, _)| *g == c).map_or([0; 5], |&(_, g)| g);
+            let at = page * 128 + x0 + i * 6;
+            buf[at..at + 5].copy_from_slice(&glyph);
+        }
+    }
+    // Rule under the title
+    for b in &mut buf[2 * 128 + 16..2 * 128 + 112] {
+        *b |= 0x08;
+    }
+    buf
+}
+
+/// Assemble the ROM (flash image starting at address 0).
+pub fn build() -> Vec<u8> {
+    let mut w: Vec<u16> = vec![0]; // rjmp main
+
+    // spi: send r24 and wait for SPIF
+    let spi = w.len() as i16;
+    w.push(io(0xB800, SPDR, 24));
+    w.push(io(0xB000, SPSR, 0));
+    w.push(sbrs(0, 7));
+    w.push(rjmp(-3));
+    w.push(RET);
+    let call_spi = |w: &Vec<u16>| rcall(spi - w.len() as i16 - 1);
+
+    // main: SPI master, display selected in command mode, FX flash deselected
+    let main = w.len() as i16;
+    w[0] = rjmp(main - 1);
+    w.push(ldi(16, 0x07)); // SS, SCK, MOSI
+    w.push(io(0xB800, DDRB, 16));
+    w.push(ldi(16, 0xD2)); // FX CS, DC, CS, RST
+    w.push(io(0xB800, DDRD, 16));
+    w.push(ldi(16, 0x82)); // RST and FX CS high
+    w.push(io(0xB800, PORTD, 16));
+    w.push(ldi(16, 0x40)); // A button pull-up
+    w.push(io(0xB800, PORTE, 16));
+    w.push(ldi(16, 0x50)); // SPE | MSTR
+    w.push(io(0xB800, SPCR, 16));
+
+    // Display set-up from INIT
+    let init_at = w.len();
+    w.extend([0, 0]); // ldi r30, ldi r31
+    w.push(ldi(17, INIT.len() as u8));
+    let cmd = w.len() as i16;
+    w.push(LPM_R24_ZP);
+    w.push(call_spi(&w));
+    w.push(dec(17));
+    w.push(brne(cmd - w.len() as i16 - 1));
+    w.push(ldi(18, 0xA6)); // r18 = last A6/A7 sent
+
+    // draw: the 1024-byte image, wrapping back to page 0 column 0
+    let draw = w.len() as i16;
+    let image_at = w.len();
+    w.extend([0, 0]); // ldi r30, ldi r31
+    w.push(sbi(PORTD, 4)); // DC: data
+    w.push(ldi(26, 0x00));
+    w.push(ldi(27, 0x04)); // X = 1024
+    let img = w.len() as i16;
+    w.push(LPM_R24_ZP);
+    w.push(call_spi(&w));
+    w.push(SBIW_X_1);
+    w.push(brne(img - w.len() as i16 - 1));
+    w.push(cbi(PORTD, 4)); // DC: command
+
+    // idle: send A7 (inverse) while A is held, A6 (normal) otherwise, and
+    // redraw so the change shows on controllers that latch it per write
+    let idle = w.len() as i16;
+    w.push(io(0xB000, PINE, 16));
+    w.push(ldi(24, 0xA6));
+    w.push(sbrs(16, 6));
+    w.push(ldi(24, 0xA7));
+    w.push(two_reg(0x1400, 24, 18)); // cp r24, r18
+    w.push(breq(idle - w.len() as i16 - 1));
+    w.push(mov(18, 24));
+    w.push(call_spi(&w));
+    w.push(rjmp(draw - w.len() as i16 - 1));
+
+    // INIT and the image follow the code
+    let data = w.len() as u16 * 2;
+    for (at, addr) in [(init_at, data), (image_at, data + INIT.len() as u16)] {
+        w[at] = ldi(30, addr as u8);
+        w[at + 1] = ldi(31, (addr >> 8) as u8);
+    }
+
+    let mut rom: Vec<u8> = w.iter().flat_map(|x| x.to_le_bytes()).collect();
+    rom.extend_from_slice(&INIT);
+    rom.extend(screen());
+    rom
+}
+
+/// Intel HEX image of the ROM, for [`Arduboy::load_hex`](crate::Arduboy::load_hex).
+pub fn hex() -> String {
+    crate::hex::to_hex(&build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Arduboy, Button};
+
+    #[test]
+    fn test_boot_rom_draws_and_inverts() {
+        let mut ard = Arduboy::new();
+        ard.load_hex(&hex()).unwrap();
+        let lit = |ard: &Arduboy| ard.framebuffer_u32().iter().filter(|&&p| p & 0xFF > 0x80).count();
+        let expected: usize = screen().iter().map(|b| b.count_ones() as usize).sum();
+        for _ in 0..3 {
+            ard.run_frame();
+        }
+        assert!(ard.display_powered_on());
+        assert_eq!(lit(&ard), expected);
+
+        ard.set_button(Button::A, true);
+        ard.run_frame();
+        ard.run_frame();
+        assert_eq!(lit(&ard), 128 * 64 - expected);
+    }
+}
@@ -0,0 +1,159 @@
+//! Out-of-bounds access checker for global objects.
+//!
+//! The ELF symbol table records the address and size of every global and
+//! static object (the compiler takes the size from the object's type, so a
+//! `uint8_t enemies[20]` is 20 bytes). [`BoundsChecker`] uses them to flag
+//! loads and stores that cannot belong to the object the program meant:
+//!
+//! | Check | Flagged access |
+//! |-------|----------------|
+//! | Gap | Lands in `.bss` where no object is (padding, or past the last object) |
+//! | Displacement | `LDD`/`STD` with the pointer in one object and `pointer+q` in another or in a gap |
+//!
+//! Gaps are only checked in `.bss`: `.data` also holds string literals,
+//! which have no symbols. Accesses through `X`, `Y+`/`-Z` and the like carry
+//! no base, so a loop that runs from one array straight into the next is
+//! only caught when it reaches a gap. Each PC is reported once, into the
+//! shared [`Diagnostics`] sink.
+//!
+//! Extents come from the symbol sizes (`st_size`) alone, not from the
+//! DWARF types: an object is one block, so an index running from one array
+//! member of a struct into the next member, or from one row of a 2-D array
+//! into the next, stays inside it and is not reported. Objects without a
+//! symbol (string literals, and locals, which live on the stack) are not
+//! covered.
+
+use crate::diagnostics::Diagnostics;
+use crate::elf::{ElfFile, DATA_OFFSET};
+use std::collections::HashSet;
+
+/// A global object in data space.
+#[derive(Debug, Clone)]
+pub struct Object {
+    pub name: String,
+    pub start: u16,
+    pub size: u16,
+}
+
+impl Object {
+    fn contains(&self, addr: u16) -> bool {
+        addr >= self.start && (addr - self.start) < self.size
+    }
+}
+
+/// Object map and reported violations.
+#[derive(Debug, Clone, Default)]
+pub struct BoundsChecker {
+    /// Checking loads and stores
+    pub enabled: bool,
+    /// Objects sorted by address
+    objects: Vec<Object>,
+    /// `.bss` range `[start, end)` checked for gaps
+    bss: (u16, u16),
+    /// Violations seen, including repeats from reported PCs
+    pub violations: u64,
+    reported: HashSet<u32>,
+}
+
+impl BoundsChecker {
+    /// Object map from the ELF symbol table and `.bss` section.
+    pub fn from_elf(elf: &ElfFile) -> Result<Self, String> {
+        let mut objects: Vec<Object> = elf.symbols.iter()
+            .filter(|(&addr, _)| addr >= DATA_OFFSET)
+            .filter_map(|(&addr, name)| {
+                let size = elf.sizes.get(&addr).copied().unwrap_or(0);
+                (size > 0).then(|| Object {
+                    name: name.clone(),
+                    start: (addr - DATA_OFFSET) as u16,
+                    size: size.min(0xFFFF) as u16,
+                })
+            })
+            .collect();
+        if objects.is_empty() {
+            return Err("ELF file has no sized RAM objects in its symbol table".into());
+        }
+        objects.sort_by_key(|o| o.start);
+        let bss = elf.sections.iter()
+            .find(|(name, addr, _)| name == ".bss" && *addr >= DATA_OFFSET)
+            .map(|&(_, addr, size)| {
+                let start = addr - DATA_OFFSET;
+                (start as u16, (start + size).min(0xFFFF) as u16)
+            })
+            .unwrap_or((0, 0));
+        Ok(BoundsChecker { enabled: true, objects, bss, ..Default::default() })
+    }
+
+    /// Object containing data-space address `addr`.
+    pub fn object_at(&self, addr: u16) -> Option<&Object> {
+        self.object_before(addr).filter(|o| o.contains(addr))
+    }
+
+    /// Nearest object at or below `addr`.
+    fn object_before(&self, addr: u16) -> Option<&Object> {
+        let idx = self.objects.partition_point(|o| o.start <= addr);
+        idx.checked_sub(1).map(|i| &self.objects[i])
+    }
+
+    /// Check an access to `addr` by the instruction at flash word address
+    /// `pc`; `base` is the pointer for `LDD`/`STD` with a displacement.
+    pub fn check(&mut self, pc: u32, addr: u16, base: Option<u16>, write: bool, frame: u32, out: &mut Diagnostics) {
+        let target = self.object_at(addr);
+        let problem = match base.map(|b| (b, self.object_at(b))) {
+            Some((b, Some(from))) if !from.contains(addr) => Some(format!(
+                "ptr+{} from `{}` (0x{:04X}, {} bytes) reaches {}",
+                addr.wrapping_sub(b), from.name, from.start, from.size,
+                match target {
+                    Some(t) => format!("`{}`", t.name),
+                    None => "no object".into(),
+                })),
+            _ if target.is_none() && addr >= self.bss.0 && addr < self.bss.1 => Some(match self.object_before(addr) {
+                Some(o) => format!("offset {} of `{}` (0x{:04X}, {} bytes)",
+                    addr - o.start, o.name, o.start, o.size),
+                None => "before the first object in .bss".into(),
+            }),
+            _ => None,
+        };
+        let Some(problem) = problem else { return };
+        self.violations += 1;
+        if self.reported.insert(pc) {
+            out.report(format!("Bounds: PC=0x{:04X} frame {} {} 0x{:04X}: {}",
+                pc * 2, frame, if write { "write" } else { "read" }, addr, problem));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker() -> BoundsChecker {
+        BoundsChecker {
+            enabled: true,
+            objects: vec![
+                Object { name: "enemies".into(), start: 0x100, size: 20 },
+                Object { name: "player".into(), start: 0x118, size: 8 },
+            ],
+            bss: (0x100, 0x120),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_gap_and_displacement() {
+        let mut b = checker();
+        let mut out = Diagnostics::default();
+        b.check(0x10, 0x113, None, true, 1, &mut out);
+        b.check(0x11, 0x11C, Some(0x118), false, 1, &mut out);
+        assert!(out.take_messages().is_empty());
+
+        b.check(0x20, 0x115, None, true, 2, &mut out);
+        b.check(0x20, 0x116, None, true, 2, &mut out);
+        b.check(0x21, 0x11A, Some(0x10E), false, 2, &mut out);
+        b.check(0x22, 0x200, None, true, 2, &mut out);
+        assert_eq!(b.violations, 3);
+        let msgs = out.take_messages();
+        assert_eq!(msgs.len(), 2, "{:?}", msgs);
+        assert_eq!(msgs[0], "Bounds: PC=0x0040 frame 2 write 0x0115: offset 21 of `enemies` (0x0100, 20 bytes)");
+        assert!(msgs[1].contains("ptr+12 from `enemies`") && msgs[1].ends_with("reaches `player`"), "{}", msgs[1]);
+    }
+}
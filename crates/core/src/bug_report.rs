@@ -0,0 +1,201 @@
+//! One-step bug report bundles.
+//!
+//! [`CaptureRecorder`] keeps the recent past that a single snapshot lacks:
+//! the last few seconds of screens and the tail of the serial output.
+//! Together with the CPU's [`TraceTail`] it writes everything a developer
+//! needs to reproduce a player's report into one ZIP archive:
+//!
+//! | Member | Contents |
+//! |--------|----------|
+//! | `game.state` | Save state, loadable like a quick save (F9) |
+//! | `program.hex` | Flash image the state was taken with |
+//! | `screen.png` | Current screen |
+//! | `recent.gif` | Recorded frames up to now |
+//! | `serial.txt` | Serial output tail |
+//! | `trace.txt` | Last instructions executed, disassembled, oldest first |
+//! | `state.txt` | [`dump_full_state_text`](crate::Arduboy::dump_full_state_text) |
+//! | `config.txt` | Emulator version, CPU, display, frame, and frontend settings |
+//!
+//! Frontends call [`CaptureRecorder::record_frame`] after each presented
+//! frame and [`CaptureRecorder::record_serial`] with the output they
+//! consumed, then [`CaptureRecorder::bundle`] when the user asks for a
+//! report.
+
+use std::collections::VecDeque;
+
+use crate::Arduboy;
+
+/// Instructions kept by [`TraceTail`]
+pub const TRACE_LEN: usize = 4096;
+
+/// Ring of recently executed instruction addresses.
+#[derive(Debug, Clone)]
+pub struct TraceTail {
+    /// Recording (one store per instruction)
+    pub enabled: bool,
+    pcs: Vec<u32>,
+    next: usize,
+}
+
+impl Default for TraceTail {
+    fn default() -> Self {
+        TraceTail { enabled: false, pcs: Vec::with_capacity(TRACE_LEN), next: 0 }
+    }
+}
+
+impl TraceTail {
+    /// Record the flash word address of an instruction about to execute.
+    pub fn push(&mut self, pc: u32) {
+        if self.pcs.len() < TRACE_LEN {
+            self.pcs.push(pc);
+        } else {
+            self.pcs[self.next] = pc;
+        }
+        self.next = (self.next + 1) % TRACE_LEN;
+    }
+
+    /// Recorded word addresses, oldest first.
+    pub fn pcs(&self) -> Vec<u32> {
+        if self.pcs.len() < TRACE_LEN {
+            return self.pcs.clone();
+        }
+        let mut out = self.pcs[self.next..].to_vec();
+        out.extend_from_slice(&self.pcs[..self.next]);
+        out
+    }
+
+    pub fn clear(&mut self) {
+        self.pcs.clear();
+        self.next = 0;
+    }
+}
+
+/// Recent screens and serial output for [`bundle`](Self::bundle).
+#[derive(Debug, Clone)]
+pub struct CaptureRecorder {
+    max_frames: usize,
+    max_serial: usize,
+    /// Screens as shown, with their size
+    frames: VecDeque<(usize, usize, Vec<bool>)>,
+    serial: VecDeque<u8>,
+}
+
+impl CaptureRecorder {
+    /// Keep up to `max_frames` screens and `max_serial` bytes of output.
+    pub fn new(max_frames: usize, max_serial: usize) -> Self {
+        CaptureRecorder { max_frames, max_serial, frames: VecDeque::new(), serial: VecDeque::new() }
+    }
+
+    /// Record the screen as shown to the player (rotation applied).
+    pub fn record_frame(&mut self, ard: &Arduboy) {
+        let (px, w, h) = ard.screen_output();
+        // A size change (rotation, display switch) starts a new clip
+        if self.frames.back().is_some_and(|&(fw, fh, _)| (fw, fh) != (w, h)) {
+            self.frames.clear();
+        }
+        if self.frames.len() == self.max_frames {
+            self.frames.pop_front();
+        }
+        self.frames.push_back((w, h, px.iter().map(|&c| (c >> 16) & 0xFF > 128).collect()));
+    }
+
+    /// Append serial output.
+    pub fn record_serial(&mut self, bytes: &[u8]) {
+        self.serial.extend(bytes);
+        let excess = self.serial.len().saturating_sub(self.max_serial);
+        self.serial.drain(..excess);
+    }
+
+    /// Number of screens held.
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Build the report archive. `config` adds frontend settings to
+    /// `config.txt` after the core's own lines.
+    pub fn bundle(&self, ard: &mut Arduboy, config: &[(&str, String)]) -> Result<Vec<u8>, String> {
+        let state = crate::savestate::encode(&ard.save_full_state(), ard.cpu_type_byte())
+            .map_err(|e| format!("save state: {}", e))?;
+        let used = ard.mem.flash.iter().rposition(|&b| b != 0xFF).map_or(0, |i| (i + 2) & !1);
+        let (px, w, h) = ard.screen_output();
+        let screen: Vec<bool> = px.iter().map(|&c| (c >> 16) & 0xFF > 128).collect();
+
+        let mut files = vec![
+            ("game.state".to_string(), state),
+            ("program.hex".to_string(), crate::hex::to_hex(&ard.mem.flash[..used]).into_bytes()),
+            ("screen.png".to_string(), crate::png::encode_png_mono(w as u32, h as u32, &screen)),
+        ];
+        if let Some((gw, gh, _)) = self.frames.front() {
+            let mut gif = crate::gif::GifEncoder::new(*gw as u16, *gh as u16, 2);
+            for (_, _, px) in &self.frames {
+                gif.add_frame_mono(px);
+            }
+            files.push(("recent.gif".to_string(), gif.finish()));
+        }
+        files.push(("serial.txt".to_string(), self.serial.iter().copied().collect()));
+        files.push(("trace.txt".to_string(), trace_text(ard).into_bytes()));
+        files.push(("state.txt".to_string(), ard.dump_full_state_text().into_bytes()));
+
+        let mut cfg = vec![
+            ("emulator", format!("arduboy-emu {}", env!("CARGO_PKG_VERSION"))),
+            ("cpu", format!("{:?}", ard.cpu_type)),
+            ("display", format!("{:?}", ard.display_type)),
+            ("rotation", ard.rotation.degrees().to_string()),
+            ("frame", ard.frame_count().to_string()),
+            ("cycles", ard.cpu.tick.to_string()),
+            ("pc", format!("0x{:04X}", ard.cpu.pc * 2)),
+            ("recorded_frames", self.frames.len().to_string()),
+        ];
+        cfg.extend(config.iter().map(|(k, v)| (*k, v.clone())));
+        let text: String = cfg.iter().map(|(k, v)| format!("{} = {}\n", k, v)).collect();
+        files.push(("config.txt".to_string(), text.into_bytes()));
+        Ok(crate::package::write_zip(&files))
+    }
+}
+
+/// The CPU's trace tail, one disassembled instruction per line.
+fn trace_text(ard: &Arduboy) -> String {
+    if !ard.trace_tail.enabled {
+        return "(instruction trace was off)\n".to_string();
+    }
+    let mut out = String::new();
+    for pc in ard.trace_tail.pcs() {
+        let word = ard.mem.read_program_word(pc as usize);
+        let next = ard.mem.read_program_word((pc as usize + 1) % (ard.mem.flash.len() / 2));
+        let (inst, _) = crate::opcodes::decode(word, next);
+        out.push_str(&format!("0x{:04X}: {}\n", pc * 2, crate::disasm::disassemble(inst, pc)));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundle_members() {
+        let mut ard = Arduboy::new();
+        ard.load_hex(&crate::boot_rom::hex()).unwrap();
+        ard.trace_tail.enabled = true;
+        let mut rec = CaptureRecorder::new(3, 8);
+        for _ in 0..5 {
+            ard.run_frame();
+            rec.record_frame(&ard);
+        }
+        rec.record_serial(b"hello, world");
+        assert_eq!(rec.frame_count(), 3);
+        assert_eq!(ard.trace_tail.pcs().len(), TRACE_LEN);
+
+        let zip = rec.bundle(&mut ard, &[("game", "boot".into())]).unwrap();
+        let ab = crate::arduboy_file::parse_arduboy(&zip).unwrap().files;
+        assert_eq!(ab["serial.txt"], b"o, world");
+        let cfg = String::from_utf8(ab["config.txt"].clone()).unwrap();
+        assert!(cfg.contains("frame = 5\n") && cfg.ends_with("game = boot\n"), "{}", cfg);
+        let trace = String::from_utf8(ab["trace.txt"].clone()).unwrap();
+        assert_eq!(trace.lines().count(), TRACE_LEN);
+        assert!(ab["recent.gif"].starts_with(b"GIF89a"));
+
+        let state = crate::savestate::decode(&ab["game.state"], ard.cpu_type_byte()).unwrap();
+        assert_eq!(state.frame_count, 5);
+    }
+}
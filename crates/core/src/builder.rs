@@ -0,0 +1,358 @@
+//! Validated construction of a customized [`Arduboy`].
+//!
+//! [`Arduboy::new_with_cpu`] gives a bare machine; everything else (board
+//! wiring, display, program, FX data, EEPROM, diagnostics) is public fields
+//! and methods that must be applied in the right order: board before
+//! display, display before program, program before FX and EEPROM (loading
+//! a program resets the CPU). [`ArduboyBuilder`] does that and checks that
+//! the settings fit together first:
+//!
+//! ```no_run
+//! use arduboy_core::{Arduboy, builder::Recorder};
+//!
+//! let hex = std::fs::read_to_string("game.hex").unwrap();
+//! let arduboy: Arduboy = Arduboy::builder()
+//!     .board_name("arduboy-fx")
+//!     .hex(&hex)
+//!     .fx(std::fs::read("game-fx.bin").unwrap(), None)
+//!     .seed(1234)
+//!     .breakpoint(0x1A4 / 2)
+//!     .record(Recorder::FrameTimeline)
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! | Setting | Default |
+//! |---------|---------|
+//! | CPU | from the board, else detected from the program, else ATmega32u4 |
+//! | Display | inferred from the CPU and SPI traffic |
+//! | FX flash | empty (erased) |
+//! | EEPROM | blank (0x00) |
+//! | Seed | fixed (`0xDEADBEEF`) |
+//! | Fuses | [`Fuses::for_cpu`] (no CKDIV8, BOOTRST unprogrammed) |
+//! | Clock | [`CLOCK_HZ`](crate::CLOCK_HZ) (16 MHz) |
+//! | Fast boot | off (see [`fast_boot`](crate::fast_boot)) |
+
+use crate::error::{BuildError, Error};
+use crate::expr::Symbols;
+use crate::fuses::Fuses;
+use crate::{Arduboy, BoardProfile, CpuType, DisplayQuirks, DisplayType, LogConfig, PanelGeometry};
+use crate::{CLOCK_HZ_RANGE, EEPROM_SIZE, FLASH_SIZE, FLASH_SIZE_2560};
+
+/// An off-by-default recorder to start before the first frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recorder {
+    /// Execution profiler ([`Arduboy::profiler`])
+    Profiler,
+    /// SPI transaction decoder ([`Arduboy::spi_decoder`])
+    SpiDecoder,
+    /// Byte-level SPI trace ([`Arduboy::spi_trace`])
+    SpiTrace,
+    /// Per-frame event timeline ([`Arduboy::frame_timeline`])
+    FrameTimeline,
+    /// Host-time phase profile ([`Arduboy::perf_trace`])
+    PerfTrace,
+}
+
+/// Settings for a new [`Arduboy`]; see the module docs.
+#[derive(Default)]
+pub struct ArduboyBuilder {
+    cpu: Option<CpuType>,
+    board: Option<Result<BoardProfile, String>>,
+    display: Option<DisplayType>,
+    quirks: Option<DisplayQuirks>,
+    geometry: Option<PanelGeometry>,
+    hex: Option<String>,
+    fx: Option<(Vec<u8>, Option<Vec<u8>>)>,
+    eeprom: Option<Vec<u8>>,
+    fuses: Option<Fuses>,
+    clock_hz: Option<u32>,
+    fast_boot: bool,
+    debug: bool,
+    log: Vec<String>,
+    seed: Option<u32>,
+    breakpoints: Vec<u32>,
+    tracepoints: Vec<String>,
+    recorders: Vec<Recorder>,
+}
+
+impl ArduboyBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// CPU type; must match the board's if both are given.
+    pub fn cpu(mut self, cpu: CpuType) -> Self {
+        self.cpu = Some(cpu);
+        self
+    }
+
+    /// Board profile (CPU, display, pin wiring, FX flash).
+    pub fn board(mut self, profile: BoardProfile) -> Self {
+        self.board = Some(Ok(profile));
+        self
+    }
+
+    /// Built-in board name or TOML file, as accepted by [`BoardProfile::load`].
+    pub fn board_name(mut self, spec: &str) -> Self {
+        self.board = Some(BoardProfile::load(spec));
+        self
+    }
+
+    /// Display type instead of detecting it from SPI traffic.
+    pub fn display(mut self, display: DisplayType) -> Self {
+        self.display = Some(display);
+        self
+    }
+
+    /// OLED controller variant (SSD1306 family only).
+    pub fn display_quirks(mut self, quirks: DisplayQuirks) -> Self {
+        self.quirks = Some(quirks);
+        self
+    }
+
+    /// Panel size for clone hardware (SSD1306 family only).
+    pub fn panel_geometry(mut self, geometry: PanelGeometry) -> Self {
+        self.geometry = Some(geometry);
+        self
+    }
+
+    /// Program in Intel HEX format.
+    pub fn hex(mut self, hex: &str) -> Self {
+        self.hex = Some(hex.to_string());
+        self
+    }
+
+    /// FX flash data and optional save area, placed by
+    /// [`Arduboy::load_fx_layout`].
+    pub fn fx(mut self, data: Vec<u8>, save: Option<Vec<u8>>) -> Self {
+        self.fx = Some((data, save));
+        self
+    }
+
+    /// Initial EEPROM contents (at most [`EEPROM_SIZE`] bytes).
+    pub fn eeprom(mut self, data: Vec<u8>) -> Self {
+        self.eeprom = Some(data);
+        self
+    }
+
+    /// Fuse bytes; CKDIV8 and BOOTRST apply from the first reset.
+    pub fn fuses(mut self, fuses: Fuses) -> Self {
+        self.fuses = Some(fuses);
+        self
+    }
+
+    /// Oscillator frequency in Hz, e.g. 8 MHz for clones (see
+    /// [`Arduboy::clock_hz`]).
+    pub fn clock_hz(mut self, hz: u32) -> Self {
+        self.clock_hz = Some(hz);
+        self
+    }
+
+    /// Detect the Arduboy2 boot logo so the frontend can skip it (see
+    /// [`Arduboy::fast_boot`]).
+    pub fn fast_boot(mut self, on: bool) -> Self {
+        self.fast_boot = on;
+        self
+    }
+
+    /// Enable all diagnostic output.
+    pub fn debug(mut self, on: bool) -> Self {
+        self.debug = on;
+        self
+    }
+
+    /// Diagnostic levels, as accepted by [`LogConfig::apply`]; may be repeated.
+    pub fn log(mut self, spec: &str) -> Self {
+        self.log.push(spec.to_string());
+        self
+    }
+
+    /// Seed of the emulator PRNG (ADC noise, random floating pins); non-zero.
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Breakpoint at a flash word address.
+    pub fn breakpoint(mut self, word_addr: u32) -> Self {
+        self.breakpoints.push(word_addr);
+        self
+    }
+
+    /// Tracepoint, as accepted by [`Tracepoint::parse`](crate::tracepoint::Tracepoint::parse).
+    pub fn tracepoint(mut self, spec: &str) -> Self {
+        self.tracepoints.push(spec.to_string());
+        self
+    }
+
+    /// Start a recorder before the first frame.
+    pub fn record(mut self, recorder: Recorder) -> Self {
+        self.recorders.push(recorder);
+        self
+    }
+
+    /// Check the settings and create the emulator.
+    pub fn build(self) -> Result<Arduboy, Error> {
+        let board = self.board.transpose().map_err(BuildError::Board)?;
+        // Parsed up front to report errors and detect the CPU
+        let flash = match &self.hex {
+            Some(hex) => {
+                let mut buf = vec![0u8; FLASH_SIZE];
+                crate::hex::parse_hex(hex, &mut buf)?;
+                Some(buf)
+            }
+            None => None,
+        };
+
+        let cpu = match (&board, self.cpu) {
+            (Some(b), Some(c)) if b.cpu != c => {
+                return Err(BuildError::CpuMismatch { board: b.name.clone(), board_cpu: b.cpu, cpu: c }.into());
+            }
+            (Some(b), _) => b.cpu,
+            (None, Some(c)) => c,
+            (None, None) => flash.as_deref().map(crate::detect_cpu_type).unwrap_or(CpuType::Atmega32u4),
+        };
+        if let (Some(b), Some(_)) = (&board, &self.fx) {
+            if !b.fx {
+                return Err(BuildError::NoFx { board: b.name.clone() }.into());
+            }
+        }
+        let display = self.display.or(board.as_ref().map(|b| b.display));
+        if display == Some(DisplayType::Pcd8544) && (self.quirks.is_some() || self.geometry.is_some()) {
+            return Err(BuildError::DisplayOptions.into());
+        }
+        if let Some(e) = &self.eeprom {
+            if e.len() > EEPROM_SIZE {
+                return Err(BuildError::EepromSize { size: e.len(), max: EEPROM_SIZE }.into());
+            }
+        }
+        if let Some(hz) = self.clock_hz.filter(|hz| !CLOCK_HZ_RANGE.contains(hz)) {
+            return Err(BuildError::Clock { hz }.into());
+        }
+        if self.seed == Some(0) {
+            return Err(BuildError::ZeroSeed.into());
+        }
+        let flash_size = if cpu == CpuType::Atmega2560 { FLASH_SIZE_2560 } else { FLASH_SIZE };
+        if let Some(bp) = self.breakpoints.iter().find(|&&bp| bp as usize >= flash_size / 2) {
+            return Err(BuildError::Breakpoint { addr: *bp * 2 }.into());
+        }
+        let mut log = LogConfig::new();
+        for spec in &self.log {
+            log.apply(spec).map_err(BuildError::Log)?;
+        }
+
+        let mut ard = match board {
+            Some(b) => Arduboy::with_board(b),
+            None => Arduboy::new_with_cpu(cpu),
+        };
+        if let Some(d) = self.display {
+            ard.display_type = d;
+        }
+        if let Some(q) = self.quirks {
+            ard.set_display_quirks(q);
+        }
+        if let Some(g) = self.geometry {
+            ard.set_panel_geometry(g);
+        }
+        if let Some(f) = self.fuses {
+            ard.set_fuses(f);
+            ard.reset();
+        }
+        if let Some(hex) = &self.hex {
+            ard.load_hex(hex)?;
+        }
+        if let Some((data, save)) = &self.fx {
+            ard.load_fx_layout(data, save.as_deref());
+        }
+        if let Some(e) = &self.eeprom {
+            ard.load_eeprom(e);
+        }
+        if let Some(hz) = self.clock_hz {
+            ard.clock_hz = hz;
+        }
+        ard.fast_boot.enabled = self.fast_boot;
+        ard.debug = self.debug;
+        ard.log = log;
+        if let Some(seed) = self.seed {
+            ard.rng_state = seed;
+        }
+        ard.breakpoints = self.breakpoints;
+        for spec in &self.tracepoints {
+            ard.tracepoints.add(spec, &Symbols::default())
+                .map_err(|detail| BuildError::Tracepoint { spec: spec.clone(), detail })?;
+        }
+        for r in self.recorders {
+            match r {
+                Recorder::Profiler => ard.profiler.start(ard.cpu.tick),
+                Recorder::SpiDecoder => ard.spi_decoder.start(),
+                Recorder::SpiTrace => ard.spi_trace.start(),
+                Recorder::FrameTimeline => ard.frame_timeline.start(),
+                Recorder::PerfTrace => ard.perf_trace.start(),
+            }
+        }
+        Ok(ard)
+    }
+}
+
+impl Arduboy {
+    /// Start building a customized emulator.
+    pub fn builder() -> ArduboyBuilder {
+        ArduboyBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RJMP .-2 at address 0
+    const LOOP_HEX: &str = ":02000000FFCF30\n:00000001FF\n";
+
+    #[test]
+    fn test_build() {
+        let a = Arduboy::builder()
+            .board_name("arduboy-fx")
+            .hex(LOOP_HEX)
+            .fx(vec![0xAB; 256], None)
+            .eeprom(vec![7; 16])
+            .seed(42)
+            .fuses(Fuses { low: 0x7F, high: 0xD1, extended: None })
+            .clock_hz(8_000_000)
+            .fast_boot(true)
+            .breakpoint(0x10)
+            .tracepoint(r#"0x0 "loop""#)
+            .record(Recorder::FrameTimeline)
+            .build()
+            .unwrap();
+        assert_eq!((a.mem.flash[0], a.mem.flash[1]), (0xFF, 0xCF));
+        assert_eq!(a.fx_flash.data[16 * 1024 * 1024 - 256], 0xAB);
+        assert_eq!(a.mem.eeprom[15], 7);
+        assert_eq!(a.rng_state, 42);
+        assert_eq!(a.clock_div(), 8);
+        assert_eq!(a.clock_hz, 8_000_000);
+        assert!(a.fast_boot.enabled);
+        assert_eq!(a.breakpoints, vec![0x10]);
+        assert!(a.tracepoints.at(0) && a.frame_timeline.enabled);
+        assert!(a.board.is_some());
+    }
+
+    #[test]
+    fn test_validation() {
+        let err = |b: ArduboyBuilder| b.build().err().unwrap().to_string();
+        assert!(err(Arduboy::builder().board_name("gamebuino").cpu(CpuType::Atmega32u4)).contains("CPU Atmega328p"));
+        assert!(err(Arduboy::builder().board_name("arduboy").fx(vec![0; 16], None)).contains("no FX flash"));
+        assert!(err(Arduboy::builder().board_name("gamebuino")
+            .panel_geometry(PanelGeometry::parse("72x40").unwrap())).contains("SSD1306"));
+        assert!(err(Arduboy::builder().eeprom(vec![0; 2048])).contains("EEPROM"));
+        assert!(err(Arduboy::builder().seed(0)).contains("seed"));
+        assert!(err(Arduboy::builder().clock_hz(100_000_000)).contains("clock"));
+        assert!(err(Arduboy::builder().breakpoint(0x8000)).contains("outside flash"));
+        assert!(Arduboy::builder().cpu(CpuType::Atmega2560).breakpoint(0x1_0000).build().is_ok());
+        assert!(err(Arduboy::builder().log("cpu=loud")).contains("log level"));
+        assert!(err(Arduboy::builder().board_name("nope")).contains("nope"));
+        assert!(matches!(Arduboy::builder().board_name("gamebuino").cpu(CpuType::Atmega32u4).build(),
+            Err(Error::Build(BuildError::CpuMismatch { board_cpu: CpuType::Atmega328p, .. }))));
+        assert!(matches!(Arduboy::builder().hex(":02000000FFCF00\n").build(), Err(Error::Hex { line: 1, .. })));
+    }
+}
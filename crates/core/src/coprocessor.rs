@@ -0,0 +1,307 @@
+//! Scriptable serial co-processor stub.
+//!
+//! Some modded Arduboys wire an ESP8266 (or another module) to the serial
+//! port for wireless features. [`Coprocessor`] stands in for it: it reads
+//! the commands the game writes to USB CDC / USART and answers them from a
+//! TOML script, so such firmware can be developed without the hardware.
+//!
+//! ```toml
+//! terminator = "\r\n"   # ends commands and reply lines (default "\r\n")
+//! echo = true           # send each command back first (AT-style)
+//! boot = ["", "ready"]  # lines sent once at start-up
+//! unknown = "ERROR"     # reply to commands no rule matches (default: none)
+//!
+//! [[rule]]
+//! match = "AT+CWJAP=*"
+//! reply = ["WIFI CONNECTED", "WIFI GOT IP", "", "OK"]
+//! delay = 60            # frames before the reply (default 0)
+//!
+//! [[rule]]
+//! match = "PING *"
+//! reply = "PONG $1"
+//!
+//! [[rule]]
+//! match = "ATE0"
+//! reply = "OK"
+//! echo = false          # a rule may switch command echo
+//! ```
+//!
+//! Rules are tried in file order. In `match`, `*` stands for any text and
+//! `$1`..`$9` in the reply insert what the wildcards matched. Replies are
+//! delivered in order: a delayed reply holds back those after it, like a
+//! busy module. [`ESP_AT`] is a built-in script for the common ESP8266 AT
+//! commands (`--coproc esp`).
+
+use std::collections::VecDeque;
+use std::path::Path;
+
+use crate::toml_lite;
+
+/// Built-in ESP8266 AT firmware script.
+pub const ESP_AT: &str = r#"
+echo = true
+boot = ["", "ready"]
+unknown = "ERROR"
+
+[[rule]]
+match = "AT"
+reply = "OK"
+
+[[rule]]
+match = "ATE0"
+reply = "OK"
+echo = false
+
+[[rule]]
+match = "ATE1"
+reply = "OK"
+echo = true
+
+[[rule]]
+match = "AT+RST"
+reply = ["OK", "", "ready"]
+delay = 30
+
+[[rule]]
+match = "AT+GMR"
+reply = ["AT version:1.7.4.0 (emulated)", "OK"]
+
+[[rule]]
+match = "AT+CWMODE*"
+reply = "OK"
+
+[[rule]]
+match = "AT+CWJAP=*"
+reply = ["WIFI CONNECTED", "WIFI GOT IP", "", "OK"]
+delay = 60
+
+[[rule]]
+match = "AT+CWQAP"
+reply = ["OK", "WIFI DISCONNECT"]
+
+[[rule]]
+match = "AT+CIFSR"
+reply = ["+CIFSR:STAIP,\"192.168.4.2\"", "+CIFSR:STAMAC,\"5c:cf:7f:00:00:01\"", "", "OK"]
+"#;
+
+/// Longest command kept while waiting for a terminator
+const MAX_LINE: usize = 1024;
+
+/// A command pattern and its answer.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    /// Pattern with `*` wildcards
+    pub pattern: String,
+    /// Reply lines, with `$n` captures
+    pub reply: Vec<String>,
+    /// Frames before the reply is sent
+    pub delay: u32,
+    /// Turn command echo on or off
+    pub echo: Option<bool>,
+}
+
+/// Co-processor state; see the module docs.
+#[derive(Debug, Clone)]
+pub struct Coprocessor {
+    pub terminator: String,
+    pub echo: bool,
+    pub rules: Vec<Rule>,
+    pub unknown: Option<String>,
+    /// Bytes from the game since the last terminator
+    line: Vec<u8>,
+    /// Replies waiting for their frame, in order
+    queue: VecDeque<(u64, Vec<u8>)>,
+    frame: u64,
+    /// Commands received
+    pub commands: u64,
+}
+
+impl Coprocessor {
+    /// Parse a script (see the module docs).
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let doc = toml_lite::parse(text)?;
+        let mut c = Coprocessor {
+            terminator: "\r\n".to_string(), echo: false, rules: Vec::new(), unknown: None,
+            line: Vec::new(), queue: VecDeque::new(), frame: 0, commands: 0,
+        };
+        let mut boot = Vec::new();
+        for (key, value) in &doc.root {
+            match key.as_str() {
+                "terminator" => c.terminator = value.as_str(key)?.to_string(),
+                "echo" => c.echo = value.as_bool(key)?,
+                "boot" => boot = lines(value, key)?,
+                "unknown" => c.unknown = Some(value.as_str(key)?.to_string()),
+                _ => return Err(format!("unknown key '{}'", key)),
+            }
+        }
+        if c.terminator.is_empty() {
+            return Err("terminator: must not be empty".into());
+        }
+        for (table, t) in &doc.tables {
+            if table != "rule" {
+                return Err(format!("[[{}]]: unknown table (expected [[rule]])", table));
+            }
+            let pattern = t.get("match").ok_or("[[rule]]: missing match")?.as_str("match")?.to_string();
+            let err = |e: String| format!("rule \"{}\": {}", pattern, e);
+            let mut rule = Rule { pattern: pattern.clone(), reply: Vec::new(), delay: 0, echo: None };
+            for (key, value) in &t.0 {
+                match key.as_str() {
+                    "match" => {}
+                    "reply" => rule.reply = lines(value, key).map_err(err)?,
+                    "delay" => rule.delay = value.as_u32(key).map_err(err)?,
+                    "echo" => rule.echo = Some(value.as_bool(key).map_err(err)?),
+                    _ => return Err(err(format!("unknown key '{}'", key))),
+                }
+            }
+            c.rules.push(rule);
+        }
+        if !boot.is_empty() {
+            let bytes = c.render(&boot, &[]);
+            c.queue.push_back((0, bytes));
+        }
+        Ok(c)
+    }
+
+    /// Load a script file, or the built-in [`ESP_AT`] script for `esp`.
+    pub fn load(spec: &str) -> Result<Self, String> {
+        if spec.eq_ignore_ascii_case("esp") {
+            return Self::parse(ESP_AT);
+        }
+        let path = Path::new(spec);
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Serial bytes written by the game.
+    pub fn feed(&mut self, data: &[u8]) {
+        let term = self.terminator.as_bytes().to_vec();
+        for &b in data {
+            if self.line.len() == MAX_LINE {
+                self.line.remove(0);
+            }
+            self.line.push(b);
+            if self.line.ends_with(&term) {
+                self.line.truncate(self.line.len() - term.len());
+                let cmd = String::from_utf8_lossy(&std::mem::take(&mut self.line)).into_owned();
+                self.command(&cmd);
+            }
+        }
+    }
+
+    /// Answer one command.
+    fn command(&mut self, cmd: &str) {
+        self.commands += 1;
+        if self.echo {
+            let mut echo = cmd.as_bytes().to_vec();
+            echo.extend_from_slice(self.terminator.as_bytes());
+            self.queue.push_back((self.frame, echo));
+        }
+        let mut out = Vec::new();
+        let found = self.rules.iter()
+            .find_map(|r| glob(&r.pattern, cmd).map(|caps| (r.clone(), caps)));
+        let delay = match found {
+            Some((rule, caps)) => {
+                if let Some(echo) = rule.echo {
+                    self.echo = echo;
+                }
+                out.extend(self.render(&rule.reply, &caps));
+                rule.delay
+            }
+            None => {
+                if let Some(u) = self.unknown.clone() {
+                    out.extend(self.render(&[u], &[]));
+                }
+                0
+            }
+        };
+        if !out.is_empty() {
+            self.queue.push_back((self.frame + delay as u64, out));
+        }
+    }
+
+    /// Reply lines with captures substituted, each ended by the terminator.
+    fn render(&self, reply: &[String], caps: &[&str]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for line in reply {
+            let mut s = line.clone();
+            for (i, cap) in caps.iter().enumerate().take(9).rev() {
+                s = s.replace(&format!("${}", i + 1), cap);
+            }
+            out.extend_from_slice(s.as_bytes());
+            out.extend_from_slice(self.terminator.as_bytes());
+        }
+        out
+    }
+
+    /// After a frame: the reply bytes now due for the game.
+    pub fn tick(&mut self) -> Vec<u8> {
+        let mut out = Vec::new();
+        while self.queue.front().is_some_and(|(due, _)| *due <= self.frame) {
+            if let Some((_, bytes)) = self.queue.pop_front() {
+                out.extend(bytes);
+            }
+        }
+        self.frame += 1;
+        out
+    }
+}
+
+/// A string or an array of strings, as lines.
+fn lines(value: &toml_lite::Value, key: &str) -> Result<Vec<String>, String> {
+    match value {
+        toml_lite::Value::Str(s) => Ok(vec![s.clone()]),
+        v => v.as_array(key)?.iter().map(|l| l.as_str(key).map(str::to_string)).collect(),
+    }
+}
+
+/// Match `text` against `pattern` with `*` wildcards; the captures on success.
+fn glob<'a>(pattern: &str, text: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let mut rest = text.strip_prefix(first)?;
+    let mut caps = Vec::new();
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        // The last part anchors at the end; others take the first occurrence
+        let at = if i + 1 == parts.len() {
+            rest.len().checked_sub(part.len()).filter(|&at| rest.is_char_boundary(at) && rest[at..] == **part)?
+        } else {
+            rest.find(part)?
+        };
+        caps.push(&rest[..at]);
+        rest = &rest[at + part.len()..];
+    }
+    rest.is_empty().then_some(caps)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_esp_script() {
+        let mut c = Coprocessor::load("esp").unwrap();
+        assert_eq!(c.tick(), b"\r\nready\r\n");
+        c.feed(b"AT\r\nAT+GMR\r");
+        assert_eq!(c.tick(), b"AT\r\nOK\r\n");
+        c.feed(b"\nATE0\r\nAT+CWJAP=\"home\",\"pw\"\r\nAT\r\nAT+FOO\r\n");
+        assert_eq!(c.tick(), b"AT+GMR\r\nAT version:1.7.4.0 (emulated)\r\nOK\r\nATE0\r\nOK\r\n");
+        // The join reply holds back the rest for 60 frames
+        for _ in 0..59 {
+            assert!(c.tick().is_empty());
+        }
+        assert_eq!(c.tick(), b"WIFI CONNECTED\r\nWIFI GOT IP\r\n\r\nOK\r\nOK\r\nERROR\r\n");
+        assert_eq!(c.commands, 6);
+    }
+
+    #[test]
+    fn test_custom_protocol() {
+        let mut c = Coprocessor::parse("terminator = \"\\n\"\n[[rule]]\nmatch = \"GET *:*\"\nreply = \"VAL $2 $1\"\n").unwrap();
+        c.feed(b"GET a:b\nPUT x\n");
+        assert_eq!(c.tick(), b"VAL b a\n");
+        assert_eq!(glob("A*", "ABC"), Some(vec!["BC"]));
+        assert_eq!(glob("A", "AB"), None);
+        assert_eq!(glob("*x*y", "1x2y"), Some(vec!["1", "2"]));
+        assert!(Coprocessor::parse("[[rule]]\nreply = \"x\"").unwrap_err().contains("missing match"));
+    }
+}
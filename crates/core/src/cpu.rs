@@ -9,11 +9,45 @@
 //! the tricky carry-chain behavior of SBC/SBCI/CPC where the Z flag is
 //! only cleared (never set) to support multi-byte comparisons.
 
-use crate::memory::Memory;
+use crate::memory::{Memory, RWWSB};
 use crate::opcodes::Instruction;
-use crate::{Arduboy, SREG_ADDR, SPH_ADDR, SPL_ADDR};
+use crate::{Arduboy, CpuType, LogCategory, LogLevel, SREG_ADDR, SPH_ADDR, SPL_ADDR, EIND_ADDR, RAMPZ_ADDR};
 use crate::{SREG_C, SREG_Z, SREG_N, SREG_V, SREG_S, SREG_H, SREG_T, SREG_I};
 
+/// Program counter width: 22 bits on the ATmega2560. Relative jumps wrap
+/// within it, and [`Arduboy::run_frame`] restarts a PC beyond flash at 0.
+pub const PC_MASK: u32 = 0x3F_FFFF;
+
+/// Cycle costs that differ between devices (AVR instruction set manual,
+/// megaAVR columns). Everything else costs the same on all three chips:
+/// loads and stores (LD/ST/LDS/STS/PUSH/POP) take 2 cycles whether they
+/// reach SRAM or I/O registers, IN/OUT 1, LPM/ELPM 3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timing {
+    pub call: u8,
+    pub rcall: u8,
+    pub icall: u8,
+    pub eicall: u8,
+    pub ret: u8,
+    pub reti: u8,
+    /// From the end of the current instruction to the first vector instruction
+    pub interrupt: u8,
+}
+
+/// 16-bit PC: the return address is two bytes.
+const TIMING_16BIT_PC: Timing = Timing { call: 4, rcall: 3, icall: 3, eicall: 3, ret: 4, reti: 4, interrupt: 4 };
+/// 22-bit PC (ATmega2560): one more cycle for each pushed or popped
+/// return address.
+const TIMING_22BIT_PC: Timing = Timing { call: 5, rcall: 4, icall: 4, eicall: 4, ret: 5, reti: 5, interrupt: 5 };
+
+/// Timing table of `cpu`.
+pub fn timing(cpu: CpuType) -> &'static Timing {
+    match cpu {
+        CpuType::Atmega2560 => &TIMING_22BIT_PC,
+        CpuType::Atmega32u4 | CpuType::Atmega328p => &TIMING_16BIT_PC,
+    }
+}
+
 /// CPU state for ATmega32u4.
 ///
 /// Contains the program counter, stack pointer, status register (SREG),
@@ -21,7 +55,7 @@ use crate::{SREG_C, SREG_Z, SREG_N, SREG_V, SREG_S, SREG_H, SREG_T, SREG_I};
 /// lives in [`Memory::data`] at offsets 0x00–0x1F.
 pub struct Cpu {
     /// Program counter (word address, not byte address)
-    pub pc: u16,
+    pub pc: u32,
     /// Stack pointer (byte address in data space)
     pub sp: u16,
     /// Status register: I T H S V N Z C (bits 7..0)
@@ -100,12 +134,15 @@ pub fn flags_logic(cpu: &mut Cpu, r: u8) {
 
 /// Skip the next instruction (for CPSE, SBRC, SBRS, SBIC, SBIS).
 ///
-/// Advances PC by 1 or 2 depending on whether the next instruction is 32-bit.
-pub fn skip_next(cpu: &mut Cpu, mem: &Memory) {
+/// Advances PC by 1 or 2 depending on whether the next instruction is 32-bit
+/// and returns the cycles the skipping instruction takes (2 or 3).
+pub fn skip_next(cpu: &mut Cpu, mem: &Memory) -> u8 {
     let nw = mem.read_program_word(cpu.pc as usize);
     let is_32 = (nw & 0xFE0E == 0x940C) || (nw & 0xFE0E == 0x940E)
              || (nw & 0xFE0F == 0x9000) || (nw & 0xFE0F == 0x9200);
-    cpu.pc = cpu.pc.wrapping_add(if is_32 { 2 } else { 1 });
+    let words = if is_32 { 2 } else { 1 };
+    cpu.pc = cpu.pc.wrapping_add(words);
+    1 + words as u8
 }
 
 // ---- Instruction execution on Arduboy ----
@@ -117,7 +154,7 @@ impl Arduboy {
     /// allowing memory-mapped I/O writes to reach peripherals (SPI, timers, etc.)
     /// via [`write_data`](Self::write_data) and [`read_data`](Self::read_data).
     pub fn execute_inst(&mut self, inst: Instruction, size: u8) -> u8 {
-        self.cpu.pc = self.cpu.pc.wrapping_add(size as u16);
+        self.cpu.pc = self.cpu.pc.wrapping_add(size as u32);
 
         match inst {
             Instruction::Nop => 1,
@@ -206,7 +243,8 @@ impl Arduboy {
                 let res = !self.mem.reg(d);
                 self.mem.set_reg(d, res);
                 let n = (res >> 7) & 1; let z = if res == 0 { 1u8 } else { 0 }; let s = n;
-                self.cpu.sreg = (self.cpu.sreg & 0b1100_0000) | (s << 4) | (n << 2) | (z << 1) | 1;
+                // H is unaffected
+                self.cpu.sreg = (self.cpu.sreg & 0b1110_0000) | (s << 4) | (n << 2) | (z << 1) | 1;
                 sync_sreg(&self.cpu, &mut self.mem); 1
             }
             Instruction::Neg { d } => {
@@ -257,26 +295,32 @@ impl Arduboy {
                 sync_sreg(&self.cpu, &mut self.mem); 2
             }
             Instruction::Fmul { d, r } => {
-                let res = ((self.mem.reg(d) as u16) * (self.mem.reg(r) as u16)) << 1;
+                // C is bit 15 of the product before the shift
+                let prod = (self.mem.reg(d) as u16) * (self.mem.reg(r) as u16);
+                let res = prod << 1;
                 self.mem.set_reg(0, res as u8); self.mem.set_reg(1, (res >> 8) as u8);
-                let c = if res & 0x8000 != 0 { 1u8 } else { 0 };
-                let z = if (res & 0xFFFF) == 0 { 1u8 } else { 0 };
+                let c = if prod & 0x8000 != 0 { 1u8 } else { 0 };
+                let z = if res == 0 { 1u8 } else { 0 };
                 self.cpu.sreg = (self.cpu.sreg & 0b1111_1100) | (z << 1) | c;
                 sync_sreg(&self.cpu, &mut self.mem); 2
             }
             Instruction::Fmuls { d, r } => {
-                let res = (((self.mem.reg(d) as i8 as i16) * (self.mem.reg(r) as i8 as i16)) << 1) as u16;
+                // C is bit 15 of the product before the shift
+                let prod = ((self.mem.reg(d) as i8 as i16) * (self.mem.reg(r) as i8 as i16)) as u16;
+                let res = prod << 1;
                 self.mem.set_reg(0, res as u8); self.mem.set_reg(1, (res >> 8) as u8);
-                let c = if res & 0x8000 != 0 { 1u8 } else { 0 };
+                let c = if prod & 0x8000 != 0 { 1u8 } else { 0 };
                 let z = if res == 0 { 1u8 } else { 0 };
                 self.cpu.sreg = (self.cpu.sreg & 0b1111_1100) | (z << 1) | c;
                 sync_sreg(&self.cpu, &mut self.mem); 2
             }
             Instruction::Fmulsu { d, r } => {
                 // Rd signed × Rr unsigned, result << 1
-                let res = (((self.mem.reg(d) as i8 as i16) * (self.mem.reg(r) as i16)) << 1) as u16;
+                // C is bit 15 of the product before the shift
+                let prod = ((self.mem.reg(d) as i8 as i16) * (self.mem.reg(r) as i16)) as u16;
+                let res = prod << 1;
                 self.mem.set_reg(0, res as u8); self.mem.set_reg(1, (res >> 8) as u8);
-                let c = if res & 0x8000 != 0 { 1u8 } else { 0 };
+                let c = if prod & 0x8000 != 0 { 1u8 } else { 0 };
                 let z = if res == 0 { 1u8 } else { 0 };
                 self.cpu.sreg = (self.cpu.sreg & 0b1111_1100) | (z << 1) | c;
                 sync_sreg(&self.cpu, &mut self.mem); 2
@@ -407,6 +451,10 @@ impl Arduboy {
                 let a = self.mem.z().wrapping_add(q as u16);
                 let v = self.mem.reg(r); self.write_data(a, v); 2
             }
+            Instruction::Xch { d } => self.rmw_z(d, |_, r| r),
+            Instruction::Las { d } => self.rmw_z(d, |m, r| m | r),
+            Instruction::Lac { d } => self.rmw_z(d, |m, r| m & !r),
+            Instruction::Lat { d } => self.rmw_z(d, |m, r| m ^ r),
 
             // -- Stack --
             Instruction::Push { r } => {
@@ -414,7 +462,9 @@ impl Arduboy {
                 self.mem.write_raw(sp, v);
                 self.cpu.sp = sp.wrapping_sub(1);
                 self.mem.data[SPH_ADDR as usize] = (self.cpu.sp >> 8) as u8;
-                self.mem.data[SPL_ADDR as usize] = self.cpu.sp as u8; 2
+                self.mem.data[SPL_ADDR as usize] = self.cpu.sp as u8;
+                if self.stack_guard.enabled { self.check_stack(); }
+                2
             }
             Instruction::Pop { d } => {
                 self.cpu.sp = self.cpu.sp.wrapping_add(1);
@@ -469,79 +519,92 @@ impl Arduboy {
 
             // -- Branch --
             Instruction::Rjmp { k } => {
-                self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u16; 2
+                self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u32 & PC_MASK; 2
             }
             Instruction::Rcall { k } => {
                 let ret = self.cpu.pc;
-                self.push_word(ret);
-                self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u16; 3
+                self.push_pc(ret);
+                self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u32 & PC_MASK;
+                timing(self.cpu_type).rcall
             }
             Instruction::Ret => {
-                self.cpu.pc = self.pop_word(); 4
+                self.cpu.pc = self.pop_pc();
+                timing(self.cpu_type).ret
             }
             Instruction::Reti => {
-                self.cpu.pc = self.pop_word();
+                if self.frame_timeline.enabled {
+                    self.frame_timeline.isr_exit(self.cpu.tick);
+                }
+                if self.frame_budget.enabled {
+                    self.frame_budget.isr_exit(self.cpu.tick);
+                }
+                if self.flush_timing.enabled {
+                    self.flush_timing.isr_exit(self.cpu.tick);
+                }
+                self.cpu.pc = self.pop_pc();
                 self.cpu.sreg |= 1 << SREG_I;
-                sync_sreg(&self.cpu, &mut self.mem); 4
+                sync_sreg(&self.cpu, &mut self.mem);
+                timing(self.cpu_type).reti
             }
-            Instruction::Jmp { k } => { self.cpu.pc = k as u16; 3 }
+            Instruction::Jmp { k } => { self.cpu.pc = k; 3 }
             Instruction::Call { k } => {
                 let ret = self.cpu.pc;
-                self.push_word(ret);
-                self.cpu.pc = k as u16; 4
+                self.push_pc(ret);
+                self.cpu.pc = k;
+                timing(self.cpu_type).call
             }
-            Instruction::Ijmp => { self.cpu.pc = self.mem.z(); 2 }
+            Instruction::Ijmp => { self.cpu.pc = self.mem.z() as u32; 2 }
             Instruction::Icall => {
                 let ret = self.cpu.pc;
-                self.push_word(ret);
-                self.cpu.pc = self.mem.z(); 3
+                self.push_pc(ret);
+                self.cpu.pc = self.mem.z() as u32;
+                timing(self.cpu_type).icall
             }
             Instruction::Eijmp => {
-                // PC ← EIND:Z — EIND only matters for >128KB flash (not applicable here)
-                let z = self.mem.z();
-                self.cpu.pc = z; 2
+                // PC ← EIND:Z
+                self.cpu.pc = self.eind_z(); 2
             }
             Instruction::Eicall => {
                 let ret = self.cpu.pc;
-                self.push_word(ret);
-                let z = self.mem.z();
-                self.cpu.pc = z; 4
+                self.push_pc(ret);
+                self.cpu.pc = self.eind_z();
+                timing(self.cpu_type).eicall
             }
             Instruction::Cpse { d, r } => {
                 if self.mem.reg(d) == self.mem.reg(r) {
-                    skip_next(&mut self.cpu, &self.mem); return 2;
+                    return skip_next(&mut self.cpu, &self.mem);
                 } 1
             }
             Instruction::Sbrc { r, b } => {
                 if self.mem.reg(r) & (1 << b) == 0 {
-                    skip_next(&mut self.cpu, &self.mem); return 2;
+                    return skip_next(&mut self.cpu, &self.mem);
                 } 1
             }
             Instruction::Sbrs { r, b } => {
                 if self.mem.reg(r) & (1 << b) != 0 {
-                    skip_next(&mut self.cpu, &self.mem); return 2;
+                    return skip_next(&mut self.cpu, &self.mem);
                 } 1
             }
             Instruction::Sbic { a, b } => {
                 let v = self.read_data(a as u16);
                 if v & (1 << b) == 0 {
-                    skip_next(&mut self.cpu, &self.mem); return 2;
+                    return skip_next(&mut self.cpu, &self.mem);
                 } 1
             }
             Instruction::Sbis { a, b } => {
                 let v = self.read_data(a as u16);
                 if v & (1 << b) != 0 {
-                    skip_next(&mut self.cpu, &self.mem); return 2;
+                    return skip_next(&mut self.cpu, &self.mem);
                 } 1
             }
             Instruction::Brbs { s, k } => {
                 if self.cpu.sreg & (1 << s) != 0 {
-                    self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u16; return 2;
+                    self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u32 & PC_MASK; return 2;
                 } 1
             }
             Instruction::Brbc { s, k } => {
                 if self.cpu.sreg & (1 << s) == 0 {
-                    self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u16; return 2;
+                    self.cpu.pc = (self.cpu.pc as i32 + k as i32) as u32 & PC_MASK; return 2;
                 } 1
             }
 
@@ -563,46 +626,30 @@ impl Arduboy {
 
             // -- LPM --
             Instruction::Lpm0 => {
-                let z = self.mem.z(); let v = self.mem.read_flash_byte(z as usize);
+                let z = self.mem.z(); let v = self.lpm(z as usize);
                 self.mem.set_reg(0, v); 3
             }
             Instruction::LpmD { d } => {
-                let z = self.mem.z(); let v = self.mem.read_flash_byte(z as usize);
+                let z = self.mem.z(); let v = self.lpm(z as usize);
                 self.mem.set_reg(d, v); 3
             }
             Instruction::LpmDInc { d } => {
-                let z = self.mem.z(); let v = self.mem.read_flash_byte(z as usize);
+                let z = self.mem.z(); let v = self.lpm(z as usize);
                 self.mem.set_reg(d, v); self.mem.set_z(z.wrapping_add(1)); 3
             }
 
             // -- ELPM (Extended LPM: RAMPZ:Z → flash) --
             Instruction::Elpm0 => {
-                let rampz = self.mem.data[0x5B] as u32;
-                let z = self.mem.z() as u32;
-                let addr = (rampz << 16) | z;
-                let v = self.mem.read_flash_byte(addr as usize);
+                let v = self.elpm(false);
                 self.mem.set_reg(0, v); 3
             }
             Instruction::ElpmD { d } => {
-                let rampz = self.mem.data[0x5B] as u32;
-                let z = self.mem.z() as u32;
-                let addr = (rampz << 16) | z;
-                let v = self.mem.read_flash_byte(addr as usize);
+                let v = self.elpm(false);
                 self.mem.set_reg(d, v); 3
             }
             Instruction::ElpmDInc { d } => {
-                let rampz = self.mem.data[0x5B] as u32;
-                let z = self.mem.z() as u32;
-                let addr = (rampz << 16) | z;
-                let v = self.mem.read_flash_byte(addr as usize);
-                self.mem.set_reg(d, v);
-                let new_z = z.wrapping_add(1);
-                self.mem.set_z(new_z as u16);
-                // Update RAMPZ on overflow
-                if new_z & 0x10000 != 0 {
-                    self.mem.data[0x5B] = self.mem.data[0x5B].wrapping_add(1);
-                }
-                3
+                let v = self.elpm(true);
+                self.mem.set_reg(d, v); 3
             }
 
             // -- Status flags --
@@ -624,18 +671,40 @@ impl Arduboy {
             Instruction::Clt => { self.cpu.sreg &= !(1 << SREG_T); sync_sreg(&self.cpu, &mut self.mem); 1 }
 
             // -- Misc --
-            Instruction::Sleep => { self.cpu.sleeping = true; 1 }
+            Instruction::Sleep => {
+                if self.frame_timeline.enabled {
+                    self.frame_timeline.sleep_enter(self.cpu.tick);
+                }
+                self.cpu.sleeping = true; 1
+            }
             Instruction::Wdr => { 1 }
             Instruction::Break => {
-                // Debug break — trigger breakpoint_hit
-                self.breakpoint_hit = true; 1
+                // A NOP unless a debugger is attached
+                if self.halt_on_break {
+                    self.break_insn_hit = true;
+                }
+                1
             }
             Instruction::Spm => {
-                // Store Program Memory — NOP in emulator (bootloader only)
+                // Store Program Memory (bootloader): SPMCSR selects the operation
+                let spmcsr = self.spmcsr();
+                let addr = self.rampz_z();
+                let word = self.mem.reg(0) as u16 | (self.mem.reg(1) as u16) << 8;
+                if self.mem.spm(spmcsr, addr, word) {
+                    // Done at once: clear the command, keep SPMIE
+                    let rwwsb = if self.mem.rww_busy { RWWSB } else { 0 };
+                    self.mem.data[0x57] = spmcsr & 0x80 | rwwsb;
+                }
+                1
+            }
+            Instruction::Des { k } => {
+                if self.log_enabled(LogCategory::Cpu, LogLevel::Warn) {
+                    eprintln!("DES 0x{:X} at pc=0x{:04X} not emulated (XMEGA only)", k, self.cpu.pc.wrapping_sub(1));
+                }
                 1
             }
             Instruction::Unknown(w) => { 
-                if self.debug {
+                if self.log_enabled(LogCategory::Cpu, LogLevel::Warn) {
                     eprintln!("UNKNOWN OPCODE 0x{:04X} at pc=0x{:04X}", w, self.cpu.pc.wrapping_sub(1));
                 }
                 1 
@@ -643,6 +712,49 @@ impl Arduboy {
         }
     }
 
+    /// XCH/LAS/LAC/LAT: (Z) ← op((Z), Rd) and Rd ← old (Z).
+    fn rmw_z(&mut self, d: u8, op: impl Fn(u8, u8) -> u8) -> u8 {
+        let a = self.mem.z();
+        let old = self.read_data(a);
+        let v = op(old, self.mem.reg(d));
+        self.write_data(a, v);
+        self.mem.set_reg(d, old);
+        2
+    }
+
+    /// LPM/ELPM byte at `addr`: flash, or a fuse/signature byte (indexed
+    /// by Z) when SPMCSR asks for one, which also ends the SPMCSR command.
+    fn lpm(&mut self, addr: usize) -> u8 {
+        let spmcsr = self.spmcsr();
+        match self.mem.lpm_special(spmcsr, addr as u16) {
+            Some(v) => {
+                self.mem.data[0x57] = spmcsr & 0xC0;
+                v
+            }
+            None => self.mem.read_flash_byte(addr),
+        }
+    }
+
+    /// RAMPZ:Z, the 24-bit flash byte address used by ELPM and SPM.
+    fn rampz_z(&self) -> usize {
+        (self.mem.data[RAMPZ_ADDR as usize] as usize) << 16 | self.mem.z() as usize
+    }
+
+    /// ELPM: the flash byte at RAMPZ:Z, wrapping at the end of flash (or a
+    /// fuse/signature byte, as for LPM). With
+    /// `inc`, RAMPZ:Z then increments as one pointer, so Z rolling over
+    /// from 0xFFFF carries into RAMPZ (unlike LPM Z+, which wraps in 64 KB).
+    fn elpm(&mut self, inc: bool) -> u8 {
+        let addr = self.rampz_z();
+        let v = self.lpm(addr);
+        if inc {
+            let next = (addr + 1) & 0xFF_FFFF;
+            self.mem.set_z(next as u16);
+            self.mem.data[RAMPZ_ADDR as usize] = (next >> 16) as u8;
+        }
+        v
+    }
+
     /// Push a 16-bit word onto the stack (high byte at higher addr)
     fn push_word(&mut self, val: u16) {
         self.mem.write_raw(self.cpu.sp, (val >> 8) as u8);
@@ -651,6 +763,43 @@ impl Arduboy {
         self.cpu.sp = self.cpu.sp.wrapping_sub(1);
         self.mem.data[SPH_ADDR as usize] = (self.cpu.sp >> 8) as u8;
         self.mem.data[SPL_ADDR as usize] = self.cpu.sp as u8;
+        if self.stack_guard.enabled { self.check_stack(); }
+    }
+
+    /// Push a return address: two bytes, or three on the ATmega2560 with
+    /// its 22-bit PC (bits 23..16 above the low word).
+    pub(crate) fn push_pc(&mut self, pc: u32) {
+        if self.cpu_type != CpuType::Atmega2560 {
+            self.push_word(pc as u16);
+            return;
+        }
+        self.mem.write_raw(self.cpu.sp, (pc >> 16) as u8);
+        self.cpu.sp = self.cpu.sp.wrapping_sub(1);
+        self.push_word(pc as u16);
+    }
+
+    /// Bytes in a pushed return address.
+    pub(crate) fn pc_bytes(&self) -> u16 {
+        if self.cpu_type == CpuType::Atmega2560 { 3 } else { 2 }
+    }
+
+    /// Pop a return address pushed by [`push_pc`](Self::push_pc).
+    fn pop_pc(&mut self) -> u32 {
+        let lo = self.pop_word() as u32;
+        if self.cpu_type != CpuType::Atmega2560 {
+            return lo;
+        }
+        self.cpu.sp = self.cpu.sp.wrapping_add(1);
+        let hi = self.mem.read_raw(self.cpu.sp) as u32;
+        self.mem.data[SPH_ADDR as usize] = (self.cpu.sp >> 8) as u8;
+        self.mem.data[SPL_ADDR as usize] = self.cpu.sp as u8;
+        hi << 16 | lo
+    }
+
+    /// EIJMP/EICALL target EIND:Z (EIND exists on the ATmega2560 only).
+    pub(crate) fn eind_z(&self) -> u32 {
+        let eind = if self.cpu_type == CpuType::Atmega2560 { self.mem.data[EIND_ADDR as usize] as u32 } else { 0 };
+        eind << 16 | self.mem.z() as u32
     }
 
     /// Pop a 16-bit word from the stack
@@ -709,6 +858,23 @@ mod tests {
         assert_eq!(a.mem.reg(10), 0x42);
     }
 
+    #[test]
+    fn test_stack_guard() {
+        let mut a = Arduboy::new();
+        a.stack_guard = crate::stack_guard::StackGuard::new(0x0900, "0x0900");
+        a.cpu.sp = 0x0901;
+        a.execute_inst(Instruction::Push { r: 0 }, 1);
+        assert!(a.diagnostics.take_messages().is_empty());
+        a.execute_inst(Instruction::Rcall { k: 0 }, 1);
+        assert_eq!(a.stack_guard.overflows, 1);
+        // A prologue reserving locals through SPH/SPL
+        a.cpu.sp = 0x0A00;
+        a.execute_inst(Instruction::Push { r: 0 }, 1);
+        a.write_data(SPH_ADDR, 0x08);
+        a.write_data(SPL_ADDR, 0x00);
+        assert_eq!(a.stack_guard.overflows, 2);
+    }
+
     #[test]
     fn test_rcall_ret() {
         let mut a = Arduboy::new();
@@ -719,6 +885,91 @@ mod tests {
         assert_eq!(a.cpu.pc, 0x101);
     }
 
+    #[test]
+    fn test_2560_call_ret_three_bytes() {
+        let mut a = Arduboy::new_with_cpu(CpuType::Atmega2560);
+        assert_eq!(a.mem.flash.len(), crate::FLASH_SIZE_2560);
+        let sp0 = a.cpu.sp;
+        a.cpu.pc = 0x1_2340;
+        let c = a.execute_inst(Instruction::Call { k: 0x2_0000 }, 2);
+        assert_eq!((c, a.cpu.pc, a.cpu.sp), (5, 0x2_0000, sp0 - 3));
+        assert_eq!(a.mem.data[sp0 as usize], 0x01);
+        a.execute_inst(Instruction::Ret, 1);
+        assert_eq!((a.cpu.pc, a.cpu.sp), (0x1_2342, sp0));
+
+        a.mem.data[EIND_ADDR as usize] = 0x03;
+        a.mem.set_z(0x0010);
+        a.execute_inst(Instruction::Eicall, 1);
+        assert_eq!(a.cpu.pc, 0x3_0010);
+        a.execute_inst(Instruction::Ret, 1);
+        assert_eq!(a.cpu.pc, 0x1_2343);
+    }
+
+    #[test]
+    fn test_2560_shadow_stack_high_pc() {
+        let mut a = Arduboy::new_with_cpu(CpuType::Atmega2560);
+        a.shadow_stack.enabled = true;
+        a.cpu.pc = 0x1_2340;
+        for (inst, size) in [(Instruction::Call { k: 0x2_0000 }, 2), (Instruction::Ret, 1)] {
+            a.track_shadow_stack(inst, size);
+            a.execute_inst(inst, size);
+        }
+        assert_eq!((a.cpu.pc, a.shadow_stack.violations), (0x1_2342, 0));
+        assert!(a.diagnostics.take_messages().is_empty());
+
+        // Only bits 23..16 of the return address overwritten
+        a.cpu.pc = 0x1_2340;
+        let sp0 = a.cpu.sp;
+        a.track_shadow_stack(Instruction::Call { k: 0x2_0000 }, 2);
+        a.execute_inst(Instruction::Call { k: 0x2_0000 }, 2);
+        a.mem.data[sp0 as usize] = 0x00;
+        a.track_shadow_stack(Instruction::Ret, 1);
+        assert_eq!(a.shadow_stack.violations, 1);
+        assert!(a.diagnostics.take_messages()[0].contains("returns to 0x4684, expected 0x24684"));
+    }
+
+    /// Run `steps` instructions of `words` loaded at 0 and return the ticks.
+    fn ticks(cpu: CpuType, words: &[u16], steps: usize) -> u64 {
+        let mut a = Arduboy::new_with_cpu(cpu);
+        for (i, w) in words.iter().enumerate() {
+            a.mem.flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+        }
+        for _ in 0..steps {
+            a.step();
+        }
+        a.cpu.tick
+    }
+
+    #[test]
+    fn test_timing_loads_stores() {
+        // ldi r16,1 (1); sts 0x100,r16 (2); lds r17,0x100 (2); out PORTB,r16 (1);
+        // in r18,PORTB (1); lds r20,PORTB (2, same as SRAM); push r16 (2); pop r19 (2)
+        let prog = [0xE001, 0x9300, 0x0100, 0x9110, 0x0100, 0xB905, 0xB125, 0x9140, 0x0025, 0x930F, 0x913F];
+        for cpu in [CpuType::Atmega32u4, CpuType::Atmega328p, CpuType::Atmega2560] {
+            assert_eq!(ticks(cpu, &prog, 8), 13, "{:?}", cpu);
+        }
+    }
+
+    #[test]
+    fn test_timing_calls() {
+        // call 0x10; rcall 0x10; ldi r30,0x10; ldi r31,0; icall; ... 0x10: ret
+        let mut prog = vec![0x940E, 0x0010, 0xD00D, 0xE1E0, 0xE0F0, 0x9509];
+        prog.resize(0x10, 0);
+        prog.push(0x9508);
+        // call 4 + ret 4, rcall 3 + ret 4, ldi 1 + ldi 1, icall 3 + ret 4
+        assert_eq!(ticks(CpuType::Atmega32u4, &prog, 8), 24);
+        assert_eq!(ticks(CpuType::Atmega328p, &prog, 8), 24);
+        // One more cycle per 3-byte return address
+        assert_eq!(ticks(CpuType::Atmega2560, &prog, 8), 30);
+    }
+
+    #[test]
+    fn test_timing_skips() {
+        // cpse r0,r0 over a 2-word lds (3); sbrc r0,0 over a nop (2); sbrs r0,0 (1)
+        let prog = [0x1000, 0x9010, 0x0100, 0xFC00, 0x0000, 0xFE00];
+        assert_eq!(ticks(CpuType::Atmega32u4, &prog, 3), 6);
+    }
+
     #[test]
     fn test_branch_taken() {
         let mut a = Arduboy::new();
@@ -748,6 +999,141 @@ mod tests {
         assert_eq!(a.mem.reg(5), 0x42);
     }
 
+    #[test]
+    fn test_elpm_rampz() {
+        let mut a = Arduboy::new();
+        a.mem.flash[0x7FFF] = 0x11;
+        a.mem.flash[0x0000] = 0x22;
+        // RAMPZ:Z = 0x00FFFF, then Z+ carries into RAMPZ
+        a.mem.data[0x5B] = 0x00;
+        a.mem.set_z(0xFFFF);
+        a.execute_inst(Instruction::ElpmDInc { d: 5 }, 1);
+        assert_eq!(a.mem.reg(5), 0x11);
+        assert_eq!((a.mem.data[0x5B], a.mem.z()), (0x01, 0x0000));
+        // 0x010000 is past the 32 KB flash and wraps to 0
+        a.execute_inst(Instruction::ElpmD { d: 6 }, 1);
+        assert_eq!(a.mem.reg(6), 0x22);
+    }
+
+    #[test]
+    fn test_elpm_large_flash() {
+        let mut a = Arduboy::new_with_cpu(CpuType::Atmega2560);
+        assert_eq!(a.mem.flash.len(), crate::FLASH_SIZE_2560);
+        for (addr, v) in [(0x0FFFF, 0x11), (0x10000, 0x22), (0x1FFFF, 0x33), (0x20000, 0x44), (0x3FFFF, 0x55), (0, 0x66)] {
+            a.mem.flash[addr] = v;
+        }
+        let elpm_inc = |a: &mut Arduboy| {
+            a.execute_inst(Instruction::ElpmDInc { d: 5 }, 1);
+            (a.mem.reg(5), a.mem.data[RAMPZ_ADDR as usize], a.mem.z())
+        };
+        // Crossing 0x10000 and the 128 KB boundary carries into RAMPZ
+        a.mem.data[RAMPZ_ADDR as usize] = 0;
+        a.mem.set_z(0xFFFF);
+        assert_eq!(elpm_inc(&mut a), (0x11, 1, 0x0000));
+        assert_eq!(elpm_inc(&mut a), (0x22, 1, 0x0001));
+        a.mem.set_z(0xFFFF);
+        assert_eq!(elpm_inc(&mut a), (0x33, 2, 0x0000));
+        assert_eq!(elpm_inc(&mut a), (0x44, 2, 0x0001));
+        // The last byte of flash, then RAMPZ:Z = 0x040000 wraps to 0
+        a.mem.data[RAMPZ_ADDR as usize] = 3;
+        a.mem.set_z(0xFFFF);
+        assert_eq!(elpm_inc(&mut a), (0x55, 4, 0x0000));
+        assert_eq!(elpm_inc(&mut a), (0x66, 4, 0x0001));
+        // LPM ignores RAMPZ, and Z+ wraps within the first 64 KB
+        a.mem.set_z(0xFFFF);
+        a.execute_inst(Instruction::LpmDInc { d: 6 }, 1);
+        assert_eq!((a.mem.reg(6), a.mem.data[RAMPZ_ADDR as usize], a.mem.z()), (0x11, 4, 0x0000));
+    }
+
+    #[test]
+    fn test_xch_las_lac_lat() {
+        let mut a = Arduboy::new();
+        a.mem.set_z(0x0200);
+        a.mem.data[0x200] = 0b1100;
+        a.mem.set_reg(4, 0b1010);
+        assert_eq!(a.execute_inst(Instruction::Las { d: 4 }, 1), 2);
+        assert_eq!((a.mem.data[0x200], a.mem.reg(4)), (0b1110, 0b1100));
+        a.mem.set_reg(4, 0b0110);
+        a.execute_inst(Instruction::Lac { d: 4 }, 1);
+        assert_eq!((a.mem.data[0x200], a.mem.reg(4)), (0b1000, 0b1110));
+        a.mem.set_reg(4, 0b1001);
+        a.execute_inst(Instruction::Lat { d: 4 }, 1);
+        assert_eq!((a.mem.data[0x200], a.mem.reg(4)), (0b0001, 0b1000));
+        a.mem.set_reg(4, 0x5A);
+        a.execute_inst(Instruction::Xch { d: 4 }, 1);
+        assert_eq!((a.mem.data[0x200], a.mem.reg(4)), (0x5A, 0b0001));
+    }
+
+    #[test]
+    fn test_spm() {
+        let mut a = Arduboy::new();
+        a.mem.set_reg(0, 0x0C);
+        a.mem.set_reg(1, 0x94);
+        a.mem.set_z(0x2002);
+        a.mem.data[0x57] = 0x01; // SPMEN: fill buffer
+        a.execute_inst(Instruction::Spm, 1);
+        assert_eq!(a.mem.data[0x57], 0x00);
+        a.mem.data[0x57] = 0x03; // PGERS
+        a.execute_inst(Instruction::Spm, 1);
+        a.mem.data[0x57] = 0x05; // PGWRT
+        a.execute_inst(Instruction::Spm, 1);
+        assert_eq!(a.mem.read_program_word(0x1001), 0x940C);
+        assert_eq!(a.mem.read_program_word(0x1000), 0xFFFF);
+        // No SPMEN: ignored
+        a.mem.data[0x57] = 0x02;
+        a.execute_inst(Instruction::Spm, 1);
+        assert_eq!(a.mem.data[0x57], 0x02);
+        assert_eq!(a.mem.read_program_word(0x1001), 0x940C);
+        // RWW busy after the write until RWWSRE
+        a.mem.data[0x57] = 0x05;
+        a.execute_inst(Instruction::Spm, 1);
+        assert_eq!(a.mem.data[0x57], 0x40);
+        a.mem.data[0x57] = 0x11;
+        a.execute_inst(Instruction::Spm, 1);
+        assert_eq!(a.mem.data[0x57], 0x00);
+    }
+
+    #[test]
+    fn test_lpm_signature() {
+        let mut a = Arduboy::new_with_cpu(CpuType::Atmega328p);
+        a.mem.set_z(2);
+        a.mem.data[0x57] = 0x21; // SIGRD
+        a.execute_inst(Instruction::LpmD { d: 5 }, 1);
+        assert_eq!((a.mem.reg(5), a.mem.data[0x57]), (0x95, 0x00));
+        a.mem.set_z(4);
+        a.mem.data[0x57] = 0x21;
+        a.execute_inst(Instruction::LpmD { d: 5 }, 1);
+        assert_eq!(a.mem.reg(5), 0x0F);
+        // Command consumed: the next LPM reads flash
+        a.mem.flash[4] = 0x42;
+        a.execute_inst(Instruction::LpmD { d: 5 }, 1);
+        assert_eq!(a.mem.reg(5), 0x42);
+    }
+
+    #[test]
+    fn test_signature_command_window() {
+        // boot_signature_byte_get: STS SPMCSR then LPM two cycles later
+        let mut a = Arduboy::new();
+        a.mem.set_z(4);
+        a.write_data(0x57, 0x21);
+        a.cpu.tick += 2;
+        a.execute_inst(Instruction::LpmD { d: 5 }, 1);
+        assert_eq!(a.mem.reg(5), 0x87);
+        // Not followed by LPM within four cycles: the command lapses
+        a.mem.flash[4] = 0x42;
+        a.write_data(0x57, 0x21);
+        a.cpu.tick += 5;
+        assert_eq!(a.read_data(0x57), 0x00);
+        a.execute_inst(Instruction::LpmD { d: 5 }, 1);
+        assert_eq!(a.mem.reg(5), 0x42);
+        // ELPM reads the fuses too (Z = 3: high fuse)
+        let mut a = Arduboy::new_with_cpu(CpuType::Atmega2560);
+        a.mem.set_z(3);
+        a.write_data(0x57, 0x09); // BLBSET
+        a.execute_inst(Instruction::ElpmD { d: 5 }, 1);
+        assert_eq!(a.mem.reg(5), 0xD9);
+    }
+
     #[test]
     fn test_mul() {
         let mut a = Arduboy::new();
@@ -0,0 +1,206 @@
+//! CPU type detection from flash contents.
+//!
+//! [`detect_cpu`] weighs several independent signals and reports the
+//! winning CPU with a confidence score and the evidence behind it:
+//!
+//! | Signal | Points toward |
+//! |--------|---------------|
+//! | JMP/RJMP entries in vector slots 27–43 (0x68–0xA8) | 32u4 (else 328P) |
+//! | `ARDUBOY`, `Arduboy`, `Arduino LLC`, `Arduino Leonardo` strings | 32u4 (Arduboy2 / USB descriptors) |
+//! | `Gamebuino` / `GAMEBUINO` strings | 328P |
+//! | LDS/STS to Timer3 (0x90–0x9D) or USB (0xD7–0xF4) registers | 32u4 |
+//! | LDS/STS to Timer2 (0x70, 0xB0–0xB6) registers | 328P |
+//!
+//! The vector table alone misreads small 328P sketches whose code happens
+//! to contain jumps at 0x68+, and 32u4 binaries that reuse unused vector
+//! slots for code; library strings and peripheral registers that exist on
+//! only one of the chips settle those cases.
+
+use crate::CpuType;
+
+/// One piece of evidence: what was found and its weight
+/// (positive → ATmega32u4, negative → ATmega328P).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evidence {
+    pub what: String,
+    pub weight: i32,
+}
+
+/// Detected CPU type with a confidence score.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CpuDetection {
+    pub cpu: CpuType,
+    /// 0.5 (coin toss) to 1.0 (all signals agree strongly)
+    pub confidence: f32,
+    pub evidence: Vec<Evidence>,
+}
+
+impl CpuDetection {
+    /// One-line summary, e.g. `Atmega32u4 (97%: vector table, "Arduboy")`.
+    pub fn summary(&self) -> String {
+        let what: Vec<&str> = self.evidence.iter().map(|e| e.what.as_str()).collect();
+        format!("{:?} ({:.0}%: {})", self.cpu, self.confidence * 100.0, what.join(", "))
+    }
+}
+
+/// Library and USB descriptor strings that identify the target
+const SIGNATURES: &[(&[u8], CpuType)] = &[
+    (b"ARDUBOY", CpuType::Atmega32u4),
+    (b"Arduboy", CpuType::Atmega32u4),
+    (b"Arduino LLC", CpuType::Atmega32u4),
+    (b"Arduino Leonardo", CpuType::Atmega32u4),
+    (b"Gamebuino", CpuType::Atmega328p),
+    (b"GAMEBUINO", CpuType::Atmega328p),
+];
+/// Weight of a matching signature string
+const SIGNATURE_WEIGHT: i32 = 3;
+
+/// Extended I/O addresses (inclusive ranges) that exist on only one CPU
+const REG_RANGES: &[(u16, u16, CpuType, &str)] = &[
+    (0x90, 0x9D, CpuType::Atmega32u4, "Timer3"),
+    (0xD7, 0xF4, CpuType::Atmega32u4, "USB"),
+    (0x70, 0x70, CpuType::Atmega328p, "Timer2"),
+    (0xB0, 0xB6, CpuType::Atmega328p, "Timer2"),
+];
+/// LDS/STS hits needed before a register range counts (data can look like code)
+const REG_MIN_HITS: usize = 2;
+
+/// Examine flash contents and guess the CPU they were built for.
+pub fn detect_cpu(flash: &[u8]) -> CpuDetection {
+    let mut evidence = Vec::new();
+    vector_table(flash, &mut evidence);
+    signatures(flash, &mut evidence);
+    register_usage(flash, &mut evidence);
+
+    let score: i32 = evidence.iter().map(|e| e.weight).sum();
+    let cpu = if score >= 0 { CpuType::Atmega32u4 } else { CpuType::Atmega328p };
+    // Each point of agreement halves the remaining doubt
+    let confidence = 1.0 - 0.5f32.powi(score.abs() + 1);
+    CpuDetection { cpu, confidence, evidence }
+}
+
+/// ATmega328P has 26 vectors (byte addresses 0x00–0x64), while ATmega32u4 has
+/// 43 vectors (0x00–0xA8). Slots 27–43 hold JMP/RJMP on a 32u4 and regular
+/// code on a 328P.
+fn vector_table(flash: &[u8], evidence: &mut Vec<Evidence>) {
+    if flash.len() < 0xAA {
+        // Too small to tell — very short programs are likely 328P sketches
+        evidence.push(Evidence { what: "tiny image".into(), weight: -1 });
+        return;
+    }
+    let mut jmp_count = 0;
+    let mut checked = 0;
+    // Vector entries are always 4 bytes (2 words)
+    for addr in (0x68..=0xA8).step_by(4) {
+        let w = u16::from_le_bytes([flash[addr], flash[addr + 1]]);
+        // JMP: 1001_010k_kkkk_110k, RJMP: 1100_kkkk_kkkk_kkkk
+        if (w & 0xFE0E) == 0x940C || (w & 0xF000) == 0xC000 {
+            jmp_count += 1;
+        }
+        checked += 1;
+    }
+    // ≥60% of the slots look like vector entries → 32u4
+    let weight = if jmp_count == checked {
+        3
+    } else if jmp_count * 10 >= checked * 6 {
+        2
+    } else if jmp_count == 0 {
+        -3
+    } else {
+        -2
+    };
+    evidence.push(Evidence { what: format!("vector table {}/{}", jmp_count, checked), weight });
+}
+
+fn signatures(flash: &[u8], evidence: &mut Vec<Evidence>) {
+    for &(sig, cpu) in SIGNATURES {
+        if flash.windows(sig.len()).any(|w| w == sig) {
+            let weight = if cpu == CpuType::Atmega32u4 { SIGNATURE_WEIGHT } else { -SIGNATURE_WEIGHT };
+            evidence.push(Evidence { what: format!("\"{}\"", String::from_utf8_lossy(sig)), weight });
+        }
+    }
+}
+
+/// Count LDS/STS instructions that address chip-specific extended I/O.
+fn register_usage(flash: &[u8], evidence: &mut Vec<Evidence>) {
+    let mut hits = [0usize; REG_RANGES.len()];
+    for pair in flash.chunks_exact(2).collect::<Vec<_>>().windows(2) {
+        let w = u16::from_le_bytes([pair[0][0], pair[0][1]]);
+        // LDS: 1001_000d_dddd_0000, STS: 1001_001d_dddd_0000, then the address
+        if (w & 0xFC0F) != 0x9000 {
+            continue;
+        }
+        let k = u16::from_le_bytes([pair[1][0], pair[1][1]]);
+        if let Some(i) = REG_RANGES.iter().position(|&(lo, hi, _, _)| (lo..=hi).contains(&k)) {
+            hits[i] += 1;
+        }
+    }
+    for (i, &(_, _, cpu, name)) in REG_RANGES.iter().enumerate() {
+        if hits[i] >= REG_MIN_HITS {
+            let weight = if cpu == CpuType::Atmega32u4 { 2 } else { -2 };
+            evidence.push(Evidence { what: format!("{} registers ×{}", name, hits[i]), weight });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 26 JMP vectors, then `fill` repeated as code
+    fn sketch_328p(fill: [u8; 2]) -> Vec<u8> {
+        let mut flash = vec![0u8; 32768];
+        for addr in (0..=0x64).step_by(4) {
+            flash[addr..addr + 4].copy_from_slice(&[0x0C, 0x94, 0x00, 0x00]);
+        }
+        for addr in (0x68..0x400).step_by(2) {
+            flash[addr..addr + 2].copy_from_slice(&fill);
+        }
+        flash
+    }
+
+    #[test]
+    fn test_strings_override_vector_table() {
+        // A small 328P sketch whose code is full of RJMPs at 0x68+
+        let mut flash = sketch_328p([0x00, 0xC0]);
+        assert_eq!(detect_cpu(&flash).cpu, CpuType::Atmega32u4);
+        flash[0x1000..0x1009].copy_from_slice(b"Gamebuino");
+        let d = detect_cpu(&flash);
+        // 3 (vectors) - 3 (string) → tie goes to 32u4; add Timer2 register use
+        assert_eq!(d.cpu, CpuType::Atmega32u4);
+        for (i, addr) in [0xB1u16, 0xB3].iter().enumerate() {
+            let at = 0x2000 + i * 4;
+            flash[at..at + 2].copy_from_slice(&0x9300u16.to_le_bytes()); // STS k, r16
+            flash[at + 2..at + 4].copy_from_slice(&addr.to_le_bytes());
+        }
+        let d = detect_cpu(&flash);
+        assert_eq!(d.cpu, CpuType::Atmega328p);
+        assert!(d.summary().contains("Timer2 registers ×2"), "{}", d.summary());
+    }
+
+    #[test]
+    fn test_confidence() {
+        let mut flash = sketch_328p([0x0F, 0xEF]); // LDI r16, 0xFF
+        let plain = detect_cpu(&flash);
+        assert_eq!(plain.cpu, CpuType::Atmega328p);
+        flash[0x1000..0x1009].copy_from_slice(b"GAMEBUINO");
+        let sure = detect_cpu(&flash);
+        assert_eq!(sure.cpu, CpuType::Atmega328p);
+        assert!(sure.confidence > plain.confidence && sure.confidence > 0.95);
+    }
+
+    #[test]
+    fn test_usb_registers() {
+        // Unusual vector use, but USB descriptors and UEDATX accesses
+        let mut flash = sketch_328p([0x0F, 0xEF]);
+        flash[0x1000..0x100B].copy_from_slice(b"Arduino LLC");
+        for i in 0..3 {
+            let at = 0x2000 + i * 4;
+            flash[at..at + 2].copy_from_slice(&0x9180u16.to_le_bytes()); // LDS r24, k
+            flash[at + 2..at + 4].copy_from_slice(&0xF1u16.to_le_bytes());
+        }
+        let d = detect_cpu(&flash);
+        assert_eq!(d.cpu, CpuType::Atmega32u4);
+        assert_eq!(d.evidence.len(), 3);
+    }
+}
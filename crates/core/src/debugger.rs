@@ -3,9 +3,26 @@
 //! - **RAM Viewer**: Hex + ASCII dump of any data-space region
 //! - **I/O Register Viewer**: Named register display for ATmega32u4 / ATmega328P
 //! - **Watchpoints**: Trigger on data-space read/write at specified addresses
+//! - **Write-protect regions**: Stop when a data-space range (e.g. the
+//!   Arduboy2 `sBuffer`) is written by code outside the functions allowed
+//!   to write it
 //!
 //! Watchpoints are checked in the emulator's `read_data` / `write_data` paths
-//! when enabled.
+//! when enabled. Write-protect regions see the same stores; pushes, calls
+//! and interrupt entry write the stack directly and are not checked.
+//!
+//! A region is written as `<range|symbol>[@<allowed>,...]`, where each
+//! allowed entry is a function symbol or a flash byte range (hex):
+//!
+//! ```text
+//! Arduboy2Base::sBuffer@Arduboy2Base::drawPixel,Arduboy2Base::fillScreen
+//! 0x0100-0x04FF@0x1A4-0x2F0
+//! ```
+//!
+//! Symbols need an ELF file; C++ names are matched against the mangled ones
+//! (see [`ElfFile::find_symbols`]).
+
+use crate::elf::{ElfFile, DATA_OFFSET};
 
 /// Watchpoint trigger type.
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -46,12 +63,99 @@ pub struct WatchHit {
     pub new_val: u8,
     /// Access kind that triggered
     pub access: WatchKind,
+    /// Write-protect hit: flash byte address of the store. `index` is then
+    /// the region's index in [`Debugger::protect_regions`].
+    pub protect_pc: Option<u32>,
+}
+
+/// A data-space range that only whitelisted code may write.
+#[derive(Debug, Clone)]
+pub struct ProtectRegion {
+    /// Region as written
+    pub spec: String,
+    /// First protected data-space address
+    pub start: u16,
+    /// Last protected data-space address (inclusive)
+    pub end: u16,
+    /// Flash byte ranges `[lo, hi)` of code allowed to write
+    pub allowed: Vec<(u32, u32)>,
+    /// Rogue writes seen
+    pub hits: u64,
+    /// Enabled
+    pub enabled: bool,
+}
+
+impl ProtectRegion {
+    /// Parse `<range|symbol>[@<allowed>,...]` (see the module docs).
+    pub fn parse(spec: &str, elf: Option<&ElfFile>) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (region, allowed) = match spec.split_once('@') {
+            Some((r, a)) => (r.trim(), Some(a)),
+            None => (spec, None),
+        };
+        let (start, end) = match hex_range(region)? {
+            Some((lo, hi)) => (lo, hi),
+            None => {
+                let elf = elf.ok_or_else(|| format!("symbol '{}' needs an ELF file", region))?;
+                let addr = match elf.find_symbols(region).as_slice() {
+                    [a] if *a >= DATA_OFFSET => *a,
+                    [_] => return Err(format!("'{}' is not in RAM", region)),
+                    [] => return Err(format!("unknown symbol '{}'", region)),
+                    _ => return Err(format!("'{}' matches several symbols", region)),
+                };
+                let size = elf.sizes.get(&addr).copied().unwrap_or(0);
+                if size == 0 {
+                    return Err(format!("size of '{}' unknown; give an address range", region));
+                }
+                (addr - DATA_OFFSET, addr - DATA_OFFSET + size - 1)
+            }
+        };
+        if start > end || end > 0xFFFF {
+            return Err(format!("bad data-space range '{}'", region));
+        }
+        let mut ranges = Vec::new();
+        for item in allowed.into_iter().flat_map(|a| a.split(',')).map(str::trim) {
+            if let Some((lo, hi)) = hex_range(item)? {
+                ranges.push((lo, hi + 1));
+                continue;
+            }
+            let elf = elf.ok_or_else(|| format!("function '{}' needs an ELF file", item))?;
+            let addrs: Vec<u32> = elf.find_symbols(item).into_iter().filter(|&a| a < DATA_OFFSET).collect();
+            if addrs.is_empty() {
+                return Err(format!("unknown function '{}'", item));
+            }
+            ranges.extend(addrs.into_iter().map(|a| elf.symbol_range(a)));
+        }
+        Ok(ProtectRegion {
+            spec: spec.to_string(),
+            start: start as u16,
+            end: end as u16,
+            allowed: ranges,
+            hits: 0,
+            enabled: true,
+        })
+    }
+}
+
+/// Inclusive `lo-hi` hex range, or None if `s` is not one.
+fn hex_range(s: &str) -> Result<Option<(u32, u32)>, String> {
+    let hex = |t: &str| {
+        let t = t.trim();
+        u32::from_str_radix(t.trim_start_matches("0x").trim_start_matches("0X"), 16).ok()
+    };
+    match s.split_once('-').map(|(a, b)| (hex(a), hex(b))) {
+        Some((Some(lo), Some(hi))) if lo <= hi => Ok(Some((lo, hi))),
+        Some((Some(_), Some(_))) => Err(format!("empty range '{}'", s)),
+        _ => Ok(None),
+    }
 }
 
 /// Debugger state.
 pub struct Debugger {
     /// Active watchpoints
     pub watchpoints: Vec<Watchpoint>,
+    /// Write-protect regions
+    pub protect_regions: Vec<ProtectRegion>,
     /// True if a watchpoint was triggered (emulator should pause)
     pub watch_hit: Option<WatchHit>,
 }
@@ -60,6 +164,7 @@ impl Debugger {
     pub fn new() -> Self {
         Debugger {
             watchpoints: Vec::new(),
+            protect_regions: Vec::new(),
             watch_hit: None,
         }
     }
@@ -94,7 +199,7 @@ impl Debugger {
             if self.watch_hit.is_none() {
                 self.watch_hit = Some(WatchHit {
                     index: i, addr, old_val, new_val,
-                    access: WatchKind::Write,
+                    access: WatchKind::Write, protect_pc: None,
                 });
             }
         }
@@ -110,7 +215,24 @@ impl Debugger {
             if self.watch_hit.is_none() {
                 self.watch_hit = Some(WatchHit {
                     index: i, addr, old_val: val, new_val: val,
-                    access: WatchKind::Read,
+                    access: WatchKind::Read, protect_pc: None,
+                });
+            }
+        }
+    }
+
+    /// Check write-protect regions for a store by the instruction at flash
+    /// byte address `pc`. Call BEFORE writing to data[].
+    #[inline]
+    pub fn check_protect(&mut self, pc: u32, addr: u16, old_val: u8, new_val: u8) {
+        for (i, r) in self.protect_regions.iter_mut().enumerate() {
+            if !r.enabled || addr < r.start || addr > r.end { continue; }
+            if r.allowed.iter().any(|&(lo, hi)| (lo..hi).contains(&pc)) { continue; }
+            r.hits += 1;
+            if self.watch_hit.is_none() {
+                self.watch_hit = Some(WatchHit {
+                    index: i, addr, old_val, new_val,
+                    access: WatchKind::Write, protect_pc: Some(pc),
                 });
             }
         }
@@ -140,6 +262,18 @@ impl Debugger {
         }
         s
     }
+
+    /// Format write-protect region list.
+    pub fn list_protect_regions(&self) -> String {
+        if self.protect_regions.is_empty() { return "No write-protect regions.\n".into(); }
+        let mut s = String::new();
+        for (i, r) in self.protect_regions.iter().enumerate() {
+            let en = if r.enabled { " " } else { "!" };
+            s.push_str(&format!("  [{}]{} 0x{:04X}-0x{:04X} {}  allowed={} hits={}\n",
+                i, en, r.start, r.end, r.spec, r.allowed.len(), r.hits));
+        }
+        s
+    }
 }
 
 impl Default for Debugger {
@@ -219,7 +353,7 @@ pub fn io_reg_names_32u4() -> Vec<(u16, &'static str)> {
         (0x49, "PLLCSR"),
         (0x4C, "SPCR"), (0x4D, "SPSR"), (0x4E, "SPDR"),
         (0x53, "SMCR"),
-        (0x57, "MCUSR"), (0x58, "MCUCR"),
+        (0x54, "MCUSR"), (0x55, "MCUCR"), (0x57, "SPMCSR"), (0x5B, "RAMPZ"),
         (0x5D, "SPL"), (0x5E, "SPH"), (0x5F, "SREG"),
         (0x60, "WDTCSR"),
         (0x61, "CLKPR"),
@@ -257,7 +391,7 @@ pub fn io_reg_names_328p() -> Vec<(u16, &'static str)> {
         (0x47, "OCR0A"), (0x48, "OCR0B"),
         (0x4C, "SPCR"), (0x4D, "SPSR"), (0x4E, "SPDR"),
         (0x53, "SMCR"),
-        (0x57, "MCUSR"), (0x58, "MCUCR"),
+        (0x54, "MCUSR"), (0x55, "MCUCR"), (0x57, "SPMCSR"),
         (0x5D, "SPL"), (0x5E, "SPH"), (0x5F, "SREG"),
         (0x60, "WDTCSR"),
         (0x61, "CLKPR"),
@@ -338,6 +472,23 @@ mod tests {
         assert_eq!(hit.new_val, 0xFF);
     }
 
+    #[test]
+    fn test_protect_region() {
+        let mut dbg = Debugger::new();
+        dbg.protect_regions.push(ProtectRegion::parse("0x100-0x4FF@0x1A4-0x1FF,0x300-0x301", None).unwrap());
+        assert_eq!(dbg.protect_regions[0].allowed, vec![(0x1A4, 0x200), (0x300, 0x302)]);
+        dbg.check_protect(0x1B0, 0x200, 0, 1);
+        dbg.check_protect(0x400, 0x500, 0, 1);
+        assert!(dbg.take_hit().is_none());
+        dbg.check_protect(0x400, 0x4FF, 0x12, 0x34);
+        let hit = dbg.take_hit().unwrap();
+        assert_eq!((hit.addr, hit.protect_pc, hit.new_val), (0x4FF, Some(0x400), 0x34));
+        assert_eq!(dbg.protect_regions[0].hits, 1);
+
+        assert!(ProtectRegion::parse("sBuffer", None).unwrap_err().contains("ELF"));
+        assert!(ProtectRegion::parse("0x500-0x100", None).is_err());
+    }
+
     #[test]
     fn test_io_name() {
         assert_eq!(io_name(0x5F, false), Some("SREG"));
@@ -0,0 +1,39 @@
+//! Report sink shared by the run-time checkers.
+//!
+//! The checkers that watch the running program —
+//! [`shadow_stack`](crate::shadow_stack), [`uninit`](crate::uninit),
+//! [`bounds`](crate::bounds), [`stack_guard`](crate::stack_guard) and
+//! [`eeprom_guard`](crate::eeprom_guard) — write their one-line findings
+//! into [`Arduboy::diagnostics`](crate::Arduboy::diagnostics) instead of
+//! keeping a queue each. A checker whose break option is set also trips the
+//! sink, and the frame loop stops like at a breakpoint. Frontends collect
+//! the messages once per frame with [`Diagnostics::take_messages`].
+
+/// Pending checker messages and break request.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    messages: Vec<String>,
+    tripped: bool,
+}
+
+impl Diagnostics {
+    /// Queue a message for the frontend.
+    pub fn report(&mut self, msg: String) {
+        self.messages.push(msg);
+    }
+
+    /// Ask the frame loop to stop after the current instruction.
+    pub fn trip(&mut self) {
+        self.tripped = true;
+    }
+
+    /// Whether a checker asked to stop since the last call.
+    pub fn take_trip(&mut self) -> bool {
+        std::mem::take(&mut self.tripped)
+    }
+
+    /// Take messages raised since the last call, oldest first.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+}
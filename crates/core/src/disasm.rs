@@ -9,7 +9,7 @@ use crate::opcodes::Instruction;
 ///
 /// The output follows AVR assembly conventions (e.g. `ADD R1, R2`).
 /// The `pc` parameter (word address) is used to resolve relative branch targets.
-pub fn disassemble(inst: Instruction, pc: u16) -> String {
+pub fn disassemble(inst: Instruction, pc: u32) -> String {
     match inst {
         Instruction::Nop => "NOP".into(),
         // Arithmetic
@@ -68,6 +68,10 @@ pub fn disassemble(inst: Instruction, pc: u16) -> String {
         Instruction::StZInc { r }  => format!("ST Z+, R{}", r),
         Instruction::StZDec { r }  => format!("ST -Z, R{}", r),
         Instruction::StZQ { r, q } => format!("STD Z+{}, R{}", q, r),
+        Instruction::Xch { d }     => format!("XCH Z, R{}", d),
+        Instruction::Las { d }     => format!("LAS Z, R{}", d),
+        Instruction::Lac { d }     => format!("LAC Z, R{}", d),
+        Instruction::Lat { d }     => format!("LAT Z, R{}", d),
         // Stack
         Instruction::Push { r }    => format!("PUSH R{}", r),
         Instruction::Pop { d }     => format!("POP R{}", d),
@@ -82,12 +86,12 @@ pub fn disassemble(inst: Instruction, pc: u16) -> String {
         Instruction::Cbi { a, b }  => format!("CBI 0x{:02X}, {}", a, b),
         // Branch
         Instruction::Rjmp { k } => {
-            let target = (pc as i32 + 1 + k as i32) as u16;
-            format!("RJMP .{:+} ; 0x{:04X}", k, target * 2)
+            let target = (pc as i32 + 1 + k as i32) as u32;
+            format!("RJMP .{:+} ; 0x{:04X}", k, target.wrapping_mul(2))
         }
         Instruction::Rcall { k } => {
-            let target = (pc as i32 + 1 + k as i32) as u16;
-            format!("RCALL .{:+} ; 0x{:04X}", k, target * 2)
+            let target = (pc as i32 + 1 + k as i32) as u32;
+            format!("RCALL .{:+} ; 0x{:04X}", k, target.wrapping_mul(2))
         }
         Instruction::Ret  => "RET".into(),
         Instruction::Reti => "RETI".into(),
@@ -103,22 +107,22 @@ pub fn disassemble(inst: Instruction, pc: u16) -> String {
         Instruction::Sbic { a, b } => format!("SBIC 0x{:02X}, {}", a, b),
         Instruction::Sbis { a, b } => format!("SBIS 0x{:02X}, {}", a, b),
         Instruction::Brbs { s, k } => {
-            let target = (pc as i32 + 1 + k as i32) as u16;
+            let target = (pc as i32 + 1 + k as i32) as u32;
             let name = match s {
                 0 => "BRCS", 1 => "BREQ", 2 => "BRMI", 3 => "BRVS",
                 4 => "BRLT", 5 => "BRHS", 6 => "BRTS", 7 => "BRIE",
                 _ => "BRBS",
             };
-            format!("{} .{:+} ; 0x{:04X}", name, k, target * 2)
+            format!("{} .{:+} ; 0x{:04X}", name, k, target.wrapping_mul(2))
         }
         Instruction::Brbc { s, k } => {
-            let target = (pc as i32 + 1 + k as i32) as u16;
+            let target = (pc as i32 + 1 + k as i32) as u32;
             let name = match s {
                 0 => "BRCC", 1 => "BRNE", 2 => "BRPL", 3 => "BRVC",
                 4 => "BRGE", 5 => "BRHC", 6 => "BRTC", 7 => "BRID",
                 _ => "BRBC",
             };
-            format!("{} .{:+} ; 0x{:04X}", name, k, target * 2)
+            format!("{} .{:+} ; 0x{:04X}", name, k, target.wrapping_mul(2))
         }
         // I/O
         Instruction::In { d, a }  => format!("IN R{}, 0x{:02X}", d, a),
@@ -153,6 +157,7 @@ pub fn disassemble(inst: Instruction, pc: u16) -> String {
         Instruction::Wdr   => "WDR".into(),
         Instruction::Break => "BREAK".into(),
         Instruction::Spm   => "SPM".into(),
+        Instruction::Des { k } => format!("DES 0x{:02X}", k),
         Instruction::Unknown(w) => format!(".dw 0x{:04X}", w),
     }
 }
@@ -184,7 +189,7 @@ pub fn disassemble_range(flash: &[u8], start_byte: usize, end_byte: usize) -> Ve
         let next = if addr + 3 < flash.len() {
             (flash[addr + 2] as u16) | ((flash[addr + 3] as u16) << 8)
         } else { 0 };
-        let pc = (addr / 2) as u16;
+        let pc = (addr / 2) as u32;
         let (inst, size) = opcodes::decode(word, next);
         let asm = disassemble(inst, pc);
         if size == 2 {
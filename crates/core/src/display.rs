@@ -4,14 +4,256 @@
 //! VRAM that is rendered to an RGBA framebuffer. Supports horizontal and
 //! vertical addressing modes, column/page address windowing, and the
 //! display-on/off command set used by the Arduboy2 library.
+//!
+//! Clone hardware with smaller SSD1306-compatible panels (96×64, 72×40) is
+//! supported through [`PanelGeometry`]: only the panel's visible window of
+//! GDDRAM is drawn, into the top-left corner of the 128×64 framebuffer.
+//!
+//! DIY builds often use related controllers (SH1106, SSD1309, SSD1305) that
+//! accept mostly the same command set. Their differences are modelled as
+//! [`DisplayQuirks`] on the one controller emulation:
+//!
+//! | Controller | GDDRAM  | Charge pump (`8D`) | Addressing modes (`20`/`21`/`22`) | Panel column |
+//! |------------|---------|--------------------|-----------------------------------|--------------|
+//! | SSD1306    | 128×64  | yes                | horizontal, vertical, page        | 0            |
+//! | SH1106     | 132×64  | no (`AD` DC-DC)    | page only                         | 2            |
+//! | SSD1309    | 128×64  | no                 | horizontal, vertical, page        | 0            |
+//! | SSD1305    | 132×64  | no (`AD` config)   | horizontal, vertical, page        | 0            |
+//!
+//! A command a controller does not implement is a one-byte no-op, so its
+//! parameter bytes are decoded as commands in their own right — as on the
+//! real chip.
+//!
+//! By default the screen shows GDDRAM whether or not the game powered the
+//! panel up. With [`DisplayQuirks::strict_power`] it stays dark, as real
+//! hardware does, until both of these have been received, and a warning
+//! names the command still missing once the game starts sending pixels:
+//!
+//! | Command | Needed |
+//! |---------|--------|
+//! | `8D 14` charge pump enable | controllers with a charge pump (`8D`) |
+//! | `AF` display on | always |
+//!
+//! Once the game has powered the panel up, switching it off again — `AE`
+//! display off, or `8D 10` charge pump off — darkens the screen in either
+//! mode, so a game that sleeps the display (screensaver, pause) shows a
+//! dark panel rather than the last image. [`Ssd1306::powered_on`] reports
+//! the current state for frontend status lines.
+//!
+//! The panel is taken to be wired the Arduboy way round: upright with `A1`
+//! (segment remap) and `C8` (COM scan decreasing), which the Arduboy2 boot
+//! sequence sends. `A0` mirrors the image left-to-right and `C0` flips it
+//! top-to-bottom, as seen on flipped homemade builds; until the game sends
+//! either command the image is upright. As on the chip, `A0`/`A1` only
+//! affects data written afterwards while `C0`/`C8` flips the image at once.
 
 use crate::{SCREEN_WIDTH, SCREEN_HEIGHT};
 
 const FB_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4; // RGBA
 
-/// SSD1306 128x64 monochrome OLED display controller
+/// Visible area of the physical panel attached to the controller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PanelGeometry {
+    /// Visible width in pixels (≤ 128)
+    pub width: usize,
+    /// Visible height in pixels (≤ 64)
+    pub height: usize,
+    /// First GDDRAM column wired to the panel
+    pub col_offset: u8,
+    /// Pixel width / height of the physical panel (1.0 = square)
+    pub pixel_aspect: f32,
+}
+
+impl PanelGeometry {
+    /// Standard Arduboy 128×64 panel
+    pub const ARDUBOY: PanelGeometry =
+        PanelGeometry { width: SCREEN_WIDTH, height: SCREEN_HEIGHT, col_offset: 0, pixel_aspect: 1.0 };
+
+    /// Parse `WxH` or `WxH+OFFSET` (GDDRAM column offset).
+    ///
+    /// Without an explicit offset, 72×40 modules use column 28 (their usual
+    /// wiring); other sizes start at column 0.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (dims, off) = match spec.split_once('+') {
+            Some((d, o)) => (d, Some(o)),
+            None => (spec, None),
+        };
+        let (w, h) = dims.split_once(['x', 'X'])
+            .ok_or_else(|| format!("invalid screen size '{}' (expected WxH)", spec))?;
+        let width: usize = w.trim().parse().map_err(|_| format!("invalid width '{}'", w))?;
+        let height: usize = h.trim().parse().map_err(|_| format!("invalid height '{}'", h))?;
+        if width == 0 || width > SCREEN_WIDTH || height == 0 || height > SCREEN_HEIGHT {
+            return Err(format!("screen size {}x{} out of range (max {}x{})",
+                width, height, SCREEN_WIDTH, SCREEN_HEIGHT));
+        }
+        let col_offset = match off {
+            Some(o) => o.trim().parse::<u8>().map_err(|_| format!("invalid column offset '{}'", o))?,
+            None if (width, height) == (72, 40) => 28,
+            None => 0,
+        };
+        if col_offset as usize + width > SCREEN_WIDTH {
+            return Err(format!("column offset {} + width {} exceeds {}", col_offset, width, SCREEN_WIDTH));
+        }
+        Ok(PanelGeometry { width, height, col_offset, pixel_aspect: 1.0 })
+    }
+}
+
+impl Default for PanelGeometry {
+    fn default() -> Self { Self::ARDUBOY }
+}
+
+/// OLED controller family driving the panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisplayController {
+    Ssd1306,
+    Sh1106,
+    Ssd1309,
+    Ssd1305,
+}
+
+impl DisplayController {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DisplayController::Ssd1306 => "SSD1306",
+            DisplayController::Sh1106 => "SH1106",
+            DisplayController::Ssd1309 => "SSD1309",
+            DisplayController::Ssd1305 => "SSD1305",
+        }
+    }
+
+    /// Parse a controller name (case-insensitive).
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "ssd1306" => Some(DisplayController::Ssd1306),
+            "sh1106" => Some(DisplayController::Sh1106),
+            "ssd1309" => Some(DisplayController::Ssd1309),
+            "ssd1305" => Some(DisplayController::Ssd1305),
+            _ => None,
+        }
+    }
+
+    /// GDDRAM width in columns.
+    pub fn ram_columns(&self) -> u8 {
+        match self {
+            DisplayController::Sh1106 | DisplayController::Ssd1305 => 132,
+            _ => 128,
+        }
+    }
+
+    /// Whether `AD` is a two-byte command (SH1106 DC-DC, SSD1305 master config).
+    fn has_ad_command(&self) -> bool {
+        matches!(self, DisplayController::Sh1106 | DisplayController::Ssd1305)
+    }
+}
+
+/// Controller-specific command behavior.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayQuirks {
+    pub controller: DisplayController,
+    /// `8D` charge pump command is recognized (takes one parameter)
+    pub charge_pump: bool,
+    /// `20`/`21`/`22` addressing commands are recognized; otherwise the
+    /// controller is fixed in page addressing mode
+    pub addressing_modes: bool,
+    /// Segment remap state after reset (`A1` instead of `A0`)
+    pub seg_remap: bool,
+    /// GDDRAM column wired to the panel's first pixel
+    pub col_offset: u8,
+    /// Keep the screen dark until the panel has been powered up
+    pub strict_power: bool,
+}
+
+impl DisplayQuirks {
+    /// Datasheet behavior of `controller`.
+    pub fn for_controller(controller: DisplayController) -> Self {
+        let base = DisplayQuirks {
+            controller, charge_pump: false, addressing_modes: true, seg_remap: false, col_offset: 0,
+            strict_power: false,
+        };
+        match controller {
+            DisplayController::Ssd1306 => DisplayQuirks { charge_pump: true, ..base },
+            DisplayController::Sh1106 => DisplayQuirks { addressing_modes: false, col_offset: 2, ..base },
+            DisplayController::Ssd1309 | DisplayController::Ssd1305 => base,
+        }
+    }
+
+    /// Parse `controller[,option...]`, e.g. `sh1106`, `ssd1309,charge-pump`,
+    /// `ssd1305,offset=4,remap`.
+    ///
+    /// Options: `charge-pump`, `no-charge-pump`, `page-only`, `remap`,
+    /// `offset=N`, `strict`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(',').map(|s| s.trim());
+        let name = parts.next().unwrap_or("");
+        let controller = DisplayController::parse(name).ok_or_else(|| format!(
+            "unknown display controller '{}' (expected ssd1306, sh1106, ssd1309, ssd1305)", name))?;
+        let mut q = Self::for_controller(controller);
+        for opt in parts.filter(|s| !s.is_empty()) {
+            match opt.to_ascii_lowercase().as_str() {
+                "charge-pump" => q.charge_pump = true,
+                "no-charge-pump" => q.charge_pump = false,
+                "page-only" => q.addressing_modes = false,
+                "remap" => q.seg_remap = true,
+                "strict" => q.strict_power = true,
+                o => match o.strip_prefix("offset=") {
+                    Some(n) => {
+                        q.col_offset = n.parse().ok()
+                            .filter(|&v: &u8| v < controller.ram_columns())
+                            .ok_or_else(|| format!("invalid column offset '{}'", n))?;
+                    }
+                    None => return Err(format!("unknown display option '{}'", opt)),
+                },
+            }
+        }
+        Ok(q)
+    }
+}
+
+impl Default for DisplayQuirks {
+    fn default() -> Self { Self::for_controller(DisplayController::Ssd1306) }
+}
+
+/// Image orientation on the panel (`A0`/`A1`, `C0`/`C8`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    /// Mirrored left-to-right (`A0`)
+    pub mirror_x: bool,
+    /// Flipped top-to-bottom (`C0`)
+    pub flip_y: bool,
+}
+
+impl Orientation {
+    pub fn name(&self) -> &'static str {
+        match (self.mirror_x, self.flip_y) {
+            (false, false) => "upright",
+            (true, false) => "mirrored",
+            (false, true) => "flipped",
+            (true, true) => "rotated 180°",
+        }
+    }
+}
+
+/// GDDRAM addressing mode (`20` command)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AddrMode {
+    Horizontal,
+    Vertical,
+    Page,
+}
+
+/// SSD1306-family 128x64 monochrome OLED display controller
 pub struct Ssd1306 {
     pub framebuffer: [u8; FB_SIZE],
+    /// Attached panel geometry
+    pub geometry: PanelGeometry,
+    /// Controller-specific command behavior
+    pub quirks: DisplayQuirks,
+    /// Current addressing mode
+    addr_mode: AddrMode,
+    /// Segment remap (`A0`/`A1`)
+    seg_remap: bool,
+    /// Orientation new data is drawn in
+    orientation: Orientation,
     /// Current column pointer
     col: u8,
     /// Current page pointer (each page = 8 rows)
@@ -26,6 +268,16 @@ pub struct Ssd1306 {
     inverted: bool,
     /// Display on/off
     display_on: bool,
+    /// Charge pump enabled (`8D 14`)
+    charge_pump_on: bool,
+    /// The panel has been powered up since reset
+    powered_up: bool,
+    /// Data has been written since the last power-sequence check
+    data_seen: bool,
+    /// Missing command already reported (strict mode)
+    warned: Option<&'static str>,
+    /// Strict-mode warnings not yet taken
+    pending: Vec<String>,
     /// Contrast level (0x00–0xFF, default 0x7F)
     pub contrast: u8,
     /// Whether framebuffer has been updated
@@ -48,20 +300,39 @@ enum CmdState {
     SetPageStart,
     SetPageEnd,
     SetContrast,
+    SetAddrMode,
+    SetChargePump,
 }
 
 impl Ssd1306 {
     pub fn new() -> Self {
+        Self::with_quirks(DisplayQuirks::default())
+    }
+
+    /// Controller in its reset state with the given quirks.
+    pub fn with_quirks(quirks: DisplayQuirks) -> Self {
         Ssd1306 {
             framebuffer: [0; FB_SIZE],
+            geometry: PanelGeometry::ARDUBOY,
+            quirks,
+            // Strict SSD1306 resets to page mode; horizontal is kept as the
+            // lenient default since it is what every Arduboy library selects
+            addr_mode: if quirks.addressing_modes { AddrMode::Horizontal } else { AddrMode::Page },
+            seg_remap: quirks.seg_remap,
+            orientation: Orientation::default(),
             col: 0,
             page: 0,
             col_start: 0,
-            col_end: 127,
+            col_end: quirks.controller.ram_columns() - 1,
             page_start: 0,
             page_end: 7,
             inverted: false,
             display_on: false,
+            charge_pump_on: false,
+            powered_up: false,
+            data_seen: false,
+            warned: None,
+            pending: Vec::new(),
             contrast: 0xCF, // SSD1306 default
             dirty: false,
             cmd_state: CmdState::Ready,
@@ -82,13 +353,13 @@ impl Ssd1306 {
 
         match self.cmd_state {
             CmdState::SetColStart => {
-                self.col_start = byte.min(127);
+                self.col_start = byte.min(self.last_col());
                 self.col = self.col_start;
                 self.cmd_state = CmdState::SetColEnd;
                 return;
             }
             CmdState::SetColEnd => {
-                self.col_end = byte.min(127);
+                self.col_end = byte.min(self.last_col());
                 self.cmd_state = CmdState::Ready;
                 return;
             }
@@ -108,23 +379,46 @@ impl Ssd1306 {
                 self.cmd_state = CmdState::Ready;
                 return;
             }
+            CmdState::SetAddrMode => {
+                self.addr_mode = match byte & 0x03 {
+                    0 => AddrMode::Horizontal,
+                    1 => AddrMode::Vertical,
+                    2 => AddrMode::Page,
+                    _ => self.addr_mode, // invalid, ignored
+                };
+                self.cmd_state = CmdState::Ready;
+                return;
+            }
+            CmdState::SetChargePump => {
+                self.charge_pump_on = byte & 0x04 != 0;
+                self.cmd_state = CmdState::Ready;
+                self.power_changed();
+                return;
+            }
             CmdState::Ready => {}
         }
 
+        let q = self.quirks;
         match byte {
-            0x21 => {
+            0x20 if q.addressing_modes => {
+                // Set memory addressing mode (1 more byte follows)
+                self.cmd_state = CmdState::SetAddrMode;
+            }
+            0x21 if q.addressing_modes => {
                 // Set column address (2 more bytes follow)
                 self.cmd_state = CmdState::SetColStart;
             }
-            0x22 => {
+            0x22 if q.addressing_modes => {
                 // Set page address (2 more bytes follow)
                 self.cmd_state = CmdState::SetPageStart;
             }
             0xAE => {
                 self.display_on = false;
+                self.power_changed();
             }
             0xAF => {
                 self.display_on = true;
+                self.power_changed();
             }
             0xA6 => {
                 self.inverted = false;
@@ -138,25 +432,50 @@ impl Ssd1306 {
             0x81 => {
                 self.cmd_state = CmdState::SetContrast;
             }
+            0x8D if q.charge_pump => {
+                // Charge pump setting (next byte: 0x14 on, 0x10 off)
+                self.cmd_state = CmdState::SetChargePump;
+            }
+            0xAD if q.controller.has_ad_command() => {
+                // DC-DC / master configuration
+                self.cmd_skip = 1;
+            }
             // Commands that take 1 parameter byte (skip next byte)
-            0x20 | // Set memory addressing mode
             0xA8 | // Set multiplex ratio
             0xD3 | // Set display offset
             0xD5 | // Set display clock divide
             0xD9 | // Set pre-charge period
             0xDA | // Set COM pins hardware config
-            0xDB | // Set VCOMH deselect level
-            0x8D   // Charge pump setting
+            0xDB   // Set VCOMH deselect level
             => {
                 self.cmd_skip = 1;
             }
             // Commands with no extra bytes (or lower nibble commands)
-            0x00..=0x0F => {} // Set lower column start address (page addressing)
-            0x10..=0x1F => {} // Set higher column start address
+            // Set lower / higher column start address (page addressing)
+            0x00..=0x0F if self.addr_mode == AddrMode::Page => {
+                self.col = ((self.col & 0xF0) | byte).min(self.last_col());
+            }
+            0x10..=0x1F if self.addr_mode == AddrMode::Page => {
+                self.col = ((self.col & 0x0F) | ((byte & 0x0F) << 4)).min(self.last_col());
+            }
+            // Set page start address (page addressing)
+            0xB0..=0xB7 if self.addr_mode == AddrMode::Page => {
+                self.page = byte & 0x07;
+            }
+            0x00..=0x1F => {}
             0x40..=0x7F => {} // Set display start line
-            0xA0 | 0xA1 => {} // Segment re-map
+            0xA0 | 0xA1 => {
+                // Segment re-map (applies to data written from now on)
+                self.seg_remap = byte == 0xA1;
+                self.orientation.mirror_x = byte == 0xA0;
+            }
             0xA4 | 0xA5 => {} // Display on/resume from GDDRAM
-            0xC0 | 0xC8 => {} // COM output scan direction
+            // COM output scan direction (flips the shown image at once)
+            0xC0 | 0xC8 if self.orientation.flip_y != (byte == 0xC0) => {
+                self.orientation.flip_y = byte == 0xC0;
+                self.flip_rows();
+            }
+            0xC0 | 0xC8 => {}
             0xE3 => {}        // NOP
             _ => {
                 // Unknown command, ignore
@@ -167,18 +486,24 @@ impl Ssd1306 {
     /// Receive a data byte (DC pin high)
     pub fn receive_data(&mut self, byte: u8) {
         self.dbg_data_count += 1;
-        let x = self.col as usize;
+        self.data_seen = true;
+        let col = if self.orientation.mirror_x { self.last_col() - self.col } else { self.col };
+        // Columns outside the panel window are not wired to any pixel
+        let x = (col as usize)
+            .wrapping_sub(self.quirks.col_offset as usize + self.geometry.col_offset as usize);
         let page = self.page as usize;
 
-        if x < SCREEN_WIDTH && page < 8 {
-            // Pixel brightness scaled by contrast (0x00=black, 0xFF=full)
-            let bright = self.contrast;
+        if x < self.geometry.width && page < 8 {
+            // Pixel brightness scaled by contrast (0x00=black, 0xFF=full);
+            // a lit pixel never stores 0, so `pixel_bits` sees it at any contrast
+            let bright = self.contrast.max(1);
             // Each byte represents 8 vertical pixels in the current column
             for bit in 0..8u8 {
                 let pixel_on = ((byte >> bit) & 1) != 0;
                 let pixel_on = pixel_on ^ self.inverted;
                 let y = page * 8 + bit as usize;
-                if y < SCREEN_HEIGHT {
+                if y < self.geometry.height {
+                    let y = if self.orientation.flip_y { self.geometry.height - 1 - y } else { y };
                     let offset = (y * SCREEN_WIDTH + x) * 4;
                     if pixel_on {
                         self.framebuffer[offset] = bright;     // R
@@ -197,16 +522,119 @@ impl Ssd1306 {
         }
 
         // Advance cursor
-        self.col += 1;
-        if self.col > self.col_end {
-            self.col = self.col_start;
-            self.page += 1;
-            if self.page > self.page_end {
-                self.page = self.page_start;
+        match self.addr_mode {
+            AddrMode::Horizontal => {
+                self.col += 1;
+                if self.col > self.col_end {
+                    self.col = self.col_start;
+                    self.page += 1;
+                    if self.page > self.page_end {
+                        self.page = self.page_start;
+                    }
+                }
+            }
+            AddrMode::Vertical => {
+                self.page += 1;
+                if self.page > self.page_end {
+                    self.page = self.page_start;
+                    self.col += 1;
+                    if self.col > self.col_end {
+                        self.col = self.col_start;
+                    }
+                }
+            }
+            AddrMode::Page => {
+                // Column wraps within the page; the page never advances
+                self.col = if self.col >= self.last_col() { 0 } else { self.col + 1 };
             }
         }
     }
 
+    /// Last GDDRAM column of the controller.
+    fn last_col(&self) -> u8 {
+        self.quirks.controller.ram_columns() - 1
+    }
+
+    /// Swap the panel's rows top-to-bottom.
+    fn flip_rows(&mut self) {
+        let row = SCREEN_WIDTH * 4;
+        let h = self.geometry.height;
+        for y in 0..h / 2 {
+            let (top, bottom) = self.framebuffer.split_at_mut((h - 1 - y) * row);
+            top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+        }
+        self.dirty = true;
+    }
+
+    /// Orientation selected by the game's remap and scan commands.
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Current segment remap state (`A1` = column 0 mapped to SEG127).
+    pub fn segment_remap(&self) -> bool {
+        self.seg_remap
+    }
+
+    /// The power-up command the panel still needs before it lights, or
+    /// None if it would show GDDRAM.
+    pub fn missing_power_command(&self) -> Option<&'static str> {
+        if self.quirks.charge_pump && !self.charge_pump_on {
+            Some("charge pump enable (8D 14)")
+        } else if !self.display_on {
+            Some("display on (AF)")
+        } else {
+            None
+        }
+    }
+
+    /// Whether the panel would light: powered up and not switched off.
+    pub fn powered_on(&self) -> bool {
+        self.missing_power_command().is_none()
+    }
+
+    fn power_changed(&mut self) {
+        self.powered_up |= self.powered_on();
+        self.dirty = true;
+    }
+
+    /// Whether the screen is dark: strict mode waiting for the power-up
+    /// sequence, or the game switched the panel off after powering it up.
+    pub fn is_dark(&self) -> bool {
+        !self.powered_on() && (self.quirks.strict_power || self.powered_up)
+    }
+
+    /// Strict mode: warn (once per missing command) if pixel data was sent
+    /// to a panel that is not powered up. Called at the end of each frame.
+    pub fn check_power(&mut self, frame: u32) {
+        if !self.quirks.strict_power || !std::mem::take(&mut self.data_seen) {
+            return;
+        }
+        let missing = self.missing_power_command();
+        if missing.is_some() && missing != self.warned {
+            self.pending.push(format!("{} F{}: screen is dark on hardware: display data sent without {}",
+                self.quirks.controller.name(), frame, missing.unwrap_or_default()));
+        }
+        self.warned = missing;
+    }
+
+    /// Take strict-mode warnings since the last call.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Lit state of every framebuffer pixel, row-major, whatever the
+    /// contrast and whether the panel is switched on.
+    pub fn pixel_bits(&self) -> Vec<bool> {
+        self.framebuffer.chunks(4).map(|p| p[0] != 0).collect()
+    }
+
+    /// RGBA pixels as shown: the framebuffer, or black while dark.
+    pub fn visible_rgba(&self) -> &[u8] {
+        static DARK: [u8; FB_SIZE] = [0; FB_SIZE];
+        if self.is_dark() { &DARK } else { &self.framebuffer }
+    }
+
     /// Reset per-frame debug counters
     pub fn dbg_reset_counters(&mut self) {
         self.dbg_cmd_count = 0;
@@ -215,11 +643,12 @@ impl Ssd1306 {
 
     /// Convert framebuffer to u32 pixel array (0xRRGGBB format for minifb)
     pub fn as_pixel_buffer(&self) -> Vec<u32> {
+        let fb = self.visible_rgba();
         let mut pixels = vec![0u32; SCREEN_WIDTH * SCREEN_HEIGHT];
         for i in 0..pixels.len() {
-            let r = self.framebuffer[i * 4] as u32;
-            let g = self.framebuffer[i * 4 + 1] as u32;
-            let b = self.framebuffer[i * 4 + 2] as u32;
+            let r = fb[i * 4] as u32;
+            let g = fb[i * 4 + 1] as u32;
+            let b = fb[i * 4 + 2] as u32;
             pixels[i] = (r << 16) | (g << 8) | b;
         }
         pixels
@@ -245,9 +674,15 @@ impl Ssd1306 {
         self.col_start = s.col_start; self.col_end = s.col_end;
         self.page_start = s.page_start; self.page_end = s.page_end;
         self.inverted = s.inverted; self.display_on = s.display_on;
+        // Not in the save format: a panel that was on was powered up
+        self.charge_pump_on = s.display_on;
+        self.powered_up = s.display_on;
         self.contrast = s.contrast;
         self.cmd_state = CmdState::Ready;
         self.cmd_skip = 0;
+        self.addr_mode = if self.quirks.addressing_modes { AddrMode::Horizontal } else { AddrMode::Page };
+        // Orientation is not in the save format either; it carries over
+        // from the running game, which set it at boot
         self.dirty = true;
     }
 }
@@ -275,6 +710,130 @@ mod tests {
         assert_eq!(display.col, 10);
     }
 
+    #[test]
+    fn test_panel_geometry() {
+        assert_eq!(PanelGeometry::parse("72x40").unwrap().col_offset, 28);
+        let g = PanelGeometry::parse("96x64+16").unwrap();
+        assert_eq!((g.width, g.height, g.col_offset), (96, 64, 16));
+        assert!(PanelGeometry::parse("200x64").is_err());
+        assert!(PanelGeometry::parse("96x64+40").is_err());
+
+        let mut display = Ssd1306::new();
+        display.geometry = PanelGeometry::parse("72x40").unwrap();
+        display.receive_command(0x21);
+        display.receive_command(27);
+        display.receive_command(28);
+        display.receive_data(0xFF); // column 27: off-panel
+        display.receive_data(0xFF); // column 28: panel x = 0
+        assert_eq!(display.framebuffer[0], display.contrast);
+        assert_eq!(display.framebuffer[4], 0);
+    }
+
+    #[test]
+    fn test_controller_quirks() {
+        let q = DisplayQuirks::parse("SSD1309,remap").unwrap();
+        assert_eq!(q.controller, DisplayController::Ssd1309);
+        assert!(!q.charge_pump && q.seg_remap);
+        assert!(DisplayQuirks::parse("ssd1305,offset=4").unwrap().col_offset == 4);
+        assert!(DisplayQuirks::parse("st7565").is_err());
+        assert!(DisplayQuirks::parse("sh1106,offset=200").is_err());
+
+        // SH1106: page addressing only, panel starts at column 2
+        let mut d = Ssd1306::with_quirks(DisplayQuirks::parse("sh1106").unwrap());
+        d.receive_command(0xB1); // page 1
+        d.receive_command(0x02); // column 2 (low nibble)
+        d.receive_command(0x10); // column 2 (high nibble)
+        d.receive_data(0x01);
+        assert_eq!(d.framebuffer[8 * SCREEN_WIDTH * 4], d.contrast);
+
+        // SSD1309 has no charge pump: 8D is a no-op and 0x14 sets the
+        // high column nibble, which horizontal mode ignores
+        let mut d = Ssd1306::with_quirks(DisplayQuirks::for_controller(DisplayController::Ssd1309));
+        d.receive_command(0x8D);
+        d.receive_command(0x14);
+        d.receive_command(0xA1);
+        assert!(d.segment_remap());
+        assert_eq!(d.cmd_skip, 0);
+    }
+
+    #[test]
+    fn test_strict_power() {
+        let mut d = Ssd1306::with_quirks(DisplayQuirks::parse("ssd1306,strict").unwrap());
+        d.receive_data(0xFF);
+        assert!(d.is_dark());
+        assert!(d.visible_rgba().iter().all(|&b| b == 0));
+        d.check_power(3);
+        assert_eq!(d.take_messages(),
+            vec!["SSD1306 F3: screen is dark on hardware: display data sent without charge pump enable (8D 14)"]);
+        d.receive_data(0xFF);
+        d.check_power(4);
+        assert!(d.take_messages().is_empty());
+
+        d.receive_command(0x8D);
+        d.receive_command(0x14);
+        assert_eq!(d.missing_power_command(), Some("display on (AF)"));
+        d.receive_command(0xAF);
+        assert!(!d.is_dark());
+        assert_eq!(d.visible_rgba()[0], d.contrast);
+
+        // Lenient default shows GDDRAM regardless
+        let mut d = Ssd1306::new();
+        d.receive_data(0xFF);
+        d.check_power(0);
+        assert!(!d.is_dark() && d.take_messages().is_empty());
+    }
+
+    #[test]
+    fn test_display_sleep() {
+        // Lenient mode: dark once the game switches a powered panel off
+        let mut d = Ssd1306::new();
+        d.receive_data(0xFF);
+        for c in [0x8D, 0x14, 0xAF] {
+            d.receive_command(c);
+        }
+        assert!(d.powered_on() && !d.is_dark());
+        d.dirty = false;
+        d.receive_command(0xAE);
+        assert!(!d.powered_on() && d.is_dark() && d.dirty);
+        assert!(d.visible_rgba().iter().all(|&b| b == 0));
+        d.receive_command(0xAF);
+        assert!(!d.is_dark());
+        d.receive_command(0x8D);
+        d.receive_command(0x10);
+        assert!(d.is_dark());
+    }
+
+    #[test]
+    fn test_orientation() {
+        let lit = |d: &Ssd1306, x: usize, y: usize| d.framebuffer[(y * SCREEN_WIDTH + x) * 4] != 0;
+        let mut d = Ssd1306::new();
+        d.receive_command(0xA1);
+        d.receive_command(0xC8);
+        d.receive_data(0x01);
+        assert!(lit(&d, 0, 0));
+        assert_eq!(d.orientation().name(), "upright");
+
+        // C0 flips what is already on screen
+        d.receive_command(0xC0);
+        assert!(lit(&d, 0, 63) && !lit(&d, 0, 0));
+        // A0 only affects new data: column 1 lands at x = 126
+        d.receive_command(0xA0);
+        d.receive_data(0x01);
+        assert!(lit(&d, 126, 63));
+        assert_eq!(d.orientation(), Orientation { mirror_x: true, flip_y: true });
+        assert_eq!(d.orientation().name(), "rotated 180°");
+    }
+
+    #[test]
+    fn test_vertical_addressing() {
+        let mut display = Ssd1306::new();
+        display.receive_command(0x20);
+        display.receive_command(0x01);
+        display.receive_data(0x01);
+        display.receive_data(0x01);
+        assert_eq!((display.col, display.page), (0, 2));
+    }
+
     #[test]
     fn test_write_pixel_data() {
         let mut display = Ssd1306::new();
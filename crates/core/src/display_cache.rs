@@ -0,0 +1,157 @@
+//! Per-game cache of the auto-detected display.
+//!
+//! Without a board profile the display type is guessed from the first SPI
+//! commands of each run, and until the guess settles some games show a
+//! garbage frame. The cache remembers what was detected, keyed by a hash of
+//! the program, and [`Arduboy::reset`](crate::Arduboy::reset) applies it so
+//! the next run starts with the right controller. Games not in the cache
+//! are detected as before and added.
+//!
+//! One game per line:
+//!
+//! ```text
+//! # program hash     display  PCD8544 pins (ATmega328P: PORTC bits)
+//! 3f2a9c0d11e4b7a8 ssd1306
+//! 90c1d2e3f4a5b6c7 pcd8544 cs=1 dc=2
+//! ```
+//!
+//! The default file is `arduboy-emu/displays.txt` in the user's
+//! configuration directory ([`DisplayCache::default_path`]).
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::DisplayType;
+
+/// Display detected for one game.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachedDisplay {
+    pub display: DisplayType,
+    /// PCD8544 (CS, DC) bits in PORTC on the ATmega328P
+    pub pcd_pins: Option<(u8, u8)>,
+}
+
+/// Detected displays by program hash.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisplayCache {
+    pub entries: BTreeMap<u64, CachedDisplay>,
+    /// Changed since loaded
+    pub dirty: bool,
+}
+
+impl DisplayCache {
+    /// `$XDG_CONFIG_HOME`, `~/.config` or `%APPDATA%`, then `arduboy-emu/displays.txt`.
+    pub fn default_path() -> Option<PathBuf> {
+        let var = |k: &str| std::env::var_os(k).filter(|v| !v.is_empty()).map(PathBuf::from);
+        let dir = var("XDG_CONFIG_HOME")
+            .or_else(|| var("HOME").map(|h| h.join(".config")))
+            .or_else(|| var("APPDATA"))?;
+        Some(dir.join("arduboy-emu").join("displays.txt"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut out = DisplayCache::default();
+        for (n, line) in text.lines().enumerate() {
+            let parts: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            if parts.is_empty() {
+                continue;
+            }
+            let err = |m: String| format!("line {}: {}", n + 1, m);
+            let hash = u64::from_str_radix(parts[0], 16).map_err(|_| err(format!("bad hash '{}'", parts[0])))?;
+            let display = match parts.get(1).copied() {
+                Some("ssd1306") => DisplayType::Ssd1306,
+                Some("pcd8544") => DisplayType::Pcd8544,
+                other => return Err(err(format!("unknown display '{}'", other.unwrap_or("")))),
+            };
+            let (mut cs, mut dc) = (None, None);
+            for opt in &parts[2..] {
+                let (slot, val) = match opt.split_once('=') {
+                    Some(("cs", v)) => (&mut cs, v),
+                    Some(("dc", v)) => (&mut dc, v),
+                    _ => return Err(err(format!("unexpected '{}'", opt))),
+                };
+                *slot = Some(val.parse::<u8>().ok().filter(|&b| b < 8)
+                    .ok_or_else(|| err(format!("bad pin bit '{}'", val)))?);
+            }
+            let pcd_pins = match (cs, dc) {
+                (Some(cs), Some(dc)) => Some((cs, dc)),
+                (None, None) => None,
+                _ => return Err(err("cs= and dc= go together".into())),
+            };
+            out.entries.insert(hash, CachedDisplay { display, pcd_pins });
+        }
+        Ok(out)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = String::from("# arduboy-emu detected displays\n");
+        for (hash, d) in &self.entries {
+            let name = if d.display == DisplayType::Pcd8544 { "pcd8544" } else { "ssd1306" };
+            out.push_str(&format!("{:016x} {}", hash, name));
+            if let Some((cs, dc)) = d.pcd_pins {
+                out.push_str(&format!(" cs={} dc={}", cs, dc));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Load `path`; a missing file gives an empty cache.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    /// Write `path` (creating its directory) and clear `dirty`.
+    pub fn save(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, self.to_text()).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    pub fn get(&self, hash: u64) -> Option<CachedDisplay> {
+        self.entries.get(&hash).copied()
+    }
+
+    /// Remember `display` for `hash`; `Unknown` is not stored.
+    pub fn record(&mut self, hash: u64, display: CachedDisplay) {
+        if display.display != DisplayType::Unknown && self.get(hash) != Some(display) {
+            self.entries.insert(hash, display);
+            self.dirty = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_applies_on_reset() {
+        let text = "# comment\n00000000000000ab ssd1306\n00000000000000cd pcd8544 cs=3 dc=4\n";
+        let cache = DisplayCache::parse(text).unwrap();
+        assert_eq!(cache.get(0xCD), Some(CachedDisplay { display: DisplayType::Pcd8544, pcd_pins: Some((3, 4)) }));
+        assert_eq!(DisplayCache::parse(&cache.to_text()).unwrap(), cache);
+        assert!(DisplayCache::parse("zz ssd1306").is_err());
+        assert!(DisplayCache::parse("ab pcd8544 cs=1").is_err());
+
+        // A recorded display is applied at the next reset of the same program
+        let mut ard = crate::Arduboy::new();
+        ard.display_cache = Some(DisplayCache::default());
+        ard.mem.flash[..4].copy_from_slice(&[0x0C, 0x94, 0x62, 0x00]);
+        ard.program_size = 4;
+        ard.reset();
+        assert_eq!(ard.display_type, DisplayType::Unknown);
+        let hash = ard.program_hash();
+        ard.display_cache.as_mut().unwrap().record(hash, CachedDisplay { display: DisplayType::Pcd8544, pcd_pins: None });
+        ard.reset();
+        assert_eq!(ard.display_type, DisplayType::Pcd8544);
+        assert!(ard.display_cache.as_ref().unwrap().dirty);
+    }
+}
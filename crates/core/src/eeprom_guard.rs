@@ -0,0 +1,230 @@
+//! EEPROM write watch and save-range guard.
+//!
+//! The Arduboy2 library keeps system settings in the first 16 bytes of
+//! EEPROM (audio on/off, unit name and ID, boot logo flags); games are
+//! expected to store their saves from `EEPROM_STORAGE_SPACE_START` (16)
+//! upward. A buggy game that writes below that corrupts the settings shared
+//! by every game on the device.
+//!
+//! [`EepromGuard`] sees every EEPROM write before it lands:
+//!
+//! - **Watch** (`log_writes`): report each write with its address, old and
+//!   new value, PC and frame
+//! - **Guard** (`save_range`): drop writes outside the game's save range and
+//!   report the first blocked write per cell
+//!
+//! The save range is set by hand, or looked up per game in a [`SaveRanges`]
+//! database keyed by program hash, one game per line:
+//!
+//! ```text
+//! # program hash     save range   title
+//! 3f2a9c0d11e4b7a8 0x010-0x04F    # Circuit Dude
+//! ```
+//!
+//! The default file is `arduboy-emu/save-ranges.txt` in the user's
+//! configuration directory ([`SaveRanges::default_path`]). With a database
+//! attached, [`Arduboy::reset`](crate::Arduboy::reset) selects the loaded
+//! game's range; games without an entry only have the system area
+//! protected, and the guard reports their hash so an entry can be added.
+//!
+//! Messages go to the shared [`Diagnostics`] sink.
+
+use crate::diagnostics::Diagnostics;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// First byte after the Arduboy2 system area (`EEPROM_STORAGE_SPACE_START`)
+pub const SYSTEM_AREA_END: u16 = 16;
+
+/// Known save ranges by program hash.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SaveRanges {
+    pub entries: BTreeMap<u64, (u16, u16)>,
+}
+
+impl SaveRanges {
+    /// `save-ranges.txt` next to the display cache in the user config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        crate::display_cache::DisplayCache::default_path().map(|p| p.with_file_name("save-ranges.txt"))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut out = SaveRanges::default();
+        for (n, line) in text.lines().enumerate() {
+            let parts: Vec<&str> = line.split('#').next().unwrap_or("").split_whitespace().collect();
+            if parts.is_empty() {
+                continue;
+            }
+            let err = |m: String| format!("line {}: {}", n + 1, m);
+            let hash = u64::from_str_radix(parts[0], 16).map_err(|_| err(format!("bad hash '{}'", parts[0])))?;
+            let range = match parts[1..] {
+                [range] => EepromGuard::parse_range(range).map_err(err)?,
+                _ => return Err(err("expected '<hash> <start>-<end>'".into())),
+            };
+            out.entries.insert(hash, range);
+        }
+        Ok(out)
+    }
+
+    /// Load `path`; a missing file gives an empty database.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn get(&self, hash: u64) -> Option<(u16, u16)> {
+        self.entries.get(&hash).copied()
+    }
+}
+
+/// EEPROM write watch and guard.
+#[derive(Debug, Clone)]
+pub struct EepromGuard {
+    /// Report every write
+    pub log_writes: bool,
+    /// Inclusive address range games may write (None = no guard)
+    pub save_range: Option<(u16, u16)>,
+    /// Per-game ranges; when set, [`select_game`](Self::select_game)
+    /// replaces `save_range` on every reset
+    pub game_ranges: Option<SaveRanges>,
+    /// Writes blocked this session
+    pub blocked: u64,
+    /// Cells already reported as blocked
+    reported: Vec<bool>,
+}
+
+impl EepromGuard {
+    pub fn new(size: usize) -> Self {
+        EepromGuard {
+            log_writes: false,
+            save_range: None,
+            game_ranges: None,
+            blocked: 0,
+            reported: vec![false; size],
+        }
+    }
+
+    /// Save range that protects only the Arduboy2 system area.
+    pub fn default_range(size: usize) -> (u16, u16) {
+        (SYSTEM_AREA_END, size.saturating_sub(1) as u16)
+    }
+
+    /// Parse an inclusive `start-end` range (decimal or `0x` hex),
+    /// e.g. `16-1023` or `0x10-0x4F`.
+    pub fn parse_range(s: &str) -> Result<(u16, u16), String> {
+        let num = |t: &str| {
+            let t = t.trim();
+            match t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+                Some(h) => u16::from_str_radix(h, 16),
+                None => t.parse(),
+            }.map_err(|_| format!("bad EEPROM address '{}'", t))
+        };
+        let (a, b) = s.split_once('-').ok_or_else(|| format!("expected start-end, got '{}'", s))?;
+        let (a, b) = (num(a)?, num(b)?);
+        if a > b {
+            return Err(format!("empty EEPROM range {}-{}", a, b));
+        }
+        Ok((a, b))
+    }
+
+    /// Guard the program with hash `hash`: its range from `game_ranges`, or
+    /// the system area alone when it has no entry. Does nothing without a
+    /// database.
+    pub fn select_game(&mut self, hash: u64, out: &mut Diagnostics) {
+        let Some(db) = &self.game_ranges else { return };
+        let (range, known) = match db.get(hash) {
+            Some(r) => (r, true),
+            None => (Self::default_range(self.reported.len()), false),
+        };
+        if self.save_range == Some(range) {
+            return;
+        }
+        self.save_range = Some(range);
+        self.reported.fill(false);
+        out.report(if known {
+            format!("EEPROM guard: game writes limited to 0x{:03X}-0x{:03X} (save range database)", range.0, range.1)
+        } else {
+            format!("EEPROM guard: program {:016x} not in the save range database; protecting the system area only", hash)
+        });
+    }
+
+    /// Check a write of `new` over `old` to cell `addr`; returns false if
+    /// the write must be dropped.
+    pub fn check(&mut self, pc: u32, addr: u16, old: u8, new: u8, frame: u32, out: &mut Diagnostics) -> bool {
+        let allowed = self.save_range.is_none_or(|(lo, hi)| (lo..=hi).contains(&addr));
+        if self.log_writes {
+            out.report(format!("EEPROM write 0x{:03X} = 0x{:02X} (was 0x{:02X}) PC=0x{:04X} frame {}{}",
+                addr, new, old, pc, frame, if allowed { "" } else { " [blocked]" }));
+        }
+        if !allowed {
+            self.blocked += 1;
+            let a = addr as usize;
+            if a < self.reported.len() && !self.reported[a] && !self.log_writes {
+                self.reported[a] = true;
+                let (lo, hi) = self.save_range.unwrap_or_default();
+                out.report(format!(
+                    "EEPROM guard: blocked write to 0x{:03X} (PC=0x{:04X}) outside save range 0x{:03X}-0x{:03X}{}",
+                    addr, pc, lo, hi,
+                    if addr < SYSTEM_AREA_END { " — Arduboy2 system area" } else { "" }));
+            }
+        }
+        allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(EepromGuard::parse_range("16-1023"), Ok((16, 1023)));
+        assert_eq!(EepromGuard::parse_range("0x10-0x4F"), Ok((0x10, 0x4F)));
+        assert!(EepromGuard::parse_range("40-20").is_err());
+        assert!(EepromGuard::parse_range("16").is_err());
+    }
+
+    #[test]
+    fn test_guard_blocks_system_area() {
+        let mut g = EepromGuard::new(1024);
+        let mut out = Diagnostics::default();
+        assert!(g.check(0x100, 2, 0xFF, 0x00, 1, &mut out));
+        g.save_range = Some(EepromGuard::default_range(1024));
+        assert!(!g.check(0x100, 2, 0xFF, 0x00, 1, &mut out));
+        assert!(!g.check(0x100, 2, 0xFF, 0x00, 2, &mut out));
+        assert!(g.check(0x104, 16, 0xFF, 0x00, 2, &mut out));
+        assert_eq!(g.blocked, 2);
+        let msgs = out.take_messages();
+        assert_eq!(msgs.len(), 1, "{:?}", msgs);
+        assert!(msgs[0].contains("system area"));
+
+        g.log_writes = true;
+        g.check(0x108, 20, 0x00, 0x2A, 3, &mut out);
+        assert_eq!(out.take_messages(), vec!["EEPROM write 0x014 = 0x2A (was 0x00) PC=0x0108 frame 3"]);
+    }
+
+    #[test]
+    fn test_save_range_database() {
+        let db = SaveRanges::parse("# comment\n00000000000000ab 0x010-0x04F  # game\n").unwrap();
+        assert_eq!(db.get(0xAB), Some((0x10, 0x4F)));
+        assert!(SaveRanges::parse("ab").is_err());
+        assert!(SaveRanges::parse("ab 40-20").is_err());
+
+        let mut g = EepromGuard::new(1024);
+        let mut out = Diagnostics::default();
+        g.select_game(0xAB, &mut out);
+        assert_eq!(g.save_range, None, "no database, no guard");
+        g.game_ranges = Some(db);
+        g.select_game(0xAB, &mut out);
+        assert_eq!(g.save_range, Some((0x10, 0x4F)));
+        assert!(!g.check(0x100, 0x50, 0xFF, 0x00, 1, &mut out));
+        g.select_game(0xCD, &mut out);
+        assert_eq!(g.save_range, Some((16, 1023)));
+        assert!(g.check(0x100, 0x50, 0xFF, 0x00, 1, &mut out));
+        let msgs = out.take_messages();
+        assert!(msgs.last().unwrap().contains("00000000000000cd"), "{:?}", msgs);
+    }
+}
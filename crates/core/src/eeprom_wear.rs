@@ -0,0 +1,201 @@
+//! EEPROM wear tracking.
+//!
+//! Every EEPE write is one erase/write cycle of the addressed cell, whether or
+//! not the value changes (`EEPROM.write()` vs `EEPROM.update()`). The
+//! ATmega32u4 datasheet rates cells for 100,000 cycles, so a game that saves
+//! on every frame wears a cell out in under half an hour of play.
+//!
+//! [`EepromWear`] keeps per-cell cycle counts for the current session and for
+//! the lifetime of the save file, and raises a warning when a cell is written
+//! on many consecutive frames. Lifetime counts are persisted by the frontend
+//! next to the `.eep` file using [`EepromWear::to_bytes`] /
+//! [`EepromWear::load`].
+
+use std::fmt::Write;
+
+/// Rated erase/write endurance per cell (ATmega32u4 / ATmega328P datasheet)
+pub const ENDURANCE: u32 = 100_000;
+
+/// Consecutive frames with a write to the same cell before warning
+pub const HAMMER_FRAMES: u32 = 60;
+
+const MAGIC: &[u8; 4] = b"EEPW";
+const FORMAT_VERSION: u32 = 1;
+
+/// Per-cell EEPROM write statistics.
+#[derive(Debug, Clone)]
+pub struct EepromWear {
+    /// Erase/write cycles per cell, including previous sessions
+    pub lifetime: Vec<u32>,
+    /// Erase/write cycles per cell in this session
+    pub session: Vec<u32>,
+    /// Session writes that stored the value already in the cell
+    pub redundant: u64,
+    /// Frame of the last write per cell
+    last_frame: Vec<Option<u32>>,
+    /// Consecutive frames with at least one write, per cell
+    streak: Vec<u32>,
+    /// Cells already reported as hammered
+    warned: Vec<bool>,
+    /// Warnings not yet collected by the frontend
+    pending: Vec<String>,
+}
+
+impl EepromWear {
+    pub fn new(size: usize) -> Self {
+        EepromWear {
+            lifetime: vec![0; size],
+            session: vec![0; size],
+            redundant: 0,
+            last_frame: vec![None; size],
+            streak: vec![0; size],
+            warned: vec![false; size],
+            pending: Vec::new(),
+        }
+    }
+
+    /// Forget all counts (e.g. when switching to another game).
+    pub fn clear(&mut self) {
+        *self = Self::new(self.lifetime.len());
+    }
+
+    /// Record one write of `new` over `old` to cell `addr` during `frame`.
+    pub fn record(&mut self, addr: u16, old: u8, new: u8, frame: u32) {
+        let a = addr as usize;
+        if a >= self.lifetime.len() {
+            return;
+        }
+        self.lifetime[a] = self.lifetime[a].saturating_add(1);
+        self.session[a] = self.session[a].saturating_add(1);
+        if old == new {
+            self.redundant += 1;
+        }
+
+        self.streak[a] = match self.last_frame[a] {
+            Some(f) if f == frame => self.streak[a],
+            Some(f) if f.wrapping_add(1) == frame => self.streak[a] + 1,
+            _ => 1,
+        };
+        self.last_frame[a] = Some(frame);
+
+        if self.streak[a] >= HAMMER_FRAMES && !self.warned[a] {
+            self.warned[a] = true;
+            let minutes = ENDURANCE as f64 / 60.0 / 60.0;
+            self.pending.push(format!(
+                "EEPROM wear: cell 0x{:03X} written on {} consecutive frames \
+                 (at 60 writes/s a real cell wears out in ~{:.0} min)",
+                addr, HAMMER_FRAMES, minutes));
+        }
+    }
+
+    /// Take warnings raised since the last call.
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Total erase/write cycles in this session.
+    pub fn session_writes(&self) -> u64 {
+        self.session.iter().map(|&c| c as u64).sum()
+    }
+
+    /// Serialize lifetime counts: `EEPW`, version, cell count, then one
+    /// little-endian u32 per cell.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(12 + self.lifetime.len() * 4);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.lifetime.len() as u32).to_le_bytes());
+        for c in &self.lifetime {
+            out.extend_from_slice(&c.to_le_bytes());
+        }
+        out
+    }
+
+    /// Load lifetime counts written by [`to_bytes`](Self::to_bytes).
+    /// Session statistics are cleared.
+    pub fn load(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() < 12 || &data[0..4] != MAGIC {
+            return Err("not an EEPROM wear file".into());
+        }
+        let word = |i: usize| u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+        let version = word(4);
+        if version != FORMAT_VERSION {
+            return Err(format!("unsupported wear file version {} (expected {})",
+                version, FORMAT_VERSION));
+        }
+        let count = word(8) as usize;
+        if data.len() < 12 + count * 4 {
+            return Err("truncated EEPROM wear file".into());
+        }
+        self.clear();
+        for i in 0..count.min(self.lifetime.len()) {
+            self.lifetime[i] = word(12 + i * 4);
+        }
+        Ok(())
+    }
+
+    /// Human-readable summary: session totals and the most-written cells.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let total = self.session_writes();
+        let cells = self.session.iter().filter(|&&c| c > 0).count();
+        let _ = writeln!(s, "EEPROM wear: {} writes to {} cells this session ({} redundant)",
+            total, cells, self.redundant);
+
+        let mut top: Vec<usize> = (0..self.lifetime.len())
+            .filter(|&i| self.lifetime[i] > 0)
+            .collect();
+        top.sort_by(|&a, &b| self.lifetime[b].cmp(&self.lifetime[a]).then(a.cmp(&b)));
+        for &i in top.iter().take(10) {
+            let pct = self.lifetime[i] as f64 * 100.0 / ENDURANCE as f64;
+            let _ = writeln!(s, "  0x{:03X}: {:>8} session {:>10} lifetime ({:.2}% of {}){}",
+                i, self.session[i], self.lifetime[i], pct, ENDURANCE,
+                if self.warned[i] { "  HAMMERED" } else { "" });
+        }
+        let worn = self.lifetime.iter().filter(|&&c| c >= ENDURANCE).count();
+        if worn > 0 {
+            let _ = writeln!(s, "  {} cell(s) past rated endurance", worn);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hammer_warning() {
+        let mut w = EepromWear::new(1024);
+        for f in 0..HAMMER_FRAMES - 1 {
+            w.record(0x10, 0, 1, f);
+            w.record(0x10, 1, 1, f);
+        }
+        assert!(w.take_warnings().is_empty());
+        // A gap resets the streak
+        w.record(0x10, 1, 2, HAMMER_FRAMES + 5);
+        assert!(w.take_warnings().is_empty());
+        for f in 0..HAMMER_FRAMES {
+            w.record(0x20, 0, 0, 1000 + f);
+        }
+        let warn = w.take_warnings();
+        assert_eq!(warn.len(), 1);
+        assert!(warn[0].contains("0x020"));
+        assert_eq!(w.redundant, HAMMER_FRAMES as u64 - 1 + HAMMER_FRAMES as u64);
+    }
+
+    #[test]
+    fn test_persist_roundtrip() {
+        let mut w = EepromWear::new(1024);
+        w.record(3, 0, 1, 0);
+        w.record(3, 1, 2, 0);
+        let bytes = w.to_bytes();
+        let mut w2 = EepromWear::new(1024);
+        w2.load(&bytes).unwrap();
+        assert_eq!(w2.lifetime[3], 2);
+        assert_eq!(w2.session_writes(), 0);
+        w2.record(3, 2, 3, 0);
+        assert_eq!(w2.lifetime[3], 3);
+        assert!(w2.load(b"nope").is_err());
+    }
+}
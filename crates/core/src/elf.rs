@@ -1,7 +1,10 @@
 //! Minimal ELF and DWARF parser for AVR debug info.
 //!
 //! Reads ELF files (.elf) to extract:
-//! - Flash contents (PT_LOAD segments)
+//! - Flash contents (PT_LOAD segments at their load address, so `.data`
+//!   initializers and bootloader-linked images land where avrdude puts them)
+//! - Section layout, including `.noinit`, `.eeprom`, `.fuse` and `.lock`
+//! - Fuse and lock bytes from the `.fuse`/`.lock` sections (`FUSES`/`LOCKBITS`)
 //! - Symbol table (.symtab) for function name lookup
 //! - DWARF .debug_line for source file + line number ↔ PC mapping
 //!
@@ -14,14 +17,25 @@
 //! The parser handles only little-endian 32-bit ELF (EM_AVR = 83) as
 //! produced by avr-gcc. DWARF versions 2–4 line programs are supported.
 
+use crate::error::{ElfError, Error};
+pub use crate::fuses::Fuses;
 use std::collections::BTreeMap;
 
+/// Offset of data-space addresses in AVR ELF files: RAM symbols and
+/// sections sit at `0x800000 +` their data-space address.
+pub const DATA_OFFSET: u32 = 0x800000;
+
 /// Parsed ELF file contents.
+#[derive(Default)]
 pub struct ElfFile {
     /// Flash image (from PT_LOAD segments below 0x800000)
     pub flash: Vec<u8>,
     /// Symbol table: byte_address → function_name
     pub symbols: BTreeMap<u32, String>,
+    /// Symbol sizes in bytes: byte_address → st_size (0 = unknown)
+    pub sizes: BTreeMap<u32, u32>,
+    /// Allocated sections: (name, address, size); RAM sections at 0x800000+
+    pub sections: Vec<(String, u32, u32)>,
     /// Sorted symbol addresses for reverse lookup
     sym_addrs: Vec<u32>,
     /// Source line map: byte_address → (file, line)
@@ -30,6 +44,76 @@ pub struct ElfFile {
     line_addrs: Vec<u32>,
     /// Entry point (byte address)
     pub entry: u32,
+    /// User comments: byte_address → text (see [`annotations`](crate::annotations))
+    pub comments: BTreeMap<u32, String>,
+    /// Every named section with its run and load addresses
+    pub layout: Vec<Section>,
+    /// Lowest flash byte address loaded (non-zero for bootloader-linked builds)
+    pub flash_base: u32,
+    /// Fuse bytes from the `.fuse` section
+    pub fuses: Option<Fuses>,
+    /// Lock byte from the `.lock` section
+    pub lock: Option<u8>,
+}
+
+/// Memory a section belongs to, from avr-gcc's address space offsets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Flash,
+    /// Data space (0x800000+)
+    Ram,
+    /// 0x810000+
+    Eeprom,
+    /// 0x820000+
+    Fuse,
+    /// 0x830000+
+    Lock,
+    /// 0x840000+
+    Signature,
+    /// Debug info, symbol tables and other non-allocated sections
+    Other,
+}
+
+impl Region {
+    fn of(addr: u32, alloc: bool) -> Region {
+        if !alloc { return Region::Other; }
+        match addr >> 16 {
+            0x00..=0x7F => Region::Flash,
+            0x80 => Region::Ram,
+            0x81 => Region::Eeprom,
+            0x82 => Region::Fuse,
+            0x83 => Region::Lock,
+            0x84 => Region::Signature,
+            _ => Region::Other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Flash => "flash",
+            Region::Ram => "ram",
+            Region::Eeprom => "eeprom",
+            Region::Fuse => "fuse",
+            Region::Lock => "lock",
+            Region::Signature => "signature",
+            Region::Other => "-",
+        }
+    }
+}
+
+/// One ELF section header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Run-time (virtual) address
+    pub addr: u32,
+    /// Load address: where the contents are stored, e.g. `.data`
+    /// initializers in flash. Equal to `addr` outside a loaded segment.
+    pub lma: u32,
+    pub size: u32,
+    pub region: Region,
+    /// No file contents (`.bss`, `.noinit`)
+    pub nobits: bool,
 }
 
 // ELF constants
@@ -37,12 +121,18 @@ const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
 const EM_AVR: u16 = 83;
 const PT_LOAD: u32 = 1;
 const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHF_ALLOC: u32 = 2;
 
+// Out-of-range reads give 0 so malformed headers cannot panic
+fn byte(d: &[u8], o: usize) -> u8 {
+    d.get(o).copied().unwrap_or(0)
+}
 fn u16le(d: &[u8], o: usize) -> u16 {
-    (d[o] as u16) | ((d[o + 1] as u16) << 8)
+    (byte(d, o) as u16) | ((byte(d, o.saturating_add(1)) as u16) << 8)
 }
 fn u32le(d: &[u8], o: usize) -> u32 {
-    (d[o] as u32) | ((d[o+1] as u32)<<8) | ((d[o+2] as u32)<<16) | ((d[o+3] as u32)<<24)
+    u16le(d, o) as u32 | (u16le(d, o.saturating_add(2)) as u32) << 16
 }
 fn read_str(d: &[u8], o: usize) -> String {
     if o >= d.len() { return String::new(); }
@@ -75,14 +165,15 @@ fn read_sleb128(d: &[u8], p: &mut usize) -> i32 {
 }
 
 /// Parse an ELF file from raw bytes.
-pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
-    if data.len() < 52 { return Err("File too small for ELF header".into()); }
-    if data[0..4] != ELF_MAGIC { return Err("Not an ELF file".into()); }
-    if data[4] != 1 { return Err("Only 32-bit ELF supported".into()); }
-    if data[5] != 1 { return Err("Only little-endian ELF supported".into()); }
+pub fn parse_elf(data: &[u8]) -> Result<ElfFile, Error> {
+    let err = |offset, kind| Err(Error::Elf { offset, kind });
+    if data.len() < 52 { return err(data.len(), ElfError::TooSmall); }
+    if data[0..4] != ELF_MAGIC { return err(0, ElfError::NotElf); }
+    if data[4] != 1 { return err(4, ElfError::Not32Bit); }
+    if data[5] != 1 { return err(5, ElfError::NotLittleEndian); }
     let e_machine = u16le(data, 18);
     if e_machine != EM_AVR {
-        return Err(format!("Not AVR ELF (machine={})", e_machine));
+        return err(18, ElfError::NotAvr { machine: e_machine });
     }
 
     let entry = u32le(data, 24);
@@ -95,19 +186,25 @@ pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
     let e_shstrndx = u16le(data, 50) as usize;
 
     // ── Load program segments ──────────────────────────────────────────
+    // Placed by physical address: `.data` runs at 0x800100 but is stored
+    // after `.text`, and bootloaders are linked high in flash.
     let mut flash = vec![0u8; 32768]; // 32KB default
+    let mut flash_base = u32::MAX;
+    // (vaddr, paddr, memsz) to map sections to their load addresses
+    let mut segments = Vec::new();
     for i in 0..e_phnum {
         let off = e_phoff + i * e_phentsize;
         if off + e_phentsize > data.len() { break; }
         let p_type = u32le(data, off);
         if p_type != PT_LOAD { continue; }
         let p_offset = u32le(data, off + 4) as usize;
-        let p_vaddr = u32le(data, off + 8) as usize;
+        let p_vaddr = u32le(data, off + 8);
+        let p_paddr = u32le(data, off + 12);
         let p_filesz = u32le(data, off + 16) as usize;
-        if p_vaddr < 0x800000 && p_offset + p_filesz <= data.len() {
-            let end = p_vaddr + p_filesz;
-            if end > flash.len() { flash.resize(end, 0xFF); }
-            flash[p_vaddr..end].copy_from_slice(&data[p_offset..p_offset + p_filesz]);
+        let p_memsz = u32le(data, off + 20);
+        segments.push((p_vaddr, p_paddr, p_memsz));
+        if p_filesz > 0 && p_offset + p_filesz <= data.len() {
+            load_flash(&mut flash, &mut flash_base, p_paddr, &data[p_offset..p_offset + p_filesz]);
         }
     }
 
@@ -123,12 +220,18 @@ pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
     let mut symtab_link = 0usize;
     let mut debug_line_off = 0usize;
     let mut debug_line_size = 0usize;
+    let mut sections = Vec::new();
+    let mut layout = Vec::new();
+    let mut fuses = None;
+    let mut lock = None;
 
     for i in 0..e_shnum {
         let sh = e_shoff + i * e_shentsize;
         if sh + e_shentsize > data.len() { break; }
         let sh_name = u32le(data, sh) as usize;
         let sh_type = u32le(data, sh + 4);
+        let sh_flags = u32le(data, sh + 8);
+        let sh_addr = u32le(data, sh + 12);
         let sh_offset = u32le(data, sh + 16) as usize;
         let sh_size = u32le(data, sh + 20) as usize;
         let sh_link = u32le(data, sh + 24) as usize;
@@ -141,6 +244,34 @@ pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
             symtab_link = sh_link;
         }
         let name = read_str(data, shstrtab_off + sh_name);
+        let alloc = sh_flags & SHF_ALLOC != 0;
+        if alloc && sh_size > 0 {
+            sections.push((name.clone(), sh_addr, sh_size as u32));
+        }
+        let contents = (sh_type != SHT_NOBITS)
+            .then(|| data.get(sh_offset..sh_offset.saturating_add(sh_size)))
+            .flatten();
+        let region = Region::of(sh_addr, alloc);
+        match (region, contents) {
+            (Region::Fuse, Some(c)) if c.len() >= 2 => {
+                fuses = Some(Fuses { low: c[0], high: c[1], extended: c.get(2).copied() });
+            }
+            (Region::Lock, Some(&[b, ..])) => lock = Some(b),
+            // Relocatable objects have no program headers: load by section
+            (Region::Flash, Some(c)) if segments.is_empty() => {
+                load_flash(&mut flash, &mut flash_base, sh_addr, c);
+            }
+            _ => {}
+        }
+        if !name.is_empty() && sh_type != 0 {
+            let lma = segments.iter()
+                .find(|&&(v, _, m)| sh_addr >= v && sh_addr < v.wrapping_add(m))
+                .map_or(sh_addr, |&(v, p, _)| sh_addr - v + p);
+            layout.push(Section {
+                name: name.clone(), addr: sh_addr, lma, size: sh_size as u32,
+                region, nobits: sh_type == SHT_NOBITS,
+            });
+        }
         if name == ".debug_line" {
             debug_line_off = sh_offset;
             debug_line_size = sh_size;
@@ -149,6 +280,7 @@ pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
 
     // ── Symbol table ───────────────────────────────────────────────────
     let mut symbols = BTreeMap::new();
+    let mut sizes = BTreeMap::new();
     if symtab_off > 0 {
         let strtab_off = if symtab_link < e_shnum {
             let sh = e_shoff + symtab_link * e_shentsize;
@@ -161,13 +293,15 @@ pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
             if off + symtab_entsize > data.len() { break; }
             let st_name = u32le(data, off) as usize;
             let st_value = u32le(data, off + 4);
-            let st_info = data[off + 12];
+            let st_size = u32le(data, off + 8);
+            let st_info = byte(data, off + 12);
             let st_type = st_info & 0xF;
             // STT_FUNC=2, STT_OBJECT=1
             if (st_type == 2 || st_type == 1) && st_name > 0 {
                 let name = read_str(data, strtab_off + st_name);
                 if !name.is_empty() {
                     symbols.insert(st_value, name);
+                    sizes.insert(st_value, st_size);
                 }
             }
         }
@@ -184,10 +318,93 @@ pub fn parse_elf(data: &[u8]) -> Result<ElfFile, String> {
     let sym_addrs: Vec<u32> = symbols.keys().copied().collect();
     let line_addrs: Vec<u32> = line_map.keys().copied().collect();
 
-    Ok(ElfFile { flash, symbols, sym_addrs, line_map, line_addrs, entry })
+    Ok(ElfFile {
+        flash, symbols, sizes, sections, sym_addrs, line_map, line_addrs, entry,
+        comments: BTreeMap::new(), layout, flash_base: if flash_base == u32::MAX { 0 } else { flash_base },
+        fuses, lock,
+    })
+}
+
+/// Copy `bytes` to flash byte address `addr`, growing the image (erased
+/// 0xFF) as needed. Addresses in the data, EEPROM and fuse spaces are ignored.
+fn load_flash(flash: &mut Vec<u8>, base: &mut u32, addr: u32, bytes: &[u8]) {
+    if addr >= DATA_OFFSET || bytes.is_empty() { return; }
+    let start = addr as usize;
+    let end = start + bytes.len().min(DATA_OFFSET as usize - start);
+    if end > flash.len() { flash.resize(end, 0xFF); }
+    flash[start..end].copy_from_slice(&bytes[..end - start]);
+    *base = (*base).min(addr);
 }
 
 impl ElfFile {
+    /// Rebuild the lookup tables after editing `symbols` or `line_map`.
+    pub fn reindex(&mut self) {
+        self.sym_addrs = self.symbols.keys().copied().collect();
+        self.line_addrs = self.line_map.keys().copied().collect();
+    }
+
+    /// Addresses of the symbols called `name`: an exact (mangled) name, or a
+    /// C++ name such as `Arduboy2Base::sBuffer` or `drawPixel` matched
+    /// against Itanium-mangled names, every overload included.
+    pub fn find_symbols(&self, name: &str) -> Vec<u32> {
+        let exact: Vec<u32> = self.symbols.iter()
+            .filter(|(_, n)| n.as_str() == name)
+            .map(|(&a, _)| a)
+            .collect();
+        if !exact.is_empty() {
+            return exact;
+        }
+        let parts: String = name.split("::").map(|p| format!("{}{}", p.len(), p)).collect();
+        let qualified = name.contains("::");
+        self.symbols.iter()
+            .filter(|(_, n)| match n.strip_prefix("_ZN").map(|m| m.strip_prefix('K').unwrap_or(m)) {
+                // Nested name: the full path, or its last component
+                Some(m) if qualified => m.starts_with(&format!("{}E", parts)),
+                Some(m) => m.contains(&format!("{}E", parts)),
+                None => !qualified && n.strip_prefix("_Z").is_some_and(|m| m.starts_with(&parts)),
+            })
+            .map(|(&a, _)| a)
+            .collect()
+    }
+
+    /// Byte range `[start, end)` of the symbol at `addr`: its ELF size, or up
+    /// to the next symbol when the size is not recorded.
+    pub fn symbol_range(&self, addr: u32) -> (u32, u32) {
+        match self.sizes.get(&addr) {
+            Some(&size) if size > 0 => (addr, addr + size),
+            _ => {
+                // Flash symbols end before data-space ones (0x800000)
+                let next = self.symbols.range(addr + 1..).next().map(|(&a, _)| a)
+                    .filter(|&a| (a >= DATA_OFFSET) == (addr >= DATA_OFFSET));
+                (addr, next.unwrap_or(addr + 2))
+            }
+        }
+    }
+
+    /// The `.noinit` section as a data-space range `[start, end)`: RAM the
+    /// C runtime leaves untouched across resets.
+    pub fn noinit(&self) -> Option<(u16, u16)> {
+        let s = self.layout.iter().find(|s| s.name == ".noinit" && s.region == Region::Ram)?;
+        let start = (s.addr - DATA_OFFSET) as u16;
+        Some((start, start.saturating_add(s.size.min(0xFFFF) as u16)))
+    }
+
+    /// Multi-line section table: name, region, run address, load address, size.
+    pub fn layout_report(&self) -> String {
+        let mut out = String::new();
+        for s in self.layout.iter().filter(|s| s.region != Region::Other) {
+            out.push_str(&format!("{:<16} {:<9} {:06X} {:06X} {:6}{}\n",
+                s.name, s.region.name(), s.addr, s.lma, s.size, if s.nobits { " nobits" } else { "" }));
+        }
+        if let Some(f) = self.fuses {
+            out.push_str(&format!("fuses: {}\n", f));
+        }
+        if let Some(l) = self.lock {
+            out.push_str(&format!("lock: {:02X}\n", l));
+        }
+        out
+    }
+
     /// Find function name containing byte address (nearest symbol at or below).
     pub fn find_function(&self, byte_addr: u32) -> Option<(&str, u32)> {
         let idx = self.sym_addrs.partition_point(|&a| a <= byte_addr);
@@ -207,8 +424,8 @@ impl ElfFile {
     }
 
     /// Format symbol + source for a given PC word address.
-    pub fn describe_pc(&self, pc_word: u16) -> String {
-        let addr = (pc_word as u32) * 2;
+    pub fn describe_pc(&self, pc_word: u32) -> String {
+        let addr = pc_word * 2;
         let mut parts = Vec::new();
         if let Some((name, offset)) = self.find_function(addr) {
             if offset == 0 {
@@ -222,6 +439,9 @@ impl ElfFile {
                 .rsplit('\\').next().unwrap_or(file);
             parts.push(format!("{}:{}", short, line));
         }
+        if let Some(comment) = self.comments.get(&addr) {
+            parts.push(format!("; {}", comment));
+        }
         if parts.is_empty() { return String::new(); }
         parts.join(" ")
     }
@@ -245,12 +465,12 @@ fn parse_debug_line(section: &[u8]) -> BTreeMap<u32, (String, u32)> {
         let prog_start = pos + header_length;
         if prog_start > unit_end { pos = unit_end; continue; }
 
-        let min_inst_len = section[pos] as u32; pos += 1;
+        let min_inst_len = byte(section, pos) as u32; pos += 1;
         if version >= 4 { pos += 1; } // max_ops_per_instruction
-        let _default_is_stmt = section[pos]; pos += 1;
-        let line_base = section[pos] as i8; pos += 1;
-        let line_range = section[pos] as u32; pos += 1;
-        let opcode_base = section[pos]; pos += 1;
+        let _default_is_stmt = byte(section, pos); pos += 1;
+        let line_base = byte(section, pos) as i8; pos += 1;
+        let line_range = byte(section, pos) as u32; pos += 1;
+        let opcode_base = byte(section, pos); pos += 1;
 
         // Standard opcode lengths
         let mut std_lens = vec![0u8; opcode_base as usize];
@@ -293,14 +513,14 @@ fn parse_debug_line(section: &[u8]) -> BTreeMap<u32, (String, u32)> {
         let mut end_sequence = false;
 
         while pos < unit_end {
-            let op = section[pos]; pos += 1;
+            let op = byte(section, pos); pos += 1;
 
             if op == 0 {
                 // Extended opcode
                 let ext_len = read_uleb128(section, &mut pos) as usize;
-                let ext_end = pos + ext_len;
+                let ext_end = pos.saturating_add(ext_len);
                 if pos >= unit_end { break; }
-                let ext_op = section[pos]; pos += 1;
+                let ext_op = byte(section, pos); pos += 1;
 
                 match ext_op {
                     1 => { // end_sequence
@@ -327,17 +547,17 @@ fn parse_debug_line(section: &[u8]) -> BTreeMap<u32, (String, u32)> {
                         let f = files.get(file as usize).cloned().unwrap_or_default();
                         result.insert(address, (f, line));
                     }
-                    2 => { address += read_uleb128(section, &mut pos) * min_inst_len; }
-                    3 => { line = (line as i32 + read_sleb128(section, &mut pos)) as u32; }
+                    2 => { address = address.wrapping_add(read_uleb128(section, &mut pos).wrapping_mul(min_inst_len)); }
+                    3 => { line = (line as i32).wrapping_add(read_sleb128(section, &mut pos)) as u32; }
                     4 => { file = read_uleb128(section, &mut pos); }
                     5 => { let _ = read_uleb128(section, &mut pos); } // set_column
                     6 | 7 => {} // negate_stmt, set_basic_block
                     8 => { // const_add_pc
                         let adj = ((255 - opcode_base) as u32 / line_range.max(1)) * min_inst_len;
-                        address += adj;
+                        address = address.wrapping_add(adj);
                     }
                     9 => { // fixed_advance_pc
-                        if pos + 1 < section.len() { address += u16le(section, pos) as u32; }
+                        address = address.wrapping_add(u16le(section, pos) as u32);
                         pos += 2;
                     }
                     10 => {} // set_prologue_end
@@ -354,8 +574,8 @@ fn parse_debug_line(section: &[u8]) -> BTreeMap<u32, (String, u32)> {
                 let adjusted = (op - opcode_base) as u32;
                 let addr_inc = (adjusted / line_range.max(1)) * min_inst_len;
                 let line_inc = line_base as i32 + (adjusted % line_range.max(1)) as i32;
-                address += addr_inc;
-                line = (line as i32 + line_inc) as u32;
+                address = address.wrapping_add(addr_inc);
+                line = (line as i32).wrapping_add(line_inc) as u32;
                 let f = files.get(file as usize).cloned().unwrap_or_default();
                 result.insert(address, (f, line));
             }
@@ -391,11 +611,91 @@ mod tests {
         assert!(parse_elf(&[0x7F, b'E', b'L', b'F']).is_err());
     }
 
+    #[test]
+    fn test_truncated_headers() {
+        // Valid ident and AVR machine, section/program header tables past the end
+        let mut d = vec![0u8; 52];
+        d[..4].copy_from_slice(b"\x7FELF");
+        d[4] = 1; d[5] = 1; d[18] = 83;
+        d[28] = 0xF0; d[32] = 0xF0; d[42] = 32; d[44] = 4; d[46] = 40; d[48] = 8;
+        for n in 0..d.len() {
+            let _ = parse_elf(&d[..n]);
+        }
+        let _ = parse_elf(&d);
+    }
+
+    /// ELF with `.text` (4 bytes at `text_at`), `.data` (run 0x800100,
+    /// stored after `.text`), `.noinit` and `.fuse` sections.
+    fn build_elf(text_at: u32) -> Vec<u8> {
+        let put16 = |d: &mut Vec<u8>, o: usize, v: u16| d[o..o + 2].copy_from_slice(&v.to_le_bytes());
+        let put32 = |d: &mut Vec<u8>, o: usize, v: u32| d[o..o + 4].copy_from_slice(&v.to_le_bytes());
+        let strtab = b"\0.text\0.data\0.noinit\0.fuse\0.shstrtab\0";
+        // header 52, 3 phdrs at 52, contents at 148, shdrs at 200
+        let mut d = vec![0u8; 200 + 6 * 40];
+        d[..4].copy_from_slice(&ELF_MAGIC);
+        d[4] = 1; d[5] = 1;
+        put16(&mut d, 18, EM_AVR);
+        put32(&mut d, 24, text_at);
+        put32(&mut d, 28, 52);
+        put32(&mut d, 32, 200);
+        put16(&mut d, 42, 32); put16(&mut d, 44, 3);
+        put16(&mut d, 46, 40); put16(&mut d, 48, 6); put16(&mut d, 50, 5);
+        d[148..152].copy_from_slice(&[0x0C, 0x94, 0x34, 0x12]);
+        d[152..154].copy_from_slice(&[0xAA, 0xBB]);
+        d[154..157].copy_from_slice(&[0xFF, 0xD0, 0xCB]);
+        d[160..160 + strtab.len()].copy_from_slice(strtab);
+        // (offset, vaddr, paddr, filesz, memsz)
+        for (i, &(o, v, p, f, m)) in [(148, text_at, text_at, 4, 4), (152, 0x800100, text_at + 4, 2, 2),
+                                      (154, 0x820000, 0x820000, 3, 3)].iter().enumerate() {
+            let h = 52 + i * 32;
+            put32(&mut d, h, PT_LOAD);
+            put32(&mut d, h + 4, o); put32(&mut d, h + 8, v); put32(&mut d, h + 12, p);
+            put32(&mut d, h + 16, f); put32(&mut d, h + 20, m);
+        }
+        // (name, type, flags, addr, offset, size)
+        for (i, &(n, t, f, a, o, sz)) in [(1, 1, 6, text_at, 148, 4), (7, 1, 3, 0x800100, 152, 2),
+                                         (13, SHT_NOBITS, 3, 0x800102, 0, 8), (21, 1, 2, 0x820000, 154, 3),
+                                         (27, 3, 0, 0, 160, strtab.len() as u32)].iter().enumerate() {
+            let h = 200 + (i + 1) * 40;
+            put32(&mut d, h, n); put32(&mut d, h + 4, t); put32(&mut d, h + 8, f);
+            put32(&mut d, h + 12, a); put32(&mut d, h + 16, o); put32(&mut d, h + 20, sz);
+        }
+        d
+    }
+
+    #[test]
+    fn test_layout_and_fuses() {
+        let elf = parse_elf(&build_elf(0)).unwrap();
+        // .data initializers stored at their load address after .text
+        assert_eq!(&elf.flash[..6], &[0x0C, 0x94, 0x34, 0x12, 0xAA, 0xBB]);
+        assert_eq!(elf.flash_base, 0);
+        let data = elf.layout.iter().find(|s| s.name == ".data").unwrap();
+        assert_eq!((data.addr, data.lma, data.region), (0x800100, 4, Region::Ram));
+        assert_eq!(elf.noinit(), Some((0x102, 0x10A)));
+        let fuses = elf.fuses.unwrap();
+        assert_eq!(fuses, Fuses { low: 0xFF, high: 0xD0, extended: Some(0xCB) });
+        assert!(!fuses.ckdiv8());
+        assert!(fuses.bootrst());
+        assert_eq!(fuses.boot_size_words(crate::CpuType::Atmega32u4), 2048);
+        assert_eq!(elf.lock, None);
+    }
+
+    #[test]
+    fn test_bootloader_offset() {
+        let elf = parse_elf(&build_elf(0x7000)).unwrap();
+        assert_eq!(elf.flash_base, 0x7000);
+        assert_eq!(&elf.flash[0x7000..0x7006], &[0x0C, 0x94, 0x34, 0x12, 0xAA, 0xBB]);
+        let mut a = crate::Arduboy::new();
+        a.load_elf(&build_elf(0x7000)).unwrap();
+        assert_eq!(a.cpu.pc, 0x3800);
+    }
+
     #[test]
     fn test_find_function() {
         let mut elf = ElfFile {
-            flash: vec![], symbols: BTreeMap::new(), sym_addrs: vec![],
-            line_map: BTreeMap::new(), line_addrs: vec![], entry: 0,
+            flash: vec![], symbols: BTreeMap::new(), sizes: BTreeMap::new(), sections: vec![], sym_addrs: vec![],
+            line_map: BTreeMap::new(), line_addrs: vec![], entry: 0, comments: BTreeMap::new(),
+            ..Default::default()
         };
         elf.symbols.insert(0x100, "main".into());
         elf.symbols.insert(0x200, "loop".into());
@@ -405,4 +705,26 @@ mod tests {
         assert_eq!(elf.find_function(0x200), Some(("loop", 0)));
         assert_eq!(elf.find_function(0x050), None);
     }
+
+    #[test]
+    fn test_find_symbols() {
+        let mut elf = ElfFile {
+            flash: vec![], symbols: BTreeMap::new(), sizes: BTreeMap::new(), sections: vec![], sym_addrs: vec![],
+            line_map: BTreeMap::new(), line_addrs: vec![], entry: 0, comments: BTreeMap::new(),
+            ..Default::default()
+        };
+        elf.symbols.insert(0x100, "_ZN12Arduboy2Base9drawPixelEiih".into());
+        elf.symbols.insert(0x180, "_Z7drawMapv".into());
+        elf.symbols.insert(0x200, "loop".into());
+        elf.symbols.insert(0x800100, "_ZN12Arduboy2Base7sBufferE".into());
+        elf.sizes.insert(0x800100, 1024);
+        assert_eq!(elf.find_symbols("Arduboy2Base::sBuffer"), vec![0x800100]);
+        assert_eq!(elf.find_symbols("drawPixel"), vec![0x100]);
+        assert_eq!(elf.find_symbols("drawMap"), vec![0x180]);
+        assert_eq!(elf.find_symbols("loop"), vec![0x200]);
+        assert!(elf.find_symbols("Arduboy2::drawPixel").is_empty());
+        assert_eq!(elf.symbol_range(0x800100), (0x800100, 0x800500));
+        assert_eq!(elf.symbol_range(0x100), (0x100, 0x180));
+        assert_eq!(elf.symbol_range(0x200), (0x200, 0x202));
+    }
 }
@@ -0,0 +1,311 @@
+//! Errors from loading programs, packages and save states.
+//!
+//! | Variant | Returned by | Location |
+//! |---------|-------------|----------|
+//! | [`Error::Hex`] | [`parse_hex`](crate::hex::parse_hex), [`Arduboy::load_hex`](crate::Arduboy::load_hex) | 1-based line |
+//! | [`Error::Archive`] | [`parse_arduboy`](crate::arduboy_file::parse_arduboy) | |
+//! | [`Error::Elf`] | [`parse_elf`](crate::elf::parse_elf), [`Arduboy::load_elf`](crate::Arduboy::load_elf) | byte offset |
+//! | [`Error::SaveState`] | [`savestate`](crate::savestate) file functions | |
+//! | [`Error::RomPatch`] | [`romhack::apply`](crate::romhack::apply), [`Arduboy::add_rom_patch`](crate::Arduboy::add_rom_patch) | |
+//! | [`Error::Plugin`] | [`Plugin::load`](crate::plugin::Plugin::load) | |
+//! | [`Error::Build`] | [`ArduboyBuilder::build`](crate::builder::ArduboyBuilder::build) | |
+//! | [`Error::Symbols`] | [`Annotations::parse`](crate::annotations::Annotations::parse), [`Annotations::load`](crate::annotations::Annotations::load) | 1-based line |
+//! | [`Error::Io`] | file reads and writes | |
+//!
+//! The GDB server reports socket errors as [`std::io::Error`]. Parsers of
+//! text settings (board profiles, flash patches, fuses, TOML configs,
+//! [`parse_clock_hz`](crate::parse_clock_hz)) still return `String`
+//! messages; [`BuildError`] carries them when they reach the builder. `Display` gives the same one-line descriptions the
+//! frontends print, and `From<Error> for String` lets functions that return
+//! `String` errors use `?` on calls that return [`Error`].
+
+use std::fmt;
+
+use crate::CpuType;
+
+/// Crate-wide error.
+#[derive(Debug)]
+pub enum Error {
+    /// Malformed Intel HEX record
+    Hex { line: usize, kind: HexError },
+    /// Unreadable `.arduboy` package
+    Archive(ArchiveError),
+    /// Unsupported or malformed ELF file
+    Elf { offset: usize, kind: ElfError },
+    /// Save state file that cannot be restored
+    SaveState(SaveStateError),
+    /// IPS/BPS patch that does not fit the loaded program
+    RomPatch(RomPatchError),
+    /// Native plugin that cannot be loaded
+    Plugin(PluginError),
+    /// Builder settings that do not fit together
+    Build(BuildError),
+    /// Malformed line in a user symbol file
+    Symbols { line: usize, detail: String },
+    /// File read or write failure
+    Io { op: &'static str, source: std::io::Error },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    /// Fewer than 5 bytes (count, address, type, checksum)
+    TooShort,
+    OddLength,
+    BadChar(char),
+    /// Bytes do not sum to zero
+    Checksum { sum: u8 },
+    /// Byte count field disagrees with the data on the line
+    Length { count: u8, found: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// No ZIP end-of-central-directory record
+    NotZip,
+    /// End-of-central-directory record cut short
+    Truncated,
+    Inflate { name: String, detail: String },
+    Empty,
+    /// No `.hex` member
+    NoHex,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ElfError {
+    /// Shorter than the ELF header
+    TooSmall,
+    NotElf,
+    Not32Bit,
+    NotLittleEndian,
+    /// `e_machine` is not AVR (83)
+    NotAvr { machine: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SaveStateError {
+    TooSmall,
+    BadMagic,
+    Version { found: u32, expected: u32 },
+    /// Saved on the other CPU (0 = ATmega32u4, 1 = ATmega328P)
+    CpuMismatch { saved: u8, current: u8 },
+    Encode(String),
+    Decompress(String),
+    Decode(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomPatchError {
+    /// Neither `PATCH` (IPS) nor `BPS1` magic
+    UnknownFormat,
+    /// Patch ends inside a record
+    Truncated,
+    /// CRC-32 of the patch file itself
+    PatchChecksum,
+    /// BPS patch made for a program of another size
+    SourceSize { expected: usize, found: usize },
+    /// BPS patch made for another program (or build)
+    SourceChecksum { expected: u32, found: u32 },
+    TargetChecksum,
+    /// A copy reaches outside the source or target
+    BadOffset,
+    /// Patched program larger than flash
+    TooLarge { size: usize, flash: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginError {
+    /// The dynamic library could not be opened
+    Open(String),
+    /// No `arduboy_plugin_init` export
+    NoEntry,
+    /// The plugin returned null for this ABI version
+    Rejected { abi: u32 },
+    /// The plugin was built for another ABI version
+    Version { found: u32, expected: u32 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// Board profile that could not be loaded
+    Board(String),
+    /// CPU set explicitly differs from the board's
+    CpuMismatch { board: String, board_cpu: CpuType, cpu: CpuType },
+    /// FX data for a board without FX flash
+    NoFx { board: String },
+    /// Display quirks or panel size for a PCD8544
+    DisplayOptions,
+    EepromSize { size: usize, max: usize },
+    /// Clock outside 1–64 MHz
+    Clock { hz: u32 },
+    ZeroSeed,
+    /// Breakpoint past the end of flash (byte address)
+    Breakpoint { addr: u32 },
+    /// Bad log level spec
+    Log(String),
+    Tracepoint { spec: String, detail: String },
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HexError::TooShort => write!(f, "Line too short"),
+            HexError::OddLength => write!(f, "Odd number of hex characters"),
+            HexError::BadChar(c) => write!(f, "Invalid hex character: {}", c),
+            HexError::Checksum { sum } => write!(f, "Checksum error: sum={}", sum),
+            HexError::Length { count, found } => write!(f, "Byte count {} but {} data bytes", count, found),
+        }
+    }
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArchiveError::NotZip => write!(f, "No End of Central Directory found — not a valid ZIP"),
+            ArchiveError::Truncated => write!(f, "EOCD truncated"),
+            ArchiveError::Inflate { name, detail } => write!(f, "Inflate error for {}: {}", name, detail),
+            ArchiveError::Empty => write!(f, "No files found in ZIP archive"),
+            ArchiveError::NoHex => write!(f, "No .hex file found in .arduboy archive"),
+        }
+    }
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::TooSmall => write!(f, "File too small for ELF header"),
+            ElfError::NotElf => write!(f, "Not an ELF file"),
+            ElfError::Not32Bit => write!(f, "Only 32-bit ELF supported"),
+            ElfError::NotLittleEndian => write!(f, "Only little-endian ELF supported"),
+            ElfError::NotAvr { machine } => write!(f, "Not AVR ELF (machine={})", machine),
+        }
+    }
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cpu = |t: &u8| ["ATmega32u4", "ATmega328P"].get(*t as usize).copied().unwrap_or("?");
+        match self {
+            SaveStateError::TooSmall => write!(f, "File too small"),
+            SaveStateError::BadMagic => write!(f, "Invalid save state file (bad magic)"),
+            SaveStateError::Version { found, expected } =>
+                write!(f, "Unsupported save state version {} (expected {})", found, expected),
+            SaveStateError::CpuMismatch { saved, current } =>
+                write!(f, "CPU type mismatch: save={} current={}", cpu(saved), cpu(current)),
+            SaveStateError::Encode(e) => write!(f, "Serialize error: {}", e),
+            SaveStateError::Decompress(e) => write!(f, "Decompress error: {}", e),
+            SaveStateError::Decode(e) => write!(f, "Deserialize error: {}", e),
+        }
+    }
+}
+
+impl fmt::Display for RomPatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomPatchError::UnknownFormat => write!(f, "Not an IPS or BPS patch"),
+            RomPatchError::Truncated => write!(f, "Patch file truncated"),
+            RomPatchError::PatchChecksum => write!(f, "Patch file checksum error (corrupt download?)"),
+            RomPatchError::SourceSize { expected, found } =>
+                write!(f, "Patch is for a {}-byte program, loaded program is {} bytes", expected, found),
+            RomPatchError::SourceChecksum { expected, found } =>
+                write!(f, "Patch is for another program (CRC32 {:08X}, loaded {:08X})", expected, found),
+            RomPatchError::TargetChecksum => write!(f, "Patched program checksum error"),
+            RomPatchError::BadOffset => write!(f, "Patch copies from outside the program"),
+            RomPatchError::TooLarge { size, flash } =>
+                write!(f, "Patched program is {} bytes, flash holds {}", size, flash),
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::Board(e) | BuildError::Log(e) => write!(f, "{}", e),
+            BuildError::CpuMismatch { board, board_cpu, cpu } =>
+                write!(f, "board '{}' has CPU {:?}, not {:?}", board, board_cpu, cpu),
+            BuildError::NoFx { board } => write!(f, "board '{}' has no FX flash", board),
+            BuildError::DisplayOptions =>
+                write!(f, "display controller and panel size only apply to SSD1306-family displays"),
+            BuildError::EepromSize { size, max } => write!(f, "EEPROM image is {} bytes (max {})", size, max),
+            BuildError::Clock { hz } => write!(f, "clock {} Hz is outside 1–64 MHz", hz),
+            BuildError::ZeroSeed => write!(f, "seed must be non-zero"),
+            BuildError::Breakpoint { addr } => write!(f, "breakpoint 0x{:04X} is outside flash", addr),
+            BuildError::Tracepoint { spec, detail } => write!(f, "tracepoint '{}': {}", spec, detail),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Hex { line, kind } => write!(f, "line {}: {}", line, kind),
+            Error::Archive(e) => e.fmt(f),
+            Error::Elf { offset, kind } => write!(f, "{} (at byte {})", kind, offset),
+            Error::SaveState(e) => e.fmt(f),
+            Error::RomPatch(e) => e.fmt(f),
+            Error::Plugin(e) => e.fmt(f),
+            Error::Build(e) => e.fmt(f),
+            Error::Symbols { line, detail } => write!(f, "line {}: {}", line, detail),
+            Error::Io { op, source } => write!(f, "{} error: {}", op, source),
+        }
+    }
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluginError::Open(e) => write!(f, "Cannot load plugin: {}", e),
+            PluginError::NoEntry => write!(f, "Not an arduboy-emu plugin (no arduboy_plugin_init)"),
+            PluginError::Rejected { abi } => write!(f, "Plugin does not support plugin ABI {}", abi),
+            PluginError::Version { found, expected } =>
+                write!(f, "Plugin built for plugin ABI {} (expected {})", found, expected),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ArchiveError> for Error {
+    fn from(e: ArchiveError) -> Self { Error::Archive(e) }
+}
+
+impl From<SaveStateError> for Error {
+    fn from(e: SaveStateError) -> Self { Error::SaveState(e) }
+}
+
+impl From<RomPatchError> for Error {
+    fn from(e: RomPatchError) -> Self { Error::RomPatch(e) }
+}
+
+impl From<PluginError> for Error {
+    fn from(e: PluginError) -> Self { Error::Plugin(e) }
+}
+
+impl From<BuildError> for Error {
+    fn from(e: BuildError) -> Self { Error::Build(e) }
+}
+
+impl From<Error> for String {
+    fn from(e: Error) -> Self { e.to_string() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let e = Error::Hex { line: 3, kind: HexError::Checksum { sum: 0xD0 } };
+        assert_eq!(e.to_string(), "line 3: Checksum error: sum=208");
+        let e = Error::Elf { offset: 18, kind: ElfError::NotAvr { machine: 40 } };
+        assert_eq!(e.to_string(), "Not AVR ELF (machine=40) (at byte 18)");
+        let e: Error = SaveStateError::CpuMismatch { saved: 1, current: 0 }.into();
+        assert_eq!(String::from(e), "CPU type mismatch: save=ATmega328P current=ATmega32u4");
+    }
+}
@@ -0,0 +1,321 @@
+//! Machine-readable profiler and debugger data.
+//!
+//! The profiler report, watchpoint list and RAM dump are formatted for a
+//! terminal. The structs here carry the same data for external dashboards
+//! and editor plugins; each serializes with serde and has a `to_json()`:
+//!
+//! | Struct | Built with | Contents |
+//! |--------|-----------|----------|
+//! | [`ProfileData`] | [`Arduboy::profile_data`](crate::Arduboy::profile_data) | Totals, PC histogram, call graph, hot regions |
+//! | [`DebuggerData`] | [`DebuggerData::new`] | Watchpoints, write-protect regions, pending hit |
+//! | [`PerfStats`] | [`PerfStats::new`] | Mean host time per frame and phase |
+//! | [`RamDump`] | [`Arduboy::ram_dump`](crate::Arduboy::ram_dump) | A data-space range |
+//!
+//! The field names are a stable interface: every top-level struct carries
+//! [`SCHEMA_VERSION`], which changes only when a field is renamed or
+//! removed. Flash addresses are byte addresses, as in the text reports;
+//! data-space addresses are as the CPU sees them.
+
+use crate::debugger::{Debugger, WatchHit, WatchKind};
+use crate::elf::ElfFile;
+use crate::perf_trace::{PerfTrace, PHASES};
+use crate::profiler::Profiler;
+use serde::Serialize;
+
+/// Version of the JSON layout
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// PC histogram entries and call edges included (hottest first)
+pub const TOP_ENTRIES: usize = 256;
+
+fn json<T: Serialize>(value: &T) -> String {
+    // Plain structs of numbers and strings cannot fail to serialize
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+/// `name` or `name+offset` of the function containing a flash byte address.
+fn symbol(elf: Option<&ElfFile>, addr: u32) -> Option<String> {
+    elf.and_then(|e| e.find_function(addr)).map(|(name, off)| match off {
+        0 => name.to_string(),
+        _ => format!("{}+{}", name, off),
+    })
+}
+
+/// Executions of one instruction.
+#[derive(Debug, Clone, Serialize)]
+pub struct PcHits {
+    pub addr: u32,
+    pub hits: u64,
+    pub instruction: String,
+    pub symbol: Option<String>,
+}
+
+/// Calls from one call site to one target.
+#[derive(Debug, Clone, Serialize)]
+pub struct CallEdge {
+    pub caller: u32,
+    pub callee: u32,
+    pub count: u64,
+    pub caller_symbol: Option<String>,
+    pub callee_symbol: Option<String>,
+}
+
+/// Contiguous executed code and its total hits.
+#[derive(Debug, Clone, Serialize)]
+pub struct HotRegion {
+    pub start: u32,
+    pub end: u32,
+    pub hits: u64,
+}
+
+/// Profiler results.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileData {
+    pub version: u32,
+    pub running: bool,
+    pub instructions: u64,
+    pub cycles: u64,
+    pub unique_addresses: usize,
+    /// Hottest [`TOP_ENTRIES`] instructions
+    pub histogram: Vec<PcHits>,
+    /// Most used [`TOP_ENTRIES`] call edges
+    pub call_graph: Vec<CallEdge>,
+    pub hot_regions: Vec<HotRegion>,
+}
+
+impl ProfileData {
+    /// Collect from the profiler; `flash` is disassembled for the
+    /// histogram and `elf` (optional) names the functions.
+    pub fn new(p: &Profiler, flash: &[u8], elf: Option<&ElfFile>) -> Self {
+        let word = |i: usize| flash.get(i..i + 2).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]));
+        ProfileData {
+            version: SCHEMA_VERSION,
+            running: p.enabled,
+            instructions: p.total_instructions,
+            cycles: p.total_cycles,
+            unique_addresses: p.unique_addresses(),
+            histogram: p.top_hits(TOP_ENTRIES).into_iter().map(|(pc, hits)| {
+                let addr = pc as usize * 2;
+                let (inst, _) = crate::opcodes::decode(word(addr), word(addr + 2));
+                PcHits {
+                    addr: addr as u32,
+                    hits,
+                    instruction: crate::disasm::disassemble(inst, pc),
+                    symbol: symbol(elf, addr as u32),
+                }
+            }).collect(),
+            call_graph: p.top_calls(TOP_ENTRIES).into_iter().map(|((from, to), count)| CallEdge {
+                caller: from * 2,
+                callee: to * 2,
+                count,
+                caller_symbol: symbol(elf, from * 2),
+                callee_symbol: symbol(elf, to * 2),
+            }).collect(),
+            hot_regions: p.flat_profile().into_iter().map(|(start, end, hits)| HotRegion {
+                start: start * 2,
+                end: end * 2,
+                hits,
+            }).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json(self)
+    }
+}
+
+fn kind_name(kind: WatchKind) -> &'static str {
+    match kind {
+        WatchKind::Write => "write",
+        WatchKind::Read => "read",
+        WatchKind::ReadWrite => "readwrite",
+    }
+}
+
+/// A watchpoint and its hit count.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchpointData {
+    pub index: usize,
+    pub addr: u16,
+    /// `read`, `write` or `readwrite`
+    pub kind: &'static str,
+    pub value_match: Option<u8>,
+    pub hits: u64,
+    pub enabled: bool,
+}
+
+/// A write-protect region and its hit count.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProtectRegionData {
+    pub index: usize,
+    pub spec: String,
+    pub start: u16,
+    pub end: u16,
+    /// Flash byte ranges `[start, end)` allowed to write
+    pub allowed: Vec<(u32, u32)>,
+    pub hits: u64,
+    pub enabled: bool,
+}
+
+/// A watchpoint or write-protect trigger.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchHitData {
+    /// Index of the watchpoint, or of the region for a protect hit
+    pub index: usize,
+    pub addr: u16,
+    pub old_val: u8,
+    pub new_val: u8,
+    pub access: &'static str,
+    /// Flash byte address of the store (write-protect hits only)
+    pub protect_pc: Option<u32>,
+}
+
+impl From<&WatchHit> for WatchHitData {
+    fn from(h: &WatchHit) -> Self {
+        WatchHitData {
+            index: h.index,
+            addr: h.addr,
+            old_val: h.old_val,
+            new_val: h.new_val,
+            access: kind_name(h.access),
+            protect_pc: h.protect_pc,
+        }
+    }
+}
+
+/// Debugger watch state.
+#[derive(Debug, Clone, Serialize)]
+pub struct DebuggerData {
+    pub version: u32,
+    pub watchpoints: Vec<WatchpointData>,
+    pub protect_regions: Vec<ProtectRegionData>,
+    /// Hit not yet taken by the frontend
+    pub hit: Option<WatchHitData>,
+}
+
+impl DebuggerData {
+    pub fn new(d: &Debugger) -> Self {
+        DebuggerData {
+            version: SCHEMA_VERSION,
+            watchpoints: d.watchpoints.iter().enumerate().map(|(index, w)| WatchpointData {
+                index,
+                addr: w.addr,
+                kind: kind_name(w.kind),
+                value_match: w.value_match,
+                hits: w.hits,
+                enabled: w.enabled,
+            }).collect(),
+            protect_regions: d.protect_regions.iter().enumerate().map(|(index, r)| ProtectRegionData {
+                index,
+                spec: r.spec.clone(),
+                start: r.start,
+                end: r.end,
+                allowed: r.allowed.clone(),
+                hits: r.hits,
+                enabled: r.enabled,
+            }).collect(),
+            hit: d.watch_hit.as_ref().map(WatchHitData::from),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json(self)
+    }
+}
+
+/// Mean host cost of one phase per frame.
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseStats {
+    pub name: &'static str,
+    pub mean_ns: u64,
+    pub mean_calls: f64,
+}
+
+/// Host time per frame from the [`PerfTrace`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PerfStats {
+    pub version: u32,
+    pub frames: usize,
+    pub mean_ns: u64,
+    pub max_ns: u64,
+    pub phases: Vec<PhaseStats>,
+}
+
+impl PerfStats {
+    pub fn new(t: &PerfTrace) -> Self {
+        let n = t.len().max(1) as u64;
+        PerfStats {
+            version: SCHEMA_VERSION,
+            frames: t.len(),
+            mean_ns: t.frames().map(|f| f.dur_ns).sum::<u64>() / n,
+            max_ns: t.frames().map(|f| f.dur_ns).max().unwrap_or(0),
+            phases: PHASES.iter().enumerate().map(|(i, p)| PhaseStats {
+                name: p.name(),
+                mean_ns: t.frames().map(|f| f.phases[i].0).sum::<u64>() / n,
+                mean_calls: t.frames().map(|f| f.phases[i].1 as u64).sum::<u64>() as f64 / n as f64,
+            }).collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        json(self)
+    }
+}
+
+/// A range of data space.
+#[derive(Debug, Clone, Serialize)]
+pub struct RamDump {
+    pub version: u32,
+    pub start: u16,
+    pub bytes: Vec<u8>,
+}
+
+impl RamDump {
+    /// `len` bytes from `start`, cut at the end of data space.
+    pub fn new(data: &[u8], start: u16, len: usize) -> Self {
+        let from = (start as usize).min(data.len());
+        let to = (from + len).min(data.len());
+        RamDump { version: SCHEMA_VERSION, start, bytes: data[from..to].to_vec() }
+    }
+
+    pub fn to_json(&self) -> String {
+        json(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_and_debugger_json() {
+        let mut p = Profiler::new();
+        p.start(0);
+        for _ in 0..3 {
+            p.record(0x10);
+        }
+        p.record(0x11);
+        p.record_call(0x11, 0x40);
+        p.stop(12);
+        // 0x10: NOP, 0x11: RET
+        let mut flash = vec![0u8; 0x100];
+        flash[0x22..0x24].copy_from_slice(&[0x08, 0x95]);
+        let data = ProfileData::new(&p, &flash, None);
+        assert_eq!((data.instructions, data.cycles), (4, 12));
+        assert_eq!((data.histogram[0].addr, data.histogram[0].hits), (0x20, 3));
+        assert_eq!(data.histogram[1].instruction, "RET");
+        let json = data.to_json();
+        assert!(json.contains("\"version\": 1"));
+        assert!(json.contains("\"callee\": 128"), "{}", json);
+
+        let mut d = Debugger::new();
+        d.add_watchpoint(0x0123, WatchKind::Write);
+        d.check_write(0x0123, 1, 2);
+        let json = DebuggerData::new(&d).to_json();
+        assert!(json.contains("\"kind\": \"write\""));
+        assert!(json.contains("\"new_val\": 2"), "{}", json);
+
+        let dump = RamDump::new(&[1, 2, 3, 4], 2, 10);
+        assert_eq!(dump.bytes, [3, 4]);
+    }
+}
@@ -0,0 +1,441 @@
+//! Debugger expressions and persistent watch displays.
+//!
+//! Expressions read registers, memory and ELF data symbols with C-style
+//! operators:
+//!
+//! | Syntax | Meaning |
+//! |--------|---------|
+//! | `123`, `0x7B` | Number |
+//! | `$r24`, `$x`/`$y`/`$z`, `$pc`, `$sp`, `$sreg` | Register (PC as byte address) |
+//! | `score` | Byte at a data symbol |
+//! | `score:u16`, `pos:i8` | Typed read: `u8`, `i8`, `u16`, `i16`, `u32`, `i32` (little-endian) |
+//! | `enemies:u16[3]` | Element 3 of a `u16` array |
+//! | `[0x100]`, `[$y + 2]:i16` | Memory at a data-space address |
+//! | `&score` | Address of a symbol or memory reference |
+//! | `+ - * / % & \| ^ << >> == != < > <= >=`, unary `- ~ !` | Operators |
+//!
+//! Types come from the `:type` suffix (default `u8`).
+//!
+//! [`DisplayList`] holds expressions that the debugger re-evaluates and
+//! prints after every step or frame, like GDB's `display`.
+
+use std::collections::HashMap;
+
+use crate::elf::DATA_OFFSET;
+use crate::Arduboy;
+
+/// Width and signedness of a memory read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Width { U8, I8, U16, I16, U32, I32 }
+
+impl Width {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "u8" => Width::U8, "i8" => Width::I8,
+            "u16" => Width::U16, "i16" => Width::I16,
+            "u32" => Width::U32, "i32" => Width::I32,
+            _ => return None,
+        })
+    }
+
+    fn bytes(self) -> i64 {
+        match self {
+            Width::U8 | Width::I8 => 1,
+            Width::U16 | Width::I16 => 2,
+            Width::U32 | Width::I32 => 4,
+        }
+    }
+
+    /// Truncate (and sign-extend) a value to this width.
+    fn cast(self, v: i64) -> i64 {
+        match self {
+            Width::U8 => v as u8 as i64,
+            Width::I8 => v as i8 as i64,
+            Width::U16 => v as u16 as i64,
+            Width::I16 => v as i16 as i64,
+            Width::U32 => v as u32 as i64,
+            Width::I32 => v as i32 as i64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reg { R(u8), X, Y, Z, Pc, Sp, Sreg }
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Num(i64),
+    Reg(Reg),
+    Mem(Box<Node>, Width),
+    AddrOf(Box<Node>),
+    Cast(Box<Node>, Width),
+    Unary(char, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+/// Data symbol names → data-space addresses, from an ELF symbol table.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols(HashMap<String, u16>);
+
+impl Symbols {
+    /// Keep the symbols that live in data space (0x800000+).
+    pub fn from_elf(symbols: &std::collections::BTreeMap<u32, String>) -> Self {
+        Symbols(symbols.iter()
+            .filter(|(&addr, _)| addr >= DATA_OFFSET)
+            .map(|(&addr, name)| (name.clone(), (addr - DATA_OFFSET) as u16))
+            .collect())
+    }
+
+    pub fn insert(&mut self, name: &str, addr: u16) {
+        self.0.insert(name.to_string(), addr);
+    }
+}
+
+/// A parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr(Node);
+
+/// Binary operators by precedence, loosest first
+const LEVELS: &[&[&str]] = &[
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    symbols: &'a Symbols,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_ws(&mut self) {
+        self.pos += self.rest().len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, tok: &str) -> bool {
+        self.skip_ws();
+        if self.rest().starts_with(tok) {
+            self.pos += tok.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        self.skip_ws();
+        let len = self.rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        if len == 0 || self.rest().starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let (src, start) = (self.src, self.pos);
+        self.pos += len;
+        Some(&src[start..self.pos])
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        'outer: loop {
+            for &op in LEVELS[level] {
+                // `<` must not match the first half of `<<`
+                let longer = LEVELS.iter().flat_map(|l| l.iter())
+                    .any(|o| o.len() > op.len() && o.starts_with(op) && self.rest().trim_start().starts_with(o));
+                if !longer && self.eat(op) {
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        for op in ['-', '~', '!'] {
+            if self.eat(&op.to_string()) {
+                return Ok(Node::Unary(op, Box::new(self.unary()?)));
+            }
+        }
+        if self.eat("&") {
+            return match self.unary()? {
+                Node::Mem(addr, _) => Ok(Node::AddrOf(addr)),
+                _ => Err("& needs a symbol or memory reference".into()),
+            };
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Node, String> {
+        let mut node = self.primary()?;
+        loop {
+            if self.eat(":") {
+                let ty = self.ident().unwrap_or("");
+                let w = Width::parse(ty).ok_or_else(|| format!("unknown type '{}' (u8, i8, u16, i16, u32, i32)", ty))?;
+                node = match node {
+                    Node::Mem(addr, _) => Node::Mem(addr, w),
+                    other => Node::Cast(Box::new(other), w),
+                };
+            } else if self.eat("[") {
+                let index = self.binary(0)?;
+                if !self.eat("]") {
+                    return Err("missing ]".into());
+                }
+                node = match node {
+                    Node::Mem(addr, w) => {
+                        let offset = Node::Binary("*", Box::new(index), Box::new(Node::Num(w.bytes())));
+                        Node::Mem(Box::new(Node::Binary("+", addr, Box::new(offset))), w)
+                    }
+                    _ => return Err("only symbols and memory references can be indexed".into()),
+                };
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        self.skip_ws();
+        if self.eat("(") {
+            let e = self.binary(0)?;
+            return if self.eat(")") { Ok(e) } else { Err("missing )".into()) };
+        }
+        if self.eat("[") {
+            let addr = self.binary(0)?;
+            return if self.eat("]") { Ok(Node::Mem(Box::new(addr), Width::U8)) } else { Err("missing ]".into()) };
+        }
+        if self.eat("$") {
+            let name = self.ident().unwrap_or("").to_ascii_lowercase();
+            let reg = match name.as_str() {
+                "x" => Reg::X, "y" => Reg::Y, "z" => Reg::Z,
+                "pc" => Reg::Pc, "sp" => Reg::Sp, "sreg" => Reg::Sreg,
+                r => match r.strip_prefix('r').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if n < 32 => Reg::R(n),
+                    _ => return Err(format!("unknown register ${}", name)),
+                },
+            };
+            return Ok(Node::Reg(reg));
+        }
+        if self.rest().starts_with(|c: char| c.is_ascii_digit()) {
+            let len = self.rest().find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(self.rest().len());
+            let tok = &self.rest()[..len];
+            let v = match tok.strip_prefix("0x").or_else(|| tok.strip_prefix("0X")) {
+                Some(h) => i64::from_str_radix(h, 16),
+                None => tok.parse(),
+            }.map_err(|_| format!("bad number '{}'", tok))?;
+            self.pos += len;
+            return Ok(Node::Num(v));
+        }
+        match self.ident() {
+            Some(name) => match self.symbols.0.get(name) {
+                Some(&addr) => Ok(Node::Mem(Box::new(Node::Num(addr as i64)), Width::U8)),
+                None => Err(format!("unknown symbol '{}'", name)),
+            },
+            None if self.rest().is_empty() => Err("unexpected end of expression".into()),
+            None => Err(format!("unexpected '{}'", self.rest())),
+        }
+    }
+}
+
+impl Expr {
+    /// Parse an expression; names are looked up in `symbols`.
+    pub fn parse(src: &str, symbols: &Symbols) -> Result<Self, String> {
+        let mut p = Parser { src, pos: 0, symbols };
+        let node = p.binary(0)?;
+        p.skip_ws();
+        if !p.rest().is_empty() {
+            return Err(format!("unexpected '{}'", p.rest()));
+        }
+        Ok(Expr(node))
+    }
+
+    /// Evaluate against the current emulator state.
+    pub fn eval(&self, arduboy: &Arduboy) -> Result<i64, String> {
+        eval(&self.0, arduboy)
+    }
+}
+
+fn eval(node: &Node, a: &Arduboy) -> Result<i64, String> {
+    let data = &a.mem.data;
+    Ok(match node {
+        Node::Num(v) => *v,
+        Node::Reg(r) => match *r {
+            Reg::R(n) => data[n as usize] as i64,
+            Reg::X => a.mem.x() as i64,
+            Reg::Y => a.mem.y() as i64,
+            Reg::Z => a.mem.z() as i64,
+            Reg::Pc => a.cpu.pc as i64 * 2,
+            Reg::Sp => a.cpu.sp as i64,
+            Reg::Sreg => a.cpu.sreg as i64,
+        },
+        Node::Mem(addr, w) => {
+            let addr = eval(addr, a)?;
+            let n = w.bytes();
+            if addr < 0 || (addr + n) as usize > data.len() {
+                return Err(format!("address 0x{:X} out of range", addr));
+            }
+            let raw = (0..n).rev().fold(0i64, |v, i| (v << 8) | data[(addr + i) as usize] as i64);
+            w.cast(raw)
+        }
+        Node::AddrOf(addr) => eval(addr, a)?,
+        Node::Cast(e, w) => w.cast(eval(e, a)?),
+        Node::Unary(op, e) => {
+            let v = eval(e, a)?;
+            match op {
+                '-' => v.wrapping_neg(),
+                '~' => !v,
+                _ => (v == 0) as i64,
+            }
+        }
+        Node::Binary(op, l, r) => {
+            let (l, r) = (eval(l, a)?, eval(r, a)?);
+            match *op {
+                "+" => l.wrapping_add(r),
+                "-" => l.wrapping_sub(r),
+                "*" => l.wrapping_mul(r),
+                "/" | "%" if r == 0 => return Err("division by zero".into()),
+                "/" => l / r,
+                "%" => l % r,
+                "&" => l & r,
+                "|" => l | r,
+                "^" => l ^ r,
+                "<<" => l.wrapping_shl(r as u32),
+                ">>" => l.wrapping_shr(r as u32),
+                "==" => (l == r) as i64,
+                "!=" => (l != r) as i64,
+                "<" => (l < r) as i64,
+                ">" => (l > r) as i64,
+                "<=" => (l <= r) as i64,
+                _ => (l >= r) as i64,
+            }
+        }
+    })
+}
+
+struct Display {
+    id: usize,
+    src: String,
+    expr: Expr,
+    last: Option<i64>,
+}
+
+/// Expressions printed after every step or frame.
+#[derive(Default)]
+pub struct DisplayList {
+    items: Vec<Display>,
+    next_id: usize,
+}
+
+impl DisplayList {
+    pub fn new() -> Self {
+        DisplayList { items: Vec::new(), next_id: 1 }
+    }
+
+    /// Parse and add an expression; returns its display number.
+    pub fn add(&mut self, src: &str, symbols: &Symbols) -> Result<usize, String> {
+        let expr = Expr::parse(src, symbols)?;
+        let id = self.next_id.max(1);
+        self.next_id = id + 1;
+        self.items.push(Display { id, src: src.trim().to_string(), expr, last: None });
+        Ok(id)
+    }
+
+    /// Remove display `id`; false if there is none.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let before = self.items.len();
+        self.items.retain(|d| d.id != id);
+        self.items.len() != before
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Evaluate every display, one line each: `1: score = 120 (0x78)`,
+    /// with `*` marking values that changed since the last call.
+    pub fn render(&mut self, arduboy: &Arduboy) -> String {
+        let mut out = Vec::with_capacity(self.items.len());
+        for d in &mut self.items {
+            match d.expr.eval(arduboy) {
+                Ok(v) => {
+                    let changed = d.last.is_some_and(|l| l != v);
+                    d.last = Some(v);
+                    out.push(format!("{}: {} = {} (0x{:X}){}", d.id, d.src, v, v, if changed { " *" } else { "" }));
+                }
+                Err(e) => out.push(format!("{}: {} = <{}>", d.id, d.src, e)),
+            }
+        }
+        out.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup() -> (Arduboy, Symbols) {
+        let mut a = Arduboy::new();
+        a.mem.data[0x100] = 0x34;
+        a.mem.data[0x101] = 0xF2;
+        a.mem.data[0x102] = 0x05;
+        a.mem.data[28] = 0x01; // Y = 0x0101
+        a.mem.data[29] = 0x01;
+        let mut syms = Symbols::default();
+        syms.insert("score", 0x100);
+        (a, syms)
+    }
+
+    fn eval_str(src: &str) -> Result<i64, String> {
+        let (a, syms) = setup();
+        Expr::parse(src, &syms)?.eval(&a)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval_str("score"), Ok(0x34));
+        assert_eq!(eval_str("score:u16"), Ok(0xF234));
+        assert_eq!(eval_str("score:i16"), Ok(0xF234u16 as i16 as i64));
+        assert_eq!(eval_str("score[2]"), Ok(5));
+        assert_eq!(eval_str("score:u16[0] + &score"), Ok(0xF234 + 0x100));
+        assert_eq!(eval_str("[$y + 1]"), Ok(5));
+        assert_eq!(eval_str("1 + 2 * 3 << 1 == 14"), Ok(1));
+        assert_eq!(eval_str("(0x10 >> 2) - -1 < 6"), Ok(1));
+        assert_eq!(eval_str("-score:i8"), Ok(-0x34));
+        assert!(eval_str("lives").unwrap_err().contains("unknown symbol"));
+        assert!(eval_str("score / 0").is_err());
+        assert!(eval_str("[0xFFFF]").is_err());
+    }
+
+    #[test]
+    fn test_display_list() {
+        let (mut a, syms) = setup();
+        let mut list = DisplayList::new();
+        assert_eq!(list.add("score", &syms), Ok(1));
+        assert_eq!(list.add("$r28:i8", &syms), Ok(2));
+        assert!(list.add("score +", &syms).is_err());
+        assert_eq!(list.render(&a), "1: score = 52 (0x34)\n2: $r28:i8 = 1 (0x1)");
+        a.mem.data[0x100] = 0x35;
+        assert_eq!(list.render(&a).lines().next(), Some("1: score = 53 (0x35) *"));
+        assert!(list.remove(1));
+        assert!(!list.remove(1));
+        assert_eq!(list.add("score", &syms), Ok(3));
+    }
+}
@@ -0,0 +1,178 @@
+//! Fast boot: detect the Arduboy2 boot logo so frontends can skip it.
+//!
+//! Arduboy2's `begin()` scrolls the ARDUBOY logo down the screen and pauses
+//! before the game starts, about three seconds after every reload. With
+//! [`FastBoot::enabled`] the logo is detected and [`FastBoot::active`]
+//! reports it, and a frontend runs those frames back to back without
+//! presenting them or playing their audio. The program runs exactly as
+//! before, so EEPROM, LEDs and timing state are the same once it ends.
+//!
+//! The logo is found, in order, by:
+//!
+//! - **Symbol**: when the loaded ELF has `bootLogo*` symbols (`bootLogo`,
+//!   `bootLogoShell`, `bootLogoCompressed`, ...), it runs from the entry of
+//!   any of them until that function returns.
+//! - **Signature**: otherwise it starts when the program reads the Arduboy2
+//!   system flags byte (EEPROM 1) with the show-logo bit set during the
+//!   first [`BOOT_WINDOW`] frames, and ends at the first frame that sleeps:
+//!   the logo busy-waits, while `nextFrame()` in the game loop idles.
+//!
+//! A logo not entered within [`BOOT_WINDOW`] frames is not looked for
+//! again until the next reset, and fast-forward stops after [`MAX_FRAMES`].
+
+use std::collections::BTreeMap;
+
+/// Frames after a reset in which the signature may start the logo
+pub const BOOT_WINDOW: u32 = 120;
+/// Longest fast-forward
+pub const MAX_FRAMES: u32 = 600;
+
+/// Show-logo bit of the system flags byte
+const FLAG_SHOW_LOGO: u8 = 1 << 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Not seen yet
+    Waiting,
+    /// Inside the logo; `sp` is the stack pointer at a `bootLogo*` entry
+    Logo { sp: Option<u16> },
+    /// Over, or never shown
+    Done,
+}
+
+/// Boot logo detector; see the module docs.
+#[derive(Debug, Clone)]
+pub struct FastBoot {
+    pub enabled: bool,
+    /// `bootLogo*` entry points (word addresses) from ELF symbols
+    entries: Vec<u32>,
+    phase: Phase,
+    /// Frames since reset
+    frame: u32,
+    /// Frames spent in the logo since reset
+    pub skipped: u32,
+}
+
+impl FastBoot {
+    pub fn new() -> Self {
+        FastBoot { enabled: false, entries: Vec::new(), phase: Phase::Waiting, frame: 0, skipped: 0 }
+    }
+
+    /// Look for the logo again after a reset.
+    pub fn restart(&mut self) {
+        self.phase = Phase::Waiting;
+        self.frame = 0;
+        self.skipped = 0;
+    }
+
+    /// Take the `bootLogo*` entry points from an ELF symbol table (byte
+    /// address → name); an empty table selects the signature.
+    pub fn set_symbols(&mut self, symbols: &BTreeMap<u32, String>) {
+        self.entries = symbols.iter()
+            .filter(|(_, name)| name.contains("bootLogo"))
+            .map(|(&addr, _)| addr / 2)
+            .collect();
+    }
+
+    /// True while the logo is running.
+    pub fn active(&self) -> bool {
+        self.enabled && matches!(self.phase, Phase::Logo { .. })
+    }
+
+    /// Whether [`step`](Self::step) needs the PC and SP of every instruction.
+    #[inline(always)]
+    pub fn watching(&self) -> bool {
+        self.enabled && match self.phase {
+            Phase::Waiting => !self.entries.is_empty(),
+            Phase::Logo { sp } => sp.is_some(),
+            Phase::Done => false,
+        }
+    }
+
+    /// Before an instruction: enter the logo at a `bootLogo*` entry point
+    /// and leave once the stack pops past that call.
+    pub fn step(&mut self, pc: u32, sp: u16) {
+        match self.phase {
+            Phase::Waiting if self.entries.contains(&pc) => self.phase = Phase::Logo { sp: Some(sp) },
+            Phase::Logo { sp: Some(entry_sp) } if sp > entry_sp => self.phase = Phase::Done,
+            _ => {}
+        }
+    }
+
+    /// The program read EEPROM byte 1 (Arduboy2 system flags).
+    pub fn system_flags_read(&mut self, flags: u8) {
+        if self.enabled && self.phase == Phase::Waiting && self.entries.is_empty()
+            && self.frame < BOOT_WINDOW && flags & FLAG_SHOW_LOGO != 0
+        {
+            self.phase = Phase::Logo { sp: None };
+        }
+    }
+
+    /// After a frame; `slept` if the CPU slept during it.
+    pub fn end_frame(&mut self, slept: bool) {
+        self.frame = self.frame.saturating_add(1);
+        if let Phase::Logo { sp } = self.phase {
+            self.skipped += 1;
+            if (sp.is_none() && slept) || self.skipped >= MAX_FRAMES {
+                self.phase = Phase::Done;
+            }
+        } else if self.phase == Phase::Waiting && self.frame >= BOOT_WINDOW {
+            self.phase = Phase::Done;
+        }
+    }
+}
+
+impl Default for FastBoot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        let mut fb = FastBoot::new();
+        fb.enabled = true;
+        fb.system_flags_read(0x01);
+        assert!(!fb.active(), "logo disabled in the system flags");
+        fb.system_flags_read(0x03);
+        assert!(fb.active());
+        fb.end_frame(false);
+        fb.end_frame(false);
+        assert!(fb.active());
+        fb.end_frame(true);
+        assert!(!fb.active());
+        assert_eq!(fb.skipped, 3);
+        // Too late after a reset
+        fb.restart();
+        for _ in 0..BOOT_WINDOW {
+            fb.end_frame(false);
+        }
+        fb.system_flags_read(0x03);
+        assert!(!fb.active());
+    }
+
+    #[test]
+    fn test_symbol() {
+        let mut fb = FastBoot::new();
+        fb.enabled = true;
+        let syms = BTreeMap::from([(0x200, "main".to_string()), (0x400, "_ZN13Arduboy2Base8bootLogoEv".to_string())]);
+        fb.set_symbols(&syms);
+        // The signature is ignored when symbols are known
+        fb.system_flags_read(0x03);
+        assert!(!fb.active());
+        assert!(fb.watching());
+        fb.step(0x100, 0x0AF0);
+        assert!(!fb.active());
+        fb.step(0x200, 0x0AF0);
+        assert!(fb.active());
+        fb.step(0x300, 0x0AE0);
+        fb.end_frame(true);
+        assert!(fb.active(), "sleeping only ends a signature match");
+        fb.step(0x102, 0x0AF2);
+        assert!(!fb.active() && !fb.watching());
+    }
+}
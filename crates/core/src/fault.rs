@@ -0,0 +1,236 @@
+//! Peripheral fault injection for robustness testing.
+//!
+//! Save/load code has error paths real hardware rarely takes: a worn
+//! EEPROM cell that does not take a write, a corrupted FX flash sector, a
+//! byte lost on the SPI bus. [`Faults`] makes the emulated peripherals
+//! misbehave on purpose so those paths can be exercised:
+//!
+//! | Spec | Effect |
+//! |------|--------|
+//! | `fx-corrupt` | FX flash reads return the stored byte XOR `xor` (default `0xFF`) |
+//! | `eeprom-fail` | EEPROM writes are dropped; the cell keeps its old value |
+//! | `spi-drop` | SPDR writes are lost: no device sees the byte and SPDR reads `0xFF` |
+//!
+//! Options follow the kind, comma-separated, e.g.
+//! `fx-corrupt,addr=0x10000-0x10FFF,xor=0x01` or `eeprom-fail,every=3,count=5`:
+//!
+//! | Option | Meaning |
+//! |--------|---------|
+//! | `addr=A-B` | Only FX byte addresses, EEPROM cells or (`spi-drop`) SPDR-writing PCs in `A..=B` |
+//! | `every=N` | Every Nth eligible access (default 1 = all) |
+//! | `p=F` | Each eligible access with probability F (seeded, repeatable) |
+//! | `count=N` | Stop after N injections |
+//!
+//! Rules come from `--fault`, the `fault` debugger command, `fault=` actions
+//! of the [`schedule`](crate::schedule) (`--at 300:fault=spi-drop,p=0.01`,
+//! `fault=off` clears) and `[[fault]]` tables in scenario files. The first
+//! injection of each rule is reported through [`Faults::take_messages`].
+
+/// Which peripheral misbehaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// FX reads return data XOR mask
+    FxCorrupt(u8),
+    EepromFail,
+    SpiDrop,
+}
+
+/// One fault rule and its counters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultRule {
+    pub kind: FaultKind,
+    /// Inclusive address range (None = all)
+    pub range: Option<(u32, u32)>,
+    pub every: u32,
+    pub probability: Option<f32>,
+    /// Injections allowed (None = unlimited)
+    pub limit: Option<u64>,
+    /// Text the rule was parsed from
+    pub spec: String,
+    /// Eligible accesses seen
+    pub seen: u64,
+    /// Faults injected
+    pub injected: u64,
+}
+
+fn parse_num(s: &str) -> Result<u32, String> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(h) => u32::from_str_radix(h, 16),
+        None => s.parse(),
+    }.map_err(|_| format!("bad number '{}'", s))
+}
+
+impl FaultRule {
+    /// Parse `<kind>[,option=value...]` (see the [module docs](self)).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(',').map(str::trim);
+        let kind = match parts.next().unwrap_or("") {
+            "fx-corrupt" => FaultKind::FxCorrupt(0xFF),
+            "eeprom-fail" => FaultKind::EepromFail,
+            "spi-drop" => FaultKind::SpiDrop,
+            other => return Err(format!("unknown fault '{}' (fx-corrupt, eeprom-fail, spi-drop)", other)),
+        };
+        let mut rule = FaultRule {
+            kind, range: None, every: 1, probability: None, limit: None,
+            spec: spec.trim().to_string(), seen: 0, injected: 0,
+        };
+        for opt in parts {
+            let (key, val) = opt.split_once('=').ok_or_else(|| format!("expected option=value, got '{}'", opt))?;
+            match key {
+                "addr" => {
+                    let (a, b) = val.split_once('-').ok_or_else(|| format!("expected addr=A-B, got '{}'", val))?;
+                    let (a, b) = (parse_num(a)?, parse_num(b)?);
+                    if a > b {
+                        return Err(format!("empty range {}", val));
+                    }
+                    rule.range = Some((a, b));
+                }
+                "every" => rule.every = parse_num(val)?.max(1),
+                "p" => rule.probability = Some(val.parse::<f32>().ok().filter(|p| (0.0..=1.0).contains(p))
+                    .ok_or_else(|| format!("bad probability '{}' (0-1)", val))?),
+                "count" => rule.limit = Some(parse_num(val)? as u64),
+                "xor" => match &mut rule.kind {
+                    FaultKind::FxCorrupt(mask) => *mask = parse_num(val).ok().filter(|&m| m <= 0xFF)
+                        .ok_or_else(|| format!("bad xor mask '{}'", val))? as u8,
+                    _ => return Err("xor= applies to fx-corrupt only".into()),
+                },
+                _ => return Err(format!("unknown option '{}'", key)),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+/// Active fault rules.
+#[derive(Debug, Clone)]
+pub struct Faults {
+    pub rules: Vec<FaultRule>,
+    rng: u32,
+    pending: Vec<String>,
+}
+
+impl Default for Faults {
+    fn default() -> Self {
+        Faults { rules: Vec::new(), rng: 0x2545_F491, pending: Vec::new() }
+    }
+}
+
+impl Faults {
+    /// Add a rule; returns its index.
+    pub fn add(&mut self, rule: FaultRule) -> usize {
+        self.rules.push(rule);
+        self.rules.len() - 1
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Option<FaultRule> {
+        (idx < self.rules.len()).then(|| self.rules.remove(idx))
+    }
+
+    pub fn clear(&mut self) {
+        self.rules.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// One line per rule with its counters.
+    pub fn list(&self) -> String {
+        self.rules.iter().enumerate()
+            .map(|(i, r)| format!("[{}] {}: {} injected of {} accesses\n", i, r.spec, r.injected, r.seen))
+            .collect()
+    }
+
+    /// Messages since the last call (first injection of each rule).
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Decide for each matching rule whether this access fails; returns
+    /// the first rule that fired.
+    fn fire(&mut self, pred: impl Fn(&FaultKind) -> bool, addr: u32, what: &str) -> Option<FaultKind> {
+        let mut fired = None;
+        for rule in self.rules.iter_mut() {
+            if !pred(&rule.kind) || rule.range.is_some_and(|(a, b)| !(a..=b).contains(&addr)) {
+                continue;
+            }
+            if rule.limit.is_some_and(|l| rule.injected >= l) {
+                continue;
+            }
+            rule.seen += 1;
+            let hit = match rule.probability {
+                Some(p) => {
+                    // xorshift32
+                    self.rng ^= self.rng << 13;
+                    self.rng ^= self.rng >> 17;
+                    self.rng ^= self.rng << 5;
+                    (self.rng as f32 / u32::MAX as f32) < p
+                }
+                None => rule.seen % rule.every as u64 == 0,
+            };
+            if hit && fired.is_none() {
+                if rule.injected == 0 {
+                    self.pending.push(format!("Fault [{}]: {} 0x{:04X}", rule.spec, what, addr));
+                }
+                rule.injected += 1;
+                fired = Some(rule.kind);
+            }
+        }
+        fired
+    }
+
+    /// Byte returned by an FX flash read of `addr`.
+    pub fn fx_read(&mut self, addr: u32, byte: u8) -> u8 {
+        match self.fire(|k| matches!(k, FaultKind::FxCorrupt(_)), addr, "FX read corrupted at") {
+            Some(FaultKind::FxCorrupt(mask)) => byte ^ mask,
+            _ => byte,
+        }
+    }
+
+    /// Whether an EEPROM write to `addr` fails.
+    pub fn eeprom_write_fails(&mut self, addr: u16) -> bool {
+        self.fire(|k| *k == FaultKind::EepromFail, addr as u32, "EEPROM write dropped at").is_some()
+    }
+
+    /// Whether the SPI byte written now is lost (`pc` as a byte address).
+    pub fn spi_drops(&mut self, pc: u32) -> bool {
+        self.fire(|k| *k == FaultKind::SpiDrop, pc, "SPI byte dropped at PC").is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eeprom_fail_and_fx_corrupt() {
+        let mut ard = crate::Arduboy::new();
+        ard.faults.add(FaultRule::parse("eeprom-fail,addr=16-31,every=2,count=2").unwrap());
+        // EEPROM write sequence: address, data, EEMPE then EEPE
+        let write = |ard: &mut crate::Arduboy, addr: u16, val: u8| {
+            ard.write_data(0x41, addr as u8);
+            ard.write_data(0x42, (addr >> 8) as u8);
+            ard.write_data(0x40, val);
+            ard.write_data(0x3F, 0x04);
+            ard.write_data(0x3F, 0x02);
+            ard.cpu.tick += ard.clock_hz as u64 / 250;
+        };
+        for i in 0..6 {
+            write(&mut ard, 16 + i, 0x10 + i as u8);
+        }
+        write(&mut ard, 40, 0x77);
+        let cells: Vec<u8> = ard.mem.eeprom[16..22].to_vec();
+        assert_eq!(cells, vec![0x10, 0xFF, 0x12, 0xFF, 0x14, 0x15]);
+        assert_eq!(ard.mem.eeprom[40], 0x77);
+        assert_eq!(ard.faults.rules[0].injected, 2);
+        assert_eq!(ard.faults.take_messages().len(), 1);
+
+        let mut f = Faults::default();
+        f.add(FaultRule::parse("fx-corrupt,addr=0x100-0x1FF,xor=0x0F").unwrap());
+        assert_eq!(f.fx_read(0x0FF, 0xA5), 0xA5);
+        assert_eq!(f.fx_read(0x100, 0xA5), 0xAA);
+        assert!(FaultRule::parse("spi-drop,xor=1").is_err());
+        assert!(FaultRule::parse("spi-drop,p=2").is_err());
+        assert!(FaultRule::parse("disk-full").is_err());
+    }
+}
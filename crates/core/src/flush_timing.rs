@@ -0,0 +1,371 @@
+//! Display flush timing against the SPI bus limit.
+//!
+//! The emulated SPI finishes a byte the moment SPDR is written, but on
+//! hardware each byte takes 8 SPI clocks. At the Arduboy's fosc/2 that is
+//! 16 CPU cycles, so a full 1 KB screen needs at least 16384 cycles
+//! (1.02 ms); Arduboy2's `paintScreen()` gets close with an 18-cycle loop.
+//! [`FlushTiming`] measures each frame's display flush (the display data
+//! bytes from the first to the last) as the bus would carry them, and
+//! compares it with that minimum at the configured SPI clock (SPCR SPR1:0,
+//! SPSR SPI2X). A byte that follows an SPSR read is taken to wait for SPIF
+//! as it would on hardware:
+//!
+//! | Field | Meaning |
+//! |-------|---------|
+//! | `window` | First data byte to the end of the last one on the bus |
+//! | `minimum` | Data bytes × cycles per byte |
+//! | `idle` | Bus idle time between bytes (`window - minimum`) |
+//! | `early` | Bytes written before the previous one finished shifting out, without polling SPIF |
+//! | `isr_cycles` / `other_bytes` | Interrupt handlers and non-data SPI bytes inside the window |
+//!
+//! Frames are flagged with a [`FlushFlag`]:
+//!
+//! | Flag | Condition |
+//! |------|-----------|
+//! | `Early` | A byte was written too soon by a timed loop: it is lost on hardware (WCOL) |
+//! | `Slow` | The window is more than twice the minimum |
+//! | `Split` | Data bytes more than [`BURST_GAP`] cycles apart (work between parts of the flush) |
+//! | `Interleaved` | Interrupts or other SPI traffic during the flush |
+//! | `Partial` / `Repeated` | Fewer bytes than one screen, or more than one screen |
+//!
+//! Cycles are counted in oscillator ticks like `Cpu::tick`, so a CLKPR
+//! prescaler stretches the byte time by the same factor, and milliseconds
+//! come from the [`Arduboy::clock_hz`](crate::Arduboy::clock_hz) the frame
+//! ran at.
+//!
+//! The first frame with each flag is reported through
+//! [`FlushTiming::take_messages`]; [`FlushTiming::report`] summarizes the
+//! recent frames. Like the frame timeline, it costs nothing while off.
+
+use std::collections::VecDeque;
+
+/// Frames kept by default (10 seconds)
+pub const DEFAULT_CAPACITY: usize = 600;
+/// Data bytes further apart than this split the flush
+pub const BURST_GAP: u64 = crate::frame_timeline::BURST_GAP;
+/// Window/minimum ratio above which a flush is slow
+const SLOW_RATIO: u64 = 2;
+
+/// CPU cycles to shift one byte out for SPCR `spcr` and SPSR SPI2X.
+pub fn spi_byte_cycles(spcr: u8, spi2x: bool) -> u64 {
+    let div = [4u64, 16, 64, 128][(spcr & 3) as usize];
+    8 * if spi2x { div / 2 } else { div }
+}
+
+/// An abnormal flush pattern.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushFlag {
+    Early,
+    Slow,
+    Split,
+    Interleaved,
+    Partial,
+    Repeated,
+}
+
+impl FlushFlag {
+    pub const ALL: [FlushFlag; 6] = [
+        FlushFlag::Early, FlushFlag::Slow, FlushFlag::Split,
+        FlushFlag::Interleaved, FlushFlag::Partial, FlushFlag::Repeated,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FlushFlag::Early => "early",
+            FlushFlag::Slow => "slow",
+            FlushFlag::Split => "split",
+            FlushFlag::Interleaved => "interleaved",
+            FlushFlag::Partial => "partial",
+            FlushFlag::Repeated => "repeated",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            FlushFlag::Early => "bytes written before the previous one finished, without waiting for SPIF (lost on hardware)",
+            FlushFlag::Slow => "flush took over twice the SPI minimum",
+            FlushFlag::Split => "flush split by other work",
+            FlushFlag::Interleaved => "interrupts or other SPI traffic during the flush",
+            FlushFlag::Partial => "less than one screen sent",
+            FlushFlag::Repeated => "more than one screen sent",
+        }
+    }
+}
+
+/// One frame's flush.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlushFrame {
+    pub frame: u32,
+    /// Display data bytes
+    pub bytes: u32,
+    /// Cycles per byte at the SPI clock in use
+    pub byte_cycles: u64,
+    pub window: u64,
+    pub minimum: u64,
+    pub idle: u64,
+    /// Runs of data bytes separated by more than [`BURST_GAP`]
+    pub bursts: u32,
+    pub early: u32,
+    pub isr_cycles: u64,
+    pub other_bytes: u32,
+    /// One screen of display data
+    pub screen_bytes: u32,
+    /// Oscillator frequency the cycles count at
+    pub clock_hz: u32,
+}
+
+impl FlushFrame {
+    /// Minimum / window (1.0 = back to back).
+    pub fn efficiency(&self) -> f32 {
+        if self.window == 0 { 1.0 } else { self.minimum as f32 / self.window as f32 }
+    }
+
+    pub fn flags(&self) -> Vec<FlushFlag> {
+        FlushFlag::ALL.into_iter().filter(|&f| match f {
+            FlushFlag::Early => self.early > 0,
+            FlushFlag::Slow => self.window > self.minimum * SLOW_RATIO,
+            FlushFlag::Split => self.bursts > 1,
+            FlushFlag::Interleaved => self.isr_cycles > 0 || self.other_bytes > 0,
+            FlushFlag::Partial => self.bytes < self.screen_bytes,
+            FlushFlag::Repeated => self.bytes > self.screen_bytes,
+        }).collect()
+    }
+
+    /// One-line summary.
+    pub fn line(&self) -> String {
+        let flags: Vec<&str> = self.flags().iter().map(|f| f.name()).collect();
+        format!("F{}: {} bytes in {} cycles ({:.2} ms), minimum {} ({:.0}%), idle {}, {} burst(s), {} early, ISR {} cycles, {} other SPI bytes{}",
+            self.frame, self.bytes, self.window, ms(self.window, self.clock_hz), self.minimum, self.efficiency() * 100.0,
+            self.idle, self.bursts, self.early, self.isr_cycles, self.other_bytes,
+            if flags.is_empty() { String::new() } else { format!(" [{}]", flags.join(", ")) })
+    }
+}
+
+fn ms(cycles: u64, clock_hz: u32) -> f64 {
+    cycles as f64 * 1000.0 / clock_hz as f64
+}
+
+/// Flush in progress.
+#[derive(Debug, Clone, Default)]
+struct Open {
+    first: u64,
+    /// Last data byte write tick and when it finishes on the bus
+    last: u64,
+    bus_end: u64,
+    bytes: u32,
+    minimum: u64,
+    idle: u64,
+    bursts: u32,
+    early: u32,
+    /// SPSR read since the last data byte
+    polled: bool,
+    /// Counted since the first byte, committed at each data byte
+    isr_pending: u64,
+    isr_cycles: u64,
+    other_pending: u32,
+    other_bytes: u32,
+    byte_cycles: u64,
+}
+
+/// Flush timing recorder.
+#[derive(Debug, Clone)]
+pub struct FlushTiming {
+    pub enabled: bool,
+    /// Frames kept before the oldest are discarded
+    pub capacity: usize,
+    frames: VecDeque<FlushFrame>,
+    open: Option<Open>,
+    /// Entry ticks of running handlers
+    isr_stack: Vec<u64>,
+    reported: Vec<FlushFlag>,
+    pending: Vec<String>,
+}
+
+impl Default for FlushTiming {
+    fn default() -> Self {
+        FlushTiming {
+            enabled: false,
+            capacity: DEFAULT_CAPACITY,
+            frames: VecDeque::new(),
+            open: None,
+            isr_stack: Vec::new(),
+            reported: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl FlushTiming {
+    /// Measured frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FlushFrame> {
+        self.frames.iter()
+    }
+
+    /// The `n`th most recent measured frame (0 = last).
+    pub fn recent(&self, n: usize) -> Option<&FlushFrame> {
+        self.frames.iter().rev().nth(n)
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.open = None;
+        self.isr_stack.clear();
+    }
+
+    /// Messages since the last call (first frame with each flag).
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// SPSR read (SPIF polling).
+    pub fn spsr_read(&mut self) {
+        if let Some(o) = self.open.as_mut() {
+            o.polled = true;
+        }
+    }
+
+    /// Byte written to SPDR: display data (`data`) or anything else, taking
+    /// `byte_cycles` on the bus.
+    pub fn spi_byte(&mut self, tick: u64, data: bool, byte_cycles: u64) {
+        if !data {
+            if let Some(o) = self.open.as_mut() {
+                o.other_pending += 1;
+            }
+            return;
+        }
+        let o = self.open.get_or_insert_with(|| Open {
+            first: tick, last: tick, bus_end: tick, bursts: 1, byte_cycles, ..Default::default()
+        });
+        if o.bytes > 0 {
+            if tick < o.bus_end {
+                // A polling loop waits for the byte on hardware
+                if !o.polled {
+                    o.early += 1;
+                }
+            } else {
+                o.idle += tick - o.bus_end;
+            }
+            if tick - o.last > BURST_GAP {
+                o.bursts += 1;
+            }
+        }
+        o.bus_end = o.bus_end.max(tick) + byte_cycles;
+        o.last = tick;
+        o.polled = false;
+        o.bytes += 1;
+        o.minimum += byte_cycles;
+        o.isr_cycles = o.isr_pending;
+        o.other_bytes = o.other_pending;
+    }
+
+    pub fn isr_enter(&mut self, tick: u64) {
+        self.isr_stack.push(tick);
+    }
+
+    pub fn isr_exit(&mut self, tick: u64) {
+        // Outermost handler only, so nested time is not counted twice
+        if let (Some(start), true) = (self.isr_stack.pop(), self.isr_stack.is_empty()) {
+            if let Some(o) = self.open.as_mut() {
+                o.isr_pending += tick - start.max(o.first);
+            }
+        }
+    }
+
+    /// End of `run_frame()`, with one screen's worth of data bytes and the
+    /// oscillator frequency.
+    pub fn end_frame(&mut self, frame: u32, screen_bytes: u32, clock_hz: u32) {
+        let Some(o) = self.open.take() else { return };
+        let f = FlushFrame {
+            frame,
+            bytes: o.bytes,
+            byte_cycles: o.byte_cycles,
+            window: o.bus_end - o.first,
+            minimum: o.minimum,
+            idle: o.idle,
+            bursts: o.bursts,
+            early: o.early,
+            isr_cycles: o.isr_cycles,
+            other_bytes: o.other_bytes,
+            screen_bytes,
+            clock_hz,
+        };
+        for flag in f.flags() {
+            if !self.reported.contains(&flag) {
+                self.reported.push(flag);
+                self.pending.push(format!("Display flush F{}: {} ({})", frame, flag.describe(), f.line()));
+            }
+        }
+        if self.frames.len() >= self.capacity.max(1) {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(f);
+    }
+
+    /// Averages and flag counts over the measured frames.
+    pub fn report(&self) -> String {
+        let n = self.frames.len() as u64;
+        if n == 0 {
+            return "Display flush: no frames measured".into();
+        }
+        let avg = |get: fn(&FlushFrame) -> u64| self.frames.iter().map(get).sum::<u64>() / n;
+        let (window, minimum) = (avg(|f| f.window), avg(|f| f.minimum));
+        let worst = self.frames.iter()
+            .min_by(|a, b| a.efficiency().total_cmp(&b.efficiency()))
+            .expect("frames measured");
+        let last = self.frames.back().expect("frames measured");
+        let flags: Vec<String> = FlushFlag::ALL.iter()
+            .map(|&fl| format!("{} {}", fl.name(), self.frames.iter().filter(|f| f.flags().contains(&fl)).count()))
+            .collect();
+        let mut out = format!("Display flush: {} frames, SPI {} cycles/byte\n", n, last.byte_cycles);
+        out.push_str(&format!("  bytes/frame   {}\n", avg(|f| f.bytes as u64)));
+        out.push_str(&format!("  window        {} cycles ({:.2} ms) avg, minimum {} ({:.2} ms)\n",
+            window, ms(window, last.clock_hz), minimum, ms(minimum, last.clock_hz)));
+        out.push_str(&format!("  efficiency    {:.0}% avg, worst {:.0}% at F{}\n",
+            if window == 0 { 100.0 } else { minimum as f64 * 100.0 / window as f64 },
+            worst.efficiency() * 100.0, worst.frame));
+        out.push_str(&format!("  idle on bus   {} cycles avg\n", avg(|f| f.idle)));
+        out.push_str(&format!("  interrupts    {} cycles avg during the flush\n", avg(|f| f.isr_cycles)));
+        out.push_str(&format!("  flags         {}", flags.join(", ")));
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flush_window_and_flags() {
+        let bc = spi_byte_cycles(0x50, true);
+        assert_eq!(bc, 16);
+        assert_eq!(spi_byte_cycles(0x53, false), 1024);
+
+        let mut t = FlushTiming { enabled: true, ..Default::default() };
+        // 4 bytes 18 cycles apart, like paintScreen
+        for i in 0..4 {
+            t.spi_byte(1000 + i * 18, true, bc);
+        }
+        t.end_frame(1, 4, crate::CLOCK_HZ);
+        let f = t.recent(0).unwrap().clone();
+        assert_eq!((f.bytes, f.minimum, f.window, f.idle), (4, 64, 3 * 18 + 16, 6));
+        assert!(f.flags().is_empty(), "{:?}", f.flags());
+
+        // Too fast, then an interrupt and a pause splitting the flush
+        t.spi_byte(5000, true, bc);
+        t.spsr_read();
+        t.spi_byte(5002, true, bc);
+        t.spi_byte(5004, true, bc);
+        t.isr_enter(5010);
+        t.spi_byte(5020, false, bc);
+        t.isr_exit(5110);
+        t.spi_byte(6000, true, bc);
+        t.end_frame(2, 3, crate::CLOCK_HZ);
+        let f = t.recent(0).unwrap();
+        assert_eq!((f.early, f.bursts, f.isr_cycles, f.other_bytes), (1, 2, 100, 1));
+        assert_eq!(f.bytes, 4);
+        assert_eq!(f.flags(), vec![FlushFlag::Early, FlushFlag::Slow, FlushFlag::Split,
+            FlushFlag::Interleaved, FlushFlag::Repeated]);
+        assert_eq!(t.take_messages().len(), 5);
+        assert!(t.report().contains("slow 1"));
+    }
+}
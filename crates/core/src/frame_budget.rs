@@ -0,0 +1,247 @@
+//! Interrupt storm and missed-deadline diagnostics.
+//!
+//! A game that spends its frame in interrupt handlers, or whose main loop
+//! cannot finish drawing within a frame, just looks slow. [`FrameBudget`]
+//! watches interrupt entry/exit and display traffic and raises a
+//! [`BudgetWarning`] when the cause is one of:
+//!
+//! | Warning | Condition |
+//! |---------|-----------|
+//! | [`Storm`](BudgetWarning::Storm) | A handler runs [`STORM_RUNS`] times back to back: its flag is set again before `RETI` |
+//! | [`Reentry`](BudgetWarning::Reentry) | A handler is entered again while it is running (`sei` inside the ISR) |
+//! | [`IsrLoad`](BudgetWarning::IsrLoad) | Handlers take over half the CPU for [`LOAD_FRAMES`] frames in a row |
+//! | [`MissedDeadline`](BudgetWarning::MissedDeadline) | No complete display update for [`DEADLINE_FRAMES`] frames while the CPU never sleeps |
+//!
+//! Each kind is reported once per vector (storm, re-entry) or once per
+//! session. Warnings are collected by the frontend with
+//! [`FrameBudget::take_warnings`], like the [`eeprom_wear`](crate::eeprom_wear)
+//! warnings.
+
+use crate::frame_timeline::vector_name;
+use crate::{CpuType, FRAME_CYCLES};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Back-to-back runs of one handler that make a storm
+pub const STORM_RUNS: u32 = 32;
+/// Cycles from `RETI` to the next entry that still count as back to back
+pub const STORM_GAP: u64 = 16;
+/// Share of a frame's cycles in handlers that counts as overloaded
+pub const LOAD_LIMIT: f32 = 0.5;
+/// Consecutive overloaded frames before warning
+pub const LOAD_FRAMES: u32 = 10;
+/// Frames without a complete display update before warning
+pub const DEADLINE_FRAMES: u32 = 4;
+
+/// A detected timing problem.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BudgetWarning {
+    Storm { vector: u16, name: String, runs: u32, frame: u32 },
+    Reentry { vector: u16, name: String, depth: usize, frame: u32 },
+    /// `share` of the CPU in handlers, `top` the busiest one
+    IsrLoad { share: f32, top: String, top_share: f32, frame: u32 },
+    /// `frames` without a complete update of `bytes` display bytes
+    MissedDeadline { frames: u32, bytes: u32, frame: u32 },
+}
+
+impl fmt::Display for BudgetWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BudgetWarning::Storm { name, runs, frame, .. } =>
+                write!(f, "Interrupt storm F{}: {} ran {} times back to back (flag set again before RETI)",
+                    frame, name, runs),
+            BudgetWarning::Reentry { name, depth, frame, .. } =>
+                write!(f, "Interrupt re-entry F{}: {} entered while running (nesting depth {})",
+                    frame, name, depth),
+            BudgetWarning::IsrLoad { share, top, top_share, frame } =>
+                write!(f, "ISR load F{}: {:.0}% of the CPU in interrupt handlers for {} frames ({} {:.0}%)",
+                    frame, share * 100.0, LOAD_FRAMES, top, top_share * 100.0),
+            BudgetWarning::MissedDeadline { frames, bytes, frame } =>
+                write!(f, "Missed deadline F{}: no complete display update ({} bytes) in {} frames with the CPU always busy",
+                    frame, bytes, frames),
+        }
+    }
+}
+
+/// Interrupt and display-update bookkeeping for the current frame.
+#[derive(Debug, Clone)]
+pub struct FrameBudget {
+    /// Checking (on by default; costs a few counters per interrupt)
+    pub enabled: bool,
+    cpu: CpuType,
+    /// Running handlers, outermost first: (vector, entry tick)
+    stack: Vec<(u16, u64)>,
+    /// Vector and tick of the last `RETI` to the main program
+    last_exit: Option<(u16, u64)>,
+    /// Vector and length of the current back-to-back run
+    run: (u16, u32),
+    /// Cycles in handlers this frame, per outermost vector
+    isr_cycles: Vec<(u16, u64)>,
+    load_streak: u32,
+    /// Display data bytes since the last complete update
+    display_bytes: u32,
+    last_data_count: u32,
+    /// Frames since the last complete update without any sleep
+    busy_frames: u32,
+    updates: u32,
+    warned: HashSet<(u8, u16)>,
+    pending: Vec<BudgetWarning>,
+}
+
+impl FrameBudget {
+    pub fn new(cpu: CpuType) -> Self {
+        FrameBudget {
+            enabled: true,
+            cpu,
+            stack: Vec::new(),
+            last_exit: None,
+            run: (0, 0),
+            isr_cycles: Vec::new(),
+            load_streak: 0,
+            display_bytes: 0,
+            last_data_count: 0,
+            busy_frames: 0,
+            updates: 0,
+            warned: HashSet::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Forget handlers in progress (CPU reset).
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.last_exit = None;
+        self.run = (0, 0);
+    }
+
+    fn warn_once(&mut self, kind: u8, vector: u16, w: BudgetWarning) {
+        if self.warned.insert((kind, vector)) {
+            self.pending.push(w);
+        }
+    }
+
+    /// Interrupt dispatched.
+    pub fn isr_enter(&mut self, vector: u16, tick: u64, frame: u32) {
+        if self.stack.iter().any(|&(v, _)| v == vector) {
+            let depth = self.stack.len() + 1;
+            let name = vector_name(self.cpu, vector);
+            self.warn_once(1, vector, BudgetWarning::Reentry { vector, name, depth, frame });
+        }
+        if self.stack.is_empty() {
+            let back_to_back = self.last_exit
+                .is_some_and(|(v, t)| v == vector && tick.saturating_sub(t) <= STORM_GAP);
+            self.run = if back_to_back { (vector, self.run.1 + 1) } else { (vector, 1) };
+            if self.run.1 >= STORM_RUNS {
+                let name = vector_name(self.cpu, vector);
+                self.warn_once(0, vector, BudgetWarning::Storm { vector, name, runs: self.run.1, frame });
+            }
+        }
+        self.stack.push((vector, tick));
+    }
+
+    /// `RETI` executed.
+    pub fn isr_exit(&mut self, tick: u64) {
+        let Some((vector, start)) = self.stack.pop() else { return };
+        if self.stack.is_empty() {
+            self.charge(vector, tick.saturating_sub(start));
+            self.last_exit = Some((vector, tick));
+        }
+    }
+
+    fn charge(&mut self, vector: u16, cycles: u64) {
+        match self.isr_cycles.iter_mut().find(|(v, _)| *v == vector) {
+            Some(slot) => slot.1 += cycles,
+            None => self.isr_cycles.push((vector, cycles)),
+        }
+    }
+
+    /// End of `run_frame()`. `data_count` is the display's running count of
+    /// data bytes, `panel_bytes` the size of a full update and `slept`
+    /// whether the CPU slept during the frame.
+    pub fn end_frame(&mut self, frame: u32, tick: u64, data_count: u32, panel_bytes: u32, slept: bool) {
+        // Handlers still running are charged up to the frame boundary
+        if let Some(&(vector, start)) = self.stack.first() {
+            self.charge(vector, tick.saturating_sub(start));
+            self.stack[0].1 = tick;
+        }
+        let total: u64 = self.isr_cycles.iter().map(|(_, c)| c).sum();
+        let share = total as f32 / FRAME_CYCLES as f32;
+        self.load_streak = if share > LOAD_LIMIT { self.load_streak + 1 } else { 0 };
+        if self.load_streak >= LOAD_FRAMES {
+            if let Some(&(v, c)) = self.isr_cycles.iter().max_by_key(|(_, c)| *c) {
+                let top = vector_name(self.cpu, v);
+                let top_share = c as f32 / FRAME_CYCLES as f32;
+                self.warn_once(2, 0, BudgetWarning::IsrLoad { share, top, top_share, frame });
+            }
+        }
+        self.isr_cycles.clear();
+
+        // The display's counter restarts when the controller is reset
+        let new_bytes = data_count.checked_sub(self.last_data_count).unwrap_or(data_count);
+        self.last_data_count = data_count;
+        self.display_bytes += new_bytes;
+        if panel_bytes > 0 && self.display_bytes >= panel_bytes {
+            self.display_bytes %= panel_bytes;
+            self.updates += 1;
+            self.busy_frames = 0;
+        } else if slept {
+            self.busy_frames = 0;
+        } else {
+            self.busy_frames += 1;
+            if self.updates >= 2 && self.busy_frames >= DEADLINE_FRAMES {
+                let w = BudgetWarning::MissedDeadline { frames: self.busy_frames, bytes: panel_bytes, frame };
+                self.warn_once(3, 0, w);
+            }
+        }
+    }
+
+    /// Take warnings raised since the last call.
+    pub fn take_warnings(&mut self) -> Vec<BudgetWarning> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storm_and_reentry() {
+        let mut b = FrameBudget::new(CpuType::Atmega32u4);
+        let mut t = 0;
+        for _ in 0..STORM_RUNS {
+            b.isr_enter(0x2E, t, 5);
+            t += 40;
+            b.isr_exit(t);
+            t += 6;
+        }
+        let w = b.take_warnings();
+        assert!(matches!(w.as_slice(), [BudgetWarning::Storm { vector: 0x2E, runs: STORM_RUNS, .. }]), "{:?}", w);
+        assert!(w[0].to_string().starts_with("Interrupt storm F5: TIMER0_OVF"));
+
+        b.isr_enter(0x2A, t, 6);
+        b.isr_enter(0x2A, t + 10, 6);
+        let w = b.take_warnings();
+        assert!(matches!(w.as_slice(), [BudgetWarning::Reentry { depth: 2, .. }]), "{:?}", w);
+    }
+
+    #[test]
+    fn test_load_and_deadline() {
+        let mut b = FrameBudget::new(CpuType::Atmega32u4);
+        for f in 0..LOAD_FRAMES {
+            let t = f as u64 * FRAME_CYCLES;
+            b.isr_enter(0x2E, t, f);
+            b.isr_exit(t + FRAME_CYCLES * 6 / 10);
+            b.end_frame(f, t + FRAME_CYCLES, (f + 1) * 1024, 1024, false);
+        }
+        let w = b.take_warnings();
+        assert!(matches!(w.as_slice(), [BudgetWarning::IsrLoad { .. }]), "{:?}", w);
+
+        // Display updates stop while the CPU stays busy
+        for f in LOAD_FRAMES..LOAD_FRAMES + DEADLINE_FRAMES {
+            b.end_frame(f, 0, LOAD_FRAMES * 1024 + 100, 1024, false);
+        }
+        let w = b.take_warnings();
+        assert!(matches!(w.as_slice(), [BudgetWarning::MissedDeadline { frames: DEADLINE_FRAMES, .. }]), "{:?}", w);
+    }
+}
@@ -0,0 +1,467 @@
+//! Per-frame event timeline.
+//!
+//! Records where each frame's cycles go, as spans on a few tracks:
+//!
+//! | Track | Span |
+//! |-------|------|
+//! | interrupt (one per vector) | ISR entry to `RETI` |
+//! | `spi <device>` | First byte with the chip selected to CS going high |
+//! | `display data` | Run of display bytes sent with DC high, less than [`BURST_GAP`] cycles apart |
+//! | `sleep` | `SLEEP` to the waking interrupt |
+//!
+//! Spans still open at the end of a frame are split there. The last
+//! [`FrameTimeline::capacity`] frames are kept; [`FrameTimeline::render`]
+//! draws one as text (debugger `timeline`), and
+//! [`FrameTimeline::to_chrome_json`] exports them for `chrome://tracing` or
+//! Perfetto. Like the SPI decoder, it costs nothing while off.
+
+use crate::spi_decode::{SpiDevice, SpiSelect};
+use crate::CpuType;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Frames kept by default (10 seconds)
+pub const DEFAULT_CAPACITY: usize = 600;
+/// Display bytes further apart than this start a new burst
+pub const BURST_GAP: u64 = 256;
+/// Cycles to clock one byte out at the Arduboy's SPI speed (fosc/2)
+const SPI_BYTE_CYCLES: u64 = 16;
+
+/// What a span measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Track {
+    /// Interrupt handler (vector word address)
+    Interrupt(u16),
+    /// Chip-select window
+    Spi(SpiDevice),
+    /// Display data burst
+    DisplayData,
+    Sleep,
+}
+
+impl Track {
+    /// Track label; interrupt vectors are named for `cpu`.
+    pub fn name(&self, cpu: CpuType) -> String {
+        match self {
+            Track::Interrupt(v) => vector_name(cpu, *v),
+            Track::Spi(d) => format!("spi {}", d.name()),
+            Track::DisplayData => "display data".into(),
+            Track::Sleep => "sleep".into(),
+        }
+    }
+
+    fn category(&self) -> &'static str {
+        match self {
+            Track::Interrupt(_) => "isr",
+            Track::Spi(_) | Track::DisplayData => "spi",
+            Track::Sleep => "sleep",
+        }
+    }
+
+    /// Display order: interrupts by vector, then SPI, display, sleep.
+    fn order(&self) -> (u8, u16) {
+        match self {
+            Track::Interrupt(v) => (0, *v),
+            Track::Spi(d) => (1, *d as u16),
+            Track::DisplayData => (2, 0),
+            Track::Sleep => (3, 0),
+        }
+    }
+}
+
+/// Interrupt name for a vector word address, as dispatched by the emulator.
+pub fn vector_name(cpu: CpuType, vector: u16) -> String {
+    let name = match (cpu, vector) {
+        (CpuType::Atmega32u4, 0x22) => "TIMER1_COMPA",
+        (CpuType::Atmega32u4, 0x24) => "TIMER1_COMPB",
+        (CpuType::Atmega32u4, 0x26) => "TIMER1_COMPC",
+        (CpuType::Atmega32u4, 0x28) => "TIMER1_OVF",
+        (CpuType::Atmega32u4, 0x2A) => "TIMER0_COMPA",
+        (CpuType::Atmega32u4, 0x2C) => "TIMER0_COMPB",
+        (CpuType::Atmega32u4, 0x2E) => "TIMER0_OVF",
+        (CpuType::Atmega32u4, 0x30) => "SPI_STC",
+        (CpuType::Atmega32u4, 0x38) => "TIMER4_COMPA",
+        (CpuType::Atmega32u4, 0x3A) => "ADC",
+        (CpuType::Atmega32u4, 0x3C) => "TIMER4_COMPB",
+        (CpuType::Atmega32u4, 0x3E) => "TIMER4_COMPD",
+        (CpuType::Atmega32u4, 0x40) => "TIMER3_COMPA",
+        (CpuType::Atmega32u4, 0x42) => "TIMER3_COMPB",
+        (CpuType::Atmega32u4, 0x44) => "TIMER3_COMPC",
+        (CpuType::Atmega32u4, 0x46) => "TIMER3_OVF",
+        (CpuType::Atmega32u4, 0x48) => "TIMER4_OVF",
+        (CpuType::Atmega328p, 0x0E) => "TIMER2_COMPA",
+        (CpuType::Atmega328p, 0x10) => "TIMER2_COMPB",
+        (CpuType::Atmega328p, 0x12) => "TIMER2_OVF",
+        (CpuType::Atmega328p, 0x16) => "TIMER1_COMPA",
+        (CpuType::Atmega328p, 0x18) => "TIMER1_COMPB",
+        (CpuType::Atmega328p, 0x1A) => "TIMER1_OVF",
+        (CpuType::Atmega328p, 0x1C) => "TIMER0_COMPA",
+        (CpuType::Atmega328p, 0x1E) => "TIMER0_COMPB",
+        (CpuType::Atmega328p, 0x20) => "TIMER0_OVF",
+        (CpuType::Atmega328p, 0x22) => "SPI_STC",
+        (CpuType::Atmega328p, 0x24) => "USART_RX",
+        (CpuType::Atmega328p, 0x26) => "USART_UDRE",
+        (CpuType::Atmega328p, 0x28) => "USART_TX",
+        (CpuType::Atmega328p, 0x2A) => "ADC",
+        (CpuType::Atmega2560, 0x1A) => "TIMER2_COMPA",
+        (CpuType::Atmega2560, 0x1C) => "TIMER2_COMPB",
+        (CpuType::Atmega2560, 0x1E) => "TIMER2_OVF",
+        (CpuType::Atmega2560, 0x22) => "TIMER1_COMPA",
+        (CpuType::Atmega2560, 0x24) => "TIMER1_COMPB",
+        (CpuType::Atmega2560, 0x26) => "TIMER1_COMPC",
+        (CpuType::Atmega2560, 0x28) => "TIMER1_OVF",
+        (CpuType::Atmega2560, 0x2A) => "TIMER0_COMPA",
+        (CpuType::Atmega2560, 0x2C) => "TIMER0_COMPB",
+        (CpuType::Atmega2560, 0x2E) => "TIMER0_OVF",
+        (CpuType::Atmega2560, 0x30) => "SPI_STC",
+        (CpuType::Atmega2560, 0x32) => "USART0_RX",
+        (CpuType::Atmega2560, 0x34) => "USART0_UDRE",
+        (CpuType::Atmega2560, 0x36) => "USART0_TX",
+        (CpuType::Atmega2560, 0x3A) => "ADC",
+        (CpuType::Atmega2560, 0x40) => "TIMER3_COMPA",
+        (CpuType::Atmega2560, 0x42) => "TIMER3_COMPB",
+        (CpuType::Atmega2560, 0x44) => "TIMER3_COMPC",
+        (CpuType::Atmega2560, 0x46) => "TIMER3_OVF",
+        (CpuType::Atmega2560, 0x54) => "TIMER4_COMPA",
+        (CpuType::Atmega2560, 0x56) => "TIMER4_COMPB",
+        (CpuType::Atmega2560, 0x58) => "TIMER4_COMPC",
+        (CpuType::Atmega2560, 0x5A) => "TIMER4_OVF",
+        (CpuType::Atmega2560, 0x5E) => "TIMER5_COMPA",
+        (CpuType::Atmega2560, 0x60) => "TIMER5_COMPB",
+        (CpuType::Atmega2560, 0x62) => "TIMER5_COMPC",
+        (CpuType::Atmega2560, 0x64) => "TIMER5_OVF",
+        _ => return format!("IRQ 0x{:04X}", vector as u32 * 2),
+    };
+    name.to_string()
+}
+
+/// One span in CPU ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub track: Track,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// The spans of one frame.
+#[derive(Debug, Clone)]
+pub struct FrameEvents {
+    pub frame: u32,
+    pub start: u64,
+    pub end: u64,
+    pub spans: Vec<Span>,
+}
+
+impl FrameEvents {
+    /// Per-track (span count, cycles), in display order.
+    pub fn totals(&self) -> Vec<(Track, usize, u64)> {
+        let mut totals: Vec<(Track, usize, u64)> = Vec::new();
+        for s in &self.spans {
+            match totals.iter_mut().find(|t| t.0 == s.track) {
+                Some(t) => {
+                    t.1 += 1;
+                    t.2 += s.end - s.start;
+                }
+                None => totals.push((s.track, 1, s.end - s.start)),
+            }
+        }
+        totals.sort_by_key(|t| t.0.order());
+        totals
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SpiWindow {
+    device: SpiDevice,
+    cs: Option<(u16, u8)>,
+    start: u64,
+}
+
+/// Timeline recorder.
+#[derive(Debug, Clone)]
+pub struct FrameTimeline {
+    /// Recording
+    pub enabled: bool,
+    /// Frames kept before the oldest are discarded
+    pub capacity: usize,
+    cpu: CpuType,
+    frames: VecDeque<FrameEvents>,
+    current: Option<FrameEvents>,
+    /// Open ISRs (vector, entry tick), innermost last
+    isr_stack: Vec<(u16, u64)>,
+    /// Open CS window
+    spi: Option<SpiWindow>,
+    /// Open display burst (start tick, last byte tick)
+    burst: Option<(u64, u64)>,
+    sleep: Option<u64>,
+}
+
+impl FrameTimeline {
+    pub fn new(cpu: CpuType) -> Self {
+        FrameTimeline {
+            enabled: false,
+            capacity: DEFAULT_CAPACITY,
+            cpu,
+            frames: VecDeque::new(),
+            current: None,
+            isr_stack: Vec::new(),
+            spi: None,
+            burst: None,
+            sleep: None,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.enabled = true;
+    }
+
+    /// Stop recording; the frame in progress is dropped.
+    pub fn stop(&mut self) {
+        self.enabled = false;
+        self.current = None;
+        self.isr_stack.clear();
+        self.spi = None;
+        self.burst = None;
+        self.sleep = None;
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Completed frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameEvents> {
+        self.frames.iter()
+    }
+
+    /// The `n`th most recent completed frame (0 = last).
+    pub fn recent(&self, n: usize) -> Option<&FrameEvents> {
+        self.frames.iter().rev().nth(n)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Start of `run_frame()`. A frame cut short by a breakpoint continues
+    /// when the same frame number is resumed.
+    pub fn begin_frame(&mut self, frame: u32, tick: u64) {
+        if self.current.as_ref().is_some_and(|f| f.frame == frame) {
+            return;
+        }
+        self.current = Some(FrameEvents { frame, start: tick, end: tick, spans: Vec::new() });
+    }
+
+    /// End of `run_frame()`: split open spans and keep the frame.
+    pub fn end_frame(&mut self, tick: u64) {
+        if self.current.is_none() {
+            return;
+        }
+        for i in 0..self.isr_stack.len() {
+            let (v, start) = self.isr_stack[i];
+            self.push(Track::Interrupt(v), start, tick);
+            self.isr_stack[i].1 = tick;
+        }
+        if let Some(w) = self.spi.as_mut() {
+            let (device, start) = (w.device, w.start);
+            w.start = tick;
+            self.push(Track::Spi(device), start, tick);
+        }
+        if let Some((start, last)) = self.burst {
+            let end = (last + SPI_BYTE_CYCLES).min(tick);
+            self.push(Track::DisplayData, start, end);
+            self.burst = (last + SPI_BYTE_CYCLES > tick).then_some((tick, last));
+        }
+        if let Some(start) = self.sleep {
+            self.push(Track::Sleep, start, tick);
+            self.sleep = Some(tick);
+        }
+        let mut f = self.current.take().expect("frame in progress");
+        f.end = tick;
+        f.spans.sort_by_key(|s| s.start);
+        if self.frames.len() >= self.capacity.max(1) {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(f);
+    }
+
+    fn push(&mut self, track: Track, start: u64, end: u64) {
+        if let Some(f) = self.current.as_mut() {
+            if end > start {
+                f.spans.push(Span { track, start, end });
+            }
+        }
+    }
+
+    /// Interrupt dispatched; also ends a sleep.
+    pub fn isr_enter(&mut self, vector: u16, tick: u64) {
+        if let Some(start) = self.sleep.take() {
+            self.push(Track::Sleep, start, tick);
+        }
+        self.isr_stack.push((vector, tick));
+    }
+
+    /// `RETI` executed.
+    pub fn isr_exit(&mut self, tick: u64) {
+        if let Some((v, start)) = self.isr_stack.pop() {
+            self.push(Track::Interrupt(v), start, tick);
+        }
+    }
+
+    /// `SLEEP` executed.
+    pub fn sleep_enter(&mut self, tick: u64) {
+        self.sleep.get_or_insert(tick);
+    }
+
+    /// Byte written to SPDR with `sel` addressed.
+    pub fn spi_byte(&mut self, tick: u64, sel: &SpiSelect) {
+        if self.spi.is_some_and(|w| w.device != sel.device) {
+            self.close_spi(tick);
+        }
+        if sel.device != SpiDevice::None && self.spi.is_none() {
+            self.spi = Some(SpiWindow { device: sel.device, cs: sel.cs, start: tick });
+        }
+        let data = matches!(sel.device, SpiDevice::Ssd1306 | SpiDevice::Pcd8544) && sel.dc_data == Some(true);
+        match self.burst {
+            Some((start, last)) if data && tick - last <= BURST_GAP => self.burst = Some((start, tick)),
+            _ => {
+                self.close_burst();
+                if data {
+                    self.burst = Some((tick, tick));
+                }
+            }
+        }
+    }
+
+    /// PORTx write; a rising edge on the selected device's CS closes its window.
+    pub fn port_write(&mut self, tick: u64, addr: u16, old: u8, value: u8) {
+        if let Some(SpiWindow { cs: Some((cs_addr, bit)), .. }) = self.spi {
+            if cs_addr == addr && old & (1 << bit) == 0 && value & (1 << bit) != 0 {
+                self.close_spi(tick);
+            }
+        }
+    }
+
+    fn close_spi(&mut self, tick: u64) {
+        if let Some(w) = self.spi.take() {
+            self.push(Track::Spi(w.device), w.start, tick);
+        }
+        self.close_burst();
+    }
+
+    fn close_burst(&mut self) {
+        if let Some((start, last)) = self.burst.take() {
+            self.push(Track::DisplayData, start, last + SPI_BYTE_CYCLES);
+        }
+    }
+
+    /// Text chart of one frame, `width` columns per track, with per-track
+    /// counts and share of the frame.
+    pub fn render(&self, f: &FrameEvents, width: usize, clock_hz: u32) -> String {
+        let width = width.max(8);
+        let len = (f.end - f.start).max(1);
+        let ms = |c: u64| c as f64 * 1000.0 / clock_hz as f64;
+        let mut out = String::new();
+        let _ = writeln!(out, "Frame {}: {} cycles ({:.2} ms), {} spans",
+            f.frame, len, ms(len), f.spans.len());
+        let _ = writeln!(out, "{:14} |0 ms{:>w$}|", "", format!("{:.2} ms", ms(len)), w = width - 4);
+        for (track, count, cycles) in f.totals() {
+            let mut row = vec![' '; width];
+            for s in f.spans.iter().filter(|s| s.track == track) {
+                let a = ((s.start - f.start) * width as u64 / len) as usize;
+                let b = ((s.end - f.start) * width as u64).div_ceil(len) as usize;
+                for c in &mut row[a.min(width - 1)..b.clamp(a + 1, width)] {
+                    *c = '#';
+                }
+            }
+            let _ = writeln!(out, "{:14} |{}| {:4}x {:7} cyc {:5.1}%",
+                track.name(self.cpu), row.iter().collect::<String>(), count, cycles,
+                cycles as f64 * 100.0 / len as f64);
+        }
+        out
+    }
+
+    /// Chrome trace-event JSON of every kept frame (`chrome://tracing`,
+    /// Perfetto), one thread per track plus a frame track.
+    pub fn to_chrome_json(&self, clock_hz: u32) -> String {
+        let us = |t: u64| t as f64 * 1_000_000.0 / clock_hz as f64;
+        let mut tracks: Vec<Track> = Vec::new();
+        for s in self.frames.iter().flat_map(|f| &f.spans) {
+            if !tracks.contains(&s.track) {
+                tracks.push(s.track);
+            }
+        }
+        tracks.sort_by_key(|t| t.order());
+        let mut events = vec![
+            "{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 1, \"tid\": 0, \"args\": {\"name\": \"frame\"}}".to_string(),
+        ];
+        for (i, t) in tracks.iter().enumerate() {
+            events.push(format!("{{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 1, \"tid\": {}, \"args\": {{\"name\": \"{}\"}}}}",
+                i + 1, t.name(self.cpu)));
+        }
+        for f in &self.frames {
+            events.push(format!("{{\"name\": \"frame {}\", \"cat\": \"frame\", \"ph\": \"X\", \"ts\": {:.3}, \"dur\": {:.3}, \"pid\": 1, \"tid\": 0}}",
+                f.frame, us(f.start), us(f.end - f.start)));
+            for s in &f.spans {
+                let tid = tracks.iter().position(|t| *t == s.track).unwrap_or(0) + 1;
+                events.push(format!("{{\"name\": \"{}\", \"cat\": \"{}\", \"ph\": \"X\", \"ts\": {:.3}, \"dur\": {:.3}, \"pid\": 1, \"tid\": {}}}",
+                    s.track.name(self.cpu), s.track.category(), us(s.start), us(s.end - s.start), tid));
+            }
+        }
+        format!("{{\"displayTimeUnit\": \"ns\", \"traceEvents\": [\n  {}\n]}}\n", events.join(",\n  "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(dc_data: bool) -> SpiSelect {
+        SpiSelect { device: SpiDevice::Ssd1306, cs: Some((0x2B, 6)), dc_data: Some(dc_data) }
+    }
+
+    #[test]
+    fn test_spans() {
+        let mut t = FrameTimeline::new(CpuType::Atmega32u4);
+        t.start();
+        t.begin_frame(0, 0);
+        t.isr_enter(0x2E, 100);
+        t.isr_exit(180);
+        t.spi_byte(1000, &display(false));
+        for i in 0..4 {
+            t.spi_byte(1100 + i * 20, &display(true));
+        }
+        t.spi_byte(2000, &display(true));
+        t.port_write(2100, 0x2B, 0x00, 0x40);
+        t.sleep_enter(3000);
+        t.isr_enter(0x2E, 5000);
+        // Still in the ISR when the frame ends
+        t.end_frame(5050);
+        t.begin_frame(1, 5050);
+        t.isr_exit(5100);
+        t.end_frame(6000);
+
+        let f = t.recent(1).unwrap();
+        let spans: Vec<_> = f.spans.iter().map(|s| (s.track, s.start, s.end)).collect();
+        assert_eq!(spans, vec![
+            (Track::Interrupt(0x2E), 100, 180),
+            (Track::Spi(SpiDevice::Ssd1306), 1000, 2100),
+            (Track::DisplayData, 1100, 1176),
+            (Track::DisplayData, 2000, 2016),
+            (Track::Sleep, 3000, 5000),
+            (Track::Interrupt(0x2E), 5000, 5050),
+        ]);
+        assert_eq!(t.recent(0).unwrap().spans[0], Span { track: Track::Interrupt(0x2E), start: 5050, end: 5100 });
+        assert_eq!(f.totals()[0], (Track::Interrupt(0x2E), 2, 130));
+
+        let text = t.render(f, 50, 16_000_000);
+        assert!(text.contains("TIMER0_OVF"), "{}", text);
+        assert!(text.contains("display data"));
+        let json = t.to_chrome_json(16_000_000);
+        assert!(json.contains("\"name\": \"spi ssd1306\", \"cat\": \"spi\", \"ph\": \"X\", \"ts\": 62.500"), "{}", json);
+    }
+}
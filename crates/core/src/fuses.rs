@@ -0,0 +1,102 @@
+//! Emulated fuse bytes and the startup behavior they select.
+//!
+//! | Fuse | Bit | Effect when programmed (0) |
+//! |------|-----|----------------------------|
+//! | CKDIV8 | low 7 | CLKPR starts at /8: the CPU and timers run at 2 MHz until the program changes it |
+//! | BOOTRST | high 0 | Reset starts at the boot section instead of address 0 |
+//! | BOOTSZ1:0 | high 2:1 | Boot section size, which sets where BOOTRST starts |
+//!
+//! The defaults are the factory bootloader settings of each board except
+//! BOOTRST, which is left unprogrammed because no bootloader is loaded:
+//! games start at 0 as they always have. Fuses come from the ELF `.fuse`
+//! section, `--fuses`, or [`Arduboy::set_fuses`](crate::Arduboy::set_fuses),
+//! and LPM reads them back (see [`Memory::fuse_bits`](crate::memory::Memory::fuse_bits)).
+
+use crate::CpuType;
+use std::fmt;
+
+/// Fuse bytes as written by avr-libc's `FUSES` macro (low, high, extended).
+///
+/// Bits are active-low: 0 means programmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fuses {
+    pub low: u8,
+    pub high: u8,
+    pub extended: Option<u8>,
+}
+
+impl Fuses {
+    /// Power-on fuses for `cpu`: Arduboy (Caterina), Gamebuino Classic and
+    /// Arduino Mega settings with BOOTRST unprogrammed.
+    pub fn for_cpu(cpu: CpuType) -> Fuses {
+        match cpu {
+            CpuType::Atmega32u4 => Fuses { low: 0xFF, high: 0xD1, extended: Some(0xCB) },
+            CpuType::Atmega328p => Fuses { low: 0xFF, high: 0xDB, extended: Some(0xFD) },
+            CpuType::Atmega2560 => Fuses { low: 0xFF, high: 0xD9, extended: Some(0xFD) },
+        }
+    }
+
+    /// Parse `LOW:HIGH[:EXT]` in hex, e.g. `FF:D0:CB`.
+    pub fn parse(s: &str) -> Result<Fuses, String> {
+        let bytes = s.split(':')
+            .map(|b| u8::from_str_radix(b.trim().trim_start_matches("0x"), 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("fuses: expected LOW:HIGH[:EXT] in hex, got \"{}\"", s))?;
+        match bytes[..] {
+            [low, high] => Ok(Fuses { low, high, extended: None }),
+            [low, high, ext] => Ok(Fuses { low, high, extended: Some(ext) }),
+            _ => Err(format!("fuses: expected LOW:HIGH[:EXT] in hex, got \"{}\"", s)),
+        }
+    }
+
+    /// CKDIV8 (low bit 7): system clock divided by 8 at reset.
+    pub fn ckdiv8(&self) -> bool {
+        self.low & 0x80 == 0
+    }
+
+    /// BOOTRST (high bit 0): reset jumps to the boot section instead of 0.
+    pub fn bootrst(&self) -> bool {
+        self.high & 0x01 == 0
+    }
+
+    /// Boot section size in words from BOOTSZ1:0 (high bits 2:1), 0b00
+    /// being the largest: 256–2048 words on the 32u4, half that on the
+    /// 328P and twice that on the 2560.
+    pub fn boot_size_words(&self, cpu: CpuType) -> u32 {
+        let words = 2048 >> ((self.high >> 1) & 3);
+        match cpu {
+            CpuType::Atmega32u4 => words,
+            CpuType::Atmega328p => words / 2,
+            CpuType::Atmega2560 => words * 2,
+        }
+    }
+}
+
+impl fmt::Display for Fuses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "L:{:02X} H:{:02X}", self.low, self.high)?;
+        if let Some(e) = self.extended {
+            write!(f, " E:{:02X}", e)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_decode() {
+        let f = Fuses::parse("7F:D8:CB").unwrap();
+        assert_eq!(f, Fuses { low: 0x7F, high: 0xD8, extended: Some(0xCB) });
+        assert!(f.ckdiv8());
+        assert!(f.bootrst());
+        assert_eq!(f.boot_size_words(CpuType::Atmega32u4), 2048);
+        assert_eq!(f.boot_size_words(CpuType::Atmega2560), 4096);
+        assert_eq!(Fuses::parse("ff:de").unwrap().boot_size_words(CpuType::Atmega328p), 128);
+        assert!(Fuses::parse("FF").is_err());
+        assert!(Fuses::parse("FF:XX").is_err());
+        assert!(!Fuses::for_cpu(CpuType::Atmega32u4).bootrst());
+    }
+}
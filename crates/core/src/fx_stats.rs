@@ -0,0 +1,231 @@
+//! FX flash streaming statistics for the profiler report.
+//!
+//! FX games stream graphics, maps and text from the external flash chip
+//! over SPI. Each byte costs at least 16 CPU cycles at the maximum SPI clock,
+//! and every new address costs a command and three address bytes. So data
+//! read in small scattered pieces every frame can be cheaper in PROGMEM,
+//! while data never read at all is dead weight. [`FxStats`] is kept by the
+//! [`Profiler`](crate::profiler::Profiler) while it runs and adds these
+//! figures to its report:
+//!
+//! | Metric | Meaning |
+//! |--------|---------|
+//! | Bytes per frame | Mean and worst frame, with the minimum SPI share of the frame |
+//! | Streams | Reads started with one address; mean and longest burst |
+//! | Access pattern | Streams that continue the last one (sequential), stay in its 4 KB sector, or seek elsewhere (random) |
+//! | Hot sectors | Most-read sectors, the frames they are read in and the distinct bytes read |
+//! | Never read | Sectors holding data that nothing read during the session |
+//!
+//! A hot sector read in most frames whose distinct bytes fit in
+//! [`PROGMEM_FOOTPRINT`] is flagged as a PROGMEM candidate.
+
+use crate::peripherals::fx_flash::{FxFlash, SECTOR_SIZE};
+use crate::FRAME_CYCLES;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Distinct bytes of a hot sector small enough to suggest PROGMEM
+pub const PROGMEM_FOOTPRINT: u32 = 256;
+/// CPU cycles per SPI byte at the fastest clock (f/2)
+const SPI_BYTE_CYCLES: u64 = 16;
+/// Hot sectors listed in the report
+const HOT_SECTORS: usize = 8;
+/// Unread ranges listed in the report
+const UNREAD_RANGES: usize = 5;
+
+/// Reads from one 4 KB sector.
+#[derive(Debug, Clone)]
+struct SectorUse {
+    bytes: u64,
+    /// Profiled frames it was read in
+    frames: u32,
+    last_frame: u32,
+    /// Bitmap of the bytes read
+    seen: Vec<u64>,
+}
+
+impl SectorUse {
+    fn footprint(&self) -> u32 {
+        self.seen.iter().map(|w| w.count_ones()).sum()
+    }
+}
+
+/// FX read statistics since the profiler started.
+#[derive(Debug, Clone, Default)]
+pub struct FxStats {
+    /// Frames ended while profiling
+    pub frames: u32,
+    pub total_bytes: u64,
+    frame_bytes: u32,
+    /// Most bytes in one frame, and that frame's number
+    pub worst_frame: (u32, u32),
+    /// Frames without any FX read
+    pub idle_frames: u32,
+    pub streams: u64,
+    pub sequential: u64,
+    pub same_sector: u64,
+    pub random: u64,
+    pub longest_burst: u32,
+    burst: u32,
+    in_stream: bool,
+    /// Address after the last byte read
+    next_addr: Option<u32>,
+    sectors: HashMap<u32, SectorUse>,
+}
+
+impl FxStats {
+    /// Forget everything (profiler restart).
+    pub fn clear(&mut self) {
+        *self = FxStats::default();
+    }
+
+    /// A data byte was read from flash address `addr`.
+    pub fn read(&mut self, addr: u32) {
+        if !self.in_stream {
+            self.in_stream = true;
+            self.burst = 0;
+            self.streams += 1;
+            match self.next_addr {
+                Some(n) if n == addr => self.sequential += 1,
+                Some(n) if n as usize / SECTOR_SIZE == addr as usize / SECTOR_SIZE => self.same_sector += 1,
+                _ => self.random += 1,
+            }
+        }
+        self.burst += 1;
+        self.longest_burst = self.longest_burst.max(self.burst);
+        self.frame_bytes += 1;
+        self.total_bytes += 1;
+        self.next_addr = Some(addr.wrapping_add(1));
+
+        let frame = self.frames;
+        let offset = addr as usize % SECTOR_SIZE;
+        let s = self.sectors.entry(addr / SECTOR_SIZE as u32).or_insert_with(|| SectorUse {
+            bytes: 0,
+            frames: 0,
+            last_frame: u32::MAX,
+            seen: vec![0; SECTOR_SIZE / 64],
+        });
+        s.bytes += 1;
+        if s.last_frame != frame {
+            s.last_frame = frame;
+            s.frames += 1;
+        }
+        s.seen[offset / 64] |= 1 << (offset % 64);
+    }
+
+    /// Chip deselected: the next read starts a new stream.
+    pub fn deselect(&mut self) {
+        self.in_stream = false;
+    }
+
+    /// End of emulator frame `frame`.
+    pub fn end_frame(&mut self, frame: u32) {
+        self.frames += 1;
+        if self.frame_bytes == 0 {
+            self.idle_frames += 1;
+        }
+        if self.frame_bytes > self.worst_frame.0 {
+            self.worst_frame = (self.frame_bytes, frame);
+        }
+        self.frame_bytes = 0;
+    }
+
+    /// Report section; `fx` is scanned for sectors holding data.
+    pub fn report(&self, fx: &FxFlash) -> String {
+        let mut s = String::from("\n--- FX Flash Streaming ---\n");
+        if self.total_bytes == 0 {
+            s.push_str("No FX reads while profiling.\n");
+            return s;
+        }
+        let frames = self.frames.max(1) as u64;
+        let per_frame = self.total_bytes as f64 / frames as f64;
+        let spi_share = per_frame * SPI_BYTE_CYCLES as f64 / FRAME_CYCLES as f64 * 100.0;
+        let pct = |n: u64| n as f64 / self.streams.max(1) as f64 * 100.0;
+        let _ = writeln!(s, "Bytes read: {} over {} frames ({:.1}/frame, ≥{:.1}% of frame time on SPI)",
+            self.total_bytes, self.frames, per_frame, spi_share);
+        let _ = writeln!(s, "Worst frame: {} bytes (frame {}); {} frames without reads",
+            self.worst_frame.0, self.worst_frame.1, self.idle_frames);
+        let _ = writeln!(s, "Streams: {} (mean {:.1} bytes, longest {})",
+            self.streams, self.total_bytes as f64 / self.streams.max(1) as f64, self.longest_burst);
+        let _ = writeln!(s, "  sequential {:.1}%  same sector {:.1}%  random seek {:.1}%",
+            pct(self.sequential), pct(self.same_sector), pct(self.random));
+
+        let mut hot: Vec<_> = self.sectors.iter().collect();
+        hot.sort_by_key(|(&sector, u)| (std::cmp::Reverse(u.bytes), sector));
+        let _ = writeln!(s, "Hot sectors:\n{:>10}  {:>10}  {:>13}  {:>9}", "Sector", "Bytes", "Frames", "Distinct");
+        for (&sector, u) in hot.iter().take(HOT_SECTORS) {
+            let footprint = u.footprint();
+            let candidate = u.frames as u64 * 2 >= frames && footprint <= PROGMEM_FOOTPRINT;
+            let _ = writeln!(s, "  0x{:06X}  {:>10}  {:>6}/{:<6}  {:>7} B{}",
+                sector as usize * SECTOR_SIZE, u.bytes, u.frames, self.frames, footprint,
+                if candidate { "  → PROGMEM candidate" } else { "" });
+        }
+
+        // Sectors with data (not erased or fill) that nothing read
+        let end = fx.save_offset.min(fx.data.len()) / SECTOR_SIZE;
+        let with_data: Vec<usize> = (0..end)
+            .filter(|&sec| fx.data[sec * SECTOR_SIZE..(sec + 1) * SECTOR_SIZE].iter().any(|&b| b != 0xFF && b != fx.fill))
+            .collect();
+        let unread: Vec<usize> = with_data.iter().copied()
+            .filter(|&sec| !self.sectors.contains_key(&(sec as u32)))
+            .collect();
+        if !unread.is_empty() {
+            let _ = writeln!(s, "Never read: {} of {} sectors with data ({} KB)",
+                unread.len(), with_data.len(), unread.len() * SECTOR_SIZE / 1024);
+            let mut ranges: Vec<(usize, usize)> = Vec::new();
+            for sec in unread {
+                match ranges.last_mut() {
+                    Some(r) if r.1 == sec => r.1 = sec + 1,
+                    _ => ranges.push((sec, sec + 1)),
+                }
+            }
+            for (a, b) in ranges.iter().take(UNREAD_RANGES) {
+                let _ = writeln!(s, "  0x{:06X}-0x{:06X}", a * SECTOR_SIZE, b * SECTOR_SIZE);
+            }
+            if ranges.len() > UNREAD_RANGES {
+                let _ = writeln!(s, "  ... {} more ranges", ranges.len() - UNREAD_RANGES);
+            }
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_and_report() {
+        let mut st = FxStats::default();
+        // Frame 0: 64 bytes at 0x1000, then continue at 0x1040 after a deselect
+        for a in 0x1000..0x1040 {
+            st.read(a);
+        }
+        st.deselect();
+        for a in 0x1040..0x1050 {
+            st.read(a);
+        }
+        st.deselect();
+        st.end_frame(10);
+        // Frame 1: same sector, then a seek far away
+        st.read(0x1800);
+        st.deselect();
+        st.read(0x20000);
+        st.deselect();
+        st.end_frame(11);
+        st.end_frame(12);
+
+        assert_eq!((st.streams, st.sequential, st.same_sector, st.random), (4, 1, 1, 2));
+        assert_eq!(st.longest_burst, 64);
+        assert_eq!(st.worst_frame, (80, 10));
+        assert_eq!(st.idle_frames, 1);
+
+        let mut fx = FxFlash::new();
+        fx.load_data(&[0x55; 3 * SECTOR_SIZE]);
+        let r = st.report(&fx);
+        assert!(r.contains("Bytes read: 82 over 3 frames"), "{}", r);
+        assert!(r.contains("0x001000          81       2/3            81 B  → PROGMEM candidate"), "{}", r);
+        assert!(r.contains("Never read: 2 of 3 sectors with data (8 KB)"), "{}", r);
+        assert!(r.contains("0x000000-0x001000\n  0x002000-0x003000"), "{}", r);
+    }
+}
@@ -0,0 +1,155 @@
+//! Game files as the frontends open them: `.arduboy`, `.elf` or `.hex`.
+//!
+//! [`GameFile::load`] picks the format from the file name:
+//!
+//! | Extension | Program | FX data | Metadata |
+//! |-----------|---------|---------|----------|
+//! | `.arduboy` | HEX member | `flashdata` / `*-fx.bin` member, save from `flashsave` | title, author, rotation |
+//! | `.elf` | Loadable segments, with symbols | `--fx` file, else `<game>.bin` or `<game>-fx.bin` beside it | |
+//! | anything else | Intel HEX text | as for `.elf` | |
+//!
+//! [`GameFile::cpu`] then chooses the CPU (board profile, explicit choice,
+//! or [`detect_cpu`] on the HEX program) and [`GameFile::start`] builds an
+//! emulator for it with the program, FX data and rotation loaded
+//! ([`GameFile::load_into`] does the loading for an existing one).
+
+use crate::cpu_detect::{detect_cpu, CpuDetection};
+use crate::elf::ElfFile;
+use crate::error::{ArchiveError, Error};
+use crate::rotation::Rotation;
+use crate::{Arduboy, BoardProfile, CpuType, FLASH_SIZE};
+use std::path::Path;
+
+/// A game read from disk, not yet loaded into an emulator.
+#[derive(Debug, Clone, Default)]
+pub struct GameFile {
+    /// Path the game was loaded from
+    pub path: String,
+    /// Title from the package metadata (empty for HEX and ELF files)
+    pub title: String,
+    pub author: String,
+    /// Intel HEX program (empty for ELF files)
+    pub hex: String,
+    /// Raw ELF file
+    pub elf: Option<Vec<u8>>,
+    pub fx_data: Option<Vec<u8>>,
+    /// FX save area bundled with the package
+    pub fx_save: Option<Vec<u8>>,
+    pub rotation: Rotation,
+}
+
+impl GameFile {
+    /// Read `path`; `fx_path` overrides the FX data found for it.
+    pub fn load(path: &str, fx_path: Option<&str>) -> Result<Self, Error> {
+        let lower = path.to_lowercase();
+        let data = std::fs::read(path).map_err(|source| Error::Io { op: "Read", source })?;
+        let mut game = GameFile { path: path.to_string(), ..Default::default() };
+        if lower.ends_with(".arduboy") {
+            let ab = crate::arduboy_file::parse_arduboy(&data)?;
+            game.hex = ab.hex.ok_or(Error::Archive(ArchiveError::NoHex))?;
+            game.title = ab.title;
+            game.author = ab.author;
+            game.fx_data = ab.fx_data;
+            game.fx_save = ab.fx_save;
+            game.rotation = ab.rotation;
+        } else {
+            if lower.ends_with(".elf") {
+                game.elf = Some(data);
+            } else {
+                game.hex = String::from_utf8_lossy(&data).into_owned();
+            }
+            game.fx_data = find_fx(path);
+        }
+        if let Some(p) = fx_path {
+            game.fx_data = Some(std::fs::read(p).map_err(|source| Error::Io { op: "Read FX data", source })?);
+        }
+        Ok(game)
+    }
+
+    /// CPU detected from the HEX program; `None` for ELF files and HEX
+    /// that does not parse.
+    pub fn detect_cpu(&self) -> Option<CpuDetection> {
+        let mut flash = vec![0u8; FLASH_SIZE];
+        crate::hex::parse_hex(&self.hex, &mut flash).ok()?;
+        Some(detect_cpu(&flash))
+    }
+
+    /// CPU to run on: the board profile's, else `cpu`, else detected
+    /// (ATmega32u4 when detection is not possible). The detection is
+    /// returned when it decided, so the frontend can show a weak guess.
+    pub fn cpu(&self, board: Option<&BoardProfile>, cpu: Option<CpuType>) -> (CpuType, Option<CpuDetection>) {
+        if let Some(c) = board.map(|b| b.cpu).or(cpu) {
+            return (c, None);
+        }
+        match self.detect_cpu() {
+            Some(d) => (d.cpu, Some(d)),
+            None => (CpuType::Atmega32u4, None),
+        }
+    }
+
+    /// Load the program into flash; the parsed ELF file for ELF games.
+    pub fn load_program(&self, arduboy: &mut Arduboy) -> Result<Option<ElfFile>, Error> {
+        match &self.elf {
+            Some(data) => arduboy.load_elf(data).map(Some),
+            None => arduboy.load_hex(&self.hex).map(|_| None),
+        }
+    }
+
+    /// Load the program, FX data and rotation. `fx_save` replaces the
+    /// bundled save area (e.g. an `.fxsave` from an earlier session).
+    pub fn load_into(&self, arduboy: &mut Arduboy, fx_save: Option<&[u8]>) -> Result<Option<ElfFile>, Error> {
+        let elf = self.load_program(arduboy)?;
+        if let Some(fx) = &self.fx_data {
+            arduboy.load_fx_layout(fx, fx_save.or(self.fx_save.as_deref()));
+        }
+        arduboy.rotation = self.rotation;
+        Ok(elf)
+    }
+
+    /// New emulator on `board`, or a bare `cpu` without one, with the game
+    /// loaded; the parsed ELF file for ELF games.
+    pub fn start(&self, board: Option<BoardProfile>, cpu: CpuType) -> Result<(Arduboy, Option<ElfFile>), Error> {
+        let mut arduboy = match board {
+            Some(b) => Arduboy::with_board(b),
+            None => Arduboy::new_with_cpu(cpu),
+        };
+        let elf = self.load_into(&mut arduboy, None)?;
+        Ok((arduboy, elf))
+    }
+}
+
+/// FX data beside a HEX or ELF file: `<game>.bin`, else `<game>-fx.bin`.
+pub fn find_fx(path: &str) -> Option<Vec<u8>> {
+    let p = Path::new(path);
+    let stem = p.file_stem()?.to_str()?;
+    let dir = p.parent().unwrap_or(Path::new("."));
+    [format!("{}.bin", stem), format!("{}-fx.bin", stem)].iter()
+        .map(|name| dir.join(name))
+        .find(|f| f.exists())
+        .and_then(|f| std::fs::read(f).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_hex_with_fx_beside_it() {
+        let dir = std::env::temp_dir().join(format!("arduboy-game-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hex = dir.join("game.hex");
+        // rjmp .-2
+        std::fs::write(&hex, ":02000000FFCF30\n:00000001FF\n").unwrap();
+        std::fs::write(dir.join("game-fx.bin"), [1, 2, 3]).unwrap();
+        let game = GameFile::load(hex.to_str().unwrap(), None).unwrap();
+        assert_eq!(game.fx_data.as_deref(), Some(&[1u8, 2, 3][..]));
+        assert!(game.detect_cpu().is_some());
+        assert_eq!(game.cpu(None, Some(CpuType::Atmega328p)), (CpuType::Atmega328p, None));
+
+        let mut ard = Arduboy::new();
+        assert!(game.load_into(&mut ard, None).unwrap().is_none());
+        assert_eq!(&ard.mem.flash[..2], &[0xFF, 0xCF]);
+        assert!(matches!(GameFile::load(dir.join("none.hex").to_str().unwrap(), None), Err(Error::Io { .. })));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
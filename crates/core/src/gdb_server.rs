@@ -21,10 +21,59 @@
 //! ```
 //!
 //! The AVR register layout for GDB: R0-R31 (32 bytes), SREG (1), SP (2), PC (4).
+//!
+//! ## Address spaces
+//!
+//! avr-gdb puts the separate AVR memories in one address space, as the ELF
+//! file does ([`GdbAddr::map`]):
+//!
+//! | GDB address | Memory |
+//! |-------------|--------|
+//! | `0x000000`-`0x7FFFFF` | Flash (byte addresses) |
+//! | `0x800000`-`0x80FFFF` | Data space: registers, I/O, SRAM |
+//! | `0x810000`-`0x81FFFF` | EEPROM |
+//!
+//! so `x/4xb &var`, `print var` and `watch var` reach the right bytes. Data
+//! reads and writes go through the peripheral hooks like the CPU's (reading
+//! SP or a `PINx` register gives its live value, writing `SPL` moves the
+//! stack pointer), without firing watchpoints. Flash writes (`load`)
+//! replace the program, and watchpoints on data addresses stop `continue`
+//! with a `watch`/`rwatch`/`awatch` stop reply.
 
 use std::io::{Read, Write};
 use std::net::{TcpListener, TcpStream};
 
+use crate::debugger::{WatchHit, WatchKind};
+use crate::Arduboy;
+
+/// Start of the data space in GDB addresses
+pub const DATA_OFFSET: u32 = 0x800000;
+/// Start of EEPROM in GDB addresses
+pub const EEPROM_OFFSET: u32 = 0x810000;
+
+/// Memory a GDB address refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdbAddr {
+    /// Flash byte address
+    Flash(u32),
+    /// Data-space address
+    Data(u16),
+    /// EEPROM address
+    Eeprom(u16),
+}
+
+impl GdbAddr {
+    /// Map a GDB address; None above the EEPROM window.
+    pub fn map(addr: u32) -> Option<GdbAddr> {
+        match addr {
+            a if a < DATA_OFFSET => Some(GdbAddr::Flash(a)),
+            a if a < EEPROM_OFFSET => Some(GdbAddr::Data((a - DATA_OFFSET) as u16)),
+            a if a < EEPROM_OFFSET + 0x10000 => Some(GdbAddr::Eeprom((a - EEPROM_OFFSET) as u16)),
+            _ => None,
+        }
+    }
+}
+
 /// GDB server state.
 pub struct GdbServer {
     listener: TcpListener,
@@ -37,6 +86,8 @@ pub struct GdbSession {
     buf: Vec<u8>,
     /// Breakpoints set by GDB (byte addresses)
     pub breakpoints: Vec<u32>,
+    /// Watchpoints set by GDB: data-space address and kind, one per byte
+    watchpoints: Vec<(u16, WatchKind)>,
     /// Whether the session has been detached/killed
     pub done: bool,
 }
@@ -74,6 +125,7 @@ impl GdbServer {
             stream,
             buf: Vec::with_capacity(4096),
             breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
             done: false,
         })
     }
@@ -83,18 +135,7 @@ impl GdbServer {
 
 impl GdbSession {
     /// Read and process one GDB packet. Returns the action the emulator should take.
-    ///
-    /// `regs` = R0-R31 (32 bytes), `sreg`, `sp`, `pc` (word address)
-    /// `flash`, `data` = memory arrays
-    pub fn process_packet(
-        &mut self,
-        regs: &[u8; 32],
-        sreg: u8,
-        sp: u16,
-        pc: u16,
-        flash: &[u8],
-        data: &mut [u8],
-    ) -> std::io::Result<GdbAction> {
+    pub fn process_packet(&mut self, ard: &mut Arduboy) -> std::io::Result<GdbAction> {
         let packet = match self.read_packet() {
             Ok(p) => p,
             Err(e) => {
@@ -110,6 +151,8 @@ impl GdbSession {
 
         let cmd = packet[0] as char;
         let args = &packet[1..];
+        let regs = ard.gdb_regs();
+        let (sreg, sp, pc) = (ard.cpu.sreg, ard.cpu.sp, ard.cpu.pc);
 
         match cmd {
             // Halt reason
@@ -128,7 +171,7 @@ impl GdbSession {
                 // SP: little-endian 2 bytes
                 buf.push_str(&format!("{:02x}{:02x}", sp & 0xFF, (sp >> 8) & 0xFF));
                 // PC: byte address, little-endian 4 bytes
-                let pc_byte = (pc as u32) * 2;
+                let pc_byte = pc * 2;
                 buf.push_str(&format!("{:02x}{:02x}{:02x}{:02x}",
                     pc_byte & 0xFF, (pc_byte >> 8) & 0xFF,
                     (pc_byte >> 16) & 0xFF, (pc_byte >> 24) & 0xFF));
@@ -151,7 +194,7 @@ impl GdbSession {
                     32 => format!("{:02x}", sreg),
                     33 => format!("{:02x}{:02x}", sp & 0xFF, (sp >> 8) & 0xFF),
                     34 => {
-                        let pc_byte = (pc as u32) * 2;
+                        let pc_byte = pc * 2;
                         format!("{:02x}{:02x}{:02x}{:02x}",
                             pc_byte & 0xFF, (pc_byte >> 8) & 0xFF,
                             (pc_byte >> 16) & 0xFF, (pc_byte >> 24) & 0xFF)
@@ -166,29 +209,21 @@ impl GdbSession {
             'm' => {
                 let parts: Vec<&[u8]> = args.splitn(2, |&b| b == b',').collect();
                 if parts.len() == 2 {
-                    let addr = parse_hex_u32(parts[0]).unwrap_or(0) as usize;
-                    let len = parse_hex_u32(parts[1]).unwrap_or(0) as usize;
-                    let mut buf = String::with_capacity(len * 2);
-
-                    // AVR GDB uses byte addresses:
-                    // 0x000000-0x7FFFFF = flash (program space)
-                    // 0x800000-0x80FFFF = data space (SRAM)
-                    // 0x810000-0x81FFFF = EEPROM
+                    let addr = parse_hex_u32(parts[0]).unwrap_or(0);
+                    let len = parse_hex_u32(parts[1]).unwrap_or(0);
+                    let mut buf = String::with_capacity(len as usize * 2);
                     for i in 0..len {
-                        let a = addr + i;
-                        let byte = if a < 0x800000 {
-                            // Flash
-                            if a < flash.len() { flash[a] } else { 0xFF }
-                        } else if a < 0x810000 {
-                            // Data space
-                            let da = a - 0x800000;
-                            if da < data.len() { data[da] } else { 0 }
-                        } else {
-                            0xFF
-                        };
-                        buf.push_str(&format!("{:02x}", byte));
+                        match addr.checked_add(i).and_then(|a| read_byte(ard, a)) {
+                            Some(b) => buf.push_str(&format!("{:02x}", b)),
+                            None => break,
+                        }
+                    }
+                    // Stop at the first unmapped byte; nothing readable is an error
+                    if buf.is_empty() && len > 0 {
+                        self.send_packet(b"E01")?;
+                    } else {
+                        self.send_packet(buf.as_bytes())?;
                     }
-                    self.send_packet(buf.as_bytes())?;
                 } else {
                     self.send_packet(b"E01")?;
                 }
@@ -202,17 +237,13 @@ impl GdbSession {
                     let hex_data = &args[colon + 1..];
                     let parts: Vec<&[u8]> = header.splitn(2, |&b| b == b',').collect();
                     if parts.len() == 2 {
-                        let addr = parse_hex_u32(parts[0]).unwrap_or(0) as usize;
-                        let _len = parse_hex_u32(parts[1]).unwrap_or(0) as usize;
+                        let addr = parse_hex_u32(parts[0]).unwrap_or(0);
                         let bytes = parse_hex_bytes(hex_data);
-                        for (i, &b) in bytes.iter().enumerate() {
-                            let a = addr + i;
-                            if a >= 0x800000 && a < 0x810000 {
-                                let da = a - 0x800000;
-                                if da < data.len() { data[da] = b; }
-                            }
+                        let ok = write_memory(ard, addr, &bytes);
+                        if ok && !bytes.is_empty() && addr < DATA_OFFSET {
+                            ard.mem.invalidate_decoded();
                         }
-                        self.send_packet(b"OK")?;
+                        self.send_packet(if ok { b"OK" } else { b"E01" })?;
                     } else {
                         self.send_packet(b"E01")?;
                     }
@@ -247,9 +278,21 @@ impl GdbSession {
                             }
                             self.send_packet(b"OK")?;
                         }
-                        2 | 3 | 4 => {
-                            // Write/read/access watchpoint — accept but track via breakpoints
-                            self.send_packet(b"OK")?;
+                        2..=4 => {
+                            // Write/read/access watchpoint on data: one per byte,
+                            // up to 256 bytes
+                            let len = parts.get(2).and_then(|k| parse_hex_u32(k)).unwrap_or(1).max(1);
+                            match (GdbAddr::map(addr), watch_kind(bp_type)) {
+                                (Some(GdbAddr::Data(a)), Some(kind)) if len <= 0x100 => {
+                                    for i in 0..len as u16 {
+                                        let a = a.wrapping_add(i);
+                                        ard.debugger.add_watchpoint(a, kind);
+                                        self.watchpoints.push((a, kind));
+                                    }
+                                    self.send_packet(b"OK")?;
+                                }
+                                _ => self.send_packet(b"E01")?,
+                            }
                         }
                         _ => { self.send_packet(b"")?; }
                     }
@@ -271,7 +314,21 @@ impl GdbSession {
                             self.breakpoints.retain(|&a| a != word_addr);
                             self.send_packet(b"OK")?;
                         }
-                        2 | 3 | 4 => {
+                        2..=4 => {
+                            let len = parts.get(2).and_then(|k| parse_hex_u32(k)).unwrap_or(1).max(1);
+                            if let (Some(GdbAddr::Data(a)), Some(kind)) = (GdbAddr::map(addr), watch_kind(bp_type)) {
+                                for i in 0..len.min(0x100) as u16 {
+                                    let a = a.wrapping_add(i);
+                                    if let Some(pos) = self.watchpoints.iter().position(|&w| w == (a, kind)) {
+                                        self.watchpoints.remove(pos);
+                                    }
+                                    if let Some(idx) = ard.debugger.watchpoints.iter()
+                                        .position(|w| w.addr == a && w.kind == kind)
+                                    {
+                                        ard.debugger.remove_watchpoint(idx);
+                                    }
+                                }
+                            }
                             self.send_packet(b"OK")?;
                         }
                         _ => { self.send_packet(b"")?; }
@@ -344,6 +401,27 @@ impl GdbSession {
         self.send_packet(b"S05")
     }
 
+    /// Send a stop reply naming the watchpoint that stopped execution;
+    /// a plain SIGTRAP when GDB did not set it.
+    pub fn send_watch_reply(&mut self, hit: &WatchHit) -> std::io::Result<()> {
+        let kind = self.watchpoints.iter()
+            .filter(|w| w.0 == hit.addr)
+            .map(|w| w.1)
+            .find(|&k| k == WatchKind::ReadWrite || k == hit.access);
+        match kind {
+            Some(k) => {
+                let name = match k {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::ReadWrite => "awatch",
+                };
+                let reply = format!("T05{}:{:x};", name, DATA_OFFSET + hit.addr as u32);
+                self.send_packet(reply.as_bytes())
+            }
+            None => self.send_stop_reply(),
+        }
+    }
+
     /// Read a GDB packet from the stream.
     /// Format: $<data>#<checksum> or Ctrl+C (0x03)
     fn read_packet(&mut self) -> std::io::Result<Vec<u8>> {
@@ -415,6 +493,63 @@ impl GdbSession {
 
 const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
 
+/// Byte at a GDB address; None when unmapped.
+fn read_byte(ard: &mut Arduboy, addr: u32) -> Option<u8> {
+    match GdbAddr::map(addr)? {
+        GdbAddr::Flash(a) => Some(ard.mem.flash.get(a as usize).copied().unwrap_or(0xFF)),
+        GdbAddr::Data(a) => ((a as usize) < ard.mem.data.len()).then(|| ard.peek_data(a)),
+        GdbAddr::Eeprom(a) => ard.mem.eeprom.get(a as usize).copied(),
+    }
+}
+
+/// Store a byte at a GDB address; false when unmapped.
+fn write_byte(ard: &mut Arduboy, addr: u32, value: u8) -> bool {
+    match GdbAddr::map(addr) {
+        Some(GdbAddr::Flash(a)) => match ard.mem.flash.get_mut(a as usize) {
+            Some(b) => { *b = value; true }
+            None => false,
+        },
+        Some(GdbAddr::Data(a)) if (a as usize) < ard.mem.data.len() => {
+            ard.poke_data(a, value);
+            true
+        }
+        Some(GdbAddr::Eeprom(a)) if (a as usize) < ard.mem.eeprom.len() => {
+            ard.mem.eeprom[a as usize] = value;
+            ard.eeprom_dirty = true;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Write `bytes` from `addr` on, all or nothing: false, with nothing
+/// written, when any of them falls outside the CPU's memory.
+fn write_memory(ard: &mut Arduboy, addr: u32, bytes: &[u8]) -> bool {
+    let mapped = |a: Option<u32>| match a.and_then(GdbAddr::map) {
+        Some(GdbAddr::Flash(a)) => (a as usize) < ard.mem.flash.len(),
+        Some(GdbAddr::Data(a)) => (a as usize) < ard.mem.data.len(),
+        Some(GdbAddr::Eeprom(a)) => (a as usize) < ard.mem.eeprom.len(),
+        None => false,
+    };
+    if !(0..bytes.len() as u32).all(|i| mapped(addr.checked_add(i))) {
+        return false;
+    }
+    for (i, &b) in bytes.iter().enumerate() {
+        write_byte(ard, addr + i as u32, b);
+    }
+    true
+}
+
+/// Watchpoint kind of a `Z2`/`Z3`/`Z4` packet.
+fn watch_kind(z_type: u32) -> Option<WatchKind> {
+    match z_type {
+        2 => Some(WatchKind::Write),
+        3 => Some(WatchKind::Read),
+        4 => Some(WatchKind::ReadWrite),
+        _ => None,
+    }
+}
+
 /// Parse a hex string (as bytes) into a u32.
 fn parse_hex_u32(s: &[u8]) -> Option<u32> {
     let mut val = 0u32;
@@ -469,4 +604,41 @@ mod tests {
     fn test_parse_hex_bytes() {
         assert_eq!(parse_hex_bytes(b"48656C6C6F"), vec![0x48, 0x65, 0x6C, 0x6C, 0x6F]);
     }
+
+    #[test]
+    fn test_address_spaces() {
+        assert_eq!(GdbAddr::map(0x1234), Some(GdbAddr::Flash(0x1234)));
+        assert_eq!(GdbAddr::map(0x800100), Some(GdbAddr::Data(0x100)));
+        assert_eq!(GdbAddr::map(0x810010), Some(GdbAddr::Eeprom(0x10)));
+        assert_eq!(GdbAddr::map(0x820000), None);
+
+        let mut ard = Arduboy::new();
+        ard.mem.flash[0x10] = 0xAB;
+        ard.mem.eeprom[3] = 0x5A;
+        ard.mem.data[0x150] = 0x77;
+        assert_eq!(read_byte(&mut ard, 0x10), Some(0xAB));
+        assert_eq!(read_byte(&mut ard, 0x800150), Some(0x77));
+        assert_eq!(read_byte(&mut ard, 0x810003), Some(0x5A));
+        assert_eq!(read_byte(&mut ard, 0x80FFFF), None);
+        // SP lives in the CPU: data-space reads and writes reach it
+        assert_eq!(read_byte(&mut ard, 0x80005D), Some(ard.cpu.sp as u8));
+        assert!(write_byte(&mut ard, 0x80005E, 0x09) && write_byte(&mut ard, 0x80005D, 0x80));
+        assert_eq!(ard.cpu.sp, 0x0980);
+        assert!(write_byte(&mut ard, 0x810004, 0x11));
+        assert!(ard.eeprom_dirty && ard.mem.eeprom[4] == 0x11);
+        assert!(!write_byte(&mut ard, 0x900000, 0));
+    }
+
+    #[test]
+    fn test_write_memory_all_or_nothing() {
+        let mut ard = Arduboy::new();
+        let end = EEPROM_OFFSET + ard.mem.eeprom.len() as u32;
+        // Last two bytes exist, the third does not: nothing is written
+        assert!(!write_memory(&mut ard, end - 2, &[1, 2, 3]));
+        assert_eq!(&ard.mem.eeprom[ard.mem.eeprom.len() - 2..], &[0xFF, 0xFF]);
+        assert!(!ard.eeprom_dirty);
+        assert!(!write_memory(&mut ard, u32::MAX, &[1, 2]));
+        assert!(write_memory(&mut ard, end - 2, &[1, 2]));
+        assert_eq!(&ard.mem.eeprom[ard.mem.eeprom.len() - 2..], &[1, 2]);
+    }
 }
@@ -2,6 +2,10 @@
 //!
 //! Produces GIF89a files with LZW-compressed frames. Optimized for
 //! the Arduboy's monochrome 128×64 display using a 2-color palette.
+//! [`GifEncoder::pcd8544`] records Gamebuino games at the PCD8544's native
+//! 84×48 in LCD colors.
+
+use crate::pcd8544;
 
 /// Builder for animated GIF files.
 pub struct GifEncoder {
@@ -21,6 +25,12 @@ impl GifEncoder {
     /// `delay_cs` is the delay between frames in 1/100ths of a second.
     /// For 60fps Arduboy, use 2 (20ms).
     pub fn new(width: u16, height: u16, delay_cs: u16) -> Self {
+        Self::with_colors(width, height, delay_cs, [0x00, 0x00, 0x00], [0xFF, 0xFF, 0xFF])
+    }
+
+    /// Like [`GifEncoder::new`], with custom colors for unlit (index 0) and
+    /// lit (index 1) pixels, e.g. an LCD theme.
+    pub fn with_colors(width: u16, height: u16, delay_cs: u16, off: [u8; 3], on: [u8; 3]) -> Self {
         let mut data = Vec::with_capacity(65536);
 
         // GIF89a header
@@ -33,9 +43,9 @@ impl GifEncoder {
         data.push(0x00); // background color index
         data.push(0x00); // pixel aspect ratio
 
-        // Global Color Table (2 entries: black, white)
-        data.extend_from_slice(&[0x00, 0x00, 0x00]); // index 0: black
-        data.extend_from_slice(&[0xFF, 0xFF, 0xFF]); // index 1: white
+        // Global Color Table (2 entries: off, on)
+        data.extend_from_slice(&off); // index 0: black by default
+        data.extend_from_slice(&on);  // index 1: white by default
 
         // Netscape Application Extension (infinite loop)
         data.extend_from_slice(&[
@@ -52,6 +62,13 @@ impl GifEncoder {
         }
     }
 
+    /// An 84×48 encoder in PCD8544 LCD colors; add frames with
+    /// `add_frame_mono(&lcd.native_pixels())`.
+    pub fn pcd8544(delay_cs: u16) -> Self {
+        let [off, on] = pcd8544::PALETTE;
+        Self::with_colors(pcd8544::PCD_WIDTH as u16, pcd8544::PCD_HEIGHT as u16, delay_cs, off, on)
+    }
+
     /// Add a frame from a flat array of pixel indices (0=black, 1=white).
     ///
     /// `pixels` must have exactly `width * height` elements.
@@ -3,34 +3,41 @@
 //! Parses Intel HEX format strings (`:LLAAAATT[DD...]CC`) and loads the
 //! data into a flash memory buffer. Supports record types 00 (data),
 //! 01 (EOF), and 02 (extended segment address) for programs up to 1 MB.
+//! [`to_hex`] writes a flash image back out (up to 64 KB).
+
+use crate::error::{Error, HexError};
 
 /// Parse Intel HEX format string and load into flash memory.
 ///
 /// Returns the number of bytes loaded (highest address reached).
-pub fn parse_hex(hex: &str, flash: &mut [u8]) -> Result<usize, String> {
+pub fn parse_hex(hex: &str, flash: &mut [u8]) -> Result<usize, Error> {
     let mut max_addr = 0usize;
     let mut base_addr: u32 = 0;
 
-    for line in hex.lines() {
+    for (n, line) in hex.lines().enumerate() {
         let line = line.trim();
         if line.is_empty() { continue; }
         if !line.starts_with(':') {
             continue; // skip non-hex lines
         }
+        let err = |kind| Error::Hex { line: n + 1, kind };
 
-        let bytes = hex_line_to_bytes(&line[1..])?;
+        let bytes = hex_line_to_bytes(&line[1..]).map_err(err)?;
         if bytes.len() < 5 {
-            return Err("Line too short".into());
+            return Err(err(HexError::TooShort));
         }
 
         let byte_count = bytes[0] as usize;
+        if bytes.len() != byte_count + 5 {
+            return Err(err(HexError::Length { count: bytes[0], found: bytes.len() - 5 }));
+        }
         let addr = ((bytes[1] as u16) << 8) | bytes[2] as u16;
         let record_type = bytes[3];
 
         // Verify checksum
         let sum: u8 = bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b));
         if sum != 0 {
-            return Err(format!("Checksum error: sum={}", sum));
+            return Err(err(HexError::Checksum { sum }));
         }
 
         match record_type {
@@ -79,12 +86,12 @@ pub fn parse_hex(hex: &str, flash: &mut [u8]) -> Result<usize, String> {
 }
 
 /// Convert hex character pairs to bytes
-fn hex_line_to_bytes(hex_str: &str) -> Result<Vec<u8>, String> {
+fn hex_line_to_bytes(hex_str: &str) -> Result<Vec<u8>, HexError> {
     let mut bytes = Vec::with_capacity(hex_str.len() / 2);
     let chars: Vec<char> = hex_str.chars().collect();
 
     if chars.len() % 2 != 0 {
-        return Err("Odd number of hex characters".into());
+        return Err(HexError::OddLength);
     }
 
     for chunk in chars.chunks(2) {
@@ -96,13 +103,31 @@ fn hex_line_to_bytes(hex_str: &str) -> Result<Vec<u8>, String> {
     Ok(bytes)
 }
 
-fn hex_char(c: char) -> Result<u8, String> {
+fn hex_char(c: char) -> Result<u8, HexError> {
     match c {
         '0'..='9' => Ok(c as u8 - b'0'),
         'a'..='f' => Ok(c as u8 - b'a' + 10),
         'A'..='F' => Ok(c as u8 - b'A' + 10),
-        _ => Err(format!("Invalid hex character: {}", c)),
+        _ => Err(HexError::BadChar(c)),
+    }
+}
+
+/// Intel HEX image of a flash image of up to 64 KB, 16 bytes per record.
+pub fn to_hex(rom: &[u8]) -> String {
+    let mut s = String::new();
+    for (n, chunk) in rom.chunks(16).enumerate() {
+        let addr = (n * 16) as u16;
+        let mut rec = vec![chunk.len() as u8, (addr >> 8) as u8, addr as u8, 0];
+        rec.extend_from_slice(chunk);
+        let sum = rec.iter().fold(0u8, |a, &b| a.wrapping_add(b)).wrapping_neg();
+        s.push(':');
+        for b in rec.iter().chain(std::iter::once(&sum)) {
+            s.push_str(&format!("{:02X}", b));
+        }
+        s.push('\n');
     }
+    s.push_str(":00000001FF\n");
+    s
 }
 
 #[cfg(test)]
@@ -128,6 +153,18 @@ mod tests {
         let hex = ":100000000C9434000C944E000C944E000C944E00FF\n:00000001FF\n";
         let mut flash = vec![0u8; 32768];
         assert!(parse_hex(hex, &mut flash).is_err());
+        let hex = ":02000000FFCF30\n:02000000FFCF31\n";
+        assert!(matches!(parse_hex(hex, &mut flash),
+            Err(Error::Hex { line: 2, kind: HexError::Checksum { sum: 1 } })));
+    }
+
+    #[test]
+    fn test_short_record() {
+        // Byte count claims 16 bytes, line carries 2
+        let hex = ":10000000FFCF22\n";
+        let mut flash = vec![0u8; 32768];
+        assert!(matches!(parse_hex(hex, &mut flash),
+            Err(Error::Hex { line: 1, kind: HexError::Length { count: 16, found: 2 } })));
     }
 
     #[test]
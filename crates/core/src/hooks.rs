@@ -0,0 +1,129 @@
+//! Frame callbacks for embedding the emulator.
+//!
+//! [`Arduboy::run_frame_with`] runs a frame like [`Arduboy::run_frame`] and
+//! then hands its results to a [`FrameHooks`] implementation, so a host does
+//! not have to poll the display, serial and audio state itself:
+//!
+//! | Callback | When | Data |
+//! |----------|------|------|
+//! | `on_display_update` | the screen changed this frame | visible pixels, 0xRRGGBB |
+//! | `on_serial` | the game wrote to USB serial / USART | bytes |
+//! | `on_audio_samples` | `audio_output()` is set and there is sound | interleaved stereo f32 |
+//! | `on_breakpoint` | a breakpoint or watchpoint stopped the frame | flash byte address |
+//!
+//! Every callback has an empty default, so a host implements only what it
+//! needs:
+//!
+//! ```no_run
+//! use arduboy_core::{Arduboy, hooks::FrameHooks};
+//!
+//! struct Console;
+//! impl FrameHooks for Console {
+//!     fn on_serial(&mut self, data: &[u8]) {
+//!         print!("{}", String::from_utf8_lossy(data));
+//!     }
+//! }
+//!
+//! let mut arduboy = Arduboy::new();
+//! arduboy.run_frame_with(&mut Console);
+//! ```
+
+use crate::{Arduboy, DisplayType};
+
+/// Callbacks made by [`Arduboy::run_frame_with`].
+pub trait FrameHooks {
+    /// The visible screen (`width × height`, row stride `width`, turned by
+    /// [`Arduboy::rotation`]) after a frame that changed it.
+    fn on_display_update(&mut self, _pixels: &[u32], _width: usize, _height: usize) {}
+
+    /// Serial output produced during the frame.
+    fn on_serial(&mut self, _data: &[u8]) {}
+
+    /// Sample rate and volume (0.0–1.0) to render audio at; None skips audio.
+    fn audio_output(&self) -> Option<(u32, f32)> {
+        None
+    }
+
+    /// The frame's audio as interleaved \[L, R, ...\] samples.
+    fn on_audio_samples(&mut self, _samples: &[f32]) {}
+
+    /// Execution stopped at `pc` (flash byte address) before the frame
+    /// ended. [`Arduboy::breakpoint_hit`] stays set, as with `run_frame()`.
+    fn on_breakpoint(&mut self, _pc: u32) {}
+}
+
+impl Arduboy {
+    /// Run one frame, then report its results through `hooks`.
+    pub fn run_frame_with(&mut self, hooks: &mut dyn FrameHooks) {
+        self.run_frame();
+        if self.breakpoint_hit {
+            hooks.on_breakpoint(self.cpu.pc * 2);
+        }
+
+        let dirty = match self.display_type {
+            DisplayType::Pcd8544 => std::mem::take(&mut self.pcd8544.dirty),
+            _ => std::mem::take(&mut self.display.dirty),
+        };
+        if dirty {
+            let (px, w, h) = self.screen_output();
+            hooks.on_display_update(&px, w, h);
+        }
+
+        if !self.serial_buf.is_empty() {
+            let data = self.take_serial_output();
+            hooks.on_serial(&data);
+        }
+
+        if let Some((rate, volume)) = hooks.audio_output() {
+            if self.audio_buf.needs_render() {
+                let mut samples = Vec::new();
+                self.audio_buf.render_samples(&mut samples, rate, self.clock_hz, volume);
+                hooks.on_audio_samples(&samples);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Record {
+        screens: u32,
+        serial: Vec<u8>,
+        stops: Vec<u32>,
+    }
+
+    impl FrameHooks for Record {
+        fn on_display_update(&mut self, pixels: &[u32], width: usize, height: usize) {
+            assert_eq!(pixels.len(), width * height);
+            self.screens += 1;
+        }
+        fn on_serial(&mut self, data: &[u8]) {
+            self.serial.extend_from_slice(data);
+        }
+        fn on_breakpoint(&mut self, pc: u32) {
+            self.stops.push(pc);
+        }
+    }
+
+    #[test]
+    fn test_hooks() {
+        let mut a = Arduboy::new();
+        let mut rec = Record::default();
+        a.serial_buf.extend_from_slice(b"hi");
+        a.display.dirty = true;
+        a.run_frame_with(&mut rec);
+        assert_eq!((rec.screens, rec.serial.as_slice()), (1, &b"hi"[..]));
+
+        // Unchanged screen and no output: no callbacks
+        a.run_frame_with(&mut rec);
+        assert_eq!((rec.screens, rec.serial.len()), (1, 2));
+
+        a.breakpoints.push(a.cpu.pc);
+        a.run_frame_with(&mut rec);
+        assert_eq!(rec.stops, vec![a.cpu.pc * 2]);
+        assert!(a.breakpoint_hit);
+    }
+}
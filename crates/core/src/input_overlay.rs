@@ -0,0 +1,156 @@
+//! Button input display for streaming and TAS recordings.
+//!
+//! [`InputHistory`] keeps the button state of recent frames (recorded by
+//! [`Arduboy::run_frame`](crate::Arduboy::run_frame)), and [`render`] draws
+//! it as a small input viewer into any `0xRRGGBB` pixel buffer: a D-pad and
+//! A/B buttons showing the current frame, plus one press-history bar per
+//! button with the newest frame on the right.
+//!
+//! Layout is measured in overlay units; frontends pick the unit size in
+//! pixels to match their scale.
+
+use std::collections::VecDeque;
+use crate::Button;
+
+/// Buttons in history-bar order (top to bottom).
+pub const BUTTONS: [Button; 6] = [
+    Button::Up, Button::Down, Button::Left, Button::Right, Button::A, Button::B,
+];
+
+/// Frames shown in each history bar
+pub const HISTORY_FRAMES: usize = 32;
+
+/// Overlay size in units, including the 1-unit border.
+pub const OVERLAY_W: usize = 59;
+pub const OVERLAY_H: usize = 13;
+
+/// Bit of `button` in a button mask.
+pub fn button_bit(button: Button) -> u8 {
+    1 << BUTTONS.iter().position(|&b| b == button).unwrap_or(0)
+}
+
+/// Button masks of recent frames.
+#[derive(Debug, Clone)]
+pub struct InputHistory {
+    frames: VecDeque<u8>,
+}
+
+impl InputHistory {
+    pub fn new() -> Self {
+        InputHistory { frames: VecDeque::with_capacity(HISTORY_FRAMES) }
+    }
+
+    /// Record the button mask of one frame.
+    pub fn push(&mut self, mask: u8) {
+        if self.frames.len() == HISTORY_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(mask);
+    }
+
+    /// Mask of the most recent frame.
+    pub fn current(&self) -> u8 {
+        self.frames.back().copied().unwrap_or(0)
+    }
+
+    /// Masks from oldest to newest.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        self.frames.iter().copied()
+    }
+}
+
+impl Default for InputHistory {
+    fn default() -> Self { Self::new() }
+}
+
+/// Overlay colors (`0xRRGGBB`).
+#[derive(Debug, Clone, Copy)]
+pub struct OverlayPalette {
+    /// Panel background
+    pub bg: u32,
+    /// Released button outline
+    pub dim: u32,
+    /// History bar track
+    pub track: u32,
+    /// Pressed button / history mark
+    pub on: u32,
+}
+
+impl OverlayPalette {
+    /// Gray-on-dark palette for color output.
+    pub const COLOR: OverlayPalette = OverlayPalette { bg: 0x101418, dim: 0x505860, track: 0x283038, on: 0xF0E040 };
+    /// Two-level palette for monochrome output (GIF recordings).
+    pub const MONO: OverlayPalette = OverlayPalette { bg: 0x000000, dim: 0xFFFFFF, track: 0x000000, on: 0xFFFFFF };
+}
+
+/// Draw the overlay with its top-left corner at pixel `pos` of a
+/// `buf_w`×`buf_h` buffer, `unit` pixels per overlay unit. Clipped to the
+/// buffer.
+pub fn render(history: &InputHistory, buf: &mut [u32], buf_w: usize, buf_h: usize,
+              pos: (usize, usize), unit: usize, pal: OverlayPalette) {
+    let (x0, y0) = pos;
+    let unit = unit.max(1);
+    let mut rect = |ux: usize, uy: usize, uw: usize, uh: usize, color: u32| {
+        for y in (y0 + uy * unit)..(y0 + (uy + uh) * unit).min(buf_h) {
+            for x in (x0 + ux * unit)..(x0 + (ux + uw) * unit).min(buf_w) {
+                buf[y * buf_w + x] = color;
+            }
+        }
+    };
+    rect(0, 0, OVERLAY_W, OVERLAY_H, pal.bg);
+
+    // Buttons: 3×3 squares, filled when pressed, outlined when released
+    let cur = history.current();
+    let cells: [(Button, usize, usize); 6] = [
+        (Button::Up, 5, 1), (Button::Down, 5, 9), (Button::Left, 1, 5),
+        (Button::Right, 9, 5), (Button::A, 15, 5), (Button::B, 20, 5),
+    ];
+    for (b, ux, uy) in cells {
+        if cur & button_bit(b) != 0 {
+            rect(ux, uy, 3, 3, pal.on);
+        } else {
+            rect(ux, uy, 3, 1, pal.dim);
+            rect(ux, uy + 2, 3, 1, pal.dim);
+            rect(ux, uy + 1, 1, 1, pal.dim);
+            rect(ux + 2, uy + 1, 1, 1, pal.dim);
+        }
+    }
+
+    // History bars: one row per button, newest frame at the right edge
+    let hx = 26;
+    let n = history.frames.len();
+    for (row, &b) in BUTTONS.iter().enumerate() {
+        let uy = 1 + row * 2;
+        rect(hx, uy, HISTORY_FRAMES, 1, pal.track);
+        for (i, mask) in history.iter().enumerate() {
+            if mask & button_bit(b) != 0 {
+                rect(hx + HISTORY_FRAMES - n + i, uy, 1, 1, pal.on);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_and_render() {
+        let mut h = InputHistory::new();
+        for i in 0..40 {
+            h.push(if i == 39 { button_bit(Button::A) } else { 0 });
+        }
+        assert_eq!(h.iter().count(), HISTORY_FRAMES);
+        assert_eq!(h.current(), button_bit(Button::A));
+
+        let mut buf = vec![0u32; OVERLAY_W * OVERLAY_H];
+        render(&h, &mut buf, OVERLAY_W, OVERLAY_H, (0, 0), 1, OverlayPalette::COLOR);
+        let pal = OverlayPalette::COLOR;
+        // A pressed: filled square; B released: hollow centre
+        assert_eq!(buf[6 * OVERLAY_W + 16], pal.on);
+        assert_eq!(buf[6 * OVERLAY_W + 21], pal.bg);
+        // Newest A press at the right end of the A row (row 4)
+        assert_eq!(buf[9 * OVERLAY_W + 26 + HISTORY_FRAMES - 1], pal.on);
+        assert_eq!(buf[9 * OVERLAY_W + 26 + HISTORY_FRAMES - 2], pal.track);
+    }
+}
@@ -0,0 +1,521 @@
+//! Peripheral introspection: structured register and interrupt state.
+//!
+//! [`Arduboy::peripherals`](crate::Arduboy::peripherals) lists every
+//! peripheral of the emulated chip with its registers, decoded bit fields
+//! and interrupt enable/pending state, for I/O viewers, debug adapters and
+//! third-party GUIs. Register values are what the CPU would read, without
+//! read side effects (serial FIFOs are not popped, watchpoints do not fire).
+
+use crate::CpuType;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Prescaler meaning of a clock-select field.
+#[derive(Clone, Copy)]
+enum Clock {
+    None,
+    /// Timer0/1/3: 3-bit CS with external clock options
+    Standard,
+    /// Timer2: 3-bit CS, extra prescalers
+    Timer2,
+    /// Timer4: 4-bit CS, powers of two
+    Timer4,
+}
+
+const CLOCK_STD: [&str; 8] = [
+    "stopped", "clk/1", "clk/8", "clk/64", "clk/256", "clk/1024", "ext falling", "ext rising",
+];
+const CLOCK_T2: [&str; 8] = [
+    "stopped", "clk/1", "clk/8", "clk/32", "clk/64", "clk/128", "clk/256", "clk/1024",
+];
+const CLOCK_T4: [&str; 16] = [
+    "stopped", "clk/1", "clk/2", "clk/4", "clk/8", "clk/16", "clk/32", "clk/64", "clk/128",
+    "clk/256", "clk/512", "clk/1024", "clk/2048", "clk/4096", "clk/8192", "clk/16384",
+];
+
+/// Register: name, address, bit fields `(name, lsb, width)`.
+type RegDef = (&'static str, u16, &'static [(&'static str, u8, u8)]);
+/// Interrupt: name, enable bit `(addr, bit)`, flag bit `(addr, bit)`.
+type IrqDef = (&'static str, (u16, u8), Option<(u16, u8)>);
+
+struct PeriphDef {
+    name: &'static str,
+    regs: &'static [RegDef],
+    irqs: &'static [IrqDef],
+    /// Power-reduction bit `(PRRn, bit)`: set = peripheral clock off
+    prr: Option<(u16, u8)>,
+    /// Running when `(reg & mask) != 0`
+    run: Option<(u16, u8)>,
+    clock: Clock,
+}
+
+const NO_FIELDS: &[(&str, u8, u8)] = &[];
+
+const fn gpio(name: &'static str, regs: &'static [RegDef]) -> PeriphDef {
+    PeriphDef { name, regs, irqs: &[], prr: None, run: None, clock: Clock::None }
+}
+
+const PORTB: PeriphDef = gpio("PORTB", &[("PINB", 0x23, NO_FIELDS), ("DDRB", 0x24, NO_FIELDS), ("PORTB", 0x25, NO_FIELDS)]);
+const PORTC: PeriphDef = gpio("PORTC", &[("PINC", 0x26, NO_FIELDS), ("DDRC", 0x27, NO_FIELDS), ("PORTC", 0x28, NO_FIELDS)]);
+const PORTD: PeriphDef = gpio("PORTD", &[("PIND", 0x29, NO_FIELDS), ("DDRD", 0x2A, NO_FIELDS), ("PORTD", 0x2B, NO_FIELDS)]);
+const PORTE: PeriphDef = gpio("PORTE", &[("PINE", 0x2C, NO_FIELDS), ("DDRE", 0x2D, NO_FIELDS), ("PORTE", 0x2E, NO_FIELDS)]);
+const PORTF: PeriphDef = gpio("PORTF", &[("PINF", 0x2F, NO_FIELDS), ("DDRF", 0x30, NO_FIELDS), ("PORTF", 0x31, NO_FIELDS)]);
+
+const TIMSK_FIELDS: &[(&str, u8, u8)] = &[("OCIEB", 2, 1), ("OCIEA", 1, 1), ("TOIE", 0, 1)];
+const TIFR_FIELDS: &[(&str, u8, u8)] = &[("OCFB", 2, 1), ("OCFA", 1, 1), ("TOV", 0, 1)];
+const TIMSK16_FIELDS: &[(&str, u8, u8)] = &[("ICIE", 5, 1), ("OCIEC", 3, 1), ("OCIEB", 2, 1), ("OCIEA", 1, 1), ("TOIE", 0, 1)];
+const TIFR16_FIELDS: &[(&str, u8, u8)] = &[("ICF", 5, 1), ("OCFC", 3, 1), ("OCFB", 2, 1), ("OCFA", 1, 1), ("TOV", 0, 1)];
+const TCCRA8_FIELDS: &[(&str, u8, u8)] = &[("COMA", 6, 2), ("COMB", 4, 2), ("WGM10", 0, 2)];
+const TCCRB8_FIELDS: &[(&str, u8, u8)] = &[("FOCA", 7, 1), ("FOCB", 6, 1), ("WGM2", 3, 1), ("CS", 0, 3)];
+const TCCRA16_FIELDS: &[(&str, u8, u8)] = &[("COMA", 6, 2), ("COMB", 4, 2), ("COMC", 2, 2), ("WGM10", 0, 2)];
+const TCCRB16_FIELDS: &[(&str, u8, u8)] = &[("ICNC", 7, 1), ("ICES", 6, 1), ("WGM32", 3, 2), ("CS", 0, 3)];
+
+const TIMER0: PeriphDef = PeriphDef {
+    name: "TIMER0",
+    regs: &[
+        ("TCCR0A", 0x44, TCCRA8_FIELDS), ("TCCR0B", 0x45, TCCRB8_FIELDS),
+        ("TCNT0", 0x46, NO_FIELDS), ("OCR0A", 0x47, NO_FIELDS), ("OCR0B", 0x48, NO_FIELDS),
+        ("TIMSK0", 0x6E, TIMSK_FIELDS), ("TIFR0", 0x35, TIFR_FIELDS),
+    ],
+    irqs: &[
+        ("TIMER0_COMPA", (0x6E, 1), Some((0x35, 1))),
+        ("TIMER0_COMPB", (0x6E, 2), Some((0x35, 2))),
+        ("TIMER0_OVF", (0x6E, 0), Some((0x35, 0))),
+    ],
+    prr: Some((0x64, 5)),
+    run: Some((0x45, 0x07)),
+    clock: Clock::Standard,
+};
+
+const TIMER1: PeriphDef = PeriphDef {
+    name: "TIMER1",
+    regs: &[
+        ("TCCR1A", 0x80, TCCRA16_FIELDS), ("TCCR1B", 0x81, TCCRB16_FIELDS), ("TCCR1C", 0x82, NO_FIELDS),
+        ("TCNT1L", 0x84, NO_FIELDS), ("TCNT1H", 0x85, NO_FIELDS),
+        ("OCR1AL", 0x88, NO_FIELDS), ("OCR1AH", 0x89, NO_FIELDS),
+        ("OCR1BL", 0x8A, NO_FIELDS), ("OCR1BH", 0x8B, NO_FIELDS),
+        ("OCR1CL", 0x8C, NO_FIELDS), ("OCR1CH", 0x8D, NO_FIELDS),
+        ("TIMSK1", 0x6F, TIMSK16_FIELDS), ("TIFR1", 0x36, TIFR16_FIELDS),
+    ],
+    irqs: &[
+        ("TIMER1_COMPA", (0x6F, 1), Some((0x36, 1))),
+        ("TIMER1_COMPB", (0x6F, 2), Some((0x36, 2))),
+        ("TIMER1_COMPC", (0x6F, 3), Some((0x36, 3))),
+        ("TIMER1_OVF", (0x6F, 0), Some((0x36, 0))),
+    ],
+    prr: Some((0x64, 3)),
+    run: Some((0x81, 0x07)),
+    clock: Clock::Standard,
+};
+
+const TIMER1_328P: PeriphDef = PeriphDef {
+    regs: &[
+        ("TCCR1A", 0x80, TCCRA16_FIELDS), ("TCCR1B", 0x81, TCCRB16_FIELDS), ("TCCR1C", 0x82, NO_FIELDS),
+        ("TCNT1L", 0x84, NO_FIELDS), ("TCNT1H", 0x85, NO_FIELDS),
+        ("OCR1AL", 0x88, NO_FIELDS), ("OCR1AH", 0x89, NO_FIELDS),
+        ("OCR1BL", 0x8A, NO_FIELDS), ("OCR1BH", 0x8B, NO_FIELDS),
+        ("TIMSK1", 0x6F, TIMSK16_FIELDS), ("TIFR1", 0x36, TIFR16_FIELDS),
+    ],
+    irqs: &[
+        ("TIMER1_COMPA", (0x6F, 1), Some((0x36, 1))),
+        ("TIMER1_COMPB", (0x6F, 2), Some((0x36, 2))),
+        ("TIMER1_OVF", (0x6F, 0), Some((0x36, 0))),
+    ],
+    ..TIMER1
+};
+
+const TIMER3: PeriphDef = PeriphDef {
+    name: "TIMER3",
+    regs: &[
+        ("TCCR3A", 0x90, TCCRA16_FIELDS), ("TCCR3B", 0x91, TCCRB16_FIELDS), ("TCCR3C", 0x92, NO_FIELDS),
+        ("TCNT3L", 0x94, NO_FIELDS), ("TCNT3H", 0x95, NO_FIELDS),
+        ("OCR3AL", 0x98, NO_FIELDS), ("OCR3AH", 0x99, NO_FIELDS),
+        ("OCR3BL", 0x9A, NO_FIELDS), ("OCR3BH", 0x9B, NO_FIELDS),
+        ("OCR3CL", 0x9C, NO_FIELDS), ("OCR3CH", 0x9D, NO_FIELDS),
+        ("TIMSK3", 0x71, TIMSK16_FIELDS), ("TIFR3", 0x38, TIFR16_FIELDS),
+    ],
+    irqs: &[
+        ("TIMER3_COMPA", (0x71, 1), Some((0x38, 1))),
+        ("TIMER3_COMPB", (0x71, 2), Some((0x38, 2))),
+        ("TIMER3_COMPC", (0x71, 3), Some((0x38, 3))),
+        ("TIMER3_OVF", (0x71, 0), Some((0x38, 0))),
+    ],
+    prr: Some((0x65, 3)),
+    run: Some((0x91, 0x07)),
+    clock: Clock::Standard,
+};
+
+const TIMER4: PeriphDef = PeriphDef {
+    name: "TIMER4",
+    regs: &[
+        ("TCCR4A", 0xC0, &[("COM4A", 6, 2), ("COM4B", 4, 2), ("FOC4A", 3, 1), ("FOC4B", 2, 1), ("PWM4A", 1, 1), ("PWM4B", 0, 1)]),
+        ("TCCR4B", 0xC1, &[("PWM4X", 7, 1), ("PSR4", 6, 1), ("DTPS4", 4, 2), ("CS", 0, 4)]),
+        ("TCCR4C", 0xC2, NO_FIELDS),
+        ("TCCR4D", 0xC3, &[("FPIE4", 7, 1), ("WGM4", 0, 2)]),
+        ("TCCR4E", 0xC4, NO_FIELDS),
+        ("TCNT4", 0xBE, NO_FIELDS), ("TC4H", 0xBF, NO_FIELDS),
+        ("OCR4A", 0xCF, NO_FIELDS), ("OCR4B", 0xD0, NO_FIELDS),
+        ("OCR4C", 0xD1, NO_FIELDS), ("OCR4D", 0xD2, NO_FIELDS),
+        ("TIMSK4", 0x72, &[("OCIE4D", 7, 1), ("OCIE4A", 6, 1), ("OCIE4B", 5, 1), ("TOIE4", 2, 1)]),
+        ("TIFR4", 0x39, &[("OCF4D", 7, 1), ("OCF4A", 6, 1), ("OCF4B", 5, 1), ("TOV4", 2, 1)]),
+    ],
+    irqs: &[
+        ("TIMER4_COMPA", (0x72, 6), Some((0x39, 6))),
+        ("TIMER4_COMPB", (0x72, 5), Some((0x39, 5))),
+        ("TIMER4_COMPD", (0x72, 7), Some((0x39, 7))),
+        ("TIMER4_OVF", (0x72, 2), Some((0x39, 2))),
+    ],
+    prr: Some((0x65, 4)),
+    run: Some((0xC1, 0x0F)),
+    clock: Clock::Timer4,
+};
+
+const TIMER2: PeriphDef = PeriphDef {
+    name: "TIMER2",
+    regs: &[
+        ("TCCR2A", 0xB0, TCCRA8_FIELDS), ("TCCR2B", 0xB1, TCCRB8_FIELDS),
+        ("TCNT2", 0xB2, NO_FIELDS), ("OCR2A", 0xB3, NO_FIELDS), ("OCR2B", 0xB4, NO_FIELDS),
+        ("ASSR", 0xB6, NO_FIELDS),
+        ("TIMSK2", 0x70, TIMSK_FIELDS), ("TIFR2", 0x37, TIFR_FIELDS),
+    ],
+    irqs: &[
+        ("TIMER2_COMPA", (0x70, 1), Some((0x37, 1))),
+        ("TIMER2_COMPB", (0x70, 2), Some((0x37, 2))),
+        ("TIMER2_OVF", (0x70, 0), Some((0x37, 0))),
+    ],
+    prr: Some((0x64, 6)),
+    run: Some((0xB1, 0x07)),
+    clock: Clock::Timer2,
+};
+
+const SPI: PeriphDef = PeriphDef {
+    name: "SPI",
+    regs: &[
+        ("SPCR", 0x4C, &[("SPIE", 7, 1), ("SPE", 6, 1), ("DORD", 5, 1), ("MSTR", 4, 1), ("CPOL", 3, 1), ("CPHA", 2, 1), ("SPR", 0, 2)]),
+        ("SPSR", 0x4D, &[("SPIF", 7, 1), ("WCOL", 6, 1), ("SPI2X", 0, 1)]),
+        ("SPDR", 0x4E, NO_FIELDS),
+    ],
+    irqs: &[("SPI_STC", (0x4C, 7), Some((0x4D, 7)))],
+    prr: Some((0x64, 2)),
+    run: Some((0x4C, 0x40)),
+    clock: Clock::None,
+};
+
+const ADC: PeriphDef = PeriphDef {
+    name: "ADC",
+    regs: &[
+        ("ADMUX", 0x7C, &[("REFS", 6, 2), ("ADLAR", 5, 1), ("MUX", 0, 5)]),
+        ("ADCSRA", 0x7A, &[("ADEN", 7, 1), ("ADSC", 6, 1), ("ADATE", 5, 1), ("ADIF", 4, 1), ("ADIE", 3, 1), ("ADPS", 0, 3)]),
+        ("ADCSRB", 0x7B, NO_FIELDS),
+        ("ADCL", 0x78, NO_FIELDS), ("ADCH", 0x79, NO_FIELDS),
+    ],
+    irqs: &[("ADC", (0x7A, 3), Some((0x7A, 4)))],
+    prr: Some((0x64, 0)),
+    run: Some((0x7A, 0x80)),
+    clock: Clock::None,
+};
+
+const EEPROM: PeriphDef = PeriphDef {
+    name: "EEPROM",
+    regs: &[
+        ("EECR", 0x3F, &[("EEPM", 4, 2), ("EERIE", 3, 1), ("EEMPE", 2, 1), ("EEPE", 1, 1), ("EERE", 0, 1)]),
+        ("EEDR", 0x40, NO_FIELDS), ("EEARL", 0x41, NO_FIELDS), ("EEARH", 0x42, NO_FIELDS),
+    ],
+    irqs: &[("EE_READY", (0x3F, 3), None)],
+    prr: None,
+    run: None,
+    clock: Clock::None,
+};
+
+const PLL: PeriphDef = PeriphDef {
+    name: "PLL",
+    regs: &[
+        ("PLLCSR", 0x49, &[("PINDIV", 4, 1), ("PLLE", 1, 1), ("PLOCK", 0, 1)]),
+        ("PLLFRQ", 0x52, &[("PINMUX", 7, 1), ("PLLUSB", 6, 1), ("PLLTM", 4, 2), ("PDIV", 0, 4)]),
+    ],
+    irqs: &[],
+    prr: None,
+    run: Some((0x49, 0x02)),
+    clock: Clock::None,
+};
+
+const USB: PeriphDef = PeriphDef {
+    name: "USB",
+    regs: &[
+        ("USBCON", 0xD8, &[("USBE", 7, 1), ("FRZCLK", 5, 1), ("OTGPADE", 4, 1), ("VBUSTE", 0, 1)]),
+        ("USBSTA", 0xD9, NO_FIELDS), ("UDCON", 0xE0, NO_FIELDS), ("UDIEN", 0xE2, NO_FIELDS),
+        ("UDADDR", 0xE3, NO_FIELDS), ("UENUM", 0xE9, NO_FIELDS), ("UEINTX", 0xE8, NO_FIELDS),
+        ("UEBCLX", 0xF2, NO_FIELDS),
+    ],
+    irqs: &[],
+    prr: Some((0x65, 7)),
+    run: Some((0xD8, 0x80)),
+    clock: Clock::None,
+};
+
+const USART0: PeriphDef = PeriphDef {
+    name: "USART0",
+    regs: &[
+        ("UCSR0A", 0xC0, &[("RXC0", 7, 1), ("TXC0", 6, 1), ("UDRE0", 5, 1), ("FE0", 4, 1), ("DOR0", 3, 1), ("UPE0", 2, 1), ("U2X0", 1, 1)]),
+        ("UCSR0B", 0xC1, &[("RXCIE0", 7, 1), ("TXCIE0", 6, 1), ("UDRIE0", 5, 1), ("RXEN0", 4, 1), ("TXEN0", 3, 1)]),
+        ("UCSR0C", 0xC2, NO_FIELDS),
+        ("UBRR0L", 0xC4, NO_FIELDS), ("UBRR0H", 0xC5, NO_FIELDS),
+        ("UDR0", 0xC6, NO_FIELDS),
+    ],
+    irqs: &[
+        ("USART_RX", (0xC1, 7), Some((0xC0, 7))),
+        ("USART_UDRE", (0xC1, 5), Some((0xC0, 5))),
+        ("USART_TX", (0xC1, 6), Some((0xC0, 6))),
+    ],
+    prr: Some((0x64, 1)),
+    run: Some((0xC1, 0x18)),
+    clock: Clock::None,
+};
+
+const EXINT_32U4: PeriphDef = PeriphDef {
+    name: "EXINT",
+    regs: &[
+        ("EICRA", 0x69, NO_FIELDS), ("EICRB", 0x6A, NO_FIELDS),
+        ("EIMSK", 0x3D, NO_FIELDS), ("EIFR", 0x3C, NO_FIELDS),
+        ("PCICR", 0x68, NO_FIELDS), ("PCIFR", 0x3B, NO_FIELDS), ("PCMSK0", 0x6B, NO_FIELDS),
+    ],
+    irqs: &[
+        ("INT0", (0x3D, 0), Some((0x3C, 0))), ("INT1", (0x3D, 1), Some((0x3C, 1))),
+        ("INT2", (0x3D, 2), Some((0x3C, 2))), ("INT3", (0x3D, 3), Some((0x3C, 3))),
+        ("INT6", (0x3D, 6), Some((0x3C, 6))), ("PCINT0", (0x68, 0), Some((0x3B, 0))),
+    ],
+    prr: None,
+    run: None,
+    clock: Clock::None,
+};
+
+const EXINT_328P: PeriphDef = PeriphDef {
+    regs: &[
+        ("EICRA", 0x69, NO_FIELDS), ("EIMSK", 0x3D, NO_FIELDS), ("EIFR", 0x3C, NO_FIELDS),
+        ("PCICR", 0x68, NO_FIELDS), ("PCIFR", 0x3B, NO_FIELDS),
+        ("PCMSK0", 0x6B, NO_FIELDS), ("PCMSK1", 0x6C, NO_FIELDS), ("PCMSK2", 0x6D, NO_FIELDS),
+    ],
+    irqs: &[
+        ("INT0", (0x3D, 0), Some((0x3C, 0))), ("INT1", (0x3D, 1), Some((0x3C, 1))),
+        ("PCINT0", (0x68, 0), Some((0x3B, 0))), ("PCINT1", (0x68, 1), Some((0x3B, 1))),
+        ("PCINT2", (0x68, 2), Some((0x3B, 2))),
+    ],
+    ..EXINT_32U4
+};
+
+const EXINT_2560: PeriphDef = PeriphDef {
+    regs: &[
+        ("EICRA", 0x69, NO_FIELDS), ("EICRB", 0x6A, NO_FIELDS),
+        ("EIMSK", 0x3D, NO_FIELDS), ("EIFR", 0x3C, NO_FIELDS),
+        ("PCICR", 0x68, NO_FIELDS), ("PCIFR", 0x3B, NO_FIELDS),
+        ("PCMSK0", 0x6B, NO_FIELDS), ("PCMSK1", 0x6C, NO_FIELDS),
+    ],
+    irqs: &[
+        ("INT0", (0x3D, 0), Some((0x3C, 0))), ("INT1", (0x3D, 1), Some((0x3C, 1))),
+        ("INT2", (0x3D, 2), Some((0x3C, 2))), ("INT3", (0x3D, 3), Some((0x3C, 3))),
+        ("INT4", (0x3D, 4), Some((0x3C, 4))), ("INT5", (0x3D, 5), Some((0x3C, 5))),
+        ("INT6", (0x3D, 6), Some((0x3C, 6))), ("INT7", (0x3D, 7), Some((0x3C, 7))),
+        ("PCINT0", (0x68, 0), Some((0x3B, 0))), ("PCINT1", (0x68, 1), Some((0x3B, 1))),
+    ],
+    ..EXINT_32U4
+};
+
+const SYSTEM: PeriphDef = PeriphDef {
+    name: "SYSTEM",
+    regs: &[
+        ("SREG", 0x5F, &[("I", 7, 1), ("T", 6, 1), ("H", 5, 1), ("S", 4, 1), ("V", 3, 1), ("N", 2, 1), ("Z", 1, 1), ("C", 0, 1)]),
+        ("SPL", 0x5D, NO_FIELDS), ("SPH", 0x5E, NO_FIELDS),
+        ("SMCR", 0x53, &[("SM", 1, 3), ("SE", 0, 1)]),
+        ("MCUSR", 0x54, &[("WDRF", 3, 1), ("BORF", 2, 1), ("EXTRF", 1, 1), ("PORF", 0, 1)]),
+        ("MCUCR", 0x55, NO_FIELDS),
+        ("CLKPR", 0x61, &[("CLKPCE", 7, 1), ("CLKPS", 0, 4)]),
+        ("WDTCSR", 0x60, &[("WDIF", 7, 1), ("WDIE", 6, 1), ("WDCE", 4, 1), ("WDE", 3, 1)]),
+        ("PRR0", 0x64, NO_FIELDS),
+    ],
+    irqs: &[("WDT", (0x60, 6), Some((0x60, 7)))],
+    prr: None,
+    run: None,
+    clock: Clock::None,
+};
+
+const SYSTEM_32U4: PeriphDef = PeriphDef {
+    regs: &[
+        ("SREG", 0x5F, &[("I", 7, 1), ("T", 6, 1), ("H", 5, 1), ("S", 4, 1), ("V", 3, 1), ("N", 2, 1), ("Z", 1, 1), ("C", 0, 1)]),
+        ("SPL", 0x5D, NO_FIELDS), ("SPH", 0x5E, NO_FIELDS),
+        ("SMCR", 0x53, &[("SM", 1, 3), ("SE", 0, 1)]),
+        ("MCUSR", 0x54, &[("JTRF", 4, 1), ("WDRF", 3, 1), ("BORF", 2, 1), ("EXTRF", 1, 1), ("PORF", 0, 1)]),
+        ("MCUCR", 0x55, NO_FIELDS),
+        ("CLKPR", 0x61, &[("CLKPCE", 7, 1), ("CLKPS", 0, 4)]),
+        ("WDTCSR", 0x60, &[("WDIF", 7, 1), ("WDIE", 6, 1), ("WDCE", 4, 1), ("WDE", 3, 1)]),
+        ("PRR0", 0x64, NO_FIELDS), ("PRR1", 0x65, NO_FIELDS),
+    ],
+    ..SYSTEM
+};
+
+const PERIPHS_32U4: &[&PeriphDef] = &[
+    &SYSTEM_32U4, &PORTB, &PORTC, &PORTD, &PORTE, &PORTF, &EXINT_32U4,
+    &TIMER0, &TIMER1, &TIMER3, &TIMER4, &SPI, &ADC, &EEPROM, &PLL, &USB,
+];
+const PERIPHS_328P: &[&PeriphDef] = &[
+    &SYSTEM, &PORTB, &PORTC, &PORTD, &EXINT_328P,
+    &TIMER0, &TIMER1_328P, &TIMER2, &SPI, &ADC, &EEPROM, &USART0,
+];
+/// ATmega2560: the peripherals it shares with the other two chips
+const PERIPHS_2560: &[&PeriphDef] = &[
+    &SYSTEM, &PORTB, &PORTC, &PORTD, &PORTE, &PORTF, &EXINT_2560,
+    &TIMER0, &TIMER1, &TIMER2, &TIMER3, &SPI, &ADC, &EEPROM, &USART0,
+];
+
+// ─── Public types ───────────────────────────────────────────────────────────
+
+/// One bit field of a register.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    /// Lowest bit position
+    pub lsb: u8,
+    /// Width in bits
+    pub width: u8,
+    /// Field value (shifted down)
+    pub value: u8,
+    /// Decoded meaning, e.g. `clk/64` for clock selects
+    pub meaning: Option<&'static str>,
+}
+
+/// One register with its current value.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RegisterInfo {
+    pub name: &'static str,
+    /// Data-space address
+    pub addr: u16,
+    pub value: u8,
+    pub fields: Vec<FieldInfo>,
+}
+
+impl RegisterInfo {
+    pub fn field(&self, name: &str) -> Option<&FieldInfo> {
+        self.fields.iter().find(|f| f.name == name)
+    }
+}
+
+/// Enable and pending state of one interrupt source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InterruptInfo {
+    pub name: &'static str,
+    pub enabled: bool,
+    /// Flag set (always false for sources without a flag bit)
+    pub pending: bool,
+}
+
+/// State of one peripheral.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PeripheralInfo {
+    pub name: &'static str,
+    /// Clock not disabled by the power reduction register
+    pub powered: bool,
+    /// Powered and switched on (clock selected, enable bit set, ...)
+    pub active: bool,
+    pub registers: Vec<RegisterInfo>,
+    pub interrupts: Vec<InterruptInfo>,
+}
+
+impl PeripheralInfo {
+    pub fn register(&self, name: &str) -> Option<&RegisterInfo> {
+        self.registers.iter().find(|r| r.name == name)
+    }
+
+    /// Multi-line text view: registers with decoded fields, then interrupts.
+    pub fn format(&self) -> String {
+        let mut s = format!("{} ({})\n", self.name,
+            if !self.powered { "powered down" } else if self.active { "active" } else { "idle" });
+        for r in &self.registers {
+            let _ = write!(s, "  {:>8} (0x{:02X}) = 0x{:02X}", r.name, r.addr, r.value);
+            for f in &r.fields {
+                let _ = write!(s, " {}={}", f.name, f.value);
+                if let Some(m) = f.meaning {
+                    let _ = write!(s, "({})", m);
+                }
+            }
+            s.push('\n');
+        }
+        for i in &self.interrupts {
+            let _ = writeln!(s, "  irq {:<13} {}{}", i.name,
+                if i.enabled { "enabled" } else { "disabled" },
+                if i.pending { ", pending" } else { "" });
+        }
+        s
+    }
+}
+
+/// Describe every peripheral of `cpu`, reading registers through `peek`
+/// (each address is read once).
+pub fn describe(cpu: CpuType, mut peek: impl FnMut(u16) -> u8) -> Vec<PeripheralInfo> {
+    let defs = match cpu {
+        CpuType::Atmega32u4 => PERIPHS_32U4,
+        CpuType::Atmega328p => PERIPHS_328P,
+        CpuType::Atmega2560 => PERIPHS_2560,
+    };
+    let mut cache = [None; 0x100];
+    let mut val = |addr: u16| *cache[(addr & 0xFF) as usize].get_or_insert_with(|| peek(addr));
+    let mut out = Vec::with_capacity(defs.len());
+    for d in defs {
+        let powered = d.prr.is_none_or(|(addr, b)| val(addr) & (1 << b) == 0);
+        let active = powered && d.run.is_none_or(|(addr, mask)| val(addr) & mask != 0);
+        let mut registers = Vec::with_capacity(d.regs.len());
+        for &(name, addr, fields) in d.regs {
+            let value = val(addr);
+            let fields = fields.iter().map(|&(fname, lsb, width)| {
+                let v = (value >> lsb) & ((1u16 << width) - 1) as u8;
+                FieldInfo { name: fname, lsb, width, value: v, meaning: field_meaning(d.clock, fname, v) }
+            }).collect();
+            registers.push(RegisterInfo { name, addr, value, fields });
+        }
+        let mut interrupts = Vec::with_capacity(d.irqs.len());
+        for &(name, (ea, eb), flag) in d.irqs {
+            interrupts.push(InterruptInfo {
+                name,
+                enabled: val(ea) & (1 << eb) != 0,
+                pending: flag.is_some_and(|(fa, fb)| val(fa) & (1 << fb) != 0),
+            });
+        }
+        out.push(PeripheralInfo { name: d.name, powered, active, registers, interrupts });
+    }
+    out
+}
+
+fn field_meaning(clock: Clock, name: &str, v: u8) -> Option<&'static str> {
+    if name != "CS" {
+        return None;
+    }
+    match clock {
+        Clock::None => None,
+        Clock::Standard => CLOCK_STD.get(v as usize).copied(),
+        Clock::Timer2 => CLOCK_T2.get(v as usize).copied(),
+        Clock::Timer4 => CLOCK_T4.get(v as usize).copied(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_timer0() {
+        let mut io = vec![0u8; 0x100];
+        io[0x45] = 0x03; // TCCR0B: clk/64
+        io[0x6E] = 0x01; // TIMSK0: TOIE0
+        io[0x35] = 0x01; // TIFR0: TOV0
+        io[0x64] = 0x04; // PRR0: SPI powered down
+        let p = describe(CpuType::Atmega32u4, |a| io[a as usize]);
+        let t0 = p.iter().find(|p| p.name == "TIMER0").unwrap();
+        assert!(t0.active);
+        let cs = t0.register("TCCR0B").unwrap().field("CS").unwrap();
+        assert_eq!((cs.value, cs.meaning), (3, Some("clk/64")));
+        let ovf = t0.interrupts.iter().find(|i| i.name == "TIMER0_OVF").unwrap();
+        assert!(ovf.enabled && ovf.pending);
+        let spi = p.iter().find(|p| p.name == "SPI").unwrap();
+        assert!(!spi.powered && !spi.active);
+        assert!(t0.format().contains("CS=3(clk/64)"));
+        assert!(!describe(CpuType::Atmega328p, |_| 0).iter().any(|p| p.name == "TIMER4"));
+    }
+}
@@ -0,0 +1,217 @@
+//! Input-latency measurement.
+//!
+//! [`measure`] presses a button at different points within a frame and
+//! reports how long the game takes to respond, in emulated time. Each trial
+//! runs the game twice from the same save state, once with the press and
+//! once without, and the first frame whose screen differs is the response:
+//! animations and timers that run anyway do not count.
+//!
+//! A game can instead print a marker on serial when it handles the input
+//! (`Serial.print("IN")`); with [`LatencyConfig::marker`] set, the first
+//! frame whose serial output contains it is the response.
+//!
+//! | Phase | Frames |
+//! |-------|--------|
+//! | settle | `gap` frames with all buttons released |
+//! | press | at `trial / trials` of the way into the next frame, held [`HOLD_FRAMES`] |
+//! | wait | up to `max_frames` for the response |
+//!
+//! Latency is measured from the press to the end of the responding frame,
+//! when a frontend would present it; the host's own display pipeline adds
+//! to that.
+
+use crate::{Arduboy, Button};
+
+/// Frames the button is held in each trial
+pub const HOLD_FRAMES: u64 = 5;
+
+/// Measurement settings.
+#[derive(Debug, Clone)]
+pub struct LatencyConfig {
+    pub button: Button,
+    pub trials: u32,
+    /// Idle frames before each trial
+    pub gap: u32,
+    /// Give up on a trial after this many frames
+    pub max_frames: u32,
+    /// Serial output that signals the response (None = watch the screen)
+    pub marker: Option<Vec<u8>>,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig { button: Button::A, trials: 10, gap: 30, max_frames: 30, marker: None }
+    }
+}
+
+/// One press and its response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyTrial {
+    /// Cycles into the frame at which the button went down
+    pub phase: u64,
+    /// Press-to-response time in cycles (None = no response)
+    pub cycles: Option<u64>,
+    /// Frames run until the response, counting the press frame
+    pub frames: u32,
+}
+
+impl LatencyTrial {
+    /// Press-to-response time at `clock_hz` cycles per second.
+    pub fn ms(&self, clock_hz: u32) -> Option<f64> {
+        self.cycles.map(|c| c as f64 * 1000.0 / clock_hz as f64)
+    }
+}
+
+/// Results of [`measure`].
+#[derive(Debug, Clone)]
+pub struct LatencyReport {
+    pub button: Button,
+    pub trials: Vec<LatencyTrial>,
+    /// [`Arduboy::clock_hz`] during the measurement
+    pub clock_hz: u32,
+    /// Cycles per frame during the measurement
+    pub frame_cycles: u64,
+}
+
+impl LatencyReport {
+    /// (min, mean, max) milliseconds over trials that got a response.
+    pub fn stats(&self) -> Option<(f64, f64, f64)> {
+        let ms: Vec<f64> = self.trials.iter().filter_map(|t| t.ms(self.clock_hz)).collect();
+        if ms.is_empty() {
+            return None;
+        }
+        let min = ms.iter().cloned().fold(f64::MAX, f64::min);
+        let max = ms.iter().cloned().fold(0.0, f64::max);
+        Some((min, ms.iter().sum::<f64>() / ms.len() as f64, max))
+    }
+
+    /// Number of trials without a response.
+    pub fn missed(&self) -> usize {
+        self.trials.iter().filter(|t| t.cycles.is_none()).count()
+    }
+
+    /// One line per trial and a summary line.
+    pub fn format(&self) -> String {
+        let mut out = String::new();
+        for (i, t) in self.trials.iter().enumerate() {
+            let result = match t.ms(self.clock_hz) {
+                Some(ms) => format!("{:6.2} ms ({} frames)", ms, t.frames),
+                None => "no response".to_string(),
+            };
+            out.push_str(&format!("  trial {:2}: press at +{:6} cycles → {}\n", i + 1, t.phase, result));
+        }
+        let frame_ms = self.frame_cycles as f64 * 1000.0 / self.clock_hz as f64;
+        match self.stats() {
+            Some((min, mean, max)) => out.push_str(&format!(
+                "Latency ({:?}, {} trials): min {:.2} ms, mean {:.2} ms, max {:.2} ms ({:.1}–{:.1} frames){}",
+                self.button, self.trials.len(), min, mean, max, min / frame_ms, max / frame_ms,
+                if self.missed() > 0 { format!(", {} missed", self.missed()) } else { String::new() })),
+            None => out.push_str(&format!("Latency ({:?}): no response in any trial", self.button)),
+        }
+        out
+    }
+}
+
+/// Run the trials. Fails if the game does not run deterministically from a
+/// save state, which would make screen comparison meaningless.
+pub fn measure(arduboy: &mut Arduboy, cfg: &LatencyConfig) -> Result<LatencyReport, String> {
+    let trials = cfg.trials.max(1);
+    let mut report = LatencyReport {
+        button: cfg.button,
+        trials: Vec::new(),
+        clock_hz: arduboy.clock_hz,
+        frame_cycles: arduboy.frame_ticks(),
+    };
+    for trial in 0..trials {
+        release_all(arduboy);
+        for _ in 0..cfg.gap {
+            arduboy.run_frame();
+        }
+        arduboy.take_serial_output();
+        let start = arduboy.save_full_state();
+        let phase = report.frame_cycles * trial as u64 / trials as u64;
+
+        let result = match &cfg.marker {
+            Some(marker) => press_run(arduboy, cfg, phase, |a, _| {
+                contains(&a.take_serial_output(), marker)
+            }),
+            None => {
+                let control = screen_run(arduboy, cfg.max_frames);
+                arduboy.load_full_state(&start);
+                if trial == 0 && screen_run(arduboy, cfg.max_frames) != control {
+                    return Err("game output differs between runs from the same state; \
+                                use a serial marker instead".to_string());
+                }
+                arduboy.load_full_state(&start);
+                press_run(arduboy, cfg, phase, |a, frame| a.framebuffer_hash() != control[frame])
+            }
+        };
+        let (frames, cycles) = match result {
+            Some((frames, end)) => (frames, Some(end - start.tick - phase)),
+            None => (cfg.max_frames, None),
+        };
+        report.trials.push(LatencyTrial { phase, cycles, frames });
+    }
+    release_all(arduboy);
+    Ok(report)
+}
+
+fn release_all(arduboy: &mut Arduboy) {
+    for b in crate::input_overlay::BUTTONS {
+        arduboy.set_button(b, false);
+    }
+}
+
+/// Screen hash after each of `frames` frames, with no input.
+fn screen_run(arduboy: &mut Arduboy, frames: u32) -> Vec<u64> {
+    (0..frames).map(|_| {
+        arduboy.run_frame();
+        arduboy.framebuffer_hash()
+    }).collect()
+}
+
+/// Press the button `phase` cycles in and run until `responded(frame)`;
+/// returns the frame count and end tick of the responding frame.
+fn press_run(arduboy: &mut Arduboy, cfg: &LatencyConfig, phase: u64,
+             mut responded: impl FnMut(&mut Arduboy, usize) -> bool) -> Option<(u32, u64)> {
+    let press = arduboy.cpu.tick + phase;
+    arduboy.queue_button_event(press, cfg.button, true);
+    arduboy.queue_button_event(press + HOLD_FRAMES * arduboy.frame_ticks(), cfg.button, false);
+    for frame in 0..cfg.max_frames as usize {
+        arduboy.run_frame();
+        if responded(arduboy, frame) {
+            let end = arduboy.cpu.tick;
+            // Let the release go through before the next trial
+            while arduboy.pending_button_events() > 0 {
+                arduboy.run_frame();
+            }
+            return Some((frame as u32 + 1, end));
+        }
+    }
+    None
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    !needle.is_empty() && haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_stats() {
+        let t = |cycles: Option<u64>| LatencyTrial { phase: 0, cycles, frames: 2 };
+        let report = LatencyReport {
+            button: Button::A,
+            trials: vec![t(Some(160_000)), t(Some(320_000)), t(None)],
+            clock_hz: crate::CLOCK_HZ,
+            frame_cycles: crate::FRAME_CYCLES,
+        };
+        assert_eq!(report.stats(), Some((10.0, 15.0, 20.0)));
+        assert_eq!(report.missed(), 1);
+        let text = report.format();
+        assert!(text.contains("no response"));
+        assert!(text.ends_with("1 missed"), "{}", text);
+    }
+}
@@ -4,9 +4,10 @@
 //!
 //! Emulates the ATmega32u4 microcontroller (Arduboy) and ATmega328P (Gamebuino
 //! Classic / Arduino Uno) with 16 MHz clock, 32 KB flash, 2–2.5 KB SRAM,
-//! 1 KB EEPROM. Peripheral hardware: SSD1306 OLED display, PCD8544 Nokia LCD
-//! (Gamebuino), SPI bus, Timer0/1/2/3/4, ADC, PLL, EEPROM controller,
-//! W25Q128 FX external flash, and USB serial output.
+//! 1 KB EEPROM, plus the ATmega2560 (Arduino Mega, 256 KB flash, 8 KB SRAM).
+//! Peripheral hardware: SSD1306 OLED display, PCD8544 Nokia LCD
+//! (Gamebuino), SPI bus, Timer0/1/2/3/4/5, ADC, PLL, EEPROM controller,
+//! W25Q128 FX external flash, and USB serial / USART I/O.
 //!
 //! ## Architecture
 //!
@@ -19,11 +20,71 @@
 //! - [`peripherals`] — Timer8, Timer16, Timer4, SPI, ADC, PLL, EEPROM, FX flash
 //! - [`disasm`] — Instruction disassembler for debug views
 //! - [`profiler`] — Execution profiler with PC histogram and call graph
-//! - [`debugger`] — RAM viewer, I/O register viewer, watchpoints
+//! - [`fx_stats`] — FX flash bytes per frame, access pattern and hot/unread sectors for the profiler
+//! - [`debugger`] — RAM viewer, I/O register viewer, watchpoints, write-protect regions
+//! - [`expr`] — Debugger expressions over registers, memory and symbols; watch displays
+//! - [`tracepoint`] — Non-stopping logging breakpoints with register/memory format strings
 //! - [`gdb_server`] — GDB Remote Serial Protocol server for avr-gdb
 //! - [`elf`] — ELF/DWARF parser for debug symbols and source-level debugging
+//! - [`fuses`] — Fuse bytes: CKDIV8 clock division, BOOTRST and boot section size
 //! - [`snapshot`] — Emulator state snapshots for rewind functionality
 //! - [`savestate`] — Save state (quick save/load) with bincode serialization
+//! - [`serial_rpc`] — In-band emulator control via serial escape sequences
+//! - [`scenario`] — Scripted headless test scenarios (inputs, assertions, artifacts)
+//! - [`vcd`] — Pin-level waveform export (Value Change Dump) for GTKWave
+//! - [`spi_decode`] — SPI transaction timeline with flash/display command decoding
+//! - [`spi_trace`] — Filterable byte-level SPI trace with JSON/CSV export
+//! - [`frame_timeline`] — Per-frame interrupt, SPI, display and sleep spans; Chrome trace export
+//! - [`perf_trace`] — Host-time cost of the core's phases per frame, for Perfetto
+//! - [`score`] — ArduboyTones / Playtune score extraction and real-time synthesis
+//! - [`patch`] — In-memory flash patches: replace words, NOP out ranges, return early from functions
+//! - [`romhack`] — IPS/BPS patches applied to the program image (ROM hacks)
+//! - [`rotation`] — 90°/180° output rotation for portrait games
+//! - [`plugin`] — Native plugin ABI: frame events, state reads and overlay drawing
+//! - [`autosplit`] — RAM-condition autosplitter with LiveSplit Server output
+//! - [`export`] — Serde/JSON data for the profiler, watchpoints, perf stats and RAM dumps
+//! - [`frame_budget`] — Interrupt storm, ISR load and missed-deadline warnings
+//! - [`eeprom_wear`] — Per-cell EEPROM write counts and hammering warnings
+//! - [`eeprom_guard`] — EEPROM write log and save-range guard, with per-game save ranges
+//! - [`system_eeprom`] — Arduboy2 system settings (unit name/ID, audio, logo flags)
+//! - [`fast_boot`] — Arduboy2 boot logo detection for skipping it on reload
+//! - [`remote`] — Remote display server: frames out and buttons in over TCP or WebSocket
+//! - [`verify`] — Hash-chained input/checkpoint log for speedrun verification
+//! - [`input_overlay`] — Button state history and on-screen input viewer
+//! - [`latency`] — Input-to-screen latency measurement over repeated trials
+//! - [`toml_lite`] — Minimal TOML subset parser for scenario and config files
+//! - [`logging`] — Diagnostic levels per subsystem (cpu, spi, display, fx, ...)
+//! - [`introspect`] — Structured peripheral, register and interrupt state
+//! - [`audio_router`] — GPIO speaker pin mapping and bit-bang edge detection
+//! - [`board`] — Named hardware profiles (CPU, display, button/speaker/CS/DC pins)
+//! - [`selftest`] — Generated CPU conformance ROM reporting over serial
+//! - [`boot_rom`] — Built-in "no game loaded" screen ROM for launches without a game
+//! - [`package`] — `.arduboy` archive writer with generated `info.json`
+//! - [`state_dump`] — Sorted, diffable text dump of the full emulator state
+//! - [`realtime`] — Wall-clock pacing of emulated cycles for frontends
+//! - [`cpu_detect`] — CPU type detection with confidence score and evidence
+//! - [`game_file`] — Opening `.arduboy`, `.elf` and `.hex` games with their FX data
+//! - [`builder`] — Validated construction of a customized emulator
+//! - [`error`] — Structured errors for HEX, `.arduboy`, ELF and save state loading
+//! - [`hooks`] — Display, serial, audio and breakpoint callbacks per frame for embedders
+//! - [`shared_screen`] — Double-buffered screen handle for render threads
+//! - [`bounds`] — Out-of-bounds loads and stores on global objects, from ELF symbol sizes (not DWARF types)
+//! - [`shadow_stack`] — Shadow call stack catching overwritten return addresses
+//! - [`stack_guard`] — Stack overflow into .data/.bss, reported with the call stack
+//! - [`uninit`] — Strict mode: loads from SRAM never written since reset
+//! - [`diagnostics`] — Message sink and break request shared by the run-time checkers
+//! - [`coprocessor`] — Scriptable serial co-processor stub (ESP8266 AT commands or a custom protocol)
+//! - [`actions`] — Named frontend hotkeys with rebindable key/gamepad bindings and edge detection
+//! - [`sram_map`] — SRAM occupancy bar: .data/.bss/.noinit, heap break, SP and deepest stack
+//! - [`av_sync`] — A/V offset statistics and ±0.5% cycles-per-frame drift correction
+//! - [`bug_report`] — One-step ZIP bundle of state, recent frames, serial and trace tails
+//! - [`schedule`] — Actions at future frames or ticks: buttons, ADC readings, markers, closures
+//! - [`sound_events`] — Tone onset/offset events with frequency, note, channel and source
+//! - [`annotations`] — User symbol/comment files (`<game>.sym`) for games without ELF
+//! - [`fault`] — Injected FX corruption, failed EEPROM writes and dropped SPI bytes
+//! - [`flush_timing`] — Display flush cycles per frame against the SPI minimum, with abnormal patterns flagged
+//! - [`virtual_devices`] — Config-defined memory-mapped devices (constant, counter, RNG, file-backed buffer)
+//! - [`display_cache`] — Per-game cache of the auto-detected display, applied at reset
 //!
 //! ## Audio
 //!
@@ -35,6 +96,14 @@
 //!
 //! Stereo output: Speaker 1 (PC6 on 32u4, PD3 on 328P) → left channel,
 //! Speaker 2 (PB5) → right channel.
+//!
+//! ## Untrusted input
+//!
+//! Loading (HEX, ELF, `.arduboy`, IPS/BPS, save states, FX images) reports
+//! malformed data as an [`Error`] instead of panicking, and stepping any
+//! flash contents — including PCs outside the program — never panics. The
+//! `fuzz/` directory holds `cargo fuzz` targets for the decoder, the file
+//! parsers and arbitrary ROM execution.
 
 pub mod cpu;
 pub mod memory;
@@ -49,16 +118,86 @@ pub mod arduboy_file;
 pub mod png;
 pub mod gif;
 pub mod profiler;
+pub mod fx_stats;
 pub mod debugger;
+pub mod expr;
+pub mod tracepoint;
 pub mod gdb_server;
 pub mod elf;
+pub mod fuses;
 pub mod snapshot;
 pub mod savestate;
+pub mod serial_rpc;
+pub mod scenario;
+pub mod toml_lite;
+pub mod vcd;
+pub mod spi_decode;
+pub mod spi_trace;
+pub mod frame_timeline;
+pub mod perf_trace;
+pub mod score;
+pub mod eeprom_wear;
+pub mod eeprom_guard;
+pub mod system_eeprom;
+pub mod verify;
+pub mod input_overlay;
+pub mod latency;
+pub mod logging;
+pub mod introspect;
+pub mod audio_router;
+pub mod board;
+pub mod selftest;
+pub mod boot_rom;
+pub mod package;
+pub mod state_dump;
+pub mod realtime;
+pub mod cpu_detect;
+pub mod game_file;
+pub mod builder;
+pub mod error;
+pub mod hooks;
+pub mod shared_screen;
+pub mod bounds;
+pub mod shadow_stack;
+pub mod stack_guard;
+pub mod uninit;
+pub mod diagnostics;
+pub mod coprocessor;
+pub mod actions;
+pub mod sram_map;
+pub mod av_sync;
+pub mod bug_report;
+pub mod schedule;
+pub mod sound_events;
+pub mod annotations;
+pub mod fault;
+pub mod flush_timing;
+pub mod virtual_devices;
+pub mod display_cache;
+pub mod frame_budget;
+pub mod export;
+pub mod patch;
+pub mod romhack;
+pub mod rotation;
+pub mod plugin;
+pub mod autosplit;
+pub mod fast_boot;
+pub mod remote;
 
 pub use cpu::Cpu;
-pub use display::Ssd1306;
+pub use display::{DisplayController, DisplayQuirks, Orientation, PanelGeometry, Ssd1306};
 pub use memory::Memory;
 pub use audio_buffer::AudioBuffer;
+pub use audio_router::AudioRouter;
+pub use board::{BoardProfile, DisplayBus, GpioPin};
+pub use logging::{LogCategory, LogConfig, LogLevel};
+pub use realtime::{OverrunPolicy, RealtimeSync};
+pub use rotation::Rotation;
+pub use cpu_detect::{detect_cpu, CpuDetection};
+pub use builder::ArduboyBuilder;
+pub use error::Error;
+pub use hooks::FrameHooks;
+pub use shared_screen::SharedScreen;
 
 // ATmega32u4 constants
 /// Flash memory size: 32 KB
@@ -69,8 +208,12 @@ pub const SRAM_SIZE: usize = 2 * 1024 + 512;
 pub const SRAM_SIZE_328P: usize = 2 * 1024;
 /// EEPROM size: 1 KB
 pub const EEPROM_SIZE: usize = 1024;
-/// CPU clock frequency: 16 MHz
+/// Default CPU clock frequency: 16 MHz (see [`Arduboy::clock_hz`])
 pub const CLOCK_HZ: u32 = 16_000_000;
+/// Lowest and highest accepted [`Arduboy::clock_hz`]
+pub const CLOCK_HZ_RANGE: std::ops::RangeInclusive<u32> = 1_000_000..=64_000_000;
+/// Nominal CPU cycles run by [`Arduboy::run_frame`] (~13.5 ms at 16 MHz)
+pub const FRAME_CYCLES: u64 = CLOCK_HZ as u64 * 135 / 10000;
 
 /// SSD1306 display width in pixels
 pub const SCREEN_WIDTH: usize = 128;
@@ -86,6 +229,16 @@ pub const DATA_SIZE: usize = REG_COUNT + IO_SIZE + SRAM_SIZE;
 /// Total data space (ATmega328P)
 pub const DATA_SIZE_328P: usize = REG_COUNT + IO_SIZE + SRAM_SIZE_328P;
 
+// ATmega2560 constants
+/// Flash memory size: 256 KB for ATmega2560
+pub const FLASH_SIZE_2560: usize = 256 * 1024;
+/// SRAM size: 8 KB for ATmega2560
+pub const SRAM_SIZE_2560: usize = 8 * 1024;
+/// I/O + extended I/O register space size for ATmega2560 (0x20..0x1FF)
+pub const IO_SIZE_2560: usize = 480;
+/// Total data space (ATmega2560)
+pub const DATA_SIZE_2560: usize = REG_COUNT + IO_SIZE_2560 + SRAM_SIZE_2560;
+
 /// Target CPU type
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CpuType {
@@ -93,42 +246,40 @@ pub enum CpuType {
     Atmega32u4,
     /// ATmega328P (Gamebuino Classic, Arduino Uno)
     Atmega328p,
+    /// ATmega2560 (Arduino Mega): 256 KB flash, 22-bit PC. Never
+    /// auto-detected; select it explicitly.
+    Atmega2560,
 }
 
-/// Auto-detect CPU type from flash contents by examining the interrupt vector table.
+/// Auto-detect CPU type from flash contents.
 ///
-/// ATmega328P has 26 vectors (byte addresses 0x00–0x64), while ATmega32u4 has
-/// 43 vectors (0x00–0xA8). We check byte addresses 0x68–0xA8 (vectors 27–43):
-/// if most are JMP/RJMP instructions, the binary targets ATmega32u4; otherwise
-/// it targets ATmega328P (those addresses contain regular code, not vectors).
+/// Checks the interrupt vector table (ATmega32u4 has 43 vectors, ATmega328P
+/// 26), library and USB descriptor strings, and accesses to chip-specific
+/// registers. See [`cpu_detect::detect_cpu`] for the confidence score and
+/// the evidence behind the decision.
 pub fn detect_cpu_type(flash: &[u8]) -> CpuType {
-    if flash.len() < 0xAA {
-        // Too small to tell — very short programs are likely 328P sketches
-        return CpuType::Atmega328p;
-    }
-
-    // Count JMP/RJMP instructions in the 32u4-only vector region (0x68..0xA8)
-    let mut jmp_count = 0;
-    let mut checked = 0;
-    let mut addr = 0x68;
-    while addr <= 0xA8 {
-        let w = (flash[addr] as u16) | ((flash[addr + 1] as u16) << 8);
-        // JMP: 1001_010k_kkkk_110k → (w & 0xFE0E) == 0x940C
-        // RJMP: 1100_kkkk_kkkk_kkkk → (w & 0xF000) == 0xC000
-        if (w & 0xFE0E) == 0x940C || (w & 0xF000) == 0xC000 {
-            jmp_count += 1;
-        }
-        checked += 1;
-        // JMP is 4 bytes, RJMP is 2 bytes; vector entries are always 4 bytes (2 words)
-        addr += 4;
-    }
+    cpu_detect::detect_cpu(flash).cpu
+}
 
-    // If ≥60% of the checked slots look like vector entries → 32u4
-    if jmp_count * 10 >= checked * 6 {
-        CpuType::Atmega32u4
-    } else {
-        CpuType::Atmega328p
+/// Parse a clock frequency: `8M`, `8MHz`, `16.5mhz`, `500k` or plain Hz.
+pub fn parse_clock_hz(spec: &str) -> Result<u32, String> {
+    let s = spec.trim().to_ascii_lowercase();
+    let s = s.strip_suffix("hz").unwrap_or(&s);
+    let (num, mul) = match s.strip_suffix('m') {
+        Some(n) => (n, 1e6),
+        None => match s.strip_suffix('k') {
+            Some(n) => (n, 1e3),
+            None => (s, 1.0),
+        },
+    };
+    let hz = num.trim().parse::<f64>()
+        .map(|v| (v * mul).round())
+        .map_err(|_| format!("bad clock '{}' (e.g. 8M, 16MHz, 24000000)", spec))?;
+    // Saturating cast: NaN, negative and huge values all fall outside
+    if !CLOCK_HZ_RANGE.contains(&(hz as u32)) {
+        return Err(format!("clock '{}' is outside 1–64 MHz", spec));
     }
+    Ok(hz as u32)
 }
 
 // SREG bit positions
@@ -145,6 +296,17 @@ pub const SREG_I: u8 = 7;
 pub const SREG_ADDR: u16 = 0x5F;
 pub const SPH_ADDR: u16 = 0x5E;
 pub const SPL_ADDR: u16 = 0x5D;
+/// RAMPZ: high byte of the ELPM/SPM flash address (RAMPZ:Z)
+pub const RAMPZ_ADDR: u16 = 0x5B;
+/// EIND: high bits of the EIJMP/EICALL target (ATmega2560 only)
+pub const EIND_ADDR: u16 = 0x5C;
+/// Clock prescale register (same address on all three CPUs)
+pub const CLKPR_ADDR: u16 = 0x61;
+/// SPM control register (same address on all three CPUs)
+pub const SPMCSR_ADDR: u16 = 0x57;
+
+/// USB endpoint used by the Arduino CDC driver for host → device data
+const USB_CDC_RX_EP: u8 = 2;
 
 /// Arduboy button identifiers
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -166,11 +328,17 @@ pub struct Arduboy {
     pub timer1: peripherals::Timer16,
     pub timer3: peripherals::Timer16,
     pub timer4: peripherals::Timer4,
-    /// Timer2 (ATmega328P only, 8-bit async)
+    /// Timer2 (ATmega328P and ATmega2560, 8-bit async)
     pub timer2: peripherals::Timer8,
+    /// 16-bit Timer4 (ATmega2560 only)
+    pub timer4_2560: peripherals::Timer16,
+    /// Timer5 (ATmega2560 only)
+    pub timer5: peripherals::Timer16,
     pub spi: peripherals::Spi,
     pub pll: peripherals::Pll,
     pub adc: peripherals::Adc,
+    /// INTn / PCINTn edge detection on ports B..F
+    pub ext_int: peripherals::ExtInt,
     pub eeprom_ctrl: peripherals::EepromCtrl,
     /// Arduboy FX external SPI flash
     pub fx_flash: peripherals::FxFlash,
@@ -183,7 +351,7 @@ pub struct Arduboy {
     pub pin_e: u8,
     pub pin_f: u8,
     /// SPI output buffer with raw port state per byte
-    spi_out: Vec<(u8, u8, u8, u8)>, // (byte, portd_val, portf_val, portc_val)
+    spi_out: Vec<(u8, [u8; 5])>, // (byte, PORTB..PORTF values)
     /// Random state for ADC
     rng_state: u32,
     /// Debug counter: total SPDR writes since reset
@@ -206,35 +374,51 @@ pub struct Arduboy {
     pub dbg_fx_cs_count: u64,
     /// Debug: bytes in current FX CS transaction
     dbg_fx_bytes_in_cs: u32,
-    /// Enable debug output (eprintln)
+    /// Enable all diagnostic output (every category at every level)
     pub debug: bool,
-    /// GPIO speaker 1: previous state for edge detection
-    /// ATmega32u4: PC6 (Arduboy Speaker 1)
-    /// ATmega328P: PD3 (Gamebuino Classic speaker)
-    speaker_prev_pc6: bool,
-    /// GPIO speaker 1: tick of last edge
-    speaker_last_edge: u64,
-    /// GPIO speaker 1: measured half-period in ticks
-    speaker_half_period: u64,
-    /// GPIO speaker 1: tick when last tone was detected
-    speaker_last_active: u64,
-    /// GPIO speaker 2 (PB5): previous state for edge detection
-    speaker2_prev_pb5: bool,
-    /// GPIO speaker 2: tick of last PB5 edge
-    speaker2_last_edge: u64,
-    /// GPIO speaker 2: measured half-period in ticks
-    speaker2_half_period: u64,
-    /// GPIO speaker 2: tick when last tone was detected
-    speaker2_last_active: u64,
+    /// Per-subsystem diagnostic levels
+    pub log: LogConfig,
+    /// GPIO speaker pins and bit-bang tone detection
+    pub audio_router: AudioRouter,
+    /// Explicit board profile (None = infer wiring from CPU type and SPI traffic)
+    pub board: Option<BoardProfile>,
     /// Breakpoint addresses (word addresses)
-    pub breakpoints: Vec<u16>,
+    pub breakpoints: Vec<u32>,
     /// True if execution stopped at a breakpoint
     pub breakpoint_hit: bool,
+    /// Stop like a breakpoint after a BREAK instruction (otherwise a NOP,
+    /// as on a chip without a debugger attached)
+    pub halt_on_break: bool,
+    /// BREAK executed with `halt_on_break` set
+    break_insn_hit: bool,
+    /// Arduboy2 boot logo detection for frontends to skip it (off by default)
+    pub fast_boot: fast_boot::FastBoot,
+    /// Logging tracepoints (kept across resets like breakpoints)
+    pub tracepoints: tracepoint::Tracepoints,
+    /// Flash patches, put back after the program is reloaded
+    pub patches: patch::Patches,
+    /// IPS/BPS patches, in order, applied again when the program is reloaded
+    pub rom_patches: Vec<Vec<u8>>,
+    /// Output rotation of the screen (per game; kept across resets)
+    pub rotation: Rotation,
+    /// Bytes of flash the loaded program spans (after ROM patches)
+    pub program_size: usize,
     /// USB Serial output buffer (UEDATX writes)
     pub serial_buf: Vec<u8>,
-    /// SPI byte trace for diagnostics (first 50 entries when enabled)
-    pub spi_trace: Vec<String>,
-    pub spi_trace_enabled: bool,
+    /// Host → device serial input (USB CDC OUT endpoint / USART0 RX)
+    serial_rx: std::collections::VecDeque<u8>,
+    /// Byte-level SPI trace (zero-cost when disabled)
+    pub spi_trace: spi_trace::SpiTrace,
+    /// Per-frame event timeline (zero-cost when disabled)
+    pub frame_timeline: frame_timeline::FrameTimeline,
+    /// Interrupt storm and missed-deadline diagnostics
+    pub frame_budget: frame_budget::FrameBudget,
+    /// Display flush timing against the SPI minimum (off by default)
+    pub flush_timing: flush_timing::FlushTiming,
+    /// Host-time phase profile of `run_frame()` (zero-cost when disabled)
+    pub perf_trace: perf_trace::PerfTrace,
+    /// Pin/SPI waveform recorder (None = disabled)
+    pub vcd: Option<vcd::VcdRecorder>,
     /// USB endpoint number (UENUM register)
     usb_uenum: u8,
     /// USB device configured flag
@@ -249,14 +433,111 @@ pub struct Arduboy {
     pub led_rx: bool,
     /// EEPROM dirty flag (true if modified since last save)
     pub eeprom_dirty: bool,
+    /// EEPROM wear statistics (kept across resets)
+    pub eeprom_wear: eeprom_wear::EepromWear,
+    /// EEPROM write log and save-range guard
+    pub eeprom_guard: eeprom_guard::EepromGuard,
+    /// Injected peripheral faults (none by default)
+    pub faults: fault::Faults,
+    /// Config-defined memory-mapped devices (none by default)
+    pub devices: virtual_devices::VirtualDevices,
+    /// Displays detected in earlier runs (None = always auto-detect)
+    pub display_cache: Option<display_cache::DisplayCache>,
+    /// Out-of-bounds checker for global objects (off until loaded from an ELF)
+    pub bounds: bounds::BoundsChecker,
+    /// Shadow call stack checking RET/RETI targets (off by default)
+    pub shadow_stack: shadow_stack::ShadowStack,
+    /// Stack pointer limit above the globals (off until configured)
+    pub stack_guard: stack_guard::StackGuard,
+    /// Uninitialized SRAM loads, in strict mode (off by default)
+    pub uninit: uninit::UninitReads,
+    /// Messages from the checkers above and the EEPROM guard
+    pub diagnostics: diagnostics::Diagnostics,
+    /// Tone onset/offset detector (off by default)
+    pub sound_events: sound_events::SoundEvents,
+    /// Recently executed instructions for bug reports (off by default)
+    pub trace_tail: bug_report::TraceTail,
+    /// CPU cycles per [`run_frame`](Self::run_frame) at 16 MHz;
+    /// [`FRAME_CYCLES`] unless trimmed by [`av_sync::AvSync`]. Scaled to
+    /// [`clock_hz`](Self::clock_hz) by [`frame_ticks`](Self::frame_ticks).
+    pub frame_cycles: u64,
+    /// Oscillator frequency in Hz ([`CLOCK_HZ`] by default). One tick is one
+    /// oscillator cycle, so this sets the real time a frame covers and the
+    /// frequency of every tone; e.g. 8 MHz for clones or 24 MHz to overclock.
+    pub clock_hz: u32,
     /// Target CPU type
     pub cpu_type: CpuType,
-    /// Actual SRAM size (varies by CPU type)
-    sram_size: usize,
     /// Execution profiler (zero-cost when disabled)
     pub profiler: profiler::Profiler,
+    /// SPI transaction decoder (zero-cost when disabled)
+    pub spi_decoder: spi_decode::SpiDecoder,
+    /// Run verification log (None = disabled)
+    pub verify: Option<verify::VerifyLog>,
+    /// Pressed buttons (bit per [`input_overlay::BUTTONS`] entry)
+    pub buttons: u8,
+    /// Button masks of recent frames (input viewer)
+    pub input_history: input_overlay::InputHistory,
     /// Advanced debugger (watchpoints, RAM viewer)
     pub debugger: debugger::Debugger,
+    /// Value returned by undriven input pins
+    pub floating_pins: FloatingPinPolicy,
+    /// Next index into a [`FloatingPinPolicy::Scripted`] list
+    floating_script_pos: usize,
+    /// Actions waiting for their frame or tick
+    schedule: schedule::Schedule,
+    /// Screen published to other threads (None = not shared)
+    shared_screen: Option<shared_screen::ScreenPublisher>,
+    /// System clock division from CLKPR: oscillator ticks per CPU cycle
+    clock_div: u32,
+    /// Lowest stack pointer since reset (deepest stack use)
+    pub sp_min: u16,
+    /// Tick until which CLKPS may be written after setting CLKPCE
+    clkpce_until: Option<u64>,
+    /// Tick until which an SPMCSR command waits for its SPM or LPM
+    spm_until: Option<u64>,
+    /// Interrupt sources in vector priority order for this CPU
+    irq_priority: &'static [peripherals::IrqSource],
+    /// Earliest tick at which a timer can raise an enabled interrupt
+    next_irq_tick: u64,
+    /// Re-evaluate interrupts after the current instruction (I/O write,
+    /// input change, SEI/RETI)
+    irq_recheck: bool,
+    /// I was just set: one more instruction runs before an interrupt
+    irq_inhibit: bool,
+    /// Frame stopped by [`run_frame_budgeted`](Self::run_frame_budgeted)
+    frame_run: Option<FrameRun>,
+}
+
+/// Ticks between host clock checks in a budgeted frame
+const BUDGET_CHECK_TICKS: u64 = 1024;
+
+/// A frame in progress, kept while a budgeted run is out of host time.
+struct FrameRun {
+    frame_start: u64,
+    end_tick: u64,
+    last_update: u64,
+    perf_start: Option<std::time::Instant>,
+    /// PC samples for stuck detection (debug only)
+    pc_counts: Option<std::collections::HashMap<u32, u32>>,
+    last_sample: u64,
+    slept: bool,
+}
+
+/// Why [`Arduboy::run_frame`]'s loop stopped.
+enum FrameStop {
+    Done,
+    Breakpoint,
+    OutOfTime,
+}
+
+/// Where [`Arduboy::run_frame_budgeted`] stopped a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCursor {
+    /// Frame in progress
+    pub frame: u32,
+    /// Ticks run so far, out of `total`
+    pub done: u64,
+    pub total: u64,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -266,6 +547,49 @@ pub enum DisplayType {
     Pcd8544,
 }
 
+/// What an input pin reads as when nothing drives it.
+///
+/// A pin floats when DDRx = 0, the PORTx pull-up is off and no external
+/// source (e.g. a pressed button) pulls it low. Real hardware picks up noise
+/// there, which some games sample for entropy.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum FloatingPinPolicy {
+    /// Always read high (previous behaviour)
+    #[default]
+    High,
+    /// Fresh bits from the emulator PRNG on every read (deterministic per seed)
+    Random,
+    /// Cycle through a fixed list of bytes, one per PINx read (an empty
+    /// list reads high)
+    Scripted(Vec<u8>),
+}
+
+impl FloatingPinPolicy {
+    /// Parse `high`, `random` or `script:<byte>,<byte>,...` (hex with `0x` or decimal).
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec {
+            "high" => Ok(FloatingPinPolicy::High),
+            "random" => Ok(FloatingPinPolicy::Random),
+            _ => {
+                let list = spec.strip_prefix("script:")
+                    .ok_or_else(|| format!("unknown floating-pin policy '{}' (high|random|script:..)", spec))?;
+                let bytes = list.split(',').map(|t| {
+                    let t = t.trim();
+                    let v = match t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+                        Some(h) => u8::from_str_radix(h, 16),
+                        None => t.parse::<u8>(),
+                    };
+                    v.map_err(|_| format!("bad byte '{}' in floating-pin script", t))
+                }).collect::<Result<Vec<u8>, String>>()?;
+                if bytes.is_empty() {
+                    return Err("empty floating-pin script".into());
+                }
+                Ok(FloatingPinPolicy::Scripted(bytes))
+            }
+        }
+    }
+}
+
 impl Arduboy {
     /// Create a new Arduboy emulator (ATmega32u4) with all peripherals in reset state.
     pub fn new() -> Self {
@@ -277,12 +601,18 @@ impl Arduboy {
         let sram_size = match cpu_type {
             CpuType::Atmega32u4 => SRAM_SIZE,
             CpuType::Atmega328p => SRAM_SIZE_328P,
+            CpuType::Atmega2560 => SRAM_SIZE_2560,
         };
-        let data_size = REG_COUNT + IO_SIZE + sram_size;
+        let (io_size, flash_size) = match cpu_type {
+            CpuType::Atmega2560 => (IO_SIZE_2560, FLASH_SIZE_2560),
+            _ => (IO_SIZE, FLASH_SIZE),
+        };
+        let data_size = REG_COUNT + io_size + sram_size;
 
-        // Timer0: same register addresses on both chips, different interrupt vectors
+        // Timer0: same register addresses on all chips; the 2560 shares the
+        // 32u4's vectors for Timer0/1/3
         let timer0_addrs = match cpu_type {
-            CpuType::Atmega32u4 => peripherals::Timer8Addrs {
+            CpuType::Atmega32u4 | CpuType::Atmega2560 => peripherals::Timer8Addrs {
                 tifr: 0x35, tccr_a: 0x44, tccr_b: 0x45,
                 ocr_a: 0x47, ocr_b: 0x48, timsk: 0x6E, tcnt: 0x46,
                 int_ovf: peripherals::INT_TIMER0_OVF,
@@ -302,7 +632,7 @@ impl Arduboy {
 
         // Timer1: same register addresses, different vectors
         let timer1_addrs = match cpu_type {
-            CpuType::Atmega32u4 => peripherals::Timer16Addrs {
+            CpuType::Atmega32u4 | CpuType::Atmega2560 => peripherals::Timer16Addrs {
                 tifr: 0x36, tccr_a: 0x80, tccr_b: 0x81, tccr_c: 0x82,
                 ocr_ah: 0x89, ocr_al: 0x88, ocr_bh: 0x8B, ocr_bl: 0x8A,
                 ocr_ch: 0x8D, ocr_cl: 0x8C,
@@ -324,7 +654,7 @@ impl Arduboy {
             },
         };
 
-        // Timer3: ATmega32u4 only
+        // Timer3: ATmega32u4 and ATmega2560
         let timer3_addrs = peripherals::Timer16Addrs {
             tifr: 0x38, tccr_a: 0x90, tccr_b: 0x91, tccr_c: 0x92,
             ocr_ah: 0x99, ocr_al: 0x98, ocr_bh: 0x9B, ocr_bl: 0x9A,
@@ -336,29 +666,59 @@ impl Arduboy {
             int_compc: peripherals::INT_TIMER3_COMPC,
         };
 
-        // Timer2: ATmega328P only (8-bit, different addresses from Timer0)
+        // Timer2: ATmega328P and ATmega2560 (8-bit, different addresses from Timer0)
+        let (int_ovf, int_compa, int_compb) = match cpu_type {
+            CpuType::Atmega2560 => (peripherals::INT_2560_TIMER2_OVF, peripherals::INT_2560_TIMER2_COMPA, peripherals::INT_2560_TIMER2_COMPB),
+            _ => (peripherals::INT_328P_TIMER2_OVF, peripherals::INT_328P_TIMER2_COMPA, peripherals::INT_328P_TIMER2_COMPB),
+        };
         let timer2_addrs = peripherals::Timer8Addrs {
             tifr: 0x37, tccr_a: 0xB0, tccr_b: 0xB1,
             ocr_a: 0xB3, ocr_b: 0xB4, timsk: 0x70, tcnt: 0xB2,
-            int_ovf: peripherals::INT_328P_TIMER2_OVF,
-            int_compa: peripherals::INT_328P_TIMER2_COMPA,
-            int_compb: peripherals::INT_328P_TIMER2_COMPB,
+            int_ovf, int_compa, int_compb,
             is_timer2: true,
         };
 
+        // Timer4/Timer5: ATmega2560 only (16-bit, unlike the 32u4's Timer4)
+        let timer4_2560_addrs = peripherals::Timer16Addrs {
+            tifr: 0x39, tccr_a: 0xA0, tccr_b: 0xA1, tccr_c: 0xA2,
+            ocr_ah: 0xA9, ocr_al: 0xA8, ocr_bh: 0xAB, ocr_bl: 0xAA,
+            ocr_ch: 0xAD, ocr_cl: 0xAC,
+            timsk: 0x72, tcnth: 0xA5, tcntl: 0xA4,
+            int_ovf: peripherals::INT_2560_TIMER4_OVF,
+            int_compa: peripherals::INT_2560_TIMER4_COMPA,
+            int_compb: peripherals::INT_2560_TIMER4_COMPB,
+            int_compc: peripherals::INT_2560_TIMER4_COMPC,
+        };
+        let timer5_addrs = peripherals::Timer16Addrs {
+            tifr: 0x3A, tccr_a: 0x120, tccr_b: 0x121, tccr_c: 0x122,
+            ocr_ah: 0x129, ocr_al: 0x128, ocr_bh: 0x12B, ocr_bl: 0x12A,
+            ocr_ch: 0x12D, ocr_cl: 0x12C,
+            timsk: 0x73, tcnth: 0x125, tcntl: 0x124,
+            int_ovf: peripherals::INT_2560_TIMER5_OVF,
+            int_compa: peripherals::INT_2560_TIMER5_COMPA,
+            int_compb: peripherals::INT_2560_TIMER5_COMPB,
+            int_compc: peripherals::INT_2560_TIMER5_COMPC,
+        };
+
         let mut ard = Arduboy {
             cpu: Cpu::new(),
-            mem: Memory::new_with_size(data_size),
+            mem: Memory::new_with_sizes(data_size, flash_size),
             display: Ssd1306::new(),
             timer0: peripherals::Timer8::new(timer0_addrs),
             timer1: peripherals::Timer16::new(timer1_addrs),
             timer3: peripherals::Timer16::new(timer3_addrs),
             timer4: peripherals::Timer4::new(),
             timer2: peripherals::Timer8::new(timer2_addrs),
+            timer4_2560: peripherals::Timer16::new(timer4_2560_addrs),
+            timer5: peripherals::Timer16::new(timer5_addrs),
             spi: peripherals::Spi::new(),
             pll: peripherals::Pll::new(),
             adc: peripherals::Adc::new(),
-            eeprom_ctrl: peripherals::EepromCtrl::new(),
+            ext_int: peripherals::ExtInt::new(cpu_type),
+            eeprom_ctrl: peripherals::EepromCtrl::new(match cpu_type {
+                CpuType::Atmega328p => peripherals::INT_328P_EE_READY,
+                _ => peripherals::INT_EE_READY,
+            }),
             fx_flash: peripherals::FxFlash::new(),
             spdr_in: 0,
             pin_b: 0xFF, pin_c: 0xFF, pin_d: 0xFF, pin_e: 0xFF, pin_f: 0xFF,
@@ -377,19 +737,27 @@ impl Arduboy {
             dbg_fx_cs_count: 0,
             dbg_fx_bytes_in_cs: 0,
             debug: false,
-            speaker_prev_pc6: false,
-            speaker_last_edge: 0,
-            speaker_half_period: 0,
-            speaker_last_active: 0,
-            speaker2_prev_pb5: false,
-            speaker2_last_edge: 0,
-            speaker2_half_period: 0,
-            speaker2_last_active: 0,
+            log: LogConfig::new(),
+            audio_router: AudioRouter::for_cpu(cpu_type),
+            board: None,
             breakpoints: Vec::new(),
             breakpoint_hit: false,
+            halt_on_break: false,
+            break_insn_hit: false,
+            fast_boot: fast_boot::FastBoot::new(),
+            tracepoints: tracepoint::Tracepoints::default(),
+            patches: patch::Patches::default(),
+            rom_patches: Vec::new(),
+            rotation: Rotation::None,
+            program_size: 0,
             serial_buf: Vec::new(),
-            spi_trace: Vec::new(),
-            spi_trace_enabled: false,
+            serial_rx: std::collections::VecDeque::new(),
+            spi_trace: spi_trace::SpiTrace::new(),
+            frame_timeline: frame_timeline::FrameTimeline::new(cpu_type),
+            frame_budget: frame_budget::FrameBudget::new(cpu_type),
+            flush_timing: flush_timing::FlushTiming::default(),
+            perf_trace: perf_trace::PerfTrace::new(),
+            vcd: None,
             usb_uenum: 0,
             usb_configured: false,
             audio_buf: AudioBuffer::new(),
@@ -397,10 +765,40 @@ impl Arduboy {
             led_tx: false,
             led_rx: false,
             eeprom_dirty: false,
+            eeprom_wear: eeprom_wear::EepromWear::new(EEPROM_SIZE),
+            eeprom_guard: eeprom_guard::EepromGuard::new(EEPROM_SIZE),
+            faults: fault::Faults::default(),
+            devices: virtual_devices::VirtualDevices::default(),
+            display_cache: None,
+            bounds: bounds::BoundsChecker::default(),
+            shadow_stack: shadow_stack::ShadowStack::default(),
+            stack_guard: stack_guard::StackGuard::default(),
+            uninit: uninit::UninitReads::default(),
+            diagnostics: diagnostics::Diagnostics::default(),
+            sound_events: sound_events::SoundEvents::default(),
+            trace_tail: bug_report::TraceTail::default(),
+            frame_cycles: FRAME_CYCLES,
+            clock_hz: CLOCK_HZ,
             cpu_type,
-            sram_size,
             profiler: profiler::Profiler::new(),
+            spi_decoder: spi_decode::SpiDecoder::new(),
+            verify: None,
+            buttons: 0,
+            input_history: input_overlay::InputHistory::new(),
             debugger: debugger::Debugger::new(),
+            floating_pins: FloatingPinPolicy::High,
+            floating_script_pos: 0,
+            schedule: schedule::Schedule::default(),
+            shared_screen: None,
+            clock_div: 1,
+            clkpce_until: None,
+            spm_until: None,
+            sp_min: (data_size - 1) as u16,
+            irq_priority: peripherals::irq_priority(cpu_type),
+            next_irq_tick: 0,
+            irq_recheck: true,
+            irq_inhibit: false,
+            frame_run: None,
         };
         // Initialize SP to top of SRAM
         let sp = (data_size - 1) as u16;
@@ -408,19 +806,96 @@ impl Arduboy {
         ard.mem.data[SPL_ADDR as usize] = (sp & 0xFF) as u8;
         ard.cpu.sp = sp;
 
+        // Signature and fuses read back through LPM (factory bootloader settings)
+        match cpu_type {
+            CpuType::Atmega32u4 => {}
+            CpuType::Atmega328p => {
+                ard.mem.signature = [0x1E, 0x95, 0x0F];
+                ard.mem.fuse_bits[1] = 0x0F;
+            }
+            CpuType::Atmega2560 => {
+                ard.mem.signature = [0x1E, 0x98, 0x01];
+                ard.mem.fuse_bits[1] = 0x0F;
+            }
+        }
+        ard.set_fuses(fuses::Fuses::for_cpu(cpu_type));
+
         // ATmega328P defaults: PCD8544 display, DC=PC2(A2), CS=PC1(A1).
         // Auto-detection in flush_spi may override CS/DC pins for non-standard configs.
 
         ard
     }
 
+    /// Create an emulator wired as described by a board profile.
+    ///
+    /// Buttons, display CS/DC, speaker pins and the display controller come
+    /// from the profile instead of the per-CPU defaults and auto-detection.
+    pub fn with_board(profile: BoardProfile) -> Self {
+        let mut ard = Self::new_with_cpu(profile.cpu);
+        ard.set_display_quirks(profile.controller);
+        ard.audio_router.set_pins(profile.speaker[0], profile.speaker[1]);
+        ard.display_type = profile.display;
+        ard.board = Some(profile);
+        ard
+    }
+
     /// Load an Intel HEX file into flash memory and reset the CPU.
     ///
     /// Returns the number of bytes loaded on success.
-    pub fn load_hex(&mut self, hex_str: &str) -> Result<usize, String> {
-        let size = hex::parse_hex(hex_str, &mut self.mem.flash)?;
+    pub fn load_hex(&mut self, hex_str: &str) -> Result<usize, Error> {
+        self.program_size = hex::parse_hex(hex_str, &mut self.mem.flash)?;
+        self.fast_boot.set_symbols(&Default::default());
+        self.apply_rom_patches()?;
+        self.patches.apply(&mut self.mem.flash);
         self.reset();
-        Ok(size)
+        Ok(self.program_size)
+    }
+
+    /// Apply an IPS or BPS patch (see [`romhack`]) to the loaded program
+    /// and reset. Add ROM patches before any flash [`patch`]es; the patch
+    /// is kept and applied again when the program is reloaded.
+    ///
+    /// Returns the size of the patched program.
+    pub fn add_rom_patch(&mut self, patch: Vec<u8>) -> Result<usize, Error> {
+        self.patch_program(&patch)?;
+        self.rom_patches.push(patch);
+        self.reset();
+        Ok(self.program_size)
+    }
+
+    fn apply_rom_patches(&mut self) -> Result<(), Error> {
+        for patch in std::mem::take(&mut self.rom_patches) {
+            let result = self.patch_program(&patch);
+            self.rom_patches.push(patch);
+            result?;
+        }
+        Ok(())
+    }
+
+    fn patch_program(&mut self, patch: &[u8]) -> Result<(), Error> {
+        let image = romhack::apply(&self.mem.flash[..self.program_size], patch)?;
+        let flash = self.mem.flash.len();
+        if image.len() > flash {
+            return Err(error::RomPatchError::TooLarge { size: image.len(), flash }.into());
+        }
+        self.mem.flash[image.len()..self.program_size.max(image.len())].fill(0xFF);
+        self.mem.flash[..image.len()].copy_from_slice(&image);
+        self.program_size = image.len();
+        Ok(())
+    }
+
+    /// Add patches to the loaded program (see [`patch`]).
+    pub fn add_patches(&mut self, patches: patch::Patches) {
+        self.patches.extend(patches, &mut self.mem.flash);
+        self.mem.invalidate_decoded();
+    }
+
+    /// Enable or disable a patch by index or name; false if there is no
+    /// such patch.
+    pub fn set_patch_enabled(&mut self, which: &str, on: bool) -> bool {
+        let found = self.patches.set_enabled(which, on, &mut self.mem.flash);
+        self.mem.invalidate_decoded();
+        found
     }
 
     /// Load FX flash data from binary at offset 0. Use load_fx_layout for correct placement.
@@ -453,8 +928,9 @@ impl Arduboy {
         // Data area: 256-byte (page) aligned
         let data_pages = (data.len() + 255) / 256;
 
-        let save_start_page = TOTAL_PAGES - save_pages;
-        let data_start_page = save_start_page - data_pages;
+        // Oversized images are clipped at the start of the chip
+        let save_start_page = TOTAL_PAGES.saturating_sub(save_pages);
+        let data_start_page = save_start_page.saturating_sub(data_pages);
 
         let data_offset = data_start_page * 256;
         let save_offset = save_start_page * 256;
@@ -465,80 +941,289 @@ impl Arduboy {
                 self.fx_flash.load_data_at(save_data, save_offset);
             }
         }
+        self.fx_flash.save_offset = save_offset;
 
         (data_start_page as u16, save_start_page as u16)
     }
 
+    /// Write the FX save area to `path` (the game's `.fxsave` file) if the
+    /// game changed it since it was loaded or last flushed.
+    ///
+    /// Returns whether the file was written.
+    pub fn flush_fx_save(&mut self, path: &std::path::Path) -> Result<bool, Error> {
+        if !self.fx_flash.save_modified() {
+            return Ok(false);
+        }
+        std::fs::write(path, self.fx_flash.save_area())
+            .map_err(|source| Error::Io { op: "Write", source })?;
+        self.fx_flash.commit_save();
+        Ok(true)
+    }
+
     /// Reset the CPU and all peripherals to power-on state.
     ///
-    /// Flash and FX flash data are preserved (they represent ROM content).
+    /// Flash and FX flash data are preserved (they represent ROM content);
+    /// instructions are decoded again from it. This is [`clear_display`],
+    /// [`reset_peripherals`] and [`reset_cpu_only`] on cleared data memory,
+    /// plus the frame-level state (schedule, sound events, boot logo skip).
+    ///
+    /// [`clear_display`]: Self::clear_display
+    /// [`reset_peripherals`]: Self::reset_peripherals
+    /// [`reset_cpu_only`]: Self::reset_cpu_only
     pub fn reset(&mut self) {
-        self.cpu = Cpu::new();
+        if let Some(v) = self.verify.as_mut() {
+            v.mark(self.frame_count, "reset");
+        }
+        self.cpu.tick = 0;
+        self.mem.invalidate_decoded();
+        self.fast_boot.restart();
+        self.frame_budget.reset();
         self.mem.data.fill(0);
-        let data_size = REG_COUNT + IO_SIZE + self.sram_size;
-        let sp = (data_size - 1) as u16;
+        self.mem.set_all_init(false);
+        self.floating_script_pos = 0;
+        self.schedule.clear();
+        self.sound_events.clear();
+        self.clear_display();
+        if self.eeprom_guard.game_ranges.is_some() {
+            let hash = self.program_hash();
+            self.eeprom_guard.select_game(hash, &mut self.diagnostics);
+        }
+        self.reset_peripherals();
+        self.reset_cpu_only();
+        // Note: eeprom_dirty is NOT cleared on reset (tracks unsaved changes)
+        // Note: FX flash data is NOT cleared on reset (persistent storage)
+        // Note: breakpoints are NOT cleared on reset
+    }
+
+    /// Restart the CPU core alone: R0–R31, SREG, RAMPZ and EIND cleared,
+    /// SP at the top of SRAM and PC at the reset vector (or the boot
+    /// section when BOOTRST is programmed). A frame in progress, pending
+    /// interrupt state, debugger stops and the stack tracking are dropped.
+    ///
+    /// SRAM, I/O registers, peripherals, the display, EEPROM and flash keep
+    /// their contents, so the game restarts against hardware as it left it.
+    /// The cycle counter runs on: peripheral timing is measured against it.
+    pub fn reset_cpu_only(&mut self) {
+        self.cpu = Cpu { tick: self.cpu.tick, ..Cpu::new() };
+        self.mem.data[..REG_COUNT].fill(0);
+        for addr in [RAMPZ_ADDR, EIND_ADDR, SREG_ADDR] {
+            self.mem.data[addr as usize] = 0;
+        }
+        self.shadow_stack.clear();
+        self.stack_guard.clear();
+        self.diagnostics.take_trip();
+        let sp = (self.mem.data.len() - 1) as u16;
         self.mem.data[SPH_ADDR as usize] = (sp >> 8) as u8;
         self.mem.data[SPL_ADDR as usize] = (sp & 0xFF) as u8;
         self.cpu.sp = sp;
-        self.display = Ssd1306::new();
-        self.pcd8544 = pcd8544::Pcd8544::new();
-        self.display_type = if self.cpu_type == CpuType::Atmega328p {
-            DisplayType::Pcd8544
-        } else {
-            DisplayType::Unknown
-        };
+        self.sp_min = sp;
+        self.next_irq_tick = 0;
+        self.irq_recheck = true;
+        self.irq_inhibit = false;
+        self.frame_run = None;
+        self.breakpoint_hit = false;
+        self.break_insn_hit = false;
+        // An erased or empty boot section runs through to 0 on hardware too
+        if let Some((start, end)) = self.boot_section() {
+            let code = &self.mem.flash[start as usize * 2..end as usize * 2];
+            if code.iter().any(|&b| b != 0x00 && b != 0xFF) {
+                self.cpu.pc = start;
+            }
+        }
+    }
+
+    /// Return the on-chip peripherals to power-on state: the I/O registers
+    /// (except SP, SREG, RAMPZ and EIND, which belong to the CPU), timers,
+    /// SPI, PLL, ADC, EEPROM and SPM control, pins, USB and USART, the
+    /// LEDs, audio routing and the clock prescaler (from CKDIV8).
+    ///
+    /// The CPU, SRAM, the display controller, EEPROM contents and FX flash
+    /// are kept. The serial and SPI capture buffers are emptied.
+    pub fn reset_peripherals(&mut self) {
+        // RAMPZ, EIND, SPL, SPH and SREG (0x5B-0x5F) survive
+        let core = self.mem.data[RAMPZ_ADDR as usize..=SREG_ADDR as usize].to_vec();
+        let io = REG_COUNT..self.ram_start();
+        self.mem.data[io].fill(0);
+        self.mem.data[RAMPZ_ADDR as usize..=SREG_ADDR as usize].copy_from_slice(&core);
+        self.next_irq_tick = 0;
+        self.irq_recheck = true;
         self.timer0.reset();
         self.timer1.reset();
         self.timer3.reset();
         self.timer4.reset();
         self.timer2.reset();
+        self.timer4_2560.reset();
+        self.timer5.reset();
         self.spi.reset();
         self.pll.reset();
         self.adc.reset();
         self.eeprom_ctrl.reset();
+        self.spm_until = None;
         self.pin_b = 0xFF;
         self.pin_c = 0xFF;
         self.pin_d = 0xFF;
         self.pin_e = 0xFF;
         self.pin_f = 0xFF;
+        self.ext_int.sync(self.pin_levels());
         self.spi_out.clear();
         self.spdr_in = 0;
         self.fx_cs_prev = true;
-        // Default Gamebuino Classic: DC=PC2, CS=PC1
-        self.pcd_cs_bit = if self.cpu_type == CpuType::Atmega328p { 1 } else { 0xFF };
-        self.pcd_dc_bit = if self.cpu_type == CpuType::Atmega328p { 2 } else { 0xFF };
         self.dbg_fx_transfers = 0;
         self.dbg_fx_cs_count = 0;
         self.dbg_fx_bytes_in_cs = 0;
-        self.speaker_prev_pc6 = false;
-        self.speaker_last_edge = 0;
-        self.speaker_half_period = 0;
-        self.speaker_last_active = 0;
-        self.speaker2_prev_pb5 = false;
-        self.speaker2_last_edge = 0;
-        self.speaker2_half_period = 0;
-        self.speaker2_last_active = 0;
-        self.breakpoint_hit = false;
+        self.audio_router.reset();
         self.serial_buf.clear();
+        self.serial_rx.clear();
         self.spi_trace.clear();
         self.usb_uenum = 0;
         self.usb_configured = false;
         self.led_rgb = (0, 0, 0);
         self.led_tx = false;
         self.led_rx = false;
-        // USART0 initial state (328P): UDRE0=1 (ready to transmit)
-        if self.cpu_type == CpuType::Atmega328p {
+        // USART0 initial state (328P, 2560): UDRE0=1 (ready to transmit)
+        if self.cpu_type != CpuType::Atmega32u4 {
             self.mem.data[0xC0] = 0x20; // UCSR0A: UDRE0=1
         }
-        // Note: eeprom_dirty is NOT cleared on reset (tracks unsaved changes)
-        // Note: FX flash data is NOT cleared on reset (persistent storage)
-        // Note: breakpoints are NOT cleared on reset
+        // Fuses: CKDIV8 starts the clock at /8
+        let fuses = self.fuses();
+        self.clkpce_until = None;
+        self.mem.data[CLKPR_ADDR as usize] = if fuses.ckdiv8() { 3 } else { 0 };
+        self.sync_clock_div();
+    }
+
+    /// Blank the display and return the display controllers to power-on
+    /// state (display off, default contrast and addressing). The display
+    /// type is detected again unless a board profile fixes it.
+    ///
+    /// Only the display changes; the game keeps running and redraws on
+    /// its next frame once it sends its init sequence again.
+    pub fn clear_display(&mut self) {
+        let geometry = self.display.geometry;
+        self.display = Ssd1306::with_quirks(self.display.quirks);
+        self.display.geometry = geometry;
+        self.display.dirty = true;
+        self.pcd8544 = pcd8544::Pcd8544::new();
+        self.pcd8544.dirty = true;
+        self.display_type = match &self.board {
+            Some(b) => b.display,
+            None if self.cpu_type == CpuType::Atmega328p => DisplayType::Pcd8544,
+            None => DisplayType::Unknown,
+        };
+        // Default Gamebuino Classic: DC=PC2, CS=PC1
+        self.pcd_cs_bit = if self.cpu_type == CpuType::Atmega328p { 1 } else { 0xFF };
+        self.pcd_dc_bit = if self.cpu_type == CpuType::Atmega328p { 2 } else { 0xFF };
+        if self.board.is_none() {
+            self.apply_cached_display();
+        }
+    }
+
+    /// Current fuse bytes (as LPM reads them back).
+    pub fn fuses(&self) -> fuses::Fuses {
+        let [low, _, ext, high] = self.mem.fuse_bits;
+        fuses::Fuses { low, high, extended: Some(ext) }
+    }
+
+    /// Program the fuse bytes. CKDIV8 and BOOTRST take effect at the next
+    /// reset; an absent extended byte keeps the current one.
+    pub fn set_fuses(&mut self, fuses: fuses::Fuses) {
+        self.mem.fuse_bits[0] = fuses.low;
+        self.mem.fuse_bits[3] = fuses.high;
+        if let Some(ext) = fuses.extended {
+            self.mem.fuse_bits[2] = ext;
+        }
+    }
+
+    /// Boot section as (start, end) word addresses when BOOTRST is
+    /// programmed, sized by BOOTSZ.
+    pub fn boot_section(&self) -> Option<(u32, u32)> {
+        let fuses = self.fuses();
+        if !fuses.bootrst() {
+            return None;
+        }
+        let end = (self.mem.flash.len() / 2) as u32;
+        Some((end - fuses.boot_size_words(self.cpu_type), end))
+    }
+
+    /// SRAM occupancy: static data and heap from `elf` (if given), the
+    /// current SP and [`sp_min`](Self::sp_min).
+    pub fn sram_map(&self, elf: Option<&elf::ElfFile>) -> sram_map::SramMap {
+        sram_map::SramMap::new(elf, &self.mem.data, self.ram_start() as u16, self.cpu.sp, self.sp_min)
+    }
+
+    /// First SRAM address, just past the registers and I/O space.
+    fn ram_start(&self) -> usize {
+        match self.cpu_type {
+            CpuType::Atmega2560 => REG_COUNT + IO_SIZE_2560,
+            _ => REG_COUNT + IO_SIZE,
+        }
+    }
+
+    /// System clock division currently selected by CLKPR (1–256).
+    pub fn clock_div(&self) -> u32 {
+        self.clock_div
+    }
+
+    /// Take the clock division from CLKPR and pass it on to the timers.
+    fn sync_clock_div(&mut self) {
+        let clkps = self.mem.data[CLKPR_ADDR as usize] & 0x0F;
+        self.clock_div = 1 << clkps.min(8);
+        self.timer0.set_clock_div(self.clock_div);
+        self.timer1.set_clock_div(self.clock_div);
+        self.timer3.set_clock_div(self.clock_div);
+        self.timer4.set_clock_div(self.clock_div);
+        self.timer2.set_clock_div(self.clock_div);
+        self.timer4_2560.set_clock_div(self.clock_div);
+        self.timer5.set_clock_div(self.clock_div);
+        // Timer event ticks move with the clock
+        self.irq_recheck = true;
+    }
+
+    /// CLKPR write: CLKPCE alone opens a four-cycle window in which the
+    /// next write (CLKPCE clear) sets CLKPS. Reserved values are ignored.
+    fn write_clkpr(&mut self, value: u8) {
+        if value == 0x80 {
+            self.clkpce_until = Some(self.cpu.tick + 4 * self.clock_div as u64);
+            return;
+        }
+        let armed = self.clkpce_until.take().is_some_and(|t| self.cpu.tick <= t);
+        if armed && value & 0x80 == 0 && value & 0x0F <= 8 {
+            self.mem.data[CLKPR_ADDR as usize] = value & 0x0F;
+            self.sync_clock_div();
+        }
+    }
+
+    /// SPMCSR write: a command (SPMEN set) lapses unless SPM or LPM
+    /// follows within four cycles.
+    fn write_spmcsr(&mut self, value: u8) {
+        self.spm_until = (value & 0x01 != 0).then_some(self.cpu.tick + 4 * self.clock_div as u64);
+        self.mem.data[SPMCSR_ADDR as usize] = value;
+    }
+
+    /// SPMCSR as SPM and LPM see it: a lapsed command is cleared, keeping
+    /// SPMIE and RWWSB.
+    fn spmcsr(&mut self) -> u8 {
+        if self.spm_until.is_some_and(|t| self.cpu.tick > t) {
+            self.spm_until = None;
+            self.mem.data[SPMCSR_ADDR as usize] &= 0xC0;
+        }
+        self.mem.data[SPMCSR_ADDR as usize]
     }
 
     /// Set button state (true = pressed)
     pub fn set_button(&mut self, btn: Button, pressed: bool) {
+        let bit = input_overlay::button_bit(btn);
+        if pressed { self.buttons |= bit; } else { self.buttons &= !bit; }
+        self.irq_recheck = true;
+
         // Active-low: pressed = bit cleared, released = bit set
 
+        if let Some(b) = &self.board {
+            let pin = b.buttons[bit.trailing_zeros() as usize];
+            let reg = self.pin_reg(pin.port_index());
+            if pressed { *reg &= !pin.mask(); } else { *reg |= pin.mask(); }
+            return;
+        }
+
         match self.cpu_type {
             CpuType::Atmega32u4 => {
                 // --- Arduboy pin mapping (32u4) ---
@@ -583,24 +1268,136 @@ impl Arduboy {
                 };
                 if pressed { *pin &= !(1 << bit); } else { *pin |= 1 << bit; }
             }
+            // No standard wiring; buttons need a board profile
+            CpuType::Atmega2560 => {}
+        }
+    }
+
+    /// Schedule a button change at an exact CPU tick.
+    ///
+    /// The change is applied by [`run_frame`](Self::run_frame) before the
+    /// first instruction starting at or after `tick`, so games that poll
+    /// several times per frame see it mid-frame. Ticks already passed apply
+    /// before the next instruction. Pending events are dropped on reset.
+    pub fn queue_button_event(&mut self, tick: u64, button: Button, pressed: bool) {
+        self.schedule(schedule::At::Tick(tick), schedule::Action::Button(button, pressed));
+    }
+
+    /// Number of queued button events not yet applied.
+    pub fn pending_button_events(&self) -> usize {
+        self.schedule.pending_buttons()
+    }
+
+    /// Fix the 10-bit reading of ADC `channel` (0–15), or return it to noise
+    /// with None. Kept across resets.
+    pub fn set_adc_input(&mut self, channel: u8, value: Option<u16>) {
+        if let Some(input) = self.adc.inputs.get_mut(channel as usize) {
+            *input = value;
+        }
+    }
+
+    /// PINx input latch for a port index (0 = B ... 4 = F).
+    fn pin_reg(&mut self, port: usize) -> &mut u8 {
+        match port {
+            0 => &mut self.pin_b,
+            1 => &mut self.pin_c,
+            2 => &mut self.pin_d,
+            3 => &mut self.pin_e,
+            _ => &mut self.pin_f,
         }
     }
 
-    /// Run one frame of emulation (~13.5ms = ~216000 cycles at 16MHz)
+    /// Frames run since power-on (not cleared by reset).
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Ticks covered by one [`run_frame`](Self::run_frame): `frame_cycles`
+    /// scaled from 16 MHz to [`clock_hz`](Self::clock_hz), so a frame is
+    /// always ~13.5 ms of emulated time.
+    pub fn frame_ticks(&self) -> u64 {
+        self.frame_cycles * self.clock_hz as u64 / CLOCK_HZ as u64
+    }
+
+    /// Run one frame of emulation (~13.5 ms = ~216000 cycles at 16 MHz).
+    /// A frame left unfinished by
+    /// [`run_frame_budgeted`](Self::run_frame_budgeted) is completed.
     pub fn run_frame(&mut self) {
-        let cycles = (CLOCK_HZ as u64 * 135) / 10000; // 216000
-        let end_tick = self.cpu.tick + cycles;
-        let mut last_update = self.cpu.tick;
+        let mut run = match self.frame_run.take() {
+            Some(run) => run,
+            None => self.begin_frame_run(),
+        };
+        if let FrameStop::Done = self.run_frame_ticks(&mut run, None) {
+            self.finish_frame(run);
+        }
+    }
+
+    /// Run at most about `max_host_micros` of host time of the current
+    /// frame, for single-threaded frontends that must not block their UI
+    /// thread on a slow machine. Returns None when the frame completed
+    /// (or stopped at a breakpoint, see
+    /// [`breakpoint_hit`](Self::breakpoint_hit)), or where it stopped; the
+    /// next call, or [`run_frame`](Self::run_frame), resumes it. Each call
+    /// runs at least a thousand cycles, so emulation always progresses.
+    pub fn run_frame_budgeted(&mut self, max_host_micros: u64) -> Option<FrameCursor> {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_micros(max_host_micros);
+        let mut run = match self.frame_run.take() {
+            Some(run) => run,
+            None => self.begin_frame_run(),
+        };
+        match self.run_frame_ticks(&mut run, Some(deadline)) {
+            FrameStop::Done => {
+                self.finish_frame(run);
+                None
+            }
+            FrameStop::Breakpoint => None,
+            FrameStop::OutOfTime => {
+                let cursor = FrameCursor {
+                    frame: self.frame_count,
+                    done: self.cpu.tick - run.frame_start,
+                    total: run.end_tick - run.frame_start,
+                };
+                self.frame_run = Some(run);
+                Some(cursor)
+            }
+        }
+    }
+
+    /// Start a frame: scheduled actions, recorders and the frame's end tick.
+    fn begin_frame_run(&mut self) -> FrameRun {
+        let frame_start = self.cpu.tick;
+        let end_tick = frame_start + self.frame_ticks();
+        let perf_start = self.perf_trace.begin_frame();
 
         // Begin sample-accurate audio recording for this frame
         self.audio_buf.begin_frame(self.cpu.tick);
 
+        if self.frame_timeline.enabled {
+            self.frame_timeline.begin_frame(self.frame_count, frame_start);
+        }
+        if let Some(v) = self.verify.as_mut() {
+            v.begin_frame(self.frame_count, [self.pin_b, self.pin_c, self.pin_d, self.pin_e, self.pin_f]);
+        }
+        // Actions scheduled for this frame (or overdue ticks)
+        self.run_scheduled(frame_start);
+        self.input_history.push(self.buttons);
+
         // PC sampling for stuck detection (debug only)
-        let mut pc_counts: Option<std::collections::HashMap<u16, u32>> =
-            if self.debug { Some(std::collections::HashMap::new()) } else { None };
-        let mut last_sample = self.cpu.tick;
+        let pc_counts = self.log_enabled(LogCategory::Cpu, LogLevel::Debug).then(std::collections::HashMap::new);
+        FrameRun {
+            frame_start, end_tick, last_update: frame_start, perf_start,
+            pc_counts, last_sample: frame_start, slept: false,
+        }
+    }
 
-        while self.cpu.tick < end_tick {
+    /// Run the frame up to its end tick, a stop condition or `deadline`.
+    fn run_frame_ticks(&mut self, run: &mut FrameRun, deadline: Option<std::time::Instant>) -> FrameStop {
+        let mut lap = run.perf_start.map(|_| std::time::Instant::now());
+        let resumed = self.cpu.tick;
+        while self.cpu.tick < run.end_tick {
+            if self.schedule.tick_due(self.cpu.tick) {
+                self.run_scheduled(run.frame_start);
+            }
             if !self.cpu.sleeping {
                 let pc_byte = self.cpu.pc as usize * 2;
                 if pc_byte >= self.mem.flash.len() {
@@ -610,18 +1407,31 @@ impl Arduboy {
                 // Check breakpoints
                 if !self.breakpoints.is_empty() && self.breakpoints.contains(&self.cpu.pc) {
                     self.breakpoint_hit = true;
-                    return;
+                    return FrameStop::Breakpoint;
+                }
+
+                // BREAK instruction with a debugger attached
+                if self.break_insn_hit {
+                    self.break_insn_hit = false;
+                    self.breakpoint_hit = true;
+                    return FrameStop::Breakpoint;
                 }
 
                 // Check watchpoint hits
                 if self.debugger.watch_hit.is_some() {
                     self.breakpoint_hit = true;
-                    return;
+                    return FrameStop::Breakpoint;
+                }
+
+                // Shadow stack mismatches, stack overflows, uninitialized reads
+                if self.diagnostics.take_trip() {
+                    self.breakpoint_hit = true;
+                    return FrameStop::Breakpoint;
                 }
                 
-                if let Some(ref mut counts) = pc_counts {
-                    if self.cpu.tick - last_sample >= 64 {
-                        last_sample = self.cpu.tick;
+                if let Some(ref mut counts) = run.pc_counts {
+                    if self.cpu.tick - run.last_sample >= 64 {
+                        run.last_sample = self.cpu.tick;
                         *counts.entry(self.cpu.pc).or_insert(0) += 1;
                     }
                 }
@@ -629,31 +1439,88 @@ impl Arduboy {
                 self.step();
             } else {
                 self.cpu.tick += 4;
+                run.slept = true;
+            }
+            if self.irq_recheck || self.cpu.tick >= self.next_irq_tick {
+                self.service_interrupts();
+            }
+            if self.vcd.is_some() {
+                self.vcd_sample();
             }
 
-            if self.cpu.tick - last_update >= 128 {
-                last_update = self.cpu.tick;
+            if self.cpu.tick - run.last_update >= 128 {
+                run.last_update = self.cpu.tick;
+                self.perf_trace.lap(perf_trace::Phase::Cpu, &mut lap);
                 self.flush_spi();
+                self.perf_trace.lap(perf_trace::Phase::SpiFlush, &mut lap);
                 self.update_peripherals();
+                if self.sound_events.enabled {
+                    self.sample_sound_events();
+                }
+                self.perf_trace.lap(perf_trace::Phase::Peripherals, &mut lap);
+                if deadline.is_some_and(|d| self.cpu.tick - resumed >= BUDGET_CHECK_TICKS && std::time::Instant::now() >= d) {
+                    return FrameStop::OutOfTime;
+                }
             }
         }
+        self.perf_trace.lap(perf_trace::Phase::Cpu, &mut lap);
+        FrameStop::Done
+    }
+
+    /// End a frame: peripherals, audio, recorders and the frame counter.
+    fn finish_frame(&mut self, run: FrameRun) {
+        let mut lap = run.perf_start.map(|_| std::time::Instant::now());
         self.update_peripherals();
+        self.perf_trace.lap(perf_trace::Phase::Peripherals, &mut lap);
         self.flush_spi();
+        self.perf_trace.lap(perf_trace::Phase::SpiFlush, &mut lap);
 
         // End sample-accurate audio recording for this frame
         self.audio_buf.end_frame(self.cpu.tick);
-        
+        self.perf_trace.lap(perf_trace::Phase::Audio, &mut lap);
+
+        if let Some(v) = self.verify.as_mut() {
+            v.end_frame(self.frame_count, &self.mem.data[REG_COUNT + IO_SIZE..], &self.mem.eeprom);
+        }
+        if self.frame_timeline.enabled {
+            self.frame_timeline.end_frame(self.cpu.tick);
+        }
+        if self.frame_budget.enabled {
+            let data_count = match self.display_type {
+                DisplayType::Pcd8544 => self.pcd8544.dbg_data_count,
+                _ => self.display.dbg_data_count,
+            };
+            self.frame_budget.end_frame(self.frame_count, self.cpu.tick, data_count, self.panel_bytes(), run.slept);
+        }
+        if self.flush_timing.enabled {
+            self.flush_timing.end_frame(self.frame_count, self.panel_bytes(), self.clock_hz);
+        }
+        if self.fast_boot.enabled {
+            self.fast_boot.end_frame(run.slept);
+        }
+        if self.profiler.enabled {
+            self.profiler.fx.end_frame(self.frame_count);
+        }
+        self.perf_trace.end_frame(self.frame_count, run.perf_start);
+        if self.display_type != DisplayType::Pcd8544 {
+            self.display.check_power(self.frame_count);
+        }
+
         self.frame_count += 1;
+        if let Some(mut shared) = self.shared_screen.take() {
+            shared.publish(self, self.frame_count);
+            self.shared_screen = Some(shared);
+        }
         
         // Per-frame diagnostics (first 10 frames)
-        if self.debug && self.frame_count <= 10 {
+        if self.log_enabled(LogCategory::Spi, LogLevel::Debug) && self.frame_count <= 10 {
             eprintln!("Frame {}: SPI={} FX={} disp_cmd={} disp_data={} sleeping={} pc=0x{:04X} display_type={:?}",
                 self.frame_count, self.dbg_spdr_writes, self.dbg_fx_transfers,
                 self.display.dbg_cmd_count, self.display.dbg_data_count,
                 self.cpu.sleeping, self.cpu.pc, self.display_type);
         }
         // PCD8544 diagnostics (debug mode only)
-        if self.debug && self.cpu_type == CpuType::Atmega328p && self.frame_count <= 5 {
+        if self.log_enabled(LogCategory::Display, LogLevel::Debug) && self.cpu_type == CpuType::Atmega328p && self.frame_count <= 5 {
             eprintln!("[PCD] F{}: SPI={} pcd_cmd={} pcd_data={} type={:?} cs_bit={} dc_bit={} DDRC=0x{:02X} PORTC=0x{:02X} vram[0..4]={:02X},{:02X},{:02X},{:02X} dmode={}",
                 self.frame_count, self.dbg_spdr_writes,
                 self.pcd8544.dbg_cmd_count, self.pcd8544.dbg_data_count,
@@ -663,7 +1530,7 @@ impl Arduboy {
                 self.pcd8544.display_mode);
         }
         // FX diagnostics for first 5 frames
-        if self.debug && self.fx_flash.loaded && self.frame_count <= 5 {
+        if self.log_enabled(LogCategory::Fx, LogLevel::Debug) && self.fx_flash.loaded && self.frame_count <= 5 {
             eprintln!("[FX-diag] F{}: SPI_total={} FX_xfer={} disp_cmd={} disp_data={} sleeping={} pc=0x{:04X} DDRD=0x{:02X} PORTD=0x{:02X} display={:?}",
                 self.frame_count, self.dbg_spdr_writes, self.dbg_fx_transfers,
                 self.display.dbg_cmd_count, self.display.dbg_data_count,
@@ -672,7 +1539,7 @@ impl Arduboy {
                 self.display_type);
         }
         
-        if let Some(pc_counts) = pc_counts {
+        if let Some(pc_counts) = run.pc_counts {
             if self.frame_count <= 5 && !pc_counts.is_empty() {
                 let mut top: Vec<_> = pc_counts.into_iter().collect();
                 top.sort_by(|a, b| b.1.cmp(&a.1));
@@ -690,35 +1557,107 @@ impl Arduboy {
         }
     }
 
+    /// Log tracepoints at the current PC.
+    #[inline]
+    fn check_tracepoints(&mut self) {
+        if !self.tracepoints.is_empty() && self.tracepoints.at(self.cpu.pc) {
+            let mut tracepoints = std::mem::take(&mut self.tracepoints);
+            tracepoints.fire(self);
+            self.tracepoints = tracepoints;
+        }
+    }
+
+    /// Check a load or store against the global object map.
+    fn check_bounds(&mut self, inst: opcodes::Instruction) {
+        use opcodes::Instruction::*;
+        let (x, y, z) = (self.mem.x(), self.mem.y(), self.mem.z());
+        let (addr, base, write) = match inst {
+            Lds { k, .. } => (k, None, false),
+            Sts { k, .. } => (k, None, true),
+            LdX { .. } | LdXInc { .. } => (x, None, false),
+            LdXDec { .. } => (x.wrapping_sub(1), None, false),
+            LdY { .. } | LdYInc { .. } => (y, None, false),
+            LdYDec { .. } => (y.wrapping_sub(1), None, false),
+            LdYQ { q, .. } => (y.wrapping_add(q as u16), Some(y), false),
+            LdZ { .. } | LdZInc { .. } => (z, None, false),
+            LdZDec { .. } => (z.wrapping_sub(1), None, false),
+            LdZQ { q, .. } => (z.wrapping_add(q as u16), Some(z), false),
+            StX { .. } | StXInc { .. } => (x, None, true),
+            StXDec { .. } => (x.wrapping_sub(1), None, true),
+            StY { .. } | StYInc { .. } => (y, None, true),
+            StYDec { .. } => (y.wrapping_sub(1), None, true),
+            StYQ { q, .. } => (y.wrapping_add(q as u16), Some(y), true),
+            StZ { .. } | StZInc { .. } => (z, None, true),
+            StZDec { .. } => (z.wrapping_sub(1), None, true),
+            StZQ { q, .. } => (z.wrapping_add(q as u16), Some(z), true),
+            _ => return,
+        };
+        self.bounds.check(self.cpu.pc, addr, base, write, self.frame_count, &mut self.diagnostics);
+    }
+
+    /// Update the shadow stack for a call or return about to execute.
+    fn track_shadow_stack(&mut self, inst: opcodes::Instruction, size: u8) {
+        use opcodes::Instruction;
+        let pc = self.cpu.pc;
+        let sp = self.cpu.sp;
+        match inst {
+            Instruction::Call { .. } | Instruction::Rcall { .. }
+            | Instruction::Icall | Instruction::Eicall => {
+                self.shadow_stack.push(sp.wrapping_sub(self.pc_bytes()), pc + size as u32, pc, false);
+            }
+            Instruction::Ret | Instruction::Reti => {
+                // Same layout as `push_pc`: low word, then bits 23..16 on the ATmega2560
+                let lo = self.mem.read_raw(sp.wrapping_add(1)) as u32;
+                let hi = self.mem.read_raw(sp.wrapping_add(2)) as u32;
+                let ext = if self.pc_bytes() == 3 { self.mem.read_raw(sp.wrapping_add(3)) as u32 } else { 0 };
+                let target = ext << 16 | hi << 8 | lo;
+                let reti = matches!(inst, Instruction::Reti);
+                self.shadow_stack.ret(pc, sp, target, reti, self.frame_count, &mut self.diagnostics);
+            }
+            _ => {}
+        }
+    }
+
+    /// Check the stack pointer against the stack guard after it moved down.
+    pub(crate) fn check_stack(&mut self) {
+        let calls = if self.shadow_stack.enabled { self.shadow_stack.frames() } else { &[] };
+        self.stack_guard.check(self.cpu.sp, &self.mem.data, self.cpu.pc, self.frame_count, calls, &mut self.diagnostics);
+    }
+
     /// Execute a single instruction
     fn step(&mut self) {
-        let pc = self.cpu.pc as usize;
-        let word = self.mem.read_program_word(pc);
-        let next_word = if pc + 1 < FLASH_SIZE / 2 {
-            self.mem.read_program_word(pc + 1)
-        } else {
-            0
-        };
-        let (inst, size) = opcodes::decode(word, next_word);
+        self.check_tracepoints();
+        let (inst, size) = self.mem.fetch(self.cpu.pc as usize);
+        if self.bounds.enabled {
+            self.check_bounds(inst);
+        }
+        if self.shadow_stack.enabled {
+            self.track_shadow_stack(inst, size);
+        }
+        if self.trace_tail.enabled {
+            self.trace_tail.push(self.cpu.pc);
+        }
+        if self.fast_boot.watching() {
+            self.fast_boot.step(self.cpu.pc, self.cpu.sp);
+        }
 
-        // Profiler: record PC hit and call/ret tracking
+        // Profiler: record PC hit and call/ret tracking (word addresses)
         if self.profiler.enabled {
-            self.profiler.record(self.cpu.pc);
+            let pc = self.cpu.pc;
+            self.profiler.record(pc);
             match inst {
                 opcodes::Instruction::Call { k } => {
-                    self.profiler.record_call(self.cpu.pc, k as u16);
+                    self.profiler.record_call(pc, k);
                 }
                 opcodes::Instruction::Rcall { k } => {
-                    let target = (self.cpu.pc as i32 + 1 + k as i32) as u16;
-                    self.profiler.record_call(self.cpu.pc, target);
+                    let target = (pc as i32 + 1 + k as i32) as u32 & cpu::PC_MASK;
+                    self.profiler.record_call(pc, target);
                 }
                 opcodes::Instruction::Icall => {
-                    let z = self.mem.z();
-                    self.profiler.record_call(self.cpu.pc, z);
+                    self.profiler.record_call(pc, self.mem.z() as u32);
                 }
                 opcodes::Instruction::Eicall => {
-                    let z = self.mem.z();
-                    self.profiler.record_call(self.cpu.pc, z);
+                    self.profiler.record_call(pc, self.eind_z());
                 }
                 opcodes::Instruction::Ret | opcodes::Instruction::Reti => {
                     self.profiler.record_ret();
@@ -727,26 +1666,49 @@ impl Arduboy {
             }
         }
 
+        let ie = self.cpu.sreg & (1 << SREG_I) != 0;
         let cycles = self.execute_inst(inst, size);
-        self.cpu.tick += cycles as u64;
+        self.retire(cycles, ie);
+    }
+
+    /// Account an executed instruction: advance the clock, track the stack
+    /// depth and hold off interrupts for one instruction when SEI or RETI
+    /// has just set I.
+    fn retire(&mut self, cycles: u8, was_ie: bool) {
+        self.cpu.tick += cycles as u64 * self.clock_div as u64;
+        self.sp_min = self.sp_min.min(self.cpu.sp);
+        if !was_ie && self.cpu.sreg & (1 << SREG_I) != 0 {
+            self.irq_inhibit = true;
+            self.irq_recheck = true;
+        }
     }
 
     /// Execute a single instruction and return its disassembly.
     ///
     /// Used by the debugger for step-by-step execution.
     pub fn step_one(&mut self) -> String {
+        self.check_tracepoints();
         let pc = self.cpu.pc;
-        let word = self.mem.read_program_word(pc as usize);
-        let next_word = if (pc as usize) + 1 < FLASH_SIZE / 2 {
-            self.mem.read_program_word(pc as usize + 1)
-        } else { 0 };
-        let (inst, size) = opcodes::decode(word, next_word);
+        let (inst, size) = self.mem.fetch(pc as usize);
+        if self.bounds.enabled {
+            self.check_bounds(inst);
+        }
+        if self.shadow_stack.enabled {
+            self.track_shadow_stack(inst, size);
+        }
+        if self.trace_tail.enabled {
+            self.trace_tail.push(pc);
+        }
         let asm = disasm::disassemble(inst, pc);
+        let ie = self.cpu.sreg & (1 << SREG_I) != 0;
         let cycles = self.execute_inst(inst, size);
-        self.cpu.tick += cycles as u64;
+        self.retire(cycles, ie);
         // Update peripherals after each step
         self.flush_spi();
         self.update_peripherals();
+        if self.vcd.is_some() {
+            self.vcd_sample();
+        }
         format!("0x{:04X}: {}", pc * 2, asm)
     }
 
@@ -754,7 +1716,7 @@ impl Arduboy {
     pub fn disasm_at_pc(&self) -> String {
         let pc = self.cpu.pc;
         let word = self.mem.read_program_word(pc as usize);
-        let next_word = if (pc as usize) + 1 < FLASH_SIZE / 2 {
+        let next_word = if (pc as usize) + 1 < self.mem.flash.len() / 2 {
             self.mem.read_program_word(pc as usize + 1)
         } else { 0 };
         let (inst, _) = opcodes::decode(word, next_word);
@@ -792,9 +1754,67 @@ impl Arduboy {
         debugger::dump_io_regs_all(&self.mem.data, self.cpu_type == CpuType::Atmega328p)
     }
 
+    /// Every peripheral with its registers, decoded fields and interrupt
+    /// state. Values are what the CPU would read; serial receive FIFOs are
+    /// not consumed and watchpoints do not trigger.
+    pub fn peripherals(&mut self) -> Vec<introspect::PeripheralInfo> {
+        let cpu = self.cpu_type;
+        introspect::describe(cpu, |addr| self.peek_data(addr))
+    }
+
+    /// Data-space byte as the CPU would read it, without read side effects:
+    /// receive queues are not popped and watchpoints do not fire.
+    pub fn peek_data(&mut self, addr: u16) -> u8 {
+        match (self.cpu_type, addr) {
+            // Data registers that pop a receive queue when read
+            (CpuType::Atmega32u4, 0xF1) | (CpuType::Atmega328p, 0xC6) => self.mem.data[addr as usize],
+            _ => {
+                let watchpoints = std::mem::take(&mut self.debugger.watchpoints);
+                let strict = std::mem::take(&mut self.uninit.enabled);
+                let v = self.read_data(addr);
+                self.debugger.watchpoints = watchpoints;
+                self.uninit.enabled = strict;
+                v
+            }
+        }
+    }
+
+    /// Data-space store from a debugger: peripheral hooks run as for the
+    /// CPU's stores (an `SPL` write moves SP), watchpoints and write-protect
+    /// regions do not fire.
+    pub fn poke_data(&mut self, addr: u16, value: u8) {
+        let watchpoints = std::mem::take(&mut self.debugger.watchpoints);
+        let regions = std::mem::take(&mut self.debugger.protect_regions);
+        self.write_data(addr, value);
+        self.debugger.watchpoints = watchpoints;
+        self.debugger.protect_regions = regions;
+    }
+
+    /// Canonical text dump of CPU, peripheral registers and internal state,
+    /// and memory checksums, one sorted `key = value` line each, for diffing
+    /// two runs (see [`state_dump`]). Register reads have no side effects.
+    pub fn dump_full_state_text(&mut self) -> String {
+        state_dump::dump(self)
+    }
+
     /// Get profiler report string.
     pub fn profiler_report(&self) -> String {
-        self.profiler.report(&self.mem.flash)
+        let mut s = self.profiler.report(&self.mem.flash);
+        if self.fx_flash.loaded {
+            s.push_str(&self.profiler.fx.report(&self.fx_flash));
+        }
+        s
+    }
+
+    /// Profiler results as serializable data, with function names from
+    /// `elf` when given.
+    pub fn profile_data(&self, elf: Option<&elf::ElfFile>) -> export::ProfileData {
+        export::ProfileData::new(&self.profiler, &self.mem.flash, elf)
+    }
+
+    /// `len` bytes of data space from `start` as serializable data.
+    pub fn ram_dump(&self, start: u16, len: usize) -> export::RamDump {
+        export::RamDump::new(&self.mem.data, start, len)
     }
 
     /// Get register values as a 32-byte array (for GDB).
@@ -809,6 +1829,150 @@ impl Arduboy {
         std::mem::take(&mut self.serial_buf)
     }
 
+    /// Queue bytes sent from the host to the emulated serial port.
+    ///
+    /// On the ATmega32u4 the bytes appear on the USB CDC OUT endpoint (EP2),
+    /// where `Serial.available()` / `Serial.read()` pick them up. On the
+    /// ATmega328P they are delivered through USART0 (RXC0 / UDR0).
+    pub fn push_serial_input(&mut self, data: &[u8]) {
+        self.serial_rx.extend(data.iter().copied());
+        self.irq_recheck = true;
+    }
+
+    /// Number of host → device serial bytes not yet read by the game.
+    pub fn serial_input_pending(&self) -> usize {
+        self.serial_rx.len()
+    }
+
+    /// Start a run verification log with a checkpoint every `interval`
+    /// frames. The current EEPROM is recorded as the starting state.
+    pub fn start_verify(&mut self, interval: u32, game: &str) {
+        let fx = if self.fx_flash.loaded { Some(&self.fx_flash.data[..]) } else { None };
+        self.verify = Some(verify::VerifyLog::new(interval, game, &self.mem.flash, fx, &self.mem.eeprom));
+    }
+
+    /// FNV-1a hash of the loaded program, the [`display_cache`] key.
+    pub fn program_hash(&self) -> u64 {
+        verify::fnv64(&self.mem.flash[..self.program_size])
+    }
+
+    /// Start with the display an earlier run detected for this program.
+    fn apply_cached_display(&mut self) {
+        let Some(cached) = self.display_cache.as_ref().and_then(|c| c.get(self.program_hash())) else {
+            return;
+        };
+        self.display_type = cached.display;
+        if let Some((cs, dc)) = cached.pcd_pins.filter(|_| self.cpu_type == CpuType::Atmega328p) {
+            self.pcd_cs_bit = cs;
+            self.pcd_dc_bit = dc;
+        }
+    }
+
+    /// Remember a display just auto-detected.
+    fn cache_detected_display(&mut self) {
+        if self.display_cache.is_none() {
+            return;
+        }
+        let cached = display_cache::CachedDisplay {
+            display: self.display_type,
+            pcd_pins: (self.cpu_type == CpuType::Atmega328p).then_some((self.pcd_cs_bit, self.pcd_dc_bit)),
+        };
+        let hash = self.program_hash();
+        if let Some(cache) = self.display_cache.as_mut() {
+            cache.record(hash, cached);
+        }
+    }
+
+    /// FNV-1a checksums of program flash, SRAM and EEPROM.
+    pub fn checksums(&self) -> (u64, u64, u64) {
+        (verify::fnv64(&self.mem.flash),
+         verify::fnv64(&self.mem.data[REG_COUNT + IO_SIZE..]),
+         verify::fnv64(&self.mem.eeprom))
+    }
+
+    /// Start recording a pin-level waveform of the given probes to `path`.
+    pub fn start_vcd(&mut self, path: &std::path::Path, probes: Vec<vcd::VcdProbe>) -> Result<(), Error> {
+        let mut rec = vcd::VcdRecorder::create(path, probes, self.clock_hz)
+            .map_err(|source| Error::Io { op: "Create", source })?;
+        rec.sample_ports(self.cpu.tick, &self.pin_levels());
+        self.vcd = Some(rec);
+        Ok(())
+    }
+
+    /// Stop VCD recording and flush the file. Returns the number of value
+    /// changes recorded, or `Ok(0)` when not recording.
+    pub fn finish_vcd(&mut self) -> Result<u64, Error> {
+        let Some(rec) = self.vcd.take() else { return Ok(0) };
+        let changes = rec.changes;
+        rec.finish().map_err(|source| Error::Io { op: "Write", source })?;
+        Ok(changes)
+    }
+
+    /// Current pin levels of ports B..F, as the game would read PINx.
+    pub fn pin_levels(&self) -> [u8; 5] {
+        let d = &self.mem.data;
+        let level = |ddr: usize, port: usize, pin: u8| (d[port] & d[ddr]) | (pin & !d[ddr]);
+        [
+            level(0x24, 0x25, self.pin_b),
+            level(0x27, 0x28, self.pin_c),
+            level(0x2A, 0x2B, self.pin_d),
+            level(0x2D, 0x2E, self.pin_e),
+            level(0x30, 0x31, self.pin_f),
+        ]
+    }
+
+    /// Display data bytes in one full screen update.
+    fn panel_bytes(&self) -> u32 {
+        match self.display_type {
+            DisplayType::Pcd8544 => (pcd8544::PCD_WIDTH * pcd8544::PCD_PAGES) as u32,
+            _ => (self.display.geometry.width * self.display.geometry.height / 8) as u32,
+        }
+    }
+
+    /// Which SPI slave the current CS/DC pin levels address, for the decoder.
+    fn spi_select(&self, fx_cs_active: bool) -> spi_decode::SpiSelect {
+        use spi_decode::{SpiDevice, SpiSelect};
+        if fx_cs_active {
+            return SpiSelect { device: SpiDevice::Flash, cs: Some((0x2B, 1)), dc_data: None };
+        }
+        if let Some(b) = &self.board {
+            let device = match (b.bus, b.display) {
+                (DisplayBus::I2c, _) => SpiDevice::None,
+                (_, DisplayType::Pcd8544) => SpiDevice::Pcd8544,
+                _ => SpiDevice::Ssd1306,
+            };
+            let d = &self.mem.data;
+            if device != SpiDevice::None && d[b.display_cs.port_addr as usize] & b.display_cs.mask() == 0 {
+                let dc = d[b.display_dc.port_addr as usize] & b.display_dc.mask() != 0;
+                return SpiSelect { device, cs: Some((b.display_cs.port_addr, b.display_cs.bit)), dc_data: Some(dc) };
+            }
+            return SpiSelect { device: SpiDevice::None, cs: None, dc_data: None };
+        }
+        // (device, PORTx address, CS bit, DC bit) of the display
+        let (device, port, cs_bit, dc_bit) = if self.cpu_type == CpuType::Atmega328p {
+            // Gamebuino Classic defaults until auto-detection settles (CS=PC1, DC=PC2)
+            let (cs, dc) = if self.pcd_cs_bit == 0xFF { (1, 2) } else { (self.pcd_cs_bit, self.pcd_dc_bit) };
+            (SpiDevice::Pcd8544, 0x28u16, cs, dc)
+        } else if self.display_type == DisplayType::Pcd8544 {
+            (SpiDevice::Pcd8544, 0x31, 6, 5)
+        } else {
+            (SpiDevice::Ssd1306, 0x2B, 6, 4)
+        };
+        let v = self.mem.data[port as usize];
+        if v & (1 << cs_bit) == 0 {
+            SpiSelect { device, cs: Some((port, cs_bit)), dc_data: Some(v & (1 << dc_bit) != 0) }
+        } else {
+            SpiSelect { device: SpiDevice::None, cs: None, dc_data: None }
+        }
+    }
+
+    fn vcd_sample(&mut self) {
+        let levels = self.pin_levels();
+        if let Some(vcd) = self.vcd.as_mut() {
+            vcd.sample_ports(self.cpu.tick, &levels);
+        }
+    }
+
     /// Save EEPROM contents to a byte vector.
     pub fn save_eeprom(&self) -> Vec<u8> {
         self.mem.eeprom.clone()
@@ -821,6 +1985,17 @@ impl Arduboy {
         self.eeprom_dirty = false;
     }
 
+    /// Arduboy2 system settings stored in EEPROM bytes 0–15.
+    pub fn system_settings(&self) -> system_eeprom::SystemSettings {
+        system_eeprom::SystemSettings::read(&self.mem.eeprom)
+    }
+
+    /// Store Arduboy2 system settings (marks the EEPROM dirty).
+    pub fn set_system_settings(&mut self, settings: &system_eeprom::SystemSettings) {
+        settings.write(&mut self.mem.eeprom);
+        self.eeprom_dirty = true;
+    }
+
     /// Get current RGB LED state as (red, green, blue).
     ///
     /// Arduboy LED pins: Red=PB6(OC1B), Green=PB7(OC1C), Blue=PB5(OC1A).
@@ -829,39 +2004,112 @@ impl Arduboy {
         self.led_rgb
     }
 
+    /// Levels driven onto `pin_addr`'s port by timer output-compare units,
+    /// as `(mask, levels)`: bits in `mask` follow a connected channel and
+    /// read as the matching bit of `levels`.
+    fn oc_pin_outputs(&self, pin_addr: u16) -> (u8, u8) {
+        // Fast path for every PINx read while no timer drives a pin
+        if !(self.timer0.oc_connected() || self.timer1.oc_connected()
+            || self.timer2.oc_connected() || self.timer3.oc_connected())
+        {
+            return (0, 0);
+        }
+        // (port, bit, timer, channel)
+        let pins: [(u16, u8, u8, usize); 6] = if self.cpu_type == CpuType::Atmega328p {
+            [
+                (0x29, 6, 0, 0), // OC0A = PD6
+                (0x29, 5, 0, 1), // OC0B = PD5
+                (0x23, 1, 1, 0), // OC1A = PB1
+                (0x23, 2, 1, 1), // OC1B = PB2
+                (0x23, 3, 2, 0), // OC2A = PB3
+                (0x29, 3, 2, 1), // OC2B = PD3
+            ]
+        } else {
+            [
+                (0x23, 7, 0, 0), // OC0A = PB7
+                (0x29, 0, 0, 1), // OC0B = PD0
+                (0x23, 5, 1, 0), // OC1A = PB5
+                (0x23, 6, 1, 1), // OC1B = PB6
+                (0x23, 7, 1, 2), // OC1C = PB7
+                (0x26, 6, 3, 0), // OC3A = PC6
+            ]
+        };
+        let (mut mask, mut levels) = (0u8, 0u8);
+        for (addr, bit, timer, ch) in pins {
+            if addr != pin_addr { continue; }
+            let level = match timer {
+                0 => self.timer0.oc_output(ch),
+                1 => self.timer1.oc_output(ch),
+                2 => self.timer2.oc_output(ch),
+                _ => self.timer3.oc_output(ch),
+            };
+            let Some(mut level) = level else { continue };
+            let m = 1u8 << bit;
+            if mask & m != 0 {
+                // PB7 on the 32u4: OC0A and OC1C meet in the output compare
+                // modulator, which ANDs when PORTB7 is set and ORs otherwise.
+                let prev = levels & m != 0;
+                let and = self.mem.data[0x25] & m != 0;
+                level = if and { prev && level } else { prev || level };
+            }
+            mask |= m;
+            levels = if level { levels | m } else { levels & !m };
+        }
+        (mask, levels)
+    }
+
+    /// Replace output-pin bits of a PINx value with any timer waveform that
+    /// overrides PORTx on that pin (COMnx != 0 and DDR bit set).
+    fn merge_oc_outputs(&self, pin_addr: u16, value: u8, ddr: u8) -> u8 {
+        let (mask, levels) = self.oc_pin_outputs(pin_addr);
+        let mask = mask & ddr;
+        (value & !mask) | (levels & mask)
+    }
+
+    /// Compose a PINx value from DDRx/PORTx (at `pin_addr + 1/+2`), the
+    /// external level `ext`, the floating-pin policy and timer OC outputs.
+    fn read_pin_port(&mut self, pin_addr: u16, ext: u8) -> u8 {
+        let ddr = self.mem.data[pin_addr as usize + 1];
+        let port = self.mem.data[pin_addr as usize + 2];
+        let mut v = (port & ddr) | (ext & !ddr);
+        // Inputs without pull-up that nothing is pulling low
+        let floating = !ddr & !port & ext;
+        if floating != 0 {
+            let noise = match &self.floating_pins {
+                FloatingPinPolicy::High => 0xFF,
+                FloatingPinPolicy::Random => self.next_random(),
+                FloatingPinPolicy::Scripted(bytes) if bytes.is_empty() => 0xFF,
+                FloatingPinPolicy::Scripted(bytes) => {
+                    let b = bytes[self.floating_script_pos % bytes.len()];
+                    self.floating_script_pos = (self.floating_script_pos + 1) % bytes.len();
+                    b
+                }
+            };
+            v = (v & !floating) | (noise & floating);
+        }
+        self.merge_oc_outputs(pin_addr, v, ddr)
+    }
+
     /// Read from data space with peripheral hooks
     pub fn read_data(&mut self, addr: u16) -> u8 {
         let a = addr as usize;
 
+        // Virtual devices shadow everything else (fast path: skip if none)
+        if !self.devices.is_empty() {
+            if let Some(v) = self.devices.read(addr) {
+                return v;
+            }
+        }
+
         // GPIO PIN reads: merge input (buttons/external) with output state
         // For output pins (DDRx bit = 1): return PORTx value
         // For input pins (DDRx bit = 0): return pin_x (external input/buttons)
         match addr {
-            0x23 => { // PINB
-                let ddr = self.mem.data[0x24];
-                let port = self.mem.data[0x25];
-                return (port & ddr) | (self.pin_b & !ddr);
-            }
-            0x26 => { // PINC
-                let ddr = self.mem.data[0x27];
-                let port = self.mem.data[0x28];
-                return (port & ddr) | (self.pin_c & !ddr);
-            }
-            0x29 => { // PIND
-                let ddr = self.mem.data[0x2A];
-                let port = self.mem.data[0x2B];
-                return (port & ddr) | (self.pin_d & !ddr);
-            }
-            0x2C => { // PINE
-                let ddr = self.mem.data[0x2D];
-                let port = self.mem.data[0x2E];
-                return (port & ddr) | (self.pin_e & !ddr);
-            }
-            0x2F => { // PINF
-                let ddr = self.mem.data[0x30];
-                let port = self.mem.data[0x31];
-                return (port & ddr) | (self.pin_f & !ddr);
-            }
+            0x23 => return self.read_pin_port(0x23, self.pin_b),
+            0x26 => return self.read_pin_port(0x26, self.pin_c),
+            0x29 => return self.read_pin_port(0x29, self.pin_d),
+            0x2C => return self.read_pin_port(0x2C, self.pin_e),
+            0x2F => return self.read_pin_port(0x2F, self.pin_f),
             _ => {}
         }
 
@@ -883,24 +2131,48 @@ impl Arduboy {
                 return v;
             }
         }
-        // Timer2 reads (ATmega328P only)
-        if self.cpu_type == CpuType::Atmega328p {
+        // Timer2 reads (ATmega328P, ATmega2560)
+        if self.cpu_type != CpuType::Atmega32u4 {
             if let Some(v) = self.timer2.read(addr, self.cpu.tick, &self.mem.data) {
                 return v;
             }
         }
+        // Timer4/Timer5 reads (ATmega2560 only)
+        if self.cpu_type == CpuType::Atmega2560 {
+            if let Some(v) = self.timer4_2560.read(addr, self.cpu.tick, &self.mem.data) {
+                return v;
+            }
+            if let Some(v) = self.timer5.read(addr, self.cpu.tick, &self.mem.data) {
+                return v;
+            }
+        }
         // SPI reads
         if let Some(v) = self.spi.read(addr) {
+            if self.flush_timing.enabled && addr == 0x4D {
+                self.flush_timing.spsr_read();
+            }
             return v;
         }
         // PLL read
         if addr == 0x49 {
             return self.pll.read();
         }
+        // SPM control read: a lapsed command reads as cleared
+        if addr == SPMCSR_ADDR {
+            return self.spmcsr();
+        }
+        // EEPROM control read: EEMPE/EEPE from the controller timing
+        if addr == 0x3F {
+            return self.eeprom_ctrl.read(self.mem.data[0x3F], self.cpu.tick);
+        }
         // EEPROM data read
         if addr == 0x40 {
             let ea = self.mem.data[0x41] as u16 | ((self.mem.data[0x42] as u16) << 8);
-            return self.mem.eeprom.get(ea as usize).copied().unwrap_or(0xFF);
+            let v = self.mem.eeprom.get(ea as usize).copied().unwrap_or(0xFF);
+            if ea == 1 {
+                self.fast_boot.system_flags_read(v);
+            }
+            return v;
         }
         // ADC reads
         if let Some(v) = self.adc.read(addr) {
@@ -909,6 +2181,18 @@ impl Arduboy {
 
         // USB Serial register reads (ATmega32u4 only)
         if self.cpu_type == CpuType::Atmega32u4 {
+            // CDC OUT endpoint: report host data via RXOUTI/RWAL and byte count
+            if self.usb_uenum == USB_CDC_RX_EP {
+                let avail = self.serial_rx.len().min(64) as u8;
+                match addr {
+                    0xE8 => { // UEINTX - FIFOCON, plus RWAL|RXOUTI when data is waiting
+                        return if avail > 0 { 0xA4 } else { 0x80 };
+                    }
+                    0xF1 => return self.serial_rx.pop_front().unwrap_or(0), // UEDATX
+                    0xF2 => return avail, // UEBCLX
+                    _ => {}
+                }
+            }
             match addr {
                 0xE8 => { // UEINTX - always report ready to send
                     return 0xA1;
@@ -927,14 +2211,23 @@ impl Arduboy {
             }
         }
 
-        // USART0 register reads (ATmega328P only)
-        if self.cpu_type == CpuType::Atmega328p {
+        // USART1-3 (ATmega2560): always ready, received nothing
+        if self.cpu_type == CpuType::Atmega2560 && matches!(addr, 0xC8 | 0xD0 | 0x130) {
+            return 0x20 | (self.mem.data[a] & 0x40);
+        }
+
+        // USART0 register reads (ATmega328P, ATmega2560)
+        if self.cpu_type != CpuType::Atmega32u4 {
             match addr {
-                0xC0 => { // UCSR0A — always report UDRE0=1 (ready), TXC0
-                    return 0x20 | (self.mem.data[0xC0] & 0x40);
+                0xC0 => { // UCSR0A — always report UDRE0=1 (ready), TXC0, RXC0
+                    return 0x20 | (self.mem.data[0xC0] & 0x40) | self.usart_rxc0();
                 }
                 0xC1 => return self.mem.data[0xC1], // UCSR0B
-                0xC6 => return 0x00, // UDR0 — no receive data
+                0xC6 => { // UDR0 — next received byte (0 when the queue is empty)
+                    return if self.usart_rxc0() != 0 {
+                        self.serial_rx.pop_front().unwrap_or(0)
+                    } else { 0x00 };
+                }
                 _ => {}
             }
         }
@@ -944,21 +2237,68 @@ impl Arduboy {
             if !self.debugger.watchpoints.is_empty() {
                 self.debugger.check_read(addr, v);
             }
+            if self.uninit.enabled && a >= self.ram_start() && !self.mem.is_init(addr) {
+                self.uninit_read(addr);
+            }
             v
         } else {
             0
         }
     }
 
+    /// Turn strict mode on or off: report loads from SRAM not written
+    /// since reset (see [`uninit`]). Turn it on before the program starts;
+    /// bytes written earlier count as unwritten until written again.
+    pub fn set_strict(&mut self, on: bool) {
+        self.uninit.enabled = on;
+        self.mem.track_init(on);
+    }
+
+    /// Report a load from an unwritten SRAM byte (strict mode).
+    fn uninit_read(&mut self, addr: u16) {
+        // The PC has already moved past the load; LDS is two words
+        let pc = self.cpu.pc as usize;
+        let lds = pc >= 2 && self.mem.read_program_word(pc - 2) & 0xFE0F == 0x9000;
+        let load_pc = pc.saturating_sub(if lds { 2 } else { 1 }) as u32;
+        let (inst, _) = self.mem.fetch(load_pc as usize);
+        self.uninit.read(load_pc, addr, self.frame_count, || disasm::disassemble(inst, load_pc), &mut self.diagnostics);
+    }
+
     /// Write to data space with peripheral hooks
     pub fn write_data(&mut self, addr: u16, value: u8) {
         let a = addr as usize;
         let old = if a < self.mem.data.len() { self.mem.data[a] } else { 0 };
+        self.mem.mark_init(addr);
+        // An I/O write may enable, raise or clear an interrupt
+        if a < self.ram_start() {
+            self.irq_recheck = true;
+        }
 
         // Watchpoint check (fast path: skip if no watchpoints)
         if !self.debugger.watchpoints.is_empty() {
             self.debugger.check_write(addr, old, value);
         }
+        if !self.debugger.protect_regions.is_empty() {
+            // The PC has already moved past the store; STS is two words
+            let pc = self.cpu.pc as usize;
+            let sts = pc >= 2 && self.mem.read_program_word(pc - 2) & 0xFE0F == 0x9200;
+            let store_pc = pc.saturating_sub(if sts { 2 } else { 1 }) as u32 * 2;
+            self.debugger.check_protect(store_pc, addr, old, value);
+        }
+        if !self.devices.is_empty() && self.devices.write(addr, value) {
+            return;
+        }
+
+        // SPI decoder: CS rising edges on PORTC/PORTD/PORTF end transactions
+        if self.spi_decoder.enabled && matches!(addr, 0x28 | 0x2B | 0x31) {
+            self.spi_decoder.port_write(addr, old, value);
+        }
+        if self.spi_trace.enabled && matches!(addr, 0x25 | 0x28 | 0x2B | 0x2E | 0x31) {
+            self.spi_trace.port_write(self.cpu.tick, self.cpu.pc * 2, addr, old, value);
+        }
+        if self.frame_timeline.enabled && matches!(addr, 0x28 | 0x2B | 0x31) {
+            self.frame_timeline.port_write(self.cpu.tick, addr, old, value);
+        }
 
         // PINx toggle writes: writing 1 to PINx bit toggles PORTx bit
         match addr {
@@ -993,28 +2333,15 @@ impl Arduboy {
             _ => {}
         }
 
+        // Speaker pins (bit-bang audio) on any PORTx write
+        if matches!(addr, 0x25 | 0x28 | 0x2B | 0x2E | 0x31) {
+            self.audio_router.port_write(addr, value, self.cpu.tick, &mut self.audio_buf);
+        }
+
         // GPIO DDR/PORT writes - track pin changes
-        match addr {
+        let gpio = match addr {
             0x24 | 0x25 => { // DDRB, PORTB
                 if a < self.mem.data.len() {
-                    // Detect PB5 (speaker pin 2) transitions for GPIO-driven audio
-                    if addr == 0x25 {
-                        let new_pb5 = value & (1 << 5) != 0;
-                        if new_pb5 != self.speaker2_prev_pb5 {
-                            let tick = self.cpu.tick;
-                            // Record edge in sample-accurate audio buffer
-                            self.audio_buf.right.push(tick, new_pb5);
-                            if self.speaker2_last_edge > 0 {
-                                let half = tick.saturating_sub(self.speaker2_last_edge);
-                                if half >= 400 && half <= 270000 {
-                                    self.speaker2_half_period = half;
-                                    self.speaker2_last_active = tick;
-                                }
-                            }
-                            self.speaker2_last_edge = tick;
-                            self.speaker2_prev_pb5 = new_pb5;
-                        }
-                    }
                     self.mem.data[a] = value;
                     // Track LED states from PORTB
                     // RX LED = PB0 (active-low)
@@ -1024,101 +2351,63 @@ impl Arduboy {
                     self.led_rgb.0 = if value & (1 << 6) != 0 { 255 } else { 0 }; // Red
                     self.led_rgb.1 = if value & (1 << 7) != 0 { 255 } else { 0 }; // Green
                 }
-                return;
+                true
             }
             0x27 | 0x28 => { // DDRC, PORTC
                 if a < self.mem.data.len() {
-                    // Trace PORTC/DDRC writes for diagnostics
-                    if self.spi_trace_enabled && self.spi_trace.len() < 200 {
-                        let old = self.mem.data[a];
-                        let reg_name = if addr == 0x28 { "PORTC" } else { "DDRC" };
-                        self.spi_trace.push(format!("{}_WRITE old=0x{:02X} new=0x{:02X} PC=0x{:04X}",
-                            reg_name, old, value, self.cpu.pc));
-                    }
-                    // Detect PC6 (speaker pin 1) transitions for GPIO-driven audio
-                    if addr == 0x28 {
-                        let new_pc6 = value & (1 << 6) != 0;
-                        if new_pc6 != self.speaker_prev_pc6 {
-                            let tick = self.cpu.tick;
-                            // Record edge in sample-accurate audio buffer
-                            self.audio_buf.left.push(tick, new_pc6);
-                            if self.speaker_last_edge > 0 {
-                                let half = tick.saturating_sub(self.speaker_last_edge);
-                                // Valid audio range: ~30Hz to ~20kHz
-                                // half-period: 16MHz/(2*20000)=400 to 16MHz/(2*30)=266666
-                                if half >= 400 && half <= 270000 {
-                                    self.speaker_half_period = half;
-                                    self.speaker_last_active = tick;
-                                }
-                            }
-                            self.speaker_last_edge = tick;
-                            self.speaker_prev_pc6 = new_pc6;
-                        }
-                    }
                     self.mem.data[a] = value;
                 }
-                return;
+                true
             }
             0x2A => { // DDRD
                 if a < self.mem.data.len() { self.mem.data[a] = value; }
-                return;
+                true
             }
             0x2B => { // PORTD
                 if a < self.mem.data.len() { self.mem.data[a] = value; }
                 // TX LED = PD5 (active-low)
                 self.led_tx = value & (1 << 5) == 0;
 
-                // Gamebuino Classic speaker: PD3 (Arduino D3)
-                // Reuses speaker1 fields (PC6 is unused on 328P)
-                if self.cpu_type == CpuType::Atmega328p {
-                    let new_pd3 = value & (1 << 3) != 0;
-                    if new_pd3 != self.speaker_prev_pc6 {
-                        let tick = self.cpu.tick;
-                        self.audio_buf.left.push(tick, new_pd3);
-                        if self.speaker_last_edge > 0 {
-                            let half = tick.saturating_sub(self.speaker_last_edge);
-                            if half >= 400 && half <= 270000 {
-                                self.speaker_half_period = half;
-                                self.speaker_last_active = tick;
-                            }
-                        }
-                        self.speaker_last_edge = tick;
-                        self.speaker_prev_pc6 = new_pd3;
-                    }
-                }
-
                 // FX Flash CS = PD1 (Arduino D2): detect rising edge (deselect)
                 // Only when PD1 is configured as output (DDR check)
-                if self.fx_flash.loaded && (self.mem.data[0x2A] & (1 << 1) != 0) {
+                let fx_fitted = self.board.as_ref().is_none_or(|b| b.fx);
+                if fx_fitted && self.fx_flash.loaded && (self.mem.data[0x2A] & (1 << 1) != 0) {
                     let new_cs_high = value & (1 << 1) != 0;
                     if new_cs_high && !self.fx_cs_prev {
-                        if self.debug && self.dbg_fx_cs_count < 20 {
+                        if self.log_enabled(LogCategory::Fx, LogLevel::Debug) && self.dbg_fx_cs_count < 20 {
                             eprintln!("  FX CS↑ (deselect) after {} SPI bytes, state={:?}",
                                 self.dbg_fx_bytes_in_cs, self.fx_flash.state);
                         }
                         self.fx_flash.deselect();
+                        self.profiler.fx.deselect();
                         self.dbg_fx_cs_count += 1;
                     }
                     if !new_cs_high && self.fx_cs_prev {
                         // CS going LOW: start of new transaction
                         self.dbg_fx_bytes_in_cs = 0;
-                        if self.debug && self.dbg_fx_cs_count < 20 {
+                        if self.log_enabled(LogCategory::Fx, LogLevel::Debug) && self.dbg_fx_cs_count < 20 {
                             eprintln!("  FX CS↓ (select) transaction #{}", self.dbg_fx_cs_count);
                         }
                     }
                     self.fx_cs_prev = new_cs_high;
                 }
-                return;
+                true
             }
             0x2D | 0x2E => { // DDRE, PORTE
                 if a < self.mem.data.len() { self.mem.data[a] = value; }
-                return;
+                true
             }
             0x30 | 0x31 => { // DDRF, PORTF
                 if a < self.mem.data.len() { self.mem.data[a] = value; }
-                return;
+                true
             }
-            _ => {}
+            _ => false,
+        };
+        if gpio {
+            // Output levels can trigger INTn/PCINTn as on the chip
+            let levels = self.pin_levels();
+            self.ext_int.sample(levels, &mut self.mem.data);
+            return;
         }
 
         // SP writes
@@ -1131,6 +2420,8 @@ impl Arduboy {
             SPL_ADDR => {
                 self.cpu.sp = (self.cpu.sp & 0xFF00) | value as u16;
                 if a < self.mem.data.len() { self.mem.data[a] = value; }
+                // SPH is written first, so SP is whole again here
+                if self.stack_guard.enabled { self.check_stack(); }
                 return;
             }
             SREG_ADDR => {
@@ -1141,6 +2432,16 @@ impl Arduboy {
             _ => {}
         }
 
+        if addr == CLKPR_ADDR {
+            self.write_clkpr(value);
+            return;
+        }
+        if addr == SPMCSR_ADDR {
+            self.write_spmcsr(value);
+            return;
+        }
+        // EIFR/PCIFR flag clears
+        if self.ext_int.write(addr, value, &mut self.mem.data) { return; }
         // Timer0 writes
         if self.timer0.write(addr, value, old, &mut self.mem.data) { return; }
         // Timer1 writes
@@ -1154,8 +2455,13 @@ impl Arduboy {
                 return;
             }
         }
-        // Timer2 writes (ATmega328P only)
-        if self.cpu_type == CpuType::Atmega328p {
+        // Timer4/Timer5 writes (ATmega2560 only)
+        if self.cpu_type == CpuType::Atmega2560 {
+            if self.timer4_2560.write(addr, value, old, &mut self.mem.data) { return; }
+            if self.timer5.write(addr, value, old, &mut self.mem.data) { return; }
+        }
+        // Timer2 writes (ATmega328P, ATmega2560)
+        if self.cpu_type != CpuType::Atmega32u4 {
             let was_pwm = self.timer2.is_pwm_dac_active();
             let old_ocr_b = self.timer2.ocr_b();
             if self.timer2.write(addr, value, old, &mut self.mem.data) {
@@ -1180,6 +2486,12 @@ impl Arduboy {
             if a < self.mem.data.len() { self.mem.data[a] = value; }
             // If SPDR written, data goes to SPI output with current DC state
             if addr == 0x4E {
+                if self.faults.spi_drops(self.cpu.pc * 2) {
+                    // Lost on the bus: no device clocks it in, MISO floats high
+                    self.spdr_in = 0xFF;
+                    self.mem.data[0x4E] = 0xFF;
+                    return;
+                }
                 let portd = self.mem.data[0x2B];
                 let portf = self.mem.data[0x31];
                 let ddrd = self.mem.data[0x2A];
@@ -1190,28 +2502,62 @@ impl Arduboy {
                 
                 // FX Flash CS = PD1 (Arduino D2, active LOW)
                 let fx_cs_active = self.fx_flash.loaded
+                    && self.board.as_ref().is_none_or(|b| b.fx)
                     && (ddrd & (1 << 1) != 0)   // PD1 configured as output
                     && (portd & (1 << 1) == 0);  // PD1 driven LOW
                 
                 // FX flash: transfer byte and capture MISO response
                 if fx_cs_active {
-                    let response = self.fx_flash.transfer(value);
+                    let read_addr = match self.fx_flash.state {
+                        peripherals::fx_flash::FxState::Reading { addr } => Some(addr),
+                        _ => None,
+                    };
+                    if let (true, Some(addr)) = (self.profiler.enabled, read_addr) {
+                        self.profiler.fx.read(addr);
+                    }
+                    let mut response = self.fx_flash.transfer(value);
+                    if let Some(addr) = read_addr {
+                        response = self.faults.fx_read(addr, response);
+                    }
                     self.spdr_in = response;
                     self.mem.data[0x4E] = response;
                     self.dbg_fx_transfers += 1;
                     self.dbg_fx_bytes_in_cs += 1;
-                    if self.debug && self.dbg_fx_transfers <= 20 {
+                    if self.log_enabled(LogCategory::Fx, LogLevel::Trace) && self.dbg_fx_transfers <= 20 {
                         eprintln!("[FX-xfer] #{} MOSI=0x{:02X} MISO=0x{:02X} state={:?} PC=0x{:04X}",
                             self.dbg_fx_transfers, value, response, self.fx_flash.state, self.cpu.pc);
                     }
                 } else {
                     self.spdr_in = 0xFF;
                 }
+                if let Some(vcd) = self.vcd.as_mut() {
+                    vcd.spi_transfer(self.cpu.tick, value, self.spdr_in);
+                }
+                if self.spi_decoder.enabled || self.spi_trace.enabled || self.frame_timeline.enabled
+                    || self.flush_timing.enabled
+                {
+                    let sel = self.spi_select(fx_cs_active);
+                    if self.flush_timing.enabled {
+                        let data = matches!(sel.device, spi_decode::SpiDevice::Ssd1306 | spi_decode::SpiDevice::Pcd8544)
+                            && sel.dc_data == Some(true);
+                        let cycles = flush_timing::spi_byte_cycles(self.mem.data[0x4C], self.spi.spi2x) * self.clock_div as u64;
+                        self.flush_timing.spi_byte(self.cpu.tick, data, cycles);
+                    }
+                    if self.frame_timeline.enabled {
+                        self.frame_timeline.spi_byte(self.cpu.tick, &sel);
+                    }
+                    if self.spi_decoder.enabled {
+                        self.spi_decoder.byte(self.cpu.tick, sel, value, self.spdr_in);
+                    }
+                    if self.spi_trace.enabled {
+                        self.spi_trace.transfer(self.cpu.tick, self.cpu.pc * 2, sel, value, self.spdr_in);
+                    }
+                }
                 
                 // Display: always push to display SPI buffer.
                 // flush_spi() checks the display's own CS (PD6 for SSD1306,
                 // PF6 for PCD8544) and discards bytes when CS is HIGH.
-                if self.debug && (self.dbg_spdr_writes < 30 || (self.dbg_spdr_writes >= 85 && self.dbg_spdr_writes < 100)
+                if self.log_enabled(LogCategory::Spi, LogLevel::Trace) && (self.dbg_spdr_writes < 30 || (self.dbg_spdr_writes >= 85 && self.dbg_spdr_writes < 100)
                     || (self.dbg_spdr_writes >= 1024 && self.dbg_spdr_writes < 1040)) {
                     eprintln!("  SPI#{:3} val=0x{:02X} PD4={} PD6={} PF5={} PF6={} FX_CS={}",
                         self.dbg_spdr_writes, value, 
@@ -1219,16 +2565,8 @@ impl Arduboy {
                         (portf >> 5) & 1, (portf >> 6) & 1,
                         if fx_cs_active { "LO" } else { "HI" });
                 }
-                let portc = self.mem.data[0x28];
-                if self.spi_trace_enabled && self.spi_trace.len() < 200 {
-                    let ddrc = self.mem.data[0x27];
-                    let portb = self.mem.data[0x25];
-                    let ddrb = self.mem.data[0x24];
-                    let ddrd = self.mem.data[0x2A];
-                    self.spi_trace.push(format!("SPDR val=0x{:02X} PC=0x{:04X} PORTB=0x{:02X}(DDR={:02X}) PORTC=0x{:02X}(DDR={:02X}) PORTD=0x{:02X}(DDR={:02X})",
-                        value, self.cpu.pc, portb, ddrb, portc, ddrc, portd, ddrd));
-                }
-                self.spi_out.push((value, portd, portf, portc));
+                let ports = [self.mem.data[0x25], self.mem.data[0x28], portd, self.mem.data[0x2E], portf];
+                self.spi_out.push((value, ports));
                 self.dbg_spdr_writes += 1;
             }
             return;
@@ -1244,14 +2582,20 @@ impl Arduboy {
         // EEPROM control write
         if addr == 0x3F {
             let ea = self.mem.data[0x41] as u16 | ((self.mem.data[0x42] as u16) << 8);
-            if value & 0x02 != 0 {
+            if self.eeprom_ctrl.write(value, self.cpu.tick, self.clock_hz, self.clock_div) {
                 let data_val = self.mem.data[0x40];
-                if (ea as usize) < self.mem.eeprom.len() {
+                let old = self.mem.eeprom.get(ea as usize).copied().unwrap_or(0xFF);
+                if (ea as usize) < self.mem.eeprom.len()
+                    && !self.faults.eeprom_write_fails(ea)
+                    && self.eeprom_guard.check(self.cpu.pc * 2, ea, old, data_val, self.frame_count, &mut self.diagnostics)
+                {
+                    self.eeprom_wear.record(ea, old, data_val, self.frame_count);
                     self.mem.eeprom[ea as usize] = data_val;
                     self.eeprom_dirty = true;
                 }
             }
-            if a < self.mem.data.len() { self.mem.data[a] = value & !2; }
+            // EEPM and EERIE are stored; EEMPE/EEPE are timing, EERE completes at once
+            if a < self.mem.data.len() { self.mem.data[a] = value & 0x38; }
             return;
         }
 
@@ -1303,8 +2647,23 @@ impl Arduboy {
             }
         }
 
-        // USART0 registers (ATmega328P only)
-        if self.cpu_type == CpuType::Atmega328p {
+        // USART1-3 (ATmega2560): transmitted bytes are dropped, TXC set at once
+        if self.cpu_type == CpuType::Atmega2560 {
+            match addr {
+                0xC8 | 0xD0 | 0x130 => { // UCSRnA — writing TXCn clears it
+                    self.mem.data[a] &= !(value & 0x40);
+                    return;
+                }
+                0xCE | 0xD6 | 0x136 => { // UDRn
+                    self.mem.data[a - 6] |= 0x60;
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        // USART0 registers (ATmega328P, ATmega2560)
+        if self.cpu_type != CpuType::Atmega32u4 {
             match addr {
             0xC0 => { // UCSR0A — writing TXC0 bit clears it
                 if a < self.mem.data.len() {
@@ -1326,7 +2685,7 @@ impl Arduboy {
                 let ucsr0b = self.mem.data[0xC1];
                 if ucsr0b & (1 << 3) != 0 {
                     self.serial_buf.push(value);
-                    if self.debug {
+                    if self.log_enabled(LogCategory::Usb, LogLevel::Debug) {
                         let ch = if value >= 0x20 && value < 0x7F {
                             value as char
                         } else { '.' };
@@ -1363,14 +2722,19 @@ impl Arduboy {
 
     /// Flush SPI output to display
     fn flush_spi(&mut self) {
-        let bytes: Vec<(u8, u8, u8, u8)> = self.spi_out.drain(..).collect();
-        for (byte, portd, portf, portc) in bytes {
+        let bytes: Vec<(u8, [u8; 5])> = self.spi_out.drain(..).collect();
+        for (byte, ports) in bytes {
+            let (portc, portd, portf) = (ports[1], ports[2], ports[4]);
             // Decode DC and CS based on display type and CPU
             // Arduboy (32u4):           DC=PD4(bit4), CS=PD6(bit6) - active LOW
             // Gamebuino (32u4 PCD8544): DC=PF5(bit5), CS=PF6(bit6) - active LOW
             // Gamebuino Classic (328P): DC=PC2(bit2), CS=PC1(bit1) - active LOW (defaults)
             //   The Gamebuino library allows configurable pins; auto-detected at runtime.
-            let (is_data, cs_high) = if self.cpu_type == CpuType::Atmega328p {
+            // A board profile names the pins explicitly (I2C displays never see SPI).
+            let (is_data, cs_high) = if let Some(b) = &self.board {
+                let level = |p: GpioPin| ports[p.port_index()] & p.mask() != 0;
+                (level(b.display_dc), b.bus == DisplayBus::I2c || level(b.display_cs))
+            } else if self.cpu_type == CpuType::Atmega328p {
                 if self.pcd_cs_bit == 0xFF {
                     // Auto-detect: look for PCD8544 init commands with PORTC bits LOW
                     // Standard Gamebuino Classic: CS=PC1, DC=PC2
@@ -1380,7 +2744,7 @@ impl Arduboy {
                     let low_out_bits: Vec<u8> = (0..6)
                         .filter(|&b| ddrc & (1 << b) != 0 && portc & (1 << b) == 0)
                         .collect();
-                    if self.debug && self.dbg_spdr_writes < 20 {
+                    if self.log_enabled(LogCategory::Display, LogLevel::Debug) && self.dbg_spdr_writes < 20 {
                         eprintln!("[PCD-detect] SPI#{} val=0x{:02X} DDRC=0x{:02X} PORTC=0x{:02X} low_out={:?}",
                             self.dbg_spdr_writes, byte, ddrc, portc, low_out_bits);
                     }
@@ -1389,7 +2753,8 @@ impl Arduboy {
                         self.pcd_dc_bit = low_out_bits[0];
                         self.pcd_cs_bit = low_out_bits[1];
                         self.display_type = DisplayType::Pcd8544;
-                        if self.debug {
+                        self.cache_detected_display();
+                        if self.log_enabled(LogCategory::Display, LogLevel::Info) {
                             eprintln!("PCD8544 auto-detected: CS=PC{}, DC=PC{} (cmd=0x{:02X}, PORTC=0x{:02X}, DDRC=0x{:02X})",
                                 self.pcd_cs_bit, self.pcd_dc_bit, byte, portc, ddrc);
                         }
@@ -1400,7 +2765,7 @@ impl Arduboy {
                 } else {
                     let is_d = portc & (1 << self.pcd_dc_bit) != 0;
                     let cs_h = portc & (1 << self.pcd_cs_bit) != 0;
-                    if self.debug && self.pcd8544.dbg_cmd_count + self.pcd8544.dbg_data_count < 10 {
+                    if self.log_enabled(LogCategory::Display, LogLevel::Trace) && self.pcd8544.dbg_cmd_count + self.pcd8544.dbg_data_count < 10 {
                         eprintln!("[PCD] val=0x{:02X} PORTC=0x{:02X} dc={} cs_hi={}", byte, portc, is_d, cs_h);
                     }
                     (is_d, cs_h)
@@ -1419,7 +2784,7 @@ impl Arduboy {
                         let gb_cs_active = portf & (1 << 6) == 0;
                         let gb_dc_cmd = portf & (1 << 5) == 0;
 
-                        if self.debug && self.dbg_spdr_writes < 30 {
+                        if self.log_enabled(LogCategory::Display, LogLevel::Debug) && self.dbg_spdr_writes < 30 {
                             eprintln!("  DETECT: val=0x{:02X} ardu(cs={} dc_cmd={}) gb(cs={} dc_cmd={})",
                                 byte, ardu_cs_active, ardu_dc_cmd, gb_cs_active, gb_dc_cmd);
                         }
@@ -1427,7 +2792,8 @@ impl Arduboy {
                         if ardu_cs_active && ardu_dc_cmd {
                             if byte >= 0x80 {
                                 self.display_type = DisplayType::Ssd1306;
-                                if self.debug {
+                                self.cache_detected_display();
+                                if self.log_enabled(LogCategory::Display, LogLevel::Info) {
                                     eprintln!("Display auto-detected: SSD1306 (first cmd: 0x{:02X}, PD4=0 PD6=0)", byte);
                                 }
                             }
@@ -1435,7 +2801,8 @@ impl Arduboy {
                         if self.display_type == DisplayType::Unknown && gb_cs_active && gb_dc_cmd {
                             if byte == 0x21 || byte == 0x20 {
                                 self.display_type = DisplayType::Pcd8544;
-                                if self.debug {
+                                self.cache_detected_display();
+                                if self.log_enabled(LogCategory::Display, LogLevel::Info) {
                                     eprintln!("Display auto-detected: PCD8544 (first cmd: 0x{:02X}, PF5=0 PF6=0)", byte);
                                 }
                             }
@@ -1451,18 +2818,9 @@ impl Arduboy {
 
             // Skip SPI bytes when display CS is HIGH (not selected)
             if cs_high {
-                if self.spi_trace_enabled && self.spi_trace.len() < 200 {
-                    self.spi_trace.push(format!("SKIP val=0x{:02X} PORTC=0x{:02X} cs_high=true", byte, portc));
-                }
                 continue;
             }
 
-            if self.spi_trace_enabled && self.spi_trace.len() < 200 {
-                self.spi_trace.push(format!("{} val=0x{:02X} PORTC=0x{:02X} dc_bit={} cs_bit={}",
-                    if is_data { "DATA" } else { "CMD " }, byte, portc,
-                    self.pcd_dc_bit, self.pcd_cs_bit));
-            }
-
             match self.display_type {
                 DisplayType::Pcd8544 => {
                     if is_data {
@@ -1487,130 +2845,149 @@ impl Arduboy {
 
     /// Update all peripherals and handle interrupts
     fn update_peripherals(&mut self) {
-        let ie = self.cpu.sreg & (1 << SREG_I) != 0;
-        let tick = self.cpu.tick;
-
         // Flush SPI to display
         self.flush_spi();
+        self.adc.update(&mut self.rng_state);
+        self.service_interrupts();
+    }
 
-        // Timer0
-        self.timer0.update(tick, &mut self.mem.data);
-        if ie {
-            if let Some(vec_addr) = self.timer0.check_interrupt() {
-                self.cpu.sleeping = false;
-                self.do_interrupt(vec_addr);
-                return;
-            }
-        }
+    /// Bring interrupt flags up to date and take the highest-priority
+    /// pending interrupt, if any. Called after every instruction that may
+    /// have changed the interrupt state and whenever the clock reaches
+    /// `next_irq_tick`, so a timer interrupt is taken
+    /// at the instruction boundary following its match.
+    fn service_interrupts(&mut self) {
+        self.irq_recheck = false;
+        let tick = self.cpu.tick;
 
-        // Timer1
+        let levels = self.pin_levels();
+        self.ext_int.sample(levels, &mut self.mem.data);
+        let has_timer3 = self.cpu_type != CpuType::Atmega328p;
+        let has_timer4 = self.cpu_type == CpuType::Atmega32u4;
+        let has_timer2 = self.cpu_type != CpuType::Atmega32u4;
+        let has_timer45 = self.cpu_type == CpuType::Atmega2560;
+        self.timer0.update(tick, &mut self.mem.data);
         self.timer1.update(tick, &mut self.mem.data);
-        if ie {
-            if let Some(vec_addr) = self.timer1.check_interrupt() {
-                self.cpu.sleeping = false;
-                self.do_interrupt(vec_addr);
-                return;
-            }
-        }
-
-        // Timer3 (ATmega32u4 only)
-        if self.cpu_type == CpuType::Atmega32u4 {
+        if has_timer3 {
             self.timer3.update(tick, &mut self.mem.data);
-            if ie {
-                if let Some(vec_addr) = self.timer3.check_interrupt() {
-                    self.cpu.sleeping = false;
-                    self.do_interrupt(vec_addr);
-                    return;
-                }
-            }
         }
-
-        // Timer4 (ATmega32u4 only)
-        if self.cpu_type == CpuType::Atmega32u4 {
+        if has_timer4 {
             self.timer4.update(tick, &mut self.mem.data);
-            if ie {
-                if let Some(vec_addr) = self.timer4.check_interrupt() {
-                    self.cpu.sleeping = false;
-                    self.do_interrupt(vec_addr);
-                    return;
-                }
-            }
         }
-
-        // Timer2 (ATmega328P only)
-        if self.cpu_type == CpuType::Atmega328p {
+        if has_timer2 {
             self.timer2.update(tick, &mut self.mem.data);
-            if ie {
-                if let Some(vec_addr) = self.timer2.check_interrupt() {
-                    self.cpu.sleeping = false;
-                    self.do_interrupt(vec_addr);
-                    return;
-                }
-            }
         }
-
-        // SPI
-        if ie {
-            if let Some(vec_addr) = self.spi.check_interrupt() {
+        if has_timer45 {
+            self.timer4_2560.update(tick, &mut self.mem.data);
+            self.timer5.update(tick, &mut self.mem.data);
+        }
+        if self.cpu_type != CpuType::Atmega32u4 {
+            let rxc0 = self.usart_rxc0();
+            self.mem.data[0xC0] = (self.mem.data[0xC0] & !0x80) | rxc0;
+        }
+        self.next_irq_tick = [
+            self.timer0.next_event(),
+            self.timer1.next_event(),
+            self.timer3.next_event().filter(|_| has_timer3),
+            self.timer4.next_event().filter(|_| has_timer4),
+            self.timer2.next_event().filter(|_| has_timer2),
+            self.timer4_2560.next_event().filter(|_| has_timer45),
+            self.timer5.next_event().filter(|_| has_timer45),
+            self.eeprom_ctrl.next_event(self.mem.data[0x3F]),
+        ].into_iter().flatten().min().unwrap_or(u64::MAX);
+
+        if self.cpu.sreg & (1 << SREG_I) == 0 {
+            return;
+        }
+        // The instruction after SEI/RETI runs before any interrupt
+        if self.irq_inhibit {
+            self.irq_inhibit = false;
+            self.irq_recheck = true;
+            return;
+        }
+        for &src in self.irq_priority {
+            if let Some(vec_addr) = self.take_interrupt(src) {
                 self.cpu.sleeping = false;
                 self.do_interrupt(vec_addr);
                 return;
             }
         }
+    }
 
-        // USART0 interrupts (328P only — 32u4 uses USB serial)
-        if ie && self.cpu_type == CpuType::Atmega328p {
-            let ucsr0a = self.mem.data[0xC0];
-            let ucsr0b = self.mem.data[0xC1];
-            // UDRE interrupt: UDRIE0(bit5) && UDRE0(bit5)
-            if (ucsr0b & 0x20 != 0) && (ucsr0a & 0x20 != 0) {
-                self.cpu.sleeping = false;
-                self.do_interrupt(peripherals::INT_328P_USART_UDRE);
-                return;
-            }
-            // TX Complete interrupt: TXCIE0(bit6) && TXC0(bit6)
-            if (ucsr0b & 0x40 != 0) && (ucsr0a & 0x40 != 0) {
-                self.cpu.sleeping = false;
-                // TXC0 is auto-cleared when executing the interrupt
-                self.mem.data[0xC0] &= !0x40;
-                self.do_interrupt(peripherals::INT_328P_USART_TX);
-                return;
-            }
-            // RX Complete interrupt: RXCIE0(bit7) && RXC0(bit7)
-            if (ucsr0b & 0x80 != 0) && (ucsr0a & 0x80 != 0) {
-                self.cpu.sleeping = false;
-                self.do_interrupt(peripherals::INT_328P_USART_RX);
-                return;
-            }
+    /// Pending interrupt of `src` with the lowest vector; clears the flag
+    /// taken.
+    fn take_interrupt(&mut self, src: peripherals::IrqSource) -> Option<u16> {
+        use peripherals::IrqSource;
+        match src {
+            IrqSource::ExtInt => self.ext_int.check_interrupt(&mut self.mem.data),
+            IrqSource::Timer0 => self.timer0.check_interrupt(),
+            IrqSource::Timer1 => self.timer1.check_interrupt(),
+            IrqSource::Timer2 => self.timer2.check_interrupt(),
+            IrqSource::Timer3 => self.timer3.check_interrupt(),
+            IrqSource::Timer4 => self.timer4.check_interrupt(),
+            IrqSource::Timer4Mega => self.timer4_2560.check_interrupt(),
+            IrqSource::Timer5 => self.timer5.check_interrupt(),
+            IrqSource::Spi => self.spi.check_interrupt(),
+            IrqSource::Usart0 => self.usart0_interrupt(),
+            IrqSource::Adc => self.adc.check_interrupt(),
+            IrqSource::Eeprom => self.eeprom_ctrl.check_interrupt(self.mem.data[0x3F], self.cpu.tick),
         }
+    }
 
-        // ADC
-        self.adc.update(&mut self.rng_state);
-        if ie {
-            if let Some(vec_addr) = self.adc.check_interrupt() {
-                self.cpu.sleeping = false;
-                self.do_interrupt(vec_addr);
-                return;
-            }
+    /// USART0 interrupts (328P, 2560 — 32u4 uses USB serial) in vector
+    /// order: RX complete, data register empty, TX complete.
+    fn usart0_interrupt(&mut self) -> Option<u16> {
+        let (int_rx, int_udre, int_tx) = if self.cpu_type == CpuType::Atmega2560 {
+            (peripherals::INT_2560_USART0_RX, peripherals::INT_2560_USART0_UDRE, peripherals::INT_2560_USART0_TX)
+        } else {
+            (peripherals::INT_328P_USART_RX, peripherals::INT_328P_USART_UDRE, peripherals::INT_328P_USART_TX)
+        };
+        let ucsr0a = self.mem.data[0xC0];
+        let ucsr0b = self.mem.data[0xC1];
+        // RX Complete interrupt: RXCIE0(bit7) && RXC0(bit7)
+        if (ucsr0b & 0x80 != 0) && (ucsr0a & 0x80 != 0) {
+            return Some(int_rx);
+        }
+        // UDRE interrupt: UDRIE0(bit5) && UDRE0(bit5)
+        if (ucsr0b & 0x20 != 0) && (ucsr0a & 0x20 != 0) {
+            return Some(int_udre);
         }
+        // TX Complete interrupt: TXCIE0(bit6) && TXC0(bit6)
+        if (ucsr0b & 0x40 != 0) && (ucsr0a & 0x40 != 0) {
+            // TXC0 is auto-cleared when executing the interrupt
+            self.mem.data[0xC0] &= !0x40;
+            return Some(int_tx);
+        }
+        None
+    }
+
+    /// USART0 RXC0 flag (0x80) if RXEN0 is set and host data is queued.
+    fn usart_rxc0(&self) -> u8 {
+        if self.mem.data[0xC1] & 0x10 != 0 && !self.serial_rx.is_empty() { 0x80 } else { 0 }
     }
 
     /// Execute an interrupt: push PC, jump to vector
     fn do_interrupt(&mut self, vector: u16) {
+        if self.frame_timeline.enabled {
+            self.frame_timeline.isr_enter(vector, self.cpu.tick);
+        }
+        if self.frame_budget.enabled {
+            self.frame_budget.isr_enter(vector, self.cpu.tick, self.frame_count);
+        }
+        if self.flush_timing.enabled {
+            self.flush_timing.isr_enter(self.cpu.tick);
+        }
         let pc = self.cpu.pc;
-        // Push return address (same order as push_word/CALL)
-        self.mem.data[self.cpu.sp as usize] = (pc >> 8) as u8;
-        self.cpu.sp = self.cpu.sp.wrapping_sub(1);
-        self.mem.data[self.cpu.sp as usize] = pc as u8;
-        self.cpu.sp = self.cpu.sp.wrapping_sub(1);
-        // Sync SP to memory registers
-        self.mem.data[SPH_ADDR as usize] = (self.cpu.sp >> 8) as u8;
-        self.mem.data[SPL_ADDR as usize] = self.cpu.sp as u8;
+        if self.shadow_stack.enabled {
+            self.shadow_stack.push(self.cpu.sp.wrapping_sub(self.pc_bytes()), pc, vector as u32, true);
+        }
+        // Push return address (same order as CALL)
+        self.push_pc(pc);
         // Disable interrupts
         self.cpu.sreg &= !(1 << SREG_I);
         self.mem.data[SREG_ADDR as usize] = self.cpu.sreg;
-        self.cpu.pc = vector;
-        self.cpu.tick += 5;
+        self.cpu.pc = vector as u32;
+        self.cpu.tick += cpu::timing(self.cpu_type).interrupt as u64 * self.clock_div as u64;
     }
 
     /// Get display pixel buffer as RGBA u32 slice (for minifb etc)
@@ -1632,12 +3009,104 @@ impl Arduboy {
         }
     }
 
+    /// Set the SSD1306 panel geometry for clone hardware (e.g. 96×64, 72×40).
+    pub fn set_panel_geometry(&mut self, geometry: display::PanelGeometry) {
+        self.display.geometry = geometry;
+        self.display.framebuffer.fill(0);
+        self.display.dirty = true;
+    }
+
+    /// Whether a diagnostic message of `level` in `cat` should be printed.
+    pub fn log_enabled(&self, cat: LogCategory, level: LogLevel) -> bool {
+        self.debug || self.log.enabled(cat, level)
+    }
+
+    /// Select the OLED controller variant (SH1106, SSD1309, ...) and its
+    /// quirks. Resets the controller state; panel geometry is kept.
+    pub fn set_display_quirks(&mut self, quirks: display::DisplayQuirks) {
+        let geometry = self.display.geometry;
+        self.display = Ssd1306::with_quirks(quirks);
+        self.display.geometry = geometry;
+        self.display.dirty = true;
+    }
+
+    /// Visible screen size in pixels.
+    ///
+    /// The SSD1306 reports its panel geometry; the PCD8544 image is centered
+    /// in the full 128×64 framebuffer. Visible pixels are the top-left
+    /// `width × height` of [`framebuffer_u32`](Self::framebuffer_u32) /
+    /// [`framebuffer_rgba`](Self::framebuffer_rgba) (row stride [`SCREEN_WIDTH`]).
+    pub fn screen_size(&self) -> (usize, usize) {
+        match self.display_type {
+            DisplayType::Pcd8544 => (SCREEN_WIDTH, SCREEN_HEIGHT),
+            _ => (self.display.geometry.width, self.display.geometry.height),
+        }
+    }
+
+    /// Whether the display is powered and switched on (false while a game
+    /// has the panel asleep).
+    pub fn display_powered_on(&self) -> bool {
+        match self.display_type {
+            DisplayType::Pcd8544 => self.pcd8544.powered_on(),
+            _ => self.display.powered_on(),
+        }
+    }
+
+    /// Pixel width / height of the panel (1.0 = square pixels).
+    pub fn pixel_aspect(&self) -> f32 {
+        match self.display_type {
+            DisplayType::Pcd8544 => 1.0,
+            _ => self.display.geometry.pixel_aspect,
+        }
+    }
+
+    /// Visible pixels only, as 0xRRGGBB (`screen_size()` dimensions).
+    pub fn screen_u32(&self) -> Vec<u32> {
+        let (w, h) = self.screen_size();
+        let full = self.framebuffer_u32();
+        if w == SCREEN_WIDTH {
+            return full[..SCREEN_WIDTH * h].to_vec();
+        }
+        (0..h).flat_map(|y| full[y * SCREEN_WIDTH..y * SCREEN_WIDTH + w].iter().copied()).collect()
+    }
+
+    /// Visible pixels as shown to the player: [`screen_u32`](Self::screen_u32)
+    /// turned by [`rotation`](Self::rotation). Returns `(pixels, width, height)`.
+    pub fn screen_output(&self) -> (Vec<u32>, usize, usize) {
+        let (w, h) = self.screen_size();
+        let px = self.screen_u32();
+        match self.rotation {
+            Rotation::None => (px, w, h),
+            r => r.rotate(&px, w, h),
+        }
+    }
+
     /// Get display framebuffer RGBA bytes
     pub fn framebuffer_rgba(&self) -> &[u8] {
         match self.display_type {
             DisplayType::Pcd8544 => &self.pcd8544.framebuffer,
-            _ => &self.display.framebuffer,
+            _ => self.display.visible_rgba(),
+        }
+    }
+
+    /// 64-bit FNV-1a hash of the lit/unlit state of every pixel.
+    ///
+    /// Taken from the controller's pixel memory (the SSD1306 framebuffer,
+    /// PCD8544 VRAM), so it is stable across palettes, contrast and the
+    /// panel being switched off. Used by test scenarios to assert screen
+    /// contents.
+    pub fn framebuffer_hash(&self) -> u64 {
+        let bits = match self.display_type {
+            DisplayType::Pcd8544 => self.pcd8544.native_pixels(),
+            _ => self.display.pixel_bits(),
+        };
+        let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+        for px in bits.chunks(8) {
+            let byte = px.iter().enumerate().fold(0u8, |b, (i, &on)| b | (on as u8) << i);
+            h ^= byte as u64;
+            h = h.wrapping_mul(0x0000_0100_0000_01B3);
         }
+        h
     }
 
     /// Simple xorshift PRNG
@@ -1656,43 +3125,7 @@ impl Arduboy {
     ///
     /// Priority within each channel: hardware timer > GPIO bit-bang.
     pub fn get_audio_tone(&self) -> (f32, f32) {
-        let t1 = self.timer1.get_tone_hz(CLOCK_HZ);
-
-        // Timer3/Timer4 only on 32u4
-        let t3 = if self.cpu_type == CpuType::Atmega32u4 {
-            self.timer3.get_tone_hz(CLOCK_HZ)
-        } else { 0.0 };
-        let t4 = if self.cpu_type == CpuType::Atmega32u4 {
-            self.timer4.get_tone_hz(CLOCK_HZ)
-        } else { 0.0 };
-
-        // Timer2 only on 328P (Gamebuino sound)
-        let t2 = if self.cpu_type == CpuType::Atmega328p {
-            self.timer2.get_tone_hz(CLOCK_HZ)
-        } else { 0.0 };
-
-        // GPIO bit-bang speaker 1: derive frequency from toggle rate
-        // ATmega32u4: PC6 (Arduboy), ATmega328P: PD3 (Gamebuino Classic)
-        let gpio1_hz = if self.speaker_half_period > 0 {
-            let age = self.cpu.tick.saturating_sub(self.speaker_last_active);
-            if age < 250_000 {
-                CLOCK_HZ as f32 / (2.0 * self.speaker_half_period as f32)
-            } else { 0.0 }
-        } else { 0.0 };
-
-        // GPIO bit-bang speaker 2 (PB5): derive frequency from toggle rate
-        let gpio2_hz = if self.speaker2_half_period > 0 {
-            let age = self.cpu.tick.saturating_sub(self.speaker2_last_active);
-            if age < 250_000 {
-                CLOCK_HZ as f32 / (2.0 * self.speaker2_half_period as f32)
-            } else { 0.0 }
-        } else { 0.0 };
-
-        // Left: Timer3 > Timer4 > Timer2 > GPIO speaker 1 (PC6 on 32u4, PD3 on 328P)
-        let left = if t3 > 0.0 { t3 } else if t4 > 0.0 { t4 } else if t2 > 0.0 { t2 } else { gpio1_hz };
-        // Right: Timer1 > GPIO PB5
-        let right = if t1 > 0.0 { t1 } else { gpio2_hz };
-
+        let [(left, _), (right, _)] = self.audio_sources();
         (left, right)
     }
 
@@ -1711,40 +3144,67 @@ impl Arduboy {
             data: self.mem.data.clone(),
             eeprom: self.mem.eeprom.clone(),
             framebuffer: fb.to_vec(),
+            width: SCREEN_WIDTH,
             frame: self.frame_count,
         }
     }
 
     /// Restore state from a snapshot (rewind).
     pub fn restore_snapshot(&mut self, snap: &snapshot::Snapshot) {
-        self.cpu.pc = snap.pc;
+        if let Some(v) = self.verify.as_mut() {
+            v.mark(self.frame_count, "rewind");
+        }
+        self.cpu.pc = snap.pc & cpu::PC_MASK;
         self.cpu.sp = snap.sp;
         self.cpu.sreg = snap.sreg;
         self.cpu.tick = snap.tick;
         self.cpu.sleeping = snap.sleeping;
+        self.eeprom_ctrl.reset();
+        self.spm_until = None;
+        self.frame_run = None;
         let len = snap.data.len().min(self.mem.data.len());
         self.mem.data[..len].copy_from_slice(&snap.data[..len]);
+        self.mem.set_all_init(true);
+        self.adc.sync_mux(&self.mem.data);
+        self.sync_clock_div();
+        self.sound_events.clear();
         let elen = snap.eeprom.len().min(self.mem.eeprom.len());
         self.mem.eeprom[..elen].copy_from_slice(&snap.eeprom[..elen]);
         match self.display_type {
             DisplayType::Pcd8544 => {
                 let flen = snap.framebuffer.len().min(self.pcd8544.framebuffer.len());
                 self.pcd8544.framebuffer[..flen].copy_from_slice(&snap.framebuffer[..flen]);
+                self.pcd8544.dirty = true;
             }
             _ => {
                 let flen = snap.framebuffer.len().min(self.display.framebuffer.len());
                 self.display.framebuffer[..flen].copy_from_slice(&snap.framebuffer[..flen]);
+                self.display.dirty = true;
             }
         }
         self.frame_count = snap.frame;
     }
 
     /// Load flash from an ELF file, returning parsed debug info.
-    pub fn load_elf(&mut self, data: &[u8]) -> Result<elf::ElfFile, String> {
+    ///
+    /// An image with nothing at address 0 (a bootloader linked into the boot
+    /// section) starts at its entry point. Fuses from a `.fuse` section are
+    /// programmed before the reset.
+    pub fn load_elf(&mut self, data: &[u8]) -> Result<elf::ElfFile, Error> {
         let elf = elf::parse_elf(data)?;
         let flash_len = elf.flash.len().min(self.mem.flash.len());
         self.mem.flash[..flash_len].copy_from_slice(&elf.flash[..flash_len]);
+        self.program_size = flash_len;
+        self.fast_boot.set_symbols(&elf.symbols);
+        self.apply_rom_patches()?;
+        self.patches.apply(&mut self.mem.flash);
+        if let Some(fuses) = elf.fuses {
+            self.set_fuses(fuses);
+        }
         self.reset();
+        if elf.flash_base > 0 && (elf.entry as usize) < flash_len {
+            self.cpu.pc = elf.entry / 2;
+        }
         Ok(elf)
     }
 
@@ -1755,6 +3215,7 @@ impl Arduboy {
         match self.cpu_type {
             CpuType::Atmega32u4 => 0,
             CpuType::Atmega328p => 1,
+            CpuType::Atmega2560 => 2,
         }
     }
 
@@ -1786,6 +3247,8 @@ impl Arduboy {
             timer1: self.timer1.save_state(),
             timer2: self.timer2.save_state(),
             timer3: self.timer3.save_state(),
+            timer4_2560: self.timer4_2560.save_state(),
+            timer5: self.timer5.save_state(),
             timer4: self.timer4.save_state(),
 
             // Peripherals
@@ -1808,14 +3271,14 @@ impl Arduboy {
             fx_cs_prev: self.fx_cs_prev,
             pcd_cs_bit: self.pcd_cs_bit,
             pcd_dc_bit: self.pcd_dc_bit,
-            speaker_prev_pc6: self.speaker_prev_pc6,
-            speaker_last_edge: self.speaker_last_edge,
-            speaker_half_period: self.speaker_half_period,
-            speaker_last_active: self.speaker_last_active,
-            speaker2_prev_pb5: self.speaker2_prev_pb5,
-            speaker2_last_edge: self.speaker2_last_edge,
-            speaker2_half_period: self.speaker2_half_period,
-            speaker2_last_active: self.speaker2_last_active,
+            speaker_prev_pc6: self.audio_router.left.prev,
+            speaker_last_edge: self.audio_router.left.last_edge,
+            speaker_half_period: self.audio_router.left.half_period,
+            speaker_last_active: self.audio_router.left.last_active,
+            speaker2_prev_pb5: self.audio_router.right.prev,
+            speaker2_last_edge: self.audio_router.right.last_edge,
+            speaker2_half_period: self.audio_router.right.half_period,
+            speaker2_last_active: self.audio_router.right.last_active,
             usb_uenum: self.usb_uenum,
             usb_configured: self.usb_configured,
             led_rgb: self.led_rgb,
@@ -1829,8 +3292,11 @@ impl Arduboy {
     /// Restore the full emulator state from a save state.
     /// Clears the rewind buffer state (caller should also clear external RewindBuffer).
     pub fn load_full_state(&mut self, s: &savestate::SaveState) {
+        if let Some(v) = self.verify.as_mut() {
+            v.mark(self.frame_count, "state-load");
+        }
         // CPU
-        self.cpu.pc = s.pc;
+        self.cpu.pc = s.pc & cpu::PC_MASK;
         self.cpu.sp = s.sp;
         self.cpu.sreg = s.sreg;
         self.cpu.tick = s.tick;
@@ -1839,6 +3305,7 @@ impl Arduboy {
         // Memory
         let len = s.data.len().min(self.mem.data.len());
         self.mem.data[..len].copy_from_slice(&s.data[..len]);
+        self.mem.set_all_init(true);
         let elen = s.eeprom.len().min(self.mem.eeprom.len());
         self.mem.eeprom[..elen].copy_from_slice(&s.eeprom[..elen]);
 
@@ -1856,14 +3323,22 @@ impl Arduboy {
         self.timer1.load_state(&s.timer1);
         self.timer2.load_state(&s.timer2);
         self.timer3.load_state(&s.timer3);
+        self.timer4_2560.load_state(&s.timer4_2560);
+        self.timer5.load_state(&s.timer5);
         self.timer4.load_state(&s.timer4);
+        self.sync_clock_div();
 
         // Peripherals
         self.spi.load_state(&s.spi);
         self.adc.load_state(&s.adc);
+        self.adc.sync_mux(&self.mem.data);
+        self.sound_events.clear();
         self.pll.load_state(&s.pll);
+        self.eeprom_ctrl.reset();
+        self.spm_until = None;
+        self.frame_run = None;
         self.fx_flash.load_state(savestate::FxFlashState {
-            data: s.fx_flash.data.clone(),
+            journal: s.fx_flash.journal.clone(),
             loaded: s.fx_flash.loaded,
             write_enabled: s.fx_flash.write_enabled,
             powered_down: s.fx_flash.powered_down,
@@ -1875,6 +3350,7 @@ impl Arduboy {
         self.pin_d = s.pin_d;
         self.pin_e = s.pin_e;
         self.pin_f = s.pin_f;
+        self.ext_int.sync(self.pin_levels());
 
         // Misc
         self.spdr_in = s.spdr_in;
@@ -1883,14 +3359,14 @@ impl Arduboy {
         self.fx_cs_prev = s.fx_cs_prev;
         self.pcd_cs_bit = s.pcd_cs_bit;
         self.pcd_dc_bit = s.pcd_dc_bit;
-        self.speaker_prev_pc6 = s.speaker_prev_pc6;
-        self.speaker_last_edge = s.speaker_last_edge;
-        self.speaker_half_period = s.speaker_half_period;
-        self.speaker_last_active = s.speaker_last_active;
-        self.speaker2_prev_pb5 = s.speaker2_prev_pb5;
-        self.speaker2_last_edge = s.speaker2_last_edge;
-        self.speaker2_half_period = s.speaker2_half_period;
-        self.speaker2_last_active = s.speaker2_last_active;
+        self.audio_router.left.prev = s.speaker_prev_pc6;
+        self.audio_router.left.last_edge = s.speaker_last_edge;
+        self.audio_router.left.half_period = s.speaker_half_period;
+        self.audio_router.left.last_active = s.speaker_last_active;
+        self.audio_router.right.prev = s.speaker2_prev_pb5;
+        self.audio_router.right.last_edge = s.speaker2_last_edge;
+        self.audio_router.right.half_period = s.speaker2_half_period;
+        self.audio_router.right.last_active = s.speaker2_last_active;
         self.usb_uenum = s.usb_uenum;
         self.usb_configured = s.usb_configured;
         self.led_rgb = s.led_rgb;
@@ -1903,6 +3379,7 @@ impl Arduboy {
         self.spi_out.clear();
         self.serial_buf.clear();
         self.breakpoint_hit = false;
+        self.break_insn_hit = false;
         self.eeprom_dirty = false;
     }
 }
@@ -1986,6 +3463,30 @@ mod tests {
         assert_eq!(ard.pin_b & (1 << 1), 1 << 1);
     }
 
+    #[test]
+    fn test_board_profile_wiring() {
+        let board = BoardProfile::parse(r#"
+buttons = ["PB0", "PB1", "PB2", "PB3", "PD0", "PC7"]
+speaker = ["PB6"]
+display_cs = "PB7"
+display_dc = "PE2"
+"#).unwrap();
+        let mut ard = Arduboy::with_board(board);
+        assert_eq!(ard.display_type, DisplayType::Ssd1306);
+        assert_eq!(ard.audio_router.left.pin, Some(GpioPin::new('B', 6)));
+        ard.set_button(Button::B, true);
+        assert_eq!(ard.pin_c & 0x80, 0);
+        assert_eq!(ard.pin_b, 0xFF, "Arduboy B pin PB4 untouched");
+
+        // CS (PB7) low, DC (PE2) low: command byte reaches the SSD1306
+        ard.mem.data[0x25] = 0x00;
+        ard.mem.data[0x2E] = 0x00;
+        ard.mem.data[0x2B] = 0xFF;
+        ard.write_data(0x4E, 0xAF); // display on
+        ard.flush_spi();
+        assert_eq!(ard.display.dbg_cmd_count, 1);
+    }
+
     #[test]
     fn test_load_hex() {
         let mut ard = Arduboy::new();
@@ -1996,9 +3497,539 @@ mod tests {
         assert_eq!(ard.mem.flash[1], 0x94);
     }
 
+    #[test]
+    fn test_usb_serial_input() {
+        let mut ard = Arduboy::new();
+        ard.write_data(0xE9, USB_CDC_RX_EP); // UENUM = CDC OUT
+        assert_eq!(ard.read_data(0xF2), 0); // UEBCLX: nothing queued
+        assert_eq!(ard.read_data(0xE8) & 0x04, 0); // RXOUTI clear
+        ard.push_serial_input(b"hi");
+        assert_eq!(ard.read_data(0xF2), 2);
+        assert_eq!(ard.read_data(0xE8) & 0x24, 0x24); // RXOUTI + RWAL
+        assert_eq!(ard.read_data(0xF1), b'h');
+        assert_eq!(ard.read_data(0xF1), b'i');
+        assert_eq!(ard.serial_input_pending(), 0);
+    }
+
+    #[test]
+    fn test_usart_serial_input() {
+        let mut ard = Arduboy::new_with_cpu(CpuType::Atmega328p);
+        ard.push_serial_input(b"A");
+        assert_eq!(ard.read_data(0xC0) & 0x80, 0); // RXEN0 off: no RXC0
+        ard.write_data(0xC1, 0x18); // RXEN0 | TXEN0
+        assert_eq!(ard.read_data(0xC0) & 0x80, 0x80);
+        assert_eq!(ard.read_data(0xC6), b'A');
+        assert_eq!(ard.read_data(0xC0) & 0x80, 0);
+    }
+
+    #[test]
+    fn test_eeprom_guard() {
+        let mut ard = Arduboy::new();
+        ard.eeprom_guard.save_range = Some(eeprom_guard::EepromGuard::default_range(EEPROM_SIZE));
+        let write = |ard: &mut Arduboy, addr: u16, v: u8| {
+            ard.write_data(0x41, addr as u8);
+            ard.write_data(0x42, (addr >> 8) as u8);
+            ard.write_data(0x40, v);
+            ard.write_data(0x3F, 0x04); // EEMPE
+            ard.write_data(0x3F, 0x02); // EEPE
+            ard.cpu.tick += ard.clock_hz as u64 / 250; // past the 3.3 ms write
+        };
+        write(&mut ard, 0x02, 0x00); // Arduboy2 audio setting
+        write(&mut ard, 0x10, 0x42);
+        assert_eq!(ard.mem.eeprom[0x02], 0xFF);
+        assert_eq!(ard.mem.eeprom[0x10], 0x42);
+        assert_eq!(ard.eeprom_guard.blocked, 1);
+        assert_eq!(ard.eeprom_wear.session_writes(), 1);
+    }
+
+    #[test]
+    fn test_run_frame_budgeted() {
+        let mut whole = Arduboy::new();
+        let mut sliced = Arduboy::new();
+        whole.run_frame();
+        // No host time: every call stops at the first check
+        let mut calls = 0;
+        while let Some(c) = sliced.run_frame_budgeted(0) {
+            assert_eq!(c.frame, 0);
+            assert!(c.done >= BUDGET_CHECK_TICKS * (calls + 1) && c.done < c.total);
+            calls += 1;
+        }
+        assert!(calls > 100, "{} calls", calls);
+        assert_eq!((sliced.frame_count(), sliced.cpu.tick), (1, whole.cpu.tick));
+        // run_frame finishes a sliced frame
+        assert!(sliced.run_frame_budgeted(0).is_some());
+        sliced.run_frame();
+        whole.run_frame();
+        assert_eq!((sliced.frame_count(), sliced.cpu.tick), (2, whole.cpu.tick));
+        assert_eq!(sliced.run_frame_budgeted(1_000_000), None);
+    }
+
+    #[test]
+    fn test_eeprom_write_time() {
+        let mut ard = Arduboy::new();
+        ard.write_data(0x40, 0x5A);
+        ard.write_data(0x3F, 0x04 | 0x08); // EEMPE, EERIE
+        ard.write_bit(0x3F, 1, true); // sbi EECR, EEPE
+        assert_eq!(ard.mem.eeprom[0], 0x5A);
+        assert_eq!(ard.read_data(0x3F), 0x0A, "EEPE set while programming");
+        ard.cpu.sreg |= 1 << SREG_I;
+        ard.service_interrupts();
+        assert_eq!(ard.cpu.pc, 0, "EE_READY waits for the write");
+        ard.cpu.tick = ard.next_irq_tick;
+        assert_eq!(ard.cpu.tick, ard.clock_hz as u64 * 26_368 / 8_000_000);
+        ard.service_interrupts();
+        assert_eq!(ard.cpu.pc, peripherals::INT_EE_READY as u32);
+    }
+
+    #[test]
+    fn test_framebuffer_hash_ignores_contrast() {
+        let mut ard = Arduboy::new();
+        let blank = ard.framebuffer_hash();
+        ard.display.receive_command(0x81);
+        ard.display.receive_command(0x10); // dim contrast
+        ard.display.receive_data(0xFF);
+        let drawn = ard.framebuffer_hash();
+        assert_ne!(drawn, blank);
+        ard.display.receive_command(0xAE); // display off
+        assert_eq!(ard.framebuffer_hash(), drawn);
+    }
+
+    #[test]
+    fn test_granular_resets() {
+        let mut ard = Arduboy::new();
+        ard.cpu.pc = 0x100;
+        ard.cpu.tick = 5000;
+        ard.mem.set_reg(5, 7);
+        ard.mem.data[0x200] = 0xAA;
+        ard.mem.eeprom[0] = 0x55;
+        ard.write_data(0x45, 0x03); // TCCR0B
+        ard.display.framebuffer[0] = 0xFF;
+
+        ard.reset_cpu_only();
+        assert_eq!((ard.cpu.pc, ard.mem.reg(5), ard.cpu.tick), (0, 0, 5000));
+        assert_eq!((ard.mem.data[0x200], ard.mem.data[0x45]), (0xAA, 0x03));
+        assert_eq!(ard.display.framebuffer[0], 0xFF);
+
+        ard.write_data(SPL_ADDR, 0x00);
+        ard.write_data(SREG_ADDR, 0x80);
+        ard.reset_peripherals();
+        assert_eq!(ard.mem.data[0x45], 0x00);
+        assert_eq!((ard.mem.data[SPL_ADDR as usize], ard.mem.data[SREG_ADDR as usize]), (0x00, 0x80));
+        assert_eq!((ard.mem.data[0x200], ard.display.framebuffer[0]), (0xAA, 0xFF));
+
+        ard.clear_display();
+        assert_eq!(ard.display.framebuffer[0], 0x00);
+        assert_eq!((ard.mem.data[0x200], ard.mem.eeprom[0]), (0xAA, 0x55));
+    }
+
+    #[test]
+    fn test_protect_region_store_pc() {
+        let mut ard = Arduboy::new();
+        // STS 0x0150, r16; ST X, r16
+        ard.mem.flash[..6].copy_from_slice(&[0x00, 0x93, 0x50, 0x01, 0x0C, 0x93]);
+        ard.mem.data[26] = 0x50;
+        ard.mem.data[27] = 0x01;
+        ard.debugger.protect_regions.push(debugger::ProtectRegion::parse("0x100-0x4FF@0x4-0x5", None).unwrap());
+        ard.step_one();
+        assert_eq!(ard.debugger.take_hit().unwrap().protect_pc, Some(0));
+        ard.step_one();
+        assert!(ard.debugger.take_hit().is_none());
+    }
+
+    #[test]
+    fn test_spi_decoder_framing() {
+        let mut ard = Arduboy::new();
+        ard.spi_decoder.start();
+        ard.write_data(0x2A, 0xFF); // DDRD: all outputs
+        ard.write_data(0x2B, 0x00); // PORTD: display CS (PD6) and DC (PD4) low
+        ard.write_data(0x4E, 0xAF);
+        ard.write_data(0x2B, 0x10); // DC high: data phase
+        ard.write_data(0x4E, 0x01);
+        ard.write_data(0x4E, 0x02);
+        ard.write_data(0x2B, 0x50); // CS high: end of transaction
+        let t: Vec<_> = ard.spi_decoder.transactions().collect();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].summary, "DISPLAY ON");
+        assert_eq!(t[1].dc_data, Some(true));
+        assert_eq!(t[1].mosi, vec![0x01, 0x02]);
+    }
+
+    #[test]
+    fn test_fx_loader_page_copy() {
+        // What the FX bootloader does per page: read 128 bytes from external
+        // flash above 64 KB over SPI, fill the SPM buffer, erase and write
+        let mut ard = Arduboy::new();
+        let image: Vec<u8> = (0..0x20000u32).map(|i| (i as u8).wrapping_mul(7) ^ (i >> 16) as u8).collect();
+        ard.load_fx_data(&image);
+        ard.write_data(0x2A, 0xFF); // DDRD: all outputs
+        ard.write_data(0x2B, 0xFD); // PORTD: FX CS (PD1) low, display CS high
+        for b in [0x03, 0x01, 0x23, 0x00] {
+            ard.write_data(0x4E, b);
+        }
+        let mut page = Vec::new();
+        for _ in 0..memory::SPM_PAGE_SIZE {
+            ard.write_data(0x4E, 0x00);
+            page.push(ard.read_data(0x4E));
+        }
+        ard.write_data(0x2B, 0xFF);
+        assert_eq!(page, image[0x12300..0x12380]);
+
+        ard.mem.set_z(0x4000);
+        for pair in page.chunks(2) {
+            ard.mem.set_reg(0, pair[0]);
+            ard.mem.set_reg(1, pair[1]);
+            ard.write_data(0x57, 0x01);
+            ard.execute_inst(opcodes::Instruction::Spm, 1);
+            ard.mem.set_z(ard.mem.z() + 2);
+        }
+        ard.mem.set_z(0x4000);
+        for cmd in [0x03, 0x05] {
+            ard.write_data(0x57, cmd);
+            ard.execute_inst(opcodes::Instruction::Spm, 1);
+        }
+        assert_eq!(ard.mem.flash[0x4000..0x4080], image[0x12300..0x12380]);
+    }
+
+    #[test]
+    fn test_pin_change_interrupt_on_button() {
+        // PCINT4 (B button, PB4) wakes a sleeping CPU into the PCINT0 vector
+        let mut ard = Arduboy::new();
+        ard.write_data(0x6B, 0x10); // PCMSK0
+        ard.write_data(0x68, 0x01); // PCICR
+        ard.cpu.sreg |= 1 << SREG_I;
+        ard.cpu.pc = 0x100;
+        ard.cpu.sleeping = true;
+        ard.update_peripherals();
+        assert_eq!(ard.cpu.pc, 0x100);
+        ard.set_button(Button::B, true);
+        ard.update_peripherals();
+        assert_eq!(ard.cpu.pc, peripherals::INT_PCINT0 as u32);
+        assert!(!ard.cpu.sleeping);
+        assert_eq!(ard.mem.data[0x3B], 0x00);
+    }
+
+    #[test]
+    fn test_interrupt_priority_after_sei() {
+        // Timer0 and Timer1 compare A both pending: Timer1's lower vector
+        // wins on the 32u4, and only after the instruction following SEI
+        let mut ard = Arduboy::new();
+        ard.write_data(0x44, 0x02); // TCCR0A: CTC
+        ard.write_data(0x47, 0x0F); // OCR0A
+        ard.write_data(0x6E, 0x02); // TIMSK0: OCIE0A
+        ard.write_data(0x45, 0x01); // TCCR0B: clk/1
+        ard.write_data(0x89, 0x00); // OCR1AH
+        ard.write_data(0x88, 0x0F); // OCR1AL
+        ard.write_data(0x6F, 0x02); // TIMSK1: OCIE1A
+        ard.write_data(0x81, 0x09); // TCCR1B: CTC, clk/1
+        ard.mem.flash[0x40..0x44].copy_from_slice(&[0x78, 0x94, 0x03, 0x95]); // sei; inc r16
+        ard.cpu.pc = 0x20;
+        ard.cpu.tick = 64;
+        ard.update_peripherals();
+        ard.step_one();
+        assert_eq!(ard.cpu.pc, 0x21);
+        ard.step_one();
+        assert_eq!(ard.mem.data[16], 1);
+        assert_eq!(ard.cpu.pc, peripherals::INT_TIMER1_COMPA as u32);
+        // Timer0's match is still pending behind it
+        assert_eq!(ard.timer0.check_interrupt(), Some(peripherals::INT_TIMER0_COMPA));
+    }
+
+    #[test]
+    fn test_timer_interrupt_latency() {
+        // Timer0 compare match at count 100 with the CPU spinning in a loop:
+        // the interrupt is taken at the next instruction boundary rather
+        // than at the next periodic peripheral update
+        let mut ard = Arduboy::new();
+        ard.write_data(0x47, 100); // OCR0A
+        ard.write_data(0x6E, 0x02); // TIMSK0: OCIE0A
+        ard.write_data(0x45, 0x01); // TCCR0B: clk/1
+        ard.mem.flash[0..2].copy_from_slice(&[0xFF, 0xCF]); // rjmp .-2
+        ard.cpu.sreg |= 1 << SREG_I;
+        ard.breakpoints.push(peripherals::INT_TIMER0_COMPA as u32);
+        ard.run_frame();
+        assert!(ard.breakpoint_hit);
+        assert!((100..=106).contains(&ard.cpu.tick), "taken at tick {}", ard.cpu.tick);
+    }
+
+    #[test]
+    fn test_clock_hz() {
+        // A frame covers the same emulated time at any clock
+        let mut ard = Arduboy::new();
+        ard.clock_hz = 8_000_000;
+        ard.mem.flash[0..2].copy_from_slice(&[0xFF, 0xCF]); // rjmp .-2
+        ard.run_frame();
+        assert!(ard.cpu.tick.abs_diff(FRAME_CYCLES / 2) <= 2, "tick {}", ard.cpu.tick);
+        // Timer tones follow the clock: Timer3 CTC toggle, OCR3A = 999, clk/1
+        ard.write_data(0x98, 0xE7);
+        ard.write_data(0x99, 0x03);
+        ard.write_data(0x90, 0x40);
+        ard.write_data(0x91, 0x09);
+        assert_eq!(ard.timer3.get_tone_hz(ard.clock_hz), 4000.0);
+
+        assert_eq!(parse_clock_hz("8M"), Ok(8_000_000));
+        assert_eq!(parse_clock_hz("16.5MHz"), Ok(16_500_000));
+        assert_eq!(parse_clock_hz("24000000"), Ok(24_000_000));
+        assert!(parse_clock_hz("500k").is_err());
+        assert!(parse_clock_hz("fast").is_err());
+    }
+
+    #[test]
+    fn test_break_halts_when_enabled() {
+        // break; rjmp .-2
+        let mut ard = Arduboy::new();
+        ard.mem.flash[0..4].copy_from_slice(&[0x98, 0x95, 0xFF, 0xCF]);
+        ard.run_frame();
+        assert!(!ard.breakpoint_hit);
+        ard.reset();
+        ard.halt_on_break = true;
+        ard.run_frame();
+        assert!(ard.breakpoint_hit);
+        assert_eq!(ard.cpu.pc, 1);
+    }
+
+    #[test]
+    fn test_ckdiv8_and_clkpr() {
+        let mut ard = Arduboy::new();
+        ard.set_fuses(fuses::Fuses { low: 0x7F, high: 0xD1, extended: None });
+        ard.reset();
+        assert_eq!(ard.clock_div(), 8);
+        assert_eq!(ard.mem.data[CLKPR_ADDR as usize], 3);
+        // nop: one CPU cycle is eight 16 MHz ticks
+        ard.step();
+        assert_eq!(ard.cpu.tick, 8);
+        // CLKPS without CLKPCE first is ignored
+        ard.write_data(CLKPR_ADDR, 0x00);
+        assert_eq!(ard.clock_div(), 8);
+        // clock_prescale_set(clock_div_1)
+        ard.write_data(CLKPR_ADDR, 0x80);
+        ard.write_data(CLKPR_ADDR, 0x00);
+        assert_eq!(ard.clock_div(), 1);
+        ard.step();
+        assert_eq!(ard.cpu.tick, 9);
+    }
+
+    #[test]
+    fn test_flush_timing_follows_clock() {
+        // 8 MHz crystal with CLKPR /2: 4 MHz CPU, SPI at fosc/2 = 16 CPU
+        // cycles (32 ticks) per byte
+        let mut ard = Arduboy::new();
+        ard.clock_hz = 8_000_000;
+        ard.write_data(CLKPR_ADDR, 0x80);
+        ard.write_data(CLKPR_ADDR, 0x01);
+        assert_eq!(ard.clock_div(), 2);
+        ard.flush_timing.enabled = true;
+        ard.write_data(0x4C, 0x50); // SPCR: SPE, MSTR, fosc/4
+        ard.write_data(0x4D, 0x01); // SPSR: SPI2X
+        ard.write_data(0x2A, 0xFF); // DDRD: all outputs
+        ard.write_data(0x2B, 0x10); // PORTD: display CS (PD6) low, DC (PD4) high
+        for _ in 0..4 {
+            ard.write_data(0x4E, 0x55);
+            ard.cpu.tick += 40;
+        }
+        ard.run_frame();
+        let f = ard.flush_timing.recent(0).unwrap();
+        assert_eq!((f.byte_cycles, f.minimum, f.window, f.clock_hz), (32, 128, 3 * 40 + 32, 8_000_000));
+        assert!(f.line().contains("152 cycles (0.02 ms)"), "{}", f.line());
+    }
+
+    #[test]
+    fn test_bootrst_starts_at_boot_section() {
+        let mut ard = Arduboy::new();
+        // BOOTRST, BOOTSZ = 512 words: boot section at word 0x3E00
+        ard.set_fuses(fuses::Fuses { low: 0xFF, high: 0xDC, extended: None });
+        assert_eq!(ard.boot_section(), Some((0x3E00, 0x4000)));
+        // Empty boot section runs through to 0
+        ard.reset();
+        assert_eq!(ard.cpu.pc, 0);
+        ard.mem.flash[0x7C00..0x7C02].copy_from_slice(&[0xFF, 0xCF]); // rjmp .-2
+        ard.reset();
+        assert_eq!(ard.cpu.pc, 0x3E00);
+    }
+
+    #[test]
+    fn test_frame_timeline_sleep() {
+        // sleep; rjmp .-4 (no interrupts enabled: sleeps to the end of the frame)
+        let mut ard = Arduboy::new();
+        ard.mem.flash[0..4].copy_from_slice(&[0x88, 0x95, 0xFE, 0xCF]);
+        ard.frame_timeline.start();
+        ard.run_frame();
+        let f = ard.frame_timeline.recent(0).unwrap();
+        assert_eq!(f.frame, 0);
+        assert_eq!(f.spans.len(), 1);
+        assert_eq!(f.spans[0].track, frame_timeline::Track::Sleep);
+        assert!(f.spans[0].end - f.spans[0].start > FRAME_CYCLES - 8);
+    }
+
+    #[test]
+    fn test_verify_replay_matches() {
+        // loop: in r16, PINF; sts 0x0100, r16; rjmp loop
+        let prog: [u16; 4] = [0xB10F, 0x9300, 0x0100, 0xCFFC];
+        let load = || {
+            let mut ard = Arduboy::new();
+            for (i, w) in prog.iter().enumerate() {
+                ard.mem.flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+            }
+            ard
+        };
+        let mut ard = load();
+        ard.start_verify(4, "loop");
+        for f in 0..8 {
+            ard.set_button(Button::Up, f == 3);
+            ard.run_frame();
+        }
+        let log = ard.verify.as_ref().unwrap().finish();
+        let info = verify::parse_log(&log).unwrap();
+        assert_eq!(info.inputs.len(), 3);
+
+        let mut replay = load();
+        replay.start_verify(info.interval, &info.game);
+        let mut inputs = info.inputs.iter().peekable();
+        for frame in 0..info.frames {
+            while let Some((_, p)) = inputs.next_if(|(f, _)| *f == frame) {
+                replay.pin_b = p[0]; replay.pin_c = p[1]; replay.pin_d = p[2];
+                replay.pin_e = p[3]; replay.pin_f = p[4];
+            }
+            replay.run_frame();
+        }
+        assert_eq!(replay.verify.as_ref().unwrap().finish(), log);
+    }
+
+    #[test]
+    fn test_full_state_dump() {
+        let mut a = Arduboy::new();
+        let mut b = Arduboy::new();
+        let text = a.dump_full_state_text();
+        assert_eq!(text, b.dump_full_state_text());
+        let keys: Vec<&str> = text.lines().skip(1).map(|l| l.split(" = ").next().unwrap()).collect();
+        assert!(keys.windows(2).all(|w| w[0] < w[1]), "sorted, no duplicates");
+        assert!(text.contains("\ncpu.r31 = 0x00\n"));
+        assert!(text.contains("\nio.TIMER0.TCCR0B = 0x00\n"));
+        assert!(text.contains("\ntimer1.prescale = "));
+
+        b.mem.data[0x180] = 1;
+        b.write_data(0x45, 0x03); // TCCR0B: clk/64
+        let after = b.dump_full_state_text();
+        let changed: Vec<&str> = text.lines().zip(after.lines())
+            .filter(|(x, y)| x != y)
+            .map(|(x, _)| x.split(" = ").next().unwrap())
+            .collect();
+        assert!(changed.contains(&"mem.sram"));
+        assert!(changed.contains(&"mem.sram.0x0100"));
+        assert!(changed.contains(&"io.TIMER0.TCCR0B"));
+        assert!(!changed.contains(&"mem.sram.0x0200"));
+    }
+
+    #[test]
+    fn test_queued_button_events() {
+        // loop: in r16, PINF; sts 0x0100, r16; rjmp loop
+        let prog: [u16; 4] = [0xB10F, 0x9300, 0x0100, 0xCFFC];
+        let load = || {
+            let mut ard = Arduboy::new();
+            for (i, w) in prog.iter().enumerate() {
+                ard.mem.flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+            }
+            ard
+        };
+        let mut ard = load();
+        ard.start_verify(2, "loop");
+        ard.queue_button_event(500_000, Button::Up, false);
+        ard.queue_button_event(1_000, Button::Up, true);
+        assert_eq!(ard.pending_button_events(), 2);
+        ard.run_frame();
+        assert_eq!(ard.mem.data[0x100] & 0x80, 0, "press seen within the frame");
+        assert_eq!(ard.pending_button_events(), 1);
+        ard.run_frame();
+        ard.run_frame();
+        assert_eq!(ard.mem.data[0x100] & 0x80, 0x80);
+        let log = ard.verify.as_ref().unwrap().finish();
+        let info = verify::parse_log(&log).unwrap();
+        assert_eq!(info.events.len(), 2);
+        assert_eq!(info.events[0].2, Button::Up);
+        assert!(info.events[0].1 >= 1_000 && info.events[0].1 < 1_010);
+
+        let mut replay = load();
+        replay.start_verify(info.interval, &info.game);
+        for frame in 0..info.frames {
+            for &(_, cycle, b, p) in info.events.iter().filter(|e| e.0 == frame) {
+                replay.queue_button_event(replay.cpu.tick + cycle, b, p);
+            }
+            replay.run_frame();
+        }
+        assert_eq!(replay.verify.as_ref().unwrap().finish(), log);
+    }
+
+    #[test]
+    fn test_vcd_pin_capture() {
+        let path = std::env::temp_dir().join("arduboy_vcd_pin_capture.vcd");
+        let mut ard = Arduboy::new();
+        ard.start_vcd(&path, vcd::parse_probes("PB5,PF7").unwrap()).unwrap();
+        ard.write_data(0x24, 0x20); // DDRB: PB5 output, driven low
+        ard.cpu.tick = 16;
+        ard.vcd_sample();
+        ard.set_button(Button::Up, true); // PF7 pulled low
+        ard.cpu.tick = 32;
+        ard.vcd_sample();
+        assert_eq!(ard.finish_vcd().unwrap(), 4);
+        let out = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        // Inputs idle high (pull-ups / released buttons)
+        assert!(out.contains("#0\n1!\n1\"\n"));
+        assert!(out.contains("#1000000\n0!\n"));
+        assert!(out.contains("#2000000\n0\"\n"));
+    }
+
     /// Diagnostic test: loads a Gamebuino Classic HEX and runs frames,
     /// printing detailed SPI/display state to find black screen causes.
     /// Run with: cargo test test_328p_display_diag -- --nocapture
+    #[test]
+    fn test_timer_oc_pin_output() {
+        let mut ard = Arduboy::new();
+        ard.write_data(0x24, 0x80); // DDRB: PB7 (OC0A) output, PORTB7 = 0
+        ard.write_data(0x47, 100);  // OCR0A
+        ard.write_data(0x44, 0x83); // TCCR0A: COM0A=2, fast PWM (WGM=3)
+        ard.write_data(0x45, 0x01); // TCCR0B: clk/1
+        ard.timer0.update(50, &mut ard.mem.data);
+        assert_eq!(ard.read_data(0x23) & 0x80, 0x80, "high before compare match");
+        ard.timer0.update(150, &mut ard.mem.data);
+        assert_eq!(ard.read_data(0x23) & 0x80, 0, "low after compare match");
+
+        // CTC toggle: OC0A flips on every match
+        let mut ard = Arduboy::new();
+        ard.write_data(0x24, 0x80);
+        ard.write_data(0x47, 9);
+        ard.write_data(0x44, 0x42); // COM0A=1, WGM01 (CTC)
+        ard.write_data(0x45, 0x01);
+        ard.timer0.update(10, &mut ard.mem.data);
+        assert_eq!(ard.read_data(0x23) & 0x80, 0x80);
+        ard.timer0.update(20, &mut ard.mem.data);
+        assert_eq!(ard.read_data(0x23) & 0x80, 0);
+
+        // Disconnected compare output: pin follows PORTB again
+        ard.write_data(0x44, 0x02);
+        ard.write_data(0x25, 0x80);
+        assert_eq!(ard.read_data(0x23) & 0x80, 0x80);
+    }
+
+    #[test]
+    fn test_floating_pin_policy() {
+        let mut ard = Arduboy::new();
+        assert_eq!(ard.read_data(0x2F), 0xFF, "default policy reads high");
+
+        ard.floating_pins = FloatingPinPolicy::parse("script:0x00,0x0F").unwrap();
+        ard.write_data(0x31, 0xF0); // PORTF: pull-ups on PF4..PF7
+        ard.pin_f = !0x10;         // PF4 pulled low externally
+        assert_eq!(ard.read_data(0x2F), 0xE0);
+        assert_eq!(ard.read_data(0x2F), 0xEF);
+        assert_eq!(ard.read_data(0x2F), 0xE0, "script cycles");
+
+        ard.floating_pins = FloatingPinPolicy::Scripted(Vec::new());
+        assert_eq!(ard.read_data(0x2F), 0xEF, "empty script reads high");
+
+        assert!(FloatingPinPolicy::parse("noise").is_err());
+        assert!(FloatingPinPolicy::parse("script:0x1FF").is_err());
+        assert_eq!(FloatingPinPolicy::parse("random"), Ok(FloatingPinPolicy::Random));
+    }
+
     #[test]
     fn test_328p_display_diag() {
         let mut ard = Arduboy::new_with_cpu(CpuType::Atmega328p);
@@ -2037,8 +4068,9 @@ mod tests {
         println!("[DIAG] Reset vector: flash[0..4] = {:02X} {:02X} {:02X} {:02X}",
             ard.mem.flash[0], ard.mem.flash[1], ard.mem.flash[2], ard.mem.flash[3]);
 
-        // Enable SPI byte trace
-        ard.spi_trace_enabled = true;
+        // Enable SPI byte trace, including port writes
+        ard.spi_trace.filter.ports = true;
+        ard.spi_trace.start();
 
         // Run frames and collect diagnostics
         for frame in 1..=2 {
@@ -2105,16 +4137,17 @@ mod tests {
         }
 
         // Dump SPI trace
-        let portc_writes = ard.spi_trace.iter().filter(|s| s.contains("PORTC_WRITE")).count();
-        let ddrc_writes = ard.spi_trace.iter().filter(|s| s.contains("DDRC_WRITE")).count();
-        let spdr_writes_in_trace = ard.spi_trace.iter().filter(|s| s.starts_with("SPDR")).count();
-        let skip_count = ard.spi_trace.iter().filter(|s| s.starts_with("SKIP")).count();
-        let cmd_count = ard.spi_trace.iter().filter(|s| s.starts_with("CMD")).count();
-        let data_count = ard.spi_trace.iter().filter(|s| s.starts_with("DATA")).count();
-
-        println!("\n[DIAG] === TRACE SUMMARY (v2) ===");
-        println!("[DIAG] trace_entries={} PORTC_WRITE={} DDRC_WRITE={} SPDR={} SKIP={} CMD={} DATA={}",
-            ard.spi_trace.len(), portc_writes, ddrc_writes, spdr_writes_in_trace,
+        use spi_trace::SpiTraceKind;
+        let mosi = || ard.spi_trace.entries().filter(|e| e.kind == SpiTraceKind::Mosi);
+        let portc_writes = ard.spi_trace.entries().filter(|e| e.kind == SpiTraceKind::Port(0x28)).count();
+        let spdr_writes_in_trace = mosi().count();
+        let skip_count = mosi().filter(|e| !e.cs_active).count();
+        let cmd_count = mosi().filter(|e| e.cs_active && e.dc_data == Some(false)).count();
+        let data_count = mosi().filter(|e| e.cs_active && e.dc_data == Some(true)).count();
+
+        println!("\n[DIAG] === TRACE SUMMARY (v3) ===");
+        println!("[DIAG] trace_entries={} PORTC_WRITE={} SPDR={} SKIP={} CMD={} DATA={}",
+            ard.spi_trace.len(), portc_writes, spdr_writes_in_trace,
             skip_count, cmd_count, data_count);
 
         if portc_writes == 0 {
@@ -2125,8 +4158,8 @@ mod tests {
 
         println!("\n[DIAG] === SPI BYTE TRACE (first {} of {} entries) ===",
             ard.spi_trace.len().min(200), ard.spi_trace.len());
-        for (i, entry) in ard.spi_trace.iter().take(200).enumerate() {
-            println!("[TRACE {:3}] {}", i, entry);
+        for (i, entry) in ard.spi_trace.entries().take(200).enumerate() {
+            println!("[TRACE {:3}] {}", i, entry.format_line());
         }
     }
 }
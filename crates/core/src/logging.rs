@@ -0,0 +1,174 @@
+//! Leveled diagnostics with per-subsystem categories.
+//!
+//! Core diagnostics go to stderr when their category's level allows it.
+//! Levels are set per [`LogCategory`] with a spec string such as
+//! `"spi=trace,fx=debug"` or `"info"` (all categories), from the CLI
+//! (`--log`) or the step debugger (`log`). The legacy
+//! [`Arduboy::debug`](crate::Arduboy::debug) flag still enables everything.
+
+use std::fmt::Write;
+
+/// Message severity, from least to most verbose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_lowercase().as_str() {
+            "off" | "none" => LogLevel::Off,
+            "error" => LogLevel::Error,
+            "warn" | "warning" => LogLevel::Warn,
+            "info" => LogLevel::Info,
+            "debug" => LogLevel::Debug,
+            "trace" => LogLevel::Trace,
+            _ => return None,
+        })
+    }
+}
+
+/// Emulator subsystem a message belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogCategory {
+    /// Instruction execution, PC hotspots
+    Cpu,
+    /// SPI bus traffic
+    Spi,
+    /// SSD1306 / PCD8544 controllers and display detection
+    Display,
+    /// FX flash chip
+    Fx,
+    /// USB CDC / USART serial
+    Usb,
+    /// Timers and interrupts
+    Timer,
+    /// Tone and waveform generation
+    Audio,
+    /// EEPROM controller
+    Eeprom,
+}
+
+impl LogCategory {
+    pub const ALL: [LogCategory; 8] = [
+        LogCategory::Cpu, LogCategory::Spi, LogCategory::Display, LogCategory::Fx,
+        LogCategory::Usb, LogCategory::Timer, LogCategory::Audio, LogCategory::Eeprom,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogCategory::Cpu => "cpu",
+            LogCategory::Spi => "spi",
+            LogCategory::Display => "display",
+            LogCategory::Fx => "fx",
+            LogCategory::Usb => "usb",
+            LogCategory::Timer => "timer",
+            LogCategory::Audio => "audio",
+            LogCategory::Eeprom => "eeprom",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|c| c.name().eq_ignore_ascii_case(s))
+    }
+}
+
+/// Level per category. Everything defaults to [`LogLevel::Error`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogConfig {
+    levels: [LogLevel; LogCategory::ALL.len()],
+}
+
+impl LogConfig {
+    pub fn new() -> Self {
+        LogConfig { levels: [LogLevel::Error; LogCategory::ALL.len()] }
+    }
+
+    pub fn level(&self, cat: LogCategory) -> LogLevel {
+        self.levels[cat as usize]
+    }
+
+    pub fn set(&mut self, cat: LogCategory, level: LogLevel) {
+        self.levels[cat as usize] = level;
+    }
+
+    pub fn set_all(&mut self, level: LogLevel) {
+        self.levels = [level; LogCategory::ALL.len()];
+    }
+
+    /// Whether a `level` message in `cat` is shown.
+    pub fn enabled(&self, cat: LogCategory, level: LogLevel) -> bool {
+        level != LogLevel::Off && level <= self.level(cat)
+    }
+
+    /// Apply a comma-separated spec: `level` sets every category,
+    /// `category=level` sets one (`all=level` is the same as `level`).
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (cat, lvl) = match item.split_once('=') {
+                Some((c, l)) => (Some(c.trim()), l.trim()),
+                None => (None, item),
+            };
+            let level = LogLevel::parse(lvl)
+                .ok_or_else(|| format!("unknown log level '{}' (off, error, warn, info, debug, trace)", lvl))?;
+            match cat {
+                None | Some("all") => self.set_all(level),
+                Some(c) => {
+                    let cat = LogCategory::parse(c).ok_or_else(|| format!(
+                        "unknown log category '{}' (cpu, spi, display, fx, usb, timer, audio, eeprom)", c))?;
+                    self.set(cat, level);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Current levels as a spec string, e.g. `cpu=error spi=trace ...`.
+    pub fn describe(&self) -> String {
+        let mut s = String::new();
+        for cat in LogCategory::ALL {
+            let _ = write!(s, "{}{}={}", if s.is_empty() { "" } else { " " },
+                cat.name(), self.level(cat).name());
+        }
+        s
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_spec() {
+        let mut c = LogConfig::new();
+        assert!(!c.enabled(LogCategory::Fx, LogLevel::Debug));
+        c.apply("warn, fx=trace").unwrap();
+        assert!(c.enabled(LogCategory::Fx, LogLevel::Trace));
+        assert!(c.enabled(LogCategory::Spi, LogLevel::Warn));
+        assert!(!c.enabled(LogCategory::Spi, LogLevel::Info));
+        assert!(!c.enabled(LogCategory::Spi, LogLevel::Off));
+        assert!(c.apply("gpu=debug").is_err());
+        assert!(c.apply("fx=loud").is_err());
+        assert!(c.describe().starts_with("cpu=warn spi=warn display=warn fx=trace"));
+    }
+}
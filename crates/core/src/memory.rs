@@ -8,10 +8,39 @@
 //! | 0x0020–0x00FF | I/O + extended I/O registers |
 //! | 0x0100+       | SRAM (2560 bytes on 32u4, 2048 bytes on 328P) |
 //!
-//! Flash (32 KB) and EEPROM (1 KB) are separate address spaces.
+//! Flash (32 KB) and EEPROM (1 KB) are separate address spaces. Flash is
+//! written a page at a time through SPM's temporary page buffer, as the FX
+//! bootloader does when it copies a game out of external flash. LPM (or
+//! ELPM) after BLBSET or SIGRD reads the fuse/lock bytes and the signature
+//! row instead; the command lapses if none follows within four cycles.
+//!
+//! Instructions are decoded once per flash load: [`fetch`](Memory::fetch)
+//! keeps each decoded word until SPM rewrites its page or
+//! [`invalidate_decoded`](Memory::invalidate_decoded) is called. Code that
+//! writes `flash` directly must call it (a reset does).
+//!
+//! With [`track_init`](Memory::track_init) on, a bitmap records which
+//! data-space bytes have been written since the last reset, for strict
+//! mode's uninitialized-read reports ([`crate::uninit`]).
 
+use crate::opcodes::{self, Instruction};
 use crate::{DATA_SIZE, FLASH_SIZE, EEPROM_SIZE};
 
+/// Flash page size written by SPM (ATmega32u4 and ATmega328P)
+pub const SPM_PAGE_SIZE: usize = 128;
+/// Flash page size on parts above 64 KB (ATmega2560)
+pub const SPM_PAGE_SIZE_LARGE: usize = 256;
+
+// SPMCSR command bits
+const SPMEN: u8 = 1 << 0;
+const PGERS: u8 = 1 << 1;
+const PGWRT: u8 = 1 << 2;
+const BLBSET: u8 = 1 << 3;
+const RWWSRE: u8 = 1 << 4;
+const SIGRD: u8 = 1 << 5;
+/// SPMCSR bit 6: RWW section busy after an erase or write
+pub const RWWSB: u8 = 1 << 6;
+
 /// AVR memory model containing data space, flash, and EEPROM.
 pub struct Memory {
     /// Unified data space: registers (0x00-0x1F) + I/O (0x20-0xFF) + SRAM (0x100+)
@@ -20,26 +49,54 @@ pub struct Memory {
     pub flash: Vec<u8>,
     /// EEPROM
     pub eeprom: Vec<u8>,
+    /// SPM temporary page buffer, one flash page long
+    spm_buffer: Vec<u8>,
+    /// RWW section locked by a page erase/write until RWWSRE
+    pub rww_busy: bool,
+    /// Fuse and lock bytes in LPM/BLBSET order: low, lock, extended, high
+    pub fuse_bits: [u8; 4],
+    /// Device signature bytes read with SIGRD (Z = 0, 2, 4)
+    pub signature: [u8; 3],
+    /// Decoded instruction and size by word address, filled on first fetch
+    decoded: Vec<Option<(Instruction, u8)>>,
+    /// Bit per data-space byte written since the last reset; empty when
+    /// not tracking
+    init: Vec<u64>,
 }
 
 impl Memory {
     pub fn new() -> Self {
-        Memory {
-            data: vec![0u8; DATA_SIZE],
-            flash: vec![0u8; FLASH_SIZE],
-            eeprom: vec![0xFFu8; EEPROM_SIZE],
-        }
+        Self::new_with_sizes(DATA_SIZE, FLASH_SIZE)
     }
 
     /// Create memory with a specific data-space size (REG + IO + SRAM).
     pub fn new_with_size(data_size: usize) -> Self {
+        Self::new_with_sizes(data_size, FLASH_SIZE)
+    }
+
+    /// Create memory with specific data-space and flash sizes.
+    pub fn new_with_sizes(data_size: usize, flash_size: usize) -> Self {
+        let page = if flash_size > 0x10000 { SPM_PAGE_SIZE_LARGE } else { SPM_PAGE_SIZE };
         Memory {
             data: vec![0u8; data_size],
-            flash: vec![0u8; FLASH_SIZE],
+            flash: vec![0u8; flash_size],
             eeprom: vec![0xFFu8; EEPROM_SIZE],
+            spm_buffer: vec![0xFF; page],
+            rww_busy: false,
+            // Arduboy (Caterina, 4 KB boot section, BOOTRST unprogrammed)
+            // and the 32u4 signature
+            fuse_bits: [0xFF, 0x2F, 0xCB, 0xD1],
+            signature: [0x1E, 0x95, 0x87],
+            decoded: vec![None; flash_size / 2],
+            init: Vec::new(),
         }
     }
 
+    /// Flash page size written by SPM on this part.
+    pub fn spm_page_size(&self) -> usize {
+        self.spm_buffer.len()
+    }
+
     // --- Register access ---
 
     #[inline(always)]
@@ -120,13 +177,104 @@ impl Memory {
         }
     }
 
-    /// Read single byte from flash at byte address
+    /// Decoded instruction at a word address and its size in words.
     #[inline(always)]
-    pub fn read_flash_byte(&self, byte_addr: usize) -> u8 {
-        if byte_addr < self.flash.len() {
-            self.flash[byte_addr]
+    pub fn fetch(&mut self, word_addr: usize) -> (Instruction, u8) {
+        if let Some(Some(d)) = self.decoded.get(word_addr) {
+            return *d;
+        }
+        let next_word = if word_addr + 1 < self.flash.len() / 2 {
+            self.read_program_word(word_addr + 1)
         } else {
             0
+        };
+        let d = opcodes::decode(self.read_program_word(word_addr), next_word);
+        if let Some(slot) = self.decoded.get_mut(word_addr) {
+            *slot = Some(d);
+        }
+        d
+    }
+
+    /// Drop all decoded instructions after `flash` was changed.
+    pub fn invalidate_decoded(&mut self) {
+        self.decoded.fill(None);
+    }
+
+    /// Drop decoded instructions overlapping a flash byte range, including a
+    /// two-word instruction that starts just before it.
+    fn invalidate_decoded_range(&mut self, start: usize, len: usize) {
+        let end = ((start + len) / 2).min(self.decoded.len());
+        self.decoded[(start / 2).saturating_sub(1)..end].fill(None);
+    }
+
+    /// Read single byte from flash at byte address. Address bits above the
+    /// configured flash size (32 KB, or 256 KB on the ATmega2560) are
+    /// ignored, so RAMPZ:Z wraps like on the real part.
+    #[inline(always)]
+    pub fn read_flash_byte(&self, byte_addr: usize) -> u8 {
+        self.flash[byte_addr % self.flash.len()]
+    }
+
+    /// Execute SPM for the command in `spmcsr`, with RAMPZ:Z in `addr` and
+    /// R1:R0 in `word`:
+    ///
+    /// | SPMCSR | Action |
+    /// |--------|--------|
+    /// | SPMEN | Store `word` in the page buffer at the Z offset |
+    /// | PGERS + SPMEN | Erase the page at Z to 0xFF |
+    /// | PGWRT + SPMEN | Program the buffer into the page at Z, then clear the buffer |
+    /// | RWWSRE + SPMEN | Re-enable the RWW section (clears [`rww_busy`](Self::rww_busy)) |
+    /// | BLBSET + SPMEN | Program the boot lock bits (BLB0x/BLB1x) from R0 |
+    ///
+    /// Erase and write complete at once but leave the RWW section busy.
+    /// Returns false if SPMEN was not set.
+    pub fn spm(&mut self, spmcsr: u8, addr: usize, word: u16) -> bool {
+        if spmcsr & SPMEN == 0 {
+            return false;
+        }
+        let size = self.spm_buffer.len();
+        let page = (addr % self.flash.len()) & !(size - 1);
+        match spmcsr & 0x3E {
+            0 => {
+                let off = addr & (size - 1) & !1;
+                self.spm_buffer[off..off + 2].copy_from_slice(&word.to_le_bytes());
+            }
+            PGERS => {
+                self.flash[page..page + size].fill(0xFF);
+                self.invalidate_decoded_range(page, size);
+                self.rww_busy = true;
+            }
+            PGWRT => {
+                // Programming only clears bits
+                for (f, b) in self.flash[page..page + size].iter_mut().zip(&self.spm_buffer) {
+                    *f &= b;
+                }
+                self.spm_buffer.fill(0xFF);
+                self.invalidate_decoded_range(page, size);
+                self.rww_busy = true;
+            }
+            RWWSRE => self.rww_busy = false,
+            BLBSET => self.fuse_bits[1] &= word as u8 | 0xC3,
+            _ => {}
+        }
+        true
+    }
+
+    /// Byte LPM reads at `z` while SPMCSR holds a read command: a fuse or
+    /// lock byte after BLBSET, the signature row after SIGRD. `None` when
+    /// LPM reads flash as usual.
+    pub fn lpm_special(&self, spmcsr: u8, z: u16) -> Option<u8> {
+        if spmcsr & SPMEN == 0 {
+            return None;
+        }
+        match spmcsr & 0x3E {
+            BLBSET => Some(self.fuse_bits[z as usize & 3]),
+            SIGRD => Some(match z {
+                0 | 2 | 4 => self.signature[z as usize / 2],
+                1 => 0x80, // RC oscillator calibration
+                _ => 0xFF,
+            }),
+            _ => None,
         }
     }
 
@@ -142,6 +290,38 @@ impl Memory {
     pub fn write_raw(&mut self, addr: u16, v: u8) {
         let a = addr as usize;
         if a < self.data.len() { self.data[a] = v; }
+        self.mark_init(addr);
+    }
+
+    // --- Initialized bitmap ---
+
+    /// Start (all bytes unwritten) or stop tracking written bytes.
+    pub fn track_init(&mut self, on: bool) {
+        self.init = if on { vec![0; self.data.len().div_ceil(64)] } else { Vec::new() };
+    }
+
+    pub fn tracking_init(&self) -> bool {
+        !self.init.is_empty()
+    }
+
+    /// Record a write to `addr`.
+    #[inline(always)]
+    pub fn mark_init(&mut self, addr: u16) {
+        let a = addr as usize;
+        if let Some(w) = self.init.get_mut(a / 64) {
+            *w |= 1 << (a % 64);
+        }
+    }
+
+    /// Whether `addr` has been written; always true when not tracking.
+    pub fn is_init(&self, addr: u16) -> bool {
+        let a = addr as usize;
+        self.init.get(a / 64).is_none_or(|w| w >> (a % 64) & 1 != 0)
+    }
+
+    /// Mark every byte unwritten (reset) or written (state load).
+    pub fn set_all_init(&mut self, written: bool) {
+        self.init.fill(if written { !0 } else { 0 });
     }
 }
 
@@ -164,6 +344,82 @@ mod tests {
         assert_eq!(mem.data[31], 0x12);
     }
 
+    #[test]
+    fn test_spm_page_write() {
+        let mut mem = Memory::new();
+        mem.flash[0x1000..0x1080].fill(0x00);
+        assert!(!mem.spm(0, 0x1000, 0x1234));
+        for i in 0..SPM_PAGE_SIZE / 2 {
+            mem.spm(SPMEN, 0x1000 + i * 2, 0x0100 + i as u16);
+        }
+        mem.spm(PGERS | SPMEN, 0x1042, 0);
+        assert!(mem.flash[0x1000..0x1080].iter().all(|&b| b == 0xFF));
+        mem.spm(PGWRT | SPMEN, 0x1000, 0);
+        assert_eq!(mem.read_program_word(0x800), 0x0100);
+        assert_eq!(mem.read_program_word(0x83F), 0x013F);
+        assert_eq!(mem.flash[0x1080], 0x00);
+        // The buffer is cleared after a write
+        mem.spm(PGWRT | SPMEN, 0x1080, 0);
+        assert_eq!(mem.flash[0x1080], 0x00);
+        assert!(mem.rww_busy);
+        mem.spm(RWWSRE | SPMEN, 0, 0);
+        assert!(!mem.rww_busy);
+    }
+
+    #[test]
+    fn test_decode_cache_invalidation() {
+        let mut mem = Memory::new();
+        // JMP at the last word of a page, its address word in the next page
+        mem.flash[0xFFE..0x1002].copy_from_slice(&[0x0C, 0x94, 0x34, 0x12]);
+        assert!(matches!(mem.fetch(0x7FF), (Instruction::Jmp { k: 0x1234 }, 2)));
+        mem.flash[0xFFE..0x1000].fill(0x00);
+        assert!(matches!(mem.fetch(0x7FF), (Instruction::Jmp { .. }, 2)), "cached until invalidated");
+        mem.invalidate_decoded();
+        assert!(matches!(mem.fetch(0x7FF), (Instruction::Nop, 1)));
+        mem.flash[0xFFE..0x1000].copy_from_slice(&[0x0C, 0x94]);
+        mem.invalidate_decoded();
+        mem.fetch(0x7FF);
+        mem.spm(PGERS | SPMEN, 0x1000, 0);
+        assert!(matches!(mem.fetch(0x7FF), (Instruction::Jmp { k: 0xFFFF }, 2)));
+        // Out of range: decoded, not cached
+        assert!(matches!(mem.fetch(0x4000), (Instruction::Nop, 1)));
+    }
+
+    #[test]
+    fn test_init_bitmap() {
+        let mut m = Memory::new();
+        assert!(m.is_init(0x200), "everything counts as written when not tracking");
+        m.track_init(true);
+        assert!(!m.is_init(0x200) && !m.is_init(0x23F));
+        m.write_raw(0x23F, 1);
+        assert!(m.is_init(0x23F) && !m.is_init(0x240) && !m.is_init(0x23E));
+        m.set_all_init(false);
+        assert!(!m.is_init(0x23F));
+        assert!(m.is_init(0xFFFF), "outside data space");
+    }
+
+    #[test]
+    fn test_fuse_and_signature_read() {
+        let mut mem = Memory::new();
+        assert_eq!(mem.lpm_special(SIGRD, 0), None);
+        assert_eq!(mem.lpm_special(SPMEN, 0), None);
+        let sig: Vec<u8> = [0, 2, 4].iter().map(|&z| mem.lpm_special(SIGRD | SPMEN, z).unwrap()).collect();
+        assert_eq!(sig, [0x1E, 0x95, 0x87]);
+        assert_eq!(mem.lpm_special(BLBSET | SPMEN, 3), Some(0xD1));
+        // Only the boot lock bits can be programmed
+        mem.spm(BLBSET | SPMEN, 0, 0x00);
+        assert_eq!(mem.lpm_special(BLBSET | SPMEN, 1), Some(0x03));
+    }
+
+    #[test]
+    fn test_large_page() {
+        let mut mem = Memory::new_with_sizes(DATA_SIZE, 0x40000);
+        assert_eq!(mem.spm_page_size(), SPM_PAGE_SIZE_LARGE);
+        mem.flash[0x20000..0x20100].fill(0x00);
+        mem.spm(PGERS | SPMEN, 0x200FE, 0);
+        assert!(mem.flash[0x20000..0x20100].iter().all(|&b| b == 0xFF));
+    }
+
     #[test]
     fn test_program_word() {
         let mut mem = Memory::new();
@@ -69,6 +69,11 @@ pub enum Instruction {
     StZInc { r: u8 },
     StZDec { r: u8 },
     StZQ { r: u8, q: u8 },
+    // Read-modify-write at Z (XMEGA): Rd gets the old byte
+    Xch { d: u8 },
+    Las { d: u8 },
+    Lac { d: u8 },
+    Lat { d: u8 },
     // Stack
     Push { r: u8 },
     Pop { d: u8 },
@@ -132,6 +137,8 @@ pub enum Instruction {
     Wdr,
     Break,
     Spm,
+    /// DES round K (XMEGA)
+    Des { k: u8 },
     Unknown(u16),
 }
 
@@ -429,6 +436,8 @@ fn decode_1001(word: u16, _next_word: u16) -> (Instruction, u8) {
             0x6 => return (Instruction::Lsr { d: d_r }, 1),
             0x7 => return (Instruction::Ror { d: d_r }, 1),
             0xA => return (Instruction::Dec { d: d_r }, 1),
+            // DES: 1001 0100 KKKK 1011
+            0xB if word & 0x0100 == 0 => return (Instruction::Des { k: d_r & 0x0F }, 1),
             _ => {}
         }
     }
@@ -508,6 +517,10 @@ fn decode_1001(word: u16, _next_word: u16) -> (Instruction, u8) {
             // STS handled in 32-bit above (0x0000)
             0x1 => return (Instruction::StZInc { r: d_r }, 1),
             0x2 => return (Instruction::StZDec { r: d_r }, 1),
+            0x4 => return (Instruction::Xch { d: d_r }, 1),
+            0x5 => return (Instruction::Las { d: d_r }, 1),
+            0x6 => return (Instruction::Lac { d: d_r }, 1),
+            0x7 => return (Instruction::Lat { d: d_r }, 1),
             0x9 => return (Instruction::StYInc { r: d_r }, 1),
             0xA => return (Instruction::StYDec { r: d_r }, 1),
             0xC => return (Instruction::StX { r: d_r }, 1),
@@ -813,4 +826,17 @@ mod tests {
             _ => panic!("Expected Adiw, got {:?}", inst),
         }
     }
+
+    #[test]
+    fn test_decode_xmega_extras() {
+        // XCH Z, R17 => 1001 0011 0001 0100
+        assert!(matches!(decode(0x9314, 0).0, Instruction::Xch { d: 17 }));
+        assert!(matches!(decode(0x9315, 0).0, Instruction::Las { d: 17 }));
+        assert!(matches!(decode(0x9316, 0).0, Instruction::Lac { d: 17 }));
+        assert!(matches!(decode(0x9317, 0).0, Instruction::Lat { d: 17 }));
+        // DES 0x0F => 1001 0100 1111 1011
+        assert!(matches!(decode(0x94FB, 0).0, Instruction::Des { k: 15 }));
+        assert!(matches!(decode(0x95FB, 0).0, Instruction::Unknown(0x95FB)));
+        assert!(matches!(decode(0x9598, 0).0, Instruction::Break));
+    }
 }
@@ -0,0 +1,258 @@
+//! `.arduboy` package writer.
+//!
+//! [`Package`] collects a game's build artifacts and metadata and writes
+//! them as a `.arduboy` ZIP archive with a generated `info.json`, the
+//! reverse of [`arduboy_file`](crate::arduboy_file):
+//!
+//! | Member | Source | Checked |
+//! |--------|--------|---------|
+//! | `<name>.hex` | HEX file, or the flash image of an ELF | Parses, ends below the bootloader (0x7000) |
+//! | `<name>-fx.bin` | FX data | Whole 256-byte pages |
+//! | `<name>-save.bin` | FX save area | Whole 4 KB sectors; data + save fit the 16 MB chip |
+//! | `title.png` | Title image | PNG, 128×64 |
+//! | `info.json` | Metadata fields | Title present |
+//!
+//! Members are deflated (or stored when that is smaller) and carry a fixed
+//! 1980-01-01 timestamp, so the same inputs always give the same file.
+
+use crate::png::crc32;
+use crate::rotation::Rotation;
+
+/// First byte of the Caterina bootloader; programs must end below it
+pub const BOOTLOADER_START: usize = 0x7000;
+/// FX flash chip size
+const FX_CHIP_SIZE: usize = 16 * 1024 * 1024;
+const FX_PAGE: usize = 256;
+const FX_SECTOR: usize = 4096;
+
+/// Metadata and artifacts of a game to package.
+#[derive(Debug, Clone, Default)]
+pub struct Package {
+    pub title: String,
+    pub author: String,
+    pub description: String,
+    pub version: String,
+    pub genre: String,
+    pub url: String,
+    pub license: String,
+    /// Base name of the members (`<name>.hex`, `<name>-fx.bin`, ...)
+    pub name: String,
+    /// Intel HEX program
+    pub hex: String,
+    pub fx_data: Option<Vec<u8>>,
+    pub fx_save: Option<Vec<u8>>,
+    /// PNG shown by loaders and cart builders
+    pub title_image: Option<Vec<u8>>,
+    pub rotation: Rotation,
+}
+
+impl Package {
+    /// Check the artifacts; returns the program size in bytes.
+    pub fn validate(&self) -> Result<usize, String> {
+        if self.title.trim().is_empty() {
+            return Err("a title is required".into());
+        }
+        let mut flash = vec![0xFF; crate::FLASH_SIZE];
+        let size = crate::hex::parse_hex(&self.hex, &mut flash).map_err(|e| format!("program: {}", e))?;
+        if size == 0 {
+            return Err("program: HEX file holds no data".into());
+        }
+        if size > BOOTLOADER_START {
+            return Err(format!("program is {} bytes; the bootloader leaves {}", size, BOOTLOADER_START));
+        }
+        let data = self.fx_data.as_ref().map_or(0, |d| d.len());
+        let save = self.fx_save.as_ref().map_or(0, |s| s.len());
+        if self.fx_data.is_some() && (data == 0 || !data.is_multiple_of(FX_PAGE)) {
+            return Err(format!("FX data is {} bytes, not a whole number of {}-byte pages", data, FX_PAGE));
+        }
+        if self.fx_save.is_some() && (save == 0 || !save.is_multiple_of(FX_SECTOR)) {
+            return Err(format!("FX save is {} bytes, not a whole number of {}-byte sectors", save, FX_SECTOR));
+        }
+        if data + save > FX_CHIP_SIZE {
+            return Err(format!("FX data and save are {} bytes; the chip holds {}", data + save, FX_CHIP_SIZE));
+        }
+        if let Some(png) = &self.title_image {
+            match png_size(png) {
+                Some((128, 64)) => {}
+                Some((w, h)) => return Err(format!("title image is {}x{}, expected 128x64", w, h)),
+                None => return Err("title image is not a PNG file".into()),
+            }
+        }
+        Ok(size)
+    }
+
+    fn member(&self, suffix: &str) -> String {
+        format!("{}{}", self.name, suffix)
+    }
+
+    /// The generated `info.json`.
+    pub fn info_json(&self) -> String {
+        let mut top = Vec::new();
+        top.push(("schemaVersion", "3".to_string()));
+        for (key, value) in [
+            ("title", &self.title), ("version", &self.version), ("author", &self.author),
+            ("description", &self.description), ("genre", &self.genre),
+            ("url", &self.url), ("license", &self.license),
+        ] {
+            if !value.is_empty() {
+                top.push((key, json_string(value)));
+            }
+        }
+        if self.rotation != Rotation::None {
+            top.push(("rotation", self.rotation.degrees().to_string()));
+        }
+
+        let mut bin = vec![
+            ("title", json_string(&self.title)),
+            ("filename", json_string(&self.member(".hex"))),
+            ("device", json_string(if self.fx_data.is_some() { "ArduboyFX" } else { "Arduboy" })),
+        ];
+        if self.fx_data.is_some() {
+            bin.push(("flashdata", json_string(&self.member("-fx.bin"))));
+        }
+        if self.fx_save.is_some() {
+            bin.push(("flashsave", json_string(&self.member("-save.bin"))));
+        }
+        if self.title_image.is_some() {
+            bin.push(("cartimage", json_string("title.png")));
+        }
+        let fields = |f: &[(&str, String)], indent: &str| f.iter()
+            .map(|(k, v)| format!("{}\"{}\": {}", indent, k, v))
+            .collect::<Vec<_>>()
+            .join(",\n");
+        format!("{{\n{},\n  \"binaries\": [\n    {{\n{}\n    }}\n  ]\n}}\n",
+            fields(&top, "  "), fields(&bin, "      "))
+    }
+
+    /// Validate and build the archive.
+    pub fn to_zip(&self) -> Result<Vec<u8>, String> {
+        self.validate()?;
+        let mut files = vec![
+            ("info.json".to_string(), self.info_json().into_bytes()),
+            (self.member(".hex"), self.hex.clone().into_bytes()),
+        ];
+        if let Some(d) = &self.fx_data {
+            files.push((self.member("-fx.bin"), d.clone()));
+        }
+        if let Some(s) = &self.fx_save {
+            files.push((self.member("-save.bin"), s.clone()));
+        }
+        if let Some(png) = &self.title_image {
+            files.push(("title.png".to_string(), png.clone()));
+        }
+        Ok(write_zip(&files))
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Width and height from a PNG's IHDR chunk.
+fn png_size(png: &[u8]) -> Option<(u32, u32)> {
+    if png.len() < 24 || &png[..8] != b"\x89PNG\r\n\x1a\n" || &png[12..16] != b"IHDR" {
+        return None;
+    }
+    let be = |at: usize| u32::from_be_bytes([png[at], png[at + 1], png[at + 2], png[at + 3]]);
+    Some((be(16), be(20)))
+}
+
+// ─── Minimal ZIP Writer ─────────────────────────────────────────────────────
+
+/// DOS date of 1980-01-01 (time 00:00)
+const DOS_DATE: u16 = 0x0021;
+
+pub(crate) fn write_zip(files: &[(String, Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (name, data) in files {
+        let crc = crc32(&[], data);
+        let deflated = miniz_oxide::deflate::compress_to_vec(data, 9);
+        let (method, body): (u16, &[u8]) = if deflated.len() < data.len() {
+            (8, &deflated)
+        } else {
+            (0, data)
+        };
+        let offset = out.len() as u32;
+        // Fields shared by the local header and the central directory entry
+        let mut common = Vec::new();
+        common.extend_from_slice(&20u16.to_le_bytes()); // version needed
+        common.extend_from_slice(&0u16.to_le_bytes()); // flags
+        common.extend_from_slice(&method.to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // time
+        common.extend_from_slice(&DOS_DATE.to_le_bytes());
+        common.extend_from_slice(&crc.to_le_bytes());
+        common.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        common.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        common.extend_from_slice(&0u16.to_le_bytes()); // extra length
+
+        out.extend_from_slice(&0x04034b50u32.to_le_bytes());
+        out.extend_from_slice(&common);
+        out.extend_from_slice(name.as_bytes());
+        out.extend_from_slice(body);
+
+        central.extend_from_slice(&0x02014b50u32.to_le_bytes());
+        central.extend_from_slice(&20u16.to_le_bytes()); // version made by
+        central.extend_from_slice(&common);
+        central.extend_from_slice(&[0; 6]); // comment length, disk, internal attributes
+        central.extend_from_slice(&0u32.to_le_bytes()); // external attributes
+        central.extend_from_slice(&offset.to_le_bytes());
+        central.extend_from_slice(name.as_bytes());
+    }
+    let cd_offset = out.len() as u32;
+    out.extend_from_slice(&central);
+    out.extend_from_slice(&0x06054b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]); // disk numbers
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(files.len() as u16).to_le_bytes());
+    out.extend_from_slice(&(central.len() as u32).to_le_bytes());
+    out.extend_from_slice(&cd_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_round_trip() {
+        let mut p = Package {
+            title: "Test Game".into(),
+            author: "Someone".into(),
+            name: "test".into(),
+            hex: crate::hex::to_hex(&[0x0C, 0x94, 0x00, 0x00]),
+            fx_data: Some(vec![0xAB; 512]),
+            fx_save: Some(vec![0xFF; 4096]),
+            title_image: Some(crate::png::encode_png_mono(128, 64, &[false; 128 * 64])),
+            rotation: Rotation::Cw90,
+            ..Default::default()
+        };
+        let zip = p.to_zip().unwrap();
+        let ab = crate::arduboy_file::parse_arduboy(&zip).unwrap();
+        assert_eq!(ab.title, "Test Game");
+        assert_eq!(ab.author, "Someone");
+        assert_eq!(ab.rotation, Rotation::Cw90);
+        assert_eq!(ab.fx_data.as_deref(), Some(&[0xAB; 512][..]));
+        assert_eq!(ab.fx_save.map(|s| s.len()), Some(4096));
+        assert!(ab.files.contains_key("title.png"));
+
+        p.fx_data = Some(vec![0; 300]);
+        assert!(p.to_zip().unwrap_err().contains("256-byte pages"));
+        p.fx_data = None;
+        p.title_image = Some(crate::png::encode_png_mono(64, 64, &[false; 64 * 64]));
+        assert_eq!(p.validate().unwrap_err(), "title image is 64x64, expected 128x64");
+    }
+}
@@ -0,0 +1,228 @@
+//! In-memory flash patches.
+//!
+//! A patch file changes the program after it is loaded, without editing the
+//! HEX: skipping a busy-wait on hardware the emulator does not model, or a
+//! cheat. Each line is one patch, optionally named with a `name:` prefix;
+//! `#` starts a comment:
+//!
+//! ```text
+//! # Arduboy2 waits for USB on some boards
+//! usb: ret Arduboy2Core::waitForUSB
+//! lives: 0x1A4 = e089 9508
+//! nop 0x0200-0x0210
+//! ```
+//!
+//! | Patch | Effect |
+//! |-------|--------|
+//! | `<addr> = <word> ...` | Replace instruction words (hex, as in the disassembly) from a flash byte address |
+//! | `nop <start>-<end>` | Fill a flash byte range `[start, end)` with `NOP` |
+//! | `ret <symbol\|addr>` | Return immediately from a function: `RET` at its entry |
+//!
+//! Symbols need an ELF file; C++ names are matched against the mangled ones
+//! (see [`ElfFile::find_symbols`]). Patches are kept across resets and put
+//! back after the program is reloaded. Disabling one restores the original
+//! words.
+
+use crate::elf::ElfFile;
+
+const NOP: u16 = 0x0000;
+const RET: u16 = 0x9508;
+
+/// One patch.
+#[derive(Debug, Clone)]
+pub struct Patch {
+    pub name: String,
+    /// Line as written, without the name
+    pub spec: String,
+    /// Flash byte address
+    pub addr: u32,
+    pub words: Vec<u16>,
+    /// Words the patch replaced
+    original: Vec<u16>,
+    pub enabled: bool,
+}
+
+/// Patches in file order.
+#[derive(Debug, Clone, Default)]
+pub struct Patches {
+    patches: Vec<Patch>,
+}
+
+fn hex(s: &str) -> Option<u32> {
+    u32::from_str_radix(s.trim().trim_start_matches("0x").trim_start_matches("0X"), 16).ok()
+}
+
+fn even(addr: u32) -> Result<u32, String> {
+    match addr % 2 {
+        0 => Ok(addr),
+        _ => Err(format!("0x{:X} is not an instruction address (odd)", addr)),
+    }
+}
+
+/// Flash byte address of a hex address or function symbol.
+fn location(s: &str, elf: Option<&ElfFile>) -> Result<u32, String> {
+    if let Some(addr) = hex(s) {
+        return even(addr);
+    }
+    let elf = elf.ok_or_else(|| format!("'{}': symbols need an ELF file", s))?;
+    match elf.find_symbols(s).as_slice() {
+        [] => Err(format!("unknown symbol '{}'", s)),
+        [addr] => even(*addr),
+        many => Err(format!("'{}' matches {} symbols", s, many.len())),
+    }
+}
+
+impl Patch {
+    /// Parse one patch line (without the name).
+    pub fn parse(name: &str, spec: &str, elf: Option<&ElfFile>) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (addr, words) = if let Some(range) = spec.strip_prefix("nop ") {
+            let (start, end) = range.split_once('-')
+                .and_then(|(a, b)| Some((hex(a)?, hex(b)?)))
+                .ok_or_else(|| format!("expected nop <start>-<end>, got '{}'", spec))?;
+            if end <= start {
+                return Err(format!("empty range '{}'", range.trim()));
+            }
+            (even(start)?, vec![NOP; (end - start).div_ceil(2) as usize])
+        } else if let Some(func) = spec.strip_prefix("ret ") {
+            (location(func.trim(), elf)?, vec![RET])
+        } else if let Some((addr, words)) = spec.split_once('=') {
+            let addr = hex(addr).ok_or_else(|| format!("bad address '{}'", addr.trim()))?;
+            let words = words.split_whitespace()
+                .map(|w| u16::from_str_radix(w, 16).map_err(|_| format!("bad word '{}'", w)))
+                .collect::<Result<Vec<_>, _>>()?;
+            if words.is_empty() {
+                return Err("no replacement words".into());
+            }
+            (even(addr)?, words)
+        } else {
+            return Err(format!("expected '<addr> = <words>', 'nop <range>' or 'ret <fn>', got '{}'", spec));
+        };
+        Ok(Patch { name: name.to_string(), spec: spec.to_string(), addr, words, original: Vec::new(), enabled: true })
+    }
+
+    fn write(flash: &mut [u8], addr: u32, words: &[u16]) {
+        for (i, w) in words.iter().enumerate() {
+            let at = addr as usize + i * 2;
+            if let Some(b) = flash.get_mut(at..at + 2) {
+                b.copy_from_slice(&w.to_le_bytes());
+            }
+        }
+    }
+}
+
+impl Patches {
+    /// Parse a patch file; errors name the line.
+    pub fn parse(text: &str, elf: Option<&ElfFile>) -> Result<Self, String> {
+        let mut patches = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            // `name:` prefix, but not the `::` of a C++ symbol
+            let (name, spec) = match line.split_once(':') {
+                Some((name, spec)) if !spec.starts_with(':') && !name.contains(char::is_whitespace) =>
+                    (name.to_string(), spec),
+                _ => (format!("patch{}", patches.len()), line),
+            };
+            let patch = Patch::parse(&name, spec, elf).map_err(|e| format!("line {}: {}", n + 1, e))?;
+            patches.push(patch);
+        }
+        Ok(Patches { patches })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Patch> {
+        self.patches.iter()
+    }
+
+    /// Append `other`, patching `flash`.
+    pub fn extend(&mut self, other: Patches, flash: &mut [u8]) {
+        let first = self.patches.len();
+        self.patches.extend(other.patches);
+        for p in &mut self.patches[first..] {
+            Self::install(p, flash);
+        }
+    }
+
+    /// Patch freshly loaded `flash`, remembering the words replaced.
+    pub fn apply(&mut self, flash: &mut [u8]) {
+        for p in &mut self.patches {
+            Self::install(p, flash);
+        }
+    }
+
+    fn install(p: &mut Patch, flash: &mut [u8]) {
+        p.original = (0..p.words.len())
+            .map(|i| p.addr as usize + i * 2)
+            .map(|at| flash.get(at..at + 2).map_or(0xFFFF, |b| u16::from_le_bytes([b[0], b[1]])))
+            .collect();
+        if p.enabled {
+            Patch::write(flash, p.addr, &p.words);
+        }
+    }
+
+    /// Enable or disable a patch by index or name; false if there is no
+    /// such patch.
+    pub fn set_enabled(&mut self, which: &str, on: bool, flash: &mut [u8]) -> bool {
+        let found = self.patches.iter_mut().enumerate()
+            .find(|(i, p)| p.name == which || which.parse() == Ok(*i));
+        let Some((_, p)) = found else { return false };
+        p.enabled = on;
+        let words = if on { &p.words } else { &p.original };
+        Patch::write(flash, p.addr, words);
+        true
+    }
+
+    /// Remove all patches, restoring the original words.
+    pub fn clear(&mut self, flash: &mut [u8]) {
+        for p in self.patches.iter().rev().filter(|p| p.enabled) {
+            Patch::write(flash, p.addr, &p.original);
+        }
+        self.patches.clear();
+    }
+
+    /// Format the patch list.
+    pub fn list(&self) -> String {
+        if self.patches.is_empty() {
+            return "No patches.\n".into();
+        }
+        self.patches.iter().enumerate()
+            .map(|(i, p)| format!("  [{}]{} {:<12} 0x{:04X} {} word(s)  {}\n",
+                i, if p.enabled { " " } else { "!" }, p.name, p.addr, p.words.len(), p.spec))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_apply_toggle() {
+        let text = "# comment\nlives: 0x10 = e089 9508\nnop 0x20-0x25  # trailing\nret 0x30\n";
+        let mut patches = Patches::parse(text, None).unwrap();
+        let names: Vec<_> = patches.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["lives", "patch1", "patch2"]);
+        assert_eq!(patches.iter().nth(1).unwrap().words, [NOP; 3]);
+
+        let mut flash = vec![0xFFu8; 0x40];
+        patches.apply(&mut flash);
+        assert_eq!(flash[0x10..0x14], [0x89, 0xE0, 0x08, 0x95]);
+        assert_eq!(flash[0x20..0x26], [0; 6]);
+        assert_eq!(flash[0x30..0x32], [0x08, 0x95]);
+
+        assert!(patches.set_enabled("lives", false, &mut flash));
+        assert_eq!(flash[0x10..0x14], [0xFF; 4]);
+        assert!(patches.set_enabled("0", true, &mut flash));
+        assert_eq!(flash[0x10], 0x89);
+        assert!(!patches.set_enabled("speed", true, &mut flash));
+
+        assert!(Patches::parse("ret Game::update", None).unwrap_err().contains("line 1: 'Game::update': symbols need an ELF"));
+        assert!(Patches::parse("\n0x11 = 0000", None).unwrap_err().starts_with("line 2: 0x11 is not"));
+    }
+}
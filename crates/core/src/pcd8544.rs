@@ -4,13 +4,17 @@
 //! centered within the standard 128×64 framebuffer for unified rendering.
 //! Supports basic and extended instruction sets, horizontal/vertical
 //! addressing, and contrast/bias configuration commands.
+//! [`Pcd8544::native_pixels`] gives the panel at its own 84×48 for
+//! screenshots and recordings that should not carry the border.
 
 use crate::{SCREEN_WIDTH, SCREEN_HEIGHT};
 
 const FB_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4; // RGBA
-const PCD_WIDTH: usize = 84;
-const PCD_HEIGHT: usize = 48;
-const PCD_PAGES: usize = 6; // 48 / 8
+pub const PCD_WIDTH: usize = 84;
+pub const PCD_HEIGHT: usize = 48;
+pub const PCD_PAGES: usize = 6; // 48 / 8
+/// LCD colors of clear (index 0) and dark (index 1) pixels for exports
+pub const PALETTE: [[u8; 3]; 2] = [[0xC0, 0xD8, 0x78], [0x3C, 0x48, 0x28]];
 
 /// PCD8544 84x48 monochrome LCD display controller (Nokia 5110)
 pub struct Pcd8544 {
@@ -106,6 +110,11 @@ impl Pcd8544 {
         }
     }
 
+    /// Whether the controller is out of power-down mode.
+    pub fn powered_on(&self) -> bool {
+        !self.power_down
+    }
+
     pub fn receive_data(&mut self, byte: u8) {
         self.dbg_data_count += 1;
 
@@ -182,6 +191,18 @@ impl Pcd8544 {
         }
     }
 
+    /// The panel at native resolution, row-major `PCD_WIDTH × PCD_HEIGHT`,
+    /// true = dark pixel (inverse mode applied as in the framebuffer).
+    pub fn native_pixels(&self) -> Vec<bool> {
+        let inverse = self.display_mode == 5;
+        let mut out = vec![false; PCD_WIDTH * PCD_HEIGHT];
+        for (i, px) in out.iter_mut().enumerate() {
+            let (x, y) = (i % PCD_WIDTH, i / PCD_WIDTH);
+            *px = ((self.vram[(y / 8) * PCD_WIDTH + x] >> (y % 8)) & 1 != 0) ^ inverse;
+        }
+        out
+    }
+
     pub fn dbg_reset_counters(&mut self) {
         self.dbg_cmd_count = 0;
         self.dbg_data_count = 0;
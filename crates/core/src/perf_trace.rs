@@ -0,0 +1,206 @@
+//! Host-time profile of the emulator's own phases.
+//!
+//! Where the [`frame_timeline`](crate::frame_timeline) shows emulated time,
+//! this measures the wall-clock cost of emulating each frame, split into
+//! the core's phases:
+//!
+//! | Phase | Work |
+//! |-------|------|
+//! | `cpu` | Instruction decode and execute |
+//! | `spi flush` | Delivering SPI bytes to the display controller |
+//! | `peripherals` | Timers, ADC, interrupt dispatch |
+//! | `audio` | Closing the frame's sample-accurate audio buffer |
+//!
+//! Timing each instruction would cost more than the instruction, so phase
+//! times are summed over the frame and exported as back-to-back child spans
+//! of the frame's `run_frame` span, in the Trace Event format that
+//! `chrome://tracing` and Perfetto read. Costs nothing while off.
+
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Frames kept by default (10 seconds)
+pub const DEFAULT_CAPACITY: usize = 600;
+
+/// A timed part of `run_frame()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Cpu,
+    SpiFlush,
+    Peripherals,
+    Audio,
+}
+
+pub const PHASES: [Phase; 4] = [Phase::Cpu, Phase::SpiFlush, Phase::Peripherals, Phase::Audio];
+
+impl Phase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Cpu => "cpu",
+            Phase::SpiFlush => "spi flush",
+            Phase::Peripherals => "peripherals",
+            Phase::Audio => "audio",
+        }
+    }
+}
+
+/// Time spent on one frame.
+#[derive(Debug, Clone)]
+pub struct PerfFrame {
+    pub frame: u32,
+    /// Nanoseconds since the trace started
+    pub start_ns: u64,
+    pub dur_ns: u64,
+    /// (nanoseconds, calls) per entry of [`PHASES`]
+    pub phases: [(u64, u32); 4],
+}
+
+/// Phase timer and recorded frames.
+#[derive(Debug, Clone)]
+pub struct PerfTrace {
+    /// Recording
+    pub enabled: bool,
+    /// Frames kept before the oldest are discarded
+    pub capacity: usize,
+    epoch: Instant,
+    frames: VecDeque<PerfFrame>,
+    current: [(u64, u32); 4],
+}
+
+impl PerfTrace {
+    pub fn new() -> Self {
+        PerfTrace {
+            enabled: false,
+            capacity: DEFAULT_CAPACITY,
+            epoch: Instant::now(),
+            frames: VecDeque::new(),
+            current: [(0, 0); 4],
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// Recorded frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &PerfFrame> {
+        self.frames.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Start timing a frame; None while off.
+    pub fn begin_frame(&mut self) -> Option<Instant> {
+        self.current = [(0, 0); 4];
+        self.enabled.then(Instant::now)
+    }
+
+    /// Charge the time since `lap` to `phase` and restart the lap. Does
+    /// nothing when `lap` is None (not recording).
+    #[inline]
+    pub fn lap(&mut self, phase: Phase, lap: &mut Option<Instant>) {
+        if let Some(t) = lap {
+            let now = Instant::now();
+            let slot = &mut self.current[phase as usize];
+            slot.0 += (now - *t).as_nanos() as u64;
+            slot.1 += 1;
+            *t = now;
+        }
+    }
+
+    /// Record the frame started at `start`.
+    pub fn end_frame(&mut self, frame: u32, start: Option<Instant>) {
+        let Some(start) = start else { return };
+        if self.frames.len() >= self.capacity.max(1) {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(PerfFrame {
+            frame,
+            start_ns: (start - self.epoch).as_nanos() as u64,
+            dur_ns: start.elapsed().as_nanos() as u64,
+            phases: self.current,
+        });
+    }
+
+    /// Mean per-frame cost of each phase, one line.
+    pub fn summary(&self) -> String {
+        let n = self.frames.len().max(1) as f64;
+        let us = |ns: u64| ns as f64 / 1000.0 / n;
+        let total: u64 = self.frames.iter().map(|f| f.dur_ns).sum();
+        let mut parts = vec![format!("run_frame {:.1} µs", us(total))];
+        for (i, p) in PHASES.iter().enumerate() {
+            let ns: u64 = self.frames.iter().map(|f| f.phases[i].0).sum();
+            parts.push(format!("{} {:.1} µs", p.name(), us(ns)));
+        }
+        format!("{} frames, mean per frame: {}", self.frames.len(), parts.join(", "))
+    }
+
+    /// Trace Event JSON: a `run_frame` span per frame with its phases laid
+    /// out inside it in order (durations are per-frame totals).
+    pub fn to_chrome_json(&self) -> String {
+        let us = |ns: u64| ns as f64 / 1000.0;
+        let mut events = vec![
+            "{\"name\": \"process_name\", \"ph\": \"M\", \"pid\": 2, \"tid\": 1, \"args\": {\"name\": \"emulator (host time)\"}}".to_string(),
+        ];
+        for f in &self.frames {
+            events.push(format!("{{\"name\": \"run_frame\", \"cat\": \"frame\", \"ph\": \"X\", \"ts\": {:.3}, \"dur\": {:.3}, \"pid\": 2, \"tid\": 1, \"args\": {{\"frame\": {}}}}}",
+                us(f.start_ns), us(f.dur_ns), f.frame));
+            let mut t = f.start_ns;
+            for (i, p) in PHASES.iter().enumerate() {
+                let (ns, calls) = f.phases[i];
+                if calls == 0 {
+                    continue;
+                }
+                events.push(format!("{{\"name\": \"{}\", \"cat\": \"phase\", \"ph\": \"X\", \"ts\": {:.3}, \"dur\": {:.3}, \"pid\": 2, \"tid\": 1, \"args\": {{\"calls\": {}}}}}",
+                    p.name(), us(t), us(ns), calls));
+                t += ns;
+            }
+        }
+        format!("{{\"displayTimeUnit\": \"ns\", \"traceEvents\": [\n  {}\n]}}\n", events.join(",\n  "))
+    }
+}
+
+impl Default for PerfTrace {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phases() {
+        let mut p = PerfTrace::new();
+        assert!(p.begin_frame().is_none());
+        p.start();
+        let start = p.begin_frame();
+        let mut lap = start;
+        p.lap(Phase::Cpu, &mut lap);
+        p.lap(Phase::Cpu, &mut lap);
+        p.lap(Phase::Audio, &mut lap);
+        p.end_frame(7, start);
+        let f = p.frames().next().unwrap();
+        assert_eq!(f.frame, 7);
+        assert_eq!((f.phases[0].1, f.phases[1].1, f.phases[3].1), (2, 0, 1));
+        assert!(f.phases.iter().map(|x| x.0).sum::<u64>() <= f.dur_ns);
+        let json = p.to_chrome_json();
+        assert!(json.contains("\"name\": \"run_frame\""));
+        assert!(json.contains("\"name\": \"audio\""));
+        assert!(!json.contains("spi flush"));
+        assert!(p.summary().starts_with("1 frames"));
+    }
+}
@@ -4,6 +4,12 @@
 //! readings. The ADSC (start conversion) bit in ADCSRA triggers a conversion;
 //! the result is placed in ADCH:ADCL and ADSC is cleared to signal completion.
 //! This allows `analogRead()` and `initRandomSeed()` to function correctly.
+//!
+//! Hosts can fix the reading of individual channels ([`Adc::inputs`], set
+//! with [`Arduboy::set_adc_input`](crate::Arduboy::set_adc_input)), e.g. to
+//! feed a scripted battery voltage or sensor value. The channel comes from
+//! the MUX bits of ADMUX (and MUX5 in ADCSRB on the 32u4), and ADLAR selects
+//! left-adjusted results as on hardware.
 
 use super::INT_ADC;
 
@@ -11,6 +17,8 @@ use super::INT_ADC;
 const ADCL: u16 = 0x78;
 const ADCH: u16 = 0x79;
 const ADCSRA: u16 = 0x7A;
+const ADCSRB: u16 = 0x7B;
+const ADMUX: u16 = 0x7C;
 
 pub struct Adc {
     pub aden: bool,
@@ -19,6 +27,11 @@ pub struct Adc {
     pub adif: bool,
     pub adch: u8,
     pub adcl: u8,
+    /// Last ADMUX / ADCSRB written (channel selection, ADLAR)
+    pub admux: u8,
+    pub adcsrb: u8,
+    /// Fixed 10-bit reading per single-ended channel; None reads noise
+    pub inputs: [Option<u16>; 16],
 }
 
 impl Adc {
@@ -26,24 +39,65 @@ impl Adc {
         Adc {
             aden: false, adsc: false, adie: false, adif: false,
             adch: 0, adcl: 0,
+            admux: 0, adcsrb: 0,
+            inputs: [None; 16],
         }
     }
 
+    /// Reset registers; host-set inputs are kept.
     pub fn reset(&mut self) {
-        *self = Adc::new();
+        *self = Adc { inputs: self.inputs, ..Adc::new() };
+    }
+
+    /// Re-read the channel selection from I/O memory (after a state load).
+    pub fn sync_mux(&mut self, data: &[u8]) {
+        self.admux = data[ADMUX as usize];
+        self.adcsrb = data[ADCSRB as usize];
+    }
+
+    /// Single-ended channel selected by ADMUX / ADCSRB, if any.
+    fn channel(&self) -> Option<usize> {
+        let mux = (self.admux & 0x1F) as usize;
+        let ch = if self.adcsrb & 0x20 != 0 { mux + 8 } else { mux };
+        (mux < 8 && ch < 16).then_some(ch)
+    }
+
+    /// Store a conversion result in ADCH:ADCL.
+    fn convert(&mut self, rng: &mut u32) {
+        match self.channel().and_then(|ch| self.inputs[ch]) {
+            Some(v) => {
+                let v = v.min(0x3FF);
+                if self.admux & 0x20 != 0 {
+                    self.adch = (v >> 2) as u8;
+                    self.adcl = ((v & 3) << 6) as u8;
+                } else {
+                    self.adch = (v >> 8) as u8;
+                    self.adcl = v as u8;
+                }
+            }
+            None => {
+                self.adch = xorshift(rng);
+                self.adcl = xorshift(rng);
+            }
+        }
     }
 
     /// Returns true if addr was handled
     pub fn write(&mut self, addr: u16, value: u8, rng: &mut u32) -> bool {
+        // Selection registers are noted but stored as plain memory
+        if addr == ADMUX {
+            self.admux = value;
+        } else if addr == ADCSRB {
+            self.adcsrb = value;
+        }
         if addr == ADCSRA {
             self.aden = value & 0x80 != 0;
             self.adsc = value & 0x40 != 0;
             self.adie = value & 0x08 != 0;
             self.adif = value & 0x10 != 0;
             if self.aden && self.adsc {
-                // Instant conversion with random result
-                self.adch = xorshift(rng);
-                self.adcl = xorshift(rng);
+                // Instant conversion: host input or random result
+                self.convert(rng);
                 self.adsc = false;
             }
             return true;
@@ -72,8 +126,7 @@ impl Adc {
         if self.aden && self.adie {
             self.adif = true;
             self.adsc = false;
-            self.adch = xorshift(rng);
-            self.adcl = xorshift(rng);
+            self.convert(rng);
         }
     }
 
@@ -4,15 +4,128 @@
 //! EECR (0x3F), EEDR (0x40), EEARL (0x41), EEARH (0x42).
 //! Actual read/write operations are handled in [`Arduboy::read_data`] and
 //! [`Arduboy::write_data`] by intercepting EECR writes.
+//!
+//! [`EepromCtrl`] models the controller timing:
+//!
+//! - Writing EEMPE (with EEPE clear) opens a four-cycle window; EEPE only
+//!   starts a write inside it, so a write without the EEMPE sequence is
+//!   ignored as on hardware.
+//! - A write keeps EEPE set for the programming time of the EEPM mode:
+//!   3.3 ms for erase and write, 1.6 ms for erase or write only (26,368 and
+//!   13,184 cycles of the 8 MHz calibrated RC oscillator, whatever the CPU
+//!   clock). Writes started while EEPE is set are ignored.
+//! - While EERIE is set and no write is in progress the EE_READY interrupt
+//!   is requested; it is level-triggered and has no flag.
+//!
+//! The data is stored when the write starts, so reads during programming
+//! see the new value. The timing is not part of save states: a write in
+//! progress completes when a state is loaded.
+//!
+//! [`Arduboy::read_data`]: crate::Arduboy::read_data
+//! [`Arduboy::write_data`]: crate::Arduboy::write_data
 
-/// EEPROM control registers
-/// EECR = 0x3F, EEDR = 0x40, EEARL = 0x41, EEARH = 0x42
-/// Actual EEPROM data is in Memory::eeprom
-/// Read/write hooks are handled in Arduboy::read_data/write_data
+/// EECR bits
+const EERIE: u8 = 0x08;
+const EEMPE: u8 = 0x04;
+const EEPE: u8 = 0x02;
+/// Calibrated RC oscillator cycles of an erase-and-write and of an erase
+/// or write alone
+const ATOMIC_RC_CYCLES: u64 = 26_368;
+const SPLIT_RC_CYCLES: u64 = 13_184;
+const RC_HZ: u64 = 8_000_000;
 
-pub struct EepromCtrl;
+/// EEPROM control timing; the EECR bits other than EEMPE/EEPE are kept in
+/// data memory.
+pub struct EepromCtrl {
+    /// EE_READY vector of the CPU
+    int_ready: u16,
+    /// Last tick at which EEPE may start a write
+    eempe_until: Option<u64>,
+    /// Tick at which the write in progress completes
+    busy_until: Option<u64>,
+}
 
 impl EepromCtrl {
-    pub fn new() -> Self { EepromCtrl }
-    pub fn reset(&mut self) {}
+    pub fn new(int_ready: u16) -> Self {
+        EepromCtrl { int_ready, eempe_until: None, busy_until: None }
+    }
+
+    pub fn reset(&mut self) {
+        self.eempe_until = None;
+        self.busy_until = None;
+    }
+
+    /// Whether a write is in progress at `tick`.
+    pub fn busy(&self, tick: u64) -> bool {
+        self.busy_until.is_some_and(|t| tick < t)
+    }
+
+    /// EECR as read at `tick`, from the stored bits `eecr`.
+    pub fn read(&self, eecr: u8, tick: u64) -> u8 {
+        let mut v = eecr & !(EEMPE | EEPE);
+        if self.eempe_until.is_some_and(|t| tick <= t) { v |= EEMPE; }
+        if self.busy(tick) { v |= EEPE; }
+        v
+    }
+
+    /// EECR write at `tick`. Returns true when it starts a write, which
+    /// completes after the programming time for the EEPM mode in `value`;
+    /// `clock_hz` and `clock_div` convert times to ticks.
+    pub fn write(&mut self, value: u8, tick: u64, clock_hz: u32, clock_div: u32) -> bool {
+        if self.busy(tick) {
+            return false;
+        }
+        if value & EEPE != 0 {
+            let armed = self.eempe_until.take().is_some_and(|t| tick <= t);
+            if !armed {
+                return false;
+            }
+            let rc_cycles = if (value >> 4) & 3 == 0 { ATOMIC_RC_CYCLES } else { SPLIT_RC_CYCLES };
+            self.busy_until = Some(tick + clock_hz as u64 * rc_cycles / RC_HZ);
+            return true;
+        }
+        if value & EEMPE != 0 {
+            self.eempe_until = Some(tick + 4 * clock_div as u64);
+        }
+        false
+    }
+
+    /// Tick at which a pending write completes, for interrupt scheduling.
+    pub fn next_event(&self, eecr: u8) -> Option<u64> {
+        self.busy_until.filter(|_| eecr & EERIE != 0)
+    }
+
+    /// EE_READY: level-triggered while EERIE is set and no write is in
+    /// progress.
+    pub fn check_interrupt(&self, eecr: u8, tick: u64) -> Option<u16> {
+        (eecr & EERIE != 0 && !self.busy(tick)).then_some(self.int_ready)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_timing() {
+        let mut e = EepromCtrl::new(0x3C);
+        assert!(!e.write(EEPE, 0, 16_000_000, 1), "EEPE without EEMPE is ignored");
+        e.write(EEMPE, 100, 16_000_000, 1);
+        assert_eq!(e.read(0, 104), EEMPE);
+        assert!(!e.write(EEMPE | EEPE, 105, 16_000_000, 1), "window closed");
+        e.write(EEMPE, 200, 16_000_000, 1);
+        assert!(e.write(EEMPE | EEPE, 202, 16_000_000, 1));
+        assert_eq!(e.read(EERIE, 202), EERIE | EEPE);
+        assert_eq!(e.check_interrupt(EERIE, 202), None);
+        assert_eq!(e.next_event(EERIE), Some(202 + 52_736));
+        // Busy: a new sequence is ignored
+        e.write(EEMPE, 300, 16_000_000, 1);
+        assert!(!e.write(EEPE, 301, 16_000_000, 1));
+        assert_eq!(e.read(EERIE, 202 + 52_736), EERIE);
+        assert_eq!(e.check_interrupt(EERIE, 202 + 52_736), Some(0x3C));
+        // Write-only mode takes half as long
+        e.write(EEMPE, 60_000, 8_000_000, 1);
+        assert!(e.write(0x20 | EEPE, 60_001, 8_000_000, 1));
+        assert!(e.busy(60_001 + 13_183) && !e.busy(60_001 + 13_184));
+    }
 }
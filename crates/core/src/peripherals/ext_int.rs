@@ -0,0 +1,203 @@
+//! External interrupts (INTn) and pin change interrupts (PCINTn).
+//!
+//! Pin levels of ports B..F are sampled after every GPIO write and on each
+//! peripheral update. An edge on an INTn pin sets its EIFR flag according
+//! to the sense control bits in EICRA/EICRB (any change, falling or rising);
+//! the low-level mode fires while the pin is held low and sets no flag.
+//! Any change on a pin enabled in PCMSKn sets PCIFR bit n. Flags are
+//! cleared by writing 1 to them or when the interrupt is taken.
+//!
+//! | CPU   | INTn pins                      | PCINT groups                 |
+//! |-------|--------------------------------|------------------------------|
+//! | 32u4  | INT0–3 = PD0–3, INT6 = PE6     | PCINT0 = PB0–7               |
+//! | 328P  | INT0 = PD2, INT1 = PD3         | PCINT0/1/2 = PB, PC0–6, PD   |
+//! | 2560  | INT0–3 = PD0–3, INT4–7 = PE4–7 | PCINT0 = PB, PCINT1 = PE0    |
+//!
+//! Ports J and K (the 2560's other PCINT pins) are not emulated.
+
+use crate::CpuType;
+
+const PCIFR: u16 = 0x3B;
+const EIFR: u16 = 0x3C;
+const EIMSK: u16 = 0x3D;
+const PCICR: u16 = 0x68;
+const EICRA: u16 = 0x69;
+const PCMSK0: u16 = 0x6B;
+
+/// Port indices into [`Arduboy::pin_levels`](crate::Arduboy::pin_levels)
+const PORT_B: usize = 0;
+const PORT_C: usize = 1;
+const PORT_D: usize = 2;
+const PORT_E: usize = 3;
+
+/// INTn line: (n, port, bit, vector)
+type IntLine = (u8, usize, u8, u16);
+/// PCINT group: (n, port, pins wired to the group, vector)
+type PcintGroup = (u8, usize, u8, u16);
+
+const INT_32U4: &[IntLine] = &[
+    (0, PORT_D, 0, super::INT_INT0), (1, PORT_D, 1, super::INT_INT1),
+    (2, PORT_D, 2, super::INT_INT2), (3, PORT_D, 3, super::INT_INT3),
+    (6, PORT_E, 6, super::INT_INT6),
+];
+const PCINT_32U4: &[PcintGroup] = &[(0, PORT_B, 0xFF, super::INT_PCINT0)];
+
+const INT_328P: &[IntLine] = &[
+    (0, PORT_D, 2, super::INT_328P_INT0), (1, PORT_D, 3, super::INT_328P_INT1),
+];
+const PCINT_328P: &[PcintGroup] = &[
+    (0, PORT_B, 0xFF, super::INT_328P_PCINT0),
+    (1, PORT_C, 0x7F, super::INT_328P_PCINT1),
+    (2, PORT_D, 0xFF, super::INT_328P_PCINT2),
+];
+
+const INT_2560: &[IntLine] = &[
+    (0, PORT_D, 0, super::INT_2560_INT0), (1, PORT_D, 1, super::INT_2560_INT0 + 2),
+    (2, PORT_D, 2, super::INT_2560_INT0 + 4), (3, PORT_D, 3, super::INT_2560_INT0 + 6),
+    (4, PORT_E, 4, super::INT_2560_INT0 + 8), (5, PORT_E, 5, super::INT_2560_INT0 + 10),
+    (6, PORT_E, 6, super::INT_2560_INT0 + 12), (7, PORT_E, 7, super::INT_2560_INT0 + 14),
+];
+const PCINT_2560: &[PcintGroup] = &[
+    (0, PORT_B, 0xFF, super::INT_2560_PCINT0),
+    (1, PORT_E, 0x01, super::INT_2560_PCINT1),
+];
+
+pub struct ExtInt {
+    ints: &'static [IntLine],
+    pcints: &'static [PcintGroup],
+    /// Pin levels of ports B..F at the last sample
+    prev: [u8; 5],
+}
+
+impl ExtInt {
+    pub fn new(cpu: CpuType) -> Self {
+        let (ints, pcints) = match cpu {
+            CpuType::Atmega32u4 => (INT_32U4, PCINT_32U4),
+            CpuType::Atmega328p => (INT_328P, PCINT_328P),
+            CpuType::Atmega2560 => (INT_2560, PCINT_2560),
+        };
+        ExtInt { ints, pcints, prev: [0xFF; 5] }
+    }
+
+    /// Take `levels` as the current pin state without raising flags (after
+    /// a reset or state load).
+    pub fn sync(&mut self, levels: [u8; 5]) {
+        self.prev = levels;
+    }
+
+    /// Compare `levels` with the last sample and set EIFR/PCIFR flags for
+    /// the edges found.
+    pub fn sample(&mut self, levels: [u8; 5], data: &mut [u8]) {
+        if levels == self.prev {
+            return;
+        }
+        for &(n, port, bit, _) in self.ints {
+            let was = self.prev[port] >> bit & 1;
+            let now = levels[port] >> bit & 1;
+            if was == now {
+                continue;
+            }
+            let isc = Self::sense(n, data);
+            let hit = match isc {
+                1 => true,
+                2 => now == 0,
+                3 => now == 1,
+                _ => false, // low level: no flag
+            };
+            if hit {
+                data[EIFR as usize] |= 1 << n;
+            }
+        }
+        for &(n, port, pins, _) in self.pcints {
+            let mask = data[(PCMSK0 + n as u16) as usize] & pins;
+            if (levels[port] ^ self.prev[port]) & mask != 0 {
+                data[PCIFR as usize] |= 1 << n;
+            }
+        }
+        self.prev = levels;
+    }
+
+    /// ISCn1:0 for INTn from EICRA (INT0–3) or EICRB (INT4–7).
+    fn sense(n: u8, data: &[u8]) -> u8 {
+        let reg = data[(EICRA + (n / 4) as u16) as usize];
+        (reg >> ((n % 4) * 2)) & 3
+    }
+
+    /// Highest-priority pending interrupt; clears the flag it takes.
+    pub fn check_interrupt(&mut self, data: &mut [u8]) -> Option<u16> {
+        let eimsk = data[EIMSK as usize];
+        for &(n, port, bit, vector) in self.ints {
+            if eimsk & (1 << n) == 0 {
+                continue;
+            }
+            if Self::sense(n, data) == 0 {
+                if self.prev[port] & (1 << bit) == 0 {
+                    return Some(vector);
+                }
+            } else if data[EIFR as usize] & (1 << n) != 0 {
+                data[EIFR as usize] &= !(1 << n);
+                return Some(vector);
+            }
+        }
+        let pending = data[PCICR as usize] & data[PCIFR as usize];
+        for &(n, _, _, vector) in self.pcints {
+            if pending & (1 << n) != 0 {
+                data[PCIFR as usize] &= !(1 << n);
+                return Some(vector);
+            }
+        }
+        None
+    }
+
+    /// Handle writes to the flag registers (write 1 to clear). Returns
+    /// true if addr was handled.
+    pub fn write(&mut self, addr: u16, value: u8, data: &mut [u8]) -> bool {
+        if addr == EIFR || addr == PCIFR {
+            data[addr as usize] &= !value;
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(d: u8) -> [u8; 5] {
+        [0xFF, 0xFF, d, 0xFF, 0xFF]
+    }
+
+    #[test]
+    fn test_falling_edge() {
+        let mut data = vec![0u8; 0x100];
+        let mut ei = ExtInt::new(CpuType::Atmega328p);
+        data[EICRA as usize] = 0b10; // INT0 falling
+        data[EIMSK as usize] = 0x01;
+        ei.sample(levels(0xFF), &mut data);
+        assert_eq!(ei.check_interrupt(&mut data), None);
+        // Falling edge on PD2
+        ei.sample(levels(0xFB), &mut data);
+        assert_eq!(data[EIFR as usize], 0x01);
+        assert_eq!(ei.check_interrupt(&mut data), Some(crate::peripherals::INT_328P_INT0));
+        assert_eq!(data[EIFR as usize], 0x00);
+        ei.sample(levels(0xFF), &mut data);
+        assert_eq!(data[EIFR as usize], 0x00);
+    }
+
+    #[test]
+    fn test_pin_change() {
+        let mut data = vec![0u8; 0x100];
+        let mut ei = ExtInt::new(CpuType::Atmega32u4);
+        data[PCMSK0 as usize] = 0x10; // PB4
+        ei.sample([0xF7, 0xFF, 0xFF, 0xFF, 0xFF], &mut data);
+        assert_eq!(data[PCIFR as usize], 0x00);
+        ei.sample([0xE7, 0xFF, 0xFF, 0xFF, 0xFF], &mut data);
+        assert_eq!(data[PCIFR as usize], 0x01);
+        // Flag pends until PCIE0 is set
+        assert_eq!(ei.check_interrupt(&mut data), None);
+        data[PCICR as usize] = 0x01;
+        assert_eq!(ei.check_interrupt(&mut data), Some(crate::peripherals::INT_PCINT0));
+        assert!(ei.write(PCIFR, 0x01, &mut data));
+    }
+}
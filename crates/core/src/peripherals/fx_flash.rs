@@ -12,8 +12,21 @@
 /// - 0x04: Write Disable
 /// - 0x02: Page Program (addr24 + data)
 /// - 0x20: Sector Erase (4KB)
+///
+/// Bytes outside the loaded image read as `fill` (0xFF, like erased NOR
+/// flash, unless configured otherwise). The first program or erase of each
+/// sector since the image loaded copies its contents into a journal, so
+/// `modified_sectors()` can tell which sectors now differ from the loaded
+/// image. Save states carry only the journaled sectors, and the save area is
+/// written to the `.fxsave` file only when it changed; both stay small
+/// however large the chip is.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
 
 const FLASH_SIZE: usize = 16 * 1024 * 1024; // 16MB
+/// Erase unit (4KB sector)
+pub const SECTOR_SIZE: usize = 4096;
 
 // JEDEC ID for W25Q128JV
 const JEDEC_MFR: u8 = 0xEF;     // Winbond
@@ -44,8 +57,15 @@ pub struct FxFlash {
     pub data: Vec<u8>,
     pub state: FxState,
     pub loaded: bool,
+    /// Value of bytes not covered by the loaded image (set before loading)
+    pub fill: u8,
     write_enabled: bool,
     powered_down: bool,
+    /// Start of the save area at the end of flash (`FLASH_SIZE` if none)
+    pub save_offset: usize,
+    /// Journal: sector → contents before its first program/erase since the
+    /// image loaded
+    original: BTreeMap<usize, Vec<u8>>,
 }
 
 impl FxFlash {
@@ -54,15 +74,79 @@ impl FxFlash {
             data: Vec::new(), // Lazy: only allocate when data is loaded
             state: FxState::Idle,
             loaded: false,
+            fill: 0xFF,
             write_enabled: false,
             powered_down: false,
+            save_offset: FLASH_SIZE,
+            original: BTreeMap::new(),
         }
     }
 
     fn ensure_data(&mut self) {
         if self.data.is_empty() {
-            self.data = vec![0xFF; FLASH_SIZE];
+            self.data = vec![self.fill; FLASH_SIZE];
+        }
+        self.original.clear();
+        self.save_offset = FLASH_SIZE;
+    }
+
+    /// Journal a sector's loaded contents before its first change.
+    fn touch(&mut self, sector: usize) {
+        if !self.original.contains_key(&sector) {
+            let copy = self.sector(sector).to_vec();
+            self.original.insert(sector, copy);
+        }
+    }
+
+    fn range(&self, sector: usize) -> Range<usize> {
+        let start = (sector * SECTOR_SIZE).min(self.data.len());
+        start..(start + SECTOR_SIZE).min(self.data.len())
+    }
+
+    fn sector(&self, sector: usize) -> &[u8] {
+        &self.data[self.range(sector)]
+    }
+
+    /// Sectors whose contents differ from the loaded image, ascending.
+    /// Sectors erased and rewritten with the same data do not count.
+    pub fn modified_sectors(&self) -> Vec<usize> {
+        self.original.iter()
+            .filter(|&(&s, orig)| self.sector(s) != &orig[..])
+            .map(|(&s, _)| s)
+            .collect()
+    }
+
+    /// Sectors programmed or erased since the image loaded (the journal),
+    /// ascending. Unlike `modified_sectors()` this includes sectors
+    /// rewritten with their original data.
+    pub fn dirty_sectors(&self) -> Vec<usize> {
+        self.original.keys().copied().collect()
+    }
+
+    /// True if a sector in the save area differs from the loaded save.
+    pub fn save_modified(&self) -> bool {
+        let first = self.save_offset / SECTOR_SIZE;
+        self.original.range(first..).any(|(&s, orig)| self.sector(s) != &orig[..])
+    }
+
+    /// Save area contents, for writing to the `.fxsave` file.
+    pub fn save_area(&self) -> &[u8] {
+        self.data.get(self.save_offset..).unwrap_or(&[])
+    }
+
+    /// Drop the save area from the journal once it has been written to the
+    /// `.fxsave` file, which now holds it.
+    pub fn commit_save(&mut self) {
+        let first = self.save_offset / SECTOR_SIZE;
+        self.original.retain(|&s, _| s < first);
+    }
+
+    /// True if every byte of the sector reads 0xFF (erased).
+    pub fn is_blank(&self, sector: usize) -> bool {
+        if self.data.is_empty() {
+            return self.fill == 0xFF;
         }
+        self.sector(sector).iter().all(|&b| b == 0xFF)
     }
 
     /// Load flash data from binary data. Data is loaded at start of flash by default.
@@ -79,7 +163,8 @@ impl FxFlash {
     /// Load flash data at a specific offset
     pub fn load_data_at(&mut self, bin: &[u8], offset: usize) {
         self.ensure_data();
-        let end = (offset + bin.len()).min(FLASH_SIZE);
+        let offset = offset.min(FLASH_SIZE);
+        let end = offset.saturating_add(bin.len()).min(FLASH_SIZE);
         let len = end - offset;
         self.data[offset..end].copy_from_slice(&bin[..len]);
         self.loaded = true;
@@ -181,7 +266,7 @@ impl FxFlash {
 
             FxState::Reading { addr } => {
                 let val = if self.data.is_empty() {
-                    0xFF
+                    self.fill
                 } else {
                     let idx = (addr as usize) % self.data.len();
                     self.data[idx]
@@ -229,6 +314,7 @@ impl FxFlash {
             FxState::Programming { addr } => {
                 if self.write_enabled && !self.data.is_empty() {
                     let idx = (addr as usize) % self.data.len();
+                    self.touch(idx / SECTOR_SIZE);
                     // Flash programming can only clear bits (AND operation)
                     self.data[idx] &= mosi;
                     // Stay within same 256-byte page
@@ -245,8 +331,9 @@ impl FxFlash {
                 if new_count >= 3 {
                     if self.write_enabled && !self.data.is_empty() {
                         // Erase 4KB sector
-                        let sector_start = (new_addr as usize) & !(4096 - 1);
-                        let sector_end = (sector_start + 4096).min(self.data.len());
+                        let sector_start = (new_addr as usize % FLASH_SIZE) & !(SECTOR_SIZE - 1);
+                        let sector_end = (sector_start + SECTOR_SIZE).min(self.data.len());
+                        self.touch(sector_start / SECTOR_SIZE);
                         for b in &mut self.data[sector_start..sector_end] {
                             *b = 0xFF;
                         }
@@ -262,21 +349,123 @@ impl FxFlash {
     }
 
     /// Capture state for save state. FX command state is reset to Idle.
+    /// Only journaled sectors are stored; the rest is the loaded image.
     pub fn save_state(&self) -> crate::savestate::FxFlashState {
         crate::savestate::FxFlashState {
-            data: self.data.clone(),
+            journal: self.original.keys()
+                .map(|&s| (s as u32, self.sector(s).to_vec()))
+                .collect(),
             loaded: self.loaded,
             write_enabled: self.write_enabled,
             powered_down: self.powered_down,
         }
     }
 
-    /// Restore state from save state.
+    /// Restore state from save state. Sectors changed since then go back to
+    /// the loaded image before the saved journal is applied, so the same
+    /// image must be loaded.
     pub fn load_state(&mut self, s: crate::savestate::FxFlashState) {
-        self.data = s.data;
+        for (sector, orig) in std::mem::take(&mut self.original) {
+            let range = self.range(sector);
+            self.data[range].copy_from_slice(&orig);
+        }
+        if !self.data.is_empty() {
+            for (sector, contents) in s.journal {
+                let sector = sector as usize;
+                self.touch(sector);
+                let range = self.range(sector);
+                let len = range.len().min(contents.len());
+                self.data[range.start..range.start + len].copy_from_slice(&contents[..len]);
+            }
+        }
         self.loaded = s.loaded;
         self.write_enabled = s.write_enabled;
         self.powered_down = s.powered_down;
         self.state = FxState::Idle; // Reset transient SPI state
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(fx: &mut FxFlash, bytes: &[u8]) -> Vec<u8> {
+        let out = bytes.iter().map(|&b| fx.transfer(b)).collect();
+        fx.deselect();
+        out
+    }
+
+    #[test]
+    fn test_read_above_64k() {
+        let mut fx = FxFlash::new();
+        let image: Vec<u8> = (0..0x30000u32).map(|i| (i >> 16) as u8 ^ i as u8).collect();
+        fx.load_data(&image);
+        // Continuous read across the 64 KB boundary, then from bank 2
+        let out = command(&mut fx, &[0x03, 0x00, 0xFF, 0xFE, 0, 0, 0, 0]);
+        assert_eq!(out[4..], [image[0xFFFE], image[0xFFFF], image[0x10000], image[0x10001]]);
+        let out = command(&mut fx, &[0x0B, 0x02, 0x34, 0x56, 0xFF, 0, 0]);
+        assert_eq!(out[5..], [image[0x23456], image[0x23457]]);
+    }
+
+    #[test]
+    fn test_fill_and_modified_sectors() {
+        let mut fx = FxFlash::new();
+        fx.load_data(&[0x11; 8192]);
+        assert_eq!(command(&mut fx, &[0x03, 0x00, 0x20, 0x00, 0, 0])[4..], [0xFF, 0xFF]);
+        assert!(!fx.is_blank(0) && fx.is_blank(2));
+        assert!(fx.modified_sectors().is_empty());
+
+        // Erase sector 1, program a byte in sector 2
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x20, 0x00, 0x10, 0x00]);
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x02, 0x00, 0x20, 0x05, 0x42]);
+        assert!(fx.is_blank(1));
+        assert_eq!(fx.modified_sectors(), vec![1, 2]);
+
+        // Restoring the original contents clears the sector again
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x20, 0x00, 0x20, 0x00]);
+        assert_eq!(fx.modified_sectors(), vec![1]);
+
+        assert_eq!(fx.dirty_sectors(), vec![1, 2]);
+
+        let mut zero = FxFlash::new();
+        zero.fill = 0x00;
+        zero.load_data(&[0x11; 16]);
+        assert_eq!(command(&mut zero, &[0x03, 0x00, 0x00, 0x10, 0])[4], 0x00);
+        assert!(!zero.is_blank(0));
+    }
+
+    #[test]
+    fn test_journal_state_and_save() {
+        let mut fx = FxFlash::new();
+        fx.load_data(&[0x11; 8192]);
+        fx.save_offset = FLASH_SIZE - SECTOR_SIZE;
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x02, 0x00, 0x10, 0x00, 0x01]);
+        let state = fx.save_state();
+        assert_eq!(state.journal.len(), 1);
+        assert_eq!(state.journal[0].1.len(), SECTOR_SIZE);
+
+        // Later writes are undone by loading the state
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x20, 0x00, 0x00, 0x00]);
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x02, 0xFF, 0xF0, 0x00, 0x42]);
+        assert!(fx.save_modified());
+        fx.load_state(state);
+        assert_eq!(fx.data[0], 0x11);
+        assert_eq!(fx.data[0x1000], 0x01);
+        assert_eq!(fx.data[FLASH_SIZE - SECTOR_SIZE], 0xFF);
+        assert_eq!(fx.dirty_sectors(), vec![1]);
+
+        // Committing the save area drops it from the journal
+        command(&mut fx, &[0x06]);
+        command(&mut fx, &[0x02, 0xFF, 0xF0, 0x00, 0x42]);
+        assert_eq!((fx.save_area().len(), fx.save_area()[0]), (SECTOR_SIZE, 0x42));
+        fx.commit_save();
+        assert!(!fx.save_modified());
+        assert_eq!(fx.dirty_sectors(), vec![1]);
+    }
+}
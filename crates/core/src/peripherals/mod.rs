@@ -1,4 +1,4 @@
-//! ATmega32u4 / ATmega328P peripheral emulation.
+//! ATmega32u4 / ATmega328P / ATmega2560 peripheral emulation.
 //!
 //! Contains hardware peripherals needed to run Arduboy and Gamebuino games:
 //!
@@ -10,6 +10,7 @@
 //! - [`Pll`] — PLL frequency synthesizer (USB clock, fast PWM)
 //! - [`EepromCtrl`] — EEPROM read/write controller (save data)
 //! - [`FxFlash`] — W25Q128 16 MB external SPI flash (Arduboy FX game data)
+//! - [`ExtInt`] — External (INTn) and pin change (PCINTn) interrupts
 
 mod timer8;
 mod timer16;
@@ -19,6 +20,9 @@ mod eeprom;
 mod adc;
 mod pll;
 pub mod fx_flash;
+mod ext_int;
+#[cfg(test)]
+mod timer_conformance;
 
 pub use timer8::{Timer8, Timer8Addrs};
 pub use timer16::{Timer16, Timer16Addrs};
@@ -28,9 +32,17 @@ pub use eeprom::EepromCtrl;
 pub use adc::Adc;
 pub use pll::Pll;
 pub use fx_flash::FxFlash;
+pub use ext_int::ExtInt;
 
 // ─── ATmega32u4 interrupt vector addresses (word addresses) ────────────────
 
+pub const INT_INT0: u16 = 0x0002;
+pub const INT_INT1: u16 = 0x0004;
+pub const INT_INT2: u16 = 0x0006;
+pub const INT_INT3: u16 = 0x0008;
+pub const INT_INT6: u16 = 0x000E;
+pub const INT_PCINT0: u16 = 0x0012;
+
 pub const INT_TIMER0_COMPA: u16 = 0x002A;
 pub const INT_TIMER0_COMPB: u16 = 0x002C;
 pub const INT_TIMER0_OVF: u16 = 0x002E;
@@ -44,6 +56,7 @@ pub const INT_TIMER3_COMPC: u16 = 0x0044;
 pub const INT_TIMER3_OVF: u16 = 0x0046;
 pub const INT_SPI: u16 = 0x0030;
 pub const INT_ADC: u16 = 0x003A;
+pub const INT_EE_READY: u16 = 0x003C;
 
 // Timer4 (32u4 only)
 pub const INT_TIMER4_OVF: u16 = 0x0048;
@@ -53,6 +66,11 @@ pub const INT_TIMER4_COMPD: u16 = 0x004E;
 
 // ─── ATmega328P interrupt vector addresses (word addresses) ────────────────
 
+pub const INT_328P_INT0: u16 = 0x0002;
+pub const INT_328P_INT1: u16 = 0x0004;
+pub const INT_328P_PCINT0: u16 = 0x0006;
+pub const INT_328P_PCINT1: u16 = 0x0008;
+pub const INT_328P_PCINT2: u16 = 0x000A;
 pub const INT_328P_TIMER0_COMPA: u16 = 0x001C;
 pub const INT_328P_TIMER0_COMPB: u16 = 0x001E;
 pub const INT_328P_TIMER0_OVF: u16 = 0x0020;
@@ -67,3 +85,61 @@ pub const INT_328P_USART_RX: u16 = 0x0024;
 pub const INT_328P_USART_UDRE: u16 = 0x0026;
 pub const INT_328P_USART_TX: u16 = 0x0028;
 pub const INT_328P_ADC: u16 = 0x002A;
+pub const INT_328P_EE_READY: u16 = 0x002C;
+
+// ─── ATmega2560 interrupt vector addresses (word addresses) ────────────────
+// Timer0/1/3, SPI, ADC and EE_READY use the same vectors as the ATmega32u4.
+
+/// INT0; INT1–INT7 follow at 2-word steps
+pub const INT_2560_INT0: u16 = 0x0002;
+pub const INT_2560_PCINT0: u16 = 0x0012;
+pub const INT_2560_PCINT1: u16 = 0x0014;
+pub const INT_2560_TIMER2_COMPA: u16 = 0x001A;
+pub const INT_2560_TIMER2_COMPB: u16 = 0x001C;
+pub const INT_2560_TIMER2_OVF: u16 = 0x001E;
+pub const INT_2560_USART0_RX: u16 = 0x0032;
+pub const INT_2560_USART0_UDRE: u16 = 0x0034;
+pub const INT_2560_USART0_TX: u16 = 0x0036;
+pub const INT_2560_TIMER4_COMPA: u16 = 0x0054;
+pub const INT_2560_TIMER4_COMPB: u16 = 0x0056;
+pub const INT_2560_TIMER4_COMPC: u16 = 0x0058;
+pub const INT_2560_TIMER4_OVF: u16 = 0x005A;
+pub const INT_2560_TIMER5_COMPA: u16 = 0x005E;
+pub const INT_2560_TIMER5_COMPB: u16 = 0x0060;
+pub const INT_2560_TIMER5_COMPC: u16 = 0x0062;
+pub const INT_2560_TIMER5_OVF: u16 = 0x0064;
+
+// ─── Interrupt priority ────────────────────────────────────────────────────
+
+/// Peripheral owning a contiguous block of interrupt vectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IrqSource {
+    ExtInt,
+    Timer0,
+    Timer1,
+    Timer2,
+    Timer3,
+    /// 10-bit Timer4 (ATmega32u4)
+    Timer4,
+    /// 16-bit Timer4 (ATmega2560)
+    Timer4Mega,
+    Timer5,
+    Spi,
+    Usart0,
+    Adc,
+    Eeprom,
+}
+
+/// Interrupt sources of `cpu` ordered by their vectors. A lower vector
+/// address has higher priority, and each source's vectors are contiguous,
+/// so the first source with a pending interrupt holds the winning vector.
+pub fn irq_priority(cpu: crate::CpuType) -> &'static [IrqSource] {
+    use IrqSource::*;
+    match cpu {
+        crate::CpuType::Atmega32u4 => &[ExtInt, Timer1, Timer0, Spi, Adc, Eeprom, Timer3, Timer4],
+        crate::CpuType::Atmega328p => &[ExtInt, Timer2, Timer1, Timer0, Spi, Usart0, Adc, Eeprom],
+        crate::CpuType::Atmega2560 => &[
+            ExtInt, Timer2, Timer1, Timer0, Spi, Usart0, Adc, Eeprom, Timer3, Timer4Mega, Timer5,
+        ],
+    }
+}
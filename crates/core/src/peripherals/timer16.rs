@@ -37,6 +37,8 @@ pub struct Timer16 {
     addrs: Timer16Addrs,
     tick: u64,
     prescale: u32,
+    /// System clock prescaler (CLKPR): CPU ticks per timer clock
+    clock_div: u32,
     tcnt: u16,
     top: u16,
     ctc: bool,
@@ -56,6 +58,8 @@ pub struct Timer16 {
     foc_a: bool,
     foc_b: bool,
     foc_c: bool,
+    // OCnA/OCnB/OCnC output latches for non-PWM compare output modes
+    oc_level: [bool; 3],
     // Interrupt flags (counts of pending)
     tov: u32,
     ocf_a: u32,
@@ -84,6 +88,7 @@ impl Timer16 {
             addrs,
             tick: 0,
             prescale: 0,
+            clock_div: 1,
             tcnt: 0,
             top: 0xFFFF,
             ctc: false,
@@ -92,6 +97,7 @@ impl Timer16 {
             com_a: 0, com_b: 0, com_c: 0,
             ocr_a: 0, ocr_b: 0, ocr_c: 0,
             foc_a: false, foc_b: false, foc_c: false,
+            oc_level: [false; 3],
             tov: 0, ocf_a: 0, ocf_b: 0, ocf_c: 0,
             toie: false, ocie_a: false, ocie_b: false, ocie_c: false,
             int_ov, int_compa, int_compb, int_compc,
@@ -104,6 +110,12 @@ impl Timer16 {
         *self = Timer16::new(addrs);
     }
 
+    /// Set the system clock division (CLKPR) the timer clock runs behind.
+    pub fn set_clock_div(&mut self, div: u32) {
+        self.clock_div = div;
+        self.update_state();
+    }
+
     fn update_state(&mut self) {
         let wgm = ((self.wgm[3] as u8) << 3) | ((self.wgm[2] as u8) << 2)
             | ((self.wgm[1] as u8) << 1) | (self.wgm[0] as u8);
@@ -134,7 +146,7 @@ impl Timer16 {
             4 => 256,
             5 => 1024,
             _ => 1,
-        };
+        } * self.clock_div;
     }
 
     pub fn write(&mut self, addr: u16, value: u8, _old: u8, data: &mut [u8]) -> bool {
@@ -253,6 +265,7 @@ impl Timer16 {
         self.tick += (interval as u64) * (self.prescale as u64);
 
         let old_tcnt = self.tcnt;
+        self.latch_compare_outputs(old_tcnt as u32, old_tcnt as u32 + interval);
 
         if self.ctc && self.ocr_a > 0 {
             // CTC mode: counter resets to 0 at OCR_A (unconditional — not gated on ocie_a)
@@ -305,6 +318,28 @@ impl Timer16 {
         }
     }
 
+    /// Tick at which the next enabled interrupt flag can be raised (compare
+    /// match, CTC clear or overflow); None while stopped or with no
+    /// interrupt enabled. May be early, never late.
+    pub fn next_event(&self) -> Option<u64> {
+        if self.prescale == 0 || !(self.ocie_a || self.ocie_b || self.ocie_c || self.toie) { return None; }
+        let cnt = self.tcnt as u32;
+        let wrap = if self.ctc && self.ocr_a > 0 && cnt <= self.ocr_a as u32 {
+            self.ocr_a as u32
+        } else if cnt <= self.top as u32 {
+            self.top as u32 + 1
+        } else {
+            0x10000
+        };
+        let mut counts = wrap.saturating_sub(cnt).max(1);
+        for (on, ocr) in [(self.ocie_a, self.ocr_a), (self.ocie_b, self.ocr_b), (self.ocie_c, self.ocr_c)] {
+            if on && ocr as u32 > cnt {
+                counts = counts.min(ocr as u32 - cnt);
+            }
+        }
+        Some(self.tick + counts as u64 * self.prescale as u64)
+    }
+
     pub fn update(&mut self, tick: u64, data: &mut [u8]) {
         self.do_update(tick);
         data[self.addrs.tcnth as usize] = (self.tcnt >> 8) as u8;
@@ -332,6 +367,99 @@ impl Timer16 {
         None
     }
 
+    fn wgm_mode(&self) -> u8 {
+        ((self.wgm[3] as u8) << 3) | ((self.wgm[2] as u8) << 2)
+            | ((self.wgm[1] as u8) << 1) | (self.wgm[0] as u8)
+    }
+
+    fn channel(&self, ch: usize) -> Option<(u8, u16)> {
+        match ch {
+            0 => Some((self.com_a, self.ocr_a)),
+            1 => Some((self.com_b, self.ocr_b)),
+            2 => Some((self.com_c, self.ocr_c)),
+            _ => None,
+        }
+    }
+
+    /// Apply compare-match events in `(old_cnt, new_cnt]` to the OCnx latches.
+    ///
+    /// Only modes whose output is event-driven use the latch: Normal/CTC for
+    /// every channel, and toggle-on-match (COM=1) for OCnA in modes 9/11/14/15.
+    fn latch_compare_outputs(&mut self, old_cnt: u32, new_cnt: u32) {
+        let wgm = self.wgm_mode();
+        let top = if self.ctc {
+            if self.ocr_a == 0 || old_cnt > self.ocr_a as u32 { return; }
+            self.ocr_a as u32
+        } else {
+            self.top as u32
+        };
+        for ch in 0..3 {
+            let Some((com, ocr)) = self.channel(ch) else { continue };
+            let ocr = ocr as u32;
+            if com == 0 || ocr > top {
+                continue;
+            }
+            let latched = match wgm {
+                0 | 4 | 12 => true,
+                9 | 11 | 14 | 15 => ch == 0 && com == 1,
+                _ => false,
+            };
+            if !latched {
+                continue;
+            }
+            let matches = super::timer8::compare_hits(new_cnt, ocr, top + 1)
+                - super::timer8::compare_hits(old_cnt, ocr, top + 1);
+            if matches == 0 {
+                continue;
+            }
+            match com {
+                1 => self.oc_level[ch] ^= matches & 1 != 0,
+                2 => self.oc_level[ch] = false,
+                _ => self.oc_level[ch] = true,
+            }
+        }
+    }
+
+    /// Whether any compare output is connected (COMnx != 0), i.e. whether
+    /// [`oc_output`](Self::oc_output) can return `Some` for some channel.
+    pub fn oc_connected(&self) -> bool {
+        self.com_a | self.com_b | self.com_c != 0
+    }
+
+    /// Current level of output-compare pin OCnA/OCnB/OCnC (`ch` = 0/1/2).
+    ///
+    /// Returns `None` when the compare output is disconnected, so the pin
+    /// follows PORTx as usual.
+    pub fn oc_output(&self, ch: usize) -> Option<bool> {
+        let (com, ocr) = self.channel(ch)?;
+        if com == 0 {
+            return None;
+        }
+        let wgm = self.wgm_mode();
+        // The counter wraps at 0xFFFF internally; the fixed 8/9/10-bit TOPs
+        // are 2^n-1 so masking recovers the hardware count.
+        let cnt = self.tcnt & self.top;
+        let toggles_a = ch == 0 && com == 1;
+        match wgm {
+            0 | 4 | 12 => Some(self.oc_level[ch]),
+            // Fast PWM: set at BOTTOM, cleared on match (COM=2)
+            5 | 6 | 7 | 14 | 15 => match com {
+                1 if toggles_a && wgm >= 14 => Some(self.oc_level[0]),
+                1 => None,
+                2 => Some(cnt <= ocr),
+                _ => Some(cnt > ocr),
+            },
+            // Phase (and frequency) correct PWM: cleared on up-count match
+            1 | 2 | 3 | 8 | 9 | 10 | 11 => match com {
+                1 if toggles_a && (wgm == 9 || wgm == 11) => Some(self.oc_level[0]),
+                1 => None,
+                2 => Some(ocr >= self.top || cnt < ocr),
+                _ => Some(ocr < self.top && cnt >= ocr),
+            },
+            _ => None,
+        }
+    }
+
     /// Get tone frequency in Hz from CTC toggle mode.
     /// Returns 0.0 if timer is not generating a tone.
     /// Arduboy: Timer3 OC3A=PC6, Timer1 OC1A/OC1C for speaker pins.
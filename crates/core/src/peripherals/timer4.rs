@@ -34,6 +34,8 @@ pub struct Timer4 {
     cs: u8,
     /// Prescaler value derived from CS bits
     prescale: u32,
+    /// System clock prescaler (CLKPR): CPU ticks per timer clock
+    clock_div: u32,
     /// Last update tick
     tick: u64,
     /// WGM mode (WGM41:WGM40 from TCCR4D)
@@ -61,7 +63,7 @@ impl Timer4 {
             ocr_a: 0, ocr_b: 0, ocr_c: 0xFF, ocr_d: 0,
             tccr_a: 0, tccr_b: 0, tccr_c: 0, tccr_d: 0, tccr_e: 0,
             dt4: 0, timsk: 0,
-            cs: 0, prescale: 0, tick: 0,
+            cs: 0, prescale: 0, clock_div: 1, tick: 0,
             wgm: 0,
             tov: 0, ocf_a: 0, ocf_b: 0, ocf_d: 0,
         }
@@ -71,6 +73,12 @@ impl Timer4 {
         *self = Self::new();
     }
 
+    /// Set the system clock division (CLKPR) the timer clock runs behind.
+    pub fn set_clock_div(&mut self, div: u32) {
+        self.clock_div = div;
+        self.prescale = Self::decode_prescale(self.cs) * div;
+    }
+
     /// Decode prescaler from CS43:CS40 bits.
     /// Timer4 has extended prescaler: /1, /2, /4, /8, ..., /16384
     fn decode_prescale(cs: u8) -> u32 {
@@ -122,6 +130,22 @@ impl Timer4 {
         }
     }
 
+    /// Tick at which the next enabled interrupt flag can be raised (compare
+    /// match or TOP); None while stopped or with no interrupt enabled. May
+    /// be early, never late.
+    pub fn next_event(&self) -> Option<u64> {
+        if self.prescale == 0 || self.timsk & 0xE4 == 0 { return None; }
+        let cnt = self.tcnt as u32;
+        let top = self.get_top() as u32;
+        let mut counts = if top > cnt { top - cnt } else { 1 };
+        for (bit, ocr) in [(6, self.ocr_a), (5, self.ocr_b), (7, self.ocr_d)] {
+            if self.timsk & (1 << bit) != 0 && ocr as u32 > cnt {
+                counts = counts.min(ocr as u32 - cnt);
+            }
+        }
+        Some(self.tick + counts as u64 * self.prescale as u64)
+    }
+
     /// Get TOP value based on WGM mode
     fn get_top(&self) -> u16 {
         match self.wgm & 0x03 {
@@ -197,7 +221,7 @@ impl Timer4 {
             0xC1 => { // TCCR4B
                 self.tccr_b = value;
                 self.cs = value & 0x0F;
-                self.prescale = Self::decode_prescale(self.cs);
+                self.prescale = Self::decode_prescale(self.cs) * self.clock_div;
                 if self.prescale > 0 && self.tick == 0 { self.tick = 1; }
                 true
             }
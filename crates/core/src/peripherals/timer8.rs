@@ -30,6 +30,8 @@ pub struct Timer8 {
     addrs: Timer8Addrs,
     tick: u64,
     prescale: u32,
+    /// System clock prescaler (CLKPR): CPU ticks per timer clock
+    clock_div: u32,
     cs: u8,
     mode: u8,
     // Waveform generation mode bits
@@ -42,6 +44,8 @@ pub struct Timer8 {
     ocr0a: u8,
     ocr0b: u8,
     tcnt_shadow: u8,
+    // OC0A/OC0B output latches for non-PWM compare output modes
+    oc_level: [bool; 2],
     // Interrupt flags
     tov0: u32,
     ocf0a: u32,
@@ -61,12 +65,14 @@ impl Timer8 {
             addrs,
             tick: 0,
             prescale: 0,
+            clock_div: 1,
             cs: 0,
             mode: 0,
             wgm00: false, wgm01: false, wgm02: false,
             com_a: 0, com_b: 0,
             ocr0a: 0, ocr0b: 0,
             tcnt_shadow: 0,
+            oc_level: [false; 2],
             tov0: 0, ocf0a: 0, ocf0b: 0,
             toie0: false, ocie0a: false, ocie0b: false,
             dbg_ovf_count: 0, dbg_int_fire_count: 0,
@@ -77,6 +83,12 @@ impl Timer8 {
         *self = Timer8::new(self.addrs.clone());
     }
 
+    /// Set the system clock division (CLKPR) the timer clock runs behind.
+    pub fn set_clock_div(&mut self, div: u32) {
+        self.clock_div = div;
+        self.update_prescale();
+    }
+
     fn update_prescale(&mut self) {
         self.prescale = if self.addrs.is_timer2 {
             // Timer2 (ATmega328P async timer) — different prescaler table
@@ -102,7 +114,7 @@ impl Timer8 {
                 5 => 1024,
                 _ => 0,  // external clock (not emulated)
             }
-        };
+        } * self.clock_div;
         let wgm = ((self.wgm02 as u8) << 2) | ((self.wgm01 as u8) << 1) | (self.wgm00 as u8);
         self.mode = wgm;
     }
@@ -158,7 +170,7 @@ impl Timer8 {
     }
 
     /// Handle reads from timer registers. Returns Some(value) if handled.
-    pub fn read(&mut self, addr: u16, tick: u64, data: &[u8]) -> Option<u8> {
+    pub fn read(&mut self, addr: u16, tick: u64, _data: &[u8]) -> Option<u8> {
         if addr == self.addrs.tifr {
             return Some(
                 ((self.tov0.min(1)) as u8)
@@ -167,24 +179,40 @@ impl Timer8 {
             );
         }
         if addr == self.addrs.tcnt {
-            self.do_update(tick, data);
-            return Some(self.tcnt_shadow);
+            return Some(self.count_at(tick));
         }
         None
     }
 
-    fn do_update(&mut self, tick: u64, _data: &[u8]) {
-        if self.prescale == 0 { return; }
+    /// Counter value at `tick` without advancing the timer (flags are only
+    /// raised by [`update`](Self::update)).
+    fn count_at(&self, tick: u64) -> u8 {
+        if self.prescale == 0 { return self.tcnt_shadow; }
         let ticks_since = tick.wrapping_sub(self.tick);
-        let interval = (ticks_since / self.prescale as u64) as u32;
-        if interval == 0 { return; }
+        let interval = ticks_since / self.prescale as u64;
+        let top = self.top() as u64;
+        ((self.tcnt_shadow as u64 + interval) % (top + 1)) as u8
+    }
 
-        let top = if self.mode == 2 || self.mode == 7 {
-            if self.ocr0a > 0 { self.ocr0a as u32 } else { 0xFF }
-        } else { 0xFF };
+    /// TOP: OCR0A in CTC and Fast PWM mode 7, else 0xFF.
+    fn top(&self) -> u32 {
+        if (self.mode == 2 || self.mode == 7) && self.ocr0a > 0 { self.ocr0a as u32 } else { 0xFF }
+    }
 
-        let new_cnt = self.tcnt_shadow as u32 + interval;
-        self.tcnt_shadow = if top > 0 { (new_cnt % (top + 1)) as u8 } else { new_cnt as u8 };
+    /// Tick at which the next enabled interrupt flag can be raised (compare
+    /// match or wrap at TOP); None while stopped or with no interrupt
+    /// enabled. May be early, never late.
+    pub fn next_event(&self) -> Option<u64> {
+        if self.prescale == 0 || !(self.ocie0a || self.ocie0b || self.toie0) { return None; }
+        let cnt = self.tcnt_shadow as u32;
+        let top = self.top();
+        let mut counts = (top + 1).saturating_sub(cnt).max(1);
+        for (on, ocr) in [(self.ocie0a, self.ocr0a as u32), (self.ocie0b, self.ocr0b as u32)] {
+            if on && ocr > cnt {
+                counts = counts.min(ocr - cnt);
+            }
+        }
+        Some(self.tick + counts as u64 * self.prescale as u64)
     }
 
     /// Update timer state
@@ -198,13 +226,12 @@ impl Timer8 {
         let old_cnt = self.tcnt_shadow as u32;
         let new_cnt = old_cnt + interval;
 
-        // WGM mode determines TOP value
-        let top = if self.mode == 2 || self.mode == 7 {
-            // CTC mode (WGM=010) or Fast PWM with OCRA top (WGM=111)
-            if self.ocr0a > 0 { self.ocr0a as u32 } else { 0xFF }
-        } else {
-            0xFF // Normal mode
-        };
+        // WGM mode determines TOP value: OCR0A in CTC (WGM=010) and Fast
+        // PWM with OCRA top (WGM=111)
+        let top = self.top();
+
+        // Drive the OC0x output latches before the counter wraps
+        self.latch_compare_outputs(old_cnt, new_cnt, top);
 
         // Count overflows/compare matches
         if top > 0 {
@@ -214,28 +241,21 @@ impl Timer8 {
 
             if overflows > 0 {
                 self.dbg_ovf_count += overflows;
-                if self.mode == 2 || self.mode == 7 {
-                    // CTC: compare match fires, TOV fires at MAX
-                    self.ocf0a = self.ocf0a.saturating_add(overflows);
-                }
                 // TOV fires at MAX (0xFF overflow) in all modes except CTC-only
                 // In modes 0, 3 (Normal, Fast PWM with TOP=MAX), TOV fires on overflow
                 if self.mode != 2 {
                     self.tov0 = self.tov0.saturating_add(overflows);
                 }
-                // Check if we crossed compare match B
-                if self.ocr0b > 0 && remainder >= self.ocr0b as u32 && old_cnt < self.ocr0b as u32 {
-                    self.ocf0b = self.ocf0b.saturating_add(1);
-                }
-            } else {
-                // No overflow but may cross compare match
-                if old_cnt < self.ocr0a as u32 && new_cnt >= self.ocr0a as u32 && self.ocr0a > 0 {
-                    self.ocf0a = self.ocf0a.saturating_add(1);
-                }
-                if old_cnt < self.ocr0b as u32 && new_cnt >= self.ocr0b as u32 && self.ocr0b > 0 {
-                    self.ocf0b = self.ocf0b.saturating_add(1);
-                }
             }
+            // Compare matches in (old_cnt, new_cnt]: once per period, when
+            // the counter reaches OCR0x (in CTC that is TOP, before the clear)
+            let hits = |ocr: u8| {
+                let ocr = ocr as u32;
+                if ocr == 0 || ocr > top { return 0; }
+                compare_hits(new_cnt, ocr, top + 1) - compare_hits(old_cnt, ocr, top + 1)
+            };
+            self.ocf0a = self.ocf0a.saturating_add(hits(self.ocr0a));
+            self.ocf0b = self.ocf0b.saturating_add(hits(self.ocr0b));
 
             self.tcnt_shadow = remainder as u8;
         } else {
@@ -246,6 +266,83 @@ impl Timer8 {
         self.tick += (interval as u64) * (self.prescale as u64);
     }
 
+    /// Apply compare-match events in `(old_cnt, new_cnt]` to the OC0x latches.
+    ///
+    /// Only modes whose output is event-driven use the latch: Normal/CTC for
+    /// both channels, and toggle-on-match (COM=1) for OC0A in modes 5/7.
+    fn latch_compare_outputs(&mut self, old_cnt: u32, new_cnt: u32, top: u32) {
+        let period = top + 1;
+        for ch in 0..2 {
+            let (com, ocr) = if ch == 0 {
+                (self.com_a, self.ocr0a as u32)
+            } else {
+                (self.com_b, self.ocr0b as u32)
+            };
+            if com == 0 || ocr > top {
+                continue;
+            }
+            let latched = match self.mode {
+                0 | 2 => true,
+                5 | 7 => ch == 0 && com == 1,
+                _ => false,
+            };
+            if !latched {
+                continue;
+            }
+            let matches = compare_hits(new_cnt, ocr, period) - compare_hits(old_cnt, ocr, period);
+            if matches == 0 {
+                continue;
+            }
+            match com {
+                1 => self.oc_level[ch] ^= matches & 1 != 0,
+                2 => self.oc_level[ch] = false,
+                _ => self.oc_level[ch] = true,
+            }
+        }
+    }
+
+    /// Whether any compare output is connected (COMnx != 0), i.e. whether
+    /// [`oc_output`](Self::oc_output) can return `Some` for some channel.
+    pub fn oc_connected(&self) -> bool {
+        self.com_a | self.com_b != 0
+    }
+
+    /// Current level of output-compare pin OC0A (`ch` = 0) or OC0B (`ch` = 1).
+    ///
+    /// Returns `None` when the compare output is disconnected (COM = 0, or a
+    /// COM/WGM combination the datasheet marks as "normal port operation"),
+    /// so the pin follows PORTx as usual.
+    pub fn oc_output(&self, ch: usize) -> Option<bool> {
+        let (com, ocr) = match ch {
+            0 => (self.com_a, self.ocr0a),
+            1 => (self.com_b, self.ocr0b),
+            _ => return None,
+        };
+        if com == 0 {
+            return None;
+        }
+        let cnt = self.tcnt_shadow;
+        match self.mode {
+            // Normal / CTC: output latch toggled, cleared or set on match
+            0 | 2 => Some(self.oc_level[ch]),
+            // Fast PWM (TOP=0xFF / OCR0A): set at BOTTOM, cleared on match
+            3 | 7 => match com {
+                1 if ch == 0 && self.mode == 7 => Some(self.oc_level[0]),
+                1 => None,
+                2 => Some(cnt <= ocr),
+                _ => Some(cnt > ocr),
+            },
+            // Phase correct (TOP=0xFF / OCR0A): cleared on up-count match
+            1 | 5 => match com {
+                1 if ch == 0 && self.mode == 5 => Some(self.oc_level[0]),
+                1 => None,
+                2 => Some(ocr == 0xFF || cnt < ocr),
+                _ => Some(ocr != 0xFF && cnt >= ocr),
+            },
+            _ => None,
+        }
+    }
+
     /// Check for pending interrupts. Returns vector address if interrupt fires.
     ///
     /// Priority order matches ATmega328P datasheet: COMPA > COMPB > OVF.
@@ -334,3 +431,10 @@ impl Timer8 {
         self.toie0 = s.toie0; self.ocie0a = s.ocie0a; self.ocie0b = s.ocie0b;
     }
 }
+
+/// Number of counter values `k` in `0..=n` with `k % period == ocr`.
+///
+/// Used to count compare matches between two unwrapped counter positions.
+pub(crate) fn compare_hits(n: u32, ocr: u32, period: u32) -> u32 {
+    if period == 0 || n < ocr { 0 } else { (n - ocr) / period + 1 }
+}
@@ -0,0 +1,389 @@
+//! Native plugins: third-party tool windows without forking the emulator.
+//!
+//! A plugin is a dynamic library (`.so`, `.dylib`, `.dll`) exporting one C
+//! function:
+//!
+//! ```c
+//! const ArduboyPlugin *arduboy_plugin_init(uint32_t abi_version);
+//! ```
+//!
+//! It returns its [`PluginApi`] table, or null if it cannot work with
+//! `abi_version` ([`PLUGIN_ABI`]). After every frame the host calls
+//! `on_frame` with a [`FrameInfo`] and a [`HostApi`] through which the
+//! plugin reads emulator state and contributes overlay draw commands:
+//!
+//! | `HostApi` function | Does |
+//! |--------------------|------|
+//! | `read_data` | Data-space byte: registers, I/O (without read side effects), SRAM |
+//! | `read_flash` | Program memory byte |
+//! | `read_eeprom` | EEPROM byte |
+//! | `peripherals_json` | [`Arduboy::peripherals`] as JSON, copied into a buffer |
+//! | `fill_rect` | Overlay rectangle in screen pixels, `0xAARRGGBB` |
+//! | `set_status` | One line of text the frontend shows (the window title in the desktop GUI) |
+//! | `log` | Message on stderr, prefixed with the plugin name |
+//!
+//! Plugins only read: they cannot change memory or registers, so a
+//! speedrun timer or map viewer cannot desync a recording. All structs are
+//! `#[repr(C)]`; new fields are only ever added at the end (`HostApi::size`
+//! tells the plugin how many there are) and [`PLUGIN_ABI`] changes if an
+//! existing one does. Pointers passed to a callback are valid only during
+//! that call. [`Plugin::from_entry`] takes the same entry point from a
+//! plugin linked into the host instead of loaded from a file.
+
+use std::ffi::{c_char, c_void, CStr};
+use std::path::Path;
+
+use crate::error::PluginError;
+use crate::{Arduboy, Error};
+
+/// Version of the plugin interface described here.
+pub const PLUGIN_ABI: u32 = 1;
+
+/// Name of the entry point a plugin library exports.
+pub const ENTRY_SYMBOL: &str = "arduboy_plugin_init";
+
+/// `arduboy_plugin_init`
+pub type InitFn = unsafe extern "C" fn(abi_version: u32) -> *const PluginApi;
+
+/// Table a plugin returns from `arduboy_plugin_init`. It must stay valid
+/// until `destroy` is called.
+#[repr(C)]
+pub struct PluginApi {
+    /// [`PLUGIN_ABI`] the plugin was built against
+    pub abi_version: u32,
+    /// Display name, NUL-terminated UTF-8
+    pub name: *const c_char,
+    /// Passed back to every callback
+    pub state: *mut c_void,
+    /// Called after each emulated frame
+    pub on_frame: Option<unsafe extern "C" fn(state: *mut c_void, host: *const HostApi, frame: *const FrameInfo)>,
+    /// Called once before the library is unloaded
+    pub destroy: Option<unsafe extern "C" fn(state: *mut c_void)>,
+}
+
+/// The frame that just ran.
+#[repr(C)]
+pub struct FrameInfo {
+    /// Frames run since the program was loaded
+    pub frame: u64,
+    /// CPU clock in 16 MHz ticks
+    pub tick: u64,
+    /// Program counter (flash byte address)
+    pub pc: u32,
+    pub sp: u16,
+    pub sreg: u8,
+    /// Held buttons, [`input_overlay::button_bit`](crate::input_overlay::button_bit) layout
+    pub buttons: u8,
+    /// Visible screen, `screen_width × screen_height` pixels of `0xRRGGBB`
+    pub screen: *const u32,
+    pub screen_width: u32,
+    pub screen_height: u32,
+}
+
+/// Functions the host offers during a callback; `ctx` is the first
+/// argument of each.
+#[repr(C)]
+pub struct HostApi {
+    /// Size of this struct in bytes
+    pub size: u32,
+    pub ctx: *mut c_void,
+    pub read_data: unsafe extern "C" fn(ctx: *mut c_void, addr: u16) -> u8,
+    pub read_flash: unsafe extern "C" fn(ctx: *mut c_void, addr: u32) -> u8,
+    pub read_eeprom: unsafe extern "C" fn(ctx: *mut c_void, addr: u16) -> u8,
+    /// Copies up to `len` bytes of JSON into `buf` (not NUL-terminated) and
+    /// returns the full length, so a plugin can retry with a larger buffer.
+    pub peripherals_json: unsafe extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> usize,
+    pub fill_rect: unsafe extern "C" fn(ctx: *mut c_void, x: i32, y: i32, w: i32, h: i32, argb: u32),
+    /// NUL-terminated UTF-8; an empty string clears the status
+    pub set_status: unsafe extern "C" fn(ctx: *mut c_void, text: *const c_char),
+    pub log: unsafe extern "C" fn(ctx: *mut c_void, text: *const c_char),
+}
+
+/// Filled rectangle in screen pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverlayRect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    /// `0xAARRGGBB`; alpha 0 is treated as opaque
+    pub argb: u32,
+}
+
+/// What plugins drew for the last frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overlay {
+    pub rects: Vec<OverlayRect>,
+    /// Latest status line of each plugin that set one, as `name: text`
+    pub status: Vec<String>,
+}
+
+impl Overlay {
+    /// Blend the rectangles into a `buf_w`×`buf_h` buffer showing the
+    /// screen at `scale` pixels per screen pixel. Clipped to the buffer.
+    pub fn render(&self, buf: &mut [u32], buf_w: usize, buf_h: usize, scale: usize) {
+        let scale = scale.max(1) as i64;
+        for r in &self.rects {
+            let alpha = match r.argb >> 24 { 0 => 255, a => a };
+            let clip = |v: i32, max: usize| (v as i64 * scale).clamp(0, max as i64) as usize;
+            let (x0, x1) = (clip(r.x, buf_w), clip(r.x.saturating_add(r.w), buf_w));
+            let (y0, y1) = (clip(r.y, buf_h), clip(r.y.saturating_add(r.h), buf_h));
+            for y in y0..y1 {
+                for px in &mut buf[y * buf_w + x0..y * buf_w + x1] {
+                    *px = blend(*px, r.argb, alpha);
+                }
+            }
+        }
+    }
+}
+
+fn blend(dst: u32, src: u32, alpha: u32) -> u32 {
+    let mix = |shift: u32| {
+        let (d, s) = ((dst >> shift) & 0xFF, (src >> shift) & 0xFF);
+        ((s * alpha + d * (255 - alpha)) / 255) << shift
+    };
+    mix(16) | mix(8) | mix(0)
+}
+
+/// A loaded plugin. Dropping it calls `destroy` and unloads the library.
+pub struct Plugin {
+    api: *const PluginApi,
+    name: String,
+    status: String,
+    // Dropped after `destroy` has run
+    _lib: Option<libloading::Library>,
+}
+
+impl Plugin {
+    /// Load a plugin library.
+    pub fn load(path: &Path) -> Result<Plugin, Error> {
+        // Safety: loading runs the library's initializers; plugins are
+        // trusted code the user asked for
+        let lib = unsafe { libloading::Library::new(path) }
+            .map_err(|e| PluginError::Open(e.to_string()))?;
+        let init: InitFn = *unsafe { lib.get::<InitFn>(ENTRY_SYMBOL.as_bytes()) }
+            .map_err(|_| PluginError::NoEntry)?;
+        let mut plugin = Self::from_entry(init)?;
+        plugin._lib = Some(lib);
+        Ok(plugin)
+    }
+
+    /// Start a plugin linked into the host from its entry point.
+    pub fn from_entry(init: InitFn) -> Result<Plugin, Error> {
+        let api = unsafe { init(PLUGIN_ABI) };
+        if api.is_null() {
+            return Err(PluginError::Rejected { abi: PLUGIN_ABI }.into());
+        }
+        let table = unsafe { &*api };
+        if table.abi_version != PLUGIN_ABI {
+            return Err(PluginError::Version { found: table.abi_version, expected: PLUGIN_ABI }.into());
+        }
+        let name = if table.name.is_null() {
+            "plugin".to_string()
+        } else {
+            unsafe { CStr::from_ptr(table.name) }.to_string_lossy().into_owned()
+        };
+        Ok(Plugin { api, name, status: String::new(), _lib: None })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Status line last set by the plugin.
+    pub fn status(&self) -> &str {
+        &self.status
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        let table = unsafe { &*self.api };
+        if let Some(destroy) = table.destroy {
+            unsafe { destroy(table.state) };
+        }
+    }
+}
+
+/// Loaded plugins and what they drew.
+#[derive(Default)]
+pub struct Plugins {
+    list: Vec<Plugin>,
+    pub overlay: Overlay,
+}
+
+impl Plugins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, plugin: Plugin) {
+        self.list.push(plugin);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Plugin> {
+        self.list.iter()
+    }
+
+    /// Report the frame that just ran to every plugin and collect their
+    /// overlay.
+    pub fn on_frame(&mut self, arduboy: &mut Arduboy) {
+        self.overlay.rects.clear();
+        if self.list.is_empty() {
+            return;
+        }
+        let (screen, w, h) = arduboy.screen_output();
+        let info = FrameInfo {
+            frame: arduboy.frame_count as u64,
+            tick: arduboy.cpu.tick,
+            pc: arduboy.cpu.pc * 2,
+            sp: arduboy.cpu.sp,
+            sreg: arduboy.cpu.sreg,
+            buttons: arduboy.input_history.current(),
+            screen: screen.as_ptr(),
+            screen_width: w as u32,
+            screen_height: h as u32,
+        };
+        for plugin in &mut self.list {
+            let table = unsafe { &*plugin.api };
+            let Some(on_frame) = table.on_frame else { continue };
+            let mut ctx = HostCtx { arduboy, overlay: &mut self.overlay, plugin_name: &plugin.name, status: &mut plugin.status };
+            let host = HostApi {
+                size: std::mem::size_of::<HostApi>() as u32,
+                ctx: &mut ctx as *mut HostCtx as *mut c_void,
+                read_data: host_read_data,
+                read_flash: host_read_flash,
+                read_eeprom: host_read_eeprom,
+                peripherals_json: host_peripherals_json,
+                fill_rect: host_fill_rect,
+                set_status: host_set_status,
+                log: host_log,
+            };
+            unsafe { on_frame(table.state, &host, &info) };
+        }
+        self.overlay.status = self.list.iter()
+            .filter(|p| !p.status.is_empty())
+            .map(|p| format!("{}: {}", p.name, p.status))
+            .collect();
+    }
+}
+
+/// What `HostApi::ctx` points to during a callback.
+struct HostCtx<'a> {
+    arduboy: &'a mut Arduboy,
+    overlay: &'a mut Overlay,
+    plugin_name: &'a str,
+    status: &'a mut String,
+}
+
+unsafe fn host<'a>(ctx: *mut c_void) -> &'a mut HostCtx<'a> {
+    &mut *(ctx as *mut HostCtx)
+}
+
+unsafe fn text(ptr: *const c_char) -> String {
+    if ptr.is_null() { String::new() } else { CStr::from_ptr(ptr).to_string_lossy().into_owned() }
+}
+
+unsafe extern "C" fn host_read_data(ctx: *mut c_void, addr: u16) -> u8 {
+    host(ctx).arduboy.peek_data(addr)
+}
+
+unsafe extern "C" fn host_read_flash(ctx: *mut c_void, addr: u32) -> u8 {
+    host(ctx).arduboy.mem.flash.get(addr as usize).copied().unwrap_or(0xFF)
+}
+
+unsafe extern "C" fn host_read_eeprom(ctx: *mut c_void, addr: u16) -> u8 {
+    host(ctx).arduboy.mem.eeprom.get(addr as usize).copied().unwrap_or(0xFF)
+}
+
+unsafe extern "C" fn host_peripherals_json(ctx: *mut c_void, buf: *mut u8, len: usize) -> usize {
+    let json = serde_json::to_string(&host(ctx).arduboy.peripherals()).unwrap_or_default();
+    if !buf.is_null() {
+        std::ptr::copy_nonoverlapping(json.as_ptr(), buf, json.len().min(len));
+    }
+    json.len()
+}
+
+unsafe extern "C" fn host_fill_rect(ctx: *mut c_void, x: i32, y: i32, w: i32, h: i32, argb: u32) {
+    if w > 0 && h > 0 {
+        host(ctx).overlay.rects.push(OverlayRect { x, y, w, h, argb });
+    }
+}
+
+unsafe extern "C" fn host_set_status(ctx: *mut c_void, t: *const c_char) {
+    *host(ctx).status = text(t);
+}
+
+unsafe extern "C" fn host_log(ctx: *mut c_void, t: *const c_char) {
+    eprintln!("[{}] {}", host(ctx).plugin_name, text(t));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static DESTROYED: AtomicU32 = AtomicU32::new(0);
+
+    unsafe extern "C" fn on_frame(_state: *mut c_void, host: *const HostApi, frame: *const FrameInfo) {
+        let (host, frame) = (&*host, &*frame);
+        assert_eq!((frame.screen_width, frame.screen_height), (128, 64));
+        // Mark the byte at 0x0100 as a rectangle of its value's width
+        let v = (host.read_data)(host.ctx, 0x0100);
+        (host.fill_rect)(host.ctx, 0, 0, v as i32, 2, 0xFF00FF00);
+        let mut buf = [0u8; 16];
+        let n = (host.peripherals_json)(host.ctx, buf.as_mut_ptr(), buf.len());
+        assert!(n > buf.len() && buf.starts_with(b"[{\"name\":"));
+        (host.set_status)(host.ctx, c"split 1".as_ptr());
+    }
+
+    unsafe extern "C" fn destroy(_state: *mut c_void) {
+        DESTROYED.fetch_add(1, Ordering::SeqCst);
+    }
+
+    struct Table(PluginApi);
+    unsafe impl Sync for Table {}
+    static TABLE: Table = Table(PluginApi {
+        abi_version: PLUGIN_ABI,
+        name: c"test".as_ptr(),
+        state: std::ptr::null_mut(),
+        on_frame: Some(on_frame),
+        destroy: Some(destroy),
+    });
+
+    unsafe extern "C" fn init(abi: u32) -> *const PluginApi {
+        if abi == PLUGIN_ABI { &TABLE.0 } else { std::ptr::null() }
+    }
+
+    unsafe extern "C" fn init_reject(_abi: u32) -> *const PluginApi {
+        std::ptr::null()
+    }
+
+    #[test]
+    fn test_plugin_frame_and_overlay() {
+        let mut a = Arduboy::new();
+        a.mem.data[0x100] = 3;
+        let mut plugins = Plugins::new();
+        plugins.add(Plugin::from_entry(init).unwrap());
+        a.run_frame();
+        plugins.on_frame(&mut a);
+        assert_eq!(plugins.overlay.rects, vec![OverlayRect { x: 0, y: 0, w: 3, h: 2, argb: 0xFF00FF00 }]);
+        assert_eq!(plugins.overlay.status, vec!["test: split 1".to_string()]);
+
+        let mut buf = vec![0u32; 8 * 8];
+        plugins.overlay.render(&mut buf, 8, 8, 2);
+        assert_eq!(buf[5], 0x00FF00);
+        assert_eq!(buf[6], 0);
+        assert_eq!(buf[3 * 8], 0x00FF00);
+        assert_eq!(buf[4 * 8], 0);
+
+        drop(plugins);
+        assert_eq!(DESTROYED.load(Ordering::SeqCst), 1);
+        assert!(matches!(Plugin::from_entry(init_reject), Err(Error::Plugin(PluginError::Rejected { abi: 1 }))));
+        assert!(matches!(Plugin::load(Path::new("/nonexistent/plugin.so")), Err(Error::Plugin(PluginError::Open(_)))));
+    }
+}
@@ -3,6 +3,10 @@
 //! Generates valid PNG files using uncompressed (stored) deflate blocks.
 //! This produces larger files than optimal but is simple and dependency-free.
 //! Suitable for 128×64 Arduboy screenshots where file size is trivial.
+//! [`ApngEncoder`] writes animated PNGs at 1 bit per pixel.
+//! [`encode_pcd8544`] saves a PCD8544 screen at its native 84×48 in LCD colors.
+
+use crate::pcd8544::{self, Pcd8544};
 
 /// Encode an RGBA pixel buffer as a PNG file.
 ///
@@ -83,6 +87,132 @@ pub fn encode_png_mono(width: u32, height: u32, pixels: &[bool]) -> Vec<u8> {
     png
 }
 
+/// Encode a monochrome image as a 1-bit PNG with two colors.
+///
+/// `pixels` is a flat array of booleans (true = `on`, false = `off`).
+pub fn encode_png_palette(width: u32, height: u32, pixels: &[bool], off: [u8; 3], on: [u8; 3]) -> Vec<u8> {
+    let mut png = Vec::new();
+    png.extend_from_slice(&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]);
+
+    // IHDR: 1-bit indexed color
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[1, 3, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"PLTE", &[off, on].concat());
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&pack_rows(width as usize, height as usize, pixels)));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// A PCD8544 (Gamebuino Classic) screen at its native 84×48 in LCD colors,
+/// without the border of the 128×64 framebuffer.
+pub fn encode_pcd8544(lcd: &Pcd8544) -> Vec<u8> {
+    let [off, on] = pcd8544::PALETTE;
+    encode_png_palette(pcd8544::PCD_WIDTH as u32, pcd8544::PCD_HEIGHT as u32, &lcd.native_pixels(), off, on)
+}
+
+/// Filtered 1-bit rows (filter byte None, MSB first).
+fn pack_rows(width: usize, height: usize, pixels: &[bool]) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((width.div_ceil(8) + 1) * height);
+    for row in pixels.chunks(width).take(height) {
+        raw.push(0); // filter: None
+        for bits in row.chunks(8) {
+            raw.push(bits.iter().enumerate().fold(0u8, |b, (i, &on)| b | ((on as u8) << (7 - i))));
+        }
+    }
+    raw
+}
+
+/// Builder for animated PNG (APNG) files with a 2-color palette.
+///
+/// Frames are stored at 1 bit per pixel; a frame identical to the previous
+/// one extends its display time instead of being stored again.
+pub struct ApngEncoder {
+    width: u32,
+    height: u32,
+    /// Colors of unlit and lit pixels
+    palette: [[u8; 3]; 2],
+    /// Time per added frame: `delay_num / delay_den` seconds
+    delay_num: u16,
+    delay_den: u16,
+    /// Filtered 1-bit rows and the number of added frames each one spans
+    frames: Vec<(Vec<u8>, u16)>,
+}
+
+impl ApngEncoder {
+    /// Create an encoder; each added frame is shown `delay_num / delay_den`
+    /// seconds (1/60 for every Arduboy frame).
+    pub fn new(width: u32, height: u32, delay_num: u16, delay_den: u16, off: [u8; 3], on: [u8; 3]) -> Self {
+        ApngEncoder {
+            width, height, palette: [off, on],
+            delay_num: delay_num.max(1), delay_den,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Add a frame from a monochrome boolean array (true = lit).
+    pub fn add_frame_mono(&mut self, pixels: &[bool]) {
+        let raw = pack_rows(self.width as usize, self.height as usize, pixels);
+        let max_span = u16::MAX / self.delay_num;
+        match self.frames.last_mut() {
+            Some((prev, span)) if *prev == raw && *span < max_span => *span += 1,
+            _ => self.frames.push((raw, 1)),
+        }
+    }
+
+    /// Number of distinct frames stored so far.
+    pub fn frame_count(&self) -> u32 {
+        self.frames.len() as u32
+    }
+
+    /// Finalize the APNG and return the complete file data.
+    pub fn finish(self) -> Vec<u8> {
+        let mut png = Vec::new();
+        png.extend_from_slice(&[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A]);
+
+        // IHDR: 1-bit indexed color
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        ihdr.extend_from_slice(&[1, 3, 0, 0, 0]);
+        write_chunk(&mut png, b"IHDR", &ihdr);
+        write_chunk(&mut png, b"PLTE", &self.palette.concat());
+
+        // acTL: frame count, loop forever
+        let mut actl = (self.frames.len() as u32).to_be_bytes().to_vec();
+        actl.extend_from_slice(&0u32.to_be_bytes());
+        write_chunk(&mut png, b"acTL", &actl);
+
+        let mut seq = 0u32;
+        for (i, (raw, span)) in self.frames.iter().enumerate() {
+            let mut fctl = Vec::with_capacity(26);
+            fctl.extend_from_slice(&seq.to_be_bytes());
+            fctl.extend_from_slice(&self.width.to_be_bytes());
+            fctl.extend_from_slice(&self.height.to_be_bytes());
+            fctl.extend_from_slice(&[0; 8]); // x, y offset
+            fctl.extend_from_slice(&(self.delay_num * span).to_be_bytes());
+            fctl.extend_from_slice(&self.delay_den.to_be_bytes());
+            fctl.extend_from_slice(&[0, 0]); // dispose: none, blend: source
+            write_chunk(&mut png, b"fcTL", &fctl);
+            seq += 1;
+
+            let zlib_data = zlib_stored(raw);
+            if i == 0 {
+                write_chunk(&mut png, b"IDAT", &zlib_data);
+            } else {
+                let mut fdat = seq.to_be_bytes().to_vec();
+                fdat.extend_from_slice(&zlib_data);
+                write_chunk(&mut png, b"fdAT", &fdat);
+                seq += 1;
+            }
+        }
+        write_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
 fn write_chunk(out: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
     let len = data.len() as u32;
     out.extend_from_slice(&len.to_be_bytes());
@@ -135,7 +265,7 @@ fn adler32(data: &[u8]) -> u32 {
 }
 
 // CRC-32 (PNG/zlib)
-fn crc32(chunk_type: &[u8], data: &[u8]) -> u32 {
+pub(crate) fn crc32(chunk_type: &[u8], data: &[u8]) -> u32 {
     let mut crc: u32 = 0xFFFFFFFF;
     for &b in chunk_type.iter().chain(data.iter()) {
         crc ^= b as u32;
@@ -149,3 +279,48 @@ fn crc32(chunk_type: &[u8], data: &[u8]) -> u32 {
     }
     crc ^ 0xFFFFFFFF
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apng_merges_repeated_frames() {
+        let mut apng = ApngEncoder::new(10, 2, 1, 60, [0, 0, 0], [255, 255, 255]);
+        let mut px = vec![false; 20];
+        apng.add_frame_mono(&px);
+        apng.add_frame_mono(&px);
+        px[9] = true;
+        apng.add_frame_mono(&px);
+        assert_eq!(apng.frame_count(), 2);
+        assert_eq!(apng.frames[1].0, vec![0, 0x00, 0x40, 0, 0x00, 0x00]);
+
+        let data = apng.finish();
+        let find = |tag: &[u8]| data.windows(4).position(|w| w == tag).unwrap() + 4;
+        let actl = find(b"acTL");
+        assert_eq!(&data[actl..actl + 4], &2u32.to_be_bytes());
+        // First frame shown for 2/60 s
+        let fctl = find(b"fcTL");
+        assert_eq!(&data[fctl + 20..fctl + 24], &[0, 2, 0, 60]);
+        let fdat = find(b"fdAT");
+        assert_eq!(&data[fdat..fdat + 4], &2u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_pcd8544_native_png() {
+        let mut lcd = Pcd8544::new();
+        lcd.vram[0] = 0x01; // top-left pixel
+        lcd.vram[pcd8544::PCD_WIDTH * 5 + 83] = 0x80; // bottom-right pixel
+        let px = lcd.native_pixels();
+        assert_eq!(px.len(), 84 * 48);
+        assert!(px[0] && px[84 * 48 - 1]);
+        assert_eq!(px.iter().filter(|&&p| p).count(), 2);
+
+        let data = encode_pcd8544(&lcd);
+        let find = |tag: &[u8]| data.windows(4).position(|w| w == tag).unwrap() + 4;
+        let ihdr = find(b"IHDR");
+        assert_eq!(&data[ihdr..ihdr + 8], &[0, 0, 0, 84, 0, 0, 0, 48]);
+        let plte = find(b"PLTE");
+        assert_eq!(&data[plte..plte + 6], &pcd8544::PALETTE.concat()[..]);
+    }
+}
@@ -5,10 +5,12 @@
 //! - Total instruction and cycle counts
 //! - Top-N hotspot analysis with disassembly
 //! - Call graph tracking (CALL/RET pairs)
+//! - FX flash streaming statistics ([`FxStats`])
 //!
 //! The profiler is zero-cost when disabled — all data lives in this struct,
 //! and the emulator core calls [`Profiler::record`] only when enabled.
 
+use crate::fx_stats::FxStats;
 use std::collections::HashMap;
 
 /// Execution profiler state.
@@ -16,7 +18,7 @@ pub struct Profiler {
     /// Whether profiling is currently active
     pub enabled: bool,
     /// Per-PC hit counts (word address → count)
-    pc_hits: HashMap<u16, u64>,
+    pc_hits: HashMap<u32, u64>,
     /// Total instructions executed while profiling
     pub total_instructions: u64,
     /// Total cycles elapsed while profiling
@@ -24,9 +26,11 @@ pub struct Profiler {
     /// Cycle counter at profiler start
     start_tick: u64,
     /// Call stack depth tracker: (caller_pc, callee_pc) → count
-    call_graph: HashMap<(u16, u16), u64>,
+    call_graph: HashMap<(u32, u32), u64>,
     /// Current call stack for tracking (limited depth)
-    call_stack: Vec<u16>,
+    call_stack: Vec<u32>,
+    /// FX flash reads while profiling
+    pub fx: FxStats,
 }
 
 impl Profiler {
@@ -39,6 +43,7 @@ impl Profiler {
             start_tick: 0,
             call_graph: HashMap::new(),
             call_stack: Vec::new(),
+            fx: FxStats::default(),
         }
     }
 
@@ -47,6 +52,7 @@ impl Profiler {
         self.pc_hits.clear();
         self.call_graph.clear();
         self.call_stack.clear();
+        self.fx.clear();
         self.total_instructions = 0;
         self.total_cycles = 0;
         self.start_tick = tick;
@@ -61,14 +67,14 @@ impl Profiler {
 
     /// Record execution of an instruction at the given PC (word address).
     #[inline]
-    pub fn record(&mut self, pc: u16) {
+    pub fn record(&mut self, pc: u32) {
         *self.pc_hits.entry(pc).or_insert(0) += 1;
         self.total_instructions += 1;
     }
 
     /// Record a CALL/RCALL/ICALL instruction.
     #[inline]
-    pub fn record_call(&mut self, caller_pc: u16, target_pc: u16) {
+    pub fn record_call(&mut self, caller_pc: u32, target_pc: u32) {
         *self.call_graph.entry((caller_pc, target_pc)).or_insert(0) += 1;
         if self.call_stack.len() < 128 {
             self.call_stack.push(caller_pc);
@@ -87,7 +93,7 @@ impl Profiler {
     }
 
     /// Get top-N hottest addresses by execution count.
-    pub fn top_hits(&self, n: usize) -> Vec<(u16, u64)> {
+    pub fn top_hits(&self, n: usize) -> Vec<(u32, u64)> {
         let mut v: Vec<_> = self.pc_hits.iter().map(|(&pc, &cnt)| (pc, cnt)).collect();
         v.sort_by(|a, b| b.1.cmp(&a.1));
         v.truncate(n);
@@ -95,7 +101,7 @@ impl Profiler {
     }
 
     /// Get top-N call edges by invocation count.
-    pub fn top_calls(&self, n: usize) -> Vec<((u16, u16), u64)> {
+    pub fn top_calls(&self, n: usize) -> Vec<((u32, u32), u64)> {
         let mut v: Vec<_> = self.call_graph.iter()
             .map(|(&edge, &cnt)| (edge, cnt)).collect();
         v.sort_by(|a, b| b.1.cmp(&a.1));
@@ -105,7 +111,7 @@ impl Profiler {
 
     /// Get flat profile: addresses grouped into ranges (basic blocks).
     /// Returns sorted vec of (start_addr, end_addr, total_hits).
-    pub fn flat_profile(&self) -> Vec<(u16, u16, u64)> {
+    pub fn flat_profile(&self) -> Vec<(u32, u32, u64)> {
         if self.pc_hits.is_empty() { return vec![]; }
         let mut addrs: Vec<_> = self.pc_hits.keys().copied().collect();
         addrs.sort();
@@ -0,0 +1,255 @@
+//! Real-time synchronization for frontends.
+//!
+//! [`RealtimeSync`] ties emulated CPU cycles to host wall-clock time so each
+//! frontend does not need its own timing loop. The frontend reports the
+//! cycles it ran and asks how far ahead or behind real time it is:
+//!
+//! ```no_run
+//! use arduboy_core::{Arduboy, RealtimeSync, FRAME_CYCLES};
+//! use std::time::Instant;
+//!
+//! let mut arduboy = Arduboy::new();
+//! let mut sync = RealtimeSync::at_frame_rate(60);
+//! loop {
+//!     for _ in 0..sync.frames_due(Instant::now()).max(1) {
+//!         arduboy.run_frame();
+//!         sync.ran(FRAME_CYCLES);
+//!     }
+//!     // ... present the frame ...
+//!     std::thread::sleep(sync.sleep_time(Instant::now()));
+//! }
+//! ```
+//!
+//! Time is measured from a fixed start point rather than by adding up frame
+//! durations, so rounding in sleeps and timers never accumulates into drift.
+//! When the emulator runs more than [`RealtimeSync::max_lag`] ahead
+//! (fast-forward) the clock is restarted instead of stalling. What happens
+//! when it falls behind (slow host, window drag, debugger stop, a game that
+//! overruns on a slow board) is set by the [`OverrunPolicy`]:
+//!
+//! | Policy | Behind real time |
+//! |--------|------------------|
+//! | [`Slowdown`](OverrunPolicy::Slowdown) | At most one frame is due; the rest is dropped, so the game slows down like the hardware |
+//! | [`CatchUp`](OverrunPolicy::CatchUp) | Extra frames are due until the debt is repaid; debt beyond `max_debt` is dropped (default, 100 ms) |
+//! | [`FreeRun`](OverrunPolicy::FreeRun) | No pacing: one frame is always due and there is never a sleep |
+
+use crate::FRAME_CYCLES;
+use std::time::{Duration, Instant};
+
+/// Lag beyond which the clock is restarted
+const DEFAULT_MAX_LAG: Duration = Duration::from_millis(100);
+
+/// How emulated time follows real time when the emulator falls behind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverrunPolicy {
+    /// Never run more than one frame to catch up
+    Slowdown,
+    /// Run extra frames to repay up to `max_debt` of lag
+    CatchUp { max_debt: Duration },
+    /// Run as fast as the host allows
+    FreeRun,
+}
+
+impl Default for OverrunPolicy {
+    fn default() -> Self {
+        OverrunPolicy::CatchUp { max_debt: DEFAULT_MAX_LAG }
+    }
+}
+
+impl OverrunPolicy {
+    /// Parse `slowdown`, `catchup`, `catchup:<ms>` or `free`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        match spec.split_once(':') {
+            None if spec == "slowdown" => Ok(OverrunPolicy::Slowdown),
+            None if spec == "catchup" => Ok(OverrunPolicy::default()),
+            None if spec == "free" => Ok(OverrunPolicy::FreeRun),
+            Some(("catchup", ms)) => ms.parse()
+                .map(|ms| OverrunPolicy::CatchUp { max_debt: Duration::from_millis(ms) })
+                .map_err(|_| format!("bad max debt '{}' (milliseconds)", ms)),
+            _ => Err(format!("unknown policy '{}' (slowdown, catchup[:<ms>] or free)", spec)),
+        }
+    }
+}
+
+/// Wall-clock pacing of emulated cycles.
+#[derive(Debug, Clone)]
+pub struct RealtimeSync {
+    /// Target emulated cycles per host second
+    rate: f64,
+    /// Clock start; set by the first query after creation or a restart
+    start: Option<Instant>,
+    /// Cycles emulated since `start`
+    cycles: u64,
+    /// How far emulation may run ahead of real time before restarting
+    pub max_lag: Duration,
+    /// What to do when emulation falls behind
+    pub policy: OverrunPolicy,
+    /// Number of restarts: too far ahead, or lag dropped by the policy
+    pub resyncs: u32,
+}
+
+impl RealtimeSync {
+    /// Pace `cycles_per_sec` emulated cycles per host second.
+    pub fn new(cycles_per_sec: u64) -> Self {
+        RealtimeSync {
+            rate: cycles_per_sec.max(1) as f64,
+            start: None,
+            cycles: 0,
+            max_lag: DEFAULT_MAX_LAG,
+            policy: OverrunPolicy::default(),
+            resyncs: 0,
+        }
+    }
+
+    /// Pace `fps` calls of [`Arduboy::run_frame`](crate::Arduboy::run_frame)
+    /// per host second (60 matches the original frontends).
+    pub fn at_frame_rate(fps: u32) -> Self {
+        Self::new(FRAME_CYCLES * fps as u64)
+    }
+
+    /// Target emulated cycles per host second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Change the target rate (e.g. for a speed multiplier) and restart.
+    pub fn set_rate(&mut self, cycles_per_sec: u64) {
+        self.rate = cycles_per_sec.max(1) as f64;
+        self.reset();
+    }
+
+    /// Restart the clock at the next query, forgetting accumulated lag.
+    pub fn reset(&mut self) {
+        self.start = None;
+        self.cycles = 0;
+    }
+
+    /// Record `cycles` of emulation.
+    pub fn ran(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    /// Cycles emulation is behind real time at `now` (negative when ahead).
+    pub fn lag(&mut self, now: Instant) -> f64 {
+        let max_debt = match self.policy {
+            OverrunPolicy::FreeRun => return FRAME_CYCLES as f64,
+            OverrunPolicy::Slowdown => FRAME_CYCLES as f64,
+            OverrunPolicy::CatchUp { max_debt } => max_debt.as_secs_f64() * self.rate,
+        };
+        let start = *self.start.get_or_insert(now);
+        let expected = now.saturating_duration_since(start).as_secs_f64() * self.rate;
+        let lag = expected - self.cycles as f64;
+        if lag > max_debt || -lag > self.max_lag.as_secs_f64() * self.rate {
+            // Slowdown keeps the one frame it allows, the rest restart at zero
+            let keep = if lag > 0.0 && self.policy == OverrunPolicy::Slowdown { max_debt } else { 0.0 };
+            self.start = Some(now.checked_sub(Duration::from_secs_f64(keep / self.rate)).unwrap_or(now));
+            self.cycles = 0;
+            self.resyncs += 1;
+            return keep;
+        }
+        lag
+    }
+
+    /// Cycles to run now to catch up with real time.
+    pub fn cycles_due(&mut self, now: Instant) -> u64 {
+        self.lag(now).max(0.0) as u64
+    }
+
+    /// Whole frames ([`FRAME_CYCLES`]) to run now to catch up with real time.
+    pub fn frames_due(&mut self, now: Instant) -> u32 {
+        (self.cycles_due(now) / FRAME_CYCLES) as u32
+    }
+
+    /// How long to sleep before real time reaches the emulated time.
+    pub fn sleep_time(&mut self, now: Instant) -> Duration {
+        let ahead = -self.lag(now);
+        if ahead > 0.0 {
+            Duration::from_secs_f64(ahead / self.rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_micros(16_667);
+
+    #[test]
+    fn test_sleep_and_frames_due() {
+        let t0 = Instant::now();
+        let mut sync = RealtimeSync::at_frame_rate(60);
+        assert_eq!(sync.frames_due(t0), 0);
+        sync.ran(FRAME_CYCLES);
+        let sleep = sync.sleep_time(t0);
+        assert!(sleep > FRAME - Duration::from_micros(10) && sleep < FRAME + Duration::from_micros(10));
+
+        // Half a frame later only half the sleep is left; three frames late, three are due
+        let half = sync.sleep_time(t0 + FRAME / 2);
+        assert!(half < FRAME / 2 + Duration::from_micros(10));
+        assert_eq!(sync.frames_due(t0 + FRAME * 4), 3);
+    }
+
+    #[test]
+    fn test_no_drift_over_time() {
+        // Sleeps that overshoot by 1 ms must not slow the game down
+        let t0 = Instant::now();
+        let mut sync = RealtimeSync::at_frame_rate(60);
+        let mut now = t0;
+        let mut frames = 0u64;
+        for _ in 0..600 {
+            for _ in 0..sync.frames_due(now).max(1) {
+                sync.ran(FRAME_CYCLES);
+                frames += 1;
+            }
+            now += sync.sleep_time(now) + Duration::from_millis(1);
+        }
+        let expected = (now - t0).as_secs_f64() * 60.0;
+        assert!((frames as f64 - expected).abs() < 2.0, "{} vs {}", frames, expected);
+        assert_eq!(sync.resyncs, 0);
+    }
+
+    #[test]
+    fn test_resync_after_stall() {
+        let t0 = Instant::now();
+        let mut sync = RealtimeSync::at_frame_rate(60);
+        sync.ran(FRAME_CYCLES);
+        assert!(sync.sleep_time(t0) > Duration::ZERO);
+        // A 2-second stall is dropped instead of fast-forwarding 120 frames
+        assert_eq!(sync.frames_due(t0 + Duration::from_secs(2)), 0);
+        assert_eq!(sync.resyncs, 1);
+        assert_eq!(sync.frames_due(t0 + Duration::from_secs(2) + FRAME * 2), 2);
+    }
+
+    #[test]
+    fn test_overrun_policies() {
+        let t0 = Instant::now();
+        let late = t0 + FRAME * 4;
+
+        let mut sync = RealtimeSync::at_frame_rate(60);
+        sync.policy = OverrunPolicy::Slowdown;
+        assert_eq!(sync.frames_due(t0), 0);
+        // Four frames behind: one is run, the rest is gone
+        assert_eq!(sync.frames_due(late), 1);
+        sync.ran(FRAME_CYCLES);
+        assert_eq!(sync.frames_due(late), 0);
+
+        let mut sync = RealtimeSync::at_frame_rate(60);
+        sync.policy = OverrunPolicy::CatchUp { max_debt: Duration::from_millis(50) };
+        assert_eq!(sync.frames_due(t0), 0);
+        assert_eq!(sync.frames_due(t0 + FRAME * 2), 2);
+        assert_eq!(sync.frames_due(late), 0);
+        assert_eq!(sync.resyncs, 1);
+
+        let mut sync = RealtimeSync::at_frame_rate(60);
+        sync.policy = OverrunPolicy::FreeRun;
+        sync.ran(FRAME_CYCLES * 10);
+        assert_eq!((sync.frames_due(t0), sync.sleep_time(t0)), (1, Duration::ZERO));
+
+        assert_eq!(OverrunPolicy::parse("catchup:250"),
+            Ok(OverrunPolicy::CatchUp { max_debt: Duration::from_millis(250) }));
+        assert!(OverrunPolicy::parse("rewind").is_err());
+    }
+}
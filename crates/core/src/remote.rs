@@ -0,0 +1,495 @@
+//! Remote display server: stream every frame over TCP or WebSocket and take
+//! button input back.
+//!
+//! One port serves three kinds of client, told apart by their first bytes:
+//!
+//! | Client | First bytes | Gets |
+//! |--------|-------------|------|
+//! | Browser | `GET /` | [`VIEWER_HTML`], a page that connects back over WebSocket |
+//! | WebSocket | `GET` with `Sec-WebSocket-Key` | one binary message per frame |
+//! | Raw TCP | a command line, e.g. `watch` | frames back to back on the stream |
+//!
+//! Every frame is encoded the same way: frame number (u32), width and
+//! height (u16), all little-endian, then the rows top to bottom, one bit per
+//! pixel, most significant bit leftmost, each row padded to whole bytes. The
+//! screen is turned by [`Arduboy::rotation`] and a pixel is lit when its red
+//! channel is above half.
+//!
+//! Clients send commands as text lines (raw TCP) or text messages
+//! (WebSocket):
+//!
+//! | Command | Effect |
+//! |---------|--------|
+//! | `press <button>` | hold `up`, `down`, `left`, `right`, `a` or `b` |
+//! | `release <button>` | let it go |
+//! | `reset` | reset the CPU |
+//! | `watch` | nothing; starts a raw TCP stream |
+//!
+//! Commands are applied between frames and every frame is sent, numbered,
+//! so a client sees the same pictures for the same inputs on the same
+//! frames. A client that falls more than [`MAX_QUEUED`] bytes behind misses
+//! frames (the gap shows in the frame numbers) instead of stalling the
+//! emulator.
+
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+
+use crate::scenario::parse_button;
+use crate::{Arduboy, Rotation, SCREEN_WIDTH};
+
+/// Default listen address
+pub const REMOTE_ADDR: &str = "127.0.0.1:8765";
+/// Bytes a client may have queued before frames are skipped for it
+pub const MAX_QUEUED: usize = 256 * 1024;
+/// Longest HTTP request header accepted
+const MAX_REQUEST: usize = 8192;
+/// Appended to `Sec-WebSocket-Key` before hashing (RFC 6455)
+const WS_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Browser viewer served at `/`: arrow keys, Z = A, X = B.
+pub const VIEWER_HTML: &str = r#"<!DOCTYPE html>
+<html><head><meta charset="utf-8"><title>arduboy-emu</title>
+<style>body{background:#111;color:#aaa;font:14px sans-serif;text-align:center}
+canvas{image-rendering:pixelated;width:512px;background:#000;margin-top:2em}</style></head>
+<body><canvas id="c" width="128" height="64"></canvas><p id="s">connecting…</p>
+<script>
+const c = document.getElementById('c'), g = c.getContext('2d'), s = document.getElementById('s');
+const ws = new WebSocket('ws://' + location.host + '/');
+ws.binaryType = 'arraybuffer';
+ws.onclose = () => s.textContent = 'disconnected';
+ws.onmessage = e => {
+  const d = new DataView(e.data), w = d.getUint16(4, true), h = d.getUint16(6, true), stride = (w + 7) >> 3;
+  if (c.width != w || c.height != h) { c.width = w; c.height = h; c.style.width = w * 4 + 'px'; }
+  const img = g.createImageData(w, h);
+  for (let y = 0; y < h; y++) for (let x = 0; x < w; x++) {
+    const on = d.getUint8(8 + y * stride + (x >> 3)) & (0x80 >> (x & 7)), i = (y * w + x) * 4;
+    img.data[i] = img.data[i + 1] = img.data[i + 2] = on ? 255 : 0; img.data[i + 3] = 255;
+  }
+  g.putImageData(img, 0, 0);
+  s.textContent = 'frame ' + d.getUint32(0, true);
+};
+const keys = {ArrowUp: 'up', ArrowDown: 'down', ArrowLeft: 'left', ArrowRight: 'right', z: 'a', x: 'b'};
+for (const [ev, cmd] of [['keydown', 'press'], ['keyup', 'release']]) {
+  addEventListener(ev, e => {
+    const b = keys[e.key.length == 1 ? e.key.toLowerCase() : e.key];
+    if (b && !e.repeat && ws.readyState == 1) { ws.send(cmd + ' ' + b); e.preventDefault(); }
+  });
+}
+</script></body></html>
+"#;
+
+/// A command from a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Press(crate::Button),
+    Release(crate::Button),
+    Reset,
+    Watch,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let cmd = words.next().unwrap_or("");
+        let button = |arg: Option<&str>| parse_button(arg.ok_or_else(|| format!("{}: missing button", cmd))?);
+        let c = match cmd {
+            "press" => Command::Press(button(words.next())?),
+            "release" => Command::Release(button(words.next())?),
+            "reset" => Command::Reset,
+            "watch" => Command::Watch,
+            _ => return Err(format!("unknown command '{}'", cmd)),
+        };
+        match words.next() {
+            Some(extra) => Err(format!("{}: unexpected '{}'", cmd, extra)),
+            None => Ok(c),
+        }
+    }
+}
+
+/// Encode the current screen (see the module docs).
+pub fn encode_frame(arduboy: &Arduboy, frame: u32) -> Vec<u8> {
+    let (w, h) = arduboy.screen_size();
+    let fb = arduboy.framebuffer_rgba();
+    let mut lit = Vec::with_capacity(w * h);
+    for y in 0..h {
+        lit.extend(fb[y * SCREEN_WIDTH * 4..(y * SCREEN_WIDTH + w) * 4].chunks(4).map(|px| px[0] > 128));
+    }
+    let (lit, w, h) = match arduboy.rotation {
+        Rotation::None => (lit, w, h),
+        r => r.rotate(&lit, w, h),
+    };
+    let stride = w.div_ceil(8);
+    let mut out = Vec::with_capacity(8 + stride * h);
+    out.extend_from_slice(&frame.to_le_bytes());
+    out.extend_from_slice(&(w as u16).to_le_bytes());
+    out.extend_from_slice(&(h as u16).to_le_bytes());
+    for row in lit.chunks(w) {
+        for bits in row.chunks(8) {
+            out.push(bits.iter().enumerate().fold(0, |b, (i, &on)| b | (on as u8) << (7 - i)));
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// Nothing received yet
+    Pending,
+    /// Reading an HTTP request
+    Http,
+    WebSocket,
+    Raw,
+}
+
+struct Client {
+    stream: TcpStream,
+    kind: Kind,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    /// Drop once the output is sent
+    closing: bool,
+    /// Drop now
+    dead: bool,
+}
+
+impl Client {
+    fn queue(&mut self, data: &[u8]) {
+        self.output.extend_from_slice(data);
+    }
+
+    fn flush(&mut self) {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return self.dead = true,
+                Ok(n) => { self.output.drain(..n); }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return self.dead = true,
+            }
+        }
+        if self.closing {
+            self.dead = true;
+        }
+    }
+
+    /// Read what has arrived and return the complete commands.
+    fn receive(&mut self) -> Vec<String> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut buf) {
+                Ok(0) => { self.dead = true; break; }
+                Ok(n) => self.input.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => { self.dead = true; break; }
+            }
+        }
+        if self.kind == Kind::Pending && !self.input.is_empty() {
+            self.kind = if self.input[0] == b'G' { Kind::Http } else { Kind::Raw };
+        }
+        match self.kind {
+            Kind::Http => { self.http(); Vec::new() }
+            Kind::WebSocket => self.ws_messages(),
+            Kind::Raw => {
+                let mut lines = Vec::new();
+                while let Some(end) = self.input.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = self.input.drain(..=end).collect();
+                    lines.push(String::from_utf8_lossy(&line).trim().to_string());
+                }
+                lines
+            }
+            Kind::Pending => Vec::new(),
+        }
+    }
+
+    /// Answer a complete HTTP request: the viewer page or a WebSocket upgrade.
+    fn http(&mut self) {
+        let Some(end) = self.input.windows(4).position(|w| w == b"\r\n\r\n") else {
+            if self.input.len() > MAX_REQUEST {
+                self.dead = true;
+            }
+            return;
+        };
+        let request = String::from_utf8_lossy(&self.input[..end]).into_owned();
+        self.input.drain(..end + 4);
+        let path = request.split_whitespace().nth(1).unwrap_or("");
+        let key = request.lines()
+            .filter_map(|l| l.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("sec-websocket-key"))
+            .map(|(_, v)| v.trim().to_string());
+        let response = match (key, path) {
+            (Some(key), _) => {
+                self.kind = Kind::WebSocket;
+                let accept = base64(&sha1(format!("{}{}", key, WS_GUID).as_bytes()));
+                format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                    Sec-WebSocket-Accept: {}\r\n\r\n", accept)
+            }
+            (None, "/") => {
+                self.closing = true;
+                format!("HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\n\
+                    Connection: close\r\n\r\n{}", VIEWER_HTML.len(), VIEWER_HTML)
+            }
+            _ => {
+                self.closing = true;
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            }
+        };
+        self.queue(response.as_bytes());
+    }
+
+    /// Complete WebSocket messages; text messages are commands.
+    fn ws_messages(&mut self) -> Vec<String> {
+        let mut out = Vec::new();
+        while let Some((opcode, payload, used)) = ws_parse(&self.input) {
+            self.input.drain(..used);
+            match opcode {
+                0x1 => out.push(String::from_utf8_lossy(&payload).trim().to_string()),
+                0x8 => {
+                    self.queue(&ws_header(0x8, 0));
+                    self.closing = true;
+                }
+                0x9 => {
+                    self.queue(&ws_header(0xA, payload.len()));
+                    self.queue(&payload);
+                }
+                _ => {}
+            }
+        }
+        out
+    }
+}
+
+/// Parse one client frame: opcode, unmasked payload and bytes used.
+fn ws_parse(buf: &[u8]) -> Option<(u8, Vec<u8>, usize)> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let masked = buf[1] & 0x80 != 0;
+    let (len, mut pos) = match buf[1] & 0x7F {
+        126 => (u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as usize, 4),
+        127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?) as usize, 10),
+        n => (n as usize, 2),
+    };
+    let mask = if masked {
+        let m: [u8; 4] = buf.get(pos..pos + 4)?.try_into().ok()?;
+        pos += 4;
+        m
+    } else {
+        [0; 4]
+    };
+    let data = buf.get(pos..pos.checked_add(len)?)?;
+    let payload = data.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]).collect();
+    Some((buf[0] & 0x0F, payload, pos + len))
+}
+
+/// Header of an unmasked server frame with FIN set.
+fn ws_header(opcode: u8, len: usize) -> Vec<u8> {
+    let mut h = vec![0x80 | opcode];
+    match len {
+        0..=125 => h.push(len as u8),
+        126..=0xFFFF => {
+            h.push(126);
+            h.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        _ => {
+            h.push(127);
+            h.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    h
+}
+
+/// Listening socket and connected clients; nothing blocks.
+pub struct RemoteServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    /// Command errors for the frontend to print
+    messages: Vec<String>,
+}
+
+impl RemoteServer {
+    pub fn bind(addr: &str) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(RemoteServer { listener, clients: Vec::new(), messages: Vec::new() })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Clients receiving frames.
+    pub fn viewers(&self) -> usize {
+        self.clients.iter().filter(|c| matches!(c.kind, Kind::WebSocket | Kind::Raw)).count()
+    }
+
+    /// Accept connections and apply the commands received since the last
+    /// call. Call between frames.
+    pub fn service(&mut self, arduboy: &mut Arduboy) {
+        while let Ok((stream, peer)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_err() {
+                continue;
+            }
+            let _ = stream.set_nodelay(true);
+            self.messages.push(format!("Remote: {} connected", peer));
+            self.clients.push(Client { stream, kind: Kind::Pending, input: Vec::new(), output: Vec::new(), closing: false, dead: false });
+        }
+        for client in &mut self.clients {
+            for line in client.receive() {
+                if line.is_empty() {
+                    continue;
+                }
+                match Command::parse(&line) {
+                    Ok(Command::Press(b)) => arduboy.set_button(b, true),
+                    Ok(Command::Release(b)) => arduboy.set_button(b, false),
+                    Ok(Command::Reset) => arduboy.reset(),
+                    Ok(Command::Watch) => {}
+                    Err(e) => self.messages.push(format!("Remote: {}", e)),
+                }
+            }
+            client.flush();
+        }
+        self.clients.retain(|c| !c.dead);
+    }
+
+    /// Send the current screen to every viewer; call after each frame.
+    pub fn publish(&mut self, arduboy: &Arduboy, frame: u32) {
+        if self.viewers() == 0 {
+            return;
+        }
+        let data = encode_frame(arduboy, frame);
+        for client in &mut self.clients {
+            if client.output.len() > MAX_QUEUED {
+                continue;
+            }
+            match client.kind {
+                Kind::WebSocket if !client.closing => {
+                    client.queue(&ws_header(0x2, data.len()));
+                    client.queue(&data);
+                }
+                Kind::Raw => client.queue(&data),
+                _ => continue,
+            }
+            client.flush();
+        }
+        self.clients.retain(|c| !c.dead);
+    }
+
+    /// Connection notices and command errors since the last call.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.messages)
+    }
+}
+
+/// SHA-1 digest, only for the WebSocket handshake.
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut msg = data.to_vec();
+    msg.push(0x80);
+    while msg.len() % 64 != 56 {
+        msg.push(0);
+    }
+    msg.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in msg.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &wi) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(wi);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = t;
+        }
+        for (x, v) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(v);
+        }
+    }
+    let mut out = [0u8; 20];
+    for (i, x) in h.iter().enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            out.push(if i <= chunk.len() { ALPHABET[(n >> (18 - 6 * i)) as usize & 63] as char } else { '=' });
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Button;
+
+    #[test]
+    fn test_handshake_and_frames() {
+        // RFC 6455 example key
+        assert_eq!(base64(&sha1(format!("dGhlIHNhbXBsZSBub25jZQ=={}", WS_GUID).as_bytes())),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        let mut a = Arduboy::new();
+        a.display.framebuffer[..4].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        let f = encode_frame(&a, 7);
+        assert_eq!(f.len(), 8 + 16 * 64);
+        assert_eq!(&f[..8], &[7, 0, 0, 0, 128, 0, 64, 0]);
+        assert_eq!((f[8], f[9]), (0x80, 0x00));
+        // Masked "reset" from a browser
+        let mask = [1, 2, 3, 4];
+        let mut msg = vec![0x81, 0x85];
+        msg.extend_from_slice(&mask);
+        msg.extend(b"reset".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        assert_eq!(ws_parse(&msg), Some((1, b"reset".to_vec(), 11)));
+        assert_eq!(ws_parse(&msg[..6]), None);
+        assert_eq!(ws_header(0x2, 1032), vec![0x82, 126, 0x04, 0x08]);
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!(Command::parse("press UP"), Ok(Command::Press(Button::Up)));
+        assert_eq!(Command::parse("release b"), Ok(Command::Release(Button::B)));
+        assert!(Command::parse("press").unwrap_err().contains("missing"));
+        assert!(Command::parse("press a b").unwrap_err().contains("unexpected"));
+        assert!(Command::parse("jump").is_err());
+    }
+
+    #[test]
+    fn test_raw_client() {
+        let mut server = RemoteServer::bind("127.0.0.1:0").unwrap();
+        let mut a = Arduboy::new();
+        let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+        client.write_all(b"watch\npress a\n").unwrap();
+        for _ in 0..200 {
+            server.service(&mut a);
+            if server.viewers() == 1 && a.buttons != 0 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+        assert_eq!(server.viewers(), 1);
+        assert_ne!(a.buttons, 0);
+        server.publish(&a, 3);
+        let mut header = [0u8; 8];
+        client.read_exact(&mut header).unwrap();
+        assert_eq!(header, [3, 0, 0, 0, 128, 0, 64, 0]);
+        assert!(server.take_messages()[0].contains("connected"));
+    }
+}
@@ -0,0 +1,237 @@
+//! IPS and BPS patches for ROM hacks.
+//!
+//! Community hacks of Arduboy games are distributed as patches against the
+//! program image: the flash bytes from address 0 to the end of the loaded
+//! HEX (what `avr-objcopy -O binary` writes). The format is detected from
+//! the magic:
+//!
+//! | Format | Magic | Checks |
+//! |--------|-------|--------|
+//! | IPS | `PATCH` | None beyond the record structure |
+//! | BPS | `BPS1` | Source size, CRC-32 of source, target and patch |
+//!
+//! A BPS patch made for another game or another build of it is rejected
+//! with [`RomPatchError::SourceChecksum`]; an IPS patch cannot tell. Bytes
+//! an IPS patch writes past the end of the image extend it, with any gap
+//! filled with `0xFF` like erased flash.
+
+use crate::error::RomPatchError;
+use crate::png::crc32;
+
+/// Patch file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RomPatchFormat {
+    Ips,
+    Bps,
+}
+
+impl RomPatchFormat {
+    /// Format from the file magic.
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(b"PATCH") {
+            Some(RomPatchFormat::Ips)
+        } else if patch.starts_with(b"BPS1") {
+            Some(RomPatchFormat::Bps)
+        } else {
+            None
+        }
+    }
+}
+
+/// Patch the program image `rom`, returning the new image.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomPatchError> {
+    match RomPatchFormat::detect(patch) {
+        Some(RomPatchFormat::Ips) => apply_ips(rom, patch),
+        Some(RomPatchFormat::Bps) => apply_bps(rom, patch),
+        None => Err(RomPatchError::UnknownFormat),
+    }
+}
+
+/// Reads through a patch, failing with `Truncated` at its end.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], RomPatchError> {
+        let b = self.data.get(self.pos..self.pos + n).ok_or(RomPatchError::Truncated)?;
+        self.pos += n;
+        Ok(b)
+    }
+
+    /// Big-endian number of `n` bytes (IPS)
+    fn be(&mut self, n: usize) -> Result<usize, RomPatchError> {
+        Ok(self.bytes(n)?.iter().fold(0, |v, &b| v << 8 | b as usize))
+    }
+
+    /// BPS variable-length number
+    fn number(&mut self) -> Result<usize, RomPatchError> {
+        let (mut value, mut shift) = (0usize, 1usize);
+        loop {
+            let x = self.bytes(1)?[0] as usize;
+            value = value.checked_add((x & 0x7F) * shift).ok_or(RomPatchError::BadOffset)?;
+            if x & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_shl(7).ok_or(RomPatchError::BadOffset)?;
+            value += shift;
+        }
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomPatchError> {
+    let mut out = rom.to_vec();
+    let mut r = Reader { data: patch, pos: 5 };
+    loop {
+        if r.data.get(r.pos..r.pos + 3) == Some(b"EOF") {
+            r.pos += 3;
+            break;
+        }
+        let offset = r.be(3)?;
+        let (len, fill) = match r.be(2)? {
+            0 => (r.be(2)?, Some(r.bytes(1)?[0])),
+            n => (n, None),
+        };
+        if out.len() < offset + len {
+            out.resize(offset + len, 0xFF);
+        }
+        match fill {
+            Some(b) => out[offset..offset + len].fill(b),
+            None => out[offset..offset + len].copy_from_slice(r.bytes(len)?),
+        }
+    }
+    // Optional truncation extension
+    if let Ok(size) = r.be(3) {
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+fn crc_at(patch: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]])
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, RomPatchError> {
+    if patch.len() < 4 + 12 {
+        return Err(RomPatchError::Truncated);
+    }
+    let end = patch.len() - 12;
+    if crc32(&[], &patch[..patch.len() - 4]) != crc_at(patch, end + 8) {
+        return Err(RomPatchError::PatchChecksum);
+    }
+    let mut r = Reader { data: &patch[..end], pos: 4 };
+    let source_size = r.number()?;
+    if source_size != rom.len() {
+        return Err(RomPatchError::SourceSize { expected: source_size, found: rom.len() });
+    }
+    let expected = crc_at(patch, end);
+    let found = crc32(&[], rom);
+    if expected != found {
+        return Err(RomPatchError::SourceChecksum { expected, found });
+    }
+    let target_size = r.number()?;
+    let metadata = r.number()?;
+    r.bytes(metadata)?;
+
+    let mut out = Vec::with_capacity(target_size);
+    let (mut source_rel, mut target_rel) = (0usize, 0usize);
+    // Relative copy offsets are sign-magnitude with the sign in bit 0
+    let seek = |at: usize, d: usize| match d & 1 {
+        0 => at.checked_add(d >> 1),
+        _ => at.checked_sub(d >> 1),
+    }.ok_or(RomPatchError::BadOffset);
+    while r.pos < end {
+        let cmd = r.number()?;
+        let len = (cmd >> 2) + 1;
+        match cmd & 3 {
+            0 => {
+                let at = out.len();
+                out.extend_from_slice(rom.get(at..at + len).ok_or(RomPatchError::BadOffset)?);
+            }
+            1 => out.extend_from_slice(r.bytes(len)?),
+            2 => {
+                source_rel = seek(source_rel, r.number()?)?;
+                out.extend_from_slice(rom.get(source_rel..source_rel + len).ok_or(RomPatchError::BadOffset)?);
+                source_rel += len;
+            }
+            _ => {
+                target_rel = seek(target_rel, r.number()?)?;
+                // Byte by byte: the copy may overlap what it writes
+                for _ in 0..len {
+                    let b = *out.get(target_rel).ok_or(RomPatchError::BadOffset)?;
+                    out.push(b);
+                    target_rel += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size || crc32(&[], &out) != crc_at(patch, end + 4) {
+        return Err(RomPatchError::TargetChecksum);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn number(mut v: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (v & 0x7F) as u8;
+            v >>= 7;
+            if v == 0 {
+                out.push(x | 0x80);
+                return;
+            }
+            out.push(x);
+            v -= 1;
+        }
+    }
+
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut p = b"BPS1".to_vec();
+        number(source.len(), &mut p);
+        number(target.len(), &mut p);
+        number(0, &mut p);
+        p.extend_from_slice(actions);
+        p.extend_from_slice(&crc32(&[], source).to_le_bytes());
+        p.extend_from_slice(&crc32(&[], target).to_le_bytes());
+        let crc = crc32(&[], &p);
+        p.extend_from_slice(&crc.to_le_bytes());
+        p
+    }
+
+    #[test]
+    fn test_ips() {
+        let rom = [0u8; 8];
+        // Two bytes at 2, RLE 3x 0xAA at 10 (past the end)
+        let patch = b"PATCH\x00\x00\x02\x00\x02\x12\x34\x00\x00\x0A\x00\x00\x00\x03\xAAEOF";
+        let out = apply(&rom, patch).unwrap();
+        assert_eq!(out, [0, 0, 0x12, 0x34, 0, 0, 0, 0, 0xFF, 0xFF, 0xAA, 0xAA, 0xAA]);
+        assert_eq!(apply(&rom, b"PATCH\x00\x00"), Err(RomPatchError::Truncated));
+        assert_eq!(apply(&rom, b"hello"), Err(RomPatchError::UnknownFormat));
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"abcdefgh";
+        let target = b"abcdXYXYXgh";
+        let mut actions = Vec::new();
+        number(3 << 2, &mut actions);           // SourceRead 4
+        number((1 << 2) | 1, &mut actions);     // TargetRead 2
+        actions.extend_from_slice(b"XY");
+        number((2 << 2) | 3, &mut actions);     // TargetCopy 3 from target 4
+        number(4 << 1, &mut actions);
+        number((1 << 2) | 2, &mut actions);     // SourceCopy 2 from source 6
+        number(6 << 1, &mut actions);
+        let patch = bps(source, target, &actions);
+        assert_eq!(apply(source, &patch).unwrap(), target);
+
+        assert!(matches!(apply(b"abcdefgX", &patch), Err(RomPatchError::SourceChecksum { .. })));
+        assert_eq!(apply(b"abc", &patch), Err(RomPatchError::SourceSize { expected: 8, found: 3 }));
+        let mut bad = patch.clone();
+        bad[8] ^= 1;
+        assert_eq!(apply(source, &bad), Err(RomPatchError::PatchChecksum));
+    }
+}
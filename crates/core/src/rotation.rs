@@ -0,0 +1,135 @@
+//! Screen rotation for portrait games.
+//!
+//! Some games are drawn sideways for a unit held upright (vertical
+//! shooters, card games). [`Rotation`] turns the visible screen for output
+//! only; the display controller and framebuffer keep their landscape
+//! layout. [`Arduboy::rotation`](crate::Arduboy::rotation) is applied by
+//! [`Arduboy::screen_output`](crate::Arduboy::screen_output), which feeds
+//! [`FrameHooks::on_display_update`](crate::FrameHooks::on_display_update)
+//! and the [`SharedScreen`](crate::SharedScreen), and frontends apply it to
+//! screenshots and recordings so every output agrees.
+//!
+//! | Rotation | Degrees (clockwise) | Top of the game's screen ends up |
+//! |----------|--------------------|----------------------------------|
+//! | [`None`](Rotation::None) | 0 | at the top |
+//! | [`Cw90`](Rotation::Cw90) | 90 | on the right |
+//! | [`Half`](Rotation::Half) | 180 | at the bottom |
+//! | [`Ccw90`](Rotation::Ccw90) | 270 | on the left |
+//!
+//! A `.arduboy` package can ask for one with a `"rotation"` field in its
+//! `info.json` (degrees, see [`ArduboyFile::rotation`](crate::arduboy_file::ArduboyFile::rotation)).
+
+/// Output rotation, clockwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rotation {
+    #[default]
+    None,
+    Cw90,
+    Half,
+    Ccw90,
+}
+
+impl Rotation {
+    /// Rotation of a multiple of 90 degrees (negative = counter-clockwise).
+    pub fn from_degrees(deg: i32) -> Option<Self> {
+        match deg.rem_euclid(360) {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Cw90),
+            180 => Some(Rotation::Half),
+            270 => Some(Rotation::Ccw90),
+            _ => None,
+        }
+    }
+
+    /// Parse `0`, `90`, `180`, `270` (or `-90`), `cw` or `ccw`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "cw" => Ok(Rotation::Cw90),
+            "ccw" => Ok(Rotation::Ccw90),
+            other => other.parse().ok().and_then(Self::from_degrees)
+                .ok_or_else(|| format!("expected 0, 90, 180, 270, cw or ccw, got '{}'", s)),
+        }
+    }
+
+    pub fn degrees(self) -> u16 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Cw90 => 90,
+            Rotation::Half => 180,
+            Rotation::Ccw90 => 270,
+        }
+    }
+
+    /// Next rotation for a toggle key, starting with the classic
+    /// counter-clockwise portrait view.
+    pub fn next(self) -> Self {
+        match self {
+            Rotation::None => Rotation::Ccw90,
+            Rotation::Ccw90 => Rotation::Half,
+            Rotation::Half => Rotation::Cw90,
+            Rotation::Cw90 => Rotation::None,
+        }
+    }
+
+    /// Width and height trade places.
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Rotation::Cw90 | Rotation::Ccw90)
+    }
+
+    /// Output size of a `w × h` image.
+    pub fn size(self, w: usize, h: usize) -> (usize, usize) {
+        if self.swaps_axes() { (h, w) } else { (w, h) }
+    }
+
+    /// Rotate a `w × h` image into `out` (resized to fit); returns the
+    /// output size.
+    pub fn rotate_into<T: Copy + Default>(self, src: &[T], w: usize, h: usize, out: &mut Vec<T>) -> (usize, usize) {
+        let (rw, rh) = self.size(w, h);
+        out.clear();
+        out.resize(rw * rh, T::default());
+        for y in 0..h {
+            for x in 0..w {
+                let (nx, ny) = match self {
+                    Rotation::None => (x, y),
+                    Rotation::Cw90 => (h - 1 - y, x),
+                    Rotation::Half => (w - 1 - x, h - 1 - y),
+                    Rotation::Ccw90 => (y, w - 1 - x),
+                };
+                out[ny * rw + nx] = src[y * w + x];
+            }
+        }
+        (rw, rh)
+    }
+
+    /// Rotated copy of a `w × h` image: `(pixels, width, height)`.
+    pub fn rotate<T: Copy + Default>(self, src: &[T], w: usize, h: usize) -> (Vec<T>, usize, usize) {
+        let mut out = Vec::new();
+        let (rw, rh) = self.rotate_into(src, w, h, &mut out);
+        (out, rw, rh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate() {
+        // 3×2:  1 2 3
+        //       4 5 6
+        let img = [1, 2, 3, 4, 5, 6];
+        assert_eq!(Rotation::Cw90.rotate(&img, 3, 2), (vec![4, 1, 5, 2, 6, 3], 2, 3));
+        assert_eq!(Rotation::Ccw90.rotate(&img, 3, 2), (vec![3, 6, 2, 5, 1, 4], 2, 3));
+        assert_eq!(Rotation::Half.rotate(&img, 3, 2), (vec![6, 5, 4, 3, 2, 1], 3, 2));
+        assert_eq!(Rotation::None.rotate(&img, 3, 2).0, img);
+
+        assert_eq!(Rotation::parse("-90"), Ok(Rotation::Ccw90));
+        assert_eq!(Rotation::parse("CW"), Ok(Rotation::Cw90));
+        assert!(Rotation::parse("45").is_err());
+        let mut r = Rotation::None;
+        for _ in 0..4 {
+            r = r.next();
+        }
+        assert_eq!(r, Rotation::None);
+    }
+}
@@ -10,21 +10,22 @@
 //! +------------------+
 //! | Magic "ABES"     |  4 bytes
 //! +------------------+
-//! | Format version   |  u32 little-endian (currently 1)
+//! | Format version   |  u32 little-endian (currently 3)
 //! +------------------+
-//! | CPU type         |  u8 (0 = ATmega32u4, 1 = ATmega328P)
+//! | CPU type         |  u8 (0 = ATmega32u4, 1 = ATmega328P, 2 = ATmega2560)
 //! +------------------+
 //! | Compressed data  |  deflate-compressed bincode payload
 //! +------------------+
 //! ```
 
+use crate::error::{Error, SaveStateError};
 use serde::{Serialize, Deserialize};
 use std::path::Path;
 
 /// Magic bytes identifying an arduboy-emu save state file.
 const MAGIC: &[u8; 4] = b"ABES";
 /// Current save state format version.
-const FORMAT_VERSION: u32 = 1;
+const FORMAT_VERSION: u32 = 3;
 
 // ─── Per-component state structs ────────────────────────────────────────────
 
@@ -132,7 +133,8 @@ pub struct PllState {
 
 #[derive(Serialize, Deserialize)]
 pub struct FxFlashState {
-    pub data: Vec<u8>,
+    /// Sectors changed since the FX image loaded: (sector, 4 KB contents)
+    pub journal: Vec<(u32, Vec<u8>)>,
     pub loaded: bool,
     pub write_enabled: bool,
     pub powered_down: bool,
@@ -169,7 +171,7 @@ pub struct Pcd8544State {
 #[derive(Serialize, Deserialize)]
 pub struct SaveState {
     // CPU
-    pub pc: u16,
+    pub pc: u32,
     pub sp: u16,
     pub sreg: u8,
     pub tick: u64,
@@ -190,6 +192,9 @@ pub struct SaveState {
     pub timer2: Timer8State,
     pub timer3: Timer16State,
     pub timer4: Timer4State,
+    /// ATmega2560 Timer4 and Timer5
+    pub timer4_2560: Timer16State,
+    pub timer5: Timer16State,
 
     // Peripherals
     pub spi: SpiState,
@@ -231,9 +236,16 @@ pub struct SaveState {
 // ─── File I/O ───────────────────────────────────────────────────────────────
 
 /// Save state to file with header and deflate compression.
-pub fn save_to_file(state: &SaveState, cpu_type_byte: u8, path: &Path) -> Result<(), String> {
+pub fn save_to_file(state: &SaveState, cpu_type_byte: u8, path: &Path) -> Result<(), Error> {
+    let out = encode(state, cpu_type_byte)?;
+    std::fs::write(path, &out)
+        .map_err(|source| Error::Io { op: "Write", source })
+}
+
+/// Save state file contents: header and deflated payload.
+pub fn encode(state: &SaveState, cpu_type_byte: u8) -> Result<Vec<u8>, Error> {
     let payload = bincode::serialize(state)
-        .map_err(|e| format!("Serialize error: {}", e))?;
+        .map_err(|e| SaveStateError::Encode(e.to_string()))?;
 
     let compressed = miniz_oxide::deflate::compress_to_vec(&payload, 6);
 
@@ -242,40 +254,38 @@ pub fn save_to_file(state: &SaveState, cpu_type_byte: u8, path: &Path) -> Result
     out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
     out.push(cpu_type_byte);
     out.extend_from_slice(&compressed);
-
-    std::fs::write(path, &out)
-        .map_err(|e| format!("Write error: {}", e))
+    Ok(out)
 }
 
 /// Load state from file, verifying magic, version, and CPU type.
-pub fn load_from_file(path: &Path, expected_cpu_type: u8) -> Result<SaveState, String> {
+pub fn load_from_file(path: &Path, expected_cpu_type: u8) -> Result<SaveState, Error> {
     let data = std::fs::read(path)
-        .map_err(|e| format!("Read error: {}", e))?;
+        .map_err(|source| Error::Io { op: "Read", source })?;
+    decode(&data, expected_cpu_type)
+}
 
+/// Parse save state file contents (see [`load_from_file`]).
+pub fn decode(data: &[u8], expected_cpu_type: u8) -> Result<SaveState, Error> {
     if data.len() < 9 {
-        return Err("File too small".into());
+        return Err(SaveStateError::TooSmall.into());
     }
     if &data[0..4] != MAGIC {
-        return Err("Invalid save state file (bad magic)".into());
+        return Err(SaveStateError::BadMagic.into());
     }
     let version = u32::from_le_bytes([data[4], data[5], data[6], data[7]]);
     if version != FORMAT_VERSION {
-        return Err(format!("Unsupported save state version {} (expected {})",
-            version, FORMAT_VERSION));
+        return Err(SaveStateError::Version { found: version, expected: FORMAT_VERSION }.into());
     }
     let cpu_type = data[8];
     if cpu_type != expected_cpu_type {
-        let names = ["ATmega32u4", "ATmega328P"];
-        return Err(format!("CPU type mismatch: save={} current={}",
-            names.get(cpu_type as usize).unwrap_or(&"?"),
-            names.get(expected_cpu_type as usize).unwrap_or(&"?")));
+        return Err(SaveStateError::CpuMismatch { saved: cpu_type, current: expected_cpu_type }.into());
     }
 
     let decompressed = miniz_oxide::inflate::decompress_to_vec(&data[9..])
-        .map_err(|e| format!("Decompress error: {:?}", e))?;
+        .map_err(|e| SaveStateError::Decompress(format!("{:?}", e)))?;
 
-    bincode::deserialize(&decompressed)
-        .map_err(|e| format!("Deserialize error: {}", e))
+    Ok(bincode::deserialize(&decompressed)
+        .map_err(|e| SaveStateError::Decode(e.to_string()))?)
 }
 
 /// Derive save state file path from game file path.
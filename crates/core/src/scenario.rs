@@ -0,0 +1,409 @@
+//! Scripted headless test scenarios.
+//!
+//! A scenario file describes one complete automated test run: which game to
+//! load, the inputs to feed, what to assert and which artifacts to keep.
+//! It uses a small TOML subset (key/value pairs, `[[array]]` tables, strings,
+//! integers, booleans and one-line arrays):
+//!
+//! ```toml
+//! game = "mygame.hex"       # relative to the scenario file
+//! fx = "mygame-fx.bin"      # optional FX data
+//! cpu = "32u4"              # optional: assert detected CPU (32u4 / 328p)
+//! frames = 600              # frames to run
+//! artifacts = "out"         # where screenshots are written (default ".")
+//!
+//! [[input]]
+//! frame = 30                # press on this frame...
+//! buttons = ["A", "UP"]
+//! duration = 5              # ...and release after N frames (default 1)
+//!
+//! [[expect]]
+//! frame = 120
+//! serial_contains = "READY" # serial output so far contains text
+//!
+//! [[expect]]
+//! frame = 200
+//! fb_hash = "0x9c1f3e2a7b6d5c40"   # Arduboy::framebuffer_hash()
+//!
+//! [[expect]]
+//! frame = 200
+//! ram = 0x0150              # data-space byte equals value
+//! value = 3
+//!
+//! [[expect]]
+//! frame = 240
+//! sound_hz = 880            # a tone within 3% of 880 Hz started so far
+//!
+//! [[screenshot]]
+//! frame = 300
+//! file = "title.png"
+//!
+//! [[fault]]
+//! frame = 400
+//! spec = "eeprom-fail,every=2"   # see crate::fault; "off" clears
+//! ```
+//!
+//! Failed expectations are reported with the observed value, and a PNG of
+//! the failing frame is written to the artifacts directory.
+
+use crate::{Arduboy, Button, CpuType, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::fault::FaultRule;
+use crate::schedule::{Action, At};
+use crate::sound_events::EventKind;
+use crate::toml_lite;
+use std::path::Path;
+
+// ─── Scenario model ─────────────────────────────────────────────────────────
+
+/// Scripted button press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputEvent {
+    /// Frame on which the buttons are pressed (1-based)
+    pub frame: u32,
+    pub buttons: Vec<Button>,
+    /// Frames to hold before release
+    pub duration: u32,
+}
+
+/// What an expectation checks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expect {
+    /// Accumulated serial output contains this text
+    SerialContains(String),
+    /// [`Arduboy::framebuffer_hash`] equals this value
+    FramebufferHash(u64),
+    /// Data-space byte equals value
+    Ram { addr: u16, value: u8 },
+    /// A tone within 3% of this frequency (Hz) started so far
+    SoundPlayed(u32),
+}
+
+/// An expectation evaluated after a given frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub frame: u32,
+    pub expect: Expect,
+}
+
+/// A parsed scenario file.
+#[derive(Debug, Clone, Default)]
+pub struct Scenario {
+    /// Game file (.hex / .arduboy / .elf), relative to the scenario file
+    pub game: String,
+    /// Optional FX data file
+    pub fx: Option<String>,
+    /// Expected CPU type, if asserted
+    pub cpu: Option<CpuType>,
+    /// Number of frames to run
+    pub frames: u32,
+    /// Artifact directory (screenshots)
+    pub artifacts: Option<String>,
+    pub inputs: Vec<InputEvent>,
+    pub checks: Vec<Check>,
+    /// Screenshots to save: (frame, file name)
+    pub screenshots: Vec<(u32, String)>,
+    /// Fault rules to add (None clears them) at the start of a frame
+    pub faults: Vec<(u32, Option<FaultRule>)>,
+}
+
+/// Outcome of [`Scenario::run`].
+#[derive(Debug, Clone, Default)]
+pub struct ScenarioReport {
+    pub frames_run: u32,
+    pub passed: usize,
+    /// Human-readable failure descriptions
+    pub failures: Vec<String>,
+    /// Files written (screenshots)
+    pub artifacts: Vec<String>,
+}
+
+impl ScenarioReport {
+    /// True if every expectation held.
+    pub fn success(&self) -> bool { self.failures.is_empty() }
+}
+
+impl Scenario {
+    /// Parse a scenario from TOML text.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let doc = toml_lite::parse(text)?;
+        let mut sc = Scenario { frames: 60, ..Default::default() };
+
+        for (key, val) in &doc.root {
+            match key.as_str() {
+                "game" => sc.game = val.as_str(key)?.to_string(),
+                "fx" => sc.fx = Some(val.as_str(key)?.to_string()),
+                "artifacts" => sc.artifacts = Some(val.as_str(key)?.to_string()),
+                "frames" => sc.frames = val.as_u32(key)?,
+                "cpu" => sc.cpu = Some(match val.as_str(key)?.to_lowercase().as_str() {
+                    "32u4" | "atmega32u4" => CpuType::Atmega32u4,
+                    "328p" | "atmega328p" => CpuType::Atmega328p,
+                    other => return Err(format!("cpu: unknown type \"{}\"", other)),
+                }),
+                _ => return Err(format!("unknown key \"{}\"", key)),
+            }
+        }
+        if sc.game.is_empty() {
+            return Err("missing \"game\"".into());
+        }
+
+        for (name, table) in &doc.tables {
+            let frame = table.get("frame")
+                .ok_or_else(|| format!("[[{}]]: missing \"frame\"", name))?
+                .as_u32("frame")?;
+            match name.as_str() {
+                "input" => {
+                    let list = table.get("buttons")
+                        .ok_or("[[input]]: missing \"buttons\"")?;
+                    let mut buttons = Vec::new();
+                    for b in list.as_array("buttons")? {
+                        buttons.push(parse_button(b.as_str("buttons")?)?);
+                    }
+                    let duration = match table.get("duration") {
+                        Some(v) => v.as_u32("duration")?.max(1),
+                        None => 1,
+                    };
+                    sc.inputs.push(InputEvent { frame, buttons, duration });
+                }
+                "expect" => {
+                    let expect = if let Some(v) = table.get("serial_contains") {
+                        Expect::SerialContains(v.as_str("serial_contains")?.to_string())
+                    } else if let Some(v) = table.get("fb_hash") {
+                        let s = v.as_str("fb_hash")?;
+                        let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+                        Expect::FramebufferHash(u64::from_str_radix(hex, 16)
+                            .map_err(|_| format!("fb_hash: bad hex \"{}\"", s))?)
+                    } else if let Some(v) = table.get("ram") {
+                        let addr = v.as_u32("ram")?;
+                        let value = table.get("value")
+                            .ok_or("[[expect]]: \"ram\" needs \"value\"")?
+                            .as_u32("value")?;
+                        if addr > 0xFFFF || value > 0xFF {
+                            return Err("[[expect]]: ram/value out of range".into());
+                        }
+                        Expect::Ram { addr: addr as u16, value: value as u8 }
+                    } else if let Some(v) = table.get("sound_hz") {
+                        Expect::SoundPlayed(v.as_u32("sound_hz")?)
+                    } else {
+                        return Err("[[expect]]: needs serial_contains, fb_hash, ram or sound_hz".into());
+                    };
+                    sc.checks.push(Check { frame, expect });
+                }
+                "screenshot" => {
+                    let file = match table.get("file") {
+                        Some(v) => v.as_str("file")?.to_string(),
+                        None => format!("frame_{:06}.png", frame),
+                    };
+                    sc.screenshots.push((frame, file));
+                }
+                "fault" => {
+                    let spec = table.get("spec").ok_or("[[fault]]: missing \"spec\"")?.as_str("spec")?;
+                    let rule = if spec == "off" { None } else { Some(FaultRule::parse(spec)?) };
+                    sc.faults.push((frame, rule));
+                }
+                other => return Err(format!("unknown table [[{}]]", other)),
+            }
+        }
+
+        // Always run far enough to evaluate everything
+        let last = sc.checks.iter().map(|c| c.frame)
+            .chain(sc.screenshots.iter().map(|s| s.0))
+            .chain(sc.inputs.iter().map(|i| i.frame))
+            .chain(sc.faults.iter().map(|f| f.0))
+            .max().unwrap_or(0);
+        sc.frames = sc.frames.max(last);
+        Ok(sc)
+    }
+
+    /// Run the scenario on an emulator that already has the game loaded.
+    ///
+    /// Serial output is consumed by the runner. Screenshots go to
+    /// `artifacts` (or the current directory).
+    pub fn run(&self, arduboy: &mut Arduboy) -> ScenarioReport {
+        let mut report = ScenarioReport::default();
+        let mut serial_log: Vec<u8> = Vec::new();
+        let mut onsets: Vec<f32> = Vec::new();
+        arduboy.sound_events.enabled = true;
+        let out_dir = self.artifacts.as_deref().unwrap_or(".");
+        if self.artifacts.is_some() {
+            let _ = std::fs::create_dir_all(out_dir);
+        }
+
+        if let Some(cpu) = self.cpu {
+            if arduboy.cpu_type != cpu {
+                report.failures.push(format!("cpu: expected {:?}, got {:?}", cpu, arduboy.cpu_type));
+            } else {
+                report.passed += 1;
+            }
+        }
+
+        // Scenario frame N is the Nth frame run from here
+        let start = arduboy.frame_count();
+        let at = |n: u32| At::Frame(start.saturating_add(n).saturating_sub(1));
+        for ev in &self.inputs {
+            for &b in &ev.buttons {
+                let (press, release) = (at(ev.frame), at(ev.frame.saturating_add(ev.duration)));
+                arduboy.schedule(press, Action::Button(b, true));
+                arduboy.schedule(release, Action::Button(b, false));
+            }
+        }
+        for (frame, rule) in &self.faults {
+            arduboy.schedule(at(*frame), Action::Fault(rule.clone()));
+        }
+
+        for frame in 1..=self.frames {
+            arduboy.run_frame();
+            serial_log.extend(arduboy.take_serial_output());
+            onsets.extend(arduboy.take_sound_events().iter()
+                .filter(|e| e.kind == EventKind::Onset)
+                .map(|e| e.hz));
+            report.frames_run = frame;
+
+            for check in self.checks.iter().filter(|c| c.frame == frame) {
+                match check_failure(&check.expect, arduboy, &serial_log, &onsets) {
+                    None => report.passed += 1,
+                    Some(msg) => {
+                        report.failures.push(format!("frame {}: {}", frame, msg));
+                        let path = Path::new(out_dir).join(format!("fail_frame_{:06}.png", frame));
+                        save_png(arduboy, &path, &mut report);
+                    }
+                }
+            }
+            for (_, file) in self.screenshots.iter().filter(|s| s.0 == frame) {
+                save_png(arduboy, &Path::new(out_dir).join(file), &mut report);
+            }
+        }
+        report
+    }
+}
+
+/// Describe why an expectation failed, or `None` if it holds.
+fn check_failure(expect: &Expect, arduboy: &Arduboy, serial_log: &[u8], onsets: &[f32]) -> Option<String> {
+    match expect {
+        Expect::SerialContains(text) => {
+            let log = String::from_utf8_lossy(serial_log);
+            if log.contains(text.as_str()) { None } else {
+                Some(format!("serial output does not contain {:?} (got {} bytes)", text, serial_log.len()))
+            }
+        }
+        Expect::FramebufferHash(want) => {
+            let got = arduboy.framebuffer_hash();
+            if got == *want { None } else {
+                Some(format!("fb_hash: expected 0x{:016x}, got 0x{:016x}", want, got))
+            }
+        }
+        Expect::Ram { addr, value } => {
+            let got = arduboy.mem.data.get(*addr as usize).copied().unwrap_or(0);
+            if got == *value { None } else {
+                Some(format!("ram[0x{:04X}]: expected 0x{:02X}, got 0x{:02X}", addr, value, got))
+            }
+        }
+        Expect::SoundPlayed(hz) => {
+            let want = *hz as f32;
+            if onsets.iter().any(|&f| (f - want).abs() <= want * 0.03) { None } else {
+                let mut heard: Vec<String> = onsets.iter().map(|f| format!("{:.0}", f)).collect();
+                heard.dedup();
+                Some(format!("sound_hz: no {} Hz tone started (heard: {})", hz,
+                    if heard.is_empty() { "nothing".to_string() } else { heard.join(", ") }))
+            }
+        }
+    }
+}
+
+fn save_png(arduboy: &Arduboy, path: &Path, report: &mut ScenarioReport) {
+    let png = crate::png::encode_png(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, arduboy.framebuffer_rgba());
+    match std::fs::write(path, &png) {
+        Ok(()) => report.artifacts.push(path.to_string_lossy().into_owned()),
+        Err(e) => report.failures.push(format!("{}: {}", path.display(), e)),
+    }
+}
+
+/// Parse a button name (`up`, `down`, `left`, `right`, `a`, `b`; any case).
+pub fn parse_button(name: &str) -> Result<Button, String> {
+    Ok(match name.to_uppercase().as_str() {
+        "UP" => Button::Up,
+        "DOWN" => Button::Down,
+        "LEFT" => Button::Left,
+        "RIGHT" => Button::Right,
+        "A" => Button::A,
+        "B" => Button::B,
+        _ => return Err(format!("unknown button \"{}\"", name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+        # comment
+        game = "game.hex"   # trailing comment
+        cpu = "328p"
+        frames = 100
+
+        [[input]]
+        frame = 10
+        buttons = ["a", "Up"]
+        duration = 3
+
+        [[expect]]
+        frame = 20
+        serial_contains = "hi # not a comment"
+
+        [[expect]]
+        frame = 30
+        ram = 0x0150
+        value = 7
+
+        [[screenshot]]
+        frame = 150
+    "#;
+
+    #[test]
+    fn test_parse_scenario() {
+        let sc = Scenario::parse(SAMPLE).unwrap();
+        assert_eq!(sc.game, "game.hex");
+        assert_eq!(sc.cpu, Some(CpuType::Atmega328p));
+        assert_eq!(sc.frames, 150); // extended to cover the screenshot
+        assert_eq!(sc.inputs, vec![InputEvent {
+            frame: 10, buttons: vec![Button::A, Button::Up], duration: 3,
+        }]);
+        assert_eq!(sc.checks[0].expect, Expect::SerialContains("hi # not a comment".into()));
+        assert_eq!(sc.checks[1].expect, Expect::Ram { addr: 0x150, value: 7 });
+        assert_eq!(sc.screenshots, vec![(150, "frame_000150.png".to_string())]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Scenario::parse("frames = 10").is_err()); // no game
+        assert!(Scenario::parse("game = \"x.hex\"\nbogus = 1").is_err());
+        assert!(Scenario::parse("game = \"x.hex\"\n[[input]]\nframe = 1\nbuttons = [\"Z\"]").is_err());
+        assert!(Scenario::parse("game = \"x.hex\n").is_err());
+    }
+
+    #[test]
+    fn test_huge_duration() {
+        // Release frame past u32::MAX saturates instead of overflowing
+        let sc = Scenario::parse("game = \"x.hex\"\nframes = 1\n[[input]]\nframe = 1\nbuttons = [\"A\"]\nduration = 4294967295\n").unwrap();
+        let mut ard = Arduboy::new();
+        ard.run_frame();
+        assert_eq!(sc.run(&mut ard).frames_run, 1);
+    }
+
+    #[test]
+    fn test_run_ram_check() {
+        let dir = std::env::temp_dir().join("arduboy_scenario_test");
+        let text = format!(
+            "game = \"x.hex\"\nframes = 2\nartifacts = \"{}\"\n\
+             [[expect]]\nframe = 1\nram = 0x100\nvalue = 0\n\
+             [[expect]]\nframe = 2\nram = 0x100\nvalue = 1\n",
+            dir.to_string_lossy().replace('\\', "/"));
+        let sc = Scenario::parse(&text).unwrap();
+        let mut ard = Arduboy::new();
+        let report = sc.run(&mut ard);
+        assert_eq!(report.frames_run, 2);
+        assert_eq!(report.passed, 1);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.artifacts.len(), 1); // failure screenshot
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
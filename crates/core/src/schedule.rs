@@ -0,0 +1,249 @@
+//! Frame- and tick-scheduled actions.
+//!
+//! Hosts register actions at a future frame number or CPU tick with
+//! [`Arduboy::schedule`]; [`Arduboy::run_frame`] performs them when due:
+//!
+//! | Trigger | Performed |
+//! |---------|-----------|
+//! | [`At::Frame`]`(n)` | At the start of the frame run when [`Arduboy::frame_count`] is `n` |
+//! | [`At::Tick`]`(t)` | Before the first instruction starting at or after CPU tick `t` |
+//!
+//! | Action | Effect |
+//! |--------|--------|
+//! | [`Action::Button`] | Press or release, also recorded by the verification log |
+//! | [`Action::Adc`] | Fix (or free) an ADC channel's reading |
+//! | [`Action::Marker`] | Collected by [`Arduboy::take_markers`], e.g. "screenshot" |
+//! | [`Action::Fault`] | Add a [`fault`](crate::fault) rule, or clear them all |
+//! | [`Action::Call`] | Runs a host closure on the emulator |
+//!
+//! Triggers already in the past fire at the next opportunity, and actions
+//! due together run in the order they were scheduled. Pending actions are
+//! dropped on reset. Button events queued by the latency tester, scenario
+//! runner and headless `--press` all go through here. [`parse`] reads the
+//! `<when>:<action>` text form used on command lines.
+
+use std::collections::VecDeque;
+
+use crate::fault::FaultRule;
+use crate::{Arduboy, Button};
+
+/// When an action is performed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum At {
+    /// Start of the frame with this [`Arduboy::frame_count`]
+    Frame(u32),
+    /// CPU tick
+    Tick(u64),
+}
+
+/// A scheduled action.
+pub enum Action {
+    Button(Button, bool),
+    /// ADC channel and 10-bit reading; None returns it to noise
+    Adc(u8, Option<u16>),
+    /// Host-defined event reported by [`Arduboy::take_markers`]
+    Marker(String),
+    /// Fault rule to add; None clears every rule
+    Fault(Option<FaultRule>),
+    Call(Box<dyn FnOnce(&mut Arduboy) + Send>),
+}
+
+impl std::fmt::Debug for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Button(b, p) => write!(f, "Button({:?}, {})", b, p),
+            Action::Adc(ch, v) => write!(f, "Adc({}, {:?})", ch, v),
+            Action::Marker(name) => write!(f, "Marker({:?})", name),
+            Action::Fault(rule) => write!(f, "Fault({:?})", rule.as_ref().map(|r| r.spec.as_str())),
+            Action::Call(_) => write!(f, "Call"),
+        }
+    }
+}
+
+/// A marker that fired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub name: String,
+    /// Frame it fired in ([`Arduboy::frame_count`])
+    pub frame: u32,
+    pub tick: u64,
+}
+
+/// Pending actions in firing order.
+#[derive(Debug, Default)]
+pub struct Schedule {
+    frames: VecDeque<(u32, Action)>,
+    ticks: VecDeque<(u64, Action)>,
+    pub(crate) markers: Vec<Marker>,
+}
+
+impl Schedule {
+    pub fn add(&mut self, at: At, action: Action) {
+        match at {
+            At::Frame(n) => {
+                let pos = self.frames.partition_point(|&(f, _)| f <= n);
+                self.frames.insert(pos, (n, action));
+            }
+            At::Tick(t) => {
+                let pos = self.ticks.partition_point(|&(x, _)| x <= t);
+                self.ticks.insert(pos, (t, action));
+            }
+        }
+    }
+
+    /// Whether a tick-triggered action is due.
+    pub fn tick_due(&self, tick: u64) -> bool {
+        self.ticks.front().is_some_and(|&(t, _)| t <= tick)
+    }
+
+    /// Remove the next action due at `frame` / `tick`.
+    pub fn pop_due(&mut self, frame: u32, tick: u64) -> Option<Action> {
+        if self.frames.front().is_some_and(|&(f, _)| f <= frame) {
+            return self.frames.pop_front().map(|(_, a)| a);
+        }
+        if self.tick_due(tick) {
+            return self.ticks.pop_front().map(|(_, a)| a);
+        }
+        None
+    }
+
+    /// Actions not yet performed.
+    pub fn len(&self) -> usize {
+        self.frames.len() + self.ticks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pending button actions.
+    pub fn pending_buttons(&self) -> usize {
+        self.frames.iter().map(|(_, a)| a).chain(self.ticks.iter().map(|(_, a)| a))
+            .filter(|a| matches!(a, Action::Button(..)))
+            .count()
+    }
+
+    /// Drop pending actions (fired markers are kept for the host).
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.ticks.clear();
+    }
+}
+
+/// Parse `<when>:<action>`:
+///
+/// | Part | Form |
+/// |------|------|
+/// | when | `N` (frame) or `tN` (tick) |
+/// | action | `press=B`, `release=B`, `adc=CH,VALUE` (`noise` frees it), `mark=NAME`, `screenshot[=FILE]`, `fault=SPEC` (`off` clears) |
+///
+/// `screenshot` is a marker named `screenshot` or `screenshot=FILE` for the
+/// frontend to act on.
+pub fn parse(spec: &str) -> Result<(At, Action), String> {
+    let (when, action) = spec.split_once(':')
+        .ok_or_else(|| format!("expected <when>:<action>, got '{}'", spec))?;
+    let at = match when.strip_prefix('t') {
+        Some(t) => At::Tick(t.parse().map_err(|_| format!("bad tick '{}'", t))?),
+        None => At::Frame(when.parse().map_err(|_| format!("bad frame '{}'", when))?),
+    };
+    let (kind, arg) = action.split_once('=').unwrap_or((action, ""));
+    let action = match kind {
+        "press" | "release" => Action::Button(crate::scenario::parse_button(arg)?, kind == "press"),
+        "adc" => {
+            let (ch, v) = arg.split_once(',').ok_or_else(|| format!("expected adc=CH,VALUE, got '{}'", arg))?;
+            let ch: u8 = ch.parse().ok().filter(|&c| c < 16)
+                .ok_or_else(|| format!("bad ADC channel '{}' (0-15)", ch))?;
+            let v = if v == "noise" {
+                None
+            } else {
+                Some(v.parse::<u16>().ok().filter(|&v| v < 1024)
+                    .ok_or_else(|| format!("bad ADC value '{}' (0-1023 or noise)", v))?)
+            };
+            Action::Adc(ch, v)
+        }
+        "mark" if !arg.is_empty() => Action::Marker(arg.to_string()),
+        "screenshot" => Action::Marker(action.to_string()),
+        "fault" if arg == "off" => Action::Fault(None),
+        "fault" => Action::Fault(Some(FaultRule::parse(arg)?)),
+        _ => return Err(format!("unknown action '{}'", action)),
+    };
+    Ok((at, action))
+}
+
+impl Arduboy {
+    /// Perform `action` when `at` is reached (see [`schedule`](crate::schedule)).
+    pub fn schedule(&mut self, at: At, action: Action) {
+        self.schedule.add(at, action);
+    }
+
+    /// Scheduled actions not yet performed.
+    pub fn pending_scheduled(&self) -> usize {
+        self.schedule.len()
+    }
+
+    /// Markers fired since the last call, in order.
+    pub fn take_markers(&mut self) -> Vec<Marker> {
+        std::mem::take(&mut self.schedule.markers)
+    }
+
+    /// Perform every action due now. `frame_start` is the tick the current
+    /// frame began at, for the verification log.
+    pub(crate) fn run_scheduled(&mut self, frame_start: u64) {
+        while let Some(action) = self.schedule.pop_due(self.frame_count, self.cpu.tick) {
+            match action {
+                Action::Button(button, pressed) => {
+                    self.set_button(button, pressed);
+                    if let Some(v) = self.verify.as_mut() {
+                        v.button_event(self.frame_count, self.cpu.tick - frame_start, button, pressed);
+                    }
+                }
+                Action::Adc(ch, value) => self.set_adc_input(ch, value),
+                Action::Marker(name) => {
+                    let marker = Marker { name, frame: self.frame_count, tick: self.cpu.tick };
+                    self.schedule.markers.push(marker);
+                }
+                Action::Fault(Some(rule)) => { self.faults.add(rule); }
+                Action::Fault(None) => self.faults.clear(),
+                Action::Call(f) => f(self),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_frames_ticks_and_adc() {
+        let mut ard = Arduboy::new();
+        let start = ard.cpu.tick;
+        ard.schedule(At::Frame(2), Action::Marker("second".into()));
+        ard.schedule(At::Frame(1), Action::Button(Button::A, true));
+        ard.schedule(At::Tick(start + 1000), Action::Marker("early".into()));
+        ard.schedule(At::Frame(1), Action::Call(Box::new(|a: &mut Arduboy| a.set_adc_input(3, Some(700)))));
+        assert_eq!(ard.pending_scheduled(), 4);
+
+        ard.run_frame();
+        let m = ard.take_markers();
+        assert_eq!(m.len(), 1);
+        assert_eq!((m[0].name.as_str(), m[0].frame), ("early", 0));
+        assert!(m[0].tick >= start + 1000);
+        let a_bit = crate::input_overlay::button_bit(Button::A);
+        assert_eq!(ard.buttons & a_bit, 0);
+
+        ard.run_frame();
+        assert_eq!(ard.buttons & a_bit, a_bit);
+        assert_eq!(ard.adc.inputs[3], Some(700));
+        ard.run_frame();
+        assert_eq!(ard.take_markers()[0].frame, 2);
+        assert_eq!(ard.pending_scheduled(), 0);
+
+        let (at, action) = parse("t5000:adc=3,noise").unwrap();
+        assert_eq!(at, At::Tick(5000));
+        assert!(matches!(action, Action::Adc(3, None)));
+        assert!(matches!(parse("500:screenshot=end.png").unwrap().1, Action::Marker(n) if n == "screenshot=end.png"));
+        assert!(parse("10:press=Q").is_err());
+        assert!(matches!(parse("t9:fault=spi-drop,p=0.5").unwrap().1, Action::Fault(Some(r)) if r.probability == Some(0.5)));
+    }
+}
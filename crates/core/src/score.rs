@@ -0,0 +1,458 @@
+//! Music score extraction for ArduboyTones and ArduboyPlaytune.
+//!
+//! Edge-based audio reconstruction ([`AudioBuffer`](crate::AudioBuffer))
+//! only sounds right when emulation runs at real-time speed. While
+//! fast-forwarding or skipping frames, the edges of several frames are
+//! squeezed into one audio period and the music turns into noise.
+//!
+//! For games built with one of the common score players, the note sequence
+//! can be read straight from the player's state instead:
+//!
+//! 1. [`detect`] finds the player's static variables via ELF symbols
+//!    (`tonesStart`/`tonesPlaying` for ArduboyTones, `score_start`/
+//!    `tune_playing` for ArduboyPlaytune). Stripped HEX files carry no
+//!    symbols, so this needs an `.elf` build: the players' variables sit
+//!    at addresses chosen by the linker and there is no byte pattern that
+//!    finds them reliably across compiler versions, so HEX builds keep
+//!    edge-based audio.
+//! 2. [`ScoreTracker::poll`], called once per emulated frame, notices when a
+//!    score starts or stops and decodes it from flash (or SRAM for
+//!    `tonesInRAM()` / `tone()`). A score is decoded only when the start
+//!    pointer changes or, for SRAM, when the sequence bytes do.
+//! 3. [`ScoreSynth`] plays the decoded [`Score`] at the mixer in wall-clock
+//!    time, independent of how fast the CPU is being emulated.
+//!
+//! ArduboyTones sequences are `(frequency, duration_ms)` u16 pairs ending in
+//! `TONES_END` (0x8000) or `TONES_REPEAT` (0x8001); bit 15 of a frequency is
+//! the high-volume flag. Playtune scores are byte streams: `0x9t nn` starts
+//! MIDI note `nn` on channel `t`, `0x8t` stops channel `t`, a byte below
+//! 0x80 starts a 15-bit big-endian delay in milliseconds, `0xF0` ends the
+//! score and `0xE0` restarts it.
+
+use crate::elf::{ElfFile, DATA_OFFSET};
+use crate::Arduboy;
+
+/// Score steps decoded before a runaway sequence is cut off
+const MAX_STEPS: usize = 8192;
+/// ArduboyTones end-of-sequence marker
+const TONES_END: u16 = 0x8000;
+/// ArduboyTones repeat marker
+const TONES_REPEAT: u16 = 0x8001;
+/// ArduboyTones high-volume flag in the frequency word
+const TONE_HIGH_VOLUME: u16 = 0x8000;
+
+/// Supported score player libraries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreEngine {
+    ArduboyTones,
+    ArduboyPlaytune,
+}
+
+/// Data-space addresses of a score player's state variables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScorePlayer {
+    pub engine: ScoreEngine,
+    /// `bool` set while a sequence plays
+    pub playing: u16,
+    /// Pointer to the start of the sequence
+    pub start: u16,
+    /// `bool` true when the sequence lives in flash (ArduboyTones only)
+    pub in_progmem: Option<u16>,
+}
+
+/// Locate a known score player from ELF symbols.
+pub fn detect(elf: &ElfFile) -> Option<ScorePlayer> {
+    // Data symbols live at 0x800000 + data-space address. Names may be
+    // mangled (`_ZL10tonesStart`), so match on the trailing identifier.
+    let find = |ident: &str| -> Option<u16> {
+        elf.symbols.iter()
+            .filter(|(&addr, _)| addr & 0xFF_0000 == DATA_OFFSET)
+            .find(|(_, name)| {
+                // `_ZN<class><len>identE` for class statics
+                let name = name.strip_suffix('E').filter(|_| name.starts_with("_ZN")).unwrap_or(name);
+                name.strip_suffix(ident).is_some_and(|pre| {
+                    pre.is_empty() || pre.ends_with(|c: char| c.is_ascii_digit())
+                })
+            })
+            .map(|(&addr, _)| (addr & 0xFFFF) as u16)
+    };
+    if let (Some(playing), Some(start)) = (find("tonesPlaying"), find("tonesStart")) {
+        return Some(ScorePlayer {
+            engine: ScoreEngine::ArduboyTones,
+            playing,
+            start,
+            in_progmem: find("inProgmem"),
+        });
+    }
+    if let (Some(playing), Some(start)) = (find("tune_playing"), find("score_start")) {
+        return Some(ScorePlayer {
+            engine: ScoreEngine::ArduboyPlaytune,
+            playing,
+            start,
+            in_progmem: None,
+        });
+    }
+    None
+}
+
+/// One segment of a score: per-channel frequencies held for a duration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreStep {
+    /// Length in milliseconds (0 = hold until stopped)
+    pub duration_ms: u32,
+    /// Left / right channel frequency in Hz (0 = silent)
+    pub freq: [f32; 2],
+}
+
+/// A decoded note sequence.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Score {
+    pub steps: Vec<ScoreStep>,
+    /// Restart from the first step after the last one
+    pub repeat: bool,
+}
+
+impl Score {
+    /// Total length in milliseconds of one pass (ignores held steps).
+    pub fn length_ms(&self) -> u64 {
+        self.steps.iter().map(|s| s.duration_ms as u64).sum()
+    }
+}
+
+/// Decode an ArduboyTones sequence of little-endian u16 pairs.
+///
+/// The tone is placed on the left channel (Speaker 1, matching the Timer3
+/// output that ArduboyTones drives).
+pub fn decode_tones(bytes: &[u8]) -> Score {
+    decode_tones_len(bytes).0
+}
+
+/// [`decode_tones`] plus the number of bytes the sequence spans.
+fn decode_tones_len(bytes: &[u8]) -> (Score, usize) {
+    let mut score = Score::default();
+    let word = |i: usize| -> Option<u16> {
+        Some(u16::from_le_bytes([*bytes.get(i)?, *bytes.get(i + 1)?]))
+    };
+    let mut i = 0;
+    while score.steps.len() < MAX_STEPS {
+        let Some(freq) = word(i) else { break };
+        if freq == TONES_END { return (score, i + 2); }
+        if freq == TONES_REPEAT {
+            score.repeat = true;
+            return (score, i + 2);
+        }
+        let Some(dur) = word(i + 2) else { break };
+        let hz = (freq & !TONE_HIGH_VOLUME) as f32;
+        score.steps.push(ScoreStep { duration_ms: dur as u32, freq: [hz, 0.0] });
+        i += 4;
+        // Duration 0 plays forever: nothing after it is reachable
+        if dur == 0 { break; }
+    }
+    (score, i.min(bytes.len()))
+}
+
+/// Decode a Playtune score byte stream (channel 0 → left, channel 1 → right).
+pub fn decode_playtune(bytes: &[u8]) -> Score {
+    decode_playtune_len(bytes).0
+}
+
+/// [`decode_playtune`] plus the number of bytes the score spans.
+fn decode_playtune_len(bytes: &[u8]) -> (Score, usize) {
+    let mut score = Score::default();
+    let mut freq = [0.0f32; 2];
+    let mut i = 0;
+    while i < bytes.len() && score.steps.len() < MAX_STEPS {
+        let cmd = bytes[i];
+        i += 1;
+        if cmd < 0x80 {
+            let Some(&lo) = bytes.get(i) else { break };
+            i += 1;
+            let ms = ((cmd as u32) << 8) | lo as u32;
+            if ms > 0 {
+                score.steps.push(ScoreStep { duration_ms: ms, freq });
+            }
+            continue;
+        }
+        let chan = (cmd & 0x0F) as usize;
+        match cmd & 0xF0 {
+            0x90 => {
+                let Some(&note) = bytes.get(i) else { break };
+                i += 1;
+                if chan < 2 { freq[chan] = midi_hz(note & 0x7F); }
+            }
+            0x80 if chan < 2 => freq[chan] = 0.0,
+            0xE0 => {
+                score.repeat = true;
+                break;
+            }
+            0xF0 => break,
+            // Instrument / volume extensions from later Playtune versions
+            0xC0 | 0xA0 => i += 1,
+            _ => {}
+        }
+    }
+    (score, i.min(bytes.len()))
+}
+
+/// Equal-tempered frequency of a MIDI note number (69 = A4 = 440 Hz).
+pub fn midi_hz(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+// ─── Tracker ────────────────────────────────────────────────────────────────
+
+/// Playback change reported by [`ScoreTracker::poll`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScoreEvent {
+    Start(Score),
+    Stop,
+}
+
+/// Watches a score player's variables for start/stop.
+#[derive(Debug, Clone)]
+pub struct ScoreTracker {
+    pub player: ScorePlayer,
+    was_playing: bool,
+    last_start: u16,
+    last_from_flash: bool,
+    /// Bytes the current score was decoded from (SRAM scores only)
+    last_raw: Vec<u8>,
+}
+
+impl ScoreTracker {
+    pub fn new(player: ScorePlayer) -> Self {
+        ScoreTracker { player, was_playing: false, last_start: 0, last_from_flash: false, last_raw: Vec::new() }
+    }
+
+    /// Inspect the player state; call once per emulated frame.
+    pub fn poll(&mut self, ard: &Arduboy) -> Option<ScoreEvent> {
+        let data = &ard.mem.data;
+        let byte = |a: u16| data.get(a as usize).copied().unwrap_or(0);
+        let playing = byte(self.player.playing) != 0;
+        let start = u16::from_le_bytes([byte(self.player.start), byte(self.player.start.wrapping_add(1))]);
+
+        if !playing {
+            let was = self.was_playing;
+            self.was_playing = false;
+            return if was { Some(ScoreEvent::Stop) } else { None };
+        }
+
+        let from_flash = match self.player.in_progmem {
+            Some(a) => byte(a) != 0,
+            None => true,
+        };
+        let src: &[u8] = if from_flash { &ard.mem.flash } else { data };
+        let tail = src.get(start as usize..).unwrap_or(&[]);
+
+        // tone() reuses one RAM buffer, so a new sequence may share the
+        // start pointer with the previous one: compare its bytes too.
+        let same = self.was_playing && start == self.last_start && from_flash == self.last_from_flash
+            && (from_flash || tail.starts_with(&self.last_raw));
+        self.was_playing = true;
+        if same {
+            return None;
+        }
+        let (score, len) = match self.player.engine {
+            ScoreEngine::ArduboyTones => decode_tones_len(tail),
+            ScoreEngine::ArduboyPlaytune => decode_playtune_len(tail),
+        };
+        self.last_start = start;
+        self.last_from_flash = from_flash;
+        self.last_raw.clear();
+        if !from_flash {
+            self.last_raw.extend_from_slice(&tail[..len]);
+        }
+        Some(ScoreEvent::Start(score))
+    }
+}
+
+// ─── Synthesizer ────────────────────────────────────────────────────────────
+
+/// Square-wave renderer for a [`Score`], producing interleaved stereo
+/// samples in real time.
+#[derive(Debug, Clone)]
+pub struct ScoreSynth {
+    score: Option<Score>,
+    step: usize,
+    /// Samples (per channel) left in the current step; None = hold
+    remaining: Option<u64>,
+    phase: [f32; 2],
+    sample_rate: u32,
+    volume: f32,
+    /// Next interleaved sample is the left channel
+    left_next: bool,
+    right_sample: f32,
+    /// Output silence and hold the playback position (emulation paused)
+    pub paused: bool,
+}
+
+impl ScoreSynth {
+    pub fn new(sample_rate: u32, volume: f32) -> Self {
+        ScoreSynth {
+            score: None, step: 0, remaining: None, phase: [0.0; 2],
+            sample_rate, volume, left_next: true, right_sample: 0.0, paused: false,
+        }
+    }
+
+    /// Begin playing `score` from its first step.
+    pub fn start(&mut self, score: Score) {
+        self.score = if score.steps.is_empty() { None } else { Some(score) };
+        self.phase = [0.0; 2];
+        self.enter_step(0);
+    }
+
+    pub fn stop(&mut self) {
+        self.score = None;
+    }
+
+    /// True while a score is loaded and not finished.
+    pub fn is_active(&self) -> bool {
+        self.score.is_some()
+    }
+
+    fn enter_step(&mut self, step: usize) {
+        self.step = step;
+        self.remaining = self.score.as_ref().and_then(|s| s.steps.get(step)).and_then(|st| {
+            (st.duration_ms > 0)
+                .then(|| (st.duration_ms as u64 * self.sample_rate as u64 / 1000).max(1))
+        });
+    }
+
+    /// Next interleaved sample (left, right, left, ...).
+    pub fn next_sample(&mut self) -> f32 {
+        if !self.left_next {
+            self.left_next = true;
+            return self.right_sample;
+        }
+        self.left_next = false;
+        let (l, r) = self.next_frame();
+        self.right_sample = r;
+        l
+    }
+
+    /// Next stereo frame.
+    pub fn next_frame(&mut self) -> (f32, f32) {
+        if self.paused {
+            return (0.0, 0.0);
+        }
+        let Some(freq) = self.score.as_ref().and_then(|s| s.steps.get(self.step)).map(|s| s.freq) else {
+            return (0.0, 0.0);
+        };
+        let mut out = [0.0f32; 2];
+        for ch in 0..2 {
+            if freq[ch] > 0.0 {
+                out[ch] = if self.phase[ch] < 0.5 { self.volume } else { -self.volume };
+                self.phase[ch] = (self.phase[ch] + freq[ch] / self.sample_rate as f32) % 1.0;
+            }
+        }
+        if let Some(rem) = self.remaining.as_mut() {
+            *rem -= 1;
+            if *rem == 0 {
+                let (len, repeat) = self.score.as_ref().map_or((0, false), |s| (s.steps.len(), s.repeat));
+                if self.step + 1 < len {
+                    self.enter_step(self.step + 1);
+                } else if repeat {
+                    self.enter_step(0);
+                } else {
+                    self.score = None;
+                }
+            }
+        }
+        (out[0], out[1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(w: &[u16]) -> Vec<u8> {
+        w.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_decode_tones() {
+        let s = decode_tones(&words(&[440, 100, 0, 50, 880 | TONE_HIGH_VOLUME, 25, TONES_REPEAT]));
+        assert!(s.repeat);
+        assert_eq!(s.steps.len(), 3);
+        assert_eq!(s.steps[1].freq, [0.0, 0.0]);
+        assert_eq!(s.steps[2].freq[0], 880.0);
+        assert_eq!(s.length_ms(), 175);
+        assert!(!decode_tones(&words(&[440, 100, TONES_END, 1, 1])).repeat);
+    }
+
+    #[test]
+    fn test_decode_playtune() {
+        // ch0 A4, ch1 A5, wait 0x0100 ms, stop ch0, wait 10 ms, end
+        let s = decode_playtune(&[0x90, 69, 0x91, 81, 0x01, 0x00, 0x80, 0x00, 10, 0xF0]);
+        assert_eq!(s.steps.len(), 2);
+        assert_eq!(s.steps[0], ScoreStep { duration_ms: 256, freq: [440.0, 880.0] });
+        assert_eq!(s.steps[1].freq, [0.0, 880.0]);
+        assert!(!s.repeat);
+    }
+
+    #[test]
+    fn test_synth_timing() {
+        let mut syn = ScoreSynth::new(1000, 0.5);
+        syn.start(Score {
+            steps: vec![
+                ScoreStep { duration_ms: 10, freq: [250.0, 0.0] },
+                ScoreStep { duration_ms: 5, freq: [0.0, 0.0] },
+            ],
+            repeat: false,
+        });
+        let frames: Vec<(f32, f32)> = (0..15).map(|_| syn.next_frame()).collect();
+        // 250 Hz at 1 kHz: two samples high, two low
+        assert_eq!(frames[0], (0.5, 0.0));
+        assert_eq!(frames[2], (-0.5, 0.0));
+        assert_eq!(frames[12], (0.0, 0.0));
+        assert!(!syn.is_active());
+    }
+
+    #[test]
+    fn test_tracker_start_stop() {
+        let mut ard = Arduboy::new();
+        let player = ScorePlayer {
+            engine: ScoreEngine::ArduboyTones,
+            playing: 0x200, start: 0x202, in_progmem: Some(0x204),
+        };
+        let seq = words(&[523, 200, TONES_END]);
+        ard.mem.flash[0x1000..0x1000 + seq.len()].copy_from_slice(&seq);
+        let mut t = ScoreTracker::new(player);
+        assert_eq!(t.poll(&ard), None);
+        ard.mem.data[0x202] = 0x00;
+        ard.mem.data[0x203] = 0x10;
+        ard.mem.data[0x204] = 1;
+        ard.mem.data[0x200] = 1;
+        match t.poll(&ard) {
+            Some(ScoreEvent::Start(s)) => assert_eq!(s.steps[0].freq[0], 523.0),
+            other => panic!("expected start, got {:?}", other),
+        }
+        assert_eq!(t.poll(&ard), None);
+        ard.mem.data[0x200] = 0;
+        assert_eq!(t.poll(&ard), Some(ScoreEvent::Stop));
+    }
+
+    #[test]
+    fn test_tracker_ram_buffer() {
+        let mut ard = Arduboy::new();
+        // Pointer at the top of the address space must not overflow
+        let mut t = ScoreTracker::new(ScorePlayer {
+            engine: ScoreEngine::ArduboyTones, playing: 0x200, start: 0xFFFF, in_progmem: Some(0x204),
+        });
+        ard.mem.data[0x200] = 1;
+        assert!(matches!(t.poll(&ard), Some(ScoreEvent::Start(_))));
+
+        // tone() rewrites the same SRAM buffer: a new note restarts the score
+        t.player.start = 0x202;
+        ard.mem.data[0x202..0x204].copy_from_slice(&0x300u16.to_le_bytes());
+        ard.mem.data[0x300..0x306].copy_from_slice(&words(&[440, 100, TONES_END]));
+        assert!(matches!(t.poll(&ard), Some(ScoreEvent::Start(_))));
+        assert_eq!(t.poll(&ard), None);
+        ard.mem.data[0x300..0x302].copy_from_slice(&880u16.to_le_bytes());
+        match t.poll(&ard) {
+            Some(ScoreEvent::Start(s)) => assert_eq!(s.steps[0].freq[0], 880.0),
+            other => panic!("expected restart, got {:?}", other),
+        }
+    }
+}
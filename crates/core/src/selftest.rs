@@ -0,0 +1,500 @@
+//! Generated CPU conformance ROM.
+//!
+//! [`build`] assembles an ATmega32u4 program that runs every ALU, multiply
+//! and word-arithmetic instruction over a grid of edge-case operands and
+//! incoming SREG values, comparing the result registers and SREG against a
+//! reference model written independently of [`cpu`](crate::cpu) from the AVR
+//! instruction set manual. Results go out on the USB serial channel:
+//!
+//! ```text
+//! Fxxxx      case xxxx (hex index) produced a wrong result or flag
+//! DONE       all cases ran
+//! ```
+//!
+//! The ROM is self-contained (no interrupts, no peripherals beyond UEDATX),
+//! so it also runs on real hardware: [`SelfTest::to_hex`] writes it out, and
+//! `arduboy-emu --selftest` runs it headless.
+//!
+//! Covered: ADD ADC SUB SBC AND OR EOR CP CPC MOV, SUBI SBCI ANDI ORI CPI,
+//! COM NEG SWAP INC DEC ASR LSR ROR, ADIW SBIW, MUL MULS MULSU FMUL FMULS
+//! FMULSU.
+
+use crate::Arduboy;
+
+// SREG bits
+const C: u8 = 1 << 0;
+const Z: u8 = 1 << 1;
+const N: u8 = 1 << 2;
+const V: u8 = 1 << 3;
+const S: u8 = 1 << 4;
+const H: u8 = 1 << 5;
+
+/// Operand values: zero, one, nibble edge, signed edges, all ones
+const VALUES: [u8; 6] = [0x00, 0x01, 0x0F, 0x7F, 0x80, 0xFF];
+/// Multiply operands (smaller grid to keep the ROM within 32 KB)
+const MUL_VALUES: [u8; 5] = [0x00, 0x01, 0x7F, 0x80, 0xFF];
+/// Word operands for ADIW / SBIW
+const WORDS: [u16; 5] = [0x0000, 0x00FF, 0x7FFF, 0x8000, 0xFFFF];
+/// Incoming SREG values (I clear) rotated through the carry-free cases,
+/// so flags an instruction must preserve are seen both set and clear
+const SREG_IN: [u8; 4] = [0x00, 0x7F, 0x55, 0x2A];
+
+const SREG_IO: u8 = 0x3F;
+const UENUM: u16 = 0xE9;
+const UEDATX: u16 = 0xF1;
+
+/// One conformance case.
+#[derive(Debug, Clone)]
+pub struct Case {
+    /// Instruction and operands, e.g. `ADC 0x7F,0x01 C=1`
+    pub name: String,
+    /// SREG before the instruction
+    sreg_in: u8,
+    /// `ldi` register setup
+    setup: Vec<(u8, u8)>,
+    /// The instruction under test
+    op: u16,
+    /// Registers copied after SREG is captured, as (dst, src)
+    moves: Vec<(u8, u8)>,
+    /// Expected register values (r16..r31), SREG lands in r25
+    expect: Vec<(u8, u8)>,
+}
+
+/// An assembled self-test program.
+#[derive(Debug, Clone)]
+pub struct SelfTest {
+    /// Flash image
+    pub rom: Vec<u8>,
+    /// Cases in ROM order (index = number in `F` reports)
+    pub cases: Vec<Case>,
+}
+
+/// Outcome of a headless run.
+#[derive(Debug, Clone, Default)]
+pub struct SelfTestReport {
+    pub cases: usize,
+    /// Names of failed cases
+    pub failures: Vec<String>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+// ─── Reference model ────────────────────────────────────────────────────────
+
+fn nzs(r: u8, v: bool) -> u8 {
+    let n = r & 0x80 != 0;
+    let mut f = 0;
+    if r == 0 { f |= Z; }
+    if n { f |= N; }
+    if v { f |= V; }
+    if n ^ v { f |= S; }
+    f
+}
+
+/// Replace the flags in `mask` with `f`.
+fn merge(sreg: u8, mask: u8, f: u8) -> u8 {
+    (sreg & !mask) | (f & mask)
+}
+
+const ARITH: u8 = H | S | V | N | Z | C;
+const LOGIC: u8 = S | V | N | Z;
+
+fn add(a: u8, b: u8, c: u8, sreg: u8) -> (u8, u8) {
+    let r = a.wrapping_add(b).wrapping_add(c);
+    let mut f = nzs(r, (!(a ^ b) & (a ^ r) & 0x80) != 0);
+    if (a & 0x0F) + (b & 0x0F) + c > 0x0F { f |= H; }
+    if a as u16 + b as u16 + c as u16 > 0xFF { f |= C; }
+    (r, merge(sreg, ARITH, f))
+}
+
+/// Subtract with borrow; `chain_z` keeps Z only if it was already set (SBC, CPC).
+fn sub(a: u8, b: u8, c: u8, sreg: u8, chain_z: bool) -> (u8, u8) {
+    let r = a.wrapping_sub(b).wrapping_sub(c);
+    let mut f = nzs(r, ((a ^ b) & (a ^ r) & 0x80) != 0);
+    if (a & 0x0F) < (b & 0x0F) + c { f |= H; }
+    if (a as u16) < b as u16 + c as u16 { f |= C; }
+    if chain_z && sreg & Z == 0 { f &= !Z; }
+    (r, merge(sreg, ARITH, f))
+}
+
+fn logic(r: u8, sreg: u8) -> (u8, u8) {
+    (r, merge(sreg, LOGIC, nzs(r, false)))
+}
+
+/// Shift-right flags: C = bit 0 of the operand, V = N ^ C.
+fn shift(a: u8, r: u8, sreg: u8) -> (u8, u8) {
+    let c = a & 1 != 0;
+    let n = r & 0x80 != 0;
+    let mut f = nzs(r, n ^ c);
+    if c { f |= C; }
+    (r, merge(sreg, LOGIC | C, f))
+}
+
+/// Multiply flags: C = bit 15 of the unshifted product, Z on the result.
+fn mul_flags(product: u16, result: u16, sreg: u8) -> u8 {
+    let mut f = 0;
+    if product & 0x8000 != 0 { f |= C; }
+    if result == 0 { f |= Z; }
+    merge(sreg, Z | C, f)
+}
+
+// ─── Encoding ───────────────────────────────────────────────────────────────
+
+pub(crate) fn two_reg(base: u16, d: u8, r: u8) -> u16 {
+    base | ((r as u16 & 0x10) << 5) | ((d as u16) << 4) | (r as u16 & 0x0F)
+}
+
+pub(crate) fn imm(base: u16, d: u8, k: u8) -> u16 {
+    base | ((k as u16 & 0xF0) << 4) | (((d - 16) as u16) << 4) | (k as u16 & 0x0F)
+}
+
+pub(crate) fn ldi(d: u8, k: u8) -> u16 { imm(0xE000, d, k) }
+fn cpi(d: u8, k: u8) -> u16 { imm(0x3000, d, k) }
+fn one_reg(x: u16, d: u8) -> u16 { 0x9400 | ((d as u16) << 4) | x }
+pub(crate) fn io(base: u16, a: u8, r: u8) -> u16 {
+    base | ((a as u16 & 0x30) << 5) | ((r as u16) << 4) | (a as u16 & 0x0F)
+}
+pub(crate) fn mov(d: u8, r: u8) -> u16 { two_reg(0x2C00, d, r) }
+pub(crate) fn brne(k: i16) -> u16 { 0xF401 | (((k as u16) & 0x7F) << 3) }
+fn brlo(k: i16) -> u16 { 0xF000 | (((k as u16) & 0x7F) << 3) }
+pub(crate) fn rjmp(k: i16) -> u16 { 0xC000 | ((k as u16) & 0x0FFF) }
+fn sts(addr: u16, r: u8) -> [u16; 2] { [0x9200 | ((r as u16) << 4), addr] }
+fn call(k: u16) -> [u16; 2] { [0x940E, k] }
+pub(crate) const RET: u16 = 0x9508;
+
+// ─── Case table ─────────────────────────────────────────────────────────────
+
+fn cases() -> Vec<Case> {
+    let mut out = Vec::new();
+    let mut rot = 0;
+    let mut next_sreg = || {
+        rot += 1;
+        SREG_IN[rot % SREG_IN.len()]
+    };
+    let bin = |name: &str, op: u16, a: u8, b: u8, sreg: u8, r: u8, f: u8, carry: bool| Case {
+        name: if carry {
+            format!("{} 0x{:02X},0x{:02X} C={}", name, a, b, sreg & C)
+        } else {
+            format!("{} 0x{:02X},0x{:02X}", name, a, b)
+        },
+        sreg_in: sreg,
+        setup: vec![(16, a), (17, b)],
+        op,
+        moves: vec![],
+        expect: vec![(16, r), (25, f)],
+    };
+
+    // Register-register ALU: Rd = r16, Rr = r17
+    type Alu = fn(u8, u8, u8) -> (u8, u8);
+    let plain: [(&str, u16, Alu); 7] = [
+        ("ADD", 0x0C00, |a, b, s| add(a, b, 0, s)),
+        ("SUB", 0x1800, |a, b, s| sub(a, b, 0, s, false)),
+        ("AND", 0x2000, |a, b, s| logic(a & b, s)),
+        ("OR", 0x2800, |a, b, s| logic(a | b, s)),
+        ("EOR", 0x2400, |a, b, s| logic(a ^ b, s)),
+        ("CP", 0x1400, |a, b, s| (a, sub(a, b, 0, s, false).1)),
+        ("MOV", 0x2C00, |_, b, s| (b, s)),
+    ];
+    for (name, base, f) in plain {
+        for &a in &VALUES {
+            for &b in &VALUES {
+                let s = next_sreg();
+                let (r, fl) = f(a, b, s);
+                out.push(bin(name, two_reg(base, 16, 17), a, b, s, r, fl, false));
+            }
+        }
+    }
+    // Carry-in variants, Z set on entry to exercise the SBC/CPC chaining rule
+    let carry: [(&str, u16, Alu); 3] = [
+        ("ADC", 0x1C00, |a, b, s| add(a, b, s & C, s)),
+        ("SBC", 0x0800, |a, b, s| sub(a, b, s & C, s, true)),
+        ("CPC", 0x0400, |a, b, s| (a, sub(a, b, s & C, s, true).1)),
+    ];
+    for (name, base, f) in carry {
+        for &a in &VALUES {
+            for &b in &VALUES {
+                for s in [Z, C | Z] {
+                    let (r, fl) = f(a, b, s);
+                    out.push(bin(name, two_reg(base, 16, 17), a, b, s, r, fl, true));
+                }
+            }
+        }
+    }
+
+    // Immediate forms on r16
+    let imms: [(&str, u16, Alu, bool); 5] = [
+        ("SUBI", 0x5000, |a, k, s| sub(a, k, 0, s, false), false),
+        ("SBCI", 0x4000, |a, k, s| sub(a, k, s & C, s, true), true),
+        ("ANDI", 0x7000, |a, k, s| logic(a & k, s), false),
+        ("ORI", 0x6000, |a, k, s| logic(a | k, s), false),
+        ("CPI", 0x3000, |a, k, s| (a, sub(a, k, 0, s, false).1), false),
+    ];
+    for (name, base, f, with_carry) in imms {
+        for &a in &VALUES {
+            for &k in &VALUES {
+                let sregs: &[u8] = if with_carry { &[Z, C | Z] } else { &[0] };
+                for &s in sregs {
+                    let s = if with_carry { s } else { next_sreg() };
+                    let (r, fl) = f(a, k, s);
+                    let mut c = bin(name, imm(base, 16, k), a, k, s, r, fl, with_carry);
+                    c.setup.truncate(1);
+                    out.push(c);
+                }
+            }
+        }
+    }
+
+    // Single-register ops on r16
+    type Unary = fn(u8, u8) -> (u8, u8);
+    let unary: [(&str, u16, Unary); 8] = [
+        ("COM", 0x0, |a, s| { let r = !a; (r, merge(s, LOGIC | C, nzs(r, false) | C)) }),
+        ("NEG", 0x1, |a, s| {
+            let r = a.wrapping_neg();
+            let mut f = nzs(r, r == 0x80);
+            if r != 0 { f |= C; }
+            if (r | a) & 0x08 != 0 { f |= H; }
+            (r, merge(s, ARITH, f))
+        }),
+        ("SWAP", 0x2, |a, s| (a.rotate_left(4), s)),
+        ("INC", 0x3, |a, s| { let r = a.wrapping_add(1); (r, merge(s, LOGIC, nzs(r, a == 0x7F))) }),
+        ("DEC", 0xA, |a, s| { let r = a.wrapping_sub(1); (r, merge(s, LOGIC, nzs(r, a == 0x80))) }),
+        ("ASR", 0x5, |a, s| shift(a, ((a as i8) >> 1) as u8, s)),
+        ("LSR", 0x6, |a, s| shift(a, a >> 1, s)),
+        ("ROR", 0x7, |a, s| shift(a, (a >> 1) | ((s & C) << 7), s)),
+    ];
+    for (name, x, f) in unary {
+        for &a in &VALUES {
+            let sregs: Vec<u8> = if name == "ROR" { vec![0, C] } else { vec![next_sreg()] };
+            for s in sregs {
+                let (r, fl) = f(a, s);
+                out.push(Case {
+                    name: if name == "ROR" {
+                        format!("{} 0x{:02X} C={}", name, a, s & C)
+                    } else {
+                        format!("{} 0x{:02X}", name, a)
+                    },
+                    sreg_in: s,
+                    setup: vec![(16, a)],
+                    op: one_reg(x, 16),
+                    moves: vec![],
+                    expect: vec![(16, r), (25, fl)],
+                });
+            }
+        }
+    }
+
+    // Word arithmetic on X (r27:r26)
+    for (name, base) in [("ADIW", 0x9600u16), ("SBIW", 0x9700)] {
+        for &w in &WORDS {
+            for k in [0u8, 1, 63] {
+                let s = next_sreg();
+                let r = if base == 0x9600 { w.wrapping_add(k as u16) } else { w.wrapping_sub(k as u16) };
+                let (rh, wh) = (r & 0x8000 != 0, w & 0x8000 != 0);
+                let (v, c) = if base == 0x9600 { (!wh && rh, !rh && wh) } else { (wh && !rh, rh && !wh) };
+                let mut f = if rh { N } else { 0 };
+                if r == 0 { f |= Z; }
+                if v { f |= V; }
+                if c { f |= C; }
+                if rh ^ v { f |= S; }
+                out.push(Case {
+                    name: format!("{} 0x{:04X},{}", name, w, k),
+                    sreg_in: s,
+                    setup: vec![(26, w as u8), (27, (w >> 8) as u8)],
+                    op: base | ((k as u16 & 0x30) << 2) | (1 << 4) | (k as u16 & 0x0F),
+                    moves: vec![],
+                    expect: vec![(26, r as u8), (27, (r >> 8) as u8), (25, merge(s, LOGIC | C, f))],
+                });
+            }
+        }
+    }
+
+    // Multiplies: r1:r0 copied to r18:r19 for checking
+    type Mul = fn(u8, u8) -> (u16, u16);
+    let muls: [(&str, u16, Mul); 6] = [
+        ("MUL", two_reg(0x9C00, 16, 17), |a, b| { let p = a as u16 * b as u16; (p, p) }),
+        ("MULS", 0x0200, |a, b| { let p = (a as i8 as i16 * b as i8 as i16) as u16; (p, p) }),
+        ("MULSU", 0x0300, |a, b| { let p = (a as i8 as i16).wrapping_mul(b as i16) as u16; (p, p) }),
+        ("FMUL", 0x0308, |a, b| { let p = a as u16 * b as u16; (p, p << 1) }),
+        ("FMULS", 0x0380, |a, b| {
+            let p = (a as i8 as i16).wrapping_mul(b as i8 as i16) as u16;
+            (p, p << 1)
+        }),
+        ("FMULSU", 0x0388, |a, b| {
+            let p = (a as i8 as i16).wrapping_mul(b as i16) as u16;
+            (p, p << 1)
+        }),
+    ];
+    for (name, base, f) in muls {
+        // MULS/MULSU/FMUL* encode r16..r23 relative to r16: d = 16, r = 17
+        let op = if name == "MUL" { base } else { base | 0x01 };
+        for &a in &MUL_VALUES {
+            for &b in &MUL_VALUES {
+                let s = next_sreg();
+                let (p, r) = f(a, b);
+                out.push(Case {
+                    name: format!("{} 0x{:02X},0x{:02X}", name, a, b),
+                    sreg_in: s,
+                    setup: vec![(16, a), (17, b)],
+                    op,
+                    moves: vec![(18, 0), (19, 1)],
+                    expect: vec![(18, r as u8), (19, (r >> 8) as u8), (25, mul_flags(p, r, s))],
+                });
+            }
+        }
+    }
+    out
+}
+
+// ─── Assembly ───────────────────────────────────────────────────────────────
+
+/// Assemble the self-test ROM.
+pub fn build() -> SelfTest {
+    let cases = cases();
+    let mut w: Vec<u16> = Vec::new();
+
+    // 0: jump over the subroutines
+    w.push(0); // patched below
+
+    // report: send "F" + r31:r30 as four hex digits + "\n"
+    let report = w.len() as u16;
+    w.push(ldi(24, b'F'));
+    w.extend(sts(UEDATX, 24));
+    let hexnib_call = |w: &mut Vec<u16>, src: u8, swap: bool, hexnib: u16| {
+        w.push(mov(24, src));
+        if swap { w.push(one_reg(0x2, 24)); }
+        w.extend(call(hexnib));
+    };
+    // hexnib follows report: 3 words, four digits (two swapped), 4 words
+    let hexnib = report + 3 + 4 * 3 + 2 + 4;
+    hexnib_call(&mut w, 31, true, hexnib);
+    hexnib_call(&mut w, 31, false, hexnib);
+    hexnib_call(&mut w, 30, true, hexnib);
+    hexnib_call(&mut w, 30, false, hexnib);
+    w.push(ldi(24, b'\n'));
+    w.extend(sts(UEDATX, 24));
+    w.push(RET);
+    debug_assert_eq!(w.len() as u16, hexnib);
+
+    // hexnib: r24 low nibble → ASCII hex digit on UEDATX
+    w.push(imm(0x7000, 24, 0x0F)); // andi r24, 0x0F
+    w.push(cpi(24, 10));
+    w.push(brlo(1));
+    w.push(imm(0x5000, 24, (-7i8) as u8)); // subi r24, -7
+    w.push(imm(0x5000, 24, (-48i8) as u8)); // subi r24, -'0'
+    w.extend(sts(UEDATX, 24));
+    w.push(RET);
+
+    // main: select the CDC IN endpoint
+    let main = w.len() as u16;
+    w[0] = rjmp(main as i16 - 1);
+    w.push(ldi(24, 3));
+    w.extend(sts(UENUM, 24));
+
+    for (i, c) in cases.iter().enumerate() {
+        w.push(ldi(24, c.sreg_in));
+        w.push(io(0xB800, SREG_IO, 24)); // out SREG, r24
+        for &(r, v) in &c.setup {
+            w.push(ldi(r, v));
+        }
+        w.push(c.op);
+        w.push(io(0xB000, SREG_IO, 25)); // in r25, SREG
+        for &(d, s) in &c.moves {
+            w.push(mov(d, s));
+        }
+        let n = c.expect.len() as i16;
+        for (j, &(r, v)) in c.expect.iter().enumerate() {
+            w.push(cpi(r, v));
+            w.push(brne(2 * (n - j as i16) - 1));
+        }
+        w.push(rjmp(4));
+        w.push(ldi(30, i as u8));
+        w.push(ldi(31, (i >> 8) as u8));
+        w.extend(call(report));
+    }
+
+    // "DONE\n", then spin
+    for &b in b"DONE\n" {
+        w.push(ldi(24, b));
+        w.extend(sts(UEDATX, 24));
+    }
+    w.push(rjmp(-1));
+
+    let rom = w.iter().flat_map(|x| x.to_le_bytes()).collect();
+    SelfTest { rom, cases }
+}
+
+impl SelfTest {
+    /// Load the ROM and run until it reports `DONE` or `max_frames` pass.
+    pub fn run(&self, ard: &mut Arduboy, max_frames: u32) -> Result<SelfTestReport, String> {
+        if self.rom.len() > ard.mem.flash.len() {
+            return Err(format!("self-test ROM is {} bytes, flash holds {}", self.rom.len(), ard.mem.flash.len()));
+        }
+        ard.mem.flash.fill(0xFF);
+        ard.mem.flash[..self.rom.len()].copy_from_slice(&self.rom);
+        ard.reset();
+        let mut out = Vec::new();
+        for _ in 0..max_frames {
+            ard.run_frame();
+            out.extend(ard.take_serial_output());
+            if out.ends_with(b"DONE\n") {
+                return Ok(self.parse_output(&String::from_utf8_lossy(&out)));
+            }
+        }
+        Err(format!("self-test did not finish in {} frames (serial: {:?})",
+            max_frames, String::from_utf8_lossy(&out)))
+    }
+
+    /// Map `F` lines of serial output to case names.
+    pub fn parse_output(&self, text: &str) -> SelfTestReport {
+        let failures = text.lines()
+            .filter_map(|l| l.strip_prefix('F'))
+            .map(|idx| match u16::from_str_radix(idx, 16).ok().and_then(|i| self.cases.get(i as usize)) {
+                Some(c) => c.name.clone(),
+                None => format!("unknown case '{}'", idx),
+            })
+            .collect();
+        SelfTestReport { cases: self.cases.len(), failures }
+    }
+
+    /// Intel HEX image of the ROM, for flashing real hardware.
+    pub fn to_hex(&self) -> String {
+        crate::hex::to_hex(&self.rom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_model() {
+        assert_eq!(add(0x7F, 0x01, 0, 0), (0x80, H | V | N));
+        assert_eq!(add(0xFF, 0x01, 0, 0), (0x00, H | Z | C));
+        assert_eq!(sub(0x00, 0x01, 0, 0, false), (0xFF, H | S | N | C));
+        assert_eq!(sub(0x01, 0x01, 0, 0, true), (0x00, 0), "SBC keeps Z clear");
+        assert_eq!(shift(0x01, 0x00, 0).1, Z | C | V | S);
+    }
+
+    #[test]
+    fn test_selftest_rom_passes() {
+        let st = build();
+        assert!(st.rom.len() <= crate::FLASH_SIZE);
+        let mut ard = Arduboy::new();
+        let report = st.run(&mut ard, 60).unwrap();
+        assert!(report.cases > 500);
+        assert!(report.passed(), "{} of {} cases failed: {:?}",
+            report.failures.len(), report.cases, &report.failures[..report.failures.len().min(20)]);
+    }
+
+    #[test]
+    fn test_selftest_reports_failures() {
+        let st = build();
+        let report = st.parse_output("F0000\nF0003\nDONE\n");
+        assert_eq!(report.failures, vec![st.cases[0].name.clone(), st.cases[3].name.clone()]);
+        assert!(st.to_hex().ends_with(":00000001FF\n"));
+    }
+}
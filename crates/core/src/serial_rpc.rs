@@ -0,0 +1,219 @@
+//! In-band control channel carried on the serial port.
+//!
+//! Test builds of games can drive the emulator by printing OSC-style escape
+//! sequences to `Serial`:
+//!
+//! ```text
+//! ESC ] emu;<command>[;<argument>] BEL      (BEL = 0x07, or ST = ESC \)
+//! ```
+//!
+//! | Command      | Argument          | Effect                                   |
+//! |--------------|-------------------|------------------------------------------|
+//! | `quit`       | exit code (def 0) | Terminate the emulator with that code    |
+//! | `screenshot` | file name (opt.)  | Save a PNG of the current frame          |
+//! | `title`      | text              | Set the window title                     |
+//! | `checkpoint` | name              | Log a named test checkpoint              |
+//!
+//! Example (Arduino): `Serial.print("\e]emu;quit;3\a");`
+//!
+//! [`RpcParser`] strips recognised sequences from the byte stream and passes
+//! everything else through unchanged, so normal serial output still reaches
+//! stderr or the host bridge.
+
+/// A command decoded from the serial stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RpcCommand {
+    /// Terminate the emulator with the given exit code
+    Quit(i32),
+    /// Save a screenshot (optional file name)
+    Screenshot(Option<String>),
+    /// Set the window title
+    SetTitle(String),
+    /// Named test checkpoint
+    Checkpoint(String),
+}
+
+const ESC: u8 = 0x1B;
+const BEL: u8 = 0x07;
+/// OSC payload prefix that marks an emulator command
+const PREFIX: &[u8] = b"emu;";
+/// Longest payload buffered before the sequence is treated as plain output
+const MAX_PAYLOAD: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Text,
+    /// Saw ESC
+    Esc,
+    /// Inside `ESC ]`, collecting payload
+    Osc,
+    /// Saw ESC inside the payload (possible ST terminator)
+    OscEsc,
+}
+
+/// Streaming escape-sequence parser. Sequences may span multiple `feed` calls.
+#[derive(Debug)]
+pub struct RpcParser {
+    state: State,
+    payload: Vec<u8>,
+}
+
+impl RpcParser {
+    pub fn new() -> Self {
+        RpcParser { state: State::Text, payload: Vec::new() }
+    }
+
+    /// Process serial output bytes.
+    ///
+    /// Returns the pass-through bytes (everything that is not an `emu;`
+    /// command) and the commands found, in order.
+    pub fn feed(&mut self, data: &[u8]) -> (Vec<u8>, Vec<RpcCommand>) {
+        let mut out = Vec::with_capacity(data.len());
+        let mut cmds = Vec::new();
+        for &b in data {
+            match self.state {
+                State::Text => {
+                    if b == ESC { self.state = State::Esc; } else { out.push(b); }
+                }
+                State::Esc => self.after_esc(b, &mut out),
+                State::Osc => {
+                    if b == BEL {
+                        self.finish(&mut out, &mut cmds, &[BEL]);
+                    } else if b == ESC {
+                        self.state = State::OscEsc;
+                    } else {
+                        self.payload.push(b);
+                        if self.payload.len() > MAX_PAYLOAD
+                            || (self.payload.len() <= PREFIX.len()
+                                && !PREFIX.starts_with(&self.payload))
+                        {
+                            // Not ours: hand the sequence back unchanged
+                            out.extend_from_slice(&[ESC, b']']);
+                            out.append(&mut self.payload);
+                            self.state = State::Text;
+                        }
+                    }
+                }
+                State::OscEsc => {
+                    if b == b'\\' {
+                        self.finish(&mut out, &mut cmds, &[ESC, b'\\']);
+                    } else {
+                        // Abandoned sequence: emit it, then treat the ESC afresh
+                        out.extend_from_slice(&[ESC, b']']);
+                        out.append(&mut self.payload);
+                        self.after_esc(b, &mut out);
+                    }
+                }
+            }
+        }
+        (out, cmds)
+    }
+
+    /// Handle the byte following an ESC outside a payload.
+    fn after_esc(&mut self, b: u8, out: &mut Vec<u8>) {
+        if b == b']' {
+            self.state = State::Osc;
+            self.payload.clear();
+        } else if b == ESC {
+            out.push(ESC);
+            self.state = State::Esc;
+        } else {
+            out.extend_from_slice(&[ESC, b]);
+            self.state = State::Text;
+        }
+    }
+
+    /// Complete an OSC sequence; unrecognised commands pass through verbatim.
+    fn finish(&mut self, out: &mut Vec<u8>, cmds: &mut Vec<RpcCommand>, term: &[u8]) {
+        match parse_command(&self.payload) {
+            Some(cmd) => cmds.push(cmd),
+            None => {
+                out.extend_from_slice(&[ESC, b']']);
+                out.extend_from_slice(&self.payload);
+                out.extend_from_slice(term);
+            }
+        }
+        self.payload.clear();
+        self.state = State::Text;
+    }
+}
+
+impl Default for RpcParser {
+    fn default() -> Self { Self::new() }
+}
+
+/// Decode an `emu;<command>[;<argument>]` payload.
+fn parse_command(payload: &[u8]) -> Option<RpcCommand> {
+    let body = payload.strip_prefix(PREFIX)?;
+    let text = String::from_utf8_lossy(body);
+    let (cmd, arg) = match text.split_once(';') {
+        Some((c, a)) => (c, Some(a)),
+        None => (text.as_ref(), None),
+    };
+    match cmd {
+        "quit" | "exit" => {
+            let code = arg.and_then(|a| a.trim().parse().ok()).unwrap_or(0);
+            Some(RpcCommand::Quit(code))
+        }
+        "screenshot" => Some(RpcCommand::Screenshot(
+            arg.filter(|a| !a.is_empty()).map(|a| a.to_string()))),
+        "title" => Some(RpcCommand::SetTitle(arg.unwrap_or("").to_string())),
+        "checkpoint" => Some(RpcCommand::Checkpoint(arg.unwrap_or("").to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_passthrough() {
+        let mut p = RpcParser::new();
+        let (out, cmds) = p.feed(b"score=10\r\n");
+        assert_eq!(out, b"score=10\r\n");
+        assert!(cmds.is_empty());
+    }
+
+    #[test]
+    fn test_commands() {
+        let mut p = RpcParser::new();
+        let (out, cmds) = p.feed(b"a\x1b]emu;checkpoint;boot\x07b\x1b]emu;quit;3\x1b\\");
+        assert_eq!(out, b"ab");
+        assert_eq!(cmds, vec![
+            RpcCommand::Checkpoint("boot".into()),
+            RpcCommand::Quit(3),
+        ]);
+    }
+
+    #[test]
+    fn test_split_across_feeds() {
+        let mut p = RpcParser::new();
+        let (out1, c1) = p.feed(b"x\x1b]emu;tit");
+        let (out2, c2) = p.feed(b"le;My Game\x07");
+        assert_eq!(out1, b"x");
+        assert!(out2.is_empty());
+        assert!(c1.is_empty());
+        assert_eq!(c2, vec![RpcCommand::SetTitle("My Game".into())]);
+    }
+
+    #[test]
+    fn test_foreign_sequences_untouched() {
+        let mut p = RpcParser::new();
+        let input = b"\x1b[2J\x1b]0;xterm title\x07\x1b]emu;bogus\x07";
+        let (out, cmds) = p.feed(input);
+        assert_eq!(out, input.to_vec());
+        assert!(cmds.is_empty());
+    }
+
+    #[test]
+    fn test_screenshot_and_default_quit() {
+        let mut p = RpcParser::new();
+        let (_, cmds) = p.feed(b"\x1b]emu;screenshot\x07\x1b]emu;screenshot;a.png\x07\x1b]emu;quit\x07");
+        assert_eq!(cmds, vec![
+            RpcCommand::Screenshot(None),
+            RpcCommand::Screenshot(Some("a.png".into())),
+            RpcCommand::Quit(0),
+        ]);
+    }
+}
@@ -0,0 +1,132 @@
+//! Shadow call stack for return-address corruption detection.
+//!
+//! Every `CALL`/`RCALL`/`ICALL`/`EICALL` and interrupt entry records the
+//! return address it pushed together with the stack pointer after the push.
+//! When `RET`/`RETI` executes, the address it is about to pop is compared
+//! with the shadow entry for the same stack slot:
+//!
+//! | Situation at `RET` | Result |
+//! |--------------------|--------|
+//! | Entry at this SP, same address | Normal return, entry popped |
+//! | Entry at this SP, different address | Violation: the return address was overwritten |
+//! | Entries below this SP | Dropped first (`longjmp`, stack reset) |
+//! | No entry at this SP | Not checked (`push`/`push`/`ret` jumps, calls made before enabling) |
+//!
+//! A buffer overflow on the stack that reaches the saved return address is
+//! caught at the `RET` that would otherwise jump into the weeds. Each `RET`
+//! PC is reported once, into the shared [`Diagnostics`] sink. With
+//! [`ShadowStack::break_on_mismatch`] set, the core also stops the frame
+//! like a breakpoint, right after the bad return.
+
+use crate::diagnostics::Diagnostics;
+use std::collections::HashSet;
+
+/// Entries kept before the oldest are discarded (stack resets without a return).
+const MAX_DEPTH: usize = 256;
+
+/// A return address pushed by a call or an interrupt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Stack pointer after the push
+    pub sp: u16,
+    /// Flash word address pushed
+    pub ret: u32,
+    /// Flash word address of the call, or the vector for interrupts
+    pub from: u32,
+    /// Pushed by interrupt entry rather than a call instruction
+    pub isr: bool,
+}
+
+/// Shadow stack state and reported violations.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowStack {
+    /// Tracking calls and checking returns
+    pub enabled: bool,
+    /// Stop the frame on a mismatch
+    pub break_on_mismatch: bool,
+    frames: Vec<Frame>,
+    /// Mismatches seen, including repeats from reported PCs
+    pub violations: u64,
+    reported: HashSet<u32>,
+}
+
+impl ShadowStack {
+    /// Record a return address `ret` pushed at `from`, leaving the stack pointer at `sp`.
+    pub fn push(&mut self, sp: u16, ret: u32, from: u32, isr: bool) {
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(Frame { sp, ret, from, isr });
+    }
+
+    /// Check the return at `pc` with the stack pointer at `sp` (before the
+    /// pop) and `target` the address it will pop.
+    pub fn ret(&mut self, pc: u32, sp: u16, target: u32, reti: bool, frame: u32, out: &mut Diagnostics) {
+        while self.frames.last().is_some_and(|f| f.sp < sp) {
+            self.frames.pop();
+        }
+        if self.frames.last().is_none_or(|f| f.sp != sp) {
+            return;
+        }
+        let Some(f) = self.frames.pop() else { return };
+        if f.ret == target {
+            return;
+        }
+        self.violations += 1;
+        if self.break_on_mismatch {
+            out.trip();
+        }
+        if self.reported.insert(pc) {
+            let origin = if f.isr {
+                format!("interrupt vector 0x{:04X}", f.from * 2)
+            } else {
+                format!("call at 0x{:04X}", f.from * 2)
+            };
+            out.report(format!(
+                "Shadow stack: {} at PC=0x{:04X} frame {} returns to 0x{:04X}, expected 0x{:04X} ({}, SP=0x{:04X})",
+                if reti { "RETI" } else { "RET" }, pc * 2, frame,
+                target * 2, f.ret * 2, origin, sp));
+        }
+    }
+
+    /// Current shadow frames, innermost last.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Forget all frames (CPU reset).
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mismatch_and_unwind() {
+        let mut s = ShadowStack { enabled: true, break_on_mismatch: true, ..Default::default() };
+        let mut out = Diagnostics::default();
+        s.push(0x0AFD, 0x0102, 0x0100, false);
+        s.push(0x0AF0, 0x0202, 0x0200, false);
+        s.ret(0x0300, 0x0AF0, 0x0202, false, 1, &mut out);
+        assert!(out.take_messages().is_empty());
+
+        // Overwritten return address
+        s.push(0x0AF0, 0x0202, 0x0200, false);
+        s.ret(0x0300, 0x0AF0, 0x4141, false, 2, &mut out);
+        assert_eq!(s.violations, 1);
+        assert!(out.take_trip() && !out.take_trip());
+        assert_eq!(out.take_messages(), vec![
+            "Shadow stack: RET at PC=0x0600 frame 2 returns to 0x8282, expected 0x0404 (call at 0x0400, SP=0x0AF0)",
+        ]);
+
+        // longjmp past an inner frame, then a return with no shadow entry
+        s.push(0x0AE0, 0x0302, 0x0300, false);
+        s.ret(0x0180, 0x0AFD, 0x0102, false, 3, &mut out);
+        assert!(s.frames().is_empty());
+        s.ret(0x0180, 0x0AFD, 0x1234, false, 3, &mut out);
+        assert_eq!(s.violations, 1);
+    }
+}
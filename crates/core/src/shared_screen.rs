@@ -0,0 +1,143 @@
+//! Screen handle for render threads.
+//!
+//! GUI frameworks that draw on their own thread (egui, iced, bevy) need the
+//! latest screen without copying it out of the emulator every frame or
+//! holding up emulation. [`Arduboy::share_screen`] returns a [`SharedScreen`]
+//! that any thread can clone and read; at the end of every frame the
+//! emulator fills a private back buffer and swaps it with the shared one.
+//!
+//! | Side | Cost per frame |
+//! |------|----------------|
+//! | Emulator | fill the back buffer; swap if no reader holds the lock |
+//! | Reader | none until it reads; [`SharedScreen::frame`] is lock-free |
+//!
+//! The emulator never waits: if a reader is holding the screen when a frame
+//! ends, that frame is not published and the reader sees the next one.
+
+use crate::{Arduboy, Rotation, SCREEN_WIDTH};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+/// One published screen.
+#[derive(Debug, Clone, Default)]
+pub struct ScreenFrame {
+    /// Visible pixels as 0xRRGGBB, `width × height`, row stride `width`,
+    /// turned by [`Arduboy::rotation`]
+    pub pixels: Vec<u32>,
+    pub width: usize,
+    pub height: usize,
+    /// Emulator frame number it was taken at
+    pub frame: u32,
+}
+
+struct Shared {
+    front: RwLock<ScreenFrame>,
+    /// Frame number of `front`, readable without the lock
+    frame: AtomicU32,
+}
+
+/// Read access to the most recently published screen; cheap to clone and
+/// safe to send to other threads.
+#[derive(Clone)]
+pub struct SharedScreen {
+    shared: Arc<Shared>,
+}
+
+impl SharedScreen {
+    /// Lock the current screen for reading. Keep the guard briefly: a frame
+    /// that ends while it is held is skipped.
+    pub fn read(&self) -> RwLockReadGuard<'_, ScreenFrame> {
+        self.shared.front.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Frame number of the current screen, to check for a new one without
+    /// locking.
+    pub fn frame(&self) -> u32 {
+        self.shared.frame.load(Ordering::Acquire)
+    }
+}
+
+/// Emulator side: back buffer and the shared front buffer.
+pub(crate) struct ScreenPublisher {
+    shared: Arc<Shared>,
+    back: ScreenFrame,
+}
+
+impl ScreenPublisher {
+    pub(crate) fn new() -> Self {
+        ScreenPublisher {
+            shared: Arc::new(Shared { front: RwLock::new(ScreenFrame::default()), frame: AtomicU32::new(0) }),
+            back: ScreenFrame::default(),
+        }
+    }
+
+    pub(crate) fn handle(&self) -> SharedScreen {
+        SharedScreen { shared: Arc::clone(&self.shared) }
+    }
+
+    /// Fill the back buffer from `arduboy` and swap it in unless a reader
+    /// holds the front buffer.
+    pub(crate) fn publish(&mut self, arduboy: &Arduboy, frame: u32) {
+        let (w, h) = arduboy.screen_size();
+        let fb = arduboy.framebuffer_rgba();
+        self.back.pixels.clear();
+        for y in 0..h {
+            for px in fb[y * SCREEN_WIDTH * 4..(y * SCREEN_WIDTH + w) * 4].chunks(4) {
+                self.back.pixels.push((px[0] as u32) << 16 | (px[1] as u32) << 8 | px[2] as u32);
+            }
+        }
+        let (w, h) = match arduboy.rotation {
+            Rotation::None => (w, h),
+            r => {
+                let (px, rw, rh) = r.rotate(&self.back.pixels, w, h);
+                self.back.pixels = px;
+                (rw, rh)
+            }
+        };
+        self.back.width = w;
+        self.back.height = h;
+        self.back.frame = frame;
+        if let Ok(mut front) = self.shared.front.try_write() {
+            std::mem::swap(&mut *front, &mut self.back);
+            self.shared.frame.store(frame, Ordering::Release);
+        }
+    }
+}
+
+impl Arduboy {
+    /// Handle to a copy of the screen published at the end of every frame.
+    /// Publishing starts with the first call; later calls share the same
+    /// buffer.
+    pub fn share_screen(&mut self) -> SharedScreen {
+        self.shared_screen.get_or_insert_with(ScreenPublisher::new).handle()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish() {
+        let mut a = Arduboy::new();
+        let screen = a.share_screen();
+        a.display.framebuffer[..4].copy_from_slice(&[0xCF, 0xCF, 0xCF, 0xFF]);
+        a.run_frame();
+        assert_eq!(screen.frame(), 1);
+        {
+            let s = screen.read();
+            assert_eq!((s.width, s.height, s.pixels.len()), (128, 64, 128 * 64));
+            assert_eq!(s.pixels[0], 0xCFCFCF);
+            // Held by a reader: the next frame is skipped, not waited for
+            a.run_frame();
+        }
+        assert_eq!(screen.frame(), 1);
+        let other = std::thread::spawn({
+            let screen = screen.clone();
+            move || screen.read().frame
+        });
+        a.run_frame();
+        assert!(other.join().unwrap() <= 3);
+        assert_eq!(screen.frame(), 3);
+    }
+}
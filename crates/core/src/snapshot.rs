@@ -1,21 +1,41 @@
 //! Emulator state snapshot for rewind functionality.
 //!
 //! Captures the minimum state needed to restore the emulator to a previous
-//! point in time. Snapshots are stored in a ring buffer, taken every N frames
-//! (default 60 = 1 second), allowing rewind of up to `capacity` seconds.
+//! point in time. Snapshots are stored in a ring buffer, taken every N frames,
+//! allowing rewind of up to `capacity × interval` frames.
+//!
+//! Consecutive snapshots differ in only a few hundred bytes, so the buffer
+//! stores them delta-compressed:
+//!
+//! | Entry | Stored as |
+//! |-------|-----------|
+//! | keyframe (every [`KEY_INTERVAL`] entries) | full state |
+//! | others | XOR against the previous entry, zero runs skipped |
+//!
+//! Each entry also keeps a small grayscale [`Thumbnail`] of its screen for
+//! the timeline scrubber. Any entry can be restored with
+//! [`RewindBuffer::get`], not just the most recent one.
 //!
 //! ## Usage
 //!
 //! ```text
-//! // GUI: hold Backspace to rewind
+//! // GUI: hold Backspace to rewind (Shift = 4×), Tab for the timeline
 //! // Step mode: `rewind` command
 //! ```
 
+use std::collections::VecDeque;
+
+/// A full keyframe is stored at least every this many entries
+pub const KEY_INTERVAL: usize = 16;
+
+/// Thumbnail downscale factor (128×64 → 32×16)
+pub const THUMB_SCALE: usize = 4;
+
 /// A frozen snapshot of emulator state.
 #[derive(Clone)]
 pub struct Snapshot {
     /// CPU: pc, sp, sreg, tick, sleeping
-    pub pc: u16,
+    pub pc: u32,
     pub sp: u16,
     pub sreg: u8,
     pub tick: u64,
@@ -24,19 +44,70 @@ pub struct Snapshot {
     pub data: Vec<u8>,
     /// EEPROM contents
     pub eeprom: Vec<u8>,
-    /// Display framebuffer (SSD1306 or PCD8544)
+    /// Display framebuffer (SSD1306 or PCD8544), RGBA
     pub framebuffer: Vec<u8>,
+    /// Framebuffer width in pixels
+    pub width: usize,
     /// Frame number when this snapshot was taken
     pub frame: u32,
 }
 
-/// Ring buffer of snapshots for rewind.
+/// Downscaled grayscale screen image of a snapshot.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thumbnail {
+    pub width: usize,
+    pub height: usize,
+    /// Luma 0–255, row-major
+    pub pixels: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Box-filter an RGBA framebuffer `width` pixels wide by [`THUMB_SCALE`].
+    pub fn from_rgba(fb: &[u8], width: usize) -> Self {
+        if width == 0 {
+            return Thumbnail::default();
+        }
+        let height = fb.len() / 4 / width;
+        let (tw, th) = (width / THUMB_SCALE, height / THUMB_SCALE);
+        let mut pixels = Vec::with_capacity(tw * th);
+        for ty in 0..th {
+            for tx in 0..tw {
+                let mut sum = 0u32;
+                for y in ty * THUMB_SCALE..(ty + 1) * THUMB_SCALE {
+                    for x in tx * THUMB_SCALE..(tx + 1) * THUMB_SCALE {
+                        let i = (y * width + x) * 4;
+                        sum += (fb[i] as u32 * 3 + fb[i + 1] as u32 * 6 + fb[i + 2] as u32) / 10;
+                    }
+                }
+                pixels.push((sum / (THUMB_SCALE * THUMB_SCALE) as u32) as u8);
+            }
+        }
+        Thumbnail { width: tw, height: th, pixels }
+    }
+}
+
+/// Packed state of one entry: data ‖ eeprom ‖ framebuffer.
+enum Body {
+    Key(Vec<u8>),
+    /// XOR against the previous entry's state
+    Delta(Vec<u8>),
+}
+
+struct Entry {
+    /// Registers and metadata; the state vectors are left empty
+    head: Snapshot,
+    data_len: usize,
+    eeprom_len: usize,
+    body: Body,
+    thumb: Thumbnail,
+}
+
+/// Ring buffer of delta-compressed snapshots for rewind.
 pub struct RewindBuffer {
-    buf: Vec<Option<Snapshot>>,
-    /// Write position (next slot to overwrite)
-    write_pos: usize,
-    /// Number of valid snapshots
-    count: usize,
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    /// Unpacked state of the newest entry, the base for the next delta
+    last: Vec<u8>,
     /// Frames between snapshots
     pub interval: u32,
     /// Frame counter for interval tracking
@@ -48,12 +119,10 @@ impl RewindBuffer {
     ///
     /// With interval=60 and capacity=300, stores 5 minutes of rewind at 60fps.
     pub fn new(capacity: usize, interval: u32) -> Self {
-        let mut buf = Vec::with_capacity(capacity);
-        for _ in 0..capacity { buf.push(None); }
         RewindBuffer {
-            buf,
-            write_pos: 0,
-            count: 0,
+            entries: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            last: Vec::new(),
             interval,
             frame_counter: 0,
         }
@@ -71,45 +140,174 @@ impl RewindBuffer {
     }
 
     /// Push a snapshot into the ring buffer.
-    pub fn push(&mut self, snap: Snapshot) {
-        self.buf[self.write_pos] = Some(snap);
-        self.write_pos = (self.write_pos + 1) % self.buf.len();
-        if self.count < self.buf.len() {
-            self.count += 1;
+    pub fn push(&mut self, mut snap: Snapshot) {
+        if self.entries.len() == self.capacity {
+            self.evict_oldest();
         }
+        let thumb = Thumbnail::from_rgba(&snap.framebuffer, snap.width);
+        let (data_len, eeprom_len) = (snap.data.len(), snap.eeprom.len());
+        let mut state = std::mem::take(&mut snap.data);
+        state.append(&mut snap.eeprom);
+        state.append(&mut snap.framebuffer);
+
+        let since_key = self.entries.iter().rev()
+            .position(|e| matches!(e.body, Body::Key(_)))
+            .unwrap_or(usize::MAX);
+        let body = if since_key >= KEY_INTERVAL - 1 || self.last.len() != state.len() {
+            Body::Key(state.clone())
+        } else {
+            let delta = encode_delta(&self.last, &state);
+            if delta.len() * 2 > state.len() { Body::Key(state.clone()) } else { Body::Delta(delta) }
+        };
+        self.last = state;
+        self.entries.push_back(Entry { head: snap, data_len, eeprom_len, body, thumb });
     }
 
     /// Pop the most recent snapshot (for rewind). Returns None if empty.
     pub fn pop(&mut self) -> Option<Snapshot> {
-        if self.count == 0 { return None; }
-        // Move write_pos back
-        if self.write_pos == 0 {
-            self.write_pos = self.buf.len() - 1;
-        } else {
-            self.write_pos -= 1;
+        let newest = self.entries.len().checked_sub(1)?;
+        let snap = self.get(newest)?;
+        let entry = self.entries.pop_back()?;
+        // The entry below becomes the delta base for the next push
+        if let Body::Delta(d) = &entry.body {
+            apply_delta(&mut self.last, d);
+        } else if let Some(i) = self.entries.len().checked_sub(1) {
+            self.last = self.unpack(i);
+        }
+        if self.entries.is_empty() {
+            self.last.clear();
+        }
+        Some(snap)
+    }
+
+    /// Restore entry `index` (0 = oldest) without removing it.
+    pub fn get(&self, index: usize) -> Option<Snapshot> {
+        let e = self.entries.get(index)?;
+        let mut state = if index + 1 == self.entries.len() { self.last.clone() } else { self.unpack(index) };
+        let framebuffer = state.split_off(e.data_len + e.eeprom_len);
+        let eeprom = state.split_off(e.data_len);
+        Some(Snapshot { data: state, eeprom, framebuffer, ..e.head.clone() })
+    }
+
+    /// Thumbnail of entry `index` (0 = oldest).
+    pub fn thumbnail(&self, index: usize) -> Option<&Thumbnail> {
+        self.entries.get(index).map(|e| &e.thumb)
+    }
+
+    /// Frame number of entry `index` (0 = oldest).
+    pub fn frame_at(&self, index: usize) -> Option<u32> {
+        self.entries.get(index).map(|e| e.head.frame)
+    }
+
+    /// Drop every entry newer than `index`, e.g. after jumping back to it.
+    pub fn truncate(&mut self, index: usize) {
+        if index + 1 < self.entries.len() {
+            self.last = self.unpack(index);
+            self.entries.truncate(index + 1);
+            self.frame_counter = 0;
         }
-        self.count -= 1;
-        self.buf[self.write_pos].take()
     }
 
     /// Number of stored snapshots.
-    pub fn len(&self) -> usize { self.count }
+    pub fn len(&self) -> usize { self.entries.len() }
 
     /// Whether the buffer is empty.
-    pub fn is_empty(&self) -> bool { self.count == 0 }
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
 
     /// Clear all snapshots.
     pub fn clear(&mut self) {
-        for slot in self.buf.iter_mut() { *slot = None; }
-        self.count = 0;
-        self.write_pos = 0;
+        self.entries.clear();
+        self.last.clear();
         self.frame_counter = 0;
     }
 
-    /// Estimated memory usage in bytes.
+    /// Memory used by stored state and thumbnails, in bytes.
     pub fn memory_usage(&self) -> usize {
-        self.count * (std::mem::size_of::<Snapshot>() + 3072 + 1024 + 8192)
+        self.entries.iter().map(|e| {
+            let body = match &e.body { Body::Key(b) | Body::Delta(b) => b.len() };
+            std::mem::size_of::<Entry>() + body + e.thumb.pixels.len()
+        }).sum()
+    }
+
+    /// Unpack the full state of entry `index` from its keyframe.
+    fn unpack(&self, index: usize) -> Vec<u8> {
+        let key = (0..=index).rev()
+            .find(|&i| matches!(self.entries[i].body, Body::Key(_)))
+            .expect("oldest entry is always a keyframe");
+        let mut state = match &self.entries[key].body {
+            Body::Key(b) => b.clone(),
+            Body::Delta(_) => unreachable!(),
+        };
+        for e in self.entries.range(key + 1..=index) {
+            if let Body::Delta(d) = &e.body {
+                apply_delta(&mut state, d);
+            }
+        }
+        state
+    }
+
+    /// Drop the oldest entry, turning its successor into a keyframe.
+    fn evict_oldest(&mut self) {
+        let Some(old) = self.entries.pop_front() else { return };
+        if let (Body::Key(mut base), Some(next)) = (old.body, self.entries.front_mut()) {
+            if let Body::Delta(d) = &next.body {
+                apply_delta(&mut base, d);
+                next.body = Body::Key(base);
+            }
+        }
+    }
+}
+
+/// Encode `cur` as XOR against `prev` (same length): a sequence of
+/// `[zero run][literal count][literal bytes]` with LEB128 counts.
+fn encode_delta(prev: &[u8], cur: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < cur.len() {
+        let zeros = prev[i..].iter().zip(&cur[i..]).take_while(|(a, b)| a == b).count();
+        i += zeros;
+        let lits = prev[i..].iter().zip(&cur[i..]).take_while(|(a, b)| a != b).count();
+        push_varint(&mut out, zeros);
+        push_varint(&mut out, lits);
+        out.extend(prev[i..i + lits].iter().zip(&cur[i..i + lits]).map(|(a, b)| a ^ b));
+        i += lits;
+    }
+    out
+}
+
+/// Apply a delta from [`encode_delta`] in place.
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let lits = read_varint(delta, &mut pos);
+        for (s, d) in state[i..i + lits].iter_mut().zip(&delta[pos..pos + lits]) {
+            *s ^= d;
+        }
+        i += lits;
+        pos += lits;
+    }
+}
+
+fn push_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(buf: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    while let Some(&b) = buf.get(*pos) {
+        *pos += 1;
+        v |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 { break; }
+        shift += 7;
     }
+    v
 }
 
 #[cfg(test)]
@@ -120,7 +318,7 @@ mod tests {
         Snapshot {
             pc: 0, sp: 0, sreg: 0, tick: 0, sleeping: false,
             data: vec![0; 32], eeprom: vec![0; 16],
-            framebuffer: vec![0; 64], frame,
+            framebuffer: vec![0; 64], width: 4, frame,
         }
     }
 
@@ -160,4 +358,41 @@ mod tests {
         for _ in 0..59 { assert!(!rb.tick_frame()); }
         assert!(rb.tick_frame()); // 60th frame
     }
+
+    #[test]
+    fn test_delta_random_access() {
+        // 40 snapshots through a 20-slot ring: keyframes get evicted and re-keyed
+        let mut rb = RewindBuffer::new(20, 1);
+        let snap = |frame: u32| {
+            let mut s = make_snap(frame);
+            s.data = vec![0; 2560];
+            s.data[frame as usize % 32] = frame as u8;
+            // 8×4 screen, left 4×4 block shaded by frame number
+            s.framebuffer = vec![0; 8 * 4 * 4];
+            s.width = 8;
+            for y in 0..4 {
+                s.framebuffer[y * 32..y * 32 + 16].fill(frame as u8);
+            }
+            s
+        };
+        for f in 0..40 {
+            rb.push(snap(f));
+        }
+        assert_eq!(rb.len(), 20);
+        assert_eq!(rb.frame_at(0), Some(20));
+        for i in [0, 5, 13, 19] {
+            let s = rb.get(i).unwrap();
+            let want = snap(20 + i as u32);
+            assert_eq!((s.data, s.eeprom, s.framebuffer), (want.data, want.eeprom, want.framebuffer));
+        }
+        assert_eq!(rb.thumbnail(3).unwrap().pixels, vec![23, 0]);
+
+        rb.truncate(9);
+        assert_eq!(rb.pop().unwrap().frame, 29);
+        rb.push(snap(50));
+        assert_eq!(rb.get(9).unwrap().data, snap(50).data);
+        assert_eq!(rb.get(8).unwrap().data, snap(28).data);
+        let keys = rb.entries.iter().filter(|e| matches!(e.body, Body::Key(_))).count();
+        assert!(keys <= 3, "{} keyframes", keys);
+    }
 }
@@ -0,0 +1,204 @@
+//! Tone onset/offset detection.
+//!
+//! With [`SoundEvents::enabled`] set, the core samples the tone on each
+//! channel as [`Arduboy::get_audio_tone`] reports it (timer compare
+//! configuration, or the edge period of a bit-banged speaker pin) every
+//! peripheral update, about every 128 cycles, and turns changes into events:
+//!
+//! | Change | Events |
+//! |--------|--------|
+//! | Silence → tone | `Onset` |
+//! | Tone → silence | `Offset` |
+//! | Tone → tone more than 2% away | `Offset`, then `Onset` at the same tick |
+//!
+//! Frequency jitter within 2% (bit-bang period rounding, PWM) is one tone.
+//! Events carry the channel, frequency, nearest MIDI note and the timer or
+//! pin that produced it, so a frontend can show what is playing, a test
+//! can assert a sound played, and an exporter gets note on/off pairs.
+//! Frontends collect them with [`Arduboy::take_sound_events`]; at most
+//! [`MAX_PENDING`] are kept when nobody does.
+
+use crate::{Arduboy, CpuType};
+
+/// Events kept until taken
+pub const MAX_PENDING: usize = 4096;
+/// Relative frequency change treated as a new note
+const TOLERANCE: f32 = 0.02;
+
+/// Output channel (speaker pin 1 = left, speaker pin 2 = right).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    Left,
+    Right,
+}
+
+/// What produces a tone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToneSource {
+    Timer1,
+    Timer2,
+    Timer3,
+    Timer4,
+    /// Speaker pin toggled by software
+    Gpio,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Onset,
+    Offset,
+}
+
+/// A tone starting or stopping.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoundEvent {
+    pub kind: EventKind,
+    pub channel: Channel,
+    /// Frequency of the tone that starts or stops
+    pub hz: f32,
+    /// Nearest MIDI note number (69 = A4)
+    pub note: u8,
+    pub source: ToneSource,
+    /// Frame ([`Arduboy::frame_count`]) and CPU tick of the change
+    pub frame: u32,
+    pub tick: u64,
+}
+
+/// A tone currently sounding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tone {
+    pub hz: f32,
+    pub note: u8,
+    pub source: ToneSource,
+    /// Tick it started
+    pub since: u64,
+}
+
+/// Detector state.
+#[derive(Debug, Clone, Default)]
+pub struct SoundEvents {
+    pub enabled: bool,
+    playing: [Option<Tone>; 2],
+    pending: Vec<SoundEvent>,
+}
+
+impl SoundEvents {
+    /// Feed the tone heard on `channel` now (0 Hz = silent).
+    pub fn observe(&mut self, channel: Channel, hz: f32, source: ToneSource, frame: u32, tick: u64) {
+        let slot = &mut self.playing[channel as usize];
+        let same = match slot {
+            Some(t) => hz > 0.0 && (hz - t.hz).abs() <= t.hz * TOLERANCE,
+            None => hz <= 0.0,
+        };
+        if same {
+            return;
+        }
+        let mut events = Vec::with_capacity(2);
+        if let Some(t) = slot.take() {
+            events.push(SoundEvent { kind: EventKind::Offset, channel, hz: t.hz, note: t.note, source: t.source, frame, tick });
+        }
+        if hz > 0.0 {
+            let note = midi_note(hz);
+            *slot = Some(Tone { hz, note, source, since: tick });
+            events.push(SoundEvent { kind: EventKind::Onset, channel, hz, note, source, frame, tick });
+        }
+        for ev in events {
+            if self.pending.len() < MAX_PENDING {
+                self.pending.push(ev);
+            }
+        }
+    }
+
+    /// Tones sounding now, as [left, right].
+    pub fn now_playing(&self) -> [Option<Tone>; 2] {
+        self.playing
+    }
+
+    /// Events since the last call, in order.
+    pub fn take(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Forget the current tones (reset, state load) without events.
+    pub fn clear(&mut self) {
+        self.playing = [None; 2];
+    }
+}
+
+/// Nearest MIDI note number for a frequency (clamped to 0–127).
+pub fn midi_note(hz: f32) -> u8 {
+    (69.0 + 12.0 * (hz / 440.0).log2()).round().clamp(0.0, 127.0) as u8
+}
+
+/// Note name with octave, e.g. `A4`, `C#5`.
+pub fn note_name(note: u8) -> String {
+    const NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+    format!("{}{}", NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
+
+impl Arduboy {
+    /// Tone on each channel with the timer or pin producing it, in the
+    /// priority [`get_audio_tone`](Self::get_audio_tone) uses.
+    pub fn audio_sources(&self) -> [(f32, ToneSource); 2] {
+        let is_32u4 = self.cpu_type == CpuType::Atmega32u4;
+        let t1 = self.timer1.get_tone_hz(self.clock_hz);
+        let t3 = if is_32u4 { self.timer3.get_tone_hz(self.clock_hz) } else { 0.0 };
+        let t4 = if is_32u4 { self.timer4.get_tone_hz(self.clock_hz) } else { 0.0 };
+        // Timer2 only on 328P (Gamebuino sound)
+        let t2 = if !is_32u4 { self.timer2.get_tone_hz(self.clock_hz) } else { 0.0 };
+
+        // Left: Timer3 > Timer4 > Timer2 > GPIO left speaker pin
+        let left = [(t3, ToneSource::Timer3), (t4, ToneSource::Timer4), (t2, ToneSource::Timer2)]
+            .into_iter()
+            .find(|&(hz, _)| hz > 0.0)
+            .unwrap_or((self.audio_router.left.tone_hz(self.cpu.tick, self.clock_hz), ToneSource::Gpio));
+        // Right: Timer1 > GPIO right speaker pin
+        let right = if t1 > 0.0 {
+            (t1, ToneSource::Timer1)
+        } else {
+            (self.audio_router.right.tone_hz(self.cpu.tick, self.clock_hz), ToneSource::Gpio)
+        };
+        [left, right]
+    }
+
+    /// Sound events detected since the last call (see [`sound_events`](crate::sound_events)).
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        self.sound_events.take()
+    }
+
+    /// Feed the detector with the current tones.
+    pub(crate) fn sample_sound_events(&mut self) {
+        let [(l, ls), (r, rs)] = self.audio_sources();
+        let (frame, tick) = (self.frame_count, self.cpu.tick);
+        self.sound_events.observe(Channel::Left, l, ls, frame, tick);
+        self.sound_events.observe(Channel::Right, r, rs, frame, tick);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onset_change_offset() {
+        let mut s = SoundEvents { enabled: true, ..Default::default() };
+        s.observe(Channel::Left, 440.0, ToneSource::Timer3, 1, 100);
+        s.observe(Channel::Left, 444.0, ToneSource::Timer3, 1, 200); // jitter
+        s.observe(Channel::Left, 880.0, ToneSource::Timer3, 2, 300);
+        s.observe(Channel::Right, 0.0, ToneSource::Gpio, 2, 300);
+        assert_eq!(s.now_playing()[0].map(|t| t.note), Some(81));
+        s.observe(Channel::Left, 0.0, ToneSource::Gpio, 3, 400);
+
+        let ev = s.take();
+        let summary: Vec<_> = ev.iter().map(|e| (e.kind, e.note, e.tick)).collect();
+        assert_eq!(summary, vec![
+            (EventKind::Onset, 69, 100),
+            (EventKind::Offset, 69, 300),
+            (EventKind::Onset, 81, 300),
+            (EventKind::Offset, 81, 400),
+        ]);
+        assert!(s.take().is_empty());
+        assert_eq!(note_name(69), "A4");
+        assert_eq!(note_name(61), "C#4");
+    }
+}
@@ -0,0 +1,426 @@
+//! Logic-analyzer style SPI protocol decoder.
+//!
+//! Groups the raw SPI byte stream into transactions framed by chip select,
+//! and annotates each one with a decoded summary:
+//!
+//! - **FX flash** (W25Q128, CS = PD1) — command, 24-bit address, length
+//!   (e.g. `READ 0x012300 (256 bytes)`, `JEDEC ID → EF 40 18`)
+//! - **SSD1306** (CS = PD6, DC = PD4) — command runs are decoded into
+//!   mnemonics (`COLUMN 0x00..0x7F, PAGE 0x00..0x07`); data runs report their length
+//! - **PCD8544** — command bytes in hex; data runs report their length
+//!
+//! A display transaction is also split whenever DC changes, so command and
+//! data phases appear as separate entries. Bytes clocked with no known chip
+//! selected are kept as `none` transactions, which helps spot CS bugs.
+//!
+//! The ATmega32u4/328P TWI (I²C) peripheral is not emulated, so there is no
+//! I²C traffic to decode.
+//!
+//! Like the [`profiler`](crate::profiler), the decoder costs nothing while
+//! disabled. The timeline can be queried by tick range and device, and
+//! exported as JSON via [`SpiDecoder::to_json`].
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Transactions kept before the oldest are discarded
+pub const DEFAULT_CAPACITY: usize = 4096;
+/// Bytes stored per direction per transaction (longer ones keep a count)
+const MAX_STORED_BYTES: usize = 4096;
+
+/// SPI slave a transaction was addressed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiDevice {
+    Ssd1306,
+    Pcd8544,
+    Flash,
+    /// No known chip select was active
+    None,
+}
+
+impl SpiDevice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SpiDevice::Ssd1306 => "ssd1306",
+            SpiDevice::Pcd8544 => "pcd8544",
+            SpiDevice::Flash => "flash",
+            SpiDevice::None => "none",
+        }
+    }
+
+    /// Parse a device name as used by the debugger (`display` matches both
+    /// display controllers).
+    pub fn parse(s: &str) -> Option<Vec<SpiDevice>> {
+        match s.to_ascii_lowercase().as_str() {
+            "ssd1306" => Some(vec![SpiDevice::Ssd1306]),
+            "pcd8544" => Some(vec![SpiDevice::Pcd8544]),
+            "display" => Some(vec![SpiDevice::Ssd1306, SpiDevice::Pcd8544]),
+            "flash" | "fx" => Some(vec![SpiDevice::Flash]),
+            "none" => Some(vec![SpiDevice::None]),
+            _ => None,
+        }
+    }
+}
+
+/// Chip-select framing of a byte, as seen at the SPDR write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpiSelect {
+    pub device: SpiDevice,
+    /// CS pin as (PORTx data address, bit); a rising edge ends the transaction
+    pub cs: Option<(u16, u8)>,
+    /// Display DC level: Some(true) = data, Some(false) = command
+    pub dc_data: Option<bool>,
+}
+
+/// One CS-framed SPI transaction.
+#[derive(Debug, Clone)]
+pub struct SpiTransaction {
+    pub device: SpiDevice,
+    /// Display DC phase (None for non-display devices)
+    pub dc_data: Option<bool>,
+    /// CPU tick of the first byte
+    pub start_tick: u64,
+    /// CPU tick of the last byte
+    pub end_tick: u64,
+    /// Total bytes transferred
+    pub len: usize,
+    /// Bytes sent (first [`MAX_STORED_BYTES`])
+    pub mosi: Vec<u8>,
+    /// Bytes received (first [`MAX_STORED_BYTES`])
+    pub miso: Vec<u8>,
+    /// Decoded description
+    pub summary: String,
+    cs: Option<(u16, u8)>,
+}
+
+/// SPI transaction timeline.
+pub struct SpiDecoder {
+    /// Whether decoding is active
+    pub enabled: bool,
+    /// Maximum number of completed transactions kept
+    pub capacity: usize,
+    done: VecDeque<SpiTransaction>,
+    current: Option<SpiTransaction>,
+    /// Transactions dropped because the timeline was full
+    pub dropped: u64,
+}
+
+impl SpiDecoder {
+    pub fn new() -> Self {
+        SpiDecoder {
+            enabled: false,
+            capacity: DEFAULT_CAPACITY,
+            done: VecDeque::new(),
+            current: None,
+            dropped: 0,
+        }
+    }
+
+    /// Start decoding with an empty timeline.
+    pub fn start(&mut self) {
+        self.clear();
+        self.enabled = true;
+    }
+
+    /// Stop decoding; the timeline is kept for inspection.
+    pub fn stop(&mut self) {
+        self.close();
+        self.enabled = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.done.clear();
+        self.current = None;
+        self.dropped = 0;
+    }
+
+    /// Record one byte exchange.
+    pub fn byte(&mut self, tick: u64, sel: SpiSelect, mosi: u8, miso: u8) {
+        let same = matches!(&self.current,
+            Some(t) if t.device == sel.device && t.dc_data == sel.dc_data && t.cs == sel.cs);
+        if !same {
+            self.close();
+            self.current = Some(SpiTransaction {
+                device: sel.device,
+                dc_data: sel.dc_data,
+                start_tick: tick,
+                end_tick: tick,
+                len: 0,
+                mosi: Vec::new(),
+                miso: Vec::new(),
+                summary: String::new(),
+                cs: sel.cs,
+            });
+        }
+        if let Some(t) = self.current.as_mut() {
+            t.end_tick = tick;
+            t.len += 1;
+            if t.mosi.len() < MAX_STORED_BYTES {
+                t.mosi.push(mosi);
+                t.miso.push(miso);
+            }
+        }
+    }
+
+    /// Observe a PORTx write; a rising edge on the current CS pin ends the
+    /// transaction.
+    pub fn port_write(&mut self, addr: u16, old: u8, value: u8) {
+        if let Some((cs_addr, bit)) = self.current.as_ref().and_then(|t| t.cs) {
+            if cs_addr == addr && old & (1 << bit) == 0 && value & (1 << bit) != 0 {
+                self.close();
+            }
+        }
+    }
+
+    /// Finish the open transaction, if any.
+    pub fn close(&mut self) {
+        if let Some(mut t) = self.current.take() {
+            t.summary = summarize(&t);
+            if self.done.len() >= self.capacity.max(1) {
+                self.done.pop_front();
+                self.dropped += 1;
+            }
+            self.done.push_back(t);
+        }
+    }
+
+    /// Completed transactions, oldest first.
+    pub fn transactions(&self) -> impl Iterator<Item = &SpiTransaction> {
+        self.done.iter()
+    }
+
+    /// Number of completed transactions.
+    pub fn len(&self) -> usize {
+        self.done.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.done.is_empty()
+    }
+
+    /// Completed transactions overlapping `[from, to]` ticks, optionally
+    /// restricted to some devices.
+    pub fn query(&self, from: u64, to: u64, devices: Option<&[SpiDevice]>) -> Vec<&SpiTransaction> {
+        self.done.iter()
+            .filter(|t| t.end_tick >= from && t.start_tick <= to)
+            .filter(|t| devices.is_none_or(|d| d.contains(&t.device)))
+            .collect()
+    }
+
+    /// Export the timeline as JSON.
+    pub fn to_json(&self, clock_hz: u32) -> String {
+        let mut s = String::new();
+        let _ = write!(s, "{{\n  \"clock_hz\": {},\n  \"dropped\": {},\n  \"transactions\": [",
+            clock_hz, self.dropped);
+        for (i, t) in self.done.iter().enumerate() {
+            let dc = match t.dc_data {
+                Some(true) => "\"data\"",
+                Some(false) => "\"cmd\"",
+                None => "null",
+            };
+            let _ = write!(s, "{}\n    {{\"device\": \"{}\", \"start\": {}, \"end\": {}, \"dc\": {}, \
+                \"len\": {}, \"mosi\": \"{}\", \"miso\": \"{}\", \"summary\": \"{}\"}}",
+                if i == 0 { "" } else { "," },
+                t.device.name(), t.start_tick, t.end_tick, dc, t.len,
+                hex(&t.mosi), hex(&t.miso), json_escape(&t.summary));
+        }
+        s.push_str("\n  ]\n}\n");
+        s
+    }
+}
+
+impl Default for SpiDecoder {
+    fn default() -> Self { Self::new() }
+}
+
+impl SpiTransaction {
+    /// One-line description for the debugger.
+    pub fn format_line(&self) -> String {
+        let dc = match self.dc_data {
+            Some(true) => " data",
+            Some(false) => " cmd ",
+            None => "",
+        };
+        format!("{:>10}..{:<10} {:<7}{} {}", self.start_tick, self.end_tick,
+            self.device.name(), dc, self.summary)
+    }
+}
+
+// ─── Decoders ───────────────────────────────────────────────────────────────
+
+fn summarize(t: &SpiTransaction) -> String {
+    match (t.device, t.dc_data) {
+        (SpiDevice::Flash, _) => decode_flash(&t.mosi, &t.miso, t.len),
+        (SpiDevice::Ssd1306, Some(false)) => decode_ssd1306(&t.mosi),
+        (SpiDevice::Pcd8544, Some(false)) => format!("CMD {}", hex_spaced(&t.mosi, 16)),
+        (SpiDevice::None, _) => format!("{} byte(s) {}", t.len, hex_spaced(&t.mosi, 8)),
+        _ => format!("DATA {} byte(s)", t.len),
+    }
+}
+
+/// Decode a W25Qxx flash command sequence.
+fn decode_flash(mosi: &[u8], miso: &[u8], len: usize) -> String {
+    let Some(&cmd) = mosi.first() else { return String::new() };
+    let addr = || -> Option<u32> {
+        (mosi.len() >= 4).then(|| (mosi[1] as u32) << 16 | (mosi[2] as u32) << 8 | mosi[3] as u32)
+    };
+    let with_addr = |name: &str, header: usize| match addr() {
+        Some(a) => format!("{} 0x{:06X} ({} bytes)", name, a, len.saturating_sub(header)),
+        None => format!("{} (truncated)", name),
+    };
+    let addr_only = |name: &str| match addr() {
+        Some(a) => format!("{} 0x{:06X}", name, a),
+        None => format!("{} (truncated)", name),
+    };
+    match cmd {
+        0x03 => with_addr("READ", 4),
+        0x0B => with_addr("FAST READ", 5),
+        0x02 => with_addr("PAGE PROGRAM", 4),
+        0x20 => addr_only("SECTOR ERASE"),
+        0x52 => addr_only("BLOCK ERASE 32K"),
+        0xD8 => addr_only("BLOCK ERASE 64K"),
+        0x06 => "WRITE ENABLE".into(),
+        0x04 => "WRITE DISABLE".into(),
+        0x05 => format!("READ STATUS → {}", hex_spaced(miso.get(1..).unwrap_or(&[]), 4)),
+        0x9F => format!("JEDEC ID → {}", hex_spaced(miso.get(1..).unwrap_or(&[]), 3)),
+        0x90 => format!("MANUFACTURER ID → {}", hex_spaced(miso.get(4..).unwrap_or(&[]), 2)),
+        0x4B => format!("UNIQUE ID → {}", hex_spaced(miso.get(5..).unwrap_or(&[]), 8)),
+        0xC7 | 0x60 => "CHIP ERASE".into(),
+        0xB9 => "POWER DOWN".into(),
+        0xAB => "RELEASE POWER DOWN".into(),
+        _ => format!("CMD 0x{:02X} ({} bytes)", cmd, len),
+    }
+}
+
+/// Decode an SSD1306 command stream into mnemonics.
+fn decode_ssd1306(bytes: &[u8]) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut i = 0;
+    let arg = |i: usize| bytes.get(i).copied();
+    while i < bytes.len() {
+        let c = bytes[i];
+        let (text, used) = match c {
+            0x00..=0x0F => (format!("LOW COL {}", c), 1),
+            0x10..=0x1F => (format!("HIGH COL {}", c & 0x0F), 1),
+            0x20 => (format!("ADDR MODE {}", fmt_arg(arg(i + 1))), 2),
+            0x21 => (format!("COLUMN {}..{}", fmt_arg(arg(i + 1)), fmt_arg(arg(i + 2))), 3),
+            0x22 => (format!("PAGE {}..{}", fmt_arg(arg(i + 1)), fmt_arg(arg(i + 2))), 3),
+            0x26 | 0x27 => ("H SCROLL".to_string(), 7),
+            0x29 | 0x2A => ("V+H SCROLL".to_string(), 6),
+            0x2E => ("SCROLL OFF".to_string(), 1),
+            0x2F => ("SCROLL ON".to_string(), 1),
+            0x40..=0x7F => (format!("START LINE {}", c & 0x3F), 1),
+            0x81 => (format!("CONTRAST {}", fmt_arg(arg(i + 1))), 2),
+            0x8D => (format!("CHARGE PUMP {}", fmt_arg(arg(i + 1))), 2),
+            0xA0 | 0xA1 => (format!("SEG REMAP {}", c & 1), 1),
+            0xA3 => ("V SCROLL AREA".to_string(), 3),
+            0xA4 => ("RAM DISPLAY".to_string(), 1),
+            0xA5 => ("ALL ON".to_string(), 1),
+            0xA6 => ("NORMAL".to_string(), 1),
+            0xA7 => ("INVERT".to_string(), 1),
+            0xA8 => (format!("MUX {}", fmt_arg(arg(i + 1))), 2),
+            0xAE => ("DISPLAY OFF".to_string(), 1),
+            0xAF => ("DISPLAY ON".to_string(), 1),
+            0xB0..=0xB7 => (format!("PAGE START {}", c & 7), 1),
+            0xC0 => ("COM SCAN INC".to_string(), 1),
+            0xC8 => ("COM SCAN DEC".to_string(), 1),
+            0xD3 => (format!("OFFSET {}", fmt_arg(arg(i + 1))), 2),
+            0xD5 => (format!("CLOCK {}", fmt_arg(arg(i + 1))), 2),
+            0xD9 => (format!("PRECHARGE {}", fmt_arg(arg(i + 1))), 2),
+            0xDA => (format!("COM PINS {}", fmt_arg(arg(i + 1))), 2),
+            0xDB => (format!("VCOMH {}", fmt_arg(arg(i + 1))), 2),
+            0xE3 => ("NOP".to_string(), 1),
+            _ => (format!("0x{:02X}", c), 1),
+        };
+        out.push(text);
+        i += used;
+    }
+    out.join(", ")
+}
+
+fn fmt_arg(b: Option<u8>) -> String {
+    b.map_or_else(|| "?".to_string(), |v| format!("0x{:02X}", v))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Space-separated hex, truncated to `max` bytes.
+fn hex_spaced(bytes: &[u8], max: usize) -> String {
+    let mut s: Vec<String> = bytes.iter().take(max).map(|b| format!("{:02X}", b)).collect();
+    if bytes.len() > max { s.push("…".into()); }
+    s.join(" ")
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => { let _ = write!(out, "\\u{:04x}", c as u32); }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLASH: SpiSelect = SpiSelect { device: SpiDevice::Flash, cs: Some((0x2B, 1)), dc_data: None };
+    const OLED_CMD: SpiSelect = SpiSelect { device: SpiDevice::Ssd1306, cs: Some((0x2B, 6)), dc_data: Some(false) };
+    const OLED_DATA: SpiSelect = SpiSelect { device: SpiDevice::Ssd1306, cs: Some((0x2B, 6)), dc_data: Some(true) };
+
+    #[test]
+    fn test_flash_framing_and_decode() {
+        let mut d = SpiDecoder::new();
+        d.start();
+        for (i, b) in [0x03, 0x01, 0x23, 0x00, 0, 0].iter().enumerate() {
+            d.byte(i as u64, FLASH, *b, 0xFF);
+        }
+        // CS rises, then a second read with identical framing
+        d.port_write(0x2B, 0x00, 0x02);
+        d.byte(10, FLASH, 0x9F, 0xFF);
+        d.byte(11, FLASH, 0, 0xEF);
+        d.byte(12, FLASH, 0, 0x40);
+        d.byte(13, FLASH, 0, 0x18);
+        d.close();
+        let t: Vec<_> = d.transactions().collect();
+        assert_eq!(t.len(), 2);
+        assert_eq!(t[0].summary, "READ 0x012300 (2 bytes)");
+        assert_eq!(t[1].summary, "JEDEC ID → EF 40 18");
+        assert_eq!(d.query(10, 20, Some(&[SpiDevice::Flash])).len(), 1);
+    }
+
+    #[test]
+    fn test_ssd1306_dc_split() {
+        let mut d = SpiDecoder::new();
+        d.start();
+        for b in [0xAF, 0x21, 0, 127, 0x22, 0, 7] { d.byte(1, OLED_CMD, b, 0xFF); }
+        for _ in 0..1024 { d.byte(2, OLED_DATA, 0x55, 0xFF); }
+        d.close();
+        let t: Vec<_> = d.transactions().collect();
+        assert_eq!(t[0].summary, "DISPLAY ON, COLUMN 0x00..0x7F, PAGE 0x00..0x07");
+        assert_eq!(t[1].summary, "DATA 1024 byte(s)");
+    }
+
+    #[test]
+    fn test_capacity_and_json() {
+        let mut d = SpiDecoder::new();
+        d.start();
+        d.capacity = 2;
+        for i in 0..3u64 {
+            d.byte(i, FLASH, 0x06, 0xFF);
+            d.port_write(0x2B, 0x00, 0x02);
+        }
+        assert_eq!(d.len(), 2);
+        assert_eq!(d.dropped, 1);
+        let json = d.to_json(16_000_000);
+        assert!(json.contains("\"device\": \"flash\", \"start\": 1"));
+        assert!(json.contains("\"summary\": \"WRITE ENABLE\""));
+        assert!(json.contains("\"dropped\": 1"));
+    }
+}
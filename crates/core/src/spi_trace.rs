@@ -0,0 +1,337 @@
+//! Byte-level SPI debug trace.
+//!
+//! Where the [`spi_decode`](crate::spi_decode) timeline summarizes whole
+//! transactions, the trace keeps every byte with its context:
+//!
+//! ```text
+//!       tick     pc  device  dir   byte  cs  dc
+//!     102400 0x01A4  ssd1306 mosi  0xAF  lo  cmd  *
+//!     102400 0x01A4  ssd1306 miso  0xFF  lo  cmd
+//!     102788 0x01B2  flash   mosi  0x03  lo  -    *
+//! ```
+//!
+//! (`*` marks command bytes: display bytes sent with DC low, and the first
+//! byte of each FX flash transaction.) Writes to the PORTx registers that
+//! carry CS/DC can be traced too, to debug pin handling.
+//!
+//! The trace is a ring buffer that can be started, stopped, filtered and
+//! exported (JSON or CSV) at any time, e.g. from the step debugger's
+//! `strace` command. Filters apply while recording, so a narrow filter
+//! keeps a longer history. Like the decoder, it costs nothing while off.
+
+use crate::spi_decode::{SpiDevice, SpiSelect};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// Entries kept before the oldest are discarded
+pub const DEFAULT_CAPACITY: usize = 8192;
+
+/// What a trace entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpiTraceKind {
+    /// Byte sent by the CPU (SPDR write)
+    Mosi,
+    /// Byte received from the selected device
+    Miso,
+    /// Write to PORTx at this data address (`byte` = new value)
+    Port(u16),
+}
+
+impl SpiTraceKind {
+    pub fn name(&self) -> String {
+        match self {
+            SpiTraceKind::Mosi => "mosi".into(),
+            SpiTraceKind::Miso => "miso".into(),
+            SpiTraceKind::Port(a) => format!("port{}", port_letter(*a)),
+        }
+    }
+}
+
+fn port_letter(addr: u16) -> char {
+    match addr {
+        0x25 => 'B',
+        0x28 => 'C',
+        0x2B => 'D',
+        0x2E => 'E',
+        0x31 => 'F',
+        _ => '?',
+    }
+}
+
+/// One traced byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpiTraceEntry {
+    pub tick: u64,
+    /// Program counter (byte address) of the instruction
+    pub pc: u32,
+    pub device: SpiDevice,
+    pub kind: SpiTraceKind,
+    pub byte: u8,
+    /// Chip select of `device` was active (low)
+    pub cs_active: bool,
+    /// Display DC level: Some(true) = data, Some(false) = command
+    pub dc_data: Option<bool>,
+    /// Display command byte or first byte of a flash transaction
+    pub command: bool,
+}
+
+impl SpiTraceEntry {
+    /// One-line description for the debugger.
+    pub fn format_line(&self) -> String {
+        let dc = match self.dc_data {
+            Some(true) => "data",
+            Some(false) => "cmd",
+            None => "-",
+        };
+        format!("{:>10} 0x{:04X}  {:<7} {:<5} 0x{:02X}  {}  {:<4}{}",
+            self.tick, self.pc, self.device.name(), self.kind.name(), self.byte,
+            if self.cs_active { "lo" } else { "hi" }, dc,
+            if self.command { " *" } else { "" })
+    }
+}
+
+/// Which entries are recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SpiTraceFilter {
+    /// Devices to keep (None = all)
+    pub devices: Option<Vec<SpiDevice>>,
+    /// Keep only command bytes
+    pub commands_only: bool,
+    /// Also keep PORTB–PORTF writes
+    pub ports: bool,
+}
+
+impl SpiTraceFilter {
+    /// Parse a comma-separated spec: `all`, `cmd`, `ports`, or device names
+    /// (`fx`, `display`, `ssd1306`, `pcd8544`, `none`), e.g. `fx,cmd`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut f = SpiTraceFilter::default();
+        for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            match part {
+                "all" => f = SpiTraceFilter::default(),
+                "cmd" | "commands" => f.commands_only = true,
+                "ports" => f.ports = true,
+                _ => {
+                    let d = SpiDevice::parse(part).ok_or_else(|| format!("unknown filter '{}'", part))?;
+                    f.devices.get_or_insert_with(Vec::new).extend(d);
+                }
+            }
+        }
+        Ok(f)
+    }
+
+    fn keeps(&self, e: &SpiTraceEntry) -> bool {
+        if let SpiTraceKind::Port(_) = e.kind {
+            return self.ports;
+        }
+        (!self.commands_only || e.command)
+            && self.devices.as_ref().is_none_or(|d| d.contains(&e.device))
+    }
+
+    /// Filter in the syntax accepted by [`SpiTraceFilter::parse`].
+    pub fn describe(&self) -> String {
+        let mut parts: Vec<&str> = match &self.devices {
+            Some(d) => d.iter().map(|d| d.name()).collect(),
+            None => Vec::new(),
+        };
+        if self.commands_only { parts.push("cmd"); }
+        if self.ports { parts.push("ports"); }
+        if parts.is_empty() { "all".into() } else { parts.join(",") }
+    }
+}
+
+/// CS-framed transaction in progress, for flash command detection.
+struct Frame {
+    device: SpiDevice,
+    cs: Option<(u16, u8)>,
+    bytes: usize,
+}
+
+/// Ring buffer of traced SPI bytes.
+pub struct SpiTrace {
+    /// Whether tracing is active
+    pub enabled: bool,
+    /// Maximum number of entries kept
+    pub capacity: usize,
+    pub filter: SpiTraceFilter,
+    entries: VecDeque<SpiTraceEntry>,
+    /// Entries dropped because the buffer was full
+    pub dropped: u64,
+    frame: Option<Frame>,
+}
+
+impl SpiTrace {
+    pub fn new() -> Self {
+        SpiTrace {
+            enabled: false,
+            capacity: DEFAULT_CAPACITY,
+            filter: SpiTraceFilter::default(),
+            entries: VecDeque::new(),
+            dropped: 0,
+            frame: None,
+        }
+    }
+
+    /// Start tracing with an empty buffer (the filter is kept).
+    pub fn start(&mut self) {
+        self.clear();
+        self.enabled = true;
+    }
+
+    /// Stop tracing; entries are kept for inspection.
+    pub fn stop(&mut self) {
+        self.enabled = false;
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dropped = 0;
+        self.frame = None;
+    }
+
+    /// Record one byte exchange.
+    pub fn transfer(&mut self, tick: u64, pc: u32, sel: SpiSelect, mosi: u8, miso: u8) {
+        let pos = match &mut self.frame {
+            Some(f) if f.device == sel.device && f.cs == sel.cs => { f.bytes += 1; f.bytes - 1 }
+            _ => { self.frame = Some(Frame { device: sel.device, cs: sel.cs, bytes: 1 }); 0 }
+        };
+        let command = match sel.device {
+            SpiDevice::Flash => pos == 0,
+            SpiDevice::Ssd1306 | SpiDevice::Pcd8544 => sel.dc_data == Some(false),
+            SpiDevice::None => false,
+        };
+        let cs_active = sel.cs.is_some();
+        for (kind, byte) in [(SpiTraceKind::Mosi, mosi), (SpiTraceKind::Miso, miso)] {
+            self.push(SpiTraceEntry { tick, pc, device: sel.device, kind, byte, cs_active,
+                dc_data: sel.dc_data, command });
+        }
+    }
+
+    /// Observe a PORTx write; a rising edge on the current CS pin ends the
+    /// flash transaction used for command detection.
+    pub fn port_write(&mut self, tick: u64, pc: u32, addr: u16, old: u8, value: u8) {
+        if let Some((cs_addr, bit)) = self.frame.as_ref().and_then(|f| f.cs) {
+            if cs_addr == addr && old & (1 << bit) == 0 && value & (1 << bit) != 0 {
+                self.frame = None;
+            }
+        }
+        self.push(SpiTraceEntry { tick, pc, device: SpiDevice::None, kind: SpiTraceKind::Port(addr),
+            byte: value, cs_active: false, dc_data: None, command: false });
+    }
+
+    fn push(&mut self, e: SpiTraceEntry) {
+        if !self.filter.keeps(&e) {
+            return;
+        }
+        while self.entries.len() >= self.capacity.max(1) {
+            self.entries.pop_front();
+            self.dropped += 1;
+        }
+        self.entries.push_back(e);
+    }
+
+    /// Traced entries, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &SpiTraceEntry> {
+        self.entries.iter()
+    }
+
+    /// The last `n` entries, oldest first.
+    pub fn last(&self, n: usize) -> impl Iterator<Item = &SpiTraceEntry> {
+        self.entries.iter().skip(self.entries.len().saturating_sub(n))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Export as JSON.
+    pub fn to_json(&self, clock_hz: u32) -> String {
+        let mut s = String::new();
+        let _ = write!(s, "{{\n  \"clock_hz\": {},\n  \"dropped\": {},\n  \"filter\": \"{}\",\n  \"entries\": [",
+            clock_hz, self.dropped, self.filter.describe());
+        for (i, e) in self.entries.iter().enumerate() {
+            let dc = match e.dc_data {
+                Some(true) => "\"data\"",
+                Some(false) => "\"cmd\"",
+                None => "null",
+            };
+            let _ = write!(s, "{}\n    {{\"tick\": {}, \"pc\": {}, \"device\": \"{}\", \"dir\": \"{}\", \
+                \"byte\": {}, \"cs\": {}, \"dc\": {}, \"command\": {}}}",
+                if i == 0 { "" } else { "," },
+                e.tick, e.pc, e.device.name(), e.kind.name(), e.byte, e.cs_active, dc, e.command);
+        }
+        s.push_str("\n  ]\n}\n");
+        s
+    }
+
+    /// Export as CSV with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut s = String::from("tick,pc,device,dir,byte,cs,dc,command\n");
+        for e in &self.entries {
+            let dc = match e.dc_data {
+                Some(true) => "data",
+                Some(false) => "cmd",
+                None => "",
+            };
+            let _ = writeln!(s, "{},0x{:04X},{},{},0x{:02X},{},{},{}",
+                e.tick, e.pc, e.device.name(), e.kind.name(), e.byte,
+                if e.cs_active { "low" } else { "high" }, dc, e.command as u8);
+        }
+        s
+    }
+}
+
+impl Default for SpiTrace {
+    fn default() -> Self { Self::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLASH: SpiSelect = SpiSelect { device: SpiDevice::Flash, cs: Some((0x2B, 1)), dc_data: None };
+    const OLED_CMD: SpiSelect = SpiSelect { device: SpiDevice::Ssd1306, cs: Some((0x2B, 6)), dc_data: Some(false) };
+    const OLED_DATA: SpiSelect = SpiSelect { device: SpiDevice::Ssd1306, cs: Some((0x2B, 6)), dc_data: Some(true) };
+
+    #[test]
+    fn test_commands_and_ring() {
+        let mut t = SpiTrace::new();
+        t.start();
+        t.transfer(10, 0x100, FLASH, 0x03, 0xFF);
+        t.transfer(20, 0x102, FLASH, 0x01, 0xFF);
+        t.port_write(25, 0x104, 0x2B, 0x00, 0x02); // FX CS high: transaction ends
+        t.transfer(30, 0x106, FLASH, 0x9F, 0xFF);
+        let cmds: Vec<u8> = t.entries().filter(|e| e.command && e.kind == SpiTraceKind::Mosi)
+            .map(|e| e.byte).collect();
+        assert_eq!(cmds, vec![0x03, 0x9F]);
+        // Port writes are only kept when asked for
+        assert_eq!(t.len(), 6);
+
+        t.capacity = 4;
+        t.transfer(40, 0x108, OLED_DATA, 0x55, 0xFF);
+        assert_eq!(t.len(), 4);
+        assert_eq!(t.dropped, 4);
+        assert_eq!(t.last(1).next().unwrap().tick, 40);
+    }
+
+    #[test]
+    fn test_filter_and_export() {
+        let mut t = SpiTrace::new();
+        t.filter = SpiTraceFilter::parse("display,cmd").unwrap();
+        assert_eq!(t.filter.describe(), "ssd1306,pcd8544,cmd");
+        t.start();
+        t.transfer(10, 0x100, FLASH, 0x03, 0xFF);
+        t.transfer(20, 0x102, OLED_CMD, 0xAF, 0xFF);
+        t.transfer(30, 0x104, OLED_DATA, 0x55, 0xFF);
+        assert_eq!(t.len(), 2);
+        assert!(t.entries().all(|e| e.byte == 0xAF || e.byte == 0xFF));
+        let csv = t.to_csv();
+        assert_eq!(csv.lines().nth(1), Some("20,0x0102,ssd1306,mosi,0xAF,low,cmd,1"));
+        assert!(t.to_json(16_000_000).contains("\"dir\": \"miso\""));
+        assert!(SpiTraceFilter::parse("bogus").is_err());
+    }
+}
@@ -0,0 +1,231 @@
+//! SRAM occupancy map: static data, heap and stack.
+//!
+//! [`Arduboy::sram_map`](crate::Arduboy::sram_map) combines the `.data`,
+//! `.bss` and `.noinit` extents from the ELF with the heap break read from
+//! avr-libc's `__brkval`, the current SP and the lowest SP since reset
+//! ([`Arduboy::sp_min`](crate::Arduboy::sp_min)). [`SramMap::format`]
+//! prints it as a text bar for the step debugger and [`render`] draws it
+//! into any `0xRRGGBB` pixel buffer, so the gap between heap and stack is
+//! visible at a glance.
+//!
+//! Without an ELF only the stack is known; the rest of SRAM shows as free.
+//! The heap starts where `__heap_start` does in the default linker script:
+//! after the last of `.data`, `.bss` and `.noinit`.
+
+use crate::elf::{ElfFile, Region, DATA_OFFSET};
+
+/// What an SRAM address holds, in increasing order of precedence when a
+/// bar cell covers several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Free,
+    Heap,
+    Noinit,
+    Bss,
+    Data,
+    /// Below the current SP but reached earlier
+    StackPeak,
+    Stack,
+}
+
+impl Kind {
+    /// Bar character for [`SramMap::format`].
+    pub fn symbol(self) -> char {
+        match self {
+            Kind::Data => 'D',
+            Kind::Bss => 'B',
+            Kind::Noinit => 'N',
+            Kind::Heap => 'H',
+            Kind::Free => '.',
+            Kind::StackPeak => 's',
+            Kind::Stack => 'S',
+        }
+    }
+
+    /// Bar color for [`render`].
+    pub fn color(self) -> u32 {
+        match self {
+            Kind::Data => 0x4080F0,
+            Kind::Bss => 0x40B0D0,
+            Kind::Noinit => 0x8070D0,
+            Kind::Heap => 0x50C050,
+            Kind::Free => 0x283038,
+            Kind::StackPeak => 0x806020,
+            Kind::Stack => 0xF0A030,
+        }
+    }
+}
+
+/// SRAM layout at one point in time. Ranges are `[start, end)` data-space
+/// addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SramMap {
+    /// First SRAM address (after the I/O registers)
+    pub ram_start: u16,
+    /// One past the last SRAM address
+    pub ram_end: u16,
+    pub data: Option<(u16, u16)>,
+    pub bss: Option<(u16, u16)>,
+    pub noinit: Option<(u16, u16)>,
+    /// Heap from its start to the current break (empty until `malloc`)
+    pub heap: Option<(u16, u16)>,
+    /// Current stack pointer (the stack occupies `sp + 1..ram_end`)
+    pub sp: u16,
+    /// Lowest stack pointer since reset
+    pub sp_min: u16,
+}
+
+impl SramMap {
+    /// Build the map from the ELF sections (if any), the SRAM contents and
+    /// the stack pointers.
+    pub fn new(elf: Option<&ElfFile>, data: &[u8], ram_start: u16, sp: u16, sp_min: u16) -> Self {
+        let ram_end = data.len().min(0xFFFF) as u16;
+        let section = |name: &str| {
+            let s = elf?.layout.iter().find(|s| s.name == name && s.region == Region::Ram)?;
+            let start = s.addr.wrapping_sub(DATA_OFFSET).min(0xFFFF) as u16;
+            Some((start, start.saturating_add(s.size.min(0xFFFF) as u16)))
+        };
+        let (data_sec, bss, noinit) = (section(".data"), section(".bss"), section(".noinit"));
+        let heap = elf.map(|elf| {
+            let start = [data_sec, bss, noinit].iter().flatten().map(|r| r.1).max().unwrap_or(ram_start);
+            let brk = elf.find_symbols("__brkval").first()
+                .map(|&a| a.wrapping_sub(DATA_OFFSET) as usize)
+                .filter(|&a| a + 1 < data.len())
+                .map(|a| u16::from_le_bytes([data[a], data[a + 1]]))
+                .filter(|&b| b > start && b <= ram_end)
+                .unwrap_or(start);
+            (start, brk)
+        });
+        SramMap { ram_start, ram_end, data: data_sec, bss, noinit, heap, sp, sp_min: sp_min.min(sp) }
+    }
+
+    /// What `addr` holds; the stack wins over anything it has grown into.
+    pub fn kind_at(&self, addr: u16) -> Kind {
+        let within = |r: Option<(u16, u16)>| r.is_some_and(|(s, e)| addr >= s && addr < e);
+        if addr > self.sp {
+            Kind::Stack
+        } else if addr > self.sp_min {
+            Kind::StackPeak
+        } else if within(self.data) {
+            Kind::Data
+        } else if within(self.bss) {
+            Kind::Bss
+        } else if within(self.noinit) {
+            Kind::Noinit
+        } else if within(self.heap) {
+            Kind::Heap
+        } else {
+            Kind::Free
+        }
+    }
+
+    /// Most significant kind in `[start, end)`, so small regions stay
+    /// visible in a bar cell covering many bytes.
+    pub fn kind_in(&self, start: u16, end: u16) -> Kind {
+        (start..end.max(start + 1)).map(|a| self.kind_at(a)).max().unwrap_or(Kind::Free)
+    }
+
+    /// Address range of cell `i` of `n` across SRAM.
+    fn cell(&self, i: usize, n: usize) -> (u16, u16) {
+        let span = (self.ram_end - self.ram_start) as usize;
+        let at = |i: usize| (self.ram_start as usize + i * span / n) as u16;
+        (at(i), at(i + 1))
+    }
+
+    /// End of static data and heap: where the stack must not reach.
+    pub fn heap_end(&self) -> u16 {
+        [self.data, self.bss, self.noinit, self.heap].iter().flatten()
+            .map(|r| r.1).max().unwrap_or(self.ram_start)
+    }
+
+    /// Bytes between the heap end and the deepest stack; negative once the
+    /// stack has grown into static data or heap.
+    pub fn free(&self) -> i32 {
+        self.sp_min as i32 + 1 - self.heap_end() as i32
+    }
+
+    /// Text bar `width` characters wide plus one line per region.
+    pub fn format(&self, width: usize) -> String {
+        let width = width.max(8);
+        let span = self.ram_end - self.ram_start;
+        let bar: String = (0..width)
+            .map(|i| { let (s, e) = self.cell(i, width); self.kind_in(s, e).symbol() })
+            .collect();
+        let mut out = format!("SRAM 0x{:04X}-0x{:04X} ({} bytes)\n[{}]\n",
+            self.ram_start, self.ram_end - 1, span, bar);
+        let mut line = |name: &str, r: Option<(u16, u16)>, extra: String| {
+            if let Some((s, e)) = r {
+                out.push_str(&format!("  {:<8} 0x{:04X}-0x{:04X} {:5} B{}\n",
+                    name, s, e.max(s + 1) - 1, e - s, extra));
+            }
+        };
+        line(".data", self.data, String::new());
+        line(".bss", self.bss, String::new());
+        line(".noinit", self.noinit, String::new());
+        line("heap", self.heap, self.heap.map(|h| format!(" (break 0x{:04X})", h.1)).unwrap_or_default());
+        line("stack", Some((self.sp_min + 1, self.ram_end)),
+            format!(" (SP 0x{:04X}, now {} B)", self.sp, self.ram_end - 1 - self.sp));
+        let free = self.free();
+        if free >= 0 {
+            out.push_str(&format!("  free     {} B between heap and deepest stack\n", free));
+        } else {
+            out.push_str(&format!("  COLLISION: stack reached 0x{:04X}, {} B into heap/static data\n",
+                self.sp_min + 1, -free));
+        }
+        out
+    }
+}
+
+/// Draw the map as a horizontal bar of `size` pixels (width, height) with
+/// its top-left corner at `pos` of a `buf_w`×`buf_h` buffer; low addresses
+/// on the left. Clipped to the buffer.
+pub fn render(map: &SramMap, buf: &mut [u32], buf_w: usize, buf_h: usize,
+              pos: (usize, usize), size: (usize, usize)) {
+    let (x0, y0) = pos;
+    let (w, h) = (size.0.max(1), size.1);
+    for x in x0..(x0 + w).min(buf_w) {
+        let (start, end) = map.cell(x - x0, w);
+        let color = map.kind_in(start, end).color();
+        for y in y0..(y0 + h).min(buf_h) {
+            buf[y * buf_w + x] = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn map(sp: u16, sp_min: u16) -> SramMap {
+        SramMap {
+            ram_start: 0x100, ram_end: 0xB00,
+            data: Some((0x100, 0x140)), bss: Some((0x140, 0x500)), noinit: None,
+            heap: Some((0x500, 0x580)), sp, sp_min,
+        }
+    }
+
+    #[test]
+    fn test_kinds_and_free() {
+        let m = map(0xAF0, 0xA00);
+        assert_eq!(m.kind_at(0x100), Kind::Data);
+        assert_eq!(m.kind_at(0x57F), Kind::Heap);
+        assert_eq!(m.kind_at(0x580), Kind::Free);
+        assert_eq!(m.kind_at(0xA01), Kind::StackPeak);
+        assert_eq!(m.kind_at(0xAF1), Kind::Stack);
+        assert_eq!(m.heap_end(), 0x580);
+        assert_eq!(m.free(), 0xA01 - 0x580);
+        let text = m.format(32);
+        assert!(text.contains("[DBBBBBBBBBBBBHH.............sssS]"), "{}", text);
+        assert!(map(0xAF0, 0x4FF).format(32).contains("COLLISION"));
+    }
+
+    #[test]
+    fn test_render_columns() {
+        let m = map(0xAFF, 0xAFF);
+        let mut buf = vec![0u32; 40 * 4];
+        render(&m, &mut buf, 40, 4, (4, 1), (32, 2));
+        assert_eq!(buf[40 + 4], Kind::Data.color());
+        assert_eq!(buf[40 + 35], Kind::Free.color());
+        assert_eq!(buf[0], 0);
+    }
+}
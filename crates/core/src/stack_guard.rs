@@ -0,0 +1,184 @@
+//! Stack overflow guard: catch the stack growing into global variables.
+//!
+//! The AVR stack grows down from the top of SRAM towards `.data`, `.bss`
+//! and `.noinit`, and nothing stops it when it gets there: a deep recursion
+//! or a large local array silently overwrites globals, and the program
+//! fails far from the cause. [`StackGuard`] checks the stack pointer after
+//! every `PUSH`, every return address pushed by a call or an interrupt, and
+//! every write to `SPL` (function prologues allocate their locals by
+//! writing `SPH` then `SPL`). When the lowest stacked byte falls below the
+//! limit it reports:
+//!
+//! - the stack pointer and the limit it crossed,
+//! - the PC of the next instruction and the frame number,
+//! - the call stack, innermost first, when the shadow stack
+//!   ([`crate::shadow_stack`]) is tracking calls.
+//!
+//! The limit is the end of the highest of `.data`, `.bss` and `.noinit` in
+//! the ELF, or the end of a given RAM symbol (`__heap_start`, a canary
+//! array, ...) or a data-space address. While `malloc` has moved
+//! `__brkval` above it, the heap break is the limit. Each excursion below
+//! the limit is reported once, into the shared [`Diagnostics`] sink. With
+//! [`StackGuard::break_on_overflow`] set, the core also stops the frame
+//! like a breakpoint.
+
+use crate::diagnostics::Diagnostics;
+use crate::elf::{ElfFile, Region, DATA_OFFSET};
+use crate::shadow_stack::Frame;
+
+/// Stack limit and reported overflows.
+#[derive(Debug, Clone, Default)]
+pub struct StackGuard {
+    /// Checking the stack pointer
+    pub enabled: bool,
+    /// Stop the frame on an overflow
+    pub break_on_overflow: bool,
+    /// Lowest address the stack may use
+    pub limit: u16,
+    /// What the limit is, for messages
+    what: String,
+    /// Address of `__brkval`, when the program links `malloc`
+    brkval: Option<u16>,
+    /// Overflows seen, one per excursion below the limit
+    pub overflows: u64,
+    /// Below the limit since the last report
+    over: bool,
+}
+
+impl StackGuard {
+    /// Guard with the limit at a data-space address.
+    pub fn new(limit: u16, what: &str) -> Self {
+        StackGuard { enabled: true, limit, what: what.to_string(), ..Default::default() }
+    }
+
+    /// Guard for a program. `boundary` is a RAM symbol, whose end becomes
+    /// the limit, or a hex address; without it the limit is the end of
+    /// `.data`/`.bss`/`.noinit`. Symbols and the default need the ELF.
+    pub fn from_elf(elf: Option<&ElfFile>, boundary: Option<&str>) -> Result<Self, String> {
+        let mut guard = match boundary.map(str::trim) {
+            Some(spec) if spec.starts_with("0x") || spec.starts_with("0X") => {
+                let limit = u16::from_str_radix(&spec[2..], 16)
+                    .map_err(|_| format!("bad address '{}'", spec))?;
+                StackGuard::new(limit, spec)
+            }
+            Some(name) => {
+                let elf = elf.ok_or_else(|| format!("symbol '{}' needs an ELF file", name))?;
+                let addr = match elf.find_symbols(name).as_slice() {
+                    [a] if *a >= DATA_OFFSET => *a,
+                    [_] => return Err(format!("'{}' is not in RAM", name)),
+                    [] => return Err(format!("unknown symbol '{}'", name)),
+                    _ => return Err(format!("'{}' matches several symbols", name)),
+                };
+                // Markers such as `__heap_start` have no size: the address itself
+                let end = addr - DATA_OFFSET + elf.sizes.get(&addr).copied().unwrap_or(0);
+                StackGuard::new(end.min(0xFFFF) as u16, &format!("end of {}", name))
+            }
+            None => {
+                let elf = elf.ok_or("needs an ELF file or a boundary address")?;
+                let top = elf.layout.iter()
+                    .filter(|s| s.region == Region::Ram && matches!(s.name.as_str(), ".data" | ".bss" | ".noinit"))
+                    .map(|s| (s.addr.wrapping_sub(DATA_OFFSET) + s.size, s.name.as_str()))
+                    .max()
+                    .ok_or("no .data, .bss or .noinit section")?;
+                StackGuard::new(top.0.min(0xFFFF) as u16, &format!("end of {}", top.1))
+            }
+        };
+        guard.brkval = elf
+            .and_then(|elf| elf.find_symbols("__brkval").first().copied())
+            .filter(|&a| a >= DATA_OFFSET)
+            .map(|a| (a - DATA_OFFSET) as u16);
+        Ok(guard)
+    }
+
+    /// Check the stack pointer `sp` after it moved down. `data` is the data
+    /// space (for `__brkval`), `pc` the next instruction (word address) and
+    /// `calls` the shadow stack, innermost last.
+    pub fn check(&mut self, sp: u16, data: &[u8], pc: u32, frame: u32, calls: &[Frame], out: &mut Diagnostics) {
+        let brk = self.brkval
+            .map(|a| a as usize)
+            .filter(|&a| a + 1 < data.len())
+            .map(|a| u16::from_le_bytes([data[a], data[a + 1]]))
+            .filter(|&b| b > self.limit);
+        let limit = brk.unwrap_or(self.limit);
+        // SP points at the next free byte; the last push is one above
+        let lowest = sp.wrapping_add(1);
+        if lowest >= limit {
+            self.over = false;
+            return;
+        }
+        if std::mem::replace(&mut self.over, true) {
+            return;
+        }
+        self.overflows += 1;
+        if self.break_on_overflow {
+            out.trip();
+        }
+        let what = match brk {
+            Some(_) => "heap break".to_string(),
+            None => self.what.clone(),
+        };
+        let mut msg = format!(
+            "Stack guard: SP=0x{:04X} is {} bytes below 0x{:04X} ({}), next PC=0x{:04X} frame {}",
+            sp, limit - lowest, limit, what, pc * 2, frame);
+        if !calls.is_empty() {
+            let stack: Vec<String> = calls.iter().rev()
+                .map(|f| if f.isr {
+                    format!("interrupt 0x{:04X}", f.from * 2)
+                } else {
+                    format!("0x{:04X}", f.from * 2)
+                })
+                .collect();
+            msg.push_str(&format!("; called from {}", stack.join(" <- ")));
+        }
+        out.report(msg);
+    }
+
+    /// Forget the current excursion (CPU reset).
+    pub fn clear(&mut self) {
+        self.over = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow_once_per_excursion() {
+        let mut g = StackGuard::new(0x0400, "end of .bss");
+        g.break_on_overflow = true;
+        let data = vec![0u8; 0xB00];
+        let mut out = Diagnostics::default();
+        g.check(0x03FF, &data, 0x100, 7, &[], &mut out);
+        assert!(out.take_messages().is_empty(), "last push landed on the limit");
+        let calls = [
+            Frame { sp: 0x0AFD, ret: 0x0102, from: 0x0100, isr: false },
+            Frame { sp: 0x0420, ret: 0x0300, from: 0x0016, isr: true },
+        ];
+        g.check(0x03FD, &data, 0x180, 7, &calls, &mut out);
+        g.check(0x03F0, &data, 0x181, 7, &calls, &mut out);
+        assert_eq!(g.overflows, 1);
+        assert!(out.take_trip() && !out.take_trip());
+        assert_eq!(out.take_messages(), vec![
+            "Stack guard: SP=0x03FD is 2 bytes below 0x0400 (end of .bss), next PC=0x0300 frame 7; \
+             called from interrupt 0x002C <- 0x0200",
+        ]);
+        // Back above the limit re-arms
+        g.check(0x0500, &data, 0x200, 8, &[], &mut out);
+        g.check(0x0300, &data, 0x200, 8, &[], &mut out);
+        assert_eq!(g.overflows, 2);
+    }
+
+    #[test]
+    fn test_heap_break() {
+        let mut g = StackGuard::new(0x0400, "end of .bss");
+        g.brkval = Some(0x0300);
+        let mut data = vec![0u8; 0xB00];
+        let mut out = Diagnostics::default();
+        g.check(0x0450, &data, 0, 0, &[], &mut out);
+        data[0x0300..0x0302].copy_from_slice(&0x0480u16.to_le_bytes());
+        g.check(0x0450, &data, 0, 0, &[], &mut out);
+        assert_eq!(g.overflows, 1);
+        assert!(out.take_messages()[0].contains("below 0x0480 (heap break)"));
+    }
+}
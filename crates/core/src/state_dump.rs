@@ -0,0 +1,167 @@
+//! Canonical text dump of the full emulator state.
+//!
+//! [`Arduboy::dump_full_state_text`] writes one `key = value` line per
+//! item, sorted by key, so two runs or two emulator versions can be
+//! compared with `diff`:
+//!
+//! ```text
+//! # arduboy-emu state dump v1
+//! cpu.pc = 0x01A4
+//! cpu.r00 = 0x00
+//! ...
+//! io.TIMER0.TCCR0B = 0x03
+//! irq.TIMER0_OVF = enabled
+//! mem.sram = 4f0e9a6c1d2b7780
+//! mem.sram.0x0100 = 9d1c3b5a7e2f4011
+//! timer0.prescale = 64
+//! ```
+//!
+//! Registers are listed as the CPU would read them (see
+//! [`introspect`](crate::introspect)), internal peripheral state such as
+//! prescaler phase comes from the save-state structs, and memory regions are
+//! summarized as FNV-1a checksums (SRAM also per 256-byte block, to narrow
+//! down where two runs diverge).
+
+use crate::verify::fnv64;
+use crate::{Arduboy, IO_SIZE, REG_COUNT};
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+
+const HEADER: &str = "# arduboy-emu state dump v1";
+
+/// SRAM block size for per-block checksums
+const SRAM_BLOCK: usize = 256;
+
+/// Sorted key/value lines.
+#[derive(Default)]
+struct Dump(BTreeMap<String, String>);
+
+impl Dump {
+    fn put(&mut self, key: impl Into<String>, value: impl Into<String>) {
+        self.0.insert(key.into(), value.into());
+    }
+    fn hex8(&mut self, key: impl Into<String>, v: u8) {
+        self.put(key, format!("0x{:02X}", v));
+    }
+    fn hash(&mut self, key: impl Into<String>, data: &[u8]) {
+        self.put(key, format!("{:016x}", fnv64(data)));
+    }
+    fn debug(&mut self, key: String, v: impl Debug) {
+        self.put(key, format!("{:?}", v));
+    }
+}
+
+/// `prefix.field = {:?}` for each listed field of a state struct.
+macro_rules! fields {
+    ($d:expr, $prefix:expr, $s:expr, $($f:ident),* $(,)?) => {
+        $( $d.debug(format!("{}.{}", $prefix, stringify!($f)), &$s.$f); )*
+    };
+}
+
+pub(crate) fn dump(ard: &mut Arduboy) -> String {
+    let mut d = Dump::default();
+
+    // CPU
+    d.put("cpu.type", format!("{:?}", ard.cpu_type));
+    d.put("cpu.pc", format!("0x{:04X}", ard.cpu.pc * 2));
+    d.put("cpu.sp", format!("0x{:04X}", ard.cpu.sp));
+    d.hex8("cpu.sreg", ard.cpu.sreg);
+    d.put("cpu.tick", ard.cpu.tick.to_string());
+    d.debug("cpu.sleeping".into(), ard.cpu.sleeping);
+    for r in 0..REG_COUNT {
+        d.hex8(format!("cpu.r{:02}", r), ard.mem.data[r]);
+    }
+
+    // I/O registers and interrupts as the CPU sees them
+    for p in ard.peripherals() {
+        d.put(format!("io.{}", p.name),
+            if !p.powered { "powered-down" } else if p.active { "active" } else { "idle" });
+        for r in &p.registers {
+            d.hex8(format!("io.{}.{}", p.name, r.name), r.value);
+        }
+        for i in &p.interrupts {
+            let state = match (i.enabled, i.pending) {
+                (true, true) => "enabled,pending",
+                (true, false) => "enabled",
+                (false, true) => "disabled,pending",
+                (false, false) => "disabled",
+            };
+            d.put(format!("irq.{}", i.name), state);
+        }
+    }
+
+    // Internal peripheral state
+    for (name, t) in [("timer0", ard.timer0.save_state()), ("timer2", ard.timer2.save_state())] {
+        fields!(d, name, t, tick, prescale, cs, mode, wgm00, wgm01, wgm02, com_a, com_b,
+            ocr0a, ocr0b, tcnt_shadow, tov0, ocf0a, ocf0b, toie0, ocie0a, ocie0b);
+    }
+    for (name, t) in [("timer1", ard.timer1.save_state()), ("timer3", ard.timer3.save_state())] {
+        fields!(d, name, t, tick, prescale, tcnt, top, ctc, wgm, cs, com_a, com_b, com_c,
+            ocr_a, ocr_b, ocr_c, tov, ocf_a, ocf_b, ocf_c, toie, ocie_a, ocie_b, ocie_c);
+    }
+    let t4 = ard.timer4.save_state();
+    fields!(d, "timer4", t4, tcnt, tc4h, ocr_a, ocr_b, ocr_c, ocr_d, tccr_a, tccr_b, tccr_c,
+        tccr_d, tccr_e, dt4, timsk, cs, prescale, tick, wgm, tov, ocf_a, ocf_b, ocf_d);
+    let spi = ard.spi.save_state();
+    fields!(d, "spi", spi, spif, wcol, spi2x, spie, spe);
+    let adc = ard.adc.save_state();
+    fields!(d, "adc", adc, aden, adsc, adie, adif, adch, adcl);
+    let pll = ard.pll.save_state();
+    fields!(d, "pll", pll, pindiv, plle, plock);
+    let fx = ard.fx_flash.save_state();
+    fields!(d, "fx", fx, loaded, write_enabled, powered_down);
+    d.debug("fx.state".into(), ard.fx_flash.state);
+    if fx.loaded {
+        d.hash("fx.data", &ard.fx_flash.data);
+    }
+
+    // Displays
+    d.debug("display.type".into(), ard.display_type);
+    let oled = ard.display.save_state();
+    fields!(d, "ssd1306", oled, col, page, col_start, col_end, page_start, page_end,
+        inverted, display_on, contrast);
+    d.hash("ssd1306.gddram", &oled.framebuffer);
+    let lcd = ard.pcd8544.save_state();
+    fields!(d, "pcd8544", lcd, x_addr, y_addr, extended_mode, display_mode, power_down,
+        vertical_addressing);
+    d.hash("pcd8544.vram", &lcd.vram);
+    d.put("screen", format!("{:016x}", ard.framebuffer_hash()));
+
+    // GPIO inputs, serial, audio and misc
+    for (port, v) in ["b", "c", "d", "e", "f"].iter().zip([ard.pin_b, ard.pin_c, ard.pin_d, ard.pin_e, ard.pin_f]) {
+        d.hex8(format!("gpio.pin_{}", port), v);
+    }
+    d.hex8("input.buttons", ard.buttons);
+    d.put("input.queued", ard.pending_button_events().to_string());
+    d.put("serial.rx_pending", ard.serial_input_pending().to_string());
+    d.put("serial.tx_pending", ard.serial_buf.len().to_string());
+    for (name, ch) in [("left", &ard.audio_router.left), ("right", &ard.audio_router.right)] {
+        d.put(format!("audio.{}.pin", name), ch.pin.map_or("none".into(), |p| p.name()));
+        d.put(format!("audio.{}.half_period", name), ch.half_period.to_string());
+    }
+    d.debug("led.rgb".into(), ard.led_rgb);
+    d.debug("led.rx".into(), ard.led_rx);
+    d.debug("led.tx".into(), ard.led_tx);
+    d.put("frame", ard.frame_count.to_string());
+
+    // Memory regions
+    let sram = &ard.mem.data[REG_COUNT + IO_SIZE..];
+    d.hash("mem.flash", &ard.mem.flash);
+    d.hash("mem.eeprom", &ard.mem.eeprom);
+    d.hash("mem.io", &ard.mem.data[REG_COUNT..REG_COUNT + IO_SIZE]);
+    d.hash("mem.sram", sram);
+    for (i, block) in sram.chunks(SRAM_BLOCK).enumerate() {
+        d.hash(format!("mem.sram.0x{:04X}", REG_COUNT + IO_SIZE + i * SRAM_BLOCK), block);
+    }
+
+    let mut out = String::with_capacity(d.0.len() * 32);
+    out.push_str(HEADER);
+    out.push('\n');
+    for (k, v) in &d.0 {
+        out.push_str(k);
+        out.push_str(" = ");
+        out.push_str(v);
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,175 @@
+//! Arduboy2 system EEPROM settings.
+//!
+//! The Arduboy2 library reserves EEPROM bytes 0–15 for per-device settings
+//! that every game shares, normally edited on hardware with the
+//! `SetSystemEEPROM` / `SetNameAndID` example sketches:
+//!
+//! | Byte  | Field | Notes |
+//! |-------|-------|-------|
+//! | 0     | version | EEPROM layout version |
+//! | 1     | system flags | bit 0 show unit name, bit 1 show boot logo, bit 2 logo LEDs |
+//! | 2     | audio on/off | non-zero = sound enabled |
+//! | 3–7   | reserved | |
+//! | 8–9   | unit ID | little-endian 16-bit |
+//! | 10–15 | unit name | up to 6 characters, NUL padded |
+//!
+//! [`SystemSettings`] reads and writes these fields so a frontend can set up
+//! its "virtual unit" (`--system name=EMU,audio=off`, debugger `sys`).
+
+use std::fmt;
+
+const VERSION: usize = 0;
+const SYS_FLAGS: usize = 1;
+const AUDIO_ON_OFF: usize = 2;
+const UNIT_ID: usize = 8;
+const UNIT_NAME: usize = 10;
+/// Maximum unit name length (`ARDUBOY_UNIT_NAME_LEN`)
+pub const UNIT_NAME_LEN: usize = 6;
+
+const FLAG_UNAME: u8 = 1 << 0;
+const FLAG_SHOW_LOGO: u8 = 1 << 1;
+const FLAG_SHOW_LOGO_LEDS: u8 = 1 << 2;
+
+/// Typed view of the Arduboy2 system EEPROM area.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemSettings {
+    pub version: u8,
+    /// Show the unit name below the boot logo
+    pub show_unit_name: bool,
+    /// Show the boot logo
+    pub show_logo: bool,
+    /// Flash the RGB LED during the boot logo
+    pub show_logo_leds: bool,
+    /// Sound enabled (`arduboy.audio.enabled()`)
+    pub audio: bool,
+    pub unit_id: u16,
+    /// Raw name bytes (NUL padded)
+    pub unit_name: [u8; UNIT_NAME_LEN],
+}
+
+impl SystemSettings {
+    /// Decode settings from EEPROM contents (at least 16 bytes).
+    pub fn read(eeprom: &[u8]) -> Self {
+        let flags = eeprom[SYS_FLAGS];
+        let mut unit_name = [0u8; UNIT_NAME_LEN];
+        unit_name.copy_from_slice(&eeprom[UNIT_NAME..UNIT_NAME + UNIT_NAME_LEN]);
+        SystemSettings {
+            version: eeprom[VERSION],
+            show_unit_name: flags & FLAG_UNAME != 0,
+            show_logo: flags & FLAG_SHOW_LOGO != 0,
+            show_logo_leds: flags & FLAG_SHOW_LOGO_LEDS != 0,
+            audio: eeprom[AUDIO_ON_OFF] != 0,
+            unit_id: u16::from_le_bytes([eeprom[UNIT_ID], eeprom[UNIT_ID + 1]]),
+            unit_name,
+        }
+    }
+
+    /// Encode settings into EEPROM contents; reserved bytes and unused flag
+    /// bits are left alone.
+    pub fn write(&self, eeprom: &mut [u8]) {
+        eeprom[VERSION] = self.version;
+        let mut flags = eeprom[SYS_FLAGS] & !(FLAG_UNAME | FLAG_SHOW_LOGO | FLAG_SHOW_LOGO_LEDS);
+        if self.show_unit_name { flags |= FLAG_UNAME; }
+        if self.show_logo { flags |= FLAG_SHOW_LOGO; }
+        if self.show_logo_leds { flags |= FLAG_SHOW_LOGO_LEDS; }
+        eeprom[SYS_FLAGS] = flags;
+        eeprom[AUDIO_ON_OFF] = self.audio as u8;
+        eeprom[UNIT_ID..UNIT_ID + 2].copy_from_slice(&self.unit_id.to_le_bytes());
+        eeprom[UNIT_NAME..UNIT_NAME + UNIT_NAME_LEN].copy_from_slice(&self.unit_name);
+    }
+
+    /// Unit name up to the first NUL (an erased name reads as empty).
+    pub fn name(&self) -> String {
+        self.unit_name.iter()
+            .take_while(|&&b| b != 0 && b != 0xFF)
+            .map(|&b| b as char)
+            .collect()
+    }
+
+    /// Set the unit name: up to 6 printable ASCII characters.
+    pub fn set_name(&mut self, name: &str) -> Result<(), String> {
+        if name.len() > UNIT_NAME_LEN || !name.bytes().all(|b| (0x20..0x7F).contains(&b)) {
+            return Err(format!("unit name must be up to {} ASCII characters", UNIT_NAME_LEN));
+        }
+        self.unit_name = [0; UNIT_NAME_LEN];
+        self.unit_name[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    /// Set one field by name: `name`, `id`, `audio`, `logo`, `logo-leds`,
+    /// `show-name` (booleans accept on/off, 1/0, true/false).
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let flag = || match value.to_ascii_lowercase().as_str() {
+            "on" | "1" | "true" | "yes" => Ok(true),
+            "off" | "0" | "false" | "no" => Ok(false),
+            _ => Err(format!("{}: expected on or off, got '{}'", key, value)),
+        };
+        match key {
+            "name" => self.set_name(value)?,
+            "id" => {
+                let v = value.trim();
+                self.unit_id = match v.strip_prefix("0x").or_else(|| v.strip_prefix("0X")) {
+                    Some(h) => u16::from_str_radix(h, 16),
+                    None => v.parse(),
+                }.map_err(|_| format!("id: expected 0-65535 or 0x0000-0xFFFF, got '{}'", value))?;
+            }
+            "audio" => self.audio = flag()?,
+            "logo" => self.show_logo = flag()?,
+            "logo-leds" => self.show_logo_leds = flag()?,
+            "show-name" => self.show_unit_name = flag()?,
+            _ => return Err(format!("unknown setting '{}' (name, id, audio, logo, logo-leds, show-name)", key)),
+        }
+        Ok(())
+    }
+
+    /// Apply a comma-separated `key=value` list, e.g. `name=EMU,audio=off`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (k, v) = item.split_once('=').ok_or_else(|| format!("expected key=value, got '{}'", item))?;
+            self.set(k.trim(), v.trim())?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for SystemSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on = |b: bool| if b { "on" } else { "off" };
+        writeln!(f, "name      = \"{}\"", self.name())?;
+        writeln!(f, "id        = 0x{:04X}", self.unit_id)?;
+        writeln!(f, "audio     = {}", on(self.audio))?;
+        writeln!(f, "logo      = {}", on(self.show_logo))?;
+        writeln!(f, "logo-leds = {}", on(self.show_logo_leds))?;
+        writeln!(f, "show-name = {}", on(self.show_unit_name))?;
+        write!(f, "version   = {}", self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let mut eeprom = vec![0xFFu8; 1024];
+        let mut s = SystemSettings::read(&eeprom);
+        assert!(s.audio && s.show_logo);
+        assert_eq!(s.name(), "");
+        s.apply("name=EMU, id=0x1234, audio=off, logo-leds=off").unwrap();
+        s.write(&mut eeprom);
+        assert_eq!(&eeprom[8..16], &[0x34, 0x12, b'E', b'M', b'U', 0, 0, 0]);
+        assert_eq!(eeprom[1], 0xFB);
+        assert_eq!(eeprom[2], 0);
+        assert_eq!(eeprom[16], 0xFF);
+        assert_eq!(SystemSettings::read(&eeprom), s);
+    }
+
+    #[test]
+    fn test_bad_values() {
+        let mut s = SystemSettings::read(&[0u8; 16]);
+        assert!(s.set("name", "TOOLONG").is_err());
+        assert!(s.set("audio", "loud").is_err());
+        assert!(s.set("id", "70000").is_err());
+        assert!(s.apply("color=red").is_err());
+    }
+}
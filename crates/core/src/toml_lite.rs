@@ -0,0 +1,204 @@
+//! Minimal TOML subset shared by the scenario runner and config files.
+//!
+//! Supports top-level key/value pairs, `[[array]]` tables, basic strings,
+//! integers (decimal, `0x`, `0b`, `_` separators), booleans and one-line
+//! arrays. Plain `[table]` headers, floats and multi-line values are not
+//! supported.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn as_str(&self, key: &str) -> Result<&str, String> {
+        match self {
+            Value::Str(s) => Ok(s),
+            _ => Err(format!("{}: expected a string", key)),
+        }
+    }
+    pub fn as_u32(&self, key: &str) -> Result<u32, String> {
+        match self {
+            Value::Int(n) if *n >= 0 && *n <= u32::MAX as i64 => Ok(*n as u32),
+            _ => Err(format!("{}: expected a non-negative integer", key)),
+        }
+    }
+    pub fn as_bool(&self, key: &str) -> Result<bool, String> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err(format!("{}: expected true or false", key)),
+        }
+    }
+    pub fn as_array(&self, key: &str) -> Result<&[Value], String> {
+        match self {
+            Value::Array(a) => Ok(a),
+            _ => Err(format!("{}: expected an array", key)),
+        }
+    }
+}
+
+/// One `[[name]]` table: ordered key/value pairs.
+#[derive(Debug, Default)]
+pub struct Table(pub Vec<(String, Value)>);
+
+impl Table {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TomlDoc {
+    /// Top-level keys
+    pub root: Vec<(String, Value)>,
+    /// `[[name]]` tables in file order
+    pub tables: Vec<(String, Table)>,
+}
+
+/// Parse a document. Errors carry the 1-based line number.
+pub fn parse(text: &str) -> Result<TomlDoc, String> {
+    let mut doc = TomlDoc::default();
+    for (n, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() { continue; }
+        let err = |m: &str| format!("line {}: {}", n + 1, m);
+
+        if let Some(rest) = line.strip_prefix("[[") {
+            let name = rest.strip_suffix("]]").ok_or_else(|| err("unterminated [[table]]"))?;
+            doc.tables.push((name.trim().to_string(), Table::default()));
+            continue;
+        }
+        if line.starts_with('[') {
+            return Err(err("only [[array]] tables are supported"));
+        }
+        let (key, val) = line.split_once('=').ok_or_else(|| err("expected key = value"))?;
+        let key = key.trim().trim_matches('"').to_string();
+        let mut pos = 0;
+        let val_str = val.trim();
+        let value = parse_value(val_str.as_bytes(), &mut pos).map_err(|m| err(&m))?;
+        if val_str[pos..].trim() != "" {
+            return Err(err("trailing characters after value"));
+        }
+        match doc.tables.last_mut() {
+            Some((_, t)) => t.0.push((key, value)),
+            None => doc.root.push((key, value)),
+        }
+    }
+    Ok(doc)
+}
+
+/// Remove a `#` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_str = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\\' if in_str => { escaped = !escaped; continue; }
+            '"' if !escaped => in_str = !in_str,
+            '#' if !in_str => return &line[..i],
+            _ => {}
+        }
+        escaped = false;
+    }
+    line
+}
+
+fn parse_value(s: &[u8], pos: &mut usize) -> Result<Value, String> {
+    skip_ws(s, pos);
+    match s.get(*pos) {
+        None => Err("missing value".into()),
+        Some(b'"') => {
+            *pos += 1;
+            let mut bytes = Vec::new();
+            while *pos < s.len() {
+                let c = s[*pos];
+                *pos += 1;
+                match c {
+                    b'"' => return Ok(Value::Str(String::from_utf8_lossy(&bytes).into_owned())),
+                    b'\\' => {
+                        let e = *s.get(*pos).ok_or("unterminated escape")?;
+                        *pos += 1;
+                        bytes.push(match e {
+                            b'n' => b'\n',
+                            b't' => b'\t',
+                            b'r' => b'\r',
+                            b'"' => b'"',
+                            b'\\' => b'\\',
+                            _ => return Err(format!("unsupported escape \\{}", e as char)),
+                        });
+                    }
+                    _ => bytes.push(c),
+                }
+            }
+            Err("unterminated string".into())
+        }
+        Some(b'[') => {
+            *pos += 1;
+            let mut items = Vec::new();
+            loop {
+                skip_ws(s, pos);
+                if s.get(*pos) == Some(&b']') { *pos += 1; break; }
+                items.push(parse_value(s, pos)?);
+                skip_ws(s, pos);
+                match s.get(*pos) {
+                    Some(b',') => *pos += 1,
+                    Some(b']') => { *pos += 1; break; }
+                    _ => return Err("expected , or ] in array".into()),
+                }
+            }
+            Ok(Value::Array(items))
+        }
+        Some(_) => {
+            let start = *pos;
+            while *pos < s.len() && !matches!(s[*pos], b',' | b']' | b' ' | b'\t') {
+                *pos += 1;
+            }
+            let tok = std::str::from_utf8(&s[start..*pos]).map_err(|_| "invalid UTF-8")?;
+            match tok {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => parse_int(tok).map(Value::Int)
+                    .ok_or_else(|| format!("invalid value \"{}\"", tok)),
+            }
+        }
+    }
+}
+
+fn parse_int(tok: &str) -> Option<i64> {
+    let t = tok.replace('_', "");
+    let (neg, t) = match t.strip_prefix('-') {
+        Some(r) => (true, r.to_string()),
+        None => (false, t.trim_start_matches('+').to_string()),
+    };
+    let v = if let Some(h) = t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+        i64::from_str_radix(h, 16).ok()?
+    } else if let Some(b) = t.strip_prefix("0b") {
+        i64::from_str_radix(b, 2).ok()?
+    } else {
+        t.parse().ok()?
+    };
+    Some(if neg { -v } else { v })
+}
+
+fn skip_ws(s: &[u8], pos: &mut usize) {
+    while *pos < s.len() && (s[*pos] == b' ' || s[*pos] == b'\t') { *pos += 1; }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_doc() {
+        let doc = parse("a = 1\n[[t]]\nb = [\"x\", true] # c\n[[t]]\n").unwrap();
+        assert_eq!(doc.root, vec![("a".to_string(), Value::Int(1))]);
+        assert_eq!(doc.tables.len(), 2);
+        let b = doc.tables[0].1.get("b").unwrap().as_array("b").unwrap();
+        assert_eq!(b[0].as_str("b").unwrap(), "x");
+        assert!(b[1].as_bool("b").unwrap());
+        assert!(parse("[plain]").is_err());
+    }
+}
@@ -0,0 +1,285 @@
+//! Logging tracepoints.
+//!
+//! A tracepoint is a breakpoint that does not stop: when the CPU reaches its
+//! address it formats a message from registers and memory and carries on,
+//! giving printf-style debugging of release HEX files that cannot be rebuilt
+//! with extra `Serial.print` calls.
+//!
+//! A tracepoint is written as `<addr> "<format>" [if <cond>]`, with a flash
+//! byte address in hex and an optional [`expr`](crate::expr) condition:
+//!
+//! ```text
+//! 0x1A4 "hit r24=%r24 hp=%[0x0150]" if $r24 > 3
+//! ```
+//!
+//! | Format | Value |
+//! |--------|-------|
+//! | `%r24`, `%x`/`%y`/`%z`, `%pc`, `%sp`, `%sreg` | Register |
+//! | `%[0x0150]`, `%[$y+2]:i16` | Memory at a data-space address (default `u8`) |
+//! | `%{score:u16 * 10}` | Any debugger expression |
+//! | `%#r24`, `%#[0x0150]` | Same, in hex |
+//! | `%%` | Literal `%` |
+//!
+//! Messages are collected by the frontend with [`Tracepoints::take_messages`],
+//! like the [`eeprom_guard`](crate::eeprom_guard) messages.
+
+use crate::expr::{Expr, Symbols};
+use crate::Arduboy;
+
+enum Part {
+    Text(String),
+    /// Value and whether to print it in hex
+    Value(Expr, bool),
+}
+
+/// One tracepoint.
+pub struct Tracepoint {
+    /// Flash word address
+    pub addr: u32,
+    /// Format string as written
+    pub format: String,
+    /// Condition as written
+    pub condition: Option<String>,
+    /// Times the address was reached with the condition true
+    pub hits: u64,
+    parts: Vec<Part>,
+    cond: Option<Expr>,
+}
+
+impl Tracepoint {
+    /// Parse `<addr> "<format>" [if <cond>]`; names are looked up in `symbols`.
+    pub fn parse(spec: &str, symbols: &Symbols) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (addr, rest) = spec.split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected <addr> \"<format>\" [if <cond>], got '{}'", spec))?;
+        let hex = addr.trim_start_matches("0x").trim_start_matches("0X");
+        let addr = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|a| (*a as usize) < crate::FLASH_SIZE_2560)
+            .ok_or_else(|| format!("bad flash address '{}'", addr))?;
+        let rest = rest.trim_start().strip_prefix('"')
+            .ok_or_else(|| "format must be in double quotes".to_string())?;
+        let (format, rest) = rest.split_once('"')
+            .ok_or_else(|| "missing closing quote in format".to_string())?;
+        let rest = rest.trim();
+        let condition = match rest.strip_prefix("if") {
+            _ if rest.is_empty() => None,
+            Some(c) if c.starts_with(char::is_whitespace) => Some(c.trim().to_string()),
+            _ => return Err(format!("unexpected '{}' after format (expected 'if <cond>')", rest)),
+        };
+        let cond = condition.as_deref().map(|c| Expr::parse(c, symbols)).transpose()?;
+        Ok(Tracepoint {
+            addr: addr / 2,
+            format: format.to_string(),
+            condition,
+            hits: 0,
+            parts: parse_format(format, symbols)?,
+            cond,
+        })
+    }
+
+    /// The message for the current state, or None if the condition is false.
+    /// Evaluation errors show up in the message instead of the value.
+    pub fn render(&self, arduboy: &Arduboy) -> Option<String> {
+        if let Some(cond) = &self.cond {
+            match cond.eval(arduboy) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(format!("<condition: {}>", e)),
+            }
+        }
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(t) => out.push_str(t),
+                Part::Value(e, hex) => match e.eval(arduboy) {
+                    Ok(v) if *hex => out.push_str(&format!("0x{:X}", v)),
+                    Ok(v) => out.push_str(&v.to_string()),
+                    Err(e) => out.push_str(&format!("<{}>", e)),
+                },
+            }
+        }
+        Some(out)
+    }
+}
+
+fn parse_format(fmt: &str, symbols: &Symbols) -> Result<Vec<Part>, String> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut rest = fmt;
+    while let Some(i) = rest.find('%') {
+        text.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(r) = rest.strip_prefix('%') {
+            text.push('%');
+            rest = r;
+            continue;
+        }
+        let hex = rest.starts_with('#');
+        if hex {
+            rest = &rest[1..];
+        }
+        let (src, r) = match rest.chars().next() {
+            Some('[') => {
+                let end = closing(rest, '[', ']')?;
+                // Keep a `:type` suffix with the memory reference
+                let suffix = rest[end + 1..].strip_prefix(':')
+                    .map(|s| s.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(s.len()) + 1)
+                    .unwrap_or(0);
+                (rest[..end + 1 + suffix].to_string(), &rest[end + 1 + suffix..])
+            }
+            Some('{') => {
+                let end = closing(rest, '{', '}')?;
+                (rest[1..end].to_string(), &rest[end + 1..])
+            }
+            _ => {
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+                let name = &rest[..end];
+                let is_reg = matches!(name, "x" | "y" | "z" | "pc" | "sp" | "sreg")
+                    || name.strip_prefix('r').and_then(|n| n.parse::<u8>().ok()).is_some_and(|n| n < 32);
+                if !is_reg {
+                    return Err(format!("unknown format '%{}' (use %r0-%r31, %x, %y, %z, %pc, %sp, %sreg, %[addr], %{{expr}} or %%)", name));
+                }
+                (format!("${}", name), &rest[end..])
+            }
+        };
+        if !text.is_empty() {
+            parts.push(Part::Text(std::mem::take(&mut text)));
+        }
+        parts.push(Part::Value(Expr::parse(&src, symbols)?, hex));
+        rest = r;
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    Ok(parts)
+}
+
+/// Index of the bracket closing the one at the start of `s`.
+fn closing(s: &str, open: char, close: char) -> Result<usize, String> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Ok(i);
+            }
+        }
+    }
+    Err(format!("missing '{}' in format", close))
+}
+
+/// Active tracepoints and the messages they produced.
+#[derive(Default)]
+pub struct Tracepoints {
+    points: Vec<Tracepoint>,
+    pending: Vec<String>,
+}
+
+impl Tracepoints {
+    /// Parse and add a tracepoint; returns its index.
+    pub fn add(&mut self, spec: &str, symbols: &Symbols) -> Result<usize, String> {
+        self.points.push(Tracepoint::parse(spec, symbols)?);
+        Ok(self.points.len() - 1)
+    }
+
+    /// Remove tracepoint `idx`; false if there is none.
+    pub fn remove(&mut self, idx: usize) -> bool {
+        if idx < self.points.len() {
+            self.points.remove(idx);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// True if a tracepoint is set at flash word address `pc`.
+    #[inline]
+    pub fn at(&self, pc: u32) -> bool {
+        self.points.iter().any(|t| t.addr == pc)
+    }
+
+    /// Log every tracepoint at the current PC whose condition holds.
+    pub fn fire(&mut self, arduboy: &Arduboy) {
+        let pc = arduboy.cpu.pc;
+        for t in self.points.iter_mut().filter(|t| t.addr == pc) {
+            if let Some(msg) = t.render(arduboy) {
+                t.hits += 1;
+                self.pending.push(format!("TP 0x{:04X} F{}: {}", pc * 2, arduboy.frame_count, msg));
+            }
+        }
+    }
+
+    /// One line per tracepoint.
+    pub fn list(&self) -> String {
+        if self.points.is_empty() {
+            return "No tracepoints.\n".to_string();
+        }
+        let mut out = String::new();
+        for (i, t) in self.points.iter().enumerate() {
+            out.push_str(&format!("  [{}] 0x{:04X} \"{}\"", i, t.addr * 2, t.format));
+            if let Some(c) = &t.condition {
+                out.push_str(&format!(" if {}", c));
+            }
+            out.push_str(&format!(" ({} hits)\n", t.hits));
+        }
+        out
+    }
+
+    /// Take messages logged since the last call.
+    pub fn take_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let mut a = Arduboy::new();
+        a.mem.data[24] = 42;
+        a.mem.data[0x150] = 7;
+        a.mem.data[0x151] = 0x01;
+        let mut syms = Symbols::default();
+        syms.insert("hp", 0x150);
+        let t = Tracepoint::parse(r#"0x1A4 "r24=%r24 hp=%[0x0150] w=%#[0x150]:u16 %{hp * 2} 100%%""#, &syms).unwrap();
+        assert_eq!(t.addr, 0xD2);
+        assert_eq!(t.render(&a).unwrap(), "r24=42 hp=7 w=0x107 14 100%");
+
+        let t = Tracepoint::parse(r#"1a4 "low" if hp < 5"#, &syms).unwrap();
+        assert_eq!(t.render(&a), None);
+        a.mem.data[0x150] = 3;
+        assert_eq!(t.render(&a).as_deref(), Some("low"));
+
+        assert!(Tracepoint::parse("0x1A4 no quotes", &syms).is_err());
+        assert!(Tracepoint::parse(r#"0x1A4 "%q""#, &syms).is_err());
+        assert!(Tracepoint::parse(r#"0x1A4 "%[0x10""#, &syms).is_err());
+        assert!(Tracepoint::parse(r#"0x1A4 "x" when 1"#, &syms).is_err());
+    }
+
+    #[test]
+    fn test_fire() {
+        let mut a = Arduboy::new();
+        let mut tps = Tracepoints::default();
+        tps.add(r#"0x10 "pc=%#pc""#, &Symbols::default()).unwrap();
+        a.cpu.pc = 0x08;
+        assert!(tps.at(0x08) && !tps.at(0x10));
+        tps.fire(&a);
+        assert_eq!(tps.take_messages(), vec!["TP 0x0010 F0: pc=0x10"]);
+        assert!(tps.list().contains("(1 hits)"));
+        assert!(tps.take_messages().is_empty());
+    }
+}
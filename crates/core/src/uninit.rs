@@ -0,0 +1,82 @@
+//! Strict mode: loads from SRAM that was never written.
+//!
+//! Real SRAM powers up holding arbitrary values, while the emulator starts
+//! it zeroed, so a game that reads a variable before setting it can work
+//! here and misbehave on hardware. In strict mode
+//! ([`Arduboy::set_strict`](crate::Arduboy::set_strict)) the memory keeps a
+//! bitmap of the bytes written since reset
+//! ([`Memory::track_init`](crate::memory::Memory::track_init)) and every
+//! load from an unwritten SRAM byte is reported with its PC and
+//! disassembly.
+//!
+//! The C runtime clears `.bss` and copies `.data` before `main`, so globals
+//! never trip it; what it finds is locals read before assignment (stack
+//! bytes no call, `PUSH` or store has touched yet), `malloc` memory used
+//! without being filled, and arrays read past their initialized part.
+//! A stack byte written by an earlier, deeper call counts as written, as it
+//! does not change on hardware either. Each load PC is reported once, into
+//! the shared [`Diagnostics`] sink. With [`UninitReads::break_on_read`]
+//! set, the core also stops the frame like a breakpoint.
+
+use crate::diagnostics::Diagnostics;
+use std::collections::HashSet;
+
+/// Reported uninitialized loads.
+#[derive(Debug, Clone, Default)]
+pub struct UninitReads {
+    /// Checking SRAM loads
+    pub enabled: bool,
+    /// Stop the frame on an uninitialized load
+    pub break_on_read: bool,
+    /// Uninitialized loads seen, including repeats from reported PCs
+    pub reads: u64,
+    reported: HashSet<u32>,
+}
+
+impl UninitReads {
+    /// A load at `pc` (word address) read the unwritten byte `addr`;
+    /// `asm` disassembles the load and is only called for new PCs.
+    pub fn read(&mut self, pc: u32, addr: u16, frame: u32, asm: impl FnOnce() -> String, out: &mut Diagnostics) {
+        self.reads += 1;
+        if self.break_on_read {
+            out.trip();
+        }
+        if self.reported.insert(pc) {
+            out.report(format!(
+                "Strict: uninitialized read of 0x{:04X} at PC=0x{:04X} frame {}: {}",
+                addr, pc * 2, frame, asm()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Arduboy;
+
+    #[test]
+    fn test_uninitialized_load() {
+        let mut a = Arduboy::new();
+        // sts 0x0200, r24 ; lds r25, 0x0200 ; lds r25, 0x0201 ; ld r25, X ; rjmp .-4
+        let prog: [u16; 9] = [0x9380, 0x0200, 0x9190, 0x0200, 0x9190, 0x0201, 0x919C, 0xCFFE, 0];
+        for (i, w) in prog.iter().enumerate() {
+            a.mem.flash[i * 2..i * 2 + 2].copy_from_slice(&w.to_le_bytes());
+        }
+        a.mem.invalidate_decoded();
+        a.set_strict(true);
+        a.mem.set_x(0x0300);
+        for _ in 0..4 {
+            a.step_one();
+        }
+        assert_eq!(a.uninit.reads, 2);
+        let msgs = a.diagnostics.take_messages();
+        assert_eq!(msgs.len(), 2);
+        assert!(msgs[0].starts_with("Strict: uninitialized read of 0x0201 at PC=0x0008 frame 0: "), "{}", msgs[0]);
+        assert!(msgs[1].contains("0x0300 at PC=0x000C"), "{}", msgs[1]);
+        // The loop re-reads X: counted, not reported again
+        a.step_one();
+        a.step_one();
+        a.step_one();
+        assert_eq!(a.uninit.reads, 3);
+        assert!(a.diagnostics.take_messages().is_empty());
+    }
+}
@@ -0,0 +1,309 @@
+//! Pin-level waveform export in Value Change Dump (VCD) format.
+//!
+//! Records GPIO pins, whole ports, and SPI traffic with CPU-cycle timestamps
+//! so a session can be inspected in GTKWave or any other VCD viewer.
+//!
+//! Pins are sampled after every instruction while recording is enabled, so
+//! edges land on the cycle the writing instruction completed. The pin level
+//! follows the PINx register view: driven outputs show PORTx, inputs show
+//! the external level (buttons, pull-ups).
+//!
+//! SPI transfers are recorded as two 8-bit buses, `spi_mosi` and `spi_miso`,
+//! stamped at the SPDR write. The chip-select lines are ordinary pins
+//! (e.g. `PD6` for the display, `PD1` for the FX flash).
+//!
+//! Probe list syntax (comma-separated, case-insensitive):
+//!
+//! | Probe            | Signal                                     |
+//! |------------------|--------------------------------------------|
+//! | `PORTB`..`PORTF` | 8-bit bus of the whole port                |
+//! | `PB0`..`PF7`     | single pin                                 |
+//! | `spi`            | `spi_mosi` / `spi_miso` byte buses         |
+//! | `speaker`        | speaker pins `PC6`, `PB5` (Arduboy), `PD3` (Gamebuino) |
+//! | `all`            | every port plus `spi`                      |
+//!
+//! The header is written when recording starts and value changes are
+//! streamed to the output as they happen, so memory use stays flat on long
+//! runs and a capture cut short is still readable up to its last flush.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Ports in sampling order (index into the level array)
+const PORTS: [char; 5] = ['B', 'C', 'D', 'E', 'F'];
+
+/// One recorded signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VcdProbe {
+    /// Whole port as an 8-bit bus (index into B..F)
+    Port(u8),
+    /// Single pin: (port index into B..F, bit)
+    Pin(u8, u8),
+    /// Byte shifted out on MOSI
+    SpiMosi,
+    /// Byte shifted in on MISO
+    SpiMiso,
+}
+
+impl VcdProbe {
+    /// Signal name as shown in the viewer.
+    pub fn name(&self) -> String {
+        match *self {
+            VcdProbe::Port(p) => format!("PORT{}", PORTS[p as usize]),
+            VcdProbe::Pin(p, b) => format!("P{}{}", PORTS[p as usize], b),
+            VcdProbe::SpiMosi => "spi_mosi".into(),
+            VcdProbe::SpiMiso => "spi_miso".into(),
+        }
+    }
+
+    fn width(&self) -> u8 {
+        match self {
+            VcdProbe::Pin(..) => 1,
+            _ => 8,
+        }
+    }
+}
+
+/// Parse a probe list such as `"PORTB,PD1,spi"`. Duplicates are dropped.
+pub fn parse_probes(spec: &str) -> Result<Vec<VcdProbe>, String> {
+    let mut out: Vec<VcdProbe> = Vec::new();
+    for item in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        let upper = item.to_ascii_uppercase();
+        let found: Vec<VcdProbe> = match upper.as_str() {
+            "SPI" => vec![VcdProbe::SpiMosi, VcdProbe::SpiMiso],
+            "SPEAKER" => vec![VcdProbe::Pin(1, 6), VcdProbe::Pin(0, 5), VcdProbe::Pin(2, 3)],
+            "ALL" => default_probes(),
+            _ => {
+                let port_of = |c: char| PORTS.iter().position(|&p| p == c).map(|i| i as u8);
+                let bytes = upper.as_bytes();
+                if let Some(rest) = upper.strip_prefix("PORT") {
+                    let c = rest.chars().next().filter(|_| rest.len() == 1);
+                    match c.and_then(port_of) {
+                        Some(p) => vec![VcdProbe::Port(p)],
+                        None => return Err(format!("unknown port '{}'", item)),
+                    }
+                } else if bytes.len() == 3 && bytes[0] == b'P' && (b'0'..=b'7').contains(&bytes[2]) {
+                    match port_of(bytes[1] as char) {
+                        Some(p) => vec![VcdProbe::Pin(p, bytes[2] - b'0')],
+                        None => return Err(format!("unknown pin '{}'", item)),
+                    }
+                } else {
+                    return Err(format!("unknown probe '{}' (expected PORTx, Pxn, spi, speaker, all)", item));
+                }
+            }
+        };
+        for p in found {
+            if !out.contains(&p) { out.push(p); }
+        }
+    }
+    if out.is_empty() {
+        return Err("empty probe list".into());
+    }
+    Ok(out)
+}
+
+/// Probes recorded when no list is given: every port plus SPI.
+pub fn default_probes() -> Vec<VcdProbe> {
+    let mut v: Vec<VcdProbe> = (0..PORTS.len() as u8).map(VcdProbe::Port).collect();
+    v.push(VcdProbe::SpiMosi);
+    v.push(VcdProbe::SpiMiso);
+    v
+}
+
+/// Streams value changes to a VCD file (or any writer).
+#[derive(Debug)]
+pub struct VcdRecorder<W: Write = BufWriter<File>> {
+    probes: Vec<VcdProbe>,
+    /// Last emitted value per probe (None until the first sample)
+    last: Vec<Option<u8>>,
+    out: W,
+    /// First write error; recording stops and [`finish`](Self::finish)
+    /// returns it
+    error: Option<io::Error>,
+    /// Timestamp (in ticks) of the last `#time` line written
+    last_tick: Option<u64>,
+    /// Added to CPU ticks so time keeps moving forward across resets
+    tick_offset: u64,
+    /// Picoseconds per CPU tick
+    ps_per_tick: u64,
+    /// Number of value changes recorded
+    pub changes: u64,
+}
+
+impl VcdRecorder {
+    /// Record to a new file at `path`.
+    pub fn create(path: &Path, probes: Vec<VcdProbe>, clock_hz: u32) -> io::Result<Self> {
+        Self::new(probes, clock_hz, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> VcdRecorder<W> {
+    /// Start recording to `out`; the header is written at once.
+    pub fn new(probes: Vec<VcdProbe>, clock_hz: u32, mut out: W) -> io::Result<Self> {
+        out.write_all(b"$version arduboy-emu $end\n$timescale 1ps $end\n$scope module mcu $end\n")?;
+        for (i, p) in probes.iter().enumerate() {
+            writeln!(out, "$var wire {} {} {} $end", p.width(), ident(i), p.name())?;
+        }
+        out.write_all(b"$upscope $end\n$enddefinitions $end\n")?;
+        let n = probes.len();
+        Ok(VcdRecorder {
+            probes,
+            last: vec![None; n],
+            out,
+            error: None,
+            last_tick: None,
+            tick_offset: 0,
+            ps_per_tick: 1_000_000_000_000 / clock_hz.max(1) as u64,
+            changes: 0,
+        })
+    }
+
+    /// Recorded probes, in declaration order.
+    pub fn probes(&self) -> &[VcdProbe] {
+        &self.probes
+    }
+
+    /// Record pin levels of ports B..F (as read from PINx).
+    pub fn sample_ports(&mut self, tick: u64, levels: &[u8; 5]) {
+        for i in 0..self.probes.len() {
+            let v = match self.probes[i] {
+                VcdProbe::Port(p) => levels[p as usize],
+                VcdProbe::Pin(p, b) => (levels[p as usize] >> b) & 1,
+                _ => continue,
+            };
+            self.change(tick, i, v);
+        }
+    }
+
+    /// Record one SPI byte exchange.
+    pub fn spi_transfer(&mut self, tick: u64, mosi: u8, miso: u8) {
+        for i in 0..self.probes.len() {
+            match self.probes[i] {
+                VcdProbe::SpiMosi => self.force(tick, i, mosi),
+                VcdProbe::SpiMiso => self.force(tick, i, miso),
+                _ => {}
+            }
+        }
+    }
+
+    /// Emit `value` for probe `i` if it differs from the last one.
+    fn change(&mut self, tick: u64, i: usize, value: u8) {
+        if self.last[i] != Some(value) {
+            self.force(tick, i, value);
+        }
+    }
+
+    /// Emit `value` for probe `i` unconditionally (repeated SPI bytes are
+    /// still separate transfers).
+    fn force(&mut self, tick: u64, i: usize, value: u8) {
+        let mut tick = tick + self.tick_offset;
+        if let Some(last) = self.last_tick {
+            if tick < last {
+                // CPU was reset: continue from the last timestamp
+                self.tick_offset += last - tick;
+                tick = last;
+            }
+        }
+        self.last[i] = Some(value);
+        self.changes += 1;
+        if self.error.is_some() {
+            return;
+        }
+        let new_time = self.last_tick != Some(tick);
+        self.last_tick = Some(tick);
+        if let Err(e) = self.write_change(new_time.then_some(tick), i, value) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Write one change, preceded by a `#time` line when `time` is given.
+    fn write_change(&mut self, time: Option<u64>, i: usize, value: u8) -> io::Result<()> {
+        if let Some(tick) = time {
+            writeln!(self.out, "#{}", tick * self.ps_per_tick)?;
+        }
+        if self.probes[i].width() == 1 {
+            writeln!(self.out, "{}{}", value & 1, ident(i))
+        } else {
+            writeln!(self.out, "b{:08b} {}", value, ident(i))
+        }
+    }
+
+    /// Stop recording: flush the output and hand it back, or the first
+    /// write error.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Short printable VCD identifier for probe index `i`.
+fn ident(mut i: usize) -> String {
+    // Printable ASCII '!'..='~' (94 symbols), base-94 little-endian
+    let mut s = String::new();
+    loop {
+        s.push((b'!' + (i % 94) as u8) as char);
+        i /= 94;
+        if i == 0 { break; }
+        i -= 1;
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_probes() {
+        let p = parse_probes("portb, PD1 ,spi,PD1").unwrap();
+        assert_eq!(p, vec![VcdProbe::Port(0), VcdProbe::Pin(2, 1),
+            VcdProbe::SpiMosi, VcdProbe::SpiMiso]);
+        assert_eq!(parse_probes("speaker").unwrap().len(), 3);
+        assert!(parse_probes("PG1").is_err());
+        assert!(parse_probes("PB8").is_err());
+        assert!(parse_probes("").is_err());
+    }
+
+    #[test]
+    fn test_records_only_changes() {
+        let mut v = VcdRecorder::new(vec![VcdProbe::Pin(1, 6), VcdProbe::Port(0)], 16_000_000, Vec::new()).unwrap();
+        v.sample_ports(0, &[0x00, 0x00, 0, 0, 0]);
+        v.sample_ports(10, &[0x00, 0x00, 0, 0, 0]);
+        v.sample_ports(16, &[0x21, 0x40, 0, 0, 0]);
+        assert_eq!(v.changes, 4);
+        let out = String::from_utf8(v.finish().unwrap()).unwrap();
+        assert!(out.contains("$var wire 1 ! PC6 $end"));
+        assert!(out.contains("$var wire 8 \" PORTB $end"));
+        // 16 ticks at 16 MHz = 1 µs
+        assert!(out.contains("#1000000\n1!\nb00100001 \"\n"));
+        assert!(!out.contains("#625000"));
+    }
+
+    #[test]
+    fn test_spi_bytes_repeat() {
+        let mut v = VcdRecorder::new(vec![VcdProbe::SpiMosi], 16_000_000, Vec::new()).unwrap();
+        v.spi_transfer(1, 0xAA, 0xFF);
+        v.spi_transfer(2, 0xAA, 0xFF);
+        assert_eq!(v.changes, 2);
+    }
+
+    #[test]
+    fn test_time_monotonic_across_reset() {
+        let mut v = VcdRecorder::new(vec![VcdProbe::Pin(0, 0)], 16_000_000, Vec::new()).unwrap();
+        v.sample_ports(100, &[0; 5]);
+        v.sample_ports(0, &[1, 0, 0, 0, 0]);
+        v.sample_ports(16, &[0; 5]);
+        let out = v.finish().unwrap();
+        assert!(out.ends_with(b"#6250000\n0!\n1!\n#7250000\n0!\n"));
+    }
+
+    #[test]
+    fn test_ident_unique() {
+        let ids: std::collections::HashSet<String> = (0..500).map(ident).collect();
+        assert_eq!(ids.len(), 500);
+    }
+}
@@ -0,0 +1,341 @@
+//! Run verification log for speedrun and replay submissions.
+//!
+//! While enabled, the emulator records every change of the button/GPIO input
+//! pins and, every `interval` frames, a checkpoint with hashes of SRAM,
+//! EEPROM and the input history. Every line is folded into a running hash
+//! chain, and checkpoint lines carry the chain value, so editing, removing
+//! or reordering any line breaks the chain.
+//!
+//! The log also embeds the starting EEPROM and hashes of the program flash
+//! and FX data, so a third party holding the same game can replay the
+//! recorded inputs and compare checkpoints ([`parse_log`] plus a fresh
+//! emulator). The chain is a 64-bit FNV-1a checksum — it detects edits and
+//! corruption but is not a cryptographic signature.
+//!
+//! ```text
+//! # arduboy-emu verify log v1
+//! game Some Game
+//! flash 9c1e0f4a5d27b3e1
+//! fx none
+//! interval 60
+//! eeprom FFFF…
+//! I 0 FFFFFFFFFF
+//! I 75 FFFFFFBFFF
+//! E 80 51234 A 1
+//! C 119 sram=… eeprom=… input=… chain=…
+//! M 300 reset
+//! end 600 chain=…
+//! ```
+//!
+//! `I <frame> <pins>` gives the external levels of ports B..F from that
+//! frame on; `E <frame> <cycle> <button> <1|0>` is a press or release
+//! injected `cycle` ticks into the frame (see
+//! [`Arduboy::queue_button_event`](crate::Arduboy::queue_button_event));
+//! `M <frame> <event>` marks a reset, rewind or state load before
+//! that frame. Rewinds and state loads cannot be replayed.
+
+use crate::Button;
+use std::fmt::Write;
+
+const HEADER: &str = "# arduboy-emu verify log v1";
+
+/// Button letters used in `E` records, in [`input_overlay::BUTTONS`](crate::input_overlay::BUTTONS) order
+const BUTTON_CODES: [&str; 6] = ["U", "D", "L", "R", "A", "B"];
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01B3;
+
+/// 64-bit FNV-1a hash.
+pub fn fnv64(data: &[u8]) -> u64 {
+    fnv64_with(FNV_OFFSET, data)
+}
+
+/// Continue an FNV-1a hash from `h`.
+pub fn fnv64_with(mut h: u64, data: &[u8]) -> u64 {
+    for &b in data {
+        h ^= b as u64;
+        h = h.wrapping_mul(FNV_PRIME);
+    }
+    h
+}
+
+/// Records inputs and checkpoints of a run.
+#[derive(Debug, Clone)]
+pub struct VerifyLog {
+    interval: u32,
+    text: String,
+    chain: u64,
+    input_hash: u64,
+    last_input: Option<[u8; 5]>,
+    /// Frames completed so far (index of the next frame)
+    frames: u32,
+}
+
+impl VerifyLog {
+    /// Start a log. `interval` is the checkpoint spacing in frames.
+    pub fn new(interval: u32, game: &str, flash: &[u8], fx: Option<&[u8]>, eeprom: &[u8]) -> Self {
+        let mut v = VerifyLog {
+            interval: interval.max(1),
+            text: String::new(),
+            chain: FNV_OFFSET,
+            input_hash: FNV_OFFSET,
+            last_input: None,
+            frames: 0,
+        };
+        v.push(HEADER.to_string(), false);
+        v.push(format!("game {}", game.replace(['\n', '\r'], " ")), false);
+        v.push(format!("flash {:016x}", fnv64(flash)), false);
+        v.push(match fx {
+            Some(d) => format!("fx {:016x}", fnv64(d)),
+            None => "fx none".to_string(),
+        }, false);
+        v.push(format!("interval {}", v.interval), false);
+        let mut hex = String::with_capacity(eeprom.len() * 2);
+        for b in eeprom {
+            let _ = write!(hex, "{:02X}", b);
+        }
+        v.push(format!("eeprom {}", hex), false);
+        v
+    }
+
+    /// Append a line and fold it into the chain, optionally tagging it with
+    /// the chain value.
+    fn push(&mut self, line: String, with_chain: bool) {
+        self.chain = fnv64_with(self.chain, line.as_bytes());
+        self.chain = fnv64_with(self.chain, b"\n");
+        self.text.push_str(&line);
+        if with_chain {
+            let _ = write!(self.text, " chain={:016x}", self.chain);
+        }
+        self.text.push('\n');
+    }
+
+    /// Record the input pins (ports B..F) in effect for `frame`.
+    pub fn begin_frame(&mut self, frame: u32, inputs: [u8; 5]) {
+        self.input_hash = fnv64_with(self.input_hash, &inputs);
+        if self.last_input != Some(inputs) {
+            self.last_input = Some(inputs);
+            let mut line = format!("I {} ", frame);
+            for b in inputs {
+                let _ = write!(line, "{:02X}", b);
+            }
+            self.push(line, false);
+        }
+    }
+
+    /// Record a button change applied `cycle` ticks into `frame`.
+    pub fn button_event(&mut self, frame: u32, cycle: u64, button: Button, pressed: bool) {
+        let code = BUTTON_CODES[crate::input_overlay::button_bit(button).trailing_zeros() as usize];
+        let line = format!("E {} {} {} {}", frame, cycle, code, pressed as u8);
+        self.input_hash = fnv64_with(self.input_hash, line.as_bytes());
+        self.push(line, false);
+    }
+
+    /// Finish `frame`, writing a checkpoint at the end of every interval.
+    pub fn end_frame(&mut self, frame: u32, sram: &[u8], eeprom: &[u8]) {
+        self.frames = frame + 1;
+        if self.frames.is_multiple_of(self.interval) {
+            let line = format!("C {} sram={:016x} eeprom={:016x} input={:016x}",
+                frame, fnv64(sram), fnv64(eeprom), self.input_hash);
+            self.push(line, true);
+        }
+    }
+
+    /// Mark an event (`reset`, `rewind`, `state-load`) before `frame`.
+    pub fn mark(&mut self, frame: u32, event: &str) {
+        self.push(format!("M {} {}", frame, event), false);
+    }
+
+    /// Complete log text, closed with an `end` line.
+    pub fn finish(&self) -> String {
+        let mut v = self.clone();
+        v.push(format!("end {}", self.frames), true);
+        v.text
+    }
+
+    pub fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// Frames recorded so far.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+}
+
+/// Contents of a verified log.
+#[derive(Debug, Clone, Default)]
+pub struct LogInfo {
+    pub game: String,
+    pub flash_hash: u64,
+    /// FX data hash, if the run used FX data
+    pub fx_hash: Option<u64>,
+    pub interval: u32,
+    /// EEPROM contents at the start of the run
+    pub eeprom: Vec<u8>,
+    /// Input pin levels (ports B..F) from each frame on
+    pub inputs: Vec<(u32, [u8; 5])>,
+    /// Sub-frame button events as (frame, cycle within frame, button, pressed)
+    pub events: Vec<(u32, u64, Button, bool)>,
+    /// Events before each frame
+    pub marks: Vec<(u32, String)>,
+    /// Frames in the run
+    pub frames: u32,
+}
+
+/// Parse a log and check its hash chain. Fails on the first line that does
+/// not match, or if the log is not closed by an `end` line.
+pub fn parse_log(text: &str) -> Result<LogInfo, String> {
+    let mut info = LogInfo::default();
+    let mut chain = FNV_OFFSET;
+    let mut ended = false;
+    for (n, raw) in text.lines().enumerate() {
+        let lineno = n + 1;
+        if ended {
+            return Err(format!("line {}: data after end of log", lineno));
+        }
+        let (line, tag) = match raw.rsplit_once(" chain=") {
+            Some((l, t)) => (l, Some(t)),
+            None => (raw, None),
+        };
+        chain = fnv64_with(chain, line.as_bytes());
+        chain = fnv64_with(chain, b"\n");
+        if let Some(t) = tag {
+            let expect = u64::from_str_radix(t, 16)
+                .map_err(|_| format!("line {}: bad chain value", lineno))?;
+            if expect != chain {
+                return Err(format!("line {}: hash chain mismatch (log was modified)", lineno));
+            }
+        }
+        if lineno == 1 {
+            if line != HEADER {
+                return Err("not a verify log".into());
+            }
+            continue;
+        }
+        let (key, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let hex64 = |s: &str| u64::from_str_radix(s, 16)
+            .map_err(|_| format!("line {}: bad hash '{}'", lineno, s));
+        let num = |s: &str| s.parse::<u32>()
+            .map_err(|_| format!("line {}: bad number '{}'", lineno, s));
+        match key {
+            "game" => info.game = rest.to_string(),
+            "flash" => info.flash_hash = hex64(rest)?,
+            "fx" => info.fx_hash = if rest == "none" { None } else { Some(hex64(rest)?) },
+            "interval" => info.interval = num(rest)?.max(1),
+            "eeprom" => {
+                info.eeprom = (0..rest.len() / 2)
+                    .map(|i| u8::from_str_radix(&rest[i * 2..i * 2 + 2], 16))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("line {}: bad EEPROM data", lineno))?;
+            }
+            "I" => {
+                let (f, pins) = rest.split_once(' ')
+                    .ok_or_else(|| format!("line {}: bad input line", lineno))?;
+                if pins.len() != 10 {
+                    return Err(format!("line {}: bad input line", lineno));
+                }
+                let mut p = [0u8; 5];
+                for (i, b) in p.iter_mut().enumerate() {
+                    *b = u8::from_str_radix(&pins[i * 2..i * 2 + 2], 16)
+                        .map_err(|_| format!("line {}: bad input line", lineno))?;
+                }
+                info.inputs.push((num(f)?, p));
+            }
+            "E" => {
+                let bad = || format!("line {}: bad button event", lineno);
+                let parts: Vec<&str> = rest.split(' ').collect();
+                let [f, cycle, code, state] = parts[..] else { return Err(bad()) };
+                let idx = BUTTON_CODES.iter().position(|&c| c == code).ok_or_else(bad)?;
+                let pressed = match state {
+                    "1" => true,
+                    "0" => false,
+                    _ => return Err(bad()),
+                };
+                let cycle = cycle.parse::<u64>().map_err(|_| bad())?;
+                info.events.push((num(f)?, cycle, crate::input_overlay::BUTTONS[idx], pressed));
+            }
+            "M" => {
+                let (f, ev) = rest.split_once(' ')
+                    .ok_or_else(|| format!("line {}: bad marker", lineno))?;
+                info.marks.push((num(f)?, ev.to_string()));
+            }
+            "C" => {
+                if tag.is_none() {
+                    return Err(format!("line {}: checkpoint without chain value", lineno));
+                }
+            }
+            "end" => {
+                if tag.is_none() {
+                    return Err(format!("line {}: end without chain value", lineno));
+                }
+                info.frames = num(rest)?;
+                ended = true;
+            }
+            _ => return Err(format!("line {}: unknown record '{}'", lineno, key)),
+        }
+    }
+    if !ended {
+        return Err("log is truncated (no end line)".into());
+    }
+    Ok(info)
+}
+
+/// First line where two logs differ, as `(line number, expected, actual)`.
+pub fn first_difference(expected: &str, actual: &str) -> Option<(usize, String, String)> {
+    let mut a = expected.lines();
+    let mut b = actual.lines();
+    let mut n = 0;
+    loop {
+        n += 1;
+        match (a.next(), b.next()) {
+            (None, None) => return None,
+            (x, y) if x == y => continue,
+            (x, y) => return Some((n, x.unwrap_or("").to_string(), y.unwrap_or("").to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> String {
+        let mut v = VerifyLog::new(2, "Test", &[1, 2, 3], None, &[0xFF; 4]);
+        v.begin_frame(0, [0xFF; 5]);
+        v.end_frame(0, &[0; 8], &[0xFF; 4]);
+        v.begin_frame(1, [0xEF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        v.button_event(1, 1234, Button::A, true);
+        v.end_frame(1, &[1; 8], &[0xFF; 4]);
+        v.mark(2, "reset");
+        v.finish()
+    }
+
+    #[test]
+    fn test_log_roundtrip() {
+        let log = sample();
+        let info = parse_log(&log).unwrap();
+        assert_eq!(info.game, "Test");
+        assert_eq!(info.interval, 2);
+        assert_eq!(info.eeprom, vec![0xFF; 4]);
+        assert_eq!(info.inputs.len(), 2);
+        assert_eq!(info.inputs[1], (1, [0xEF, 0xFF, 0xFF, 0xFF, 0xFF]));
+        assert_eq!(info.events, vec![(1, 1234, Button::A, true)]);
+        assert_eq!(info.marks, vec![(2, "reset".to_string())]);
+        assert_eq!(info.frames, 2);
+        assert!(log.contains("\nC 1 sram="));
+    }
+
+    #[test]
+    fn test_tamper_detected() {
+        let log = sample();
+        // Change an input without fixing the chain
+        let edited = log.replace("I 1 EF", "I 1 FF");
+        assert!(parse_log(&edited).unwrap_err().contains("mismatch"));
+        // Drop the end line
+        let cut: String = log.lines().take(log.lines().count() - 1)
+            .map(|l| format!("{}\n", l)).collect();
+        assert!(parse_log(&cut).unwrap_err().contains("truncated"));
+        assert_eq!(first_difference(&log, &edited).map(|d| d.0), Some(8));
+    }
+}
@@ -0,0 +1,275 @@
+//! Config-defined memory-mapped virtual peripherals.
+//!
+//! Lets modders try out "what if the Arduboy had X" hardware, and lets tests
+//! stub exotic hardware, without changing the emulator. Each `[[device]]`
+//! table in a TOML file maps a small behaviour onto a data-space range:
+//!
+//! ```toml
+//! [[device]]
+//! name = "ticks"
+//! addr = 0x0B00        # above SRAM (0x0100-0x0AFF) is unused on the 32u4
+//! size = 2
+//! kind = "counter"
+//! step = 1
+//!
+//! [[device]]
+//! name = "disk"
+//! addr = 0x0C00
+//! size = 256
+//! kind = "buffer"
+//! file = "disk.bin"    # relative to the TOML file
+//! ```
+//!
+//! | Kind | Reads | Writes | Keys |
+//! |------|-------|--------|------|
+//! | `constant` | Bytes of `value`, little-endian (0 past the fourth) | Ignored | `value` |
+//! | `counter` | Reading offset 0 latches the count and advances it by `step`; other offsets read the latched bytes | Set bytes of the next count | `start`, `step` |
+//! | `rng` | A fresh pseudo-random byte (seeded, repeatable) | Reseed | `seed` |
+//! | `buffer` | Stored bytes | Stored | `file` (optional) |
+//!
+//! `addr` and `size` (default 1) are required to stay within `0x20..=0xFFFF`
+//! and devices may not overlap. A device shadows whatever the address would
+//! otherwise map to, including I/O registers, so CPU loads and stores reach it
+//! first. `buffer` devices start from their file (zero-padded) and
+//! [`VirtualDevices::flush`] writes changed ones back. Device state is not
+//! part of save states.
+
+use std::path::{Path, PathBuf};
+
+use crate::toml_lite;
+
+/// What a device does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceKind {
+    Constant(u32),
+    Counter { next: u32, step: u32, latched: u32 },
+    Rng(u32),
+    Buffer { data: Vec<u8>, file: Option<PathBuf>, dirty: bool },
+}
+
+/// One mapped device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VirtualDevice {
+    pub name: String,
+    pub addr: u16,
+    pub size: u16,
+    pub kind: DeviceKind,
+    pub reads: u64,
+    pub writes: u64,
+}
+
+impl VirtualDevice {
+    fn contains(&self, addr: u16) -> bool {
+        addr >= self.addr && ((addr - self.addr) as u32) < self.size as u32
+    }
+
+    fn read(&mut self, offset: usize) -> u8 {
+        self.reads += 1;
+        match &mut self.kind {
+            DeviceKind::Constant(v) => v.to_le_bytes().get(offset).copied().unwrap_or(0),
+            DeviceKind::Counter { next, step, latched } => {
+                if offset == 0 {
+                    *latched = *next;
+                    *next = next.wrapping_add(*step);
+                }
+                latched.to_le_bytes().get(offset).copied().unwrap_or(0)
+            }
+            DeviceKind::Rng(state) => {
+                // xorshift32
+                *state ^= *state << 13;
+                *state ^= *state >> 17;
+                *state ^= *state << 5;
+                (*state >> 24) as u8
+            }
+            DeviceKind::Buffer { data, .. } => data[offset],
+        }
+    }
+
+    fn write(&mut self, offset: usize, value: u8) {
+        self.writes += 1;
+        match &mut self.kind {
+            DeviceKind::Constant(_) => {}
+            DeviceKind::Counter { next, .. } => {
+                let mut b = next.to_le_bytes();
+                if let Some(slot) = b.get_mut(offset) {
+                    *slot = value;
+                    *next = u32::from_le_bytes(b);
+                }
+            }
+            DeviceKind::Rng(state) => *state = (*state << 8 | value as u32).max(1),
+            DeviceKind::Buffer { data, dirty, .. } => {
+                *dirty |= data[offset] != value;
+                data[offset] = value;
+            }
+        }
+    }
+}
+
+/// Devices registered on the data bus (none by default).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VirtualDevices {
+    pub devices: Vec<VirtualDevice>,
+}
+
+impl VirtualDevices {
+    /// Parse `[[device]]` tables; `file` paths are resolved against `base_dir`
+    /// and read immediately.
+    pub fn parse(text: &str, base_dir: &Path) -> Result<Self, String> {
+        let doc = toml_lite::parse(text)?;
+        if let Some((key, _)) = doc.root.first() {
+            return Err(format!("{}: keys belong in [[device]] tables", key));
+        }
+        let mut out = VirtualDevices::default();
+        for (i, (table, t)) in doc.tables.iter().enumerate() {
+            if table != "device" {
+                return Err(format!("[[{}]]: unknown table (expected [[device]])", table));
+            }
+            let label = match t.get("name") {
+                Some(v) => v.as_str("name")?.to_string(),
+                None => format!("device{}", i),
+            };
+            let err = |e: String| format!("device \"{}\": {}", label, e);
+            let num = |key: &str, default: Option<u32>| -> Result<u32, String> {
+                match t.get(key) {
+                    Some(v) => v.as_u32(key).map_err(err),
+                    None => default.ok_or_else(|| err(format!("missing {}", key))),
+                }
+            };
+            for (key, _) in &t.0 {
+                if !matches!(key.as_str(), "name" | "addr" | "size" | "kind" | "value" | "start" | "step" | "seed" | "file") {
+                    return Err(err(format!("unknown key '{}'", key)));
+                }
+            }
+            let addr = num("addr", None)?;
+            let size = num("size", Some(1))?;
+            if addr < 0x20 || size == 0 || addr + size > 0x10000 {
+                return Err(err(format!("range 0x{:04X}+{} must lie within 0x0020-0xFFFF", addr, size)));
+            }
+            let kind = match t.get("kind").map(|v| v.as_str("kind")).transpose()?.unwrap_or("buffer") {
+                "constant" => DeviceKind::Constant(num("value", Some(0))?),
+                "counter" => {
+                    let start = num("start", Some(0))?;
+                    DeviceKind::Counter { next: start, step: num("step", Some(1))?, latched: start }
+                }
+                "rng" => DeviceKind::Rng(num("seed", Some(0x2545_F491))?.max(1)),
+                "buffer" => {
+                    let mut data = vec![0u8; size as usize];
+                    let file = match t.get("file") {
+                        Some(v) => {
+                            let path = base_dir.join(v.as_str("file")?);
+                            if path.exists() {
+                                let bytes = std::fs::read(&path)
+                                    .map_err(|e| err(format!("{}: {}", path.display(), e)))?;
+                                let n = bytes.len().min(data.len());
+                                data[..n].copy_from_slice(&bytes[..n]);
+                            }
+                            Some(path)
+                        }
+                        None => None,
+                    };
+                    DeviceKind::Buffer { data, file, dirty: false }
+                }
+                other => return Err(err(format!("unknown kind '{}' (constant, counter, rng, buffer)", other))),
+            };
+            let dev = VirtualDevice { name: label.clone(), addr: addr as u16, size: size as u16, kind, reads: 0, writes: 0 };
+            let end = addr + size - 1;
+            if let Some(o) = out.devices.iter().find(|o| o.contains(addr as u16) || dev.contains(o.addr)) {
+                return Err(err(format!("0x{:04X}-0x{:04X} overlaps \"{}\"", addr, end, o.name)));
+            }
+            out.devices.push(dev);
+        }
+        Ok(out)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or(Path::new("."));
+        Self::parse(&text, dir).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.devices.is_empty()
+    }
+
+    /// CPU load from `addr`, or None if no device maps it.
+    pub fn read(&mut self, addr: u16) -> Option<u8> {
+        let dev = self.devices.iter_mut().find(|d| d.contains(addr))?;
+        let offset = (addr - dev.addr) as usize;
+        Some(dev.read(offset))
+    }
+
+    /// CPU store to `addr`; returns false if no device maps it.
+    pub fn write(&mut self, addr: u16, value: u8) -> bool {
+        match self.devices.iter_mut().find(|d| d.contains(addr)) {
+            Some(dev) => {
+                let offset = (addr - dev.addr) as usize;
+                dev.write(offset, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Write changed file-backed buffers back; returns how many were written.
+    pub fn flush(&mut self) -> Result<usize, String> {
+        let mut written = 0;
+        for dev in self.devices.iter_mut() {
+            if let DeviceKind::Buffer { data, file: Some(path), dirty } = &mut dev.kind {
+                if *dirty {
+                    std::fs::write(&*path, &*data).map_err(|e| format!("{}: {}", path.display(), e))?;
+                    *dirty = false;
+                    written += 1;
+                }
+            }
+        }
+        Ok(written)
+    }
+
+    /// One line per device with its access counts.
+    pub fn list(&self) -> String {
+        self.devices.iter()
+            .map(|d| {
+                let kind = match &d.kind {
+                    DeviceKind::Constant(v) => format!("constant 0x{:X}", v),
+                    DeviceKind::Counter { next, step, .. } => format!("counter next={} step={}", next, step),
+                    DeviceKind::Rng(_) => "rng".to_string(),
+                    DeviceKind::Buffer { file: Some(p), .. } => format!("buffer {}", p.display()),
+                    DeviceKind::Buffer { .. } => "buffer".to_string(),
+                };
+                format!("{:<12} 0x{:04X}-0x{:04X} {}: {} reads, {} writes\n",
+                    d.name, d.addr, d.addr as u32 + d.size as u32 - 1, kind, d.reads, d.writes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_devices_on_bus() {
+        let text = "[[device]]\nname = \"id\"\naddr = 0x0B00\nsize = 2\nkind = \"constant\"\nvalue = 0xAB12\n\
+                    [[device]]\nname = \"ticks\"\naddr = 0x0B02\nsize = 2\nkind = \"counter\"\nstart = 0x00FF\nstep = 2\n\
+                    [[device]]\nname = \"ram\"\naddr = 0x0C00\nsize = 4\n";
+        let mut ard = crate::Arduboy::new();
+        ard.devices = VirtualDevices::parse(text, Path::new(".")).unwrap();
+        assert_eq!((ard.read_data(0x0B00), ard.read_data(0x0B01)), (0x12, 0xAB));
+        assert_eq!((ard.read_data(0x0B02), ard.read_data(0x0B03)), (0xFF, 0x00));
+        assert_eq!((ard.read_data(0x0B02), ard.read_data(0x0B03)), (0x01, 0x01));
+        ard.write_data(0x0C03, 0x5A);
+        assert_eq!(ard.read_data(0x0C03), 0x5A);
+        assert_eq!(ard.read_data(0x0C04), 0);
+        assert!(ard.devices.list().contains("ram          0x0C00-0x0C03 buffer: 1 reads, 1 writes"));
+
+        let rng = "[[device]]\naddr = 0x0D00\nkind = \"rng\"\nseed = 7\n";
+        let mut a = VirtualDevices::parse(rng, Path::new(".")).unwrap();
+        let mut b = a.clone();
+        assert_eq!(a.read(0x0D00), b.read(0x0D00));
+
+        let overlap = "[[device]]\naddr = 0x0B00\nsize = 4\n[[device]]\nname = \"x\"\naddr = 0x0B03\n";
+        assert!(VirtualDevices::parse(overlap, Path::new(".")).unwrap_err().contains("overlaps"));
+        assert!(VirtualDevices::parse("[[device]]\naddr = 0x10\n", Path::new(".")).is_err());
+        assert!(VirtualDevices::parse("[[device]]\naddr = 0x100\nkind = \"gpu\"\n", Path::new(".")).is_err());
+    }
+}
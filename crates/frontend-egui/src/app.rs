@@ -0,0 +1,464 @@
+//! Emulator state, run control and the window layout.
+//!
+//! Panels dock as tabs in the left, right, bottom or central area, or float
+//! as windows; the View menu or a right-click on a tab moves them.
+
+use crate::panels;
+use arduboy_core::actions::{Action, Actions};
+use arduboy_core::elf::ElfFile;
+use arduboy_core::{Arduboy, Button, RealtimeSync, FRAME_CYCLES};
+use eframe::egui::{self, Key, TextureHandle, Ui};
+use std::time::Instant;
+
+/// Serial output kept in the console
+const SERIAL_LOG_MAX: usize = 64 * 1024;
+
+const KEYS: [(Key, Button); 6] = [
+    (Key::ArrowUp, Button::Up),
+    (Key::ArrowDown, Button::Down),
+    (Key::ArrowLeft, Button::Left),
+    (Key::ArrowRight, Button::Right),
+    (Key::Z, Button::A),
+    (Key::X, Button::B),
+];
+
+/// Where a panel is shown.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Dock {
+    Left,
+    Right,
+    Bottom,
+    Center,
+    Floating,
+}
+
+impl Dock {
+    pub const ALL: [Dock; 5] = [Dock::Left, Dock::Right, Dock::Bottom, Dock::Center, Dock::Floating];
+    /// Docked areas, side panels first: egui lays out the central area
+    /// in the space they leave.
+    const AREAS: [Dock; 4] = [Dock::Left, Dock::Right, Dock::Bottom, Dock::Center];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Dock::Left => "Left",
+            Dock::Right => "Right",
+            Dock::Bottom => "Bottom",
+            Dock::Center => "Center",
+            Dock::Floating => "Floating",
+        }
+    }
+}
+
+/// A debugger view, shown (View menu) in its dock area.
+pub struct Panel {
+    pub title: &'static str,
+    pub open: bool,
+    pub dock: Dock,
+    body: fn(&mut Ui, &mut DebuggerApp),
+}
+
+impl Panel {
+    fn new(title: &'static str, dock: Dock, open: bool, body: fn(&mut Ui, &mut DebuggerApp)) -> Self {
+        Panel { title, open, dock, body }
+    }
+}
+
+pub struct DebuggerApp {
+    pub arduboy: Arduboy,
+    /// Symbols of an ELF program
+    pub elf: Option<ElfFile>,
+    pub running: bool,
+    sync: RealtimeSync,
+    /// Why execution last stopped
+    pub status: String,
+    /// R0-R31 and SREG before the last step or resume
+    pub prev_regs: [u8; 32],
+    pub prev_sreg: u8,
+    pub panels: Vec<Panel>,
+    /// Selected tab (panel index) of each docked area
+    tabs: [usize; 4],
+    pub screen: Option<TextureHandle>,
+    pub scale: u32,
+    /// PC the disassembly last scrolled to
+    pub disasm_pc: Option<u32>,
+    pub ram_goto: String,
+    pub ram_scroll: Option<usize>,
+    pub ram_sel: Option<usize>,
+    pub ram_edit: String,
+    pub io_all: bool,
+    pub bp_input: String,
+    pub bp_error: Option<String>,
+    pub serial_log: String,
+    pub serial_input: String,
+    /// Hotkeys shared with the minifb frontend (`--bind`)
+    actions: Actions,
+}
+
+impl DebuggerApp {
+    pub fn new(arduboy: Arduboy, elf: Option<ElfFile>, actions: Actions, run: bool) -> Self {
+        let mut app = DebuggerApp {
+            arduboy,
+            elf,
+            running: false,
+            sync: RealtimeSync::at_frame_rate(60),
+            status: String::new(),
+            prev_regs: [0; 32],
+            prev_sreg: 0,
+            panels: vec![
+                Panel::new("Screen", Dock::Center, true, panels::screen),
+                Panel::new("Registers", Dock::Left, true, panels::registers),
+                Panel::new("I/O", Dock::Left, false, panels::io),
+                Panel::new("Disassembly", Dock::Right, true, panels::disassembly),
+                Panel::new("Breakpoints", Dock::Right, true, panels::breakpoints),
+                Panel::new("RAM", Dock::Bottom, false, panels::ram),
+                Panel::new("Profiler", Dock::Bottom, false, panels::profiler),
+                Panel::new("Serial", Dock::Bottom, false, panels::serial),
+            ],
+            tabs: [0; 4],
+            screen: None,
+            scale: 4,
+            disasm_pc: None,
+            ram_goto: String::new(),
+            ram_scroll: None,
+            ram_sel: None,
+            ram_edit: String::new(),
+            io_all: false,
+            bp_input: String::new(),
+            bp_error: None,
+            serial_log: String::new(),
+            serial_input: String::new(),
+            actions,
+        };
+        app.snapshot();
+        if run {
+            app.resume();
+        } else {
+            app.stop("Paused");
+        }
+        app
+    }
+
+    /// Flash location of the PC, with the ELF symbol and source line.
+    pub fn describe(&self, pc: u32) -> String {
+        match self.elf.as_ref().map(|e| e.describe_pc(pc)) {
+            Some(d) if !d.is_empty() => format!("0x{:04X} {}", pc * 2, d),
+            _ => format!("0x{:04X}", pc * 2),
+        }
+    }
+
+    /// Flash word address of a hex byte address or ELF symbol.
+    pub fn parse_location(&self, spec: &str) -> Result<u32, String> {
+        let spec = spec.trim();
+        let hex = spec.trim_start_matches("0x").trim_start_matches("0X");
+        if let Ok(addr) = u32::from_str_radix(hex, 16) {
+            return match addr {
+                a if (a as usize) < self.arduboy.mem.flash.len() => Ok(a / 2),
+                _ => Err(format!("0x{:X} is outside flash", addr)),
+            };
+        }
+        self.elf.as_ref()
+            .and_then(|e| e.symbols.iter().find(|(_, name)| name.as_str() == spec))
+            .map(|(addr, _)| addr / 2)
+            .ok_or_else(|| format!("unknown address or symbol '{}'", spec))
+    }
+
+    fn snapshot(&mut self) {
+        self.prev_regs.copy_from_slice(&self.arduboy.mem.data[..32]);
+        self.prev_sreg = self.arduboy.cpu.sreg;
+    }
+
+    fn stop(&mut self, why: &str) {
+        self.running = false;
+        self.status = format!("{} at {}", why, self.describe(self.arduboy.cpu.pc));
+    }
+
+    /// Leave a breakpoint the PC is sitting on, which would stop the next
+    /// frame before it executes anything.
+    fn step_off_breakpoint(&mut self) {
+        self.arduboy.breakpoint_hit = false;
+        if self.arduboy.breakpoints.contains(&self.arduboy.cpu.pc) {
+            self.arduboy.step_one();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.snapshot();
+        self.step_off_breakpoint();
+        self.running = true;
+        self.status = "Running".into();
+        self.sync.reset();
+    }
+
+    pub fn pause(&mut self) {
+        self.stop("Paused");
+    }
+
+    pub fn step(&mut self) {
+        self.snapshot();
+        self.arduboy.breakpoint_hit = false;
+        self.arduboy.step_one();
+        self.collect_output();
+        if !self.check_break() {
+            self.stop("Stepped");
+        }
+    }
+
+    pub fn step_frame(&mut self) {
+        self.snapshot();
+        self.step_off_breakpoint();
+        self.arduboy.run_frame();
+        self.collect_output();
+        if !self.check_break() {
+            self.stop(&format!("Frame {}", self.arduboy.frame_count()));
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.arduboy.reset();
+        self.snapshot();
+        if self.running {
+            self.sync.reset();
+        } else {
+            self.stop("Reset");
+        }
+    }
+
+    /// Stop if the last frame or step hit a breakpoint or watchpoint.
+    fn check_break(&mut self) -> bool {
+        if let Some(hit) = self.arduboy.debugger.take_hit() {
+            self.arduboy.breakpoint_hit = false;
+            let what = match hit.protect_pc {
+                Some(pc) => format!("Write-protect [{}]: 0x{:04X} {:02X} → {:02X} written by {}",
+                    hit.index, hit.addr, hit.old_val, hit.new_val, self.describe(pc / 2)),
+                None => format!("Watchpoint [{}] {:?} 0x{:04X} {:02X} → {:02X}",
+                    hit.index, hit.access, hit.addr, hit.old_val, hit.new_val),
+            };
+            self.stop(&what);
+            return true;
+        }
+        if self.arduboy.breakpoint_hit {
+            self.arduboy.breakpoint_hit = false;
+            self.stop("Breakpoint");
+            return true;
+        }
+        false
+    }
+
+    /// Run the frames real time asks for.
+    fn run_due_frames(&mut self) {
+        for _ in 0..self.sync.frames_due(Instant::now()) {
+            self.arduboy.run_frame();
+            self.sync.ran(FRAME_CYCLES);
+            if self.check_break() {
+                break;
+            }
+        }
+        self.collect_output();
+    }
+
+    fn collect_output(&mut self) {
+        let out = self.arduboy.take_serial_output();
+        if out.is_empty() {
+            return;
+        }
+        self.serial_log.push_str(&String::from_utf8_lossy(&out));
+        if self.serial_log.len() > SERIAL_LOG_MAX {
+            let mut cut = self.serial_log.len() - SERIAL_LOG_MAX;
+            while !self.serial_log.is_char_boundary(cut) {
+                cut += 1;
+            }
+            self.serial_log.drain(..cut);
+        }
+    }
+
+    fn handle_keys(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() {
+            return;
+        }
+        let (held, run, step, frame) = ctx.input(|i| (
+            KEYS.map(|(k, _)| i.key_down(k)),
+            i.key_pressed(Key::F5),
+            i.key_pressed(Key::F10),
+            i.key_pressed(Key::F11),
+        ));
+        for ((_, b), down) in KEYS.iter().zip(held) {
+            self.arduboy.set_button(*b, down);
+        }
+        if run {
+            if self.running { self.pause() } else { self.resume() }
+        } else if step && !self.running {
+            self.step();
+        } else if frame && !self.running {
+            self.step_frame();
+        }
+
+        let actions = &mut self.actions;
+        ctx.input(|i| actions.update(|name| match name {
+            "Shift" => i.modifiers.shift,
+            n => Key::from_name(n).is_some_and(|k| i.key_down(k)),
+        }));
+        for n in 1..=6 {
+            if self.actions.pressed(Action::Scale(n)) {
+                self.scale = n as u32;
+            }
+        }
+        if self.actions.pressed(Action::Rotate) {
+            self.arduboy.rotation = self.arduboy.rotation.next();
+        }
+        if self.actions.pressed(Action::Profiler) {
+            let tick = self.arduboy.cpu.tick;
+            if self.arduboy.profiler.enabled {
+                self.arduboy.profiler.stop(tick);
+                self.show_panel("Profiler");
+            } else {
+                self.arduboy.profiler.start(tick);
+            }
+        }
+    }
+
+    fn toolbar(&mut self, ui: &mut Ui) {
+        egui::MenuBar::new().ui(ui, |ui| {
+            ui.menu_button("File", |ui| {
+                if ui.button("Reset").clicked() {
+                    self.reset();
+                }
+                if ui.button("Quit").clicked() {
+                    ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close);
+                }
+            });
+            ui.menu_button("View", |ui| {
+                for i in 0..self.panels.len() {
+                    ui.horizontal(|ui| {
+                        let p = &mut self.panels[i];
+                        ui.checkbox(&mut p.open, p.title);
+                        ui.menu_button(p.dock.name(), |ui| self.dock_menu(ui, i));
+                    });
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            if self.running {
+                if ui.button("Pause (F5)").clicked() {
+                    self.pause();
+                }
+            } else if ui.button("Run (F5)").clicked() {
+                self.resume();
+            }
+            ui.add_enabled_ui(!self.running, |ui| {
+                if ui.button("Step (F10)").clicked() {
+                    self.step();
+                }
+                if ui.button("Step frame (F11)").clicked() {
+                    self.step_frame();
+                }
+            });
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+            ui.separator();
+            if self.running {
+                ui.label(format!("Running — frame {}", self.arduboy.frame_count()));
+            } else {
+                ui.label(&self.status);
+            }
+        });
+    }
+
+    /// Open a panel and select its tab.
+    fn show_panel(&mut self, title: &str) {
+        if let Some(i) = self.panels.iter().position(|p| p.title == title) {
+            self.panels[i].open = true;
+            if let Some(area) = Dock::AREAS.iter().position(|&d| d == self.panels[i].dock) {
+                self.tabs[area] = i;
+            }
+        }
+    }
+
+    /// Dock area choice and close button of a panel.
+    fn dock_menu(&mut self, ui: &mut Ui, i: usize) {
+        let p = &mut self.panels[i];
+        for dock in Dock::ALL {
+            ui.radio_value(&mut p.dock, dock, dock.name());
+        }
+        ui.separator();
+        if ui.button("Close").clicked() {
+            p.open = false;
+        }
+    }
+
+    /// Show the open panels docked in `area` as tabs above the selected
+    /// one. The central area is always shown, empty or not.
+    fn dock_area(&mut self, ctx: &egui::Context, area: usize) {
+        let dock = Dock::AREAS[area];
+        let tabs: Vec<usize> = (0..self.panels.len())
+            .filter(|&i| self.panels[i].open && self.panels[i].dock == dock)
+            .collect();
+        if tabs.is_empty() && dock != Dock::Center {
+            return;
+        }
+        if !tabs.contains(&self.tabs[area]) {
+            self.tabs[area] = tabs.first().copied().unwrap_or(0);
+        }
+        let contents = |ui: &mut Ui, app: &mut DebuggerApp| {
+            ui.horizontal_wrapped(|ui| {
+                for &i in &tabs {
+                    let tab = ui.selectable_label(app.tabs[area] == i, app.panels[i].title);
+                    if tab.clicked() {
+                        app.tabs[area] = i;
+                    }
+                    tab.context_menu(|ui| app.dock_menu(ui, i));
+                }
+            });
+            ui.separator();
+            if tabs.contains(&app.tabs[area]) {
+                let body = app.panels[app.tabs[area]].body;
+                body(ui, app);
+            }
+        };
+        match dock {
+            Dock::Left => egui::SidePanel::left("dock-left")
+                .default_width(320.0)
+                .show(ctx, |ui| contents(ui, self)),
+            Dock::Right => egui::SidePanel::right("dock-right")
+                .default_width(360.0)
+                .show(ctx, |ui| contents(ui, self)),
+            Dock::Bottom => egui::TopBottomPanel::bottom("dock-bottom")
+                .resizable(true)
+                .default_height(240.0)
+                .show(ctx, |ui| contents(ui, self)),
+            Dock::Center | Dock::Floating => egui::CentralPanel::default().show(ctx, |ui| contents(ui, self)),
+        };
+    }
+
+    /// Show the open floating panels as windows.
+    fn floating(&mut self, ctx: &egui::Context) {
+        for i in 0..self.panels.len() {
+            let Panel { title, open, dock, body } = self.panels[i];
+            if !open || dock != Dock::Floating {
+                continue;
+            }
+            let mut open = true;
+            egui::Window::new(title).open(&mut open).show(ctx, |ui| body(ui, self));
+            self.panels[i].open = open;
+        }
+    }
+}
+
+impl eframe::App for DebuggerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.handle_keys(ctx);
+        if self.running {
+            self.run_due_frames();
+        }
+
+        egui::TopBottomPanel::top("toolbar").show(ctx, |ui| self.toolbar(ui));
+        for area in 0..Dock::AREAS.len() {
+            self.dock_area(ctx, area);
+        }
+        self.floating(ctx);
+
+        if self.running {
+            ctx.request_repaint_after(self.sync.sleep_time(Instant::now()));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Debugger frontend for arduboy-emu built on egui.
+//!
+//! Shows the game screen next to the debugger views of the terminal
+//! debugger (`--step`) as dockable panels. Each docks as a tab in the left,
+//! right, bottom or central area or floats as a window; the View menu shows,
+//! hides and moves them, as does a right-click on a tab:
+//!
+//! | Panel | Shows |
+//! |-------|-------|
+//! | Screen | Display output; game keys work while no text field has focus |
+//! | Registers | R0-R31, pointers, SREG; values changed by the last step in yellow |
+//! | Disassembly | Code around the PC; click a line to toggle a breakpoint |
+//! | RAM | Data space as hex; click a byte to edit it |
+//! | I/O | Named I/O registers |
+//! | Breakpoints | Breakpoint list; add by address or ELF symbol |
+//! | Profiler | Hot spots and call graph of the profiled run |
+//! | Serial | USB serial output and input line |
+//!
+//! Scale (1-6), rotation (V) and the profiler (T) use the hotkeys of
+//! `arduboy_core::actions`, rebindable with `--bind` as in the minifb
+//! frontend. Audio is not supported.
+
+mod app;
+mod panels;
+
+use arduboy_core::actions::Actions;
+use arduboy_core::annotations::Annotations;
+use arduboy_core::game_file::GameFile;
+use arduboy_core::{BoardProfile, CpuType, Rotation};
+use std::env;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args[1].starts_with("--") {
+        eprintln!("Usage: arduboy-egui <file.hex|file.arduboy|file.elf> [options]");
+        eprintln!();
+        eprintln!("Options:");
+        eprintln!("  --fx <file>          FX flash data");
+        eprintln!("  --cpu <type>         CPU type: 32u4, 328p or 2560 (auto-detected if omitted)");
+        eprintln!("  --board <name|file>  Board profile (arduboy, gamebuino, ... or TOML file)");
+        eprintln!("  --rotate <deg>       Screen rotation: 0, 90, 180, 270 (default: from .arduboy metadata)");
+        eprintln!("  --break <addr>       Breakpoint at a flash byte address (hex), may be repeated");
+        eprintln!("  --symbols <file>     User symbols and comments (default: <game>.sym if present)");
+        eprintln!("  --paused             Start paused at the reset vector");
+        eprintln!("  --bind <act=keys>    Rebind a hotkey, e.g. rotate=R (repeatable)");
+        eprintln!();
+        eprintln!("Keys: Arrows=D-pad Z=A X=B  F5=Run/Pause F10=Step F11=Step frame");
+        eprintln!("      1-6=Scale V=Rotate T=Profiler start/stop");
+        std::process::exit(1);
+    }
+
+    if let Err(e) = run(&args) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Value following option `name`.
+fn opt<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let game_path = &args[1];
+    let cpu = match opt(args, "--cpu") {
+        Some("328p") => Some(CpuType::Atmega328p),
+        Some("32u4") => Some(CpuType::Atmega32u4),
+        Some("2560") => Some(CpuType::Atmega2560),
+        Some(other) => return Err(format!("--cpu: unknown type '{}'", other)),
+        None => None,
+    };
+    let board = opt(args, "--board").map(BoardProfile::load).transpose()?;
+    let game = GameFile::load(game_path, opt(args, "--fx")).map_err(|e| format!("{}: {}", game_path, e))?;
+    let (cpu, detected) = game.cpu(board.as_ref(), cpu);
+    if let Some(d) = detected.filter(|d| d.confidence < 0.9) {
+        eprintln!("CPU auto-detected: {} (override with --cpu)", d.summary());
+    }
+    let (mut arduboy, mut elf) = game.start(board, cpu)?;
+    // User symbols and comments: --symbols <file>, or <game>.sym next to the game
+    let sym_path = opt(args, "--symbols").map(PathBuf::from)
+        .unwrap_or_else(|| Annotations::path_for(Path::new(game_path)));
+    if opt(args, "--symbols").is_some() || sym_path.exists() {
+        let ann = Annotations::load(&sym_path).map_err(|e| format!("--symbols: {}: {}", sym_path.display(), e))?;
+        match elf.as_mut() {
+            Some(elf) => ann.apply(elf),
+            None => elf = Some(ann.to_elf()),
+        }
+    }
+    if let Some(spec) = opt(args, "--rotate") {
+        arduboy.rotation = Rotation::parse(spec).map_err(|e| format!("--rotate: {}", e))?;
+    }
+
+    for (i, a) in args.iter().enumerate() {
+        if a == "--break" {
+            let spec = args.get(i + 1).ok_or("--break: missing address")?;
+            let addr = u32::from_str_radix(spec.trim_start_matches("0x").trim_start_matches("0X"), 16)
+                .map_err(|_| format!("--break: bad address '{}'", spec))?;
+            arduboy.breakpoints.push(addr / 2);
+        }
+    }
+    let paused = args.iter().any(|a| a == "--paused");
+    let mut actions = Actions::new();
+    for (i, _) in args.iter().enumerate().filter(|(_, a)| *a == "--bind") {
+        actions.bind(args.get(i + 1).map_or("", |s| s.as_str())).map_err(|e| format!("--bind: {}", e))?;
+    }
+
+    let title = format!("arduboy-egui — {}", game_path);
+    let options = eframe::NativeOptions {
+        viewport: eframe::egui::ViewportBuilder::default()
+            .with_title(&title)
+            .with_inner_size([1180.0, 780.0]),
+        ..Default::default()
+    };
+    let app = app::DebuggerApp::new(arduboy, elf, actions, !paused);
+    eframe::run_native(&title, options, Box::new(|_cc| Ok(Box::new(app))))
+        .map_err(|e| e.to_string())
+}
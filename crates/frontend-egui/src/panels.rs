@@ -0,0 +1,271 @@
+//! Panel contents. Each takes the `Ui` of its tab or window and the app state.
+
+use crate::app::DebuggerApp;
+use arduboy_core::disasm;
+use eframe::egui::{self, Align, Color32, ColorImage, Key, Label, RichText, ScrollArea, Sense, TextEdit, TextureOptions, Ui};
+
+/// Changed since the last step or resume
+const CHANGED: Color32 = Color32::YELLOW;
+const CURRENT: Color32 = Color32::LIGHT_GREEN;
+const BREAK: Color32 = Color32::LIGHT_RED;
+const SYMBOL: Color32 = Color32::LIGHT_BLUE;
+
+/// Flash bytes disassembled before and after the PC
+const DISASM_BEFORE: usize = 48;
+const DISASM_AFTER: usize = 96;
+
+fn mono(text: impl Into<String>) -> RichText {
+    RichText::new(text).monospace()
+}
+
+/// Enter pressed in a text field that just lost focus.
+fn submitted(ui: &Ui, r: &egui::Response) -> bool {
+    r.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter))
+}
+
+pub fn screen(ui: &mut Ui, app: &mut DebuggerApp) {
+    let (px, w, h) = app.arduboy.screen_output();
+    let rgb: Vec<u8> = px.iter()
+        .flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8])
+        .collect();
+    let image = ColorImage::from_rgb([w, h], &rgb);
+    let tex = match &mut app.screen {
+        Some(t) => {
+            t.set(image, TextureOptions::NEAREST);
+            t
+        }
+        None => app.screen.insert(ui.ctx().load_texture("screen", image, TextureOptions::NEAREST)),
+    };
+    let scale = app.scale as f32;
+    // Non-square pixels are wide on the panel, whichever way it is turned
+    let aspect = app.arduboy.pixel_aspect();
+    let size = match app.arduboy.rotation.swaps_axes() {
+        false => egui::vec2(w as f32 * scale * aspect, h as f32 * scale),
+        true => egui::vec2(w as f32 * scale, h as f32 * scale * aspect),
+    };
+    ui.add(egui::Image::new(&*tex).fit_to_exact_size(size));
+    if !app.arduboy.display_powered_on() {
+        ui.label("Display off");
+    }
+    ui.add(egui::Slider::new(&mut app.scale, 1..=8).text("scale"));
+}
+
+pub fn registers(ui: &mut Ui, app: &mut DebuggerApp) {
+    let a = &app.arduboy;
+    let reg = |text: String, changed: bool| match changed {
+        true => mono(text).color(CHANGED),
+        false => mono(text),
+    };
+    egui::Grid::new("regs").num_columns(8).show(ui, |ui| {
+        for i in 0..32 {
+            let v = a.mem.data[i];
+            ui.label(reg(format!("R{:<2} {:02X}", i, v), v != app.prev_regs[i]));
+            if i % 8 == 7 {
+                ui.end_row();
+            }
+        }
+    });
+    ui.separator();
+    ui.label(mono(format!("PC {:04X}  SP {:04X}  X {:04X}  Y {:04X}  Z {:04X}",
+        a.cpu.pc * 2, a.cpu.sp, a.mem.x(), a.mem.y(), a.mem.z())));
+    ui.label(reg(format!("SREG {} (0x{:02X})", disasm::format_sreg(a.cpu.sreg), a.cpu.sreg),
+        a.cpu.sreg != app.prev_sreg));
+    ui.label(mono(format!("Cycles {}  Frame {}", a.cpu.tick, a.frame_count())));
+}
+
+/// Code around the PC, scrolled to it whenever it moves.
+pub fn disassembly(ui: &mut Ui, app: &mut DebuggerApp) {
+    let pc = app.arduboy.cpu.pc;
+    let pc_byte = pc as usize * 2;
+    let flash = &app.arduboy.mem.flash;
+    // Decode up to the PC and from it separately, so a two-word
+    // instruction straddling the start cannot hide the PC line
+    let mut lines = disasm::disassemble_range(flash, pc_byte.saturating_sub(DISASM_BEFORE), pc_byte);
+    lines.extend(disasm::disassemble_range(flash, pc_byte, pc_byte + DISASM_AFTER));
+    let follow = app.disasm_pc != Some(pc);
+    app.disasm_pc = Some(pc);
+
+    let mut toggle = None;
+    ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+        for line in &lines {
+            let addr = line.get(2..).and_then(|l| l.split_once(':')).and_then(|(h, _)| usize::from_str_radix(h, 16).ok()).unwrap_or(0);
+            if let Some(name) = app.elf.as_ref().and_then(|e| e.symbols.get(&(addr as u32))) {
+                ui.label(mono(format!("<{}>:", name)).color(SYMBOL));
+            }
+            let word = (addr / 2) as u32;
+            let bp = app.arduboy.breakpoints.contains(&word);
+            let comment = app.elf.as_ref().and_then(|e| e.comments.get(&(addr as u32)))
+                .map(|c| format!("  ; {}", c)).unwrap_or_default();
+            let text = mono(format!("{}{} {}{}", if bp { '*' } else { ' ' }, if addr == pc_byte { '>' } else { ' ' }, line, comment));
+            let text = match (addr == pc_byte, bp) {
+                (true, _) => text.color(CURRENT).strong(),
+                (false, true) => text.color(BREAK),
+                _ => text,
+            };
+            let r = ui.add(Label::new(text).sense(Sense::click()).selectable(false));
+            if addr == pc_byte && follow {
+                r.scroll_to_me(Some(Align::Center));
+            }
+            if r.on_hover_text("Click to toggle a breakpoint").clicked() {
+                toggle = Some(word);
+            }
+        }
+    });
+    if let Some(word) = toggle {
+        let bps = &mut app.arduboy.breakpoints;
+        match bps.iter().position(|&b| b == word) {
+            Some(i) => { bps.remove(i); }
+            None => bps.push(word),
+        }
+    }
+}
+
+/// Data space as a hex dump. Edits go straight to memory, without the side
+/// effects a CPU write to an I/O register would have.
+pub fn ram(ui: &mut Ui, app: &mut DebuggerApp) {
+    ui.horizontal(|ui| {
+        ui.label("Go to");
+        let r = ui.add(TextEdit::singleline(&mut app.ram_goto).desired_width(60.0).hint_text("0x0100"));
+        if submitted(ui, &r) {
+            let hex = app.ram_goto.trim().trim_start_matches("0x").trim_start_matches("0X");
+            if let Ok(addr) = usize::from_str_radix(hex, 16) {
+                let addr = addr.min(app.arduboy.mem.data.len() - 1);
+                app.ram_scroll = Some(addr / 16);
+                app.ram_sel = Some(addr);
+                app.ram_edit = format!("{:02X}", app.arduboy.mem.data[addr]);
+            }
+        }
+        if let Some(addr) = app.ram_sel {
+            ui.separator();
+            ui.label(mono(format!("[0x{:04X}] =", addr)));
+            let r = ui.add(TextEdit::singleline(&mut app.ram_edit).desired_width(30.0));
+            if submitted(ui, &r) {
+                if let Ok(v) = u8::from_str_radix(app.ram_edit.trim(), 16) {
+                    app.arduboy.mem.data[addr] = v;
+                }
+            }
+        }
+    });
+    ui.separator();
+
+    let data = &app.arduboy.mem.data;
+    let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+    let mut area = ScrollArea::vertical().auto_shrink([false, false]);
+    if let Some(row) = app.ram_scroll.take() {
+        area = area.vertical_scroll_offset(row as f32 * (row_height + ui.spacing().item_spacing.y));
+    }
+    let mut select = None;
+    area.show_rows(ui, row_height, data.len().div_ceil(16), |ui, rows| {
+        for row in rows {
+            let bytes = &data[row * 16..(row * 16 + 16).min(data.len())];
+            ui.horizontal(|ui| {
+                ui.spacing_mut().item_spacing.x = 5.0;
+                ui.label(mono(format!("{:04X}", row * 16)).weak());
+                for (i, &v) in bytes.iter().enumerate() {
+                    let addr = row * 16 + i;
+                    let mut text = mono(format!("{:02X}", v));
+                    if app.ram_sel == Some(addr) {
+                        text = text.color(CURRENT).strong();
+                    }
+                    if ui.add(Label::new(text).sense(Sense::click()).selectable(false)).clicked() {
+                        select = Some(addr);
+                    }
+                }
+                let ascii: String = bytes.iter()
+                    .map(|&b| if (0x20..0x7F).contains(&b) { b as char } else { '.' })
+                    .collect();
+                ui.label(mono(ascii).weak());
+            });
+        }
+    });
+    if let Some(addr) = select {
+        app.ram_sel = Some(addr);
+        app.ram_edit = format!("{:02X}", app.arduboy.mem.data[addr]);
+    }
+}
+
+pub fn io(ui: &mut Ui, app: &mut DebuggerApp) {
+    ui.checkbox(&mut app.io_all, "Show all registers");
+    let text = if app.io_all { app.arduboy.dump_io_all() } else { app.arduboy.dump_io() };
+    ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| ui.label(mono(text)));
+}
+
+pub fn breakpoints(ui: &mut Ui, app: &mut DebuggerApp) {
+    ui.horizontal(|ui| {
+        let hint = if app.elf.is_some() { "0x1A4 or symbol" } else { "0x1A4" };
+        let r = ui.add(TextEdit::singleline(&mut app.bp_input).desired_width(140.0).hint_text(hint));
+        if ui.button("Add").clicked() || submitted(ui, &r) {
+            match app.parse_location(&app.bp_input) {
+                Ok(word) => {
+                    if !app.arduboy.breakpoints.contains(&word) {
+                        app.arduboy.breakpoints.push(word);
+                    }
+                    app.bp_input.clear();
+                    app.bp_error = None;
+                }
+                Err(e) => app.bp_error = Some(e),
+            }
+        }
+    });
+    if let Some(e) = &app.bp_error {
+        ui.colored_label(BREAK, e);
+    }
+    ui.separator();
+    if app.arduboy.breakpoints.is_empty() {
+        ui.label("No breakpoints. Click a line in the disassembly to add one.");
+    }
+    let mut remove = None;
+    for (i, &bp) in app.arduboy.breakpoints.iter().enumerate() {
+        ui.horizontal(|ui| {
+            if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                remove = Some(i);
+            }
+            ui.label(mono(app.describe(bp)));
+        });
+    }
+    if let Some(i) = remove {
+        app.arduboy.breakpoints.remove(i);
+    }
+}
+
+pub fn profiler(ui: &mut Ui, app: &mut DebuggerApp) {
+    let tick = app.arduboy.cpu.tick;
+    let p = &mut app.arduboy.profiler;
+    ui.horizontal(|ui| {
+        if p.enabled {
+            if ui.button("Stop").clicked() {
+                p.stop(tick);
+            }
+            ui.label("Profiling...");
+        } else if ui.button("Start").clicked() {
+            p.start(tick);
+        }
+    });
+    ui.separator();
+    if app.arduboy.profiler.enabled {
+        ui.label("Stop the profiler to see the report.");
+    } else {
+        let report = app.arduboy.profiler_report();
+        ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| ui.label(mono(report)));
+    }
+}
+
+pub fn serial(ui: &mut Ui, app: &mut DebuggerApp) {
+    ui.horizontal(|ui| {
+        let r = ui.add(TextEdit::singleline(&mut app.serial_input).desired_width(240.0).hint_text("Send a line"));
+        if submitted(ui, &r) {
+            app.serial_input.push('\n');
+            app.arduboy.push_serial_input(app.serial_input.as_bytes());
+            app.serial_input.clear();
+            r.request_focus();
+        }
+        if ui.button("Clear").clicked() {
+            app.serial_log.clear();
+        }
+    });
+    ui.separator();
+    ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .stick_to_bottom(true)
+        .show(ui, |ui| ui.label(mono(&app.serial_log)));
+}
@@ -0,0 +1,96 @@
+//! Legacy Linux framebuffer (`/dev/fbN`) output.
+//!
+//! Used with `--fb`, and when no KMS display can be opened (small SPI
+//! panels driven by fbtft, or a compositor holding DRM master). The panel
+//! geometry comes from sysfs (`/sys/class/graphics/fbN`), so no ioctls are
+//! needed.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+
+/// Geometry of a 16- or 32-bit pixel buffer.
+pub struct Surface {
+    pub width: usize,
+    pub height: usize,
+    pub stride: usize,
+    pub bytes_per_pixel: usize,
+}
+
+/// Draw a `w`×`h` 0xRRGGBB image scaled by `scale` and centered into `dst`.
+/// Pixels outside the image are left alone.
+pub fn blit(dst: &mut [u8], surface: &Surface, pixels: &[u32], w: usize, h: usize, scale: usize) {
+    let scale = scale.max(1);
+    let x0 = surface.width.saturating_sub(w * scale) / 2;
+    let y0 = surface.height.saturating_sub(h * scale) / 2;
+    let bpp = surface.bytes_per_pixel;
+    for sy in 0..(h * scale).min(surface.height) {
+        let row = &pixels[(sy / scale) * w..(sy / scale) * w + w];
+        let line = (y0 + sy) * surface.stride;
+        for sx in 0..(w * scale).min(surface.width) {
+            let rgb = row[sx / scale];
+            let o = line + (x0 + sx) * bpp;
+            if bpp == 4 {
+                dst[o..o + 4].copy_from_slice(&rgb.to_le_bytes());
+            } else {
+                let r = (rgb >> 19) & 0x1F;
+                let g = (rgb >> 10) & 0x3F;
+                let b = (rgb >> 3) & 0x1F;
+                let px = ((r << 11) | (g << 5) | b) as u16;
+                dst[o..o + 2].copy_from_slice(&px.to_le_bytes());
+            }
+        }
+    }
+}
+
+pub struct Framebuffer {
+    file: File,
+    surface: Surface,
+    buf: Vec<u8>,
+}
+
+impl Framebuffer {
+    /// Open a framebuffer device such as `/dev/fb0`.
+    pub fn open(dev: &str) -> Result<Self, String> {
+        let name = dev.rsplit('/').next().unwrap_or(dev);
+        let sys = format!("/sys/class/graphics/{}", name);
+        let read = |attr: &str| fs::read_to_string(format!("{}/{}", sys, attr))
+            .map(|s| s.trim().to_string())
+            .map_err(|e| format!("{}/{}: {}", sys, attr, e));
+
+        let size = read("virtual_size")?;
+        let (w, h) = size.split_once(',').ok_or_else(|| format!("bad virtual_size '{}'", size))?;
+        let width: usize = w.parse().map_err(|_| format!("bad virtual_size '{}'", size))?;
+        let height: usize = h.parse().map_err(|_| format!("bad virtual_size '{}'", size))?;
+        let bpp: usize = read("bits_per_pixel")?.parse().map_err(|_| "bad bits_per_pixel")?;
+        if bpp != 16 && bpp != 32 {
+            return Err(format!("{}: unsupported depth {} bpp (need 16 or 32)", dev, bpp));
+        }
+        let bytes_per_pixel = bpp / 8;
+        let stride = read("stride").ok().and_then(|s| s.parse().ok())
+            .unwrap_or(width * bytes_per_pixel);
+
+        let file = OpenOptions::new().read(true).write(true).open(dev)
+            .map_err(|e| format!("{}: {}", dev, e))?;
+        let surface = Surface { width, height, stride, bytes_per_pixel };
+        Ok(Framebuffer { file, surface, buf: vec![0; stride * height] })
+    }
+}
+
+impl crate::Output for Framebuffer {
+    fn size(&self) -> (usize, usize) {
+        (self.surface.width, self.surface.height)
+    }
+
+    fn present(&mut self, pixels: &[u32], w: usize, h: usize, scale: usize) -> Result<(), String> {
+        self.buf.fill(0);
+        blit(&mut self.buf, &self.surface, pixels, w, h, scale);
+        self.file.seek(SeekFrom::Start(0))
+            .and_then(|_| self.file.write_all(&self.buf))
+            .map_err(|e| format!("framebuffer write: {}", e))
+    }
+
+    fn clear(&mut self) {
+        self.buf.fill(0);
+        let _ = self.file.seek(SeekFrom::Start(0)).and_then(|_| self.file.write_all(&self.buf));
+    }
+}
@@ -0,0 +1,195 @@
+//! GPIO buttons through the GPIO character device (`/dev/gpiochipN`).
+//!
+//! Each button is a line request (uAPI v2) with both-edge events, so a
+//! reader thread blocks on the request until the level changes instead of
+//! polling. Pins are addressed by line offset on their chip, which is also
+//! the BCM number on a Raspberry Pi's header chip; the sysfs GPIO numbers
+//! are not used (they start at 512 since kernel 6.6).
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::mem::size_of;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// `_IOC(dir, 0xB4, nr, T)`
+const fn gpio_ioc<T>(dir: u32, nr: u32) -> libc::Ioctl {
+    ((dir << 30) | ((size_of::<T>() as u32) << 16) | (0xB4 << 8) | nr) as libc::Ioctl
+}
+
+const GET_CHIPINFO: libc::Ioctl = gpio_ioc::<ChipInfo>(2, 0x01);
+const GET_LINE: libc::Ioctl = gpio_ioc::<LineRequest>(3, 0x07);
+const GET_VALUES: libc::Ioctl = gpio_ioc::<LineValues>(3, 0x0E);
+
+const FLAG_ACTIVE_LOW: u64 = 1 << 1;
+const FLAG_INPUT: u64 = 1 << 2;
+const FLAG_EDGE_RISING: u64 = 1 << 4;
+const FLAG_EDGE_FALLING: u64 = 1 << 5;
+const FLAG_BIAS_PULL_UP: u64 = 1 << 8;
+const FLAG_BIAS_PULL_DOWN: u64 = 1 << 9;
+const FLAG_BIAS_DISABLED: u64 = 1 << 10;
+
+const EVENT_RISING_EDGE: u32 = 1;
+
+// Kernel uAPI structs (include/uapi/linux/gpio.h)
+
+#[repr(C)]
+struct ChipInfo {
+    name: [u8; 32],
+    label: [u8; 32],
+    lines: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct ConfigAttribute {
+    id: u32,
+    padding: u32,
+    value: u64,
+    mask: u64,
+}
+
+#[repr(C)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [ConfigAttribute; 10],
+}
+
+#[repr(C)]
+struct LineRequest {
+    offsets: [u32; 64],
+    consumer: [u8; 32],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: i32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+/// `struct gpio_v2_line_event`: timestamp, id, offset, two sequence
+/// numbers and padding.
+const EVENT_SIZE: usize = 48;
+
+const _: () = assert!(size_of::<ChipInfo>() == 68);
+const _: () = assert!(size_of::<LineConfig>() == 272);
+const _: () = assert!(size_of::<LineRequest>() == 592);
+
+fn ioctl<T>(file: &File, request: libc::Ioctl, arg: &mut T) -> io::Result<()> {
+    // SAFETY: each request number is built from the size of the `#[repr(C)]`
+    // struct passed with it.
+    if unsafe { libc::ioctl(file.as_raw_fd(), request, arg as *mut T) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Pull resistor setting of an input line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bias {
+    /// Leave the current setting (e.g. from the Pi's `config.txt`)
+    AsIs,
+    PullUp,
+    PullDown,
+    Disabled,
+}
+
+impl Bias {
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "as-is" => Bias::AsIs,
+            "pull-up" => Bias::PullUp,
+            "pull-down" => Bias::PullDown,
+            "disabled" => Bias::Disabled,
+            _ => return None,
+        })
+    }
+
+    fn flags(self) -> u64 {
+        match self {
+            Bias::AsIs => 0,
+            Bias::PullUp => FLAG_BIAS_PULL_UP,
+            Bias::PullDown => FLAG_BIAS_PULL_DOWN,
+            Bias::Disabled => FLAG_BIAS_DISABLED,
+        }
+    }
+}
+
+/// The chip holding the header pins: the first whose label names a pin
+/// controller (`pinctrl-bcm2835`, `pinctrl-rp1`, ...), else `gpiochip0`.
+///
+/// This keeps configs working on a Pi 5, where the header moved between
+/// `gpiochip4` and `gpiochip0` across kernel versions.
+pub fn default_chip() -> String {
+    let mut chips: Vec<String> = fs::read_dir("/dev").map(|rd| rd.flatten()
+        .map(|e| e.path().to_string_lossy().into_owned())
+        .filter(|p| p.rsplit('/').next().is_some_and(|n| n.starts_with("gpiochip")))
+        .collect()).unwrap_or_default();
+    chips.sort_by_key(|p| p.trim_start_matches("/dev/gpiochip").parse::<u32>().unwrap_or(u32::MAX));
+    chips.iter().find(|chip| {
+        let Ok(f) = File::open(chip) else { return false };
+        let mut info = ChipInfo { name: [0; 32], label: [0; 32], lines: 0 };
+        ioctl(&f, GET_CHIPINFO, &mut info).is_ok() && info.label.starts_with(b"pinctrl-")
+    }).cloned().unwrap_or_else(|| "/dev/gpiochip0".to_string())
+}
+
+/// Request line `offset` of `chip` as an edge-reporting input and keep bit
+/// `bit` of `state` equal to its active level from a background thread.
+pub fn watch_line(
+    chip: &str, offset: u32, active_low: bool, bias: Bias, bit: u32, state: Arc<AtomicU64>,
+) -> Result<(), String> {
+    let err = |e: io::Error| format!("{} line {}: {}", chip, offset, e);
+    let chip_file = OpenOptions::new().read(true).write(true).open(chip).map_err(err)?;
+
+    let mut flags = FLAG_INPUT | FLAG_EDGE_RISING | FLAG_EDGE_FALLING | bias.flags();
+    if active_low {
+        flags |= FLAG_ACTIVE_LOW;
+    }
+    let mut consumer = [0u8; 32];
+    consumer[..10].copy_from_slice(b"arduboy-fb");
+    let mut offsets = [0u32; 64];
+    offsets[0] = offset;
+    let mut req = LineRequest {
+        offsets,
+        consumer,
+        config: LineConfig { flags, num_attrs: 0, padding: [0; 5], attrs: [ConfigAttribute::default(); 10] },
+        num_lines: 1,
+        event_buffer_size: 0,
+        padding: [0; 5],
+        fd: -1,
+    };
+    ioctl(&chip_file, GET_LINE, &mut req).map_err(err)?;
+    // SAFETY: on success the kernel returned a new file descriptor we own.
+    let mut line = unsafe { File::from_raw_fd(req.fd) };
+
+    // Values are logical: 1 = active, with `active_low` already applied
+    let mut values = LineValues { bits: 0, mask: 1 };
+    ioctl(&line, GET_VALUES, &mut values).map_err(err)?;
+    set_bit(&state, bit, values.bits & 1 != 0);
+
+    std::thread::spawn(move || {
+        let mut ev = [0u8; EVENT_SIZE];
+        while line.read_exact(&mut ev).is_ok() {
+            let id = u32::from_ne_bytes([ev[8], ev[9], ev[10], ev[11]]);
+            set_bit(&state, bit, id == EVENT_RISING_EDGE);
+        }
+    });
+    Ok(())
+}
+
+fn set_bit(state: &AtomicU64, bit: u32, on: bool) {
+    if on {
+        state.fetch_or(1 << bit, Ordering::Relaxed);
+    } else {
+        state.fetch_and(!(1 << bit), Ordering::Relaxed);
+    }
+}
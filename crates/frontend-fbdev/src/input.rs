@@ -0,0 +1,299 @@
+//! Button input from GPIO pins and evdev devices.
+//!
+//! Wiring is described in a small TOML file (`--input <file>`):
+//!
+//! ```toml
+//! evdev = ["/dev/input/event0"]   # keyboards / USB pads (default: all with mapped keys)
+//! grab = true                     # take the devices from the console; default true
+//! gpiochip = "/dev/gpiochip0"     # default: the chip of the header pins
+//!
+//! [[button]]
+//! name = "A"          # up, down, left, right, a, b, quit
+//! gpio = 17           # line offset on the chip (BCM number on the Pi)
+//! active_low = true   # pressed = 0 (button to GND); default true
+//! bias = "pull-up"    # pull-up, pull-down, disabled or as-is; default
+//!                     # pull-up when active_low, else as-is
+//!
+//! [[button]]
+//! name = "B"
+//! key = 45            # evdev key code (KEY_X)
+//! ```
+//!
+//! A button may also set `chip` to use another GPIO chip. GPIO lines are
+//! requested through the character device with edge events (see
+//! [`crate::gpio`]). Evdev devices are grabbed so key presses do not also
+//! reach the console underneath. Both are read on background threads.
+//! Without a config file the arrow keys, Z/X and Esc of every input device
+//! are used.
+
+use arduboy_core::toml_lite;
+use arduboy_core::Button;
+use crate::gpio::{self, Bias};
+use std::fs::{self, File};
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
+
+/// What a physical input does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Pad(Button),
+    Quit,
+}
+
+impl Control {
+    fn parse(s: &str) -> Option<Self> {
+        Some(match s.to_lowercase().as_str() {
+            "up" => Control::Pad(Button::Up),
+            "down" => Control::Pad(Button::Down),
+            "left" => Control::Pad(Button::Left),
+            "right" => Control::Pad(Button::Right),
+            "a" => Control::Pad(Button::A),
+            "b" => Control::Pad(Button::B),
+            "quit" => Control::Quit,
+            _ => return None,
+        })
+    }
+
+    fn bit(self) -> u8 {
+        match self {
+            Control::Pad(Button::Up) => 1,
+            Control::Pad(Button::Down) => 2,
+            Control::Pad(Button::Left) => 4,
+            Control::Pad(Button::Right) => 8,
+            Control::Pad(Button::A) => 16,
+            Control::Pad(Button::B) => 32,
+            Control::Quit => 64,
+        }
+    }
+}
+
+const PAD_CONTROLS: [Control; 6] = [
+    Control::Pad(Button::Up), Control::Pad(Button::Down), Control::Pad(Button::Left),
+    Control::Pad(Button::Right), Control::Pad(Button::A), Control::Pad(Button::B),
+];
+
+/// Default keys: arrows, Z = A, X = B, Esc = quit.
+const DEFAULT_KEYS: [(u16, Control); 7] = [
+    (103, Control::Pad(Button::Up)), (108, Control::Pad(Button::Down)),
+    (105, Control::Pad(Button::Left)), (106, Control::Pad(Button::Right)),
+    (44, Control::Pad(Button::A)), (45, Control::Pad(Button::B)), (1, Control::Quit),
+];
+
+const EV_KEY: u16 = 1;
+/// Highest key code (`KEY_MAX`)
+const KEY_MAX: usize = 0x2FF;
+/// `EVIOCGBIT(EV_KEY, len)`: which key codes a device reports
+const EVIOCGBIT_KEY: libc::Ioctl =
+    ((2 << 30) | (((KEY_MAX / 8 + 1) as u32) << 16) | ((b'E' as u32) << 8) | (0x20 + EV_KEY as u32)) as libc::Ioctl;
+/// `EVIOCGRAB`: exclusive access to a device
+const EVIOCGRAB: libc::Ioctl = ((1 << 30) | (4 << 16) | ((b'E' as u32) << 8) | 0x90) as libc::Ioctl;
+
+/// A button wired to a GPIO line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpioButton {
+    /// Chip device (`None` = the `gpiochip` setting)
+    pub chip: Option<String>,
+    /// Line offset on the chip
+    pub line: u32,
+    pub active_low: bool,
+    pub bias: Bias,
+    pub control: Control,
+}
+
+/// Input wiring loaded from the config file.
+#[derive(Debug, Clone)]
+pub struct InputConfig {
+    /// Evdev device paths (empty = every `/dev/input/event*` with a mapped key)
+    pub evdev: Vec<String>,
+    /// Grab the evdev devices exclusively
+    pub grab: bool,
+    /// Default GPIO chip (`None` = detect the header chip)
+    pub gpiochip: Option<String>,
+    pub gpio: Vec<GpioButton>,
+    /// `(evdev key code, control)`
+    pub keys: Vec<(u16, Control)>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig { evdev: Vec::new(), grab: true, gpiochip: None, gpio: Vec::new(), keys: Vec::new() }
+    }
+}
+
+impl InputConfig {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let doc = toml_lite::parse(text)?;
+        let mut cfg = InputConfig::default();
+        for (key, val) in &doc.root {
+            match key.as_str() {
+                "evdev" => for v in val.as_array(key)? {
+                    cfg.evdev.push(v.as_str(key)?.to_string());
+                },
+                "grab" => cfg.grab = val.as_bool(key)?,
+                "gpiochip" => cfg.gpiochip = Some(val.as_str(key)?.to_string()),
+                _ => return Err(format!("unknown key \"{}\"", key)),
+            }
+        }
+        for (name, table) in &doc.tables {
+            if name != "button" {
+                return Err(format!("unknown table [[{}]]", name));
+            }
+            let cname = table.get("name").ok_or("[[button]]: missing \"name\"")?.as_str("name")?;
+            let control = Control::parse(cname)
+                .ok_or_else(|| format!("[[button]]: unknown button \"{}\"", cname))?;
+            match (table.get("gpio"), table.get("key")) {
+                (Some(line), None) => {
+                    let active_low = match table.get("active_low") {
+                        Some(v) => v.as_bool("active_low")?,
+                        None => true,
+                    };
+                    let bias = match table.get("bias") {
+                        Some(v) => {
+                            let s = v.as_str("bias")?;
+                            Bias::parse(s).ok_or_else(|| format!("bias: unknown setting \"{}\"", s))?
+                        }
+                        None if active_low => Bias::PullUp,
+                        None => Bias::AsIs,
+                    };
+                    let chip = table.get("chip").map(|v| v.as_str("chip").map(str::to_string)).transpose()?;
+                    cfg.gpio.push(GpioButton { chip, line: line.as_u32("gpio")?, active_low, bias, control });
+                }
+                (None, Some(code)) => {
+                    let code = code.as_u32("key")?;
+                    if code > 0x2FF {
+                        return Err(format!("key: code {} out of range", code));
+                    }
+                    cfg.keys.push((code as u16, control));
+                }
+                _ => return Err(format!("[[button]] \"{}\": needs exactly one of gpio or key", cname)),
+            }
+        }
+        Ok(cfg)
+    }
+}
+
+pub struct Input {
+    /// Controls of the GPIO buttons, by state bit
+    pins: Vec<Control>,
+    /// Active GPIO buttons, one bit each
+    pin_state: Arc<AtomicU64>,
+    /// Control bits held on evdev devices
+    keys: Arc<AtomicU8>,
+}
+
+impl Input {
+    /// Request the GPIO lines and start the evdev readers.
+    pub fn open(cfg: &InputConfig, debug: bool) -> Result<Self, String> {
+        if cfg.gpio.len() > 64 {
+            return Err("at most 64 GPIO buttons are supported".into());
+        }
+        let pin_state = Arc::new(AtomicU64::new(0));
+        let default_chip = match (&cfg.gpiochip, cfg.gpio.iter().all(|b| b.chip.is_some())) {
+            (Some(chip), _) => chip.clone(),
+            (None, false) => gpio::default_chip(),
+            (None, true) => String::new(),
+        };
+        for (bit, b) in cfg.gpio.iter().enumerate() {
+            let chip = b.chip.as_deref().unwrap_or(&default_chip);
+            if debug { eprintln!("Input: {} line {} = {:?}", chip, b.line, b.control); }
+            gpio::watch_line(chip, b.line, b.active_low, b.bias, bit as u32, pin_state.clone())?;
+        }
+        let pins = cfg.gpio.iter().map(|b| b.control).collect();
+
+        let keys = Arc::new(AtomicU8::new(0));
+        let map: Vec<(u16, Control)> = if cfg.keys.is_empty() && cfg.gpio.is_empty() {
+            DEFAULT_KEYS.to_vec()
+        } else {
+            cfg.keys.clone()
+        };
+        if !map.is_empty() {
+            let explicit = !cfg.evdev.is_empty();
+            let devices = if explicit { cfg.evdev.clone() } else { list_event_devices() };
+            for dev in devices {
+                let f = match File::open(&dev) {
+                    Ok(f) => f,
+                    Err(e) => {
+                        if explicit || debug { eprintln!("Warning: {}: {}", dev, e); }
+                        continue;
+                    }
+                };
+                // Leave mice, power buttons etc. to the system
+                if !explicit && !reports_any_key(&f, &map) {
+                    continue;
+                }
+                if cfg.grab {
+                    // SAFETY: EVIOCGRAB takes an int by value.
+                    if unsafe { libc::ioctl(f.as_raw_fd(), EVIOCGRAB, 1 as libc::c_int) } == -1 {
+                        eprintln!("Warning: {}: grab: {}", dev, std::io::Error::last_os_error());
+                    }
+                }
+                if debug { eprintln!("Input: {}", dev); }
+                let (keys, map) = (keys.clone(), map.clone());
+                std::thread::spawn(move || read_evdev(f, &map, &keys));
+            }
+        }
+        Ok(Input { pins, pin_state, keys })
+    }
+
+    /// Current control bits.
+    fn mask(&self) -> u8 {
+        let pins = self.pin_state.load(Ordering::Relaxed);
+        self.pins.iter().enumerate()
+            .filter(|&(bit, _)| pins & (1 << bit) != 0)
+            .fold(self.keys.load(Ordering::Relaxed), |mask, (_, c)| mask | c.bit())
+    }
+
+/// Held game buttons, and whether the quit control is held.
+    pub fn poll(&self) -> (Vec<(Button, bool)>, bool) {
+        let mask = self.mask();
+        let buttons = PAD_CONTROLS.iter().map(|&c| match c {
+            Control::Pad(b) => (b, mask & c.bit() != 0),
+            Control::Quit => unreachable!(),
+        }).collect();
+        (buttons, mask & Control::Quit.bit() != 0)
+    }
+}
+
+/// Whether the device can report any of the mapped keys.
+fn reports_any_key(f: &File, map: &[(u16, Control)]) -> bool {
+    let mut bits = [0u8; KEY_MAX / 8 + 1];
+    // SAFETY: the request number carries the length of `bits`.
+    if unsafe { libc::ioctl(f.as_raw_fd(), EVIOCGBIT_KEY, bits.as_mut_ptr()) } == -1 {
+        return false;
+    }
+    map.iter().any(|&(code, _)| bits[code as usize / 8] & (1 << (code % 8)) != 0)
+}
+
+fn list_event_devices() -> Vec<String> {
+    let mut devs: Vec<String> = fs::read_dir("/dev/input").map(|rd| rd.flatten()
+        .map(|e| e.path().to_string_lossy().into_owned())
+        .filter(|p| p.rsplit('/').next().is_some_and(|n| n.starts_with("event")))
+        .collect()).unwrap_or_default();
+    devs.sort();
+    devs
+}
+
+/// Read `struct input_event` records (timeval, type, code, value) and keep
+/// the bits of mapped keys up to date.
+fn read_evdev(mut f: File, map: &[(u16, Control)], keys: &AtomicU8) {
+    // timeval is two C longs: 16 bytes on 64-bit, 8 bytes on 32-bit Pi OS
+    let tv = std::mem::size_of::<usize>() * 2;
+    let mut ev = vec![0u8; tv + 8];
+    while f.read_exact(&mut ev).is_ok() {
+        let ty = u16::from_ne_bytes([ev[tv], ev[tv + 1]]);
+        let code = u16::from_ne_bytes([ev[tv + 2], ev[tv + 3]]);
+        let value = i32::from_ne_bytes([ev[tv + 4], ev[tv + 5], ev[tv + 6], ev[tv + 7]]);
+        if ty != EV_KEY || value == 2 {
+            continue; // not a key, or autorepeat
+        }
+        for &(_, control) in map.iter().filter(|(c, _)| *c == code) {
+            if value != 0 {
+                keys.fetch_or(control.bit(), Ordering::Relaxed);
+            } else {
+                keys.fetch_and(!control.bit(), Ordering::Relaxed);
+            }
+        }
+    }
+}
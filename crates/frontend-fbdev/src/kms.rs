@@ -0,0 +1,453 @@
+//! KMS/DRM output (`/dev/dri/cardN`) through a dumb buffer.
+//!
+//! The first connected connector is driven at its preferred mode from a
+//! single CPU-drawn XRGB8888 buffer, which is how Raspberry Pi OS drives its
+//! displays since the switch to `vc4-kms-v3d`. Setting the mode needs DRM
+//! master, so no compositor may be running on the card; the console's own
+//! CRTC configuration is restored on exit. Only the kernel mode-setting
+//! ioctls are used, so libdrm is not needed.
+
+use crate::fbdev::{blit, Surface};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::mem::size_of;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+
+/// `_IOWR('d', nr, T)`
+const fn drm_iowr<T>(nr: u32) -> libc::Ioctl {
+    ((3 << 30) | ((size_of::<T>() as u32) << 16) | ((b'd' as u32) << 8) | nr) as libc::Ioctl
+}
+
+const GETRESOURCES: libc::Ioctl = drm_iowr::<CardRes>(0xA0);
+const GETCRTC: libc::Ioctl = drm_iowr::<Crtc>(0xA1);
+const SETCRTC: libc::Ioctl = drm_iowr::<Crtc>(0xA2);
+const GETENCODER: libc::Ioctl = drm_iowr::<GetEncoder>(0xA6);
+const GETCONNECTOR: libc::Ioctl = drm_iowr::<GetConnector>(0xA7);
+const ADDFB: libc::Ioctl = drm_iowr::<FbCmd>(0xAE);
+const RMFB: libc::Ioctl = drm_iowr::<u32>(0xAF);
+const DIRTYFB: libc::Ioctl = drm_iowr::<FbDirty>(0xB1);
+const CREATE_DUMB: libc::Ioctl = drm_iowr::<CreateDumb>(0xB2);
+const MAP_DUMB: libc::Ioctl = drm_iowr::<MapDumb>(0xB3);
+const DESTROY_DUMB: libc::Ioctl = drm_iowr::<DestroyDumb>(0xB4);
+
+const CONNECTED: u32 = 1;
+const MODE_TYPE_PREFERRED: u32 = 1 << 3;
+
+// Kernel uAPI structs (include/uapi/drm/drm_mode.h)
+
+#[repr(C)]
+#[derive(Default)]
+struct CardRes {
+    fb_id_ptr: u64,
+    crtc_id_ptr: u64,
+    connector_id_ptr: u64,
+    encoder_id_ptr: u64,
+    count_fbs: u32,
+    count_crtcs: u32,
+    count_connectors: u32,
+    count_encoders: u32,
+    min_width: u32,
+    max_width: u32,
+    min_height: u32,
+    max_height: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct ModeInfo {
+    clock: u32,
+    hdisplay: u16,
+    hsync_start: u16,
+    hsync_end: u16,
+    htotal: u16,
+    hskew: u16,
+    vdisplay: u16,
+    vsync_start: u16,
+    vsync_end: u16,
+    vtotal: u16,
+    vscan: u16,
+    vrefresh: u32,
+    flags: u32,
+    type_: u32,
+    name: [u8; 32],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct Crtc {
+    set_connectors_ptr: u64,
+    count_connectors: u32,
+    crtc_id: u32,
+    fb_id: u32,
+    x: u32,
+    y: u32,
+    gamma_size: u32,
+    mode_valid: u32,
+    mode: ModeInfo,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetEncoder {
+    encoder_id: u32,
+    encoder_type: u32,
+    crtc_id: u32,
+    possible_crtcs: u32,
+    possible_clones: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct GetConnector {
+    encoders_ptr: u64,
+    modes_ptr: u64,
+    props_ptr: u64,
+    prop_values_ptr: u64,
+    count_modes: u32,
+    count_props: u32,
+    count_encoders: u32,
+    encoder_id: u32,
+    connector_id: u32,
+    connector_type: u32,
+    connector_type_id: u32,
+    connection: u32,
+    mm_width: u32,
+    mm_height: u32,
+    subpixel: u32,
+    pad: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbCmd {
+    fb_id: u32,
+    width: u32,
+    height: u32,
+    pitch: u32,
+    bpp: u32,
+    depth: u32,
+    handle: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct FbDirty {
+    fb_id: u32,
+    flags: u32,
+    color: u32,
+    num_clips: u32,
+    clips_ptr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CreateDumb {
+    height: u32,
+    width: u32,
+    bpp: u32,
+    flags: u32,
+    handle: u32,
+    pitch: u32,
+    size: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct MapDumb {
+    handle: u32,
+    pad: u32,
+    offset: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct DestroyDumb {
+    handle: u32,
+}
+
+// The ioctl numbers encode these sizes; a layout mistake must not compile
+const _: () = assert!(size_of::<CardRes>() == 64);
+const _: () = assert!(size_of::<ModeInfo>() == 68);
+const _: () = assert!(size_of::<Crtc>() == 104);
+const _: () = assert!(size_of::<GetEncoder>() == 20);
+const _: () = assert!(size_of::<GetConnector>() == 80);
+const _: () = assert!(size_of::<FbCmd>() == 28);
+const _: () = assert!(size_of::<FbDirty>() == 24);
+const _: () = assert!(size_of::<CreateDumb>() == 32);
+const _: () = assert!(size_of::<MapDumb>() == 16);
+
+/// Issue a DRM ioctl, retrying when interrupted.
+fn ioctl<T>(card: &File, request: libc::Ioctl, arg: &mut T) -> io::Result<()> {
+    loop {
+        // SAFETY: every request number above is built from the size of the
+        // `#[repr(C)]` struct passed with it, and pointers stored inside
+        // `arg` point at live buffers of the advertised counts.
+        let r = unsafe { libc::ioctl(card.as_raw_fd(), request, arg as *mut T) };
+        if r != -1 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if !matches!(e.raw_os_error(), Some(libc::EINTR) | Some(libc::EAGAIN)) {
+            return Err(e);
+        }
+    }
+}
+
+/// A connected connector with the CRTC and mode chosen for it.
+struct Route {
+    connector_id: u32,
+    crtc_id: u32,
+    mode: ModeInfo,
+}
+
+pub struct Kms {
+    card: File,
+    route: Route,
+    fb_id: u32,
+    handle: u32,
+    map: *mut u8,
+    map_len: usize,
+    surface: Surface,
+    /// Image geometry drawn last; the border is cleared when it changes
+    drawn: Option<(usize, usize, usize)>,
+    /// CRTC state before we took over, restored on drop
+    saved: Crtc,
+}
+
+impl Kms {
+    /// Use the first card in `/dev/dri` that has a connected display.
+    pub fn open_any() -> Result<Self, String> {
+        let mut cards: Vec<String> = fs::read_dir("/dev/dri").map(|rd| rd.flatten()
+            .map(|e| e.path().to_string_lossy().into_owned())
+            .filter(|p| p.rsplit('/').next().is_some_and(|n| n.starts_with("card")))
+            .collect()).map_err(|e| format!("/dev/dri: {}", e))?;
+        cards.sort();
+        let mut errors = Vec::new();
+        for card in &cards {
+            match Kms::open(card) {
+                Ok(kms) => return Ok(kms),
+                Err(e) => errors.push(e),
+            }
+        }
+        if errors.is_empty() {
+            return Err("/dev/dri: no cards".into());
+        }
+        Err(errors.join("; "))
+    }
+
+    /// Take over the first connected display of a card such as `/dev/dri/card1`.
+    pub fn open(dev: &str) -> Result<Self, String> {
+        let card = OpenOptions::new().read(true).write(true).custom_flags(libc::O_CLOEXEC)
+            .open(dev).map_err(|e| format!("{}: {}", dev, e))?;
+        let err = |what: &'static str| move |e: io::Error| format!("{}: {}: {}", dev, what, e);
+
+        let route = find_route(&card).map_err(err("mode resources"))?
+            .ok_or_else(|| format!("{}: no connected display", dev))?;
+        let (width, height) = (route.mode.hdisplay as usize, route.mode.vdisplay as usize);
+
+        let mut saved = Crtc { crtc_id: route.crtc_id, ..Default::default() };
+        ioctl(&card, GETCRTC, &mut saved).map_err(err("get CRTC"))?;
+
+        let mut dumb = CreateDumb { width: width as u32, height: height as u32, bpp: 32, ..Default::default() };
+        ioctl(&card, CREATE_DUMB, &mut dumb).map_err(err("create buffer"))?;
+        let handle = dumb.handle;
+        let destroy = |card: &File| {
+            let _ = ioctl(card, DESTROY_DUMB, &mut DestroyDumb { handle });
+        };
+
+        let mut fb = FbCmd {
+            width: width as u32, height: height as u32, pitch: dumb.pitch,
+            bpp: 32, depth: 24, handle, ..Default::default()
+        };
+        if let Err(e) = ioctl(&card, ADDFB, &mut fb) {
+            destroy(&card);
+            return Err(err("add framebuffer")(e));
+        }
+        let fb_id = fb.fb_id;
+        let remove = |card: &File| {
+            let _ = ioctl(card, RMFB, &mut { fb_id });
+            destroy(card);
+        };
+
+        let mut map_req = MapDumb { handle, ..Default::default() };
+        if let Err(e) = ioctl(&card, MAP_DUMB, &mut map_req) {
+            remove(&card);
+            return Err(err("map buffer")(e));
+        }
+        let map_len = dumb.size as usize;
+        // SAFETY: maps the dumb buffer at the offset the kernel just handed
+        // out for it; the mapping is released in `Drop` before the buffer.
+        let map = unsafe {
+            libc::mmap(std::ptr::null_mut(), map_len, libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED, card.as_raw_fd(), map_req.offset as libc::off_t)
+        };
+        if map == libc::MAP_FAILED {
+            let e = io::Error::last_os_error();
+            remove(&card);
+            return Err(err("mmap")(e));
+        }
+
+        let kms = Kms {
+            card,
+            route,
+            fb_id,
+            handle,
+            map: map as *mut u8,
+            map_len,
+            surface: Surface { width, height, stride: dumb.pitch as usize, bytes_per_pixel: 4 },
+            drawn: None,
+            saved,
+        };
+        // The buffer starts out with stale memory contents
+        kms.pixels().fill(0);
+        let mut crtc = Crtc {
+            set_connectors_ptr: &kms.route.connector_id as *const u32 as u64,
+            count_connectors: 1,
+            crtc_id: kms.route.crtc_id,
+            fb_id,
+            mode_valid: 1,
+            mode: kms.route.mode,
+            ..Default::default()
+        };
+        // On error `kms` is dropped, which releases the buffer again
+        ioctl(&kms.card, SETCRTC, &mut crtc)
+            .map_err(err("set mode (is another program the DRM master?)"))?;
+        Ok(kms)
+    }
+
+    /// The mapped scan-out buffer.
+    #[allow(clippy::mut_from_ref)]
+    fn pixels(&self) -> &mut [u8] {
+        // SAFETY: `map` is a live `map_len`-byte shared mapping owned by
+        // `self`, and `Kms` is neither `Send` nor `Sync`.
+        unsafe { std::slice::from_raw_parts_mut(self.map, self.map_len) }
+    }
+
+    /// Tell drivers that scan out from a shadow copy what changed.
+    fn flush(&self) {
+        let mut dirty = FbDirty { fb_id: self.fb_id, ..Default::default() };
+        // Most drivers scan the buffer directly and reject or ignore this
+        let _ = ioctl(&self.card, DIRTYFB, &mut dirty);
+    }
+}
+
+impl crate::Output for Kms {
+    fn size(&self) -> (usize, usize) {
+        (self.surface.width, self.surface.height)
+    }
+
+    fn present(&mut self, pixels: &[u32], w: usize, h: usize, scale: usize) -> Result<(), String> {
+        if self.drawn != Some((w, h, scale)) {
+            self.pixels().fill(0);
+            self.drawn = Some((w, h, scale));
+        }
+        blit(self.pixels(), &self.surface, pixels, w, h, scale);
+        self.flush();
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.pixels().fill(0);
+        self.drawn = None;
+        self.flush();
+    }
+}
+
+impl Drop for Kms {
+    fn drop(&mut self) {
+        if self.saved.fb_id != 0 {
+            let mut crtc = Crtc {
+                set_connectors_ptr: &self.route.connector_id as *const u32 as u64,
+                count_connectors: 1,
+                ..self.saved
+            };
+            let _ = ioctl(&self.card, SETCRTC, &mut crtc);
+        }
+        // SAFETY: unmaps the mapping created in `open`, exactly once.
+        unsafe { libc::munmap(self.map as *mut libc::c_void, self.map_len) };
+        let _ = ioctl(&self.card, RMFB, &mut { self.fb_id });
+        let _ = ioctl(&self.card, DESTROY_DUMB, &mut DestroyDumb { handle: self.handle });
+    }
+}
+
+/// Card resources: CRTC, connector and encoder ids.
+fn resources(card: &File) -> io::Result<(Vec<u32>, Vec<u32>)> {
+    // Hotplug can change the counts between the two calls; retry until stable
+    loop {
+        let mut res = CardRes::default();
+        ioctl(card, GETRESOURCES, &mut res)?;
+        let mut crtcs = vec![0u32; res.count_crtcs as usize];
+        let mut connectors = vec![0u32; res.count_connectors as usize];
+        let mut encoders = vec![0u32; res.count_encoders as usize];
+        let mut fill = CardRes {
+            crtc_id_ptr: crtcs.as_mut_ptr() as u64,
+            connector_id_ptr: connectors.as_mut_ptr() as u64,
+            encoder_id_ptr: encoders.as_mut_ptr() as u64,
+            count_crtcs: res.count_crtcs,
+            count_connectors: res.count_connectors,
+            count_encoders: res.count_encoders,
+            ..Default::default()
+        };
+        ioctl(card, GETRESOURCES, &mut fill)?;
+        if (fill.count_crtcs, fill.count_connectors, fill.count_encoders)
+            == (res.count_crtcs, res.count_connectors, res.count_encoders)
+        {
+            return Ok((crtcs, connectors));
+        }
+    }
+}
+
+/// The first connected connector, its preferred mode and a CRTC to drive it.
+fn find_route(card: &File) -> io::Result<Option<Route>> {
+    let (crtcs, connectors) = resources(card)?;
+    for connector_id in connectors {
+        // The first call probes the connector and reports the counts
+        let mut probe = GetConnector { connector_id, ..Default::default() };
+        ioctl(card, GETCONNECTOR, &mut probe)?;
+        if probe.connection != CONNECTED || probe.count_modes == 0 {
+            continue;
+        }
+        let mut modes = vec![ModeInfo::default(); probe.count_modes as usize];
+        let mut encoders = vec![0u32; probe.count_encoders as usize];
+        let mut conn = GetConnector {
+            connector_id,
+            modes_ptr: modes.as_mut_ptr() as u64,
+            count_modes: probe.count_modes,
+            encoders_ptr: encoders.as_mut_ptr() as u64,
+            count_encoders: probe.count_encoders,
+            ..Default::default()
+        };
+        ioctl(card, GETCONNECTOR, &mut conn)?;
+        modes.truncate(conn.count_modes as usize);
+        encoders.truncate(conn.count_encoders as usize);
+        let Some(mode) = modes.iter().find(|m| m.type_ & MODE_TYPE_PREFERRED != 0).or(modes.first())
+        else {
+            continue;
+        };
+
+        // Keep the CRTC the console uses, else any the encoders can drive
+        let mut crtc_id = 0;
+        if conn.encoder_id != 0 {
+            let mut enc = GetEncoder { encoder_id: conn.encoder_id, ..Default::default() };
+            if ioctl(card, GETENCODER, &mut enc).is_ok() {
+                crtc_id = enc.crtc_id;
+            }
+        }
+        for &encoder_id in &encoders {
+            if crtc_id != 0 {
+                break;
+            }
+            let mut enc = GetEncoder { encoder_id, ..Default::default() };
+            if ioctl(card, GETENCODER, &mut enc).is_ok() {
+                crtc_id = crtcs.iter().enumerate()
+                    .find(|&(i, _)| i < 32 && enc.possible_crtcs & (1 << i) != 0)
+                    .map_or(0, |(_, &id)| id);
+            }
+        }
+        if crtc_id != 0 {
+            return Ok(Some(Route { connector_id, crtc_id, mode: *mode }));
+        }
+    }
+    Ok(None)
+}
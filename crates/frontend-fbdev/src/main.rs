@@ -0,0 +1,218 @@
+//! Console frontend for arduboy-emu.
+//!
+//! Drives the display directly through KMS/DRM (falling back to a legacy
+//! `/dev/fbN` device) and reads buttons from GPIO lines and evdev devices,
+//! so it runs without X11 or Wayland — e.g. on a Raspberry Pi Zero inside a
+//! handheld shell, started from the console or a systemd unit. Besides the
+//! core it only uses `libc` for the kernel ioctls.
+//!
+//! Audio is not supported yet.
+
+mod fbdev;
+mod gpio;
+mod input;
+mod kms;
+
+use arduboy_core::game_file::GameFile;
+use arduboy_core::{Arduboy, BoardProfile, CpuType, FrameHooks, OverrunPolicy, RealtimeSync, Rotation, FRAME_CYCLES};
+use fbdev::Framebuffer;
+use input::{Input, InputConfig};
+use kms::Kms;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args[1].starts_with("--") {
+        eprintln!("Usage: arduboy-fb <file.hex|file.arduboy|file.elf> [options]");
+        eprintln!();
+        eprintln!("Options:");
+        eprintln!("  --card <device>      KMS/DRM card (default: first with a connected display)");
+        eprintln!("  --fb <device>        Use a legacy framebuffer device instead (fallback: /dev/fb0)");
+        eprintln!("  --input <file>       Button wiring: GPIO lines / evdev key codes (TOML)");
+        eprintln!("  --scale N            Pixel scale (default: largest that fits)");
+        eprintln!("  --fx <file>          FX flash data");
+        eprintln!("  --cpu <type>         CPU type: 32u4, 328p or 2560 (auto-detected if omitted)");
+        eprintln!("  --board <name|file>  Board profile (arduboy, gamebuino, ... or TOML file)");
+        eprintln!("  --system <k=v,...>   Set Arduboy2 system EEPROM (name, id, audio, logo, ...)");
+        eprintln!("  --rotate <deg>       Screen rotation: 0, 90, 180, 270 (default: from .arduboy metadata)");
+        eprintln!("  --overrun <policy>   When the board falls behind: slowdown (default), catchup[:<ms>] or free");
+        eprintln!("  --no-save            Disable EEPROM auto-save");
+        eprintln!("  --debug              Print diagnostics");
+        eprintln!();
+        eprintln!("Default keys: Arrows=D-pad Z=A X=B Esc=Quit");
+        std::process::exit(1);
+    }
+    let debug = args.iter().any(|a| a == "--debug");
+    let no_save = args.iter().any(|a| a == "--no-save");
+
+    if let Err(e) = run(&args, debug, no_save) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Where frames are drawn: a KMS display or a legacy framebuffer.
+trait Output {
+    /// Panel size in pixels.
+    fn size(&self) -> (usize, usize);
+
+    /// Draw a `w`×`h` 0xRRGGBB image scaled by `scale` and centered, with a
+    /// black border.
+    fn present(&mut self, pixels: &[u32], w: usize, h: usize, scale: usize) -> Result<(), String>;
+
+    /// Clear the panel (on exit).
+    fn clear(&mut self);
+
+    /// Largest integer scale that fits a `w`×`h` image on the panel.
+    fn fit_scale(&self, w: usize, h: usize) -> usize {
+        let (width, height) = self.size();
+        (width / w.max(1)).min(height / h.max(1)).max(1)
+    }
+}
+
+/// Open the display chosen by `--card` / `--fb`, else KMS with a fallback
+/// to `/dev/fb0`.
+fn open_output(args: &[String], debug: bool) -> Result<Box<dyn Output>, String> {
+    if let Some(card) = opt(args, "--card") {
+        return Ok(Box::new(Kms::open(card)?));
+    }
+    if let Some(fb) = opt(args, "--fb") {
+        return Ok(Box::new(Framebuffer::open(fb)?));
+    }
+    match Kms::open_any() {
+        Ok(kms) => Ok(Box::new(kms)),
+        Err(e) => {
+            if debug { eprintln!("KMS: {} (using /dev/fb0)", e); }
+            Ok(Box::new(Framebuffer::open("/dev/fb0")?))
+        }
+    }
+}
+
+/// Value following option `name`.
+fn opt<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(|s| s.as_str())
+}
+
+fn run(args: &[String], debug: bool, no_save: bool) -> Result<(), String> {
+    let game_path = &args[1];
+    let cfg = match opt(args, "--input") {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            InputConfig::parse(&text).map_err(|e| format!("{}: {}", path, e))?
+        }
+        None => InputConfig::default(),
+    };
+    let cpu = match opt(args, "--cpu") {
+        Some("328p") => Some(CpuType::Atmega328p),
+        Some("32u4") => Some(CpuType::Atmega32u4),
+        Some("2560") => Some(CpuType::Atmega2560),
+        Some(other) => return Err(format!("--cpu: unknown type '{}'", other)),
+        None => None,
+    };
+    let board = opt(args, "--board").map(BoardProfile::load).transpose()?;
+    let game = GameFile::load(game_path, opt(args, "--fx")).map_err(|e| format!("{}: {}", game_path, e))?;
+    let (cpu, detected) = game.cpu(board.as_ref(), cpu);
+    if let Some(d) = detected.filter(|d| d.confidence < 0.9) {
+        eprintln!("CPU auto-detected: {} (override with --cpu)", d.summary());
+    }
+    let (mut arduboy, _) = game.start(board, cpu)?;
+    arduboy.debug = debug;
+    if let Some(spec) = opt(args, "--rotate") {
+        arduboy.rotation = Rotation::parse(spec).map_err(|e| format!("--rotate: {}", e))?;
+    }
+
+    let eep = eeprom_path(game_path);
+    if let Ok(data) = fs::read(&eep) {
+        arduboy.load_eeprom(&data);
+    }
+    if let Some(spec) = opt(args, "--system") {
+        let mut sys = arduboy.system_settings();
+        sys.apply(spec).map_err(|e| format!("--system: {}", e))?;
+        arduboy.set_system_settings(&sys);
+    }
+
+    let scale: Option<usize> = opt(args, "--scale").and_then(|s| s.parse().ok());
+    let mut out = open_output(args, debug)?;
+    let input = Input::open(&cfg, debug)?;
+    if debug {
+        let (w, h) = out.size();
+        eprintln!("Display: {}x{}", w, h);
+    }
+
+    // Hide the console cursor so it does not blink over the game
+    print!("\x1b[?25l");
+    let _ = std::io::stdout().flush();
+
+    let mut sync = RealtimeSync::at_frame_rate(60);
+    sync.policy = match opt(args, "--overrun") {
+        Some(spec) => OverrunPolicy::parse(spec).map_err(|e| format!("--overrun: {}", e))?,
+        None => OverrunPolicy::Slowdown,
+    };
+    let mut last_save = Instant::now();
+    loop {
+        let (buttons, quit) = input.poll();
+        if quit {
+            break;
+        }
+        for (b, held) in buttons {
+            arduboy.set_button(b, held);
+        }
+        for _ in 0..sync.frames_due(Instant::now()).max(1) {
+            let mut screen = Screen { out: out.as_mut(), scale, error: None };
+            arduboy.run_frame_with(&mut screen);
+            if let Some(e) = screen.error {
+                return Err(e);
+            }
+            sync.ran(FRAME_CYCLES);
+        }
+
+        if !no_save && arduboy.eeprom_dirty && last_save.elapsed() >= Duration::from_secs(10) {
+            save_eeprom(&arduboy, &eep);
+            arduboy.eeprom_dirty = false;
+            last_save = Instant::now();
+        }
+
+        // The overrun policy decides whether a slow board catches up
+        std::thread::sleep(sync.sleep_time(Instant::now()));
+    }
+
+    if !no_save && arduboy.eeprom_dirty {
+        save_eeprom(&arduboy, &eep);
+    }
+    out.clear();
+    print!("\x1b[?25h");
+    let _ = std::io::stdout().flush();
+    Ok(())
+}
+
+/// Draws the screen whenever a frame changed it.
+struct Screen<'a> {
+    out: &'a mut dyn Output,
+    scale: Option<usize>,
+    error: Option<String>,
+}
+
+impl FrameHooks for Screen<'_> {
+    fn on_display_update(&mut self, pixels: &[u32], w: usize, h: usize) {
+        let scale = self.scale.unwrap_or_else(|| self.out.fit_scale(w, h));
+        if let Err(e) = self.out.present(pixels, w, h, scale) {
+            self.error = Some(e);
+        }
+    }
+}
+
+fn eeprom_path(game_path: &str) -> String {
+    let p = std::path::Path::new(game_path);
+    let stem = p.file_stem().and_then(|s| s.to_str()).unwrap_or("game");
+    let dir = p.parent().unwrap_or(std::path::Path::new("."));
+    dir.join(format!("{}.eep", stem)).to_string_lossy().into_owned()
+}
+
+fn save_eeprom(arduboy: &Arduboy, path: &str) {
+    if let Err(e) = fs::write(path, arduboy.save_eeprom()) {
+        eprintln!("EEPROM save error: {}: {}", path, e);
+    }
+}
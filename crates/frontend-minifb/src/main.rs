@@ -14,6 +14,8 @@
 //!   viewer, watchpoints, breakpoints, and execution profiler.
 //! - **GDB mode** (`--gdb <port>`): GDB Remote Serial Protocol server for
 //!   connection from avr-gdb or compatible clients.
+//! - **Scenario mode** (`--scenario <file.toml>`): Scripted test run with
+//!   inputs, assertions and screenshot artifacts; exit code 0 = pass, 1 = fail.
 //!
 //! ## v0.8.1 features
 //! - Quick save (F5) / quick load (F9) with full emulator state persistence
@@ -22,84 +24,159 @@
 //! - Execution profiler: PC histogram, hotspot analysis, call graph tracking
 //! - GDB Remote Serial Protocol server (`--gdb <port>`)
 //! - Data watchpoints (`--watch <addr>` CLI, `w` in step mode)
+//! - Write-protect regions (`--protect <range|symbol>[@<fn>,...]`, `wp` in step mode)
+//! - Out-of-bounds checks on global objects (`--bounds`, ELF only)
+//! - Shadow call stack checking return addresses (`--shadow-stack`)
+//! - Stack overflow into globals reported with the call stack (`--stack-guard`)
+//! - Strict mode reporting loads from never-written SRAM (`--strict`)
 //! - LCD effect (L key): display-accurate colors, pixel grid, ghosting, dot rounding
 //! - Profiler toggle (T key) in GUI mode
-
-use arduboy_core::{Arduboy, Button, CpuType, DisplayType, SCREEN_WIDTH, SCREEN_HEIGHT, detect_cpu_type};
+//! - Serial bridge to a host pty / COM port (`--serial-port <path|pty>`)
+//! - Serial escape-sequence control channel for game CI (`--serial-rpc`)
+//! - Scriptable serial co-processor stub, e.g. ESP8266 AT commands (`--coproc <script|esp>`)
+//! - Rebindable hotkeys, including gamepad buttons (`--bind <action=key,...>`)
+//! - Pin-level VCD waveform export for GTKWave (`--vcd <file>`)
+//! - SPI protocol decoder timeline (`spi` debugger command, `--spi-log <file.json>`)
+//! - Fast-forward-safe music from ArduboyTones/Playtune scores (`--score-audio`)
+//! - Host refresh-rate frame pacing with 60 Hz game time (`--refresh <Hz>`, `--frame-blend`)
+//! - Time-boxed emulation keeping the window responsive on slow machines (`--time-box <ms>`)
+
+mod pacing;
+mod serial_bridge;
+mod timeline;
+
+use arduboy_core::{Arduboy, Button, CpuType, DisplayType, OverrunPolicy, Rotation, SCREEN_WIDTH, SCREEN_HEIGHT};
+use arduboy_core::expr::{DisplayList, Expr, Symbols};
 use minifb::{Key, Window, WindowOptions, Scale, ScaleMode};
-use gilrs::{Gilrs, Event as GilrsEvent, EventType, Axis, Button as GilrsButton};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use gilrs::{Gilrs, GamepadId, Event as GilrsEvent, EventType, Axis, Button as GilrsButton};
 use std::env;
 use std::fs;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::{Duration, Instant};
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use serial_bridge::SerialBridge;
+use pacing::{FramePacer, EMU_FPS};
+use arduboy_core::serial_rpc::{RpcCommand, RpcParser};
+use arduboy_core::score::{ScoreEvent, ScoreSynth, ScoreTracker};
+use arduboy_core::av_sync::AvSync;
+use arduboy_core::bug_report::CaptureRecorder;
+use arduboy_core::schedule::{self, Action, At};
+use arduboy_core::actions::{Action as Hotkey, Actions};
+use arduboy_core::sound_events;
+use arduboy_core::display_cache::DisplayCache;
+use arduboy_core::annotations::Annotations;
+use arduboy_core::fuses::Fuses;
+use arduboy_core::audio_buffer::SpeakerModel;
+use arduboy_core::elf::ElfFile;
+use arduboy_core::game_file::GameFile;
 
 /// Audio output sample rate in Hz
 const AUDIO_SAMPLE_RATE: u32 = 44100;
+/// Audio queued behind the picture that drift correction aims for (ms)
+const AV_SYNC_TARGET_MS: f64 = 40.0;
+/// Screens kept for the bug report GIF (F12), ~3 s
+const BUG_REPORT_FRAMES: usize = 180;
+/// Serial output kept for bug reports, in bytes
+const BUG_REPORT_SERIAL: usize = 16384;
 /// Square wave amplitude (0.0–1.0)
 const AUDIO_VOLUME: f32 = 0.15;
+/// How often the GUI checks whether the audio output device changed
+const AUDIO_DEVICE_POLL: Duration = Duration::from_secs(2);
 /// Analog stick deadzone
 const STICK_DEADZONE: f32 = 0.3;
 /// Analog trigger deadzone
 const TRIGGER_DEADZONE: f32 = 0.2;
+/// Longest the GUI runs boot logo frames before showing one (`--fast-boot`)
+const FAST_BOOT_SLICE: Duration = Duration::from_millis(100);
 
 // ─── Audio Sources ──────────────────────────────────────────────────────────
 
-/// Hybrid audio source: uses sample-accurate PCM from ring buffer when
+/// Hybrid audio source: plays a synthesized score when one is active
+/// (`--score-audio`), otherwise uses sample-accurate PCM from ring buffer when
 /// available (GPIO bit-bang), falls back to square wave synthesis for
 /// timer-driven tones.
 struct HybridAudioSource {
     ring: Arc<std::sync::Mutex<std::collections::VecDeque<f32>>>,
+    score: Arc<std::sync::Mutex<ScoreSynth>>,
     freq_l: Arc<AtomicU32>,
     freq_r: Arc<AtomicU32>,
     sample_rate: u32,
     phase_l: f32,
     phase_r: f32,
     left_next: bool,
+    /// A score was playing at the last successful look at the synth
+    score_active: bool,
+    /// Right half of the score frame whose left half was just returned
+    score_right: Option<f32>,
 }
 
 impl HybridAudioSource {
     fn new(
         ring: Arc<std::sync::Mutex<std::collections::VecDeque<f32>>>,
+        score: Arc<std::sync::Mutex<ScoreSynth>>,
         freq_l: Arc<AtomicU32>,
         freq_r: Arc<AtomicU32>,
         sample_rate: u32,
     ) -> Self {
         HybridAudioSource {
-            ring, freq_l, freq_r, sample_rate,
+            ring, score, freq_l, freq_r, sample_rate,
             phase_l: 0.0, phase_r: 0.0, left_next: true,
+            score_active: false, score_right: None,
         }
     }
-}
 
-impl Iterator for HybridAudioSource {
-    type Item = f32;
-    fn next(&mut self) -> Option<f32> {
+    /// Next sample for the left or right channel.
+    fn sample(&mut self, left: bool) -> f32 {
+        // Score synthesis overrides emulated output while a score plays.
+        // The UI thread also takes this lock, so never wait for it here;
+        // whole stereo frames are taken so a missed lock cannot swap the
+        // channels.
+        if left {
+            self.score_right = None;
+            match self.score.try_lock() {
+                Ok(mut score) => {
+                    self.score_active = score.is_active();
+                    if self.score_active {
+                        let (l, r) = score.next_frame();
+                        self.score_right = Some(r);
+                        return l;
+                    }
+                }
+                // Busy while a score plays: one silent frame
+                Err(_) if self.score_active => {
+                    self.score_right = Some(0.0);
+                    return 0.0;
+                }
+                Err(_) => {}
+            }
+        } else if let Some(r) = self.score_right.take() {
+            return r;
+        }
         // Try to drain from sample-accurate ring buffer first
         if let Ok(mut ring) = self.ring.try_lock() {
-            if !ring.is_empty() {
-                return ring.pop_front();
+            if let Some(s) = ring.pop_front() {
+                return s;
             }
         }
         // Fallback: synthesize square wave from timer frequencies
-        if self.left_next {
-            self.left_next = false;
-            let freq = f32::from_bits(self.freq_l.load(Ordering::Relaxed));
-            if freq <= 0.0 { self.phase_l = 0.0; return Some(0.0); }
-            let s = if self.phase_l < 0.5 { AUDIO_VOLUME } else { -AUDIO_VOLUME };
-            self.phase_l += freq / self.sample_rate as f32;
-            self.phase_l %= 1.0;
-            Some(s)
-        } else {
-            self.left_next = true;
-            let freq = f32::from_bits(self.freq_r.load(Ordering::Relaxed));
-            if freq <= 0.0 { self.phase_r = 0.0; return Some(0.0); }
-            let s = if self.phase_r < 0.5 { AUDIO_VOLUME } else { -AUDIO_VOLUME };
-            self.phase_r += freq / self.sample_rate as f32;
-            self.phase_r %= 1.0;
-            Some(s)
-        }
+        let (freq, phase) = if left { (&self.freq_l, &mut self.phase_l) } else { (&self.freq_r, &mut self.phase_r) };
+        let freq = f32::from_bits(freq.load(Ordering::Relaxed));
+        if freq <= 0.0 { *phase = 0.0; return 0.0; }
+        let s = if *phase < 0.5 { AUDIO_VOLUME } else { -AUDIO_VOLUME };
+        *phase = (*phase + freq / self.sample_rate as f32) % 1.0;
+        s
+    }
+}
+
+impl Iterator for HybridAudioSource {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        let left = self.left_next;
+        self.left_next = !left;
+        Some(self.sample(left))
     }
 }
 
@@ -110,24 +187,118 @@ impl rodio::Source for HybridAudioSource {
     fn total_duration(&self) -> Option<Duration> { None }
 }
 
+/// Audio output device choice (`--audio-device`, Shift+M).
+#[derive(Debug, Clone, PartialEq)]
+enum AudioDevice {
+    /// The system default, followed when it changes (default)
+    Default,
+    /// Device at this position in the output device list
+    Index(usize),
+    /// First device whose name contains this text (lowercase)
+    Name(String),
+}
+
+impl AudioDevice {
+    fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "default" => AudioDevice::Default,
+            t => match t.parse::<usize>() {
+                Ok(n) => AudioDevice::Index(n),
+                Err(_) => AudioDevice::Name(t.to_string()),
+            },
+        }
+    }
+
+    /// Device to play on now: the chosen one while it is present, else the default.
+    fn resolve(&self) -> Option<rodio::Device> {
+        let host = rodio::cpal::default_host();
+        let chosen = match self {
+            AudioDevice::Default => None,
+            AudioDevice::Index(n) => host.output_devices().ok().and_then(|mut d| d.nth(*n)),
+            AudioDevice::Name(t) => host.output_devices().ok().and_then(|mut d| {
+                d.find(|dev| dev.name().is_ok_and(|n| n.to_lowercase().contains(t.as_str())))
+            }),
+        };
+        chosen.or_else(|| host.default_output_device())
+    }
+}
+
+/// Output device names, in `--audio-device N` order.
+fn audio_device_names() -> Vec<String> {
+    rodio::cpal::default_host().output_devices()
+        .map(|d| d.map(|dev| dev.name().unwrap_or_else(|_| "(unnamed)".into())).collect())
+        .unwrap_or_default()
+}
+
+/// An open output stream; dropping it stops playback.
+struct AudioOut {
+    _stream: rodio::OutputStream,
+    _sink: rodio::Sink,
+    /// Device it plays on
+    device: String,
+}
+
 fn setup_audio(
     ring: Arc<std::sync::Mutex<std::collections::VecDeque<f32>>>,
+    score: Arc<std::sync::Mutex<ScoreSynth>>,
     freq_l: Arc<AtomicU32>,
     freq_r: Arc<AtomicU32>,
-) -> Option<(rodio::OutputStream, rodio::OutputStreamHandle, rodio::Sink)>
+    choice: &AudioDevice,
+) -> Option<AudioOut>
 {
-    match rodio::OutputStream::try_default() {
+    let Some(device) = choice.resolve() else {
+        eprintln!("Warning: audio device: no output device found");
+        return None;
+    };
+    let name = device.name().unwrap_or_else(|_| "(unnamed)".into());
+    match rodio::OutputStream::try_from_device(&device) {
         Ok((stream, handle)) => {
             match rodio::Sink::try_new(&handle) {
                 Ok(sink) => {
-                    let source = HybridAudioSource::new(ring, freq_l, freq_r, AUDIO_SAMPLE_RATE);
+                    let source = HybridAudioSource::new(ring, score, freq_l, freq_r, AUDIO_SAMPLE_RATE);
                     sink.append(source);
-                    Some((stream, handle, sink))
+                    Some(AudioOut { _stream: stream, _sink: sink, device: name })
                 }
                 Err(e) => { eprintln!("Warning: audio sink: {}", e); None }
             }
         }
-        Err(e) => { eprintln!("Warning: audio device: {}", e); None }
+        Err(e) => { eprintln!("Warning: audio device {}: {}", name, e); None }
+    }
+}
+
+/// Render the last frame's sample-accurate audio into the output ring.
+fn push_frame_audio(arduboy: &mut Arduboy, pcm_buf: &mut Vec<f32>,
+                    audio_ring: &std::sync::Mutex<std::collections::VecDeque<f32>>) {
+    arduboy.audio_buf.render_samples(pcm_buf, AUDIO_SAMPLE_RATE, arduboy.clock_hz, AUDIO_VOLUME);
+    if let Ok(mut ring) = audio_ring.lock() {
+        let max_buf = AUDIO_SAMPLE_RATE as usize / 5;
+        if ring.len() < max_buf {
+            ring.extend(pcm_buf.iter());
+        }
+    }
+}
+
+// ─── Focus Policy ───────────────────────────────────────────────────────────
+
+/// What the GUI does while its window is not focused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FocusPolicy {
+    /// Stop emulation and audio (default)
+    Pause,
+    /// Keep running silently, ignoring the gamepad
+    Mute,
+    /// Keep running with sound and gamepad input
+    Run,
+}
+
+impl FocusPolicy {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pause" => Some(FocusPolicy::Pause),
+            "mute" => Some(FocusPolicy::Mute),
+            "run" => Some(FocusPolicy::Run),
+            _ => None,
+        }
     }
 }
 
@@ -137,39 +308,205 @@ struct GamepadState {
     up: bool, down: bool, left: bool, right: bool,
     a: bool, b: bool,
     left_stick_x: f32, left_stick_y: f32,
+    /// Named buttons held, for `Pad.<button>` hotkeys
+    held: Vec<GilrsButton>,
 }
 
 impl GamepadState {
     fn new() -> Self {
         GamepadState {
             up: false, down: false, left: false, right: false,
-            a: false, b: false, left_stick_x: 0.0, left_stick_y: 0.0,
+            a: false, b: false, left_stick_x: 0.0, left_stick_y: 0.0, held: Vec::new(),
         }
     }
     fn eff_up(&self)    -> bool { self.up    || self.left_stick_y < -STICK_DEADZONE }
     fn eff_down(&self)  -> bool { self.down  || self.left_stick_y >  STICK_DEADZONE }
     fn eff_left(&self)  -> bool { self.left  || self.left_stick_x < -STICK_DEADZONE }
     fn eff_right(&self) -> bool { self.right || self.left_stick_x >  STICK_DEADZONE }
+    /// Whether the button named as in gilrs (`South`, `Start`, ...) is held
+    fn is_held(&self, name: &str) -> bool { self.held.iter().any(|b| format!("{:?}", b) == name) }
+    fn set(&mut self, control: Button, pressed: bool) {
+        match control {
+            Button::Up    => self.up    = pressed,
+            Button::Down  => self.down  = pressed,
+            Button::Left  => self.left  = pressed,
+            Button::Right => self.right = pressed,
+            Button::A     => self.a     = pressed,
+            Button::B     => self.b     = pressed,
+        }
+    }
+}
+
+/// Which connected gamepad drives this instance (`--gamepad`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GamepadSelect {
+    /// First connected pad; moves to another pad if it is unplugged (default)
+    First,
+    /// Pad at this position among the connected pads
+    Index(usize),
+    /// First pad whose name contains this text (lowercase)
+    Name(String),
+    /// Every pad at once (shared state)
+    Any,
+    /// Ignore gamepads
+    Off,
+}
+
+impl GamepadSelect {
+    fn parse(s: &str) -> Self {
+        match s.to_lowercase().as_str() {
+            "first" => GamepadSelect::First,
+            "any" | "all" => GamepadSelect::Any,
+            "none" | "off" => GamepadSelect::Off,
+            t => match t.parse::<usize>() {
+                Ok(n) => GamepadSelect::Index(n),
+                Err(_) => GamepadSelect::Name(t.to_string()),
+            },
+        }
+    }
+}
+
+/// A physical button in a mapping profile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PadInput {
+    Named(GilrsButton),
+    /// Raw evdev/platform code, for pads without a gilrs mapping
+    Raw(u32),
+}
+
+impl PadInput {
+    fn parse(s: &str) -> Option<Self> {
+        if let Some(n) = s.strip_prefix("raw:") {
+            return n.trim().parse().ok().map(PadInput::Raw);
+        }
+        const NAMES: [(&str, GilrsButton); 19] = [
+            ("South", GilrsButton::South), ("East", GilrsButton::East),
+            ("North", GilrsButton::North), ("West", GilrsButton::West),
+            ("C", GilrsButton::C), ("Z", GilrsButton::Z),
+            ("LeftTrigger", GilrsButton::LeftTrigger), ("LeftTrigger2", GilrsButton::LeftTrigger2),
+            ("RightTrigger", GilrsButton::RightTrigger), ("RightTrigger2", GilrsButton::RightTrigger2),
+            ("Select", GilrsButton::Select), ("Start", GilrsButton::Start), ("Mode", GilrsButton::Mode),
+            ("LeftThumb", GilrsButton::LeftThumb), ("RightThumb", GilrsButton::RightThumb),
+            ("DPadUp", GilrsButton::DPadUp), ("DPadDown", GilrsButton::DPadDown),
+            ("DPadLeft", GilrsButton::DPadLeft), ("DPadRight", GilrsButton::DPadRight),
+        ];
+        NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(s)).map(|&(_, b)| PadInput::Named(b))
+    }
+}
+
+/// Button mapping for pads whose name matches (`--gamepad-map`).
+#[derive(Debug, Clone)]
+struct GamepadProfile {
+    /// Lowercase name substring; `None` matches every pad
+    name: Option<String>,
+    map: Vec<(PadInput, Button)>,
+}
+
+/// Load `[[gamepad]]` profiles from a TOML file:
+///
+/// ```toml
+/// [[gamepad]]
+/// name = "8BitDo"           # device name contains (case-insensitive)
+/// a = ["West", "North"]
+/// b = ["South", "raw:305"]
+/// ```
+fn load_gamepad_profiles(path: &str) -> Result<Vec<GamepadProfile>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let doc = arduboy_core::toml_lite::parse(&text).map_err(|e| format!("{}: {}", path, e))?;
+    if let Some((key, _)) = doc.root.first() {
+        return Err(format!("{}: unknown key \"{}\" outside [[gamepad]]", path, key));
+    }
+    let mut profiles = Vec::new();
+    for (table, entries) in &doc.tables {
+        if table != "gamepad" {
+            return Err(format!("{}: unknown table [[{}]]", path, table));
+        }
+        let mut profile = GamepadProfile { name: None, map: Vec::new() };
+        for (key, val) in &entries.0 {
+            let control = match key.as_str() {
+                "name" => { profile.name = Some(val.as_str(key)?.to_lowercase()); continue; }
+                "up" => Button::Up, "down" => Button::Down,
+                "left" => Button::Left, "right" => Button::Right,
+                "a" => Button::A, "b" => Button::B,
+                _ => return Err(format!("{}: [[gamepad]]: unknown key \"{}\"", path, key)),
+            };
+            for v in val.as_array(key)? {
+                let s = v.as_str(key)?;
+                let input = PadInput::parse(s)
+                    .ok_or_else(|| format!("{}: {}: unknown button \"{}\"", path, key, s))?;
+                profile.map.push((input, control));
+            }
+        }
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+/// Binds this instance to one gamepad (or all of them) and applies mapping
+/// profiles, so several emulator windows can each own a controller.
+struct GamepadBinding {
+    select: GamepadSelect,
+    bound: Option<GamepadId>,
+    profiles: Vec<GamepadProfile>,
+}
+
+impl GamepadBinding {
+    fn new(select: GamepadSelect, profiles: Vec<GamepadProfile>) -> Self {
+        GamepadBinding { select, bound: None, profiles }
+    }
+
+    /// Whether events from `id` drive this instance.
+    fn accepts(&self, id: GamepadId) -> bool {
+        match self.select {
+            GamepadSelect::Any => true,
+            GamepadSelect::Off => false,
+            _ => self.bound == Some(id),
+        }
+    }
+
+    /// Bind to the pad matching the selector, if one is connected.
+    fn rebind(&mut self, gilrs: &Gilrs) {
+        let mut pads = gilrs.gamepads().filter(|(_, gp)| gp.is_connected());
+        let found = match &self.select {
+            GamepadSelect::First => pads.next(),
+            GamepadSelect::Index(n) => pads.nth(*n),
+            GamepadSelect::Name(s) => pads.find(|(_, gp)| gp.name().to_lowercase().contains(s.as_str())),
+            GamepadSelect::Any | GamepadSelect::Off => None,
+        };
+        self.bound = found.map(|(id, gp)| {
+            println!("Gamepad: using [{}] \"{}\"", id, gp.name());
+            id
+        });
+    }
+
+    /// First profile matching the name of pad `id`.
+    fn profile(&self, gilrs: &Gilrs, id: GamepadId) -> Option<&GamepadProfile> {
+        let name = gilrs.gamepad(id).name().to_lowercase();
+        self.profiles.iter().find(|p| p.name.as_ref().is_none_or(|n| name.contains(n.as_str())))
+    }
 }
 
-fn init_gamepad(debug: bool) -> Option<Gilrs> {
+fn init_gamepad(binding: &mut GamepadBinding, debug: bool) -> Option<Gilrs> {
+    if binding.select == GamepadSelect::Off {
+        return None;
+    }
     match Gilrs::new() {
         Ok(gilrs) => {
-            if debug {
-                let mut found = false;
-                for (id, gp) in gilrs.gamepads() {
-                    println!("Gamepad: [{}] \"{}\" ({})", id, gp.name(), gp.os_name());
-                    found = true;
+            let count = gilrs.gamepads().count();
+            if debug || count > 1 {
+                for (n, (id, gp)) in gilrs.gamepads().enumerate() {
+                    println!("Gamepad {}: [{}] \"{}\" ({})", n, id, gp.name(), gp.os_name());
                 }
-                if !found { println!("No gamepad (hot-plug supported)."); }
             }
+            if debug && count == 0 { println!("No gamepad (hot-plug supported)."); }
+            binding.rebind(&gilrs);
             Some(gilrs)
         }
         Err(e) => { eprintln!("Warning: gamepad: {}", e); None }
     }
 }
 
-fn poll_gamepad(gilrs: &mut Gilrs, state: &mut GamepadState, debug: bool) {
+fn poll_gamepad(gilrs: &mut Gilrs, state: &mut GamepadState, binding: &mut GamepadBinding, debug: bool) {
     while let Some(GilrsEvent { event, id, .. }) = gilrs.next_event() {
         if debug {
             match &event {
@@ -182,26 +519,54 @@ fn poll_gamepad(gilrs: &mut Gilrs, state: &mut GamepadState, debug: bool) {
             }
         }
         match event {
-            EventType::ButtonPressed(b, code)  => apply_button_ext(state, b, code, true),
-            EventType::ButtonReleased(b, code) => apply_button_ext(state, b, code, false),
-            EventType::AxisChanged(a, v, _) => apply_axis(state, a, v),
             EventType::Connected => {
-                if debug {
-                    for (_, gp) in gilrs.gamepads() {
-                        if gp.is_connected() { println!("Gamepad connected: \"{}\"", gp.name()); }
-                    }
+                if debug { println!("Gamepad connected: \"{}\"", gilrs.gamepad(id).name()); }
+                if binding.bound.is_none() { binding.rebind(gilrs); }
+            }
+            EventType::Disconnected => {
+                if binding.accepts(id) {
+                    *state = GamepadState::new();
+                }
+                if binding.bound == Some(id) {
+                    println!("Gamepad: \"{}\" disconnected", gilrs.gamepad(id).name());
+                    binding.bound = None;
+                    binding.rebind(gilrs);
                 }
             }
-            EventType::Disconnected => { if debug { println!("Gamepad disconnected"); } *state = GamepadState::new(); }
+            _ if !binding.accepts(id) => {}
+            EventType::ButtonPressed(b, code)  => apply_button_ext(state, binding.profile(gilrs, id), b, code, true),
+            EventType::ButtonReleased(b, code) => apply_button_ext(state, binding.profile(gilrs, id), b, code, false),
+            EventType::AxisChanged(a, v, _) => apply_axis(state, a, v),
             _ => {}
         }
     }
 }
 
 /// Map known gilrs buttons to Arduboy controls.
+/// Buttons listed in the pad's mapping profile use the profile instead.
 /// For Unknown buttons (generic controllers without gilrs mapping DB entry),
 /// use the raw evdev code: even codes → A, odd codes → B.
-fn apply_button_ext(state: &mut GamepadState, btn: GilrsButton, code: gilrs::ev::Code, pressed: bool) {
+fn apply_button_ext(state: &mut GamepadState, profile: Option<&GamepadProfile>,
+                    btn: GilrsButton, code: gilrs::ev::Code, pressed: bool) {
+    let raw = code.into_u32() & 0xFFFF;
+    if btn != GilrsButton::Unknown {
+        state.held.retain(|&b| b != btn);
+        if pressed { state.held.push(btn); }
+    }
+    if let Some(p) = profile {
+        let mut mapped = false;
+        for &(input, control) in &p.map {
+            let hit = match input {
+                PadInput::Named(b) => b == btn && btn != GilrsButton::Unknown,
+                PadInput::Raw(r) => r == raw,
+            };
+            if hit {
+                state.set(control, pressed);
+                mapped = true;
+            }
+        }
+        if mapped { return; }
+    }
     match btn {
         GilrsButton::DPadUp    => state.up    = pressed,
         GilrsButton::DPadDown  => state.down  = pressed,
@@ -215,7 +580,6 @@ fn apply_button_ext(state: &mut GamepadState, btn: GilrsButton, code: gilrs::ev:
         GilrsButton::Unknown => {
             // Generic controller fallback: split buttons by raw code parity
             // evdev codes 288,290,292... (even) → A;  289,291,293... (odd) → B
-            let raw = code.into_u32() & 0xFFFF;
             if raw % 2 == 0 { state.a = pressed; } else { state.b = pressed; }
         }
         _ => {}
@@ -238,33 +602,119 @@ fn apply_axis(state: &mut GamepadState, axis: Axis, value: f32) {
     }
 }
 
+/// minifb key for a hotkey name (see `arduboy_core::actions`).
+fn minifb_key(name: &str) -> Option<Key> {
+    const LETTERS: [Key; 26] = [
+        Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+        Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    ];
+    const DIGITS: [Key; 10] = [
+        Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4, Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    ];
+    const FKEYS: [Key; 12] = [
+        Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12,
+    ];
+    match name {
+        "Tab" => Some(Key::Tab),
+        "Enter" => Some(Key::Enter),
+        "Backspace" => Some(Key::Backspace),
+        "Space" => Some(Key::Space),
+        "Escape" => Some(Key::Escape),
+        "Left" => Some(Key::Left),
+        "Right" => Some(Key::Right),
+        "Up" => Some(Key::Up),
+        "Down" => Some(Key::Down),
+        _ => match name.as_bytes() {
+            [c @ b'A'..=b'Z'] => Some(LETTERS[(c - b'A') as usize]),
+            [c @ b'0'..=b'9'] => Some(DIGITS[(c - b'0') as usize]),
+            [b'F', ..] => name[1..].parse::<usize>().ok().and_then(|n| FKEYS.get(n.wrapping_sub(1)).copied()),
+            _ => None,
+        },
+    }
+}
+
 // ─── Screenshot (PNG) ───────────────────────────────────────────────────────
 
+/// Displayed screen size: the panel's visible window, widened by its pixel
+/// aspect ratio (e.g. 96×64 clones, non-square LCD pixels).
+fn screen_dims(arduboy: &Arduboy) -> (usize, usize) {
+    let (w, h) = arduboy.screen_size();
+    let dw = ((w as f32 * arduboy.pixel_aspect()).round() as usize).max(1);
+    (dw, h)
+}
+
+/// Visible screen pixels at the displayed size (nearest-neighbor resample
+/// for non-square pixels). Returns `(pixels, width, height)`.
+fn screen_pixels(arduboy: &Arduboy) -> (Vec<u32>, usize, usize) {
+    let (w, h) = arduboy.screen_size();
+    let px = arduboy.screen_u32();
+    let (dw, dh) = screen_dims(arduboy);
+    if dw == w {
+        return (px, w, h);
+    }
+    let mut out = Vec::with_capacity(dw * dh);
+    for y in 0..dh {
+        for x in 0..dw {
+            out.push(px[y * w + x * w / dw]);
+        }
+    }
+    (out, dw, dh)
+}
+
+/// [`screen_pixels`] turned by the game's rotation, as screenshots and
+/// recordings show them.
+fn output_pixels(arduboy: &Arduboy) -> (Vec<u32>, usize, usize) {
+    let (px, w, h) = screen_pixels(arduboy);
+    match arduboy.rotation {
+        Rotation::None => (px, w, h),
+        r => r.rotate(&px, w, h),
+    }
+}
+
+/// PCD8544 screen at its native 84×48 (true = dark pixel), turned by the
+/// game's rotation. None for other displays, whose screen is already native.
+fn native_pixels(arduboy: &Arduboy) -> Option<(Vec<bool>, usize, usize)> {
+    use arduboy_core::pcd8544::{PCD_WIDTH, PCD_HEIGHT};
+    if !matches!(arduboy.display_type, DisplayType::Pcd8544) {
+        return None;
+    }
+    Some(arduboy.rotation.rotate(&arduboy.pcd8544.native_pixels(), PCD_WIDTH, PCD_HEIGHT))
+}
+
+/// `--native` for `screenshot`/`record`: whether to export the PCD8544 at
+/// its own geometry (warns when the game has another display).
+fn native_export(args: &[String], arduboy: &Arduboy) -> bool {
+    if !args.iter().any(|a| a == "--native") {
+        return false;
+    }
+    let pcd = native_pixels(arduboy).is_some();
+    if !pcd {
+        eprintln!("--native: not a PCD8544 game; the screen is saved as is");
+    }
+    pcd
+}
+
 /// Save a screenshot at the current display scale (nearest-neighbor upscale).
 fn save_screenshot_png(arduboy: &Arduboy, path: &str, scale: usize) -> Result<(), String> {
+    let (px, w, h) = output_pixels(arduboy);
     if scale <= 1 {
         // 1x: save efficient monochrome PNG
-        let fb = arduboy.framebuffer_rgba();
-        let pixels: Vec<bool> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
-            .map(|i| fb[i * 4] > 128)
-            .collect();
-        let png = arduboy_core::png::encode_png_mono(
-            SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32, &pixels);
+        let pixels: Vec<bool> = px.iter().map(|&c| (c >> 16) & 0xFF > 128).collect();
+        let png = arduboy_core::png::encode_png_mono(w as u32, h as u32, &pixels);
         fs::write(path, &png).map_err(|e| format!("{}: {}", path, e))
     } else {
         // Scaled: nearest-neighbor upscale to RGBA PNG
-        let fb = arduboy.framebuffer_rgba();
-        let sw = SCREEN_WIDTH * scale;
-        let sh = SCREEN_HEIGHT * scale;
+        let sw = w * scale;
+        let sh = h * scale;
         let mut scaled = vec![0u8; sw * sh * 4];
-        for y in 0..SCREEN_HEIGHT {
-            for x in 0..SCREEN_WIDTH {
-                let si = (y * SCREEN_WIDTH + x) * 4;
-                let r = fb[si]; let g = fb[si+1]; let b = fb[si+2]; let a = fb[si+3];
+        for y in 0..h {
+            for x in 0..w {
+                let c = px[y * w + x];
+                let r = (c >> 16) as u8; let g = (c >> 8) as u8; let b = c as u8;
                 for sy in 0..scale {
                     for sx in 0..scale {
                         let di = ((y * scale + sy) * sw + x * scale + sx) * 4;
-                        scaled[di] = r; scaled[di+1] = g; scaled[di+2] = b; scaled[di+3] = a;
+                        scaled[di] = r; scaled[di+1] = g; scaled[di+2] = b; scaled[di+3] = 0xFF;
                     }
                 }
             }
@@ -274,6 +724,136 @@ fn save_screenshot_png(arduboy: &Arduboy, path: &str, scale: usize) -> Result<()
     }
 }
 
+/// LCD effect colors `(on, off)`.
+/// SSD1306 OLED palette: ON → blue-white, OFF → near-black
+/// PCD8544 LCD palette:  ON → dark gray-green, OFF → yellow-green
+fn lcd_palette(is_pcd: bool) -> ([u8; 3], [u8; 3]) {
+    if is_pcd {
+        let [off, on] = arduboy_core::pcd8544::PALETTE;
+        (on, off)
+    } else {
+        ([0xA0, 0xD0, 0xFF], [0x05, 0x05, 0x08])
+    }
+}
+
+/// LCD effect (L key): display palette with temporal ghosting, then pixel
+/// grid lines and corner rounding at `cur_scale`. `prev_frame` (one entry
+/// per raw pixel) carries the ghost image from frame to frame.
+fn render_lcd_effect(raw_pixels: &[u32], fw: usize, cur_scale: usize, is_pcd: bool,
+                     prev_frame: &mut [(f32, f32, f32)], scaled_buf: &mut [u32], scaled_w: usize) {
+    let fh = raw_pixels.len() / fw;
+    let rgb = |c: [u8; 3]| (c[0] as f32, c[1] as f32, c[2] as f32);
+    let (on, off) = lcd_palette(is_pcd);
+    let (col_on, col_off) = (rgb(on), rgb(off));
+    // Temporal blend factor: PCD8544 20% previous, SSD1306 5%
+    let ghost = if is_pcd { 0.20f32 } else { 0.05f32 };
+    let fresh = 1.0 - ghost;
+
+    for i in 0..(fw * fh) {
+        let raw = raw_pixels[i];
+        // Determine if pixel is "on" (any channel > 0x40)
+        let on = (raw & 0xFFFFFF) > 0x404040;
+        let (tr, tg, tb) = if on { col_on } else { col_off };
+        // Blend with previous frame
+        let (pr, pg, pb) = prev_frame[i];
+        let nr = tr * fresh + pr * ghost;
+        let ng = tg * fresh + pg * ghost;
+        let nb = tb * fresh + pb * ghost;
+        prev_frame[i] = (nr, ng, nb);
+    }
+
+    // Scale up from prev_frame
+    for y in 0..fh {
+        for x in 0..fw {
+            let (fr, fg, fb) = prev_frame[y * fw + x];
+            let c = ((fr as u32) << 16) | ((fg as u32) << 8) | (fb as u32);
+            for sy in 0..cur_scale {
+                let base = (y * cur_scale + sy) * scaled_w + x * cur_scale;
+                for sx in 0..cur_scale {
+                    if base + sx < scaled_buf.len() { scaled_buf[base + sx] = c; }
+                }
+            }
+        }
+    }
+
+    // (2) Pixel grid lines + (4) Corner rounding (need scale ≥ 3)
+    if cur_scale >= 3 {
+        // Grid line darkness: darken the last row and column of each pixel cell
+        let grid_dim = if is_pcd { 0.55f32 } else { 0.70f32 };
+        // Corner darkness
+        let corner_dim = if is_pcd { 0.40f32 } else { 0.50f32 };
+
+        for py in 0..fh {
+            for px in 0..fw {
+                let bx = px * cur_scale;
+                let by = py * cur_scale;
+
+                for sy in 0..cur_scale {
+                    for sx in 0..cur_scale {
+                        let gx = bx + sx;
+                        let gy = by + sy;
+                        let idx = gy * scaled_w + gx;
+                        if idx >= scaled_buf.len() { continue; }
+
+                        // Is this sub-pixel on a grid edge?
+                        let on_right = sx == cur_scale - 1;
+                        let on_bottom = sy == cur_scale - 1;
+                        // Is this sub-pixel a corner of the pixel block?
+                        let is_inner_corner = (sx == 0 || on_right) && (sy == 0 || on_bottom);
+
+                        let dim = if is_inner_corner {
+                            corner_dim
+                        } else if on_right || on_bottom {
+                            grid_dim
+                        } else {
+                            1.0
+                        };
+
+                        if dim < 1.0 {
+                            let c = scaled_buf[idx];
+                            let r = (((c >> 16) & 0xFF) as f32 * dim) as u32;
+                            let g = (((c >> 8) & 0xFF) as f32 * dim) as u32;
+                            let b = ((c & 0xFF) as f32 * dim) as u32;
+                            scaled_buf[idx] = (r << 16) | (g << 8) | b;
+                        }
+                    }
+                }
+            }
+        }
+    } else if cur_scale == 2 {
+        // At 2× only do subtle grid on right/bottom edge
+        let grid_dim = if is_pcd { 0.70f32 } else { 0.80f32 };
+        for py in 0..fh {
+            for px in 0..fw {
+                let bx = px * 2;
+                let by = py * 2;
+                // Right column
+                for sy in 0..2 {
+                    let idx = (by + sy) * scaled_w + bx + 1;
+                    if idx < scaled_buf.len() {
+                        let c = scaled_buf[idx];
+                        let r = (((c >> 16) & 0xFF) as f32 * grid_dim) as u32;
+                        let g = (((c >> 8) & 0xFF) as f32 * grid_dim) as u32;
+                        let b = ((c & 0xFF) as f32 * grid_dim) as u32;
+                        scaled_buf[idx] = (r << 16) | (g << 8) | b;
+                    }
+                }
+                // Bottom row
+                for sx in 0..2 {
+                    let idx = (by + 1) * scaled_w + bx + sx;
+                    if idx < scaled_buf.len() {
+                        let c = scaled_buf[idx];
+                        let r = (((c >> 16) & 0xFF) as f32 * grid_dim) as u32;
+                        let g = (((c >> 8) & 0xFF) as f32 * grid_dim) as u32;
+                        let b = ((c & 0xFF) as f32 * grid_dim) as u32;
+                        scaled_buf[idx] = (r << 16) | (g << 8) | b;
+                    }
+                }
+            }
+        }
+    }
+}
+
 // ─── EEPROM Persistence ─────────────────────────────────────────────────────
 
 fn eeprom_path(hex_path: &str) -> String {
@@ -283,11 +863,22 @@ fn eeprom_path(hex_path: &str) -> String {
     dir.join(format!("{}.eep", stem)).to_string_lossy().into_owned()
 }
 
+/// Wear statistics file next to the `.eep` file.
+fn eeprom_wear_path(eep_path: &str) -> String {
+    format!("{}wear", eep_path)
+}
+
 fn load_eeprom(arduboy: &mut Arduboy, path: &str, debug: bool) {
     if let Ok(data) = fs::read(path) {
         arduboy.load_eeprom(&data);
         if debug { eprintln!("EEPROM loaded: {} ({} bytes)", path, data.len()); }
     }
+    let wear_path = eeprom_wear_path(path);
+    if let Ok(data) = fs::read(&wear_path) {
+        if let Err(e) = arduboy.eeprom_wear.load(&data) {
+            eprintln!("EEPROM wear: {}: {}", wear_path, e);
+        }
+    }
 }
 
 fn save_eeprom(arduboy: &Arduboy, path: &str, debug: bool) {
@@ -300,91 +891,139 @@ fn save_eeprom(arduboy: &Arduboy, path: &str, debug: bool) {
             eprintln!("EEPROM saved: {}", path);
         }
     }
+    if arduboy.eeprom_wear.session_writes() > 0 {
+        let wear_path = eeprom_wear_path(path);
+        if let Err(e) = fs::write(&wear_path, arduboy.eeprom_wear.to_bytes()) {
+            eprintln!("EEPROM wear save error: {}: {}", wear_path, e);
+        }
+    }
+}
+
+/// FX save area file next to the `.eep` file.
+fn fx_save_path(eep_path: &str) -> String {
+    std::path::Path::new(eep_path).with_extension("fxsave").to_string_lossy().into_owned()
+}
+
+/// Write the FX save area if the game changed it.
+fn save_fx(arduboy: &mut Arduboy, eep_path: &str, debug: bool) {
+    let path = fx_save_path(eep_path);
+    match arduboy.flush_fx_save(std::path::Path::new(&path)) {
+        Ok(true) if debug => eprintln!("FX save written: {}", path),
+        Ok(_) => {}
+        Err(e) => eprintln!("FX save error: {}: {}", path, e),
+    }
+}
+
+/// Print EEPROM warnings and tracepoint output raised during the last frame.
+fn print_core_messages(arduboy: &mut Arduboy) {
+    for m in arduboy.tracepoints.take_messages() {
+        println!("{}", m);
+    }
+    for w in arduboy.eeprom_wear.take_warnings() {
+        eprintln!("{}", w);
+    }
+    for m in arduboy.display.take_messages() {
+        eprintln!("{}", m);
+    }
+    for m in arduboy.diagnostics.take_messages() {
+        eprintln!("{}", m);
+    }
+    for m in arduboy.faults.take_messages() {
+        eprintln!("{}", m);
+    }
+    for m in arduboy.flush_timing.take_messages() {
+        eprintln!("{}", m);
+    }
+    for w in arduboy.frame_budget.take_warnings() {
+        eprintln!("{}", w);
+    }
+    for e in arduboy.take_sound_events() {
+        eprintln!("Sound: frame {} {:?} {:?} {:.0} Hz {} ({:?})",
+            e.frame, e.channel, e.kind, e.hz, sound_events::note_name(e.note), e.source);
+    }
+}
+
+/// Run boot logo frames back to back for up to [`FAST_BOOT_SLICE`]. Each
+/// frame's audio is dropped by the next one, so the logo stays silent.
+fn fast_forward_boot(arduboy: &mut Arduboy) {
+    let start = Instant::now();
+    while arduboy.fast_boot.active() && !arduboy.breakpoint_hit && start.elapsed() < FAST_BOOT_SLICE {
+        arduboy.run_frame();
+        print_core_messages(arduboy);
+    }
+    if !arduboy.fast_boot.active() {
+        eprintln!("Fast boot: skipped {} frames of boot logo", arduboy.fast_boot.skipped);
+    }
 }
 
 // ─── File Loading ───────────────────────────────────────────────────────────
 
-struct LoadedGame {
-    hex_str: String,
-    fx_data: Option<Vec<u8>>,
-    fx_save: Option<Vec<u8>>,
-    title: String,
-    hex_path: String,
-    /// Raw ELF bytes (when loading .elf files)
-    elf_data: Option<Vec<u8>>,
+fn load_game_file(path: &str, fx_override: Option<&str>, debug: bool) -> Result<GameFile, String> {
+    let game = GameFile::load(path, fx_override).map_err(|e| format!("{}: {}", path, e))?;
+    if debug {
+        if !game.title.is_empty() { eprintln!("Arduboy file: \"{}\" by {}", game.title, game.author); }
+        if let Some(ref fx) = game.fx_data { eprintln!("FX data: {} bytes", fx.len()); }
+    }
+    Ok(game)
+}
+
+/// Built-in start screen, run when no game is given. Its path names no file,
+/// so the game browser starts in the current directory.
+fn boot_game() -> GameFile {
+    GameFile {
+        path: "arduboy-emu-start".to_string(),
+        title: "No game".to_string(),
+        hex: arduboy_core::boot_rom::hex(),
+        ..Default::default()
+    }
 }
 
-fn load_game_file(path: &str, fx_override: Option<&str>, debug: bool) -> Result<LoadedGame, String> {
+/// `--patch` file in IPS or BPS format rather than a flash patch list.
+fn is_rom_patch(path: &str) -> bool {
     let lower = path.to_lowercase();
+    lower.ends_with(".ips") || lower.ends_with(".bps")
+}
 
-    if lower.ends_with(".arduboy") {
-        // Parse .arduboy ZIP
-        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
-        let ab = arduboy_core::arduboy_file::parse_arduboy(&data)?;
-        if debug {
-            eprintln!("Arduboy file: \"{}\" by {}", ab.title, ab.author);
-            if let Some(ref fx) = ab.fx_data { eprintln!("  FX data: {} bytes", fx.len()); }
-        }
-        Ok(LoadedGame {
-            hex_str: ab.hex.ok_or("No HEX in .arduboy file")?,
-            fx_data: ab.fx_data,
-            fx_save: ab.fx_save,
-            title: if ab.title.is_empty() { String::new() } else { ab.title },
-            hex_path: path.to_string(),
-            elf_data: None,
-        })
-    } else if lower.ends_with(".elf") {
-        // ELF binary with debug info
-        let data = fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
-        // We store the raw ELF bytes; main() will call load_elf()
-        Ok(LoadedGame {
-            hex_str: String::new(), // not used for ELF
-            fx_data: if let Some(fx_path) = fx_override {
-                Some(fs::read(fx_path).map_err(|e| format!("{}: {}", fx_path, e))?)
-            } else { auto_find_fx(path) },
-            fx_save: None,
-            title: String::new(),
-            hex_path: path.to_string(),
-            elf_data: Some(data),
-        })
-    } else {
-        // Plain .hex file
-        let hex_str = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
-        let fx_data = if let Some(fx_path) = fx_override {
-            Some(fs::read(fx_path).map_err(|e| format!("{}: {}", fx_path, e))?)
-        } else {
-            auto_find_fx(path)
-        };
-        if debug {
-            if let Some(ref fx) = fx_data { eprintln!("FX data: {} bytes", fx.len()); }
-        }
-        Ok(LoadedGame {
-            hex_str,
-            fx_data,
-            fx_save: None,
-            title: String::new(),
-            hex_path: path.to_string(),
-            elf_data: None,
-        })
+/// CPU for a game: the board profile's, an explicit choice, or detected
+/// from the flash image.
+fn game_cpu(game: &GameFile, board: Option<&arduboy_core::BoardProfile>, cpu_override: Option<CpuType>, debug: bool) -> CpuType {
+    let (cpu, detected) = game.cpu(board, cpu_override);
+    let Some(detected) = detected else { return cpu };
+    if debug || detected.confidence < 0.9 {
+        eprintln!("CPU auto-detected: {}", detected.summary());
+    }
+    if detected.confidence < 0.9 {
+        eprintln!("  (use --cpu 32u4 or --cpu 328p if the game does not run)");
     }
+    detected.cpu
 }
 
-fn auto_find_fx(hex_path: &str) -> Option<Vec<u8>> {
-    let bin = hex_path.replace(".hex", ".bin").replace(".HEX", ".bin");
-    if bin != hex_path && std::path::Path::new(&bin).exists() {
-        return fs::read(&bin).ok();
+/// Load the game's program (ELF or HEX) into flash; the parsed ELF for
+/// symbols and line info.
+fn load_program(arduboy: &mut Arduboy, game: &GameFile, debug: bool) -> Result<Option<ElfFile>, String> {
+    let Some(elf) = game.load_program(arduboy).map_err(|e| format!("{}: {}", game.path, e))? else {
+        if debug { eprintln!("Loaded {} bytes into flash", arduboy.program_size); }
+        return Ok(None);
+    };
+    eprintln!("ELF loaded: {} symbols, {} line entries",
+        elf.symbols.len(), elf.line_map.len());
+    if let Some(f) = elf.fuses {
+        eprintln!("ELF fuses: {}", f);
     }
-    let dir = std::path::Path::new(hex_path).parent().unwrap_or(std::path::Path::new("."));
-    let stem = std::path::Path::new(hex_path).file_stem().and_then(|s| s.to_str()).unwrap_or("");
-    let fx = dir.join(format!("{}-fx.bin", stem));
-    if fx.exists() { fs::read(&fx).ok() } else { None }
+    if debug { eprint!("{}", elf.layout_report()); }
+    Ok(Some(elf))
 }
 
 /// Load FX data+save into the emulator at the correct flash layout offsets.
-fn load_game_fx(arduboy: &mut Arduboy, game: &LoadedGame, debug: bool) {
+fn load_game_fx(arduboy: &mut Arduboy, game: &GameFile, debug: bool) {
     if let Some(ref fx) = game.fx_data {
-        let save = game.fx_save.as_deref();
+        // A save written by an earlier session replaces the bundled one
+        let fxsave = fs::read(fx_save_path(&eeprom_path(&game.path))).ok();
+        let save = fxsave.as_deref().or(game.fx_save.as_deref());
         let (dp, sp) = arduboy.load_fx_layout(fx, save);
+        if arduboy.board.as_ref().is_some_and(|b| !b.fx) {
+            eprintln!("FX data ignored: board profile has no FX flash (try --board arduboy-fx)");
+        }
         eprintln!("FX layout: data={} bytes at page 0x{:04X} (byte 0x{:06X}), save at page 0x{:04X}",
             fx.len(), dp, dp as u32 * 256, sp);
         if debug {
@@ -443,25 +1082,66 @@ fn switch_game(
     arduboy: &mut Arduboy, path: &str, eep_path_old: &str,
     no_save: bool, debug: bool,
 ) -> Result<(String, String, String), String> {
-    // Save current EEPROM before switching
+    // Save current EEPROM and FX save before switching
     if !no_save && arduboy.eeprom_dirty {
         save_eeprom(arduboy, eep_path_old, debug);
     }
+    if !no_save {
+        save_fx(arduboy, eep_path_old, debug);
+    }
     let game = load_game_file(path, None, debug)?;
 
-    // Auto-detect CPU type for the new game
-    let mut tmp = vec![0u8; 32768];
-    let detected = if arduboy_core::hex::parse_hex(&game.hex_str, &mut tmp).is_ok() {
-        detect_cpu_type(&tmp)
-    } else {
-        arduboy.cpu_type
+    // Auto-detect CPU type for the new game (a board profile pins it)
+    let detected = match game.detect_cpu() {
+        Some(d) if arduboy.board.is_none() => d.cpu,
+        _ => arduboy.cpu_type,
     };
 
     // If CPU type changed, reinitialize Arduboy entirely
     if detected != arduboy.cpu_type {
         let was_debug = arduboy.debug;
+        let log = arduboy.log;
+        let vcd = arduboy.vcd.take();
+        let verify = arduboy.verify.take();
+        let spi_decoder = std::mem::take(&mut arduboy.spi_decoder);
+        let spi_trace = std::mem::take(&mut arduboy.spi_trace);
+        let eeprom_guard = arduboy.eeprom_guard.clone();
+        let faults = std::mem::take(&mut arduboy.faults);
+        let devices = std::mem::take(&mut arduboy.devices);
+        let display_cache = arduboy.display_cache.take();
+        let (shadow, shadow_break) = (arduboy.shadow_stack.enabled, arduboy.shadow_stack.break_on_mismatch);
+        let (strict, strict_break) = (arduboy.uninit.enabled, arduboy.uninit.break_on_read);
+        let trace_tail = arduboy.trace_tail.enabled;
+        let sound = arduboy.sound_events.enabled;
+        let flush = arduboy.flush_timing.enabled;
+        let fx_fill = arduboy.fx_flash.fill;
+        let geometry = arduboy.display.geometry;
+        let quirks = arduboy.display.quirks;
+        let clock_hz = arduboy.clock_hz;
+        let fast_boot = arduboy.fast_boot.enabled;
         *arduboy = Arduboy::new_with_cpu(detected);
         arduboy.debug = was_debug;
+        arduboy.clock_hz = clock_hz;
+        arduboy.fast_boot.enabled = fast_boot;
+        arduboy.log = log;
+        arduboy.set_display_quirks(quirks);
+        arduboy.set_panel_geometry(geometry);
+        arduboy.vcd = vcd;
+        arduboy.verify = verify;
+        arduboy.spi_decoder = spi_decoder;
+        arduboy.spi_trace = spi_trace;
+        arduboy.eeprom_guard = eeprom_guard;
+        arduboy.faults = faults;
+        arduboy.devices = devices;
+        arduboy.display_cache = display_cache;
+        arduboy.shadow_stack.enabled = shadow;
+        arduboy.shadow_stack.break_on_mismatch = shadow_break;
+        arduboy.set_strict(strict);
+        arduboy.uninit.break_on_read = strict_break;
+        arduboy.trace_tail.enabled = trace_tail;
+        arduboy.sound_events.enabled = sound;
+        arduboy.flush_timing.enabled = flush;
+        arduboy.fx_flash.fill = fx_fill;
         if detected == CpuType::Atmega328p {
             eprintln!("CPU: ATmega328P (Gamebuino Classic mode)");
         } else {
@@ -471,9 +1151,14 @@ fn switch_game(
         arduboy.reset();
     }
 
-    arduboy.load_hex(&game.hex_str).map_err(|e| format!("HEX parse: {}", e))?;
+    // Patches address the old program
+    arduboy.patches.clear(&mut arduboy.mem.flash);
+    arduboy.rom_patches.clear();
+    load_program(arduboy, &game, debug)?;
     load_game_fx(arduboy, &game, debug);
-    let new_eep = eeprom_path(&game.hex_path);
+    arduboy.rotation = game.rotation;
+    arduboy.eeprom_wear.clear();
+    let new_eep = eeprom_path(&game.path);
     if !no_save { load_eeprom(arduboy, &new_eep, debug); }
     let title = if game.title.is_empty() {
         std::path::Path::new(path).file_stem()
@@ -482,7 +1167,7 @@ fn switch_game(
     } else {
         game.title
     };
-    Ok((game.hex_path, title, new_eep))
+    Ok((game.path, title, new_eep))
 }
 
 // ─── Main ───────────────────────────────────────────────────────────────────
@@ -502,10 +1187,33 @@ fn main() {
         }
     }
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
+    let mut args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|a| a == "package") {
+        std::process::exit(run_package(&args[2..]));
+    }
+    // `screenshot <game> ...` / `record <game> ...`: the rest is parsed
+    // like a normal run
+    let subcommand = args.get(1).filter(|a| *a == "screenshot" || *a == "record").cloned();
+    if subcommand.is_some() {
+        args.remove(1);
+    }
+    // No game: boot the built-in start screen, except for the subcommands
+    let game_path = args.get(1).filter(|a| !a.starts_with("--"));
+    if (subcommand.is_some() && game_path.is_none()) || args.iter().any(|a| a == "--help" || a == "-h") {
         eprintln!("Arduboy Emulator v0.8.1 - Rust");
         eprintln!("Usage: {} <file.hex|.arduboy|.elf> [options]", args[0]);
+        eprintln!("       {} --scenario <test.toml>", args[0]);
+        eprintln!("       {} --selftest [rom.hex]   Run the CPU conformance ROM (optionally save it)", args[0]);
+        eprintln!("       {}                        Start screen; N/P/O browse games in the current directory", args[0]);
+        eprintln!("       {} screenshot <game> [--frame N] [--out file.png] [--press F[-G]:btn] [--scale N] [--lcd] [--native]", args[0]);
+        eprintln!("                         Run headless and save the screen at frame N (default 120) as PNG");
+        eprintln!("       {} record <game> [--frames N] [--start F] [--out file.gif|.png] [--press F[-G]:btn]", args[0]);
+        eprintln!("                         [--scale N] [--lcd] [--every N]  Run headless and save an animated GIF/APNG");
+        eprintln!("                         --native saves Gamebuino (PCD8544) screens at 84x48 in LCD colors");
+        eprintln!("       {} package <game.hex|.elf> [--fx data.bin] [--fx-save save.bin] [--title-image title.png]", args[0]);
+        eprintln!("                         [--title T] [--author A] [--description D] [--version V] [--genre G]");
+        eprintln!("                         [--url U] [--license L] [--rotate DEG] [--out file.arduboy]");
+        eprintln!("                         Validate build artifacts and write a .arduboy archive");
         eprintln!();
         eprintln!("Supported formats:");
         eprintln!("  .hex             Intel HEX binary");
@@ -514,122 +1222,559 @@ fn main() {
         eprintln!();
         eprintln!("Options:");
         eprintln!("  --headless           Run without GUI");
+        eprintln!("  --remote [addr]      Run without GUI in real time, streaming the screen and taking buttons");
+        eprintln!("                       over TCP/WebSocket; open http://addr/ to view (default 127.0.0.1:8765)");
         eprintln!("  --frames N           Run N frames (headless/step, default 60)");
         eprintln!("  --debug              Show per-frame diagnostics");
         eprintln!("  --press N            Press A on frame N (headless)");
+        eprintln!("  --at <when>:<action> Scheduled action (headless, repeatable); when = frame N or tN (tick),");
+        eprintln!("                       action = press=B, release=B, adc=CH,VALUE|noise, mark=NAME, screenshot[=FILE],");
+        eprintln!("                       fault=SPEC|off");
         eprintln!("  --snapshot F         Print display at frame F (repeatable)");
         eprintln!("  --mute               Disable audio");
         eprintln!("  --fx <file.bin>      Load FX flash data");
+        eprintln!("  --fx-fill <hex>      Value of FX flash outside the image (default FF, erased)");
+        eprintln!("  --fuses <L:H[:E]>    Fuse bytes in hex, e.g. 7F:D1 (CKDIV8, BOOTRST, BOOTSZ; overrides ELF .fuse)");
+        eprintln!("  --clock <freq>       Oscillator frequency, e.g. 8M for clones or 24M to overclock (default 16M)");
+        eprintln!("  --fast-boot          Fast-forward through the Arduboy2 boot logo after every load and reset");
         eprintln!("  --break <addr>       Breakpoint at hex byte-address (repeatable)");
+        eprintln!("  --halt-on-break      Stop like a breakpoint after a BREAK instruction (default: NOP)");
+        eprintln!("  --plugin <lib>       Load a native plugin (.so/.dylib/.dll) for frame events and overlays (repeatable)");
+        eprintln!("  --bind <act=keys>    Rebind a hotkey, e.g. mute=F8,Pad.Select (repeatable; see README)");
+        eprintln!("  --autosplit <file>   Autosplitter config (TOML: start/reset/[[split]] RAM conditions)");
+        eprintln!("  --livesplit [addr]   Send autosplits to LiveSplit Server (default 127.0.0.1:16834)");
         eprintln!("  --watch <addr>       Data watchpoint at hex address (repeatable)");
+        eprintln!("  --protect <spec>     Stop on writes to a RAM range from other code: <range|symbol>[@<fn|range>,...]");
+        eprintln!("  --bounds             Report loads/stores outside global objects (ELF only)");
+        eprintln!("  --shadow-stack       Report RET/RETI to an address other than the one pushed by the call");
+        eprintln!("  --shadow-stack-break Same, and stop like a breakpoint on a mismatch");
+        eprintln!("  --stack-guard [lim]  Report the stack growing below .data/.bss, or a RAM symbol's end or hex address,");
+        eprintln!("                       with the call stack (turns on --shadow-stack)");
+        eprintln!("  --stack-guard-break [lim] Same, and stop like a breakpoint on an overflow");
+        eprintln!("  --strict             Report loads from SRAM never written since reset (PC and disassembly)");
+        eprintln!("  --strict-break       Same, and stop like a breakpoint on such a load");
+        eprintln!("  --flush-stats        Measure display flush cycles per frame against the SPI minimum (report on exit)");
+        eprintln!("  --fault <spec>       Inject peripheral faults, e.g. eeprom-fail,every=3 or fx-corrupt,addr=0x1000-0x1FFF");
+        eprintln!("                       or spi-drop,p=0.01 (repeatable; options addr=A-B, every=N, p=F, count=N, xor=M)");
+        eprintln!("  --devices <file>     Map virtual devices from a TOML file (constant, counter, rng, buffer)");
+        eprintln!("  --sound-events       Log tone onsets/offsets (frequency, note, source); title shows notes playing");
+        eprintln!("  --tracepoint <spec>  Log without stopping: '<addr> \"<fmt>\" [if <cond>]' (repeatable)");
         eprintln!("  --step               Interactive step debugger");
+        eprintln!("  --display-cache <f>  File of displays detected per game (default: arduboy-emu/displays.txt in the config dir)");
+        eprintln!("  --no-display-cache   Auto-detect the display every run without remembering it");
+        eprintln!("  --symbols <file>     User symbols/comments for the debugger and profiler (default: <game>.sym)");
+        eprintln!("  --log <spec>         Core diagnostics per category, e.g. spi=trace,fx=debug or info");
+        eprintln!("  --floating-pins <p>  Undriven input pins read as high|random|script:0xAA,0x55,...");
+        eprintln!("  --speaker-pins <l[,r]> Bit-bang speaker pins (default PC6,PB5; 328P: PD3)");
+        eprintln!("  --speaker <model>    Speaker response: soft (default), piezo, headphone or ideal");
+        eprintln!("                       (cpu, spi, display, fx, usb, timer, audio, eeprom)");
+        eprintln!("  --ex \"<cmd>\"         Run a debugger command at startup (repeatable, implies --step)");
+        eprintln!("  --autoexec <file>    Run debugger commands from a file at startup (implies --step)");
+        eprintln!("  --scenario <file>    Run a TOML test scenario (exit 0 = pass, 1 = fail)");
         eprintln!("  --gdb <port>         Start GDB remote debug server on TCP port");
         eprintln!("  --profile            Enable profiler (report on exit)");
+        eprintln!("  --profile-json <f>   Enable profiler and write its data as JSON on exit");
         eprintln!("  --scale N            Initial scale 1-6 (default 6)");
+        eprintln!("  --rotate <deg>       Screen rotation: 0, 90, 180, 270 (default: from .arduboy metadata)");
+        eprintln!("  --refresh <Hz>       Host display refresh rate; the game still runs at 60 fps (default 60)");
+        eprintln!("  --frame-blend        Blend the last two game frames when --refresh is not 60");
+        eprintln!("  --time-box <ms>      Emulate at most this long per displayed frame, finishing slow frames later");
+        eprintln!("  --overrun <policy>   When the host falls behind: slowdown, catchup[:<ms>] or free");
         eprintln!("  --serial             Show USB serial output on stderr");
+        eprintln!("  --serial-port <p>    Bridge serial to a host device (path, COMn, or \"pty\")");
+        eprintln!("  --serial-rpc         Accept ESC]emu;... control sequences on serial");
+        eprintln!("  --coproc <script>    Answer serial commands from a TOML script, or \"esp\" for ESP8266 AT commands");
+        eprintln!("  --vcd <file>         Record pin/SPI waveforms to a VCD file (GTKWave)");
+        eprintln!("  --vcd-probes <list>  VCD signals, e.g. PORTB,PD1,spi,speaker (default: all)");
+        eprintln!("  --spi-log <file>     Decode SPI transactions and write a JSON timeline on exit");
+        eprintln!("  --timeline <file>    Record per-frame ISR/SPI/sleep spans; write Chrome trace JSON on exit");
+        eprintln!("  --perf-trace <file>  Time the core's phases (cpu, SPI, peripherals, audio); Chrome trace JSON on exit");
+        eprintln!("  --latency [N]        Measure input latency over N trials (default 10) and exit");
+        eprintln!("  --latency-button <b> Button to press: up, down, left, right, a (default), b");
+        eprintln!("  --latency-marker <s> Response is serial output containing s instead of a screen change");
+        eprintln!("  --latency-warmup F   Frames to run before the first trial (default 180)");
+        eprintln!("  --state-dump <file>  Write a sorted text dump of the emulator state on exit (for diff)");
+        eprintln!("  --score-audio        Synthesize ArduboyTones/Playtune music from the score (.elf)");
         eprintln!("  --no-save            Disable EEPROM auto-save");
-        eprintln!("  --cpu <type>         CPU type: 32u4 or 328p (auto-detected if omitted)");
+        eprintln!("  --eeprom-report      Print EEPROM wear statistics on exit");
+        eprintln!("  --eeprom-log         Print every EEPROM write with address, value and PC");
+        eprintln!("  --system <k=v,...>   Set Arduboy2 system EEPROM: name, id, audio, logo, logo-leds, show-name");
+        eprintln!("  --eeprom-guard [a-b] Block EEPROM writes outside a-b (default: the game's range from the");
+        eprintln!("                       save range database, else 16-1023 to protect the system area)");
+        eprintln!("  --save-ranges <file> Save range database (default: arduboy-emu/save-ranges.txt in the config dir)");
+        eprintln!("  --verify-log <file>  Record a hash-chained input/checkpoint log for run verification");
+        eprintln!("  --verify-interval N  Frames between verification checkpoints (default 60)");
+        eprintln!("  --verify <file>      Replay a verification log against the game (exit 0 = match)");
+        eprintln!("  --cpu <type>         CPU type: 32u4, 328p or 2560 (auto-detected if omitted)");
+        eprintln!("  --board <name|file>  Board profile: arduboy, arduboy-fx, leonardo-sh1106,");
+        eprintln!("                       gamebuino, uno-i2c-oled, or a TOML file");
         eprintln!("  --lcd                Start with LCD effect enabled");
+        eprintln!("  --screen WxH[+OFF]   Panel size/column offset for clones, e.g. 96x64, 72x40+28");
+        eprintln!("  --pixel-aspect R     Pixel width/height ratio (default 1.0)");
+        eprintln!("  --display-controller <c[,opts]>  ssd1306, sh1106, ssd1309, ssd1305");
+        eprintln!("                       opts: charge-pump, no-charge-pump, page-only, remap, offset=N, strict");
+        eprintln!("  --strict-display     Keep the screen dark until the game sends charge pump on");
+        eprintln!("                       (8D 14) and display on (AF), warning about the missing one");
         eprintln!("  --no-blur            Start with blur disabled");
+        eprintln!("  --input-overlay      Start with the button input display shown (toggle: I)");
+        eprintln!("  --focus <policy>     When unfocused: pause (default), mute, or run");
+        eprintln!("  --gamepad <sel>      Controller to use: first (default), index N, name text, any, none");
+        eprintln!("  --gamepad-map <file> Per-controller button mapping profiles (TOML)");
+        eprintln!("  --audio-device <sel> Audio output: default (follows the system), index N, name text, or list");
+        eprintln!("  --no-av-sync         Keep cycles per frame fixed instead of correcting A/V drift");
+        eprintln!("  --av-stats           Print A/V offset statistics on exit");
         eprintln!();
         eprintln!("GUI keys: Arrows=D-pad Z=A X=B  1-6=Scale F11=Fullscreen");
         eprintln!("          S=Screenshot(PNG) G=GIF record D=RegDump T=Profiler");
-        eprintln!("          M=Mute F=FPS unlimited B=Blur L=LCD effect A=Audio filter");
-        eprintln!("          V=Portrait rotation  R=Reload N=Next P=Previous O=List games");
-        eprintln!("          Backspace=Rewind  Esc=Quit");
+        eprintln!("          M=Mute Shift+M=Audio device F=FPS unlimited B=Blur L=LCD effect A=Audio filter");
+        eprintln!("          V=Rotate screen  I=Input display  R=Reload N=Next P=Previous O=List games");
+        eprintln!("          Backspace=Rewind (Shift=4x)  Tab=Timeline  F12=Bug report  Esc=Quit");
         std::process::exit(1);
     }
 
-    let game_path = &args[1];
+    if let Some(i) = args.iter().position(|a| a == "--selftest") {
+        std::process::exit(run_selftest(args.get(i + 1).map(|s| s.as_str())));
+    }
+
+    if let Some(path) = args.iter()
+        .position(|a| a == "--scenario")
+        .and_then(|i| args.get(i + 1))
+    {
+        let debug = args.iter().any(|a| a == "--debug");
+        std::process::exit(run_scenario(path, debug));
+    }
+
     let headless = args.iter().any(|a| a == "--headless");
+    let remote_addr = args.iter().position(|a| a == "--remote").map(|i| {
+        args.get(i + 1).filter(|a| !a.starts_with('-')).cloned()
+            .unwrap_or_else(|| arduboy_core::remote::REMOTE_ADDR.to_string())
+    });
     let mute = args.iter().any(|a| a == "--mute");
     let debug = args.iter().any(|a| a == "--debug");
-    let step_mode = args.iter().any(|a| a == "--step");
+    let step_mode = args.iter().any(|a| a == "--step" || a == "--ex" || a == "--autoexec");
     let serial_enabled = args.iter().any(|a| a == "--serial");
+    let serial_rpc = args.iter().any(|a| a == "--serial-rpc");
     let no_save = args.iter().any(|a| a == "--no-save");
-    let profile_enabled = args.iter().any(|a| a == "--profile");
+    let profile_json: Option<&str> = args.iter()
+        .position(|a| a == "--profile-json")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
+    let profile_enabled = args.iter().any(|a| a == "--profile") || profile_json.is_some();
     let lcd_start = args.iter().any(|a| a == "--lcd");
     let no_blur = args.iter().any(|a| a == "--no-blur");
-
-    let gdb_port: Option<u16> = args.iter()
-        .position(|a| a == "--gdb")
+    let input_overlay = args.iter().any(|a| a == "--input-overlay");
+    let focus_policy = match args.iter().position(|a| a == "--focus").and_then(|i| args.get(i + 1)) {
+        Some(p) => FocusPolicy::parse(p).unwrap_or_else(|| {
+            eprintln!("--focus: expected pause, mute or run, got '{}'", p);
+            std::process::exit(1);
+        }),
+        None => FocusPolicy::Pause,
+    };
+    let gamepad_select = args.iter().position(|a| a == "--gamepad").and_then(|i| args.get(i + 1))
+        .map(|s| GamepadSelect::parse(s))
+        .unwrap_or(GamepadSelect::First);
+    let gamepad_profiles = match args.iter().position(|a| a == "--gamepad-map").and_then(|i| args.get(i + 1)) {
+        Some(path) => load_gamepad_profiles(path).unwrap_or_else(|e| {
+            eprintln!("--gamepad-map: {}", e);
+            std::process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let audio_device = match args.iter().position(|a| a == "--audio-device").and_then(|i| args.get(i + 1)) {
+        Some(sel) if sel == "list" => {
+            for (i, name) in audio_device_names().iter().enumerate() {
+                println!("{:3}. {}", i, name);
+            }
+            std::process::exit(0);
+        }
+        Some(sel) => AudioDevice::parse(sel),
+        None => AudioDevice::Default,
+    };
+    let mut av_sync = AvSync::new(AV_SYNC_TARGET_MS);
+    av_sync.correct = !args.iter().any(|a| a == "--no-av-sync");
+    let av_stats = args.iter().any(|a| a == "--av-stats");
+    let score_audio = args.iter().any(|a| a == "--score-audio");
+    let eeprom_report = args.iter().any(|a| a == "--eeprom-report");
+
+    let serial_port: Option<&str> = args.iter()
+        .position(|a| a == "--serial-port")
         .and_then(|i| args.get(i + 1))
-        .and_then(|s| s.parse().ok());
+        .map(|s| s.as_str());
 
-    let initial_scale: usize = args.iter()
-        .position(|a| a == "--scale")
+    let vcd_path: Option<&str> = args.iter()
+        .position(|a| a == "--vcd")
         .and_then(|i| args.get(i + 1))
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(6).max(1).min(6);
+        .map(|s| s.as_str());
+    let vcd_probes: Option<&str> = args.iter()
+        .position(|a| a == "--vcd-probes")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
 
-    let fx_override: Option<&str> = args.iter()
-        .position(|a| a == "--fx")
+    let spi_log: Option<&str> = args.iter()
+        .position(|a| a == "--spi-log")
         .and_then(|i| args.get(i + 1))
         .map(|s| s.as_str());
 
-    let cpu_override: Option<CpuType> = args.iter()
-        .position(|a| a == "--cpu")
+    let timeline_log: Option<&str> = args.iter()
+        .position(|a| a == "--timeline")
         .and_then(|i| args.get(i + 1))
-        .map(|s| match s.as_str() {
-            "328p" | "328P" | "atmega328p" => CpuType::Atmega328p,
-            _ => CpuType::Atmega32u4,
-        });
+        .map(|s| s.as_str());
 
-    // Load game (hex or .arduboy)
-    let game = load_game_file(game_path, fx_override, debug)
-        .expect("Failed to load game file");
+    let perf_log: Option<&str> = args.iter()
+        .position(|a| a == "--perf-trace")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
 
-    // Determine CPU type: explicit --cpu flag, or auto-detect from flash contents
-    let cpu_type = if let Some(ct) = cpu_override {
-        ct
-    } else {
-        let mut tmp = vec![0u8; 32768];
-        if arduboy_core::hex::parse_hex(&game.hex_str, &mut tmp).is_ok() {
-            let detected = detect_cpu_type(&tmp);
-            if debug {
-                eprintln!("CPU auto-detected: {:?}", detected);
+    let mut panel_geometry = arduboy_core::PanelGeometry::default();
+    if let Some(spec) = args.iter()
+        .position(|a| a == "--screen")
+        .and_then(|i| args.get(i + 1))
+    {
+        match arduboy_core::PanelGeometry::parse(spec) {
+            Ok(g) => panel_geometry = g,
+            Err(e) => {
+                eprintln!("--screen: {}", e);
+                std::process::exit(1);
             }
-            detected
-        } else {
-            CpuType::Atmega32u4
         }
-    };
-
-    let mut arduboy = Arduboy::new_with_cpu(cpu_type);
-    arduboy.debug = debug;
-    if cpu_type == CpuType::Atmega328p {
-        eprintln!("CPU: ATmega328P (Gamebuino Classic mode)");
     }
-
-    // Load game — ELF or HEX
-    let mut _elf_info: Option<arduboy_core::elf::ElfFile> = None;
-    if let Some(ref elf_data) = game.elf_data {
-        match arduboy.load_elf(elf_data) {
-            Ok(elf) => {
-                eprintln!("ELF loaded: {} symbols, {} line entries",
-                    elf.symbols.len(), elf.line_map.len());
-                _elf_info = Some(elf);
-            }
-            Err(e) => {
-                eprintln!("ELF parse error: {}", e);
+    if let Some(r) = args.iter()
+        .position(|a| a == "--pixel-aspect")
+        .and_then(|i| args.get(i + 1))
+    {
+        match r.parse::<f32>() {
+            Ok(v) if v > 0.0 && v <= 4.0 => panel_geometry.pixel_aspect = v,
+            _ => {
+                eprintln!("--pixel-aspect: expected a ratio between 0 and 4, got '{}'", r);
                 std::process::exit(1);
             }
         }
-    } else {
-        let size = arduboy.load_hex(&game.hex_str).expect("Failed to parse HEX");
-        if debug { eprintln!("Loaded {} bytes into flash", size); }
     }
 
-    load_game_fx(&mut arduboy, &game, debug);
+    let display_quirks: Option<arduboy_core::DisplayQuirks> = args.iter()
+        .position(|a| a == "--display-controller")
+        .and_then(|i| args.get(i + 1))
+        .map(|spec| match arduboy_core::DisplayQuirks::parse(spec) {
+            Ok(q) => q,
+            Err(e) => {
+                eprintln!("--display-controller: {}", e);
+                std::process::exit(1);
+            }
+        });
 
-    // Parse breakpoints
-    {
-        let mut i = 0;
-        while i < args.len() {
-            if args[i] == "--break" {
+    let verify_log: Option<&str> = args.iter()
+        .position(|a| a == "--verify-log")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
+    let verify_replay: Option<&str> = args.iter()
+        .position(|a| a == "--verify")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
+    let verify_interval: u32 = args.iter()
+        .position(|a| a == "--verify-interval")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(60);
+
+    let gdb_port: Option<u16> = args.iter()
+        .position(|a| a == "--gdb")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|s| s.parse().ok());
+
+    let initial_scale: usize = args.iter()
+        .position(|a| a == "--scale")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(6).max(1).min(6);
+
+    let refresh_hz: u32 = args.iter()
+        .position(|a| a == "--refresh")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(EMU_FPS).clamp(24, 480);
+    let frame_blend = args.iter().any(|a| a == "--frame-blend");
+    // Host time per presented frame for emulation, in µs (--time-box <ms>)
+    let time_box = match args.iter().position(|a| a == "--time-box").and_then(|i| args.get(i + 1)) {
+        Some(spec) => match spec.parse::<f64>() {
+            Ok(ms) if ms > 0.0 => Some((ms * 1000.0) as u64),
+            _ => {
+                eprintln!("--time-box: expected milliseconds, got '{}'", spec);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let overrun = match args.iter().position(|a| a == "--overrun").and_then(|i| args.get(i + 1)) {
+        Some(spec) => match OverrunPolicy::parse(spec) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                eprintln!("--overrun: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let fx_override: Option<&str> = args.iter()
+        .position(|a| a == "--fx")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| s.as_str());
+
+    let cpu_override: Option<CpuType> = args.iter()
+        .position(|a| a == "--cpu")
+        .and_then(|i| args.get(i + 1))
+        .map(|s| match s.as_str() {
+            "328p" | "328P" | "atmega328p" => CpuType::Atmega328p,
+            "2560" | "atmega2560" | "mega" => CpuType::Atmega2560,
+            _ => CpuType::Atmega32u4,
+        });
+
+    let board: Option<arduboy_core::BoardProfile> = args.iter()
+        .position(|a| a == "--board")
+        .and_then(|i| args.get(i + 1))
+        .map(|spec| match arduboy_core::BoardProfile::load(spec) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("--board: {}", e);
+                std::process::exit(1);
+            }
+        });
+
+    // Load game (hex or .arduboy)
+    let game = match game_path {
+        Some(path) => load_game_file(path, fx_override, debug).expect("Failed to load game file"),
+        None => boot_game(),
+    };
+
+    // Determine CPU type: board profile, explicit --cpu flag, or auto-detect from flash contents
+    let cpu_type = game_cpu(&game, board.as_ref(), cpu_override, debug);
+
+    let mut arduboy = match board {
+        Some(b) => {
+            eprintln!("Board: {}", b.name);
+            if b.bus == arduboy_core::DisplayBus::I2c {
+                eprintln!("Board: I2C displays are not emulated yet; the screen stays blank");
+            }
+            Arduboy::with_board(b)
+        }
+        None => Arduboy::new_with_cpu(cpu_type),
+    };
+    arduboy.debug = debug;
+    if let Some(spec) = args.iter().position(|a| a == "--log").and_then(|i| args.get(i + 1)) {
+        if let Err(e) = arduboy.log.apply(spec) {
+            eprintln!("--log: {}", e);
+            std::process::exit(1);
+        }
+    }
+    if let Some(spec) = args.iter().position(|a| a == "--floating-pins").and_then(|i| args.get(i + 1)) {
+        match arduboy_core::FloatingPinPolicy::parse(spec) {
+            Ok(p) => arduboy.floating_pins = p,
+            Err(e) => {
+                eprintln!("--floating-pins: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(spec) = args.iter().position(|a| a == "--speaker-pins").and_then(|i| args.get(i + 1)) {
+        match arduboy_core::AudioRouter::parse_pins(spec) {
+            Ok((left, right)) => arduboy.audio_router.set_pins(left, right),
+            Err(e) => {
+                eprintln!("--speaker-pins: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(v) = args.iter().position(|a| a == "--speaker").and_then(|i| args.get(i + 1)) {
+        match SpeakerModel::parse(v) {
+            Ok(m) => arduboy.audio_buf.set_speaker(m),
+            Err(e) => {
+                eprintln!("--{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    arduboy.eeprom_guard.log_writes = args.iter().any(|a| a == "--eeprom-log");
+    if let Some(i) = args.iter().position(|a| a == "--eeprom-guard") {
+        use arduboy_core::eeprom_guard::{EepromGuard, SaveRanges};
+        match args.get(i + 1).filter(|s| !s.starts_with("--")) {
+            Some(spec) => match EepromGuard::parse_range(spec) {
+                Ok((lo, hi)) => {
+                    arduboy.eeprom_guard.save_range = Some((lo, hi));
+                    eprintln!("EEPROM guard: game writes limited to 0x{:03X}-0x{:03X}", lo, hi);
+                }
+                Err(e) => {
+                    eprintln!("--eeprom-guard: {}", e);
+                    std::process::exit(1);
+                }
+            },
+            // Per-game range from the database, picked at each reset
+            None => {
+                let path = args.iter().position(|a| a == "--save-ranges").and_then(|i| args.get(i + 1))
+                    .map(PathBuf::from).or_else(SaveRanges::default_path);
+                let ranges = match &path {
+                    Some(p) => SaveRanges::load(p).unwrap_or_else(|e| {
+                        eprintln!("Save ranges: {}", e);
+                        SaveRanges::default()
+                    }),
+                    None => SaveRanges::default(),
+                };
+                arduboy.eeprom_guard.save_range = Some(EepromGuard::default_range(arduboy.mem.eeprom.len()));
+                arduboy.eeprom_guard.game_ranges = Some(ranges);
+            }
+        }
+    }
+    arduboy.set_panel_geometry(panel_geometry);
+    if let Some(q) = display_quirks {
+        arduboy.set_display_quirks(q);
+        if q.controller != arduboy_core::DisplayController::Ssd1306 {
+            eprintln!("Display controller: {}", q.controller.name());
+        }
+    }
+    if args.iter().any(|a| a == "--strict-display") {
+        arduboy.display.quirks.strict_power = true;
+    }
+    if cpu_type == CpuType::Atmega328p {
+        eprintln!("CPU: ATmega328P (Gamebuino Classic mode)");
+    } else if cpu_type == CpuType::Atmega2560 {
+        eprintln!("CPU: ATmega2560 (Arduino Mega mode)");
+    }
+
+    // Displays detected in earlier runs, so the game starts on the right one
+    let display_cache_path = if args.iter().any(|a| a == "--no-display-cache") {
+        None
+    } else {
+        args.iter().position(|a| a == "--display-cache").and_then(|i| args.get(i + 1)).map(PathBuf::from)
+            .or_else(DisplayCache::default_path)
+    };
+    if let Some(path) = &display_cache_path {
+        match DisplayCache::load(path) {
+            Ok(cache) => arduboy.display_cache = Some(cache),
+            Err(e) => eprintln!("Display cache: {}", e),
+        }
+    }
+
+    // Load game — ELF or HEX
+    let mut elf_info = match load_program(&mut arduboy, &game, debug) {
+        Ok(elf) => elf,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    arduboy.fast_boot.enabled = args.iter().any(|a| a == "--fast-boot");
+    if let Some(v) = args.iter().position(|a| a == "--clock").and_then(|i| args.get(i + 1)) {
+        match arduboy_core::parse_clock_hz(v) {
+            Ok(hz) => arduboy.clock_hz = hz,
+            Err(e) => {
+                eprintln!("--clock: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(v) = args.iter().position(|a| a == "--fuses").and_then(|i| args.get(i + 1)) {
+        match Fuses::parse(v) {
+            Ok(f) => {
+                arduboy.set_fuses(f);
+                arduboy.reset();
+                if let Some((start, _)) = arduboy.boot_section() {
+                    eprintln!("Fuses {}: boot section at 0x{:05X}", f, start * 2);
+                }
+            }
+            Err(e) => {
+                eprintln!("--{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // User symbols and comments: --symbols <file>, or <game>.sym next to the game
+    let sym_arg = args.iter().position(|a| a == "--symbols").and_then(|i| args.get(i + 1));
+    let sym_path = sym_arg.map(PathBuf::from)
+        .or_else(|| game_path.map(|p| Annotations::path_for(Path::new(p))));
+    let mut annotations = Annotations::default();
+    if let Some(path) = sym_path.as_ref().filter(|p| p.exists()) {
+        match Annotations::load(path) {
+            Ok(a) => {
+                eprintln!("Symbols: {} ({} entries)", path.display(), a.len());
+                match elf_info.as_mut() {
+                    Some(elf) => a.apply(elf),
+                    None => elf_info = Some(a.to_elf()),
+                }
+                annotations = a;
+            }
+            Err(e) => {
+                eprintln!("--symbols: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(path) = sym_arg {
+        eprintln!("Symbols: {} (new; write it with 'symsave' in --step)", path);
+    }
+
+    arduboy.rotation = game.rotation;
+    if let Some(v) = args.iter().position(|a| a == "--rotate").and_then(|i| args.get(i + 1)) {
+        match Rotation::parse(v) {
+            Ok(r) => arduboy.rotation = r,
+            Err(e) => {
+                eprintln!("--rotate: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if arduboy.rotation != Rotation::None {
+        eprintln!("Rotation: {}°", arduboy.rotation.degrees());
+    }
+
+    // IPS/BPS ROM hacks, before the flash patches that address the result
+    for (i, a) in args.iter().enumerate() {
+        if a != "--patch" { continue; }
+        let Some(path) = args.get(i + 1).filter(|p| is_rom_patch(p)) else { continue };
+        let result = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| arduboy.add_rom_patch(data).map_err(|e| e.to_string()));
+        match result {
+            Ok(size) => eprintln!("ROM patch: {} ({} bytes)", path, size),
+            Err(e) => {
+                eprintln!("--patch: {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if let Some(v) = args.iter().position(|a| a == "--fx-fill").and_then(|i| args.get(i + 1)) {
+        match parse_cli_hex(v) {
+            Some(fill) if fill <= 0xFF => arduboy.fx_flash.fill = fill as u8,
+            _ => {
+                eprintln!("--fx-fill: expected a byte in hex, got '{}'", v);
+                std::process::exit(1);
+            }
+        }
+    }
+    load_game_fx(&mut arduboy, &game, debug);
+
+    // Score audio: locate the game's music player from ELF symbols
+    let score_tracker = if score_audio {
+        match elf_info.as_ref().and_then(arduboy_core::score::detect) {
+            Some(player) => {
+                eprintln!("Score audio: {:?} detected", player.engine);
+                Some(ScoreTracker::new(player))
+            }
+            None => {
+                eprintln!("Score audio: no ArduboyTones/ArduboyPlaytune symbols found (requires an .elf build)");
+                None
+            }
+        }
+    } else { None };
+
+    // Parse breakpoints
+    {
+        let mut i = 0;
+        while i < args.len() {
+            if args[i] == "--break" {
                 if let Some(s) = args.get(i + 1) {
                     let s = s.trim_start_matches("0x").trim_start_matches("0X");
-                    if let Ok(addr) = u16::from_str_radix(s, 16) {
+                    if let Ok(addr) = u32::from_str_radix(s, 16) {
                         let word_addr = addr / 2;
                         arduboy.breakpoints.push(word_addr);
                         if debug { eprintln!("Breakpoint: 0x{:04X} (word 0x{:04X})", addr, word_addr); }
@@ -659,6 +1804,172 @@ fn main() {
         }
     }
 
+    // Parse write-protect regions
+    for (i, a) in args.iter().enumerate() {
+        if a != "--protect" { continue; }
+        if let Some(spec) = args.get(i + 1) {
+            match arduboy_core::debugger::ProtectRegion::parse(spec, elf_info.as_ref()) {
+                Ok(r) => {
+                    if debug { eprintln!("Write-protect: 0x{:04X}-0x{:04X} {}", r.start, r.end, r.spec); }
+                    arduboy.debugger.protect_regions.push(r);
+                }
+                Err(e) => {
+                    eprintln!("--protect: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    // Load flash patches
+    for (i, a) in args.iter().enumerate() {
+        if a != "--patch" { continue; }
+        if let Some(path) = args.get(i + 1).filter(|p| !is_rom_patch(p)) {
+            let parsed = fs::read_to_string(path)
+                .map_err(|e| format!("{}: {}", path, e))
+                .and_then(|text| arduboy_core::patch::Patches::parse(&text, elf_info.as_ref())
+                    .map_err(|e| format!("{}: {}", path, e)));
+            match parsed {
+                Ok(p) => {
+                    if debug { eprint!("{}", p.list()); }
+                    arduboy.add_patches(p);
+                }
+                Err(e) => {
+                    eprintln!("--patch: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+
+    if args.iter().any(|a| a == "--bounds") {
+        let checker = match &elf_info {
+            Some(elf) => arduboy_core::bounds::BoundsChecker::from_elf(elf),
+            None => Err("needs an ELF file".into()),
+        };
+        match checker {
+            Ok(b) => arduboy.bounds = b,
+            Err(e) => {
+                eprintln!("--bounds: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    arduboy.sound_events.enabled = args.iter().any(|a| a == "--sound-events");
+    arduboy.flush_timing.enabled = args.iter().any(|a| a == "--flush-stats");
+    for (i, _) in args.iter().enumerate().filter(|(_, a)| *a == "--fault") {
+        let spec = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        match arduboy_core::fault::FaultRule::parse(spec) {
+            Ok(rule) => { arduboy.faults.add(rule); }
+            Err(e) => {
+                eprintln!("--fault: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if let Some(pos) = args.iter().position(|a| a == "--devices") {
+        let path = args.get(pos + 1).map(|s| s.as_str()).unwrap_or("");
+        match arduboy_core::virtual_devices::VirtualDevices::load(Path::new(path)) {
+            Ok(devices) => {
+                eprintln!("Virtual devices: {}", devices.devices.len());
+                arduboy.devices = devices;
+            }
+            Err(e) => {
+                eprintln!("--devices: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut plugins = arduboy_core::plugin::Plugins::new();
+    for (i, _) in args.iter().enumerate().filter(|(_, a)| *a == "--plugin") {
+        let path = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        match arduboy_core::plugin::Plugin::load(Path::new(path)) {
+            Ok(plugin) => {
+                eprintln!("Plugin: {}", plugin.name());
+                plugins.add(plugin);
+            }
+            Err(e) => {
+                eprintln!("--plugin {}: {}", path, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    let mut actions = Actions::new();
+    for (i, _) in args.iter().enumerate().filter(|(_, a)| *a == "--bind") {
+        let spec = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        if let Err(e) = actions.bind(spec) {
+            eprintln!("--bind: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let mut autosplit = args.iter().position(|a| a == "--autosplit").map(|i| {
+        let path = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        let symbols = elf_info.as_ref().map(|e| Symbols::from_elf(&e.symbols)).unwrap_or_default();
+        let splitter = arduboy_core::autosplit::Autosplitter::load(Path::new(path), &symbols)
+            .unwrap_or_else(|e| {
+                eprintln!("--autosplit: {}", e);
+                std::process::exit(1);
+            });
+        eprintln!("Autosplit: {} split(s)", splitter.splits.len());
+        let livesplit = args.iter().position(|a| a == "--livesplit").map(|j| {
+            let addr = args.get(j + 1).filter(|a| !a.starts_with('-'))
+                .map(|s| s.as_str()).unwrap_or(arduboy_core::autosplit::LIVESPLIT_ADDR);
+            match arduboy_core::autosplit::LiveSplitClient::connect(addr) {
+                Ok(client) => {
+                    eprintln!("LiveSplit: connected to {}", addr);
+                    client
+                }
+                Err(e) => {
+                    eprintln!("--livesplit {}: {}", addr, e);
+                    std::process::exit(1);
+                }
+            }
+        });
+        AutoSplit { splitter, livesplit }
+    });
+    arduboy.halt_on_break = args.iter().any(|a| a == "--halt-on-break");
+    arduboy.shadow_stack.break_on_mismatch = args.iter().any(|a| a == "--shadow-stack-break");
+    arduboy.shadow_stack.enabled = arduboy.shadow_stack.break_on_mismatch
+        || args.iter().any(|a| a == "--shadow-stack");
+    if let Some(i) = args.iter().position(|a| a == "--stack-guard" || a == "--stack-guard-break") {
+        let boundary = args.get(i + 1).filter(|a| !a.starts_with('-')).map(|s| s.as_str());
+        match arduboy_core::stack_guard::StackGuard::from_elf(elf_info.as_ref(), boundary) {
+            Ok(g) => {
+                if debug { eprintln!("Stack guard: limit 0x{:04X}", g.limit); }
+                arduboy.stack_guard = g;
+            }
+            Err(e) => {
+                eprintln!("--stack-guard: {}", e);
+                std::process::exit(1);
+            }
+        }
+        arduboy.stack_guard.break_on_overflow = args[i] == "--stack-guard-break";
+        // Shadow frames give the call stack in reports
+        arduboy.shadow_stack.enabled = true;
+    }
+    if args.iter().any(|a| a == "--strict" || a == "--strict-break") {
+        arduboy.set_strict(true);
+        arduboy.uninit.break_on_read = args.iter().any(|a| a == "--strict-break");
+    }
+
+    // Parse tracepoints
+    {
+        let symbols = elf_info.as_ref().map(|e| Symbols::from_elf(&e.symbols)).unwrap_or_default();
+        for (i, a) in args.iter().enumerate() {
+            if a != "--tracepoint" { continue; }
+            if let Some(spec) = args.get(i + 1) {
+                match arduboy.tracepoints.add(spec, &symbols) {
+                    Ok(idx) => if debug { eprintln!("Tracepoint [{}]: {}", idx, spec); },
+                    Err(e) => {
+                        eprintln!("--tracepoint: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+    }
+
     // Auto-start profiler if --profile
     if profile_enabled {
         arduboy.profiler.start(arduboy.cpu.tick);
@@ -666,20 +1977,106 @@ fn main() {
     }
 
     // EEPROM: auto-load
-    let eep_path = eeprom_path(&game.hex_path);
+    let eep_path = eeprom_path(&game.path);
     if !no_save {
         load_eeprom(&mut arduboy, &eep_path, debug);
     }
+    if let Some(spec) = args.iter().position(|a| a == "--system").and_then(|i| args.get(i + 1)) {
+        let mut sys = arduboy.system_settings();
+        if let Err(e) = sys.apply(spec) {
+            eprintln!("--system: {}", e);
+            std::process::exit(1);
+        }
+        arduboy.set_system_settings(&sys);
+        eprintln!("System EEPROM: unit \"{}\" ID 0x{:04X}, audio {}", sys.name(), sys.unit_id,
+            if sys.audio { "on" } else { "off" });
+    }
 
-    if let Some(port) = gdb_port {
+    // VCD waveform recording
+    if let Some(path) = vcd_path {
+        let probes = match vcd_probes.map(arduboy_core::vcd::parse_probes) {
+            Some(Ok(p)) => p,
+            Some(Err(e)) => {
+                eprintln!("--vcd-probes: {}", e);
+                std::process::exit(1);
+            }
+            None => arduboy_core::vcd::default_probes(),
+        };
+        if let Err(e) = arduboy.start_vcd(Path::new(path), probes) {
+            eprintln!("--vcd: {}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+
+    if spi_log.is_some() {
+        arduboy.spi_decoder.start();
+    }
+    if timeline_log.is_some() {
+        arduboy.frame_timeline.start();
+    }
+    if perf_log.is_some() {
+        arduboy.perf_trace.start();
+    }
+
+    // Run verification: replay a log, or start recording one
+    if let Some(log) = verify_replay {
+        std::process::exit(run_verify(&mut arduboy, log));
+    }
+    if verify_log.is_some() {
+        arduboy.start_verify(verify_interval, &game.title);
+    }
+
+    // Serial bridge: connect the emulated USB CDC / USART to a host device
+    let mut bridge = serial_port.map(|spec| match SerialBridge::open(spec) {
+        Ok(b) => {
+            eprintln!("Serial bridge: {}", b.name);
+            b
+        }
+        Err(e) => {
+            eprintln!("Serial bridge error: {}", e);
+            std::process::exit(1);
+        }
+    });
+
+    let mut rpc = if serial_rpc { Some(RpcParser::new()) } else { None };
+    let coproc = args.iter().position(|a| a == "--coproc").map(|i| {
+        let spec = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        match arduboy_core::coprocessor::Coprocessor::load(spec) {
+            Ok(c) => {
+                if debug { eprintln!("Co-processor: {} rules", c.rules.len()); }
+                c
+            }
+            Err(e) => {
+                eprintln!("--coproc: {}", e);
+                std::process::exit(1);
+            }
+        }
+    });
+    let mut serial = SerialIo { echo: serial_enabled, bridge: &mut bridge, rpc: &mut rpc, coproc, capture: None };
+
+    let mut exit_code: Option<i32> = None;
+    if subcommand.as_deref() == Some("screenshot") {
+        exit_code = Some(run_screenshot(&args, &mut arduboy, lcd_start));
+    } else if subcommand.as_deref() == Some("record") {
+        exit_code = Some(run_record(&args, &mut arduboy, lcd_start));
+    } else if args.iter().any(|a| a == "--latency") {
+        exit_code = Some(run_latency(&args, &mut arduboy));
+    } else if let Some(port) = gdb_port {
         run_gdb_mode(&mut arduboy, port, debug);
     } else if step_mode {
-        run_step_mode(&args, &mut arduboy);
+        run_step_mode(&args, &mut arduboy, &mut elf_info, &mut annotations, sym_path.as_deref());
+    } else if let Some(addr) = remote_addr {
+        exit_code = run_remote(&args, &mut arduboy, &mut serial, &addr);
     } else if headless {
-        run_headless(&args, &mut arduboy, serial_enabled);
+        exit_code = run_headless(&args, &mut arduboy, &mut serial);
     } else {
-        run_gui(&mut arduboy, mute, debug, initial_scale, serial_enabled,
-                &game.hex_path, &game.title, no_save, lcd_start, no_blur);
+        let opts = GuiOptions {
+            hex_path: &game.path, game_title: &game.title, start_muted: mute, debug, initial_scale, no_save,
+            lcd_start, no_blur, input_overlay, focus_policy, gamepad_select, gamepad_profiles, audio_device,
+            av_sync, av_stats, refresh_hz, frame_blend, time_box, overrun, actions,
+        };
+        exit_code = run_gui(&mut arduboy, &opts, &mut serial, score_tracker, elf_info.as_ref(), &mut plugins,
+                autosplit.as_mut());
     }
 
     // Profiler report on exit
@@ -689,28 +2086,207 @@ fn main() {
         }
         eprintln!("{}", arduboy.profiler_report());
     }
+    if arduboy.flush_timing.enabled {
+        eprintln!("{}", arduboy.flush_timing.report());
+    }
+    if let Err(e) = arduboy.devices.flush() {
+        eprintln!("Virtual device write error: {}", e);
+    }
+    if let (Some(path), Some(cache)) = (&display_cache_path, arduboy.display_cache.as_mut().filter(|c| c.dirty)) {
+        if let Err(e) = cache.save(path) {
+            eprintln!("Display cache save error: {}", e);
+        }
+    }
+    if let Some(path) = profile_json {
+        match fs::write(path, arduboy.profile_data(elf_info.as_ref()).to_json()) {
+            Ok(()) => eprintln!("Profile: {}", path),
+            Err(e) => eprintln!("Profile write error: {}: {}", path, e),
+        }
+    }
+
+    // VCD: flush the streamed waveform
+    if let Some(path) = vcd_path {
+        match arduboy.finish_vcd() {
+            Ok(changes) => eprintln!("VCD: {} ({} changes)", path, changes),
+            Err(e) => eprintln!("VCD {}: {}", path, e),
+        }
+    }
+
+    // Run verification log
+    if let (Some(path), Some(v)) = (verify_log, arduboy.verify.as_ref()) {
+        match fs::write(path, v.finish()) {
+            Ok(()) => eprintln!("Verify log: {} ({} frames)", path, v.frames()),
+            Err(e) => eprintln!("Verify log write error: {}: {}", path, e),
+        }
+    }
+
+    // SPI decoder: write the transaction timeline
+    if let Some(path) = spi_log {
+        arduboy.spi_decoder.stop();
+        match fs::write(path, arduboy.spi_decoder.to_json(arduboy.clock_hz)) {
+            Ok(()) => eprintln!("SPI log: {} ({} transactions)", path, arduboy.spi_decoder.len()),
+            Err(e) => eprintln!("SPI log write error: {}: {}", path, e),
+        }
+    }
+
+    // Frame timeline: Chrome trace of the last recorded frames
+    if let Some(path) = timeline_log {
+        match fs::write(path, arduboy.frame_timeline.to_chrome_json(arduboy.clock_hz)) {
+            Ok(()) => eprintln!("Timeline: {} ({} frames)", path, arduboy.frame_timeline.len()),
+            Err(e) => eprintln!("Timeline write error: {}: {}", path, e),
+        }
+    }
+
+    // Host-time phase profile of the core
+    if let Some(path) = perf_log {
+        eprintln!("Perf: {}", arduboy.perf_trace.summary());
+        match fs::write(path, arduboy.perf_trace.to_chrome_json()) {
+            Ok(()) => eprintln!("Perf trace: {}", path),
+            Err(e) => eprintln!("Perf trace write error: {}: {}", path, e),
+        }
+    }
+
+    // Full state dump for diffing runs
+    if let Some(path) = args.iter().position(|a| a == "--state-dump").and_then(|i| args.get(i + 1)) {
+        match fs::write(path, arduboy.dump_full_state_text()) {
+            Ok(()) => eprintln!("State dump: {}", path),
+            Err(e) => eprintln!("State dump write error: {}: {}", path, e),
+        }
+    }
+
+    // EEPROM and FX save: auto-save on exit
+    if !no_save && arduboy.eeprom_dirty {
+        save_eeprom(&arduboy, &eep_path, debug);
+    }
+    if !no_save {
+        save_fx(&mut arduboy, &eep_path, debug);
+    }
+    if eeprom_report {
+        eprint!("{}", arduboy.eeprom_wear.report());
+    }
+
+    if let Some(code) = exit_code {
+        std::process::exit(code);
+    }
+}
+
+// ─── Serial I/O ─────────────────────────────────────────────────────────────
+
+/// Where the game's serial output goes each frame.
+struct SerialIo<'a> {
+    /// Copy output to stderr (`--serial`)
+    echo: bool,
+    /// Host pty / COM port bridge (`--serial-port`)
+    bridge: &'a mut Option<SerialBridge>,
+    /// Escape-sequence command parser (`--serial-rpc`)
+    rpc: &'a mut Option<RpcParser>,
+    /// Scripted module answering the game's commands (`--coproc`)
+    coproc: Option<arduboy_core::coprocessor::Coprocessor>,
+    /// Recent screens and output for bug reports (GUI)
+    capture: Option<CaptureRecorder>,
+}
+
+impl SerialIo<'_> {
+    /// Exchange serial data for one frame and return any control commands.
+    fn service(&mut self, arduboy: &mut Arduboy) -> Vec<RpcCommand> {
+        if let Some(ref mut b) = self.bridge {
+            b.poll_input(arduboy);
+        }
+        let raw = arduboy.take_serial_output();
+        if let Some(ref mut c) = self.coproc {
+            c.feed(&raw);
+            let reply = c.tick();
+            if !reply.is_empty() {
+                arduboy.push_serial_input(&reply);
+            }
+        }
+        if raw.is_empty() { return Vec::new(); }
+        let (out, cmds) = match self.rpc {
+            Some(ref mut p) => p.feed(&raw),
+            None => (raw, Vec::new()),
+        };
+        if let Some(ref mut b) = self.bridge {
+            b.send(&out);
+        }
+        if let Some(ref mut c) = self.capture {
+            c.record_serial(&out);
+        }
+        if self.echo && !out.is_empty() {
+            let _ = std::io::stderr().write_all(&out);
+            let _ = std::io::stderr().flush();
+        }
+        cmds
+    }
+}
+
+// ─── GUI Mode ───────────────────────────────────────────────────────────────
+
+/// Autosplitter and its optional LiveSplit connection.
+struct AutoSplit {
+    splitter: arduboy_core::autosplit::Autosplitter,
+    livesplit: Option<arduboy_core::autosplit::LiveSplitClient>,
+}
 
-    // EEPROM: auto-save on exit
-    if !no_save && arduboy.eeprom_dirty {
-        save_eeprom(&arduboy, &eep_path, debug);
+impl AutoSplit {
+    fn update(&mut self, arduboy: &Arduboy) {
+        for event in self.splitter.update(arduboy) {
+            eprintln!("[autosplit] {}", self.splitter.describe(&event));
+            if let Some(client) = &mut self.livesplit {
+                if let Err(e) = client.send(&event, self.splitter.game_time) {
+                    eprintln!("[autosplit] LiveSplit: {} (disconnected)", e);
+                    self.livesplit = None;
+                }
+            }
+        }
     }
 }
 
-// ─── GUI Mode ───────────────────────────────────────────────────────────────
+/// Window, audio and pacing settings for [`run_gui`], from the command line.
+struct GuiOptions<'a> {
+    hex_path: &'a str,
+    game_title: &'a str,
+    start_muted: bool,
+    debug: bool,
+    initial_scale: usize,
+    no_save: bool,
+    lcd_start: bool,
+    no_blur: bool,
+    input_overlay: bool,
+    focus_policy: FocusPolicy,
+    gamepad_select: GamepadSelect,
+    gamepad_profiles: Vec<GamepadProfile>,
+    audio_device: AudioDevice,
+    av_sync: AvSync,
+    av_stats: bool,
+    /// Host display rate for the frame pacer (`--refresh`)
+    refresh_hz: u32,
+    frame_blend: bool,
+    /// Emulation time limit per host frame in µs (`--time-box`)
+    time_box: Option<u64>,
+    overrun: Option<OverrunPolicy>,
+    /// Hotkey bindings (`--bind`)
+    actions: Actions,
+}
 
-fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale: usize,
-           serial_enabled: bool, hex_path: &str, game_title: &str, no_save: bool,
-           lcd_start: bool, no_blur: bool)
+fn run_gui(arduboy: &mut Arduboy, opts: &GuiOptions, serial: &mut SerialIo,
+           mut score_tracker: Option<ScoreTracker>, mut elf: Option<&ElfFile>,
+           plugins: &mut arduboy_core::plugin::Plugins, mut autosplit: Option<&mut AutoSplit>) -> Option<i32>
 {
-    let mut cur_hex_path = hex_path.to_string();
-    let mut scale = initial_scale;
-    let mut scaled_w = SCREEN_WIDTH * scale;
-    let mut scaled_h = SCREEN_HEIGHT * scale;
+    let GuiOptions { debug, no_save, focus_policy, frame_blend, time_box, .. } = *opts;
+    let mut gamepad = GamepadBinding::new(opts.gamepad_select.clone(), opts.gamepad_profiles.clone());
+    let mut audio_device = opts.audio_device.clone();
+    let mut av_sync = opts.av_sync.clone();
+    let mut actions = opts.actions.clone();
+    let mut cur_hex_path = opts.hex_path.to_string();
+    let mut scale = opts.initial_scale;
+    let (mut fw, mut fh) = screen_dims(arduboy);
+    let mut scaled_w = fw * scale;
+    let mut scaled_h = fh * scale;
     let make_title = |game_t: &str| -> String {
         if game_t.is_empty() { "Arduboy v0.8.1".to_string() }
         else { format!("Arduboy v0.8.1 - {}", game_t) }
     };
-    let mut title_base = make_title(game_title);
+    let mut title_base = make_title(opts.game_title);
 
     let mut window = Window::new(
         &title_base, scaled_w, scaled_h,
@@ -721,37 +2297,45 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
             ..Default::default()
         },
     ).expect("Failed to create window");
-    window.set_target_fps(60);
+    // Present at the host rate; the pacer keeps game time at 60 fps
+    let mut pacer = FramePacer::new(opts.refresh_hz, opts.overrun);
+    window.set_target_fps(if pacer.policy() == OverrunPolicy::FreeRun { 0 } else { pacer.host_fps() });
 
     let audio_ring: Arc<std::sync::Mutex<std::collections::VecDeque<f32>>> =
         Arc::new(std::sync::Mutex::new(std::collections::VecDeque::with_capacity(16384)));
     let freq_l = Arc::new(AtomicU32::new(0.0f32.to_bits()));
     let freq_r = Arc::new(AtomicU32::new(0.0f32.to_bits()));
-    let mut muted = start_muted;
-    let mut _audio = if !muted { setup_audio(audio_ring.clone(), freq_l.clone(), freq_r.clone()) } else { None };
+    let score_synth = Arc::new(std::sync::Mutex::new(ScoreSynth::new(AUDIO_SAMPLE_RATE, AUDIO_VOLUME)));
+    let mut muted = opts.start_muted;
+    let mut _audio = if !muted {
+        setup_audio(audio_ring.clone(), score_synth.clone(), freq_l.clone(), freq_r.clone(), &audio_device)
+    } else { None };
+    // Device the stream was last opened on (or tried), to notice changes
+    let mut audio_device_name = _audio.as_ref().map(|a| a.device.clone());
+    let mut last_audio_check = Instant::now();
     let mut pcm_buf: Vec<f32> = Vec::with_capacity(16384);
 
-    let mut gilrs = init_gamepad(debug);
+    let mut gilrs = init_gamepad(&mut gamepad, debug);
     let mut gp = GamepadState::new();
     let mut frame_count: u64 = 0;
     let start_time = Instant::now();
     let mut last_fps_time = Instant::now();
     let mut fps_frames: u64 = 0;
     let mut scaled_buf = vec![0u32; scaled_w * scaled_h];
-    let mut prev_m = false;
-    let mut prev_s = false;
-    let mut prev_d = false;
-    let mut prev_f = false;
-    let mut prev_g = false;
-    let mut prev_r = false;
-    let mut prev_f11 = false;
+    let mut was_focused = true;
+    let mut show_inputs = opts.input_overlay;
+    let mut show_sram = false;
+    let mut gif_inputs = false;
     let mut fullscreen = false;
-    let mut fps_unlimited = false;
+    let mut fps_unlimited = pacer.policy() == OverrunPolicy::FreeRun;
     let mut screenshot_n = 0u32;
-    let mut prev_num = [false; 6];
+    let mut bug_report_n = 0u32;
+    serial.capture = Some(CaptureRecorder::new(BUG_REPORT_FRAMES, BUG_REPORT_SERIAL));
+    arduboy.trace_tail.enabled = true;
 
     // GIF recording state
     let mut gif_encoder: Option<arduboy_core::gif::GifEncoder> = None;
+    let mut gif_dims = (0usize, 0usize);
     let mut gif_file_n = 0u32;
 
     // EEPROM auto-save timer
@@ -763,113 +2347,122 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
         .parent().unwrap_or(std::path::Path::new("."))
         .to_string_lossy().into_owned();
     let mut game_list = scan_game_dir(&game_dir);
-    let mut game_index = find_game_index(&game_list, &cur_hex_path).unwrap_or(0);
-    let mut prev_n = false;
-    let mut prev_p = false;
-    let mut prev_o = false;
-    let mut prev_b = false;
-    let mut blur_enabled = !no_blur;
+    let mut game_index = find_game_index(&game_list, &cur_hex_path);
+    let mut blur_enabled = !opts.no_blur;
     let mut blur_buf = vec![0u32; scaled_w * scaled_h];
-    let mut prev_l = false;
-    let mut lcd_effect = lcd_start;
-    let mut prev_t = false;
-    let mut prev_a = false;
-    let mut prev_v = false;
-    let mut portrait = false;
+    let mut lcd_effect = opts.lcd_start;
+    // Rotation the window was opened for
+    let mut shown_rotation = arduboy.rotation;
     let mut rot_buf: Vec<u32> = Vec::new();
-    // Temporal blend buffer for PCD8544 ghosting (128×64 float RGB)
-    let mut prev_frame: Vec<(f32, f32, f32)> = vec![(0.0, 0.0, 0.0); SCREEN_WIDTH * SCREEN_HEIGHT];
+    // Temporal blend buffer for PCD8544 ghosting (screen-sized float RGB)
+    let mut prev_frame: Vec<(f32, f32, f32)> = vec![(0.0, 0.0, 0.0); fw * fh];
+    // Previous game frame for --frame-blend
+    let mut last_raw: Vec<u32> = Vec::new();
 
-    // Rewind buffer: snapshot every 30 frames (~0.5s), up to 600 slots (~5 min)
-    let mut rewind = arduboy_core::snapshot::RewindBuffer::new(600, 30);
+    // Rewind buffer: snapshot every 6 frames (0.1s), up to 3000 slots (~5 min)
+    let mut rewind = arduboy_core::snapshot::RewindBuffer::new(3000, 6);
     let mut prev_backspace = false;
+    // Game frames owed to rewind (1× with Backspace, 4× with Shift)
+    let mut rewind_acc = 0u32;
+    let mut timeline: Option<timeline::Timeline> = None;
 
     // Save state path
     let mut state_path = arduboy_core::savestate::state_path(&cur_hex_path);
     // Notification message (shown in title bar temporarily)
     let mut notify_msg: Option<String> = None;
     let mut notify_until = Instant::now();
-    let mut prev_f5 = false;
-    let mut prev_f9 = false;
+    let mut exit_code: Option<i32> = None;
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        if let Some(ref mut g) = gilrs { poll_gamepad(g, &mut gp, debug); }
+        if let Some(ref mut g) = gilrs { poll_gamepad(g, &mut gp, &mut gamepad, debug); }
+
+        // Focus loss: release held buttons; unless the policy is "run",
+        // leave the gamepad to other applications and go quiet
+        let focused = window.is_active();
+        let focus_quiet = !focused && focus_policy != FocusPolicy::Run;
+        let focus_paused = !focused && focus_policy == FocusPolicy::Pause;
+        if focused != was_focused {
+            was_focused = focused;
+            gp = GamepadState::new();
+            if let Ok(mut s) = score_synth.lock() { s.paused = focus_quiet; }
+            if focus_quiet {
+                freq_l.store(0.0f32.to_bits(), Ordering::Relaxed);
+                freq_r.store(0.0f32.to_bits(), Ordering::Relaxed);
+                if let Ok(mut ring) = audio_ring.lock() { ring.clear(); }
+            }
+            if debug { eprintln!("Focus {}", if focused { "gained" } else { "lost" }); }
+        }
+        if focus_quiet { gp = GamepadState::new(); }
+
+        actions.update(|name| match name {
+            "Shift" => window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift),
+            n => match n.strip_prefix("Pad.") {
+                Some(b) => gp.is_held(b),
+                None => minifb_key(n).is_some_and(|k| window.is_key_down(k)),
+            },
+        });
 
         // Scale toggle (1-6)
-        let num = [
-            window.is_key_down(Key::Key1), window.is_key_down(Key::Key2),
-            window.is_key_down(Key::Key3), window.is_key_down(Key::Key4),
-            window.is_key_down(Key::Key5), window.is_key_down(Key::Key6),
-        ];
-        for i in 0..6 {
-            if num[i] && !prev_num[i] && !fullscreen {
-                scale = i + 1;
-                scaled_w = SCREEN_WIDTH * scale;
-                scaled_h = SCREEN_HEIGHT * scale;
+        for i in 1..=6 {
+            if actions.pressed(Hotkey::Scale(i)) && !fullscreen {
+                scale = i as usize;
+                scaled_w = fw * scale;
+                scaled_h = fh * scale;
                 scaled_buf.resize(scaled_w * scaled_h, 0);
-                let (ww, wh) = if portrait { (scaled_h, scaled_w) } else { (scaled_w, scaled_h) };
+                let (ww, wh) = arduboy.rotation.size(scaled_w, scaled_h);
                 window = Window::new(
                     &title_base, ww, wh,
                     WindowOptions { scale: Scale::X1, scale_mode: ScaleMode::UpperLeft, resize: true, ..Default::default() },
                 ).expect("window");
-                if fps_unlimited { window.set_target_fps(0); } else { window.set_target_fps(60); }
+                window.set_target_fps(if fps_unlimited { 0 } else { pacer.host_fps() });
             }
         }
-        prev_num = num;
 
         // Fullscreen (F11)
-        let f11 = window.is_key_down(Key::F11);
-        if f11 && !prev_f11 {
+        if actions.pressed(Hotkey::Fullscreen) {
             fullscreen = !fullscreen;
             if fullscreen {
-                scaled_w = SCREEN_WIDTH * 12;
-                scaled_h = SCREEN_HEIGHT * 12;
+                scaled_w = fw * 12;
+                scaled_h = fh * 12;
             } else {
-                scaled_w = SCREEN_WIDTH * scale;
-                scaled_h = SCREEN_HEIGHT * scale;
+                scaled_w = fw * scale;
+                scaled_h = fh * scale;
             }
             scaled_buf.resize(scaled_w * scaled_h, 0);
-            let (ww, wh) = if portrait { (scaled_h, scaled_w) } else { (scaled_w, scaled_h) };
+            let (ww, wh) = arduboy.rotation.size(scaled_w, scaled_h);
             let mut opts = WindowOptions { scale: Scale::X1, scale_mode: ScaleMode::UpperLeft, resize: true, ..Default::default() };
             if fullscreen { opts.borderless = true; }
             window = Window::new(&title_base, ww, wh, opts).expect("window");
-            if fps_unlimited { window.set_target_fps(0); } else { window.set_target_fps(60); }
+            window.set_target_fps(if fps_unlimited { 0 } else { pacer.host_fps() });
         }
-        prev_f11 = f11;
 
         // FPS unlimited toggle (F)
-        let fk = window.is_key_down(Key::F);
-        if fk && !prev_f {
+        if actions.pressed(Hotkey::Unlimited) {
             fps_unlimited = !fps_unlimited;
             if fps_unlimited {
                 window.set_target_fps(0);
                 eprintln!("FPS: unlimited");
             } else {
-                window.set_target_fps(60);
-                eprintln!("FPS: 60");
+                window.set_target_fps(pacer.host_fps());
+                pacer.reset();
+                eprintln!("FPS: {} (display {} Hz)", EMU_FPS, pacer.host_fps());
             }
         }
-        prev_f = fk;
 
         // Blur toggle (B)
-        let bk = window.is_key_down(Key::B);
-        if bk && !prev_b {
+        if actions.pressed(Hotkey::Blur) {
             blur_enabled = !blur_enabled;
             eprintln!("Blur: {}", if blur_enabled { "ON" } else { "OFF" });
         }
-        prev_b = bk;
 
         // LCD effect toggle (L)
-        let lk = window.is_key_down(Key::L);
-        if lk && !prev_l {
+        if actions.pressed(Hotkey::LcdEffect) {
             lcd_effect = !lcd_effect;
             eprintln!("LCD effect: {}", if lcd_effect { "ON" } else { "OFF" });
         }
-        prev_l = lk;
 
         // Profiler toggle (T)
-        let tk = window.is_key_down(Key::T);
-        if tk && !prev_t {
+        if actions.pressed(Hotkey::Profiler) {
             if arduboy.profiler.enabled {
                 arduboy.profiler.stop(arduboy.cpu.tick);
                 eprintln!("{}", arduboy.profiler_report());
@@ -878,57 +2471,95 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 eprintln!("Profiler: started (press T again to stop and report)");
             }
         }
-        prev_t = tk;
 
-        // Mute (M)
-        let m = window.is_key_down(Key::M);
-        if m && !prev_m {
+        // Mute (M), next audio device (Shift+M)
+        if actions.pressed(Hotkey::AudioDevice) {
+            // Default → each device in turn → default
+            let names = audio_device_names();
+            let pos = match audio_device {
+                AudioDevice::Default => None,
+                _ => audio_device_name.as_ref().and_then(|c| names.iter().position(|n| n == c)),
+            };
+            let next = pos.map_or(0, |p| p + 1);
+            audio_device = match names.get(next) {
+                Some(n) => AudioDevice::Name(n.to_lowercase()),
+                None => AudioDevice::Default,
+            };
+            if !muted {
+                _audio = None;
+                _audio = setup_audio(audio_ring.clone(), score_synth.clone(), freq_l.clone(), freq_r.clone(), &audio_device);
+                audio_device_name = _audio.as_ref().map(|a| a.device.clone());
+            }
+            eprintln!("Audio device: {}", match (&audio_device, &audio_device_name) {
+                (AudioDevice::Default, Some(n)) => format!("system default ({})", n),
+                (_, Some(n)) => n.clone(),
+                (_, None) => "none".to_string(),
+            });
+        }
+        if actions.pressed(Hotkey::Mute) {
             muted = !muted;
             if muted {
                 freq_l.store(0.0f32.to_bits(), Ordering::Relaxed);
                 freq_r.store(0.0f32.to_bits(), Ordering::Relaxed);
                 _audio = None;
             } else {
-                _audio = setup_audio(audio_ring.clone(), freq_l.clone(), freq_r.clone());
+                _audio = setup_audio(audio_ring.clone(), score_synth.clone(), freq_l.clone(), freq_r.clone(), &audio_device);
+                audio_device_name = _audio.as_ref().map(|a| a.device.clone());
+            }
+        }
+
+        // Reopen the stream when the device to play on changes: a new system
+        // default (headphones plugged in), or the chosen device unplugged or back
+        if !muted && last_audio_check.elapsed() >= AUDIO_DEVICE_POLL {
+            last_audio_check = Instant::now();
+            let want = audio_device.resolve().map(|d| d.name().unwrap_or_else(|_| "(unnamed)".into()));
+            if want.is_some() && want != audio_device_name {
+                _audio = None;
+                _audio = setup_audio(audio_ring.clone(), score_synth.clone(), freq_l.clone(), freq_r.clone(), &audio_device);
+                if let Some(a) = &_audio {
+                    eprintln!("Audio device: switched to {}", a.device);
+                }
+                audio_device_name = want;
             }
         }
-        prev_m = m;
 
-        // Audio filter toggle (A)
-        let ak = window.is_key_down(Key::A);
-        if ak && !prev_a {
+        // Audio filter toggle (A), next speaker model (Shift+A)
+        if actions.pressed(Hotkey::Speaker) {
+            let model = arduboy.audio_buf.speaker().next();
+            arduboy.audio_buf.set_speaker(model);
+            notify_msg = Some(format!("Speaker: {}", model.name()));
+            notify_until = Instant::now() + Duration::from_secs(2);
+        }
+        if actions.pressed(Hotkey::AudioFilter) {
             arduboy.audio_buf.toggle_filters();
             eprintln!("Audio filter: {}", if arduboy.audio_buf.filters_enabled { "ON" } else { "OFF" });
         }
-        prev_a = ak;
 
-        // Portrait rotation toggle (V)
-        let vk = window.is_key_down(Key::V);
-        if vk && !prev_v {
-            portrait = !portrait;
-            eprintln!("Portrait: {}", if portrait { "ON" } else { "OFF" });
-            let (ww, wh) = if portrait { (scaled_h, scaled_w) } else { (scaled_w, scaled_h) };
+        // Screen rotation (V), or a game switch to one with other metadata
+        if actions.pressed(Hotkey::Rotate) {
+            arduboy.rotation = arduboy.rotation.next();
+            eprintln!("Rotation: {}°", arduboy.rotation.degrees());
+        }
+        if arduboy.rotation != shown_rotation {
+            shown_rotation = arduboy.rotation;
+            let (ww, wh) = arduboy.rotation.size(scaled_w, scaled_h);
             let opts = WindowOptions { scale: Scale::X1, scale_mode: ScaleMode::UpperLeft, resize: true, ..Default::default() };
             window = Window::new(&title_base, ww, wh, opts).expect("window");
-            if fps_unlimited { window.set_target_fps(0); } else { window.set_target_fps(60); }
+            window.set_target_fps(if fps_unlimited { 0 } else { pacer.host_fps() });
         }
-        prev_v = vk;
 
         // Screenshot (S) — PNG at current scale
-        let s = window.is_key_down(Key::S);
-        if s && !prev_s {
-            let cur_s = scaled_w / SCREEN_WIDTH;
+        if actions.pressed(Hotkey::Screenshot) {
+            let cur_s = scaled_w / fw;
             let f = format!("screenshot_{:04}_{}x.png", screenshot_n, cur_s);
             match save_screenshot_png(arduboy, &f, cur_s) {
                 Ok(()) => { eprintln!("Screenshot: {} ({}x)", f, cur_s); screenshot_n += 1; }
                 Err(e) => eprintln!("Screenshot error: {}", e),
             }
         }
-        prev_s = s;
 
         // GIF recording toggle (G)
-        let gk = window.is_key_down(Key::G);
-        if gk && !prev_g {
+        if actions.pressed(Hotkey::GifRecord) {
             if let Some(encoder) = gif_encoder.take() {
                 // Stop recording
                 let frames = encoder.frame_count();
@@ -942,25 +2573,83 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 gif_file_n += 1;
             } else {
                 // Start recording
+                // With the input viewer on, a strip below the screen shows it
+                gif_inputs = show_inputs;
+                let strip = if gif_inputs { arduboy_core::input_overlay::OVERLAY_H } else { 0 };
+                let (gw, gh) = arduboy.rotation.size(fw, fh);
                 gif_encoder = Some(arduboy_core::gif::GifEncoder::new(
-                    SCREEN_WIDTH as u16, SCREEN_HEIGHT as u16, 2));
+                    gw as u16, (gh + strip) as u16, 2));
+                gif_dims = (gw, gh);
                 eprintln!("GIF recording started (press G to stop)");
             }
         }
-        prev_g = gk;
+
+        // Input viewer toggle (I)
+        if actions.pressed(Hotkey::InputDisplay) {
+            show_inputs = !show_inputs;
+            notify_msg = Some(format!("Input display: {}", if show_inputs { "ON" } else { "OFF" }));
+            notify_until = Instant::now() + Duration::from_secs(2);
+        }
+
+        // SRAM map toggle (H): data/bss/heap/stack bar along the top
+        if actions.pressed(Hotkey::SramMap) {
+            show_sram = !show_sram;
+            let map = arduboy.sram_map(elf);
+            if show_sram { eprint!("{}", map.format(64)); }
+            notify_msg = Some(format!("SRAM map: {} ({} B free)", if show_sram { "ON" } else { "OFF" }, map.free()));
+            notify_until = Instant::now() + Duration::from_secs(2);
+        }
+
+        // Rewind timeline (Tab): scrub with Left/Right, Enter to resume there
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        let tab = actions.pressed(Hotkey::Timeline);
+        let (tl_left, tl_right) = (actions.pressed(Hotkey::TimelineBack), actions.pressed(Hotkey::TimelineForward));
+        if let Some(tl) = timeline.as_mut() {
+            let step = if shift { 10 } else { 1 };
+            if tl_left {
+                arduboy.restore_snapshot(&tl.step(&rewind, -step));
+            }
+            if tl_right {
+                arduboy.restore_snapshot(&tl.step(&rewind, step));
+            }
+            if tl_left || tl_right {
+                notify_msg = Some(format!("Timeline: -{:.1}s", tl.seconds_back(&rewind)));
+                notify_until = Instant::now() + Duration::from_secs(2);
+            }
+            if actions.pressed(Hotkey::TimelineResume) {
+                if tl.cursor < rewind.len() {
+                    rewind.truncate(tl.cursor);
+                    eprintln!("Rewind: resumed {:.1}s back, {} snapshots remaining",
+                        tl.seconds_back(&rewind), rewind.len());
+                }
+                timeline = None;
+            } else if tab {
+                arduboy.restore_snapshot(tl.resume());
+                timeline = None;
+            }
+        } else if tab {
+            if rewind.is_empty() {
+                notify_msg = Some("Timeline: no snapshots yet".to_string());
+                notify_until = Instant::now() + Duration::from_secs(2);
+            } else {
+                timeline = Some(timeline::Timeline::open(&rewind, arduboy.save_snapshot()));
+            }
+        }
 
         // Reload (R)
-        let rk = window.is_key_down(Key::R);
-        if rk && !prev_r {
-            // Save EEPROM before reload
+        if actions.pressed(Hotkey::Reload) {
+            // Save EEPROM and FX save before reload
             if !no_save && arduboy.eeprom_dirty {
                 save_eeprom(arduboy, &eep_path, debug);
             }
+            if !no_save {
+                save_fx(arduboy, &eep_path, debug);
+            }
             // Reload the game file
             match load_game_file(&cur_hex_path, None, debug) {
                 Ok(game) => {
                     arduboy.reset();
-                    if let Err(e) = arduboy.load_hex(&game.hex_str) {
+                    if let Err(e) = load_program(arduboy, &game, debug) {
                         eprintln!("Reload error: {}", e);
                     } else {
                         load_game_fx(arduboy, &game, debug);
@@ -972,78 +2661,83 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 Err(e) => eprintln!("Reload error: {}", e),
             }
         }
-        prev_r = rk;
 
         // File browser: O = list games, N = next, P = previous
-        let ok = window.is_key_down(Key::O);
-        if ok && !prev_o {
+        if actions.pressed(Hotkey::ListGames) {
             // Rescan directory and print game list
             game_list = scan_game_dir(&game_dir);
-            game_index = find_game_index(&game_list, &cur_hex_path).unwrap_or(0);
+            game_index = find_game_index(&game_list, &cur_hex_path);
             eprintln!("--- Games in {} ({} found) ---", game_dir, game_list.len());
             for (i, g) in game_list.iter().enumerate() {
-                let marker = if i == game_index { " <<" } else { "" };
+                let marker = if game_index == Some(i) { " <<" } else { "" };
                 let name = std::path::Path::new(g).file_name()
                     .and_then(|s| s.to_str()).unwrap_or(g);
                 eprintln!("  {:3}. {}{}", i + 1, name, marker);
             }
             eprintln!("---");
         }
-        prev_o = ok;
 
-        let nk = window.is_key_down(Key::N);
-        if nk && !prev_n && !game_list.is_empty() {
-            let next_idx = (game_index + 1) % game_list.len();
+        if actions.pressed(Hotkey::NextGame) && !game_list.is_empty() {
+            let next_idx = game_index.map_or(0, |i| (i + 1) % game_list.len());
             let path = game_list[next_idx].clone();
             match switch_game(arduboy, &path, &eep_path, no_save, debug) {
                 Ok((hp, title, ep)) => {
+                    // Score symbols and SRAM sections came from the previous game's ELF
+                    if score_tracker.take().is_some() {
+                        if let Ok(mut s) = score_synth.lock() { s.stop(); }
+                    }
+                    elf = None;
                     cur_hex_path = hp; eep_path = ep;
                     state_path = arduboy_core::savestate::state_path(&cur_hex_path);
                     title_base = make_title(&title);
-                    game_index = next_idx;
+                    game_index = Some(next_idx);
                     frame_count = 0;
                     window.set_title(&title_base);
                     let name = std::path::Path::new(&path).file_name()
                         .and_then(|s| s.to_str()).unwrap_or(&path);
-                    eprintln!("Loaded [{}/{}]: {}", game_index + 1, game_list.len(), name);
+                    eprintln!("Loaded [{}/{}]: {}", next_idx + 1, game_list.len(), name);
                 }
                 Err(e) => eprintln!("Load error: {}", e),
             }
         }
-        prev_n = nk;
 
-        let pk = window.is_key_down(Key::P);
-        if pk && !prev_p && !game_list.is_empty() {
-            let prev_idx = if game_index == 0 { game_list.len() - 1 } else { game_index - 1 };
+        if actions.pressed(Hotkey::PrevGame) && !game_list.is_empty() {
+            let prev_idx = match game_index {
+                Some(i) if i > 0 => i - 1,
+                _ => game_list.len() - 1,
+            };
             let path = game_list[prev_idx].clone();
             match switch_game(arduboy, &path, &eep_path, no_save, debug) {
                 Ok((hp, title, ep)) => {
+                    // Score symbols and SRAM sections came from the previous game's ELF
+                    if score_tracker.take().is_some() {
+                        if let Ok(mut s) = score_synth.lock() { s.stop(); }
+                    }
+                    elf = None;
                     cur_hex_path = hp; eep_path = ep;
                     state_path = arduboy_core::savestate::state_path(&cur_hex_path);
                     title_base = make_title(&title);
-                    game_index = prev_idx;
+                    game_index = Some(prev_idx);
                     frame_count = 0;
                     window.set_title(&title_base);
                     let name = std::path::Path::new(&path).file_name()
                         .and_then(|s| s.to_str()).unwrap_or(&path);
-                    eprintln!("Loaded [{}/{}]: {}", game_index + 1, game_list.len(), name);
+                    eprintln!("Loaded [{}/{}]: {}", prev_idx + 1, game_list.len(), name);
                 }
                 Err(e) => eprintln!("Load error: {}", e),
             }
         }
-        prev_p = pk;
 
         // Reg dump (D)
-        let d = window.is_key_down(Key::D);
-        if d && !prev_d {
-            eprintln!("--- Regs (frame {}) ---\n{}\nNext: {}\n---",
-                frame_count, arduboy.dump_regs(), arduboy.disasm_at_pc());
+        if actions.pressed(Hotkey::DumpRegs) {
+            let (flash_sum, sram_sum, eeprom_sum) = arduboy.checksums();
+            eprintln!("--- Regs (frame {}) ---\n{}\nNext: {}\nChecksums: flash={:016x} sram={:016x} eeprom={:016x}\n---",
+                frame_count, arduboy.dump_regs(), arduboy.disasm_at_pc(),
+                flash_sum, sram_sum, eeprom_sum);
         }
-        prev_d = d;
 
         // Quick Save (F5)
-        let f5 = window.is_key_down(Key::F5);
-        if f5 && !prev_f5 {
+        if actions.pressed(Hotkey::QuickSave) {
             let state = arduboy.save_full_state();
             let cpu_byte = arduboy.cpu_type_byte();
             match arduboy_core::savestate::save_to_file(
@@ -1062,11 +2756,9 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 }
             }
         }
-        prev_f5 = f5;
 
         // Quick Load (F9)
-        let f9 = window.is_key_down(Key::F9);
-        if f9 && !prev_f9 {
+        if actions.pressed(Hotkey::QuickLoad) {
             let cpu_byte = arduboy.cpu_type_byte();
             match arduboy_core::savestate::load_from_file(
                 std::path::Path::new(&state_path), cpu_byte
@@ -1085,7 +2777,48 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 }
             }
         }
-        prev_f9 = f9;
+
+        // Bug report capture (F12)
+        if actions.pressed(Hotkey::BugReport) {
+            if let Some(ref capture) = serial.capture {
+                let config = [
+                    ("game", cur_hex_path.clone()),
+                    ("args", std::env::args().skip(1).collect::<Vec<_>>().join(" ")),
+                    ("scale", scale.to_string()),
+                    ("muted", muted.to_string()),
+                    ("lcd_effect", lcd_effect.to_string()),
+                    ("blur", blur_enabled.to_string()),
+                    ("fps_unlimited", fps_unlimited.to_string()),
+                ];
+                let fname = format!("bugreport_{:04}.zip", bug_report_n);
+                match capture.bundle(arduboy, &config)
+                    .and_then(|zip| fs::write(&fname, &zip).map(|_| zip.len()).map_err(|e| format!("{}: {}", fname, e)))
+                {
+                    Ok(size) => {
+                        eprintln!("Bug report: {} ({} frames, {} bytes)", fname, capture.frame_count(), size);
+                        notify_msg = Some("Bug report saved".to_string());
+                        notify_until = Instant::now() + Duration::from_secs(2);
+                        bug_report_n += 1;
+                    }
+                    Err(e) => {
+                        eprintln!("Bug report error: {}", e);
+                        notify_msg = Some(format!("Bug report error: {}", e));
+                        notify_until = Instant::now() + Duration::from_secs(3);
+                    }
+                }
+            }
+        }
+
+        // Emulated frames due for this presented frame: always 1 in lockstep
+        // or unlimited mode, 0..n when the host refresh rate differs
+        let due = if fps_unlimited {
+            1
+        } else if focus_paused || timeline.is_some() {
+            pacer.reset();
+            0
+        } else {
+            pacer.advance(Instant::now())
+        };
 
         // Input
         arduboy.set_button(Button::Up,    window.is_key_down(Key::Up)    || gp.eff_up());
@@ -1095,102 +2828,188 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
         arduboy.set_button(Button::A,     window.is_key_down(Key::Z)     || gp.a);
         arduboy.set_button(Button::B,     window.is_key_down(Key::X)     || gp.b);
 
-        // Rewind (Backspace) — restore previous snapshot instead of running
-        let bksp = window.is_key_down(Key::Backspace);
+        // Rewind (Backspace, Shift = 4×) — restore earlier snapshots instead
+        // of running, at a multiple of game speed
+        let bksp = actions.held(Hotkey::Rewind) && timeline.is_none();
         if bksp {
-            if let Some(snap) = rewind.pop() {
-                arduboy.restore_snapshot(&snap);
-                if !prev_backspace {
+            if !prev_backspace {
+                // First snapshot goes back immediately
+                rewind_acc = rewind.interval;
+                if rewind.is_empty() {
+                    eprintln!("Rewind: no more snapshots");
+                } else {
                     eprintln!("Rewind: {} snapshots remaining", rewind.len());
                 }
-                prev_backspace = true;
-                // Skip normal frame execution when rewinding
-                // Still render below
-            } else {
-                if !prev_backspace {
-                    eprintln!("Rewind: no more snapshots");
+            }
+            prev_backspace = true;
+            rewind_acc += due * if shift { 4 } else { 1 };
+            let mut snap = None;
+            while rewind_acc >= rewind.interval.max(1) {
+                rewind_acc -= rewind.interval.max(1);
+                match rewind.pop() {
+                    Some(s) => snap = Some(s),
+                    None => break,
                 }
-                prev_backspace = true;
             }
+            // Skip normal frame execution when rewinding; still render below
+            if let Some(snap) = snap {
+                arduboy.restore_snapshot(&snap);
+            }
+        } else if focus_paused || timeline.is_some() {
+            prev_backspace = false;
         } else {
             prev_backspace = false;
 
-            arduboy.run_frame();
-            frame_count += 1;
-            fps_frames += 1;
-
-            // Diagnostic output for first few frames when debugging
-            if debug && (frame_count == 1 || frame_count == 60 || frame_count == 120) {
-                let fb = arduboy.framebuffer_rgba();
-                let fb_nonzero = fb.chunks(4).any(|px| px[0] > 0 || px[1] > 0 || px[2] > 0);
-                let display_cmds = arduboy.display.dbg_cmd_count;
-                let display_data = arduboy.display.dbg_data_count;
-                eprintln!("[Frame {}] display_type={:?}, SPI_writes={}, FX_transfers={}, display_cmds={}, display_data={}, fb_has_content={}, PC=0x{:04X}",
-                    frame_count, arduboy.display_type,
-                    arduboy.dbg_spdr_writes, arduboy.dbg_fx_transfers,
-                    display_cmds, display_data, fb_nonzero,
-                    arduboy.cpu.pc);
-                if frame_count == 1 {
-                    eprintln!("  DDRD=0x{:02X} PORTD=0x{:02X} FX_loaded={}",
+            if frame_blend && due > 0 {
+                last_raw = screen_pixels(arduboy).0;
+            }
+            let box_end = time_box.map(|us| Instant::now() + Duration::from_micros(us));
+            for i in 0..due {
+                if i > 0 && !muted && arduboy.audio_buf.needs_render() {
+                    // Queue the previous frame's samples before the next one starts
+                    push_frame_audio(arduboy, &mut pcm_buf, &audio_ring);
+                }
+                if let Some(end) = box_end {
+                    let left = end.saturating_duration_since(Instant::now()).as_micros() as u64;
+                    if arduboy.run_frame_budgeted(left).is_some() {
+                        // Out of host time: the frame goes on next time round
+                        break;
+                    }
+                } else {
+                    arduboy.run_frame();
+                }
+                if arduboy.fast_boot.active() {
+                    fast_forward_boot(arduboy);
+                }
+                print_core_messages(arduboy);
+                plugins.on_frame(arduboy);
+                if let Some(a) = autosplit.as_deref_mut() {
+                    a.update(arduboy);
+                }
+                frame_count += 1;
+                fps_frames += 1;
+
+                // Diagnostic output for first few frames when debugging
+                if debug && (frame_count == 1 || frame_count == 60 || frame_count == 120) {
+                    let fb = arduboy.framebuffer_rgba();
+                    let fb_nonzero = fb.chunks(4).any(|px| px[0] > 0 || px[1] > 0 || px[2] > 0);
+                    let display_cmds = arduboy.display.dbg_cmd_count;
+                    let display_data = arduboy.display.dbg_data_count;
+                    eprintln!("[Frame {}] display_type={:?}, SPI_writes={}, FX_transfers={}, display_cmds={}, display_data={}, fb_has_content={}, PC=0x{:04X}",
+                        frame_count, arduboy.display_type,
+                        arduboy.dbg_spdr_writes, arduboy.dbg_fx_transfers,
+                        display_cmds, display_data, fb_nonzero,
+                        arduboy.cpu.pc);
+                    if frame_count == 1 {
+                        eprintln!("  DDRD=0x{:02X} PORTD=0x{:02X} FX_loaded={}",
+                            arduboy.mem.data[0x2A], arduboy.mem.data[0x2B],
+                            arduboy.fx_flash.loaded);
+                    }
+                }
+                // Always print FX diagnostics at frame 1 (helps debug FX games)
+                if frame_count == 1 && arduboy.fx_flash.loaded {
+                    let fb = arduboy.framebuffer_rgba();
+                    let fb_nonzero = fb.chunks(4).any(|px| px[0] > 0 || px[1] > 0 || px[2] > 0);
+                    eprintln!("[FX diag] frame=1 DDRD=0x{:02X} PORTD=0x{:02X} SPI={} FX={} display={:?} cmds={} data={} fb={}",
                         arduboy.mem.data[0x2A], arduboy.mem.data[0x2B],
-                        arduboy.fx_flash.loaded);
+                        arduboy.dbg_spdr_writes, arduboy.dbg_fx_transfers,
+                        arduboy.display_type,
+                        arduboy.display.dbg_cmd_count, arduboy.display.dbg_data_count,
+                        if fb_nonzero { "content" } else { "EMPTY" });
                 }
-            }
-            // Always print FX diagnostics at frame 1 (helps debug FX games)
-            if frame_count == 1 && arduboy.fx_flash.loaded {
-                let fb = arduboy.framebuffer_rgba();
-                let fb_nonzero = fb.chunks(4).any(|px| px[0] > 0 || px[1] > 0 || px[2] > 0);
-                eprintln!("[FX diag] frame=1 DDRD=0x{:02X} PORTD=0x{:02X} SPI={} FX={} display={:?} cmds={} data={} fb={}",
-                    arduboy.mem.data[0x2A], arduboy.mem.data[0x2B],
-                    arduboy.dbg_spdr_writes, arduboy.dbg_fx_transfers,
-                    arduboy.display_type,
-                    arduboy.display.dbg_cmd_count, arduboy.display.dbg_data_count,
-                    if fb_nonzero { "content" } else { "EMPTY" });
-            }
 
-            // Save rewind snapshot at interval
-            if rewind.tick_frame() {
-                rewind.push(arduboy.save_snapshot());
+                // Save rewind snapshot at interval
+                if rewind.tick_frame() {
+                    rewind.push(arduboy.save_snapshot());
+                }
+                if arduboy.breakpoint_hit { break; }
             }
         }
 
         if !bksp && arduboy.breakpoint_hit {
+            check_watch_hit(arduboy);
             eprintln!("*** Breakpoint: {} ***\n{}", arduboy.disasm_at_pc(), arduboy.dump_regs());
             arduboy.breakpoint_hit = false;
         }
 
-        if serial_enabled {
-            let out = arduboy.take_serial_output();
-            if !out.is_empty() {
-                let _ = std::io::stderr().write_all(&out);
-                let _ = std::io::stderr().flush();
+        for cmd in serial.service(arduboy) {
+            match cmd {
+                RpcCommand::Quit(code) => {
+                    eprintln!("Serial RPC: quit ({})", code);
+                    exit_code = Some(code);
+                }
+                RpcCommand::Screenshot(name) => {
+                    let cur_s = scaled_w / fw;
+                    let f = name.unwrap_or_else(|| {
+                        screenshot_n += 1;
+                        format!("screenshot_{:04}_{}x.png", screenshot_n - 1, cur_s)
+                    });
+                    match save_screenshot_png(arduboy, &f, cur_s) {
+                        Ok(()) => eprintln!("Screenshot: {} ({}x)", f, cur_s),
+                        Err(e) => eprintln!("Screenshot error: {}", e),
+                    }
+                }
+                RpcCommand::SetTitle(t) => {
+                    title_base = make_title(&t);
+                    window.set_title(&title_base);
+                }
+                RpcCommand::Checkpoint(name) => {
+                    eprintln!("Checkpoint: {} (frame {})", name, frame_count);
+                }
             }
         }
+        if exit_code.is_some() { break; }
+
+        if let Some(capture) = serial.capture.as_mut().filter(|_| !focus_paused && due > 0) {
+            capture.record_frame(arduboy);
+        }
 
         // GIF recording: capture frame
-        if let Some(ref mut enc) = gif_encoder {
-            let fb = arduboy.framebuffer_rgba();
-            let mono: Vec<bool> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
-                .map(|i| fb[i * 4] > 128)
-                .collect();
-            enc.add_frame_mono(&mono);
+        if let Some(enc) = gif_encoder.as_mut().filter(|_| !focus_paused && due > 0) {
+            let (px, w, h) = output_pixels(arduboy);
+            if (w, h) == gif_dims {
+                let mut px = px;
+                if gif_inputs {
+                    use arduboy_core::input_overlay::{self, OverlayPalette, OVERLAY_H};
+                    px.resize(w * (h + OVERLAY_H), 0);
+                    input_overlay::render(&arduboy.input_history, &mut px, w, h + OVERLAY_H,
+                        (0, h), 1, OverlayPalette::MONO);
+                }
+                let mono: Vec<bool> = px.iter().map(|&c| (c >> 16) & 0xFF > 128).collect();
+                enc.add_frame_mono(&mono);
+            }
         }
 
-        if !muted {
-            let (lh, rh) = arduboy.get_audio_tone();
-            if arduboy.audio_buf.needs_render() {
-                arduboy.audio_buf.render_samples(
-                    &mut pcm_buf,
-                    AUDIO_SAMPLE_RATE,
-                    arduboy_core::CLOCK_HZ,
-                    AUDIO_VOLUME,
-                );
-                if let Ok(mut ring) = audio_ring.lock() {
-                    let max_buf = AUDIO_SAMPLE_RATE as usize / 5;
-                    if ring.len() < max_buf {
-                        ring.extend(pcm_buf.iter());
+        // Score audio: follow the game's music player and synthesize it
+        // directly, so fast-forward and frame skips don't garble the music
+        if let Some(ref mut tracker) = score_tracker {
+            if let Some(ev) = tracker.poll(arduboy) {
+                if let Ok(mut s) = score_synth.lock() {
+                    match ev {
+                        ScoreEvent::Start(score) => s.start(score),
+                        ScoreEvent::Stop => s.stop(),
                     }
                 }
+            }
+        }
+        let score_playing = score_synth.lock().map(|s| s.is_active()).unwrap_or(false);
+        let mut av_synced = false;
+
+        if focus_quiet {
+            // Silenced on focus loss (see above)
+        } else if !muted && score_playing {
+            freq_l.store(0.0f32.to_bits(), Ordering::Relaxed);
+            freq_r.store(0.0f32.to_bits(), Ordering::Relaxed);
+        } else if !muted {
+            let (lh, rh) = arduboy.get_audio_tone();
+            if due == 0 {
+                // Nothing new emulated; the last frame's samples are queued
+            } else if arduboy.audio_buf.needs_render() {
+                push_frame_audio(arduboy, &mut pcm_buf, &audio_ring);
+                // Queued audio is how far the sound trails this frame
+                let queued = audio_ring.lock().map(|r| r.len() / 2).unwrap_or(0);
+                arduboy.frame_cycles = av_sync.observe(queued, AUDIO_SAMPLE_RATE);
+                av_synced = true;
                 freq_l.store(0.0f32.to_bits(), Ordering::Relaxed);
                 freq_r.store(0.0f32.to_bits(), Ordering::Relaxed);
             } else {
@@ -1198,27 +3017,40 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 freq_r.store(rh.to_bits(), Ordering::Relaxed);
             }
         }
+        if due > 0 && !av_synced {
+            // No sample-accurate audio to line up with: back to nominal speed
+            arduboy.frame_cycles = av_sync.idle();
+        }
 
-        // EEPROM auto-save (every 10 seconds if dirty)
-        if !no_save && arduboy.eeprom_dirty && last_eeprom_save.elapsed() >= Duration::from_secs(10) {
-            save_eeprom(arduboy, &eep_path, debug);
-            arduboy.eeprom_dirty = false;
+        // EEPROM and FX save auto-save (every 10 seconds if dirty)
+        if !no_save && last_eeprom_save.elapsed() >= Duration::from_secs(10) {
+            if arduboy.eeprom_dirty {
+                save_eeprom(arduboy, &eep_path, debug);
+                arduboy.eeprom_dirty = false;
+            }
+            save_fx(arduboy, &eep_path, debug);
             last_eeprom_save = Instant::now();
         }
 
+        // Follow panel geometry changes (display type switch, game switch)
+        if screen_dims(arduboy) != (fw, fh) {
+            (fw, fh) = screen_dims(arduboy);
+            prev_frame = vec![(0.0, 0.0, 0.0); fw * fh];
+            let s = if fullscreen { 12 } else { scale };
+            scaled_w = fw * s;
+            scaled_h = fh * s;
+            scaled_buf.resize(scaled_w * scaled_h, 0);
+        }
+
         // Adapt buffer to window resize (maintain aspect ratio)
         if !fullscreen {
             let (win_w, win_h) = window.get_size();
-            let (base_w, base_h) = if portrait {
-                (SCREEN_HEIGHT, SCREEN_WIDTH)
-            } else {
-                (SCREEN_WIDTH, SCREEN_HEIGHT)
-            };
+            let (base_w, base_h) = arduboy.rotation.size(fw, fh);
             let fit_scale_w = win_w / base_w;
             let fit_scale_h = win_h / base_h;
             let fit_scale = fit_scale_w.min(fit_scale_h).max(1).min(12);
-            let new_w = SCREEN_WIDTH * fit_scale;
-            let new_h = SCREEN_HEIGHT * fit_scale;
+            let new_w = fw * fit_scale;
+            let new_h = fh * fit_scale;
             if new_w != scaled_w || new_h != scaled_h {
                 scale = fit_scale.min(6).max(1);
                 scaled_w = new_w;
@@ -1228,136 +3060,21 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
         }
 
         // ── Render pipeline ──────────────────────────────────────────────
-        let raw_pixels = arduboy.framebuffer_u32();
-        let cur_scale = scaled_w / SCREEN_WIDTH;
+        let (mut raw_pixels, _, _) = screen_pixels(arduboy);
+        if frame_blend && !fps_unlimited && !pacer.lockstep() && last_raw.len() == raw_pixels.len() {
+            raw_pixels = pacing::blend_frames(&last_raw, &raw_pixels, pacer.phase());
+        }
+        let cur_scale = scaled_w / fw;
         let is_pcd = matches!(arduboy.display_type, DisplayType::Pcd8544);
 
         // (1) Color palette + (3) Temporal blend → lcd_pixels 128×64
         if lcd_effect {
-            // SSD1306 OLED palette: ON → blue-white, OFF → near-black
-            // PCD8544 LCD palette:  ON → dark gray-green, OFF → yellow-green
-            let (col_on, col_off): ((f32,f32,f32), (f32,f32,f32)) = if is_pcd {
-                ((0x3C as f32, 0x48 as f32, 0x28 as f32),
-                 (0xC0 as f32, 0xD8 as f32, 0x78 as f32))
-            } else {
-                ((0xA0 as f32, 0xD0 as f32, 0xFF as f32),
-                 (0x05 as f32, 0x05 as f32, 0x08 as f32))
-            };
-            // Temporal blend factor: PCD8544 20% previous, SSD1306 5%
-            let ghost = if is_pcd { 0.20f32 } else { 0.05f32 };
-            let fresh = 1.0 - ghost;
-
-            for i in 0..(SCREEN_WIDTH * SCREEN_HEIGHT) {
-                let raw = raw_pixels[i];
-                // Determine if pixel is "on" (any channel > 0x40)
-                let on = (raw & 0xFFFFFF) > 0x404040;
-                let (tr, tg, tb) = if on { col_on } else { col_off };
-                // Blend with previous frame
-                let (pr, pg, pb) = prev_frame[i];
-                let nr = tr * fresh + pr * ghost;
-                let ng = tg * fresh + pg * ghost;
-                let nb = tb * fresh + pb * ghost;
-                prev_frame[i] = (nr, ng, nb);
-            }
-
-            // Scale up from prev_frame
-            for y in 0..SCREEN_HEIGHT {
-                for x in 0..SCREEN_WIDTH {
-                    let (fr, fg, fb) = prev_frame[y * SCREEN_WIDTH + x];
-                    let c = ((fr as u32) << 16) | ((fg as u32) << 8) | (fb as u32);
-                    for sy in 0..cur_scale {
-                        let base = (y * cur_scale + sy) * scaled_w + x * cur_scale;
-                        for sx in 0..cur_scale {
-                            if base + sx < scaled_buf.len() { scaled_buf[base + sx] = c; }
-                        }
-                    }
-                }
-            }
-
-            // (2) Pixel grid lines + (4) Corner rounding (need scale ≥ 3)
-            if cur_scale >= 3 {
-                // Grid line darkness: darken the last row and column of each pixel cell
-                let grid_dim = if is_pcd { 0.55f32 } else { 0.70f32 };
-                // Corner darkness
-                let corner_dim = if is_pcd { 0.40f32 } else { 0.50f32 };
-
-                for py in 0..SCREEN_HEIGHT {
-                    for px in 0..SCREEN_WIDTH {
-                        let bx = px * cur_scale;
-                        let by = py * cur_scale;
-
-                        for sy in 0..cur_scale {
-                            for sx in 0..cur_scale {
-                                let gx = bx + sx;
-                                let gy = by + sy;
-                                let idx = gy * scaled_w + gx;
-                                if idx >= scaled_buf.len() { continue; }
-
-                                // Is this sub-pixel on a grid edge?
-                                let on_right = sx == cur_scale - 1;
-                                let on_bottom = sy == cur_scale - 1;
-                                // Is this sub-pixel a corner of the pixel block?
-                                let is_inner_corner = (sx == 0 && sy == 0)
-                                    || (sx == 0 && sy == cur_scale - 1)
-                                    || (sx == cur_scale - 1 && sy == 0)
-                                    || (sx == cur_scale - 1 && sy == cur_scale - 1);
-
-                                let dim = if is_inner_corner {
-                                    corner_dim
-                                } else if on_right || on_bottom {
-                                    grid_dim
-                                } else {
-                                    1.0
-                                };
-
-                                if dim < 1.0 {
-                                    let c = scaled_buf[idx];
-                                    let r = (((c >> 16) & 0xFF) as f32 * dim) as u32;
-                                    let g = (((c >> 8) & 0xFF) as f32 * dim) as u32;
-                                    let b = ((c & 0xFF) as f32 * dim) as u32;
-                                    scaled_buf[idx] = (r << 16) | (g << 8) | b;
-                                }
-                            }
-                        }
-                    }
-                }
-            } else if cur_scale == 2 {
-                // At 2× only do subtle grid on right/bottom edge
-                let grid_dim = if is_pcd { 0.70f32 } else { 0.80f32 };
-                for py in 0..SCREEN_HEIGHT {
-                    for px in 0..SCREEN_WIDTH {
-                        let bx = px * 2;
-                        let by = py * 2;
-                        // Right column
-                        for sy in 0..2 {
-                            let idx = (by + sy) * scaled_w + bx + 1;
-                            if idx < scaled_buf.len() {
-                                let c = scaled_buf[idx];
-                                let r = (((c >> 16) & 0xFF) as f32 * grid_dim) as u32;
-                                let g = (((c >> 8) & 0xFF) as f32 * grid_dim) as u32;
-                                let b = ((c & 0xFF) as f32 * grid_dim) as u32;
-                                scaled_buf[idx] = (r << 16) | (g << 8) | b;
-                            }
-                        }
-                        // Bottom row
-                        for sx in 0..2 {
-                            let idx = (by + 1) * scaled_w + bx + sx;
-                            if idx < scaled_buf.len() {
-                                let c = scaled_buf[idx];
-                                let r = (((c >> 16) & 0xFF) as f32 * grid_dim) as u32;
-                                let g = (((c >> 8) & 0xFF) as f32 * grid_dim) as u32;
-                                let b = ((c & 0xFF) as f32 * grid_dim) as u32;
-                                scaled_buf[idx] = (r << 16) | (g << 8) | b;
-                            }
-                        }
-                    }
-                }
-            }
+            render_lcd_effect(&raw_pixels, fw, cur_scale, is_pcd, &mut prev_frame, &mut scaled_buf, scaled_w);
         } else {
             // Normal rendering (no LCD effect)
-            for y in 0..SCREEN_HEIGHT {
-                for x in 0..SCREEN_WIDTH {
-                    let c = raw_pixels[y * SCREEN_WIDTH + x];
+            for y in 0..fh {
+                for x in 0..fw {
+                    let c = raw_pixels[y * fw + x];
                     for sy in 0..cur_scale {
                         let base = (y * cur_scale + sy) * scaled_w + x * cur_scale;
                         for sx in 0..cur_scale {
@@ -1413,20 +3130,33 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
             }
         }
 
-        // Display output (with optional portrait rotation)
-        let final_src = if use_blur { &blur_buf } else { &scaled_buf };
-        if portrait {
-            // Rotate 90° CCW: left side → bottom (portrait orientation)
-            let rw = scaled_h;  // rotated width  = landscape height
-            let rh = scaled_w;  // rotated height = landscape width
-            rot_buf.resize(rw * rh, 0);
-            for y in 0..scaled_h {
-                for x in 0..scaled_w {
-                    let nx = y;
-                    let ny = scaled_w - 1 - x;
-                    rot_buf[ny * rw + nx] = final_src[y * scaled_w + x];
-                }
+        // Input viewer in the bottom-right corner
+        let final_src = if use_blur { &mut blur_buf } else { &mut scaled_buf };
+        if show_inputs {
+            use arduboy_core::input_overlay::{self, OverlayPalette, OVERLAY_W, OVERLAY_H};
+            let unit = (cur_scale / 3).max(1);
+            let (ow, oh) = (OVERLAY_W * unit, OVERLAY_H * unit);
+            if ow + unit <= scaled_w && oh + unit <= scaled_h {
+                input_overlay::render(&arduboy.input_history, final_src, scaled_w, scaled_h,
+                    (scaled_w - ow - unit, scaled_h - oh - unit), unit, OverlayPalette::COLOR);
+            }
+        }
+        if show_sram {
+            let unit = (cur_scale / 3).max(1);
+            if scaled_w > 2 * unit {
+                arduboy_core::sram_map::render(&arduboy.sram_map(elf), final_src, scaled_w, scaled_h,
+                    (unit, unit), (scaled_w - 2 * unit, 2 * unit));
             }
+        }
+        plugins.overlay.render(final_src, scaled_w, scaled_h, cur_scale);
+        if let Some(tl) = timeline.as_ref() {
+            tl.render(&rewind, final_src, scaled_w, scaled_h, (cur_scale / 3).max(1));
+        }
+        let final_src: &[u32] = final_src;
+
+        // Display output (with the game's rotation)
+        if arduboy.rotation != Rotation::None {
+            let (rw, rh) = arduboy.rotation.rotate_into(final_src, scaled_w, scaled_h, &mut rot_buf);
             window.update_with_buffer(&rot_buf, rw, rh).expect("update");
         } else {
             window.update_with_buffer(final_src, scaled_w, scaled_h).expect("update");
@@ -1438,7 +3168,7 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
             let mut ti = String::new();
             if lh > 0.0 { ti.push_str(&format!(" L:{:.0}Hz", lh)); }
             if rh > 0.0 { ti.push_str(&format!(" R:{:.0}Hz", rh)); }
-            let ms = if muted { " [MUTE]" } else { "" };
+            let ms = if focus_paused { " [PAUSED]" } else if muted || focus_quiet { " [MUTE]" } else { "" };
             let fs = if fps_unlimited { " [∞]" } else { "" };
             let rec = if gif_encoder.is_some() { " [REC]" } else { "" };
             // LED status
@@ -1448,11 +3178,22 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
             } else { String::new() };
             let tx = if arduboy.led_tx { " TX" } else { "" };
             let rx = if arduboy.led_rx { " RX" } else { "" };
+            let dsp = if arduboy.display_powered_on() { "" } else { " [DISPLAY OFF]" };
             let lcd = if lcd_effect { " [LCD]" } else { "" };
             let blr = if blur_enabled { " [BLUR]" } else { "" };
             let prf = if arduboy.profiler.enabled { " [PROF]" } else { "" };
             let flt = if arduboy.audio_buf.filters_enabled { " [FILT]" } else { "" };
-            let prt = if portrait { " [PORT]" } else { "" };
+            let snd = if arduboy.sound_events.enabled {
+                let notes: Vec<String> = arduboy.sound_events.now_playing().iter().flatten()
+                    .map(|t| sound_events::note_name(t.note))
+                    .collect();
+                if notes.is_empty() { String::new() } else { format!(" [♪ {}]", notes.join(" ")) }
+            } else { String::new() };
+            let prt = match arduboy.rotation {
+                Rotation::None => String::new(),
+                r => format!(" [{}°]", r.degrees()),
+            };
+            let plg: String = plugins.overlay.status.iter().map(|s| format!(" [{}]", s)).collect();
             let ntf = if notify_msg.is_some() && Instant::now() < notify_until {
                 format!(" [{}]", notify_msg.as_ref().unwrap())
             } else {
@@ -1461,8 +3202,8 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
                 }
                 String::new()
             };
-            window.set_title(&format!("{} - {:.0} FPS{}{}{}{}{}{}{}{}{}{}{}{}{} ({}x)",
-                title_base, fps, ti, ms, fs, rec, led, tx, rx, lcd, blr, prf, flt, prt, ntf, cur_scale,
+            window.set_title(&format!("{} - {:.0} FPS{}{}{}{}{}{}{}{}{}{}{}{}{}{}{}{} ({}x)",
+                title_base, fps, ti, ms, fs, rec, led, tx, rx, dsp, lcd, blr, prf, flt, snd, prt, plg, ntf, cur_scale,
             ));
             fps_frames = 0;
             last_fps_time = Instant::now();
@@ -1479,20 +3220,217 @@ fn run_gui(arduboy: &mut Arduboy, start_muted: bool, debug: bool, initial_scale:
         }
     }
 
-    // Final EEPROM save
+    // Final EEPROM and FX save
     if !no_save && arduboy.eeprom_dirty {
         save_eeprom(arduboy, &eep_path, debug);
     }
+    if !no_save {
+        save_fx(arduboy, &eep_path, debug);
+    }
 
     if debug {
         let e = start_time.elapsed().as_secs_f64();
         eprintln!("{} frames in {:.1}s ({:.1} FPS), {} cycles", frame_count, e, frame_count as f64 / e, arduboy.cpu.tick);
     }
+    if debug || opts.av_stats {
+        eprintln!("{}", av_sync.report());
+    }
+    exit_code
+}
+
+// ─── Run Verification ───────────────────────────────────────────────────────
+
+/// Replay a verification log's inputs against the loaded game and compare
+/// every checkpoint. Returns the process exit code.
+fn run_verify(arduboy: &mut Arduboy, log_path: &str) -> i32 {
+    use arduboy_core::verify;
+    let text = match fs::read_to_string(log_path) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Verify: {}: {}", log_path, e);
+            return 1;
+        }
+    };
+    let info = match verify::parse_log(&text) {
+        Ok(i) => i,
+        Err(e) => {
+            eprintln!("Verify: {}: {}", log_path, e);
+            return 1;
+        }
+    };
+    if arduboy.checksums().0 != info.flash_hash {
+        eprintln!("Verify: program flash does not match the log (different game build)");
+        return 1;
+    }
+    let fx_hash = if arduboy.fx_flash.loaded { Some(verify::fnv64(&arduboy.fx_flash.data)) } else { None };
+    if fx_hash != info.fx_hash {
+        eprintln!("Verify: FX data does not match the log");
+        return 1;
+    }
+    if let Some((f, ev)) = info.marks.iter().find(|(_, ev)| ev != "reset") {
+        eprintln!("Verify: log contains a {} before frame {}; the run cannot be replayed", ev, f);
+        return 1;
+    }
+
+    arduboy.load_eeprom(&info.eeprom);
+    arduboy.start_verify(info.interval, &info.game);
+    let mut inputs = info.inputs.iter().peekable();
+    let mut marks = info.marks.iter().peekable();
+    for frame in 0..info.frames {
+        while marks.next_if(|(f, _)| *f == frame).is_some() {
+            arduboy.reset();
+        }
+        while let Some((_, p)) = inputs.next_if(|(f, _)| *f == frame) {
+            arduboy.pin_b = p[0];
+            arduboy.pin_c = p[1];
+            arduboy.pin_d = p[2];
+            arduboy.pin_e = p[3];
+            arduboy.pin_f = p[4];
+        }
+        let frame_start = arduboy.cpu.tick;
+        for &(_, cycle, button, pressed) in info.events.iter().filter(|e| e.0 == frame) {
+            arduboy.queue_button_event(frame_start + cycle, button, pressed);
+        }
+        arduboy.run_frame();
+    }
+
+    let replayed = arduboy.verify.as_ref().map(|v| v.finish()).unwrap_or_default();
+    match verify::first_difference(&text, &replayed) {
+        None => {
+            eprintln!("Verify: OK ({}, {} frames, {} checkpoints)",
+                info.game, info.frames, info.frames / info.interval);
+            0
+        }
+        Some((line, expected, actual)) => {
+            eprintln!("Verify: FAILED at line {}\n  log:    {}\n  replay: {}", line, expected, actual);
+            1
+        }
+    }
+}
+
+/// Run the generated CPU conformance ROM headless; exit code 0 = all cases pass.
+fn run_selftest(hex_out: Option<&str>) -> i32 {
+    let st = arduboy_core::selftest::build();
+    if let Some(path) = hex_out {
+        if let Err(e) = fs::write(path, st.to_hex()) {
+            eprintln!("Self-test: {}: {}", path, e);
+            return 1;
+        }
+        eprintln!("Self-test ROM written to {} ({} bytes)", path, st.rom.len());
+    }
+    let mut arduboy = Arduboy::new();
+    match st.run(&mut arduboy, 600) {
+        Ok(report) if report.passed() => {
+            eprintln!("Self-test: OK ({} cases)", report.cases);
+            0
+        }
+        Ok(report) => {
+            for name in &report.failures {
+                eprintln!("  FAIL {}", name);
+            }
+            eprintln!("Self-test: {} of {} cases failed", report.failures.len(), report.cases);
+            1
+        }
+        Err(e) => {
+            eprintln!("Self-test: {}", e);
+            1
+        }
+    }
+}
+
+/// `package <game> [options]`: write a `.arduboy` archive.
+fn run_package(args: &[String]) -> i32 {
+    match build_package(args) {
+        Ok((path, summary)) => {
+            eprintln!("Package written: {} ({})", path, summary);
+            0
+        }
+        Err(e) => {
+            eprintln!("package: {}", e);
+            1
+        }
+    }
+}
+
+fn build_package(args: &[String]) -> Result<(String, String), String> {
+    let input = args.first().filter(|a| !a.starts_with("--")).ok_or("expected a .hex or .elf file")?;
+    let opt = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).map(|s| s.as_str());
+    let read = |path: &str| fs::read(path).map_err(|e| format!("{}: {}", path, e));
+    let path = std::path::Path::new(input);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("game").to_string();
+
+    let hex = if input.to_lowercase().ends_with(".elf") {
+        let elf = arduboy_core::elf::parse_elf(&read(input)?).map_err(|e| format!("{}: {}", input, e))?;
+        arduboy_core::hex::to_hex(&elf.flash)
+    } else {
+        fs::read_to_string(input).map_err(|e| format!("{}: {}", input, e))?
+    };
+    let fx_data = match opt("--fx") {
+        Some(p) => Some(read(p)?),
+        None => arduboy_core::game_file::find_fx(input),
+    };
+    let text = |name: &str| opt(name).unwrap_or_default().to_string();
+    let pkg = arduboy_core::package::Package {
+        title: opt("--title").map_or_else(|| stem.clone(), str::to_string),
+        author: text("--author"),
+        description: text("--description"),
+        version: text("--version"),
+        genre: text("--genre"),
+        url: text("--url"),
+        license: text("--license"),
+        name: stem,
+        hex,
+        fx_data,
+        fx_save: opt("--fx-save").map(read).transpose()?,
+        title_image: opt("--title-image").map(read).transpose()?,
+        rotation: opt("--rotate").map(Rotation::parse).transpose()?.unwrap_or_default(),
+    };
+    let size = pkg.validate()?;
+    let zip = pkg.to_zip()?;
+    // Read it back the way the emulator loads it
+    arduboy_core::arduboy_file::parse_arduboy(&zip).map_err(|e| format!("archive does not load: {}", e))?;
+
+    let out = opt("--out").map_or_else(|| path.with_extension("arduboy").to_string_lossy().into_owned(), str::to_string);
+    fs::write(&out, &zip).map_err(|e| format!("{}: {}", out, e))?;
+    let mut summary = format!("{} bytes of program", size);
+    if let Some(d) = &pkg.fx_data {
+        summary.push_str(&format!(", {} bytes of FX data", d.len()));
+    }
+    if let Some(s) = &pkg.fx_save {
+        summary.push_str(&format!(", {} bytes of FX save", s.len()));
+    }
+    Ok((out, summary))
 }
 
 // ─── Step Mode ──────────────────────────────────────────────────────────────
 
-fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
+/// Debugger commands to run before reading stdin: the lines of the
+/// `--autoexec` file (blank lines and `#` comments skipped), then each
+/// `--ex` command in order.
+fn startup_commands(args: &[String]) -> Vec<String> {
+    let mut cmds = Vec::new();
+    if let Some(path) = args.iter().position(|a| a == "--autoexec").and_then(|i| args.get(i + 1)) {
+        match fs::read_to_string(path) {
+            Ok(text) => cmds.extend(text.lines().map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(String::from)),
+            Err(e) => eprintln!("Warning: --autoexec {}: {}", path, e),
+        }
+    }
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--ex" {
+            if let Some(cmd) = args.get(i + 1) { cmds.push(cmd.clone()); }
+            i += 2;
+        } else { i += 1; }
+    }
+    cmds
+}
+
+fn run_step_mode(
+    args: &[String], arduboy: &mut Arduboy, elf: &mut Option<ElfFile>,
+    annotations: &mut Annotations, sym_path: Option<&Path>,
+) {
     let max_steps: usize = args.iter()
         .position(|a| a == "--frames")
         .and_then(|i| args.get(i + 1))
@@ -1514,32 +3452,82 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
     println!("  w <addr> [r|w|rw]  Add watchpoint (data addr)");
     println!("  wl           List watchpoints");
     println!("  wd <idx>     Delete watchpoint");
+    println!("  wp <range|symbol>[@<fn|range>,...]  Stop on writes from other code");
+    println!("  wpl          List write-protect regions");
+    println!("  wpd <idx>    Delete write-protect region");
+    println!("  devices      List virtual devices with their access counts");
+    println!("  fault [spec] Add a peripheral fault (eeprom-fail, fx-corrupt, spi-drop), or list them");
+    println!("  fault del <idx>|off  Remove one fault, or all");
+    println!("  tp <addr> \"<fmt>\" [if <cond>]  Add tracepoint (log without stopping)");
+    println!("  tpl          List tracepoints");
+    println!("  tpd <idx>    Delete tracepoint");
+    println!("  pl           List flash patches");
+    println!("  pe|pd <idx|name>  Enable/disable flash patch");
     println!("  prof start   Start profiler");
     println!("  prof stop    Stop and show report");
     println!("  prof report  Show profiler report");
+    println!("  spi start|stop|clear  Control the SPI transaction decoder");
+    println!("  spi [N] [dev]  Last N decoded SPI transactions (dev: flash, display, ...)");
+    println!("  spi json <file>  Export SPI timeline as JSON");
+    println!("  oled         Show OLED controller state (power, contrast, orientation)");
+    println!("  sys [key value]  Show or set Arduboy2 system EEPROM (name, id, audio, logo, ...)");
+    println!("  fxmod        FX flash sectors changed since the image loaded");
+    println!("  strace start|stop|clear  Control the byte-level SPI trace");
+    println!("  strace filter <spec>  Trace only e.g. fx, display, cmd, ports (comma-separated; all)");
+    println!("  strace [N]     Last N traced SPI bytes");
+    println!("  strace json|csv <file>  Export the SPI trace");
+    println!("  timeline start|stop|clear  Control the per-frame event timeline");
+    println!("  timeline [N]   Chart of the Nth most recent frame (ISRs, SPI, display, sleep)");
+    println!("  timeline json <file>  Export recorded frames for chrome://tracing");
+    println!("  flush start|stop|clear  Control display flush timing");
+    println!("  flush [N]      Flush timing summary, or the Nth most recent frame's flush");
+    println!("  periph [name]  List peripherals, or show one with decoded fields and IRQs");
+    println!("  sram [width]   SRAM map: .data/.bss/.noinit, heap break, SP and deepest stack");
+    println!("  log [spec]   Show/set log levels, e.g. log spi=trace,fx=debug");
+    println!("  state [file] Full sorted state dump (print, or write to file for diff)");
+    println!("  p <expr>     Evaluate, e.g. p score:u16, p [$y+2]:i8, p enemies[3]");
+    println!("  display [expr]  Show expr after every step/frame (no arg: show all)");
+    println!("  undisplay [N]   Remove display N (no arg: all)");
+    println!("  sym <addr> <name|->  Name a flash (or ram:<addr> data) address; - removes it");
+    println!("  note <addr> [text]  Comment on an address shown with its disassembly; no text removes it");
+    println!("  syms         List user symbols and comments");
+    println!("  symsave [file]  Write user symbols (default: the game's .sym file)");
     println!("  q/quit       Exit");
     println!();
     println!("{}", arduboy.dump_regs());
-    println!("Next: {}", arduboy.disasm_at_pc());
+    println!("Next: {}", next_line(arduboy, elf.as_ref()));
 
     let stdin = std::io::stdin();
     let mut steps = 0usize;
     let mut ram_snapshot: Option<Vec<u8>> = None;
+    let mut startup = startup_commands(args).into_iter();
+    let mut symbols = elf.as_ref().map(|e| Symbols::from_elf(&e.symbols)).unwrap_or_default();
+    let mut displays = DisplayList::new();
 
     loop {
         let mut line = String::new();
         print!("dbg> ");
-        let _ = std::io::stdout().flush();
-        if stdin.read_line(&mut line).is_err() { break; }
+        if let Some(cmd) = startup.next() {
+            println!("{}", cmd);
+            line = cmd;
+        } else {
+            let _ = std::io::stdout().flush();
+            match stdin.read_line(&mut line) {
+                Ok(0) | Err(_) => break, // end of input (piped script)
+                Ok(_) => {}
+            }
+        }
         let parts: Vec<&str> = line.trim().split_whitespace().collect();
         if parts.is_empty() {
             // Empty line = step 1
             let asm = arduboy.step_one();
             steps += 1;
             println!("  {}", asm);
+            print_core_messages(arduboy);
             check_watch_hit(arduboy);
             println!("{}", arduboy.dump_regs());
-            println!("Next: {}", arduboy.disasm_at_pc());
+            println!("Next: {}", next_line(arduboy, elf.as_ref()));
+            print_displays(&mut displays, arduboy);
             continue;
         }
 
@@ -1561,16 +3549,24 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
                     }
                     arduboy.step_one();
                     steps += 1;
+                    print_core_messages(arduboy);
                     if check_watch_hit(arduboy) { break; }
+                    // Shadow stack, stack guard or strict mode; the report was printed above
+                    if arduboy.diagnostics.take_trip() {
+                        println!("*** Stopped by checker ***");
+                        break;
+                    }
                 }
                 println!("{}", arduboy.dump_regs());
-                println!("Next: {}", arduboy.disasm_at_pc());
+                println!("Next: {}", next_line(arduboy, elf.as_ref()));
+                print_displays(&mut displays, arduboy);
             }
 
             "f" | "frame" => {
                 let n: usize = if parts.len() > 1 { parts[1].parse().unwrap_or(1) } else { 1 };
                 for _ in 0..n {
                     arduboy.run_frame();
+                    print_core_messages(arduboy);
                     if arduboy.breakpoint_hit {
                         println!("*** Break: {} ***", arduboy.disasm_at_pc());
                         arduboy.breakpoint_hit = false;
@@ -1579,7 +3575,8 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
                     }
                 }
                 println!("{}", arduboy.dump_regs());
-                println!("Next: {}", arduboy.disasm_at_pc());
+                println!("Next: {}", next_line(arduboy, elf.as_ref()));
+                print_displays(&mut displays, arduboy);
             }
 
             "ram" => {
@@ -1622,65 +3619,187 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
             "b" => {
                 if parts.len() > 1 {
                     if let Some(addr) = parse_cli_hex(parts[1]) {
-                        let word_addr = (addr as u16) / 2;
+                        let word_addr = addr / 2;
                         arduboy.breakpoints.push(word_addr);
                         println!("Breakpoint added: 0x{:04X} (word 0x{:04X})", addr, word_addr);
                     }
-                } else {
-                    println!("Usage: b <hex-byte-addr>");
+                } else {
+                    println!("Usage: b <hex-byte-addr>");
+                }
+            }
+
+            "bl" => {
+                if arduboy.breakpoints.is_empty() {
+                    println!("No breakpoints.");
+                } else {
+                    for (i, &bp) in arduboy.breakpoints.iter().enumerate() {
+                        println!("  [{}] 0x{:04X} (byte 0x{:04X})", i, bp, bp * 2);
+                    }
+                }
+            }
+
+            "bd" => {
+                if parts.len() > 1 {
+                    if let Ok(idx) = parts[1].parse::<usize>() {
+                        if idx < arduboy.breakpoints.len() {
+                            let removed = arduboy.breakpoints.remove(idx);
+                            println!("Removed breakpoint [{}] at 0x{:04X}", idx, removed * 2);
+                        } else { println!("Invalid index."); }
+                    }
+                }
+            }
+
+            "w" => {
+                if parts.len() > 1 {
+                    if let Some(addr) = parse_cli_hex(parts[1]) {
+                        let kind = if parts.len() > 2 {
+                            match parts[2] {
+                                "r" => arduboy_core::debugger::WatchKind::Read,
+                                "w" => arduboy_core::debugger::WatchKind::Write,
+                                _ => arduboy_core::debugger::WatchKind::ReadWrite,
+                            }
+                        } else {
+                            arduboy_core::debugger::WatchKind::ReadWrite
+                        };
+                        let idx = arduboy.debugger.add_watchpoint(addr as u16, kind);
+                        println!("Watchpoint [{}]: 0x{:04X} {:?}", idx, addr, kind);
+                    }
+                } else {
+                    println!("Usage: w <hex-addr> [r|w|rw]");
+                }
+            }
+
+            "wl" => {
+                print!("{}", arduboy.debugger.list_watchpoints());
+            }
+
+            "wp" => {
+                if parts.len() > 1 {
+                    match arduboy_core::debugger::ProtectRegion::parse(&parts[1..].join(" "), elf.as_ref()) {
+                        Ok(r) => {
+                            println!("Write-protect [{}]: 0x{:04X}-0x{:04X} ({} allowed ranges)",
+                                arduboy.debugger.protect_regions.len(), r.start, r.end, r.allowed.len());
+                            arduboy.debugger.protect_regions.push(r);
+                        }
+                        Err(e) => println!("Error: {}", e),
+                    }
+                } else {
+                    println!("Usage: wp <range|symbol>[@<fn|range>,...]");
+                }
+            }
+
+            "wpl" => {
+                print!("{}", arduboy.debugger.list_protect_regions());
+            }
+
+            "wpd" => {
+                if let Some(idx) = parts.get(1).and_then(|p| p.parse::<usize>().ok()) {
+                    if idx < arduboy.debugger.protect_regions.len() {
+                        arduboy.debugger.protect_regions.remove(idx);
+                        println!("Write-protect [{}] removed.", idx);
+                    } else { println!("Invalid index."); }
+                }
+            }
+
+            "flush" => {
+                let flush = &mut arduboy.flush_timing;
+                match parts.get(1).copied() {
+                    Some("start") => {
+                        flush.enabled = true;
+                        println!("Flush timing started.");
+                    }
+                    Some("stop") => {
+                        flush.enabled = false;
+                        println!("Flush timing stopped.");
+                    }
+                    Some("clear") => {
+                        flush.clear();
+                        println!("Flush timing cleared.");
+                    }
+                    Some(n) => match n.parse().ok().and_then(|n| flush.recent(n)) {
+                        Some(f) => println!("{}", f.line()),
+                        None => println!("No such frame ({} measured).", flush.frames().count()),
+                    },
+                    None if !flush.enabled && flush.frames().next().is_none() => {
+                        println!("Flush timing is off. Use 'flush start'.");
+                    }
+                    None => println!("{}", flush.report()),
+                }
+            }
+
+            "fault" => {
+                match parts.get(1).copied() {
+                    None => {
+                        if arduboy.faults.is_empty() {
+                            println!("No faults. Add one with e.g. 'fault eeprom-fail,every=2'.");
+                        } else {
+                            print!("{}", arduboy.faults.list());
+                        }
+                    }
+                    Some("off") => {
+                        arduboy.faults.clear();
+                        println!("Faults cleared.");
+                    }
+                    Some("del") => {
+                        match parts.get(2).and_then(|p| p.parse::<usize>().ok()).and_then(|i| arduboy.faults.remove(i)) {
+                            Some(rule) => println!("Fault removed: {}", rule.spec),
+                            None => println!("Invalid index."),
+                        }
+                    }
+                    Some(_) => match arduboy_core::fault::FaultRule::parse(&parts[1..].join(" ")) {
+                        Ok(rule) => println!("Fault [{}]: {}", arduboy.faults.add(rule), parts[1..].join(" ")),
+                        Err(e) => println!("{}", e),
+                    },
                 }
             }
 
-            "bl" => {
-                if arduboy.breakpoints.is_empty() {
-                    println!("No breakpoints.");
+            "devices" => {
+                if arduboy.devices.is_empty() {
+                    println!("No virtual devices (load them with --devices <file>).");
                 } else {
-                    for (i, &bp) in arduboy.breakpoints.iter().enumerate() {
-                        println!("  [{}] 0x{:04X} (byte 0x{:04X})", i, bp, bp * 2);
-                    }
+                    print!("{}", arduboy.devices.list());
                 }
             }
 
-            "bd" => {
+            "wd" => {
                 if parts.len() > 1 {
                     if let Ok(idx) = parts[1].parse::<usize>() {
-                        if idx < arduboy.breakpoints.len() {
-                            let removed = arduboy.breakpoints.remove(idx);
-                            println!("Removed breakpoint [{}] at 0x{:04X}", idx, removed * 2);
+                        if arduboy.debugger.remove_watchpoint(idx) {
+                            println!("Watchpoint [{}] removed.", idx);
                         } else { println!("Invalid index."); }
                     }
                 }
             }
 
-            "w" => {
-                if parts.len() > 1 {
-                    if let Some(addr) = parse_cli_hex(parts[1]) {
-                        let kind = if parts.len() > 2 {
-                            match parts[2] {
-                                "r" => arduboy_core::debugger::WatchKind::Read,
-                                "w" => arduboy_core::debugger::WatchKind::Write,
-                                _ => arduboy_core::debugger::WatchKind::ReadWrite,
-                            }
-                        } else {
-                            arduboy_core::debugger::WatchKind::ReadWrite
-                        };
-                        let idx = arduboy.debugger.add_watchpoint(addr as u16, kind);
-                        println!("Watchpoint [{}]: 0x{:04X} {:?}", idx, addr, kind);
-                    }
-                } else {
-                    println!("Usage: w <hex-addr> [r|w|rw]");
+            "tp" => {
+                match arduboy.tracepoints.add(&line.trim()[2..], &symbols) {
+                    Ok(idx) => println!("Tracepoint [{}] added.", idx),
+                    Err(e) => println!("Usage: tp <addr> \"<fmt>\" [if <cond>]: {}", e),
                 }
             }
 
-            "wl" => {
-                print!("{}", arduboy.debugger.list_watchpoints());
+            "tpl" => {
+                print!("{}", arduboy.tracepoints.list());
             }
 
-            "wd" => {
+            "pl" => {
+                print!("{}", arduboy.patches.list());
+            }
+
+            "pe" | "pd" => {
+                if parts.len() > 1 {
+                    let on = parts[0] == "pe";
+                    if arduboy.set_patch_enabled(parts[1], on) {
+                        println!("Patch {} {}.", parts[1], if on { "enabled" } else { "disabled" });
+                    } else { println!("No such patch."); }
+                }
+            }
+
+            "tpd" => {
                 if parts.len() > 1 {
                     if let Ok(idx) = parts[1].parse::<usize>() {
-                        if arduboy.debugger.remove_watchpoint(idx) {
-                            println!("Watchpoint [{}] removed.", idx);
+                        if arduboy.tracepoints.remove(idx) {
+                            println!("Tracepoint [{}] removed.", idx);
                         } else { println!("Invalid index."); }
                     }
                 }
@@ -1704,6 +3823,296 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
                 }
             }
 
+            "periph" => {
+                let want = parts.get(1).map(|n| n.to_uppercase());
+                let mut found = false;
+                for p in arduboy.peripherals() {
+                    match &want {
+                        Some(n) if *n != p.name => {}
+                        Some(_) => { print!("{}", p.format()); found = true; }
+                        None => {
+                            let pending = p.interrupts.iter().filter(|i| i.pending).count();
+                            println!("  {:<8} {:<12} {} regs{}", p.name,
+                                if !p.powered { "powered-down" } else if p.active { "active" } else { "idle" },
+                                p.registers.len(),
+                                if pending > 0 { format!(", {} irq pending", pending) } else { String::new() });
+                            found = true;
+                        }
+                    }
+                }
+                if !found { println!("Unknown peripheral (type 'periph' for the list)"); }
+            }
+
+            "sram" => {
+                let width = parts.get(1).and_then(|w| w.parse().ok()).unwrap_or(64);
+                print!("{}", arduboy.sram_map(elf.as_ref()).format(width));
+            }
+
+            "state" => {
+                let text = arduboy.dump_full_state_text();
+                match parts.get(1) {
+                    Some(path) => match fs::write(path, &text) {
+                        Ok(()) => println!("State written to {} ({} lines)", path, text.lines().count()),
+                        Err(e) => println!("Write error: {}", e),
+                    },
+                    None => print!("{}", text),
+                }
+            }
+
+            "p" | "print" => {
+                let src = parts[1..].join(" ");
+                match Expr::parse(&src, &symbols).and_then(|e| e.eval(arduboy)) {
+                    Ok(v) => println!("{} = {} (0x{:X})", src, v, v),
+                    Err(e) => println!("{}", e),
+                }
+            }
+
+            "display" => {
+                if parts.len() > 1 {
+                    match displays.add(&parts[1..].join(" "), &symbols) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            println!("{}", e);
+                            continue;
+                        }
+                    }
+                }
+                if displays.is_empty() {
+                    println!("No displays.");
+                }
+                print_displays(&mut displays, arduboy);
+            }
+
+            "undisplay" => {
+                match parts.get(1).map(|s| s.parse::<usize>()) {
+                    None => displays.clear(),
+                    Some(Ok(n)) if displays.remove(n) => {}
+                    _ => println!("No display {}", parts[1]),
+                }
+            }
+
+            "fxmod" => {
+                let fx = &arduboy.fx_flash;
+                let sectors = fx.modified_sectors();
+                if sectors.is_empty() {
+                    println!("No FX sectors modified.");
+                }
+                for s in sectors {
+                    let addr = s * arduboy_core::peripherals::fx_flash::SECTOR_SIZE;
+                    println!("  sector {:4}  0x{:06X}{}", s, addr, if fx.is_blank(s) { "  (erased)" } else { "" });
+                }
+            }
+
+            "sys" => {
+                let mut sys = arduboy.system_settings();
+                if let Some(key) = parts.get(1) {
+                    let value = parts[2..].join(" ");
+                    if let Err(e) = sys.set(key, &value) {
+                        println!("{}", e);
+                        continue;
+                    }
+                    arduboy.set_system_settings(&sys);
+                }
+                println!("{}", sys);
+            }
+
+            "log" => {
+                if let Some(spec) = parts.get(1) {
+                    if let Err(e) = arduboy.log.apply(spec) { println!("{}", e); }
+                }
+                println!("Log levels: {}", arduboy.log.describe());
+            }
+
+            "oled" => {
+                let d = &arduboy.display;
+                let power = match d.missing_power_command() {
+                    None => "on".to_string(),
+                    Some(cmd) => format!("off (needs {})", cmd),
+                };
+                println!("{}: power {}, contrast 0x{:02X}, orientation {}{}",
+                    d.quirks.controller.name(), power, d.contrast, d.orientation().name(),
+                    if d.is_dark() { ", screen dark (strict)" } else { "" });
+            }
+
+            "spi" => {
+                match parts.get(1).copied() {
+                    Some("start") => {
+                        arduboy.spi_decoder.start();
+                        println!("SPI decoder started.");
+                    }
+                    Some("stop") => {
+                        arduboy.spi_decoder.stop();
+                        println!("SPI decoder stopped ({} transactions).", arduboy.spi_decoder.len());
+                    }
+                    Some("clear") => {
+                        arduboy.spi_decoder.clear();
+                        println!("SPI timeline cleared.");
+                    }
+                    Some("json") => {
+                        let Some(path) = parts.get(2) else {
+                            println!("Usage: spi json <file>");
+                            continue;
+                        };
+                        match fs::write(path, arduboy.spi_decoder.to_json(arduboy.clock_hz)) {
+                            Ok(()) => println!("Wrote {} transactions to {}", arduboy.spi_decoder.len(), path),
+                            Err(e) => println!("Write error: {}", e),
+                        }
+                    }
+                    _ => {
+                        let mut n = 20usize;
+                        let mut devices = None;
+                        for p in &parts[1..] {
+                            if let Ok(v) = p.parse() {
+                                n = v;
+                            } else if let Some(d) = arduboy_core::spi_decode::SpiDevice::parse(p) {
+                                devices = Some(d);
+                            } else {
+                                println!("Unknown device '{}' (flash, display, ssd1306, pcd8544, none)", p);
+                            }
+                        }
+                        if !arduboy.spi_decoder.enabled && arduboy.spi_decoder.is_empty() {
+                            println!("SPI decoder is off. Use 'spi start'.");
+                            continue;
+                        }
+                        let list = arduboy.spi_decoder.query(0, u64::MAX, devices.as_deref());
+                        for t in &list[list.len().saturating_sub(n)..] {
+                            println!("  {}", t.format_line());
+                        }
+                        println!("({} of {} transactions)", list.len().min(n), arduboy.spi_decoder.len());
+                    }
+                }
+            }
+
+            "timeline" => {
+                let tl = &mut arduboy.frame_timeline;
+                match parts.get(1).copied() {
+                    Some("start") => {
+                        tl.start();
+                        println!("Frame timeline started (keeps {} frames).", tl.capacity);
+                    }
+                    Some("stop") => {
+                        tl.stop();
+                        println!("Frame timeline stopped ({} frames).", tl.len());
+                    }
+                    Some("clear") => {
+                        tl.clear();
+                        println!("Frame timeline cleared.");
+                    }
+                    Some("json") => {
+                        let Some(path) = parts.get(2) else {
+                            println!("Usage: timeline json <file>");
+                            continue;
+                        };
+                        match fs::write(path, tl.to_chrome_json(arduboy.clock_hz)) {
+                            Ok(()) => println!("Wrote {} frames to {}", tl.len(), path),
+                            Err(e) => println!("Write error: {}", e),
+                        }
+                    }
+                    n => {
+                        let n = n.and_then(|p| p.parse().ok()).unwrap_or(0usize);
+                        match tl.recent(n) {
+                            Some(f) => print!("{}", tl.render(f, 64, arduboy.clock_hz)),
+                            None if !tl.enabled => println!("Frame timeline is off. Use 'timeline start', then run frames."),
+                            None => println!("No recorded frame {} back ({} recorded).", n, tl.len()),
+                        }
+                    }
+                }
+            }
+
+            "strace" => {
+                let trace = &mut arduboy.spi_trace;
+                match parts.get(1).copied() {
+                    Some("start") => {
+                        trace.start();
+                        println!("SPI trace started (filter: {}).", trace.filter.describe());
+                    }
+                    Some("stop") => {
+                        trace.stop();
+                        println!("SPI trace stopped ({} entries).", trace.len());
+                    }
+                    Some("clear") => {
+                        trace.clear();
+                        println!("SPI trace cleared.");
+                    }
+                    Some("filter") => {
+                        match parts.get(2).map(|p| arduboy_core::spi_trace::SpiTraceFilter::parse(p)) {
+                            Some(Ok(f)) => {
+                                trace.filter = f;
+                                println!("SPI trace filter: {}", trace.filter.describe());
+                            }
+                            Some(Err(e)) => println!("{} (fx, display, ssd1306, pcd8544, none, cmd, ports, all)", e),
+                            None => println!("SPI trace filter: {}", trace.filter.describe()),
+                        }
+                    }
+                    Some(fmt @ ("json" | "csv")) => {
+                        let Some(path) = parts.get(2) else {
+                            println!("Usage: strace {} <file>", fmt);
+                            continue;
+                        };
+                        let text = if fmt == "json" { trace.to_json(arduboy.clock_hz) } else { trace.to_csv() };
+                        match fs::write(path, text) {
+                            Ok(()) => println!("Wrote {} entries to {}", trace.len(), path),
+                            Err(e) => println!("Write error: {}", e),
+                        }
+                    }
+                    n => {
+                        if !trace.enabled && trace.is_empty() {
+                            println!("SPI trace is off. Use 'strace start'.");
+                            continue;
+                        }
+                        let n = n.and_then(|p| p.parse().ok()).unwrap_or(20usize);
+                        for e in trace.last(n) {
+                            println!("  {}", e.format_line());
+                        }
+                        println!("({} of {} entries, {} dropped, filter: {})",
+                            trace.len().min(n), trace.len(), trace.dropped, trace.filter.describe());
+                    }
+                }
+            }
+
+            "sym" | "note" if parts.len() > 1 => {
+                let addr = match parts[1].strip_prefix("ram:") {
+                    Some(a) => parse_cli_hex(a).map(|a| a + 0x800000),
+                    None => parse_cli_hex(parts[1]),
+                };
+                let Some(addr) = addr else {
+                    println!("Bad address: {}", parts[1]);
+                    continue;
+                };
+                let elf = elf.get_or_insert_with(ElfFile::default);
+                if parts[0] == "sym" {
+                    let name = parts.get(2).copied().filter(|&n| n != "-");
+                    annotations.set_name(addr, name);
+                    if name.is_none() { elf.symbols.remove(&addr); }
+                } else {
+                    let text = line.trim().splitn(3, char::is_whitespace).nth(2).map(str::trim).filter(|t| !t.is_empty());
+                    annotations.set_comment(addr, text);
+                    if text.is_none() { elf.comments.remove(&addr); }
+                }
+                annotations.apply(elf);
+                symbols = Symbols::from_elf(&elf.symbols);
+                println!("0x{:04X}: {}", addr, elf.describe_pc(addr / 2));
+            }
+
+            "syms" => {
+                if annotations.is_empty() {
+                    println!("No user symbols. Add one with 'sym <addr> <name>'.");
+                } else {
+                    print!("{}", annotations.to_text());
+                }
+            }
+
+            "symsave" => {
+                let Some(path) = parts.get(1).map(Path::new).or(sym_path) else {
+                    println!("Usage: symsave <file>");
+                    continue;
+                };
+                match annotations.save(path) {
+                    Ok(()) => println!("Wrote {} entries to {}", annotations.len(), path.display()),
+                    Err(e) => println!("{}: {}", path.display(), e),
+                }
+            }
+
             // Numeric: step N instructions
             _ => {
                 let n: usize = parts[0].parse().unwrap_or(1);
@@ -1715,7 +4124,8 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
                     if check_watch_hit(arduboy) { break; }
                 }
                 println!("{}", arduboy.dump_regs());
-                println!("Next: {}", arduboy.disasm_at_pc());
+                println!("Next: {}", next_line(arduboy, elf.as_ref()));
+                print_displays(&mut displays, arduboy);
             }
         }
     }
@@ -1727,9 +4137,30 @@ fn run_step_mode(args: &[String], arduboy: &mut Arduboy) {
     println!("Total: {} steps, {} cycles", steps, arduboy.cpu.tick);
 }
 
+/// Instruction at the PC, followed by its symbol, source line and comment.
+fn next_line(arduboy: &Arduboy, elf: Option<&ElfFile>) -> String {
+    match elf.map(|e| e.describe_pc(arduboy.cpu.pc)).filter(|d| !d.is_empty()) {
+        Some(desc) => format!("{}  {}", arduboy.disasm_at_pc(), desc),
+        None => arduboy.disasm_at_pc(),
+    }
+}
+
+/// Print watch displays (`display` command) after a step or frame.
+fn print_displays(displays: &mut DisplayList, arduboy: &Arduboy) {
+    if !displays.is_empty() {
+        println!("{}", displays.render(arduboy));
+    }
+}
+
 /// Check and display watchpoint hit, return true if hit.
 fn check_watch_hit(arduboy: &mut Arduboy) -> bool {
     if let Some(hit) = arduboy.debugger.take_hit() {
+        if let Some(pc) = hit.protect_pc {
+            let spec = arduboy.debugger.protect_regions.get(hit.index).map(|r| r.spec.as_str()).unwrap_or("");
+            println!("*** Write-protect [{}] {}: 0x{:04X} {:02X} → {:02X} written at PC 0x{:04X} ***",
+                hit.index, spec, hit.addr, hit.old_val, hit.new_val, pc);
+            return true;
+        }
         let name = arduboy_core::debugger::io_name(
             hit.addr, arduboy.cpu_type == CpuType::Atmega328p
         ).unwrap_or("");
@@ -1758,11 +4189,7 @@ fn run_gdb_mode(arduboy: &mut Arduboy, port: u16, debug: bool) {
 
     // Initial halt — GDB expects the target to be stopped
     loop {
-        let regs = arduboy.gdb_regs();
-        let action = session.process_packet(
-            &regs, arduboy.cpu.sreg, arduboy.cpu.sp, arduboy.cpu.pc,
-            &arduboy.mem.flash, &mut arduboy.mem.data,
-        ).expect("GDB packet error");
+        let action = session.process_packet(arduboy).expect("GDB packet error");
 
         match action {
             GdbAction::Continue => {
@@ -1771,7 +4198,7 @@ fn run_gdb_mode(arduboy: &mut Arduboy, port: u16, debug: bool) {
                 loop {
                     // Check GDB breakpoints
                     let pc_word = arduboy.cpu.pc;
-                    if session.breakpoints.iter().any(|&bp| pc_word == bp as u16) {
+                    if session.breakpoints.contains(&pc_word) {
                         break;
                     }
 
@@ -1792,11 +4219,7 @@ fn run_gdb_mode(arduboy: &mut Arduboy, port: u16, debug: bool) {
                     if session.has_pending() {
                         session.set_nonblocking(false).ok();
                         // Read the interrupt byte
-                        let regs2 = arduboy.gdb_regs();
-                        let _ = session.process_packet(
-                            &regs2, arduboy.cpu.sreg, arduboy.cpu.sp, arduboy.cpu.pc,
-                            &arduboy.mem.flash, &mut arduboy.mem.data,
-                        );
+                        let _ = session.process_packet(arduboy);
                         break;
                     }
                 }
@@ -1807,8 +4230,10 @@ fn run_gdb_mode(arduboy: &mut Arduboy, port: u16, debug: bool) {
                         eprintln!("GDB: watchpoint hit at 0x{:04X} ({:02X} → {:02X})",
                             wh.addr, wh.old_val, wh.new_val);
                     }
+                    session.send_watch_reply(&wh).expect("GDB send error");
+                } else {
+                    session.send_stop_reply().expect("GDB send error");
                 }
-                session.send_stop_reply().expect("GDB send error");
             }
 
             GdbAction::Step => {
@@ -1830,9 +4255,321 @@ fn run_gdb_mode(arduboy: &mut Arduboy, port: u16, debug: bool) {
     }
 }
 
+// ─── Scenario Mode ──────────────────────────────────────────────────────────
+
+/// Run a TOML test scenario. Returns the process exit code.
+fn run_scenario(path: &str, debug: bool) -> i32 {
+    use arduboy_core::scenario::Scenario;
+
+    let scenario = match fs::read_to_string(path)
+        .map_err(|e| format!("{}: {}", path, e))
+        .and_then(|text| Scenario::parse(&text).map_err(|e| format!("{}: {}", path, e)))
+    {
+        Ok(sc) => sc,
+        Err(e) => { eprintln!("Scenario error: {}", e); return 2; }
+    };
+
+    // Game and FX paths are relative to the scenario file
+    let base = std::path::Path::new(path).parent().unwrap_or(std::path::Path::new("."));
+    let resolve = |p: &str| base.join(p).to_string_lossy().into_owned();
+    let game_path = resolve(&scenario.game);
+    let fx_path = scenario.fx.as_deref().map(resolve);
+    let game = match load_game_file(&game_path, fx_path.as_deref(), debug) {
+        Ok(g) => g,
+        Err(e) => { eprintln!("Scenario error: {}", e); return 2; }
+    };
+
+    let mut arduboy = Arduboy::new_with_cpu(game_cpu(&game, None, scenario.cpu, debug));
+    if let Err(e) = load_program(&mut arduboy, &game, debug) {
+        eprintln!("Scenario error: {}", e);
+        return 2;
+    }
+    arduboy.debug = debug;
+    load_game_fx(&mut arduboy, &game, debug);
+
+    let report = scenario.run(&mut arduboy);
+    for a in &report.artifacts {
+        println!("  artifact: {}", a);
+    }
+    for f in &report.failures {
+        println!("  FAIL {}", f);
+    }
+    println!("Scenario {}: {} passed, {} failed, {} frames",
+        path, report.passed, report.failures.len(), report.frames_run);
+    if report.success() { 0 } else { 1 }
+}
+
 // ─── Headless Mode ──────────────────────────────────────────────────────────
 
-fn run_headless(args: &[String], arduboy: &mut Arduboy, serial_enabled: bool) {
+/// Scripted inputs for `screenshot` / `record`: `--press F:btn` taps for
+/// 5 frames, `--press F-G:btn` holds from frame F until G (repeatable).
+fn press_schedule(args: &[String]) -> Result<Vec<(u32, u32, Button)>, String> {
+    let mut presses = Vec::new();
+    for (i, a) in args.iter().enumerate() {
+        if a != "--press" {
+            continue;
+        }
+        let spec = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        let parsed = spec.split_once(':').and_then(|(range, btn)| {
+            let (from, to) = match range.split_once('-') {
+                Some((f, t)) => (f.parse().ok()?, t.parse().ok()?),
+                None => { let f: u32 = range.parse().ok()?; (f, f + 5) }
+            };
+            Some((from, to, arduboy_core::scenario::parse_button(btn).ok()?))
+        });
+        presses.push(parsed.ok_or_else(|| format!("--press: expected FRAME:BUTTON or FROM-TO:BUTTON, got '{}'", spec))?);
+    }
+    Ok(presses)
+}
+
+/// Press and release scheduled buttons before running `frame`.
+fn apply_presses(arduboy: &mut Arduboy, presses: &[(u32, u32, Button)], frame: u32) {
+    for &(from, to, b) in presses {
+        if frame == from { arduboy.set_button(b, true); }
+        if frame == to { arduboy.set_button(b, false); }
+    }
+}
+
+/// `record` subcommand: run headless with scripted `--press` inputs and
+/// save frames `--start`..`--start + --frames` as an animated GIF (or APNG
+/// when `--out` ends in `.png`). Exit code 0 on success.
+fn run_record(args: &[String], arduboy: &mut Arduboy, lcd: bool) -> i32 {
+    let opt = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1));
+    let start: u32 = opt("--start").and_then(|s| s.parse().ok()).unwrap_or(0);
+    let frames: u32 = opt("--frames").and_then(|s| s.parse().ok()).unwrap_or(300);
+    let out = opt("--out").map(|s| s.as_str()).unwrap_or("record.gif");
+    let scale: usize = opt("--scale").and_then(|s| s.parse().ok()).unwrap_or(1).clamp(1, 8);
+    // Keep every Nth game frame (GIF viewers slow down delays under 2/100 s)
+    let every: u32 = opt("--every").and_then(|s| s.parse().ok()).unwrap_or(2).max(1);
+    let presses = match press_schedule(args) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    for frame in 0..start {
+        apply_presses(arduboy, &presses, frame);
+        arduboy.run_frame();
+        arduboy.breakpoint_hit = false;
+    }
+    let native = native_export(args, arduboy);
+    let (fw, fh) = if native {
+        (arduboy_core::pcd8544::PCD_WIDTH, arduboy_core::pcd8544::PCD_HEIGHT)
+    } else {
+        screen_dims(arduboy)
+    };
+    let (fw, fh) = arduboy.rotation.size(fw, fh);
+    let (sw, sh) = (fw * scale, fh * scale);
+    let (on, off) = if native {
+        lcd_palette(true)
+    } else if lcd {
+        lcd_palette(matches!(arduboy.display_type, DisplayType::Pcd8544))
+    } else {
+        ([0xFF; 3], [0x00; 3])
+    };
+    let apng = out.to_ascii_lowercase().ends_with(".png");
+    let mut gif = arduboy_core::gif::GifEncoder::with_colors(sw as u16, sh as u16, 2, off, on);
+    let mut png = arduboy_core::png::ApngEncoder::new(sw as u32, sh as u32, every as u16, 60, off, on);
+    // GIF delays are whole centiseconds: carry the remainder between frames
+    let mut delay_acc = 0u32;
+
+    for frame in start..start + frames {
+        apply_presses(arduboy, &presses, frame);
+        arduboy.run_frame();
+        print_core_messages(arduboy);
+        arduboy.breakpoint_hit = false;
+        if !(frame - start).is_multiple_of(every) {
+            continue;
+        }
+        let src = |i: usize| (i / sw / scale) * fw + (i % sw) / scale;
+        let mono: Vec<bool> = match native_pixels(arduboy).filter(|_| native) {
+            Some((px, _, _)) => (0..sw * sh).map(|i| px[src(i)]).collect(),
+            None => {
+                let (px, _, _) = output_pixels(arduboy);
+                (0..sw * sh).map(|i| (px[src(i)] >> 16) & 0xFF > 128).collect()
+            }
+        };
+        if apng {
+            png.add_frame_mono(&mono);
+        } else {
+            delay_acc += every * 100;
+            gif.delay_cs = (delay_acc / 60) as u16;
+            delay_acc %= 60;
+            gif.add_frame_mono(&mono);
+        }
+    }
+
+    let (data, count) = if apng {
+        let n = png.frame_count();
+        (png.finish(), n)
+    } else {
+        let n = gif.frame_count();
+        (gif.finish(), n)
+    };
+    match fs::write(out, data) {
+        Ok(()) => {
+            println!("Recorded: {} ({} frames, {}x{})", out, count, sw, sh);
+            0
+        }
+        Err(e) => {
+            eprintln!("Record error: {}: {}", out, e);
+            1
+        }
+    }
+}
+
+/// Frames of LCD ghosting accumulated before a `screenshot --lcd` capture
+const SCREENSHOT_GHOST_FRAMES: u32 = 30;
+
+/// `screenshot` subcommand: run headless to `--frame N` with scripted
+/// `--press F[-G]:button` inputs and save the screen as PNG. Exit code 0 on
+/// success.
+fn run_screenshot(args: &[String], arduboy: &mut Arduboy, lcd: bool) -> i32 {
+    let opt = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1));
+    let frames: u32 = opt("--frame").and_then(|s| s.parse().ok()).unwrap_or(120);
+    let out = opt("--out").map(|s| s.as_str()).unwrap_or("screenshot.png");
+    let scale: usize = opt("--scale").and_then(|s| s.parse().ok()).unwrap_or(1).clamp(1, 16);
+
+    let presses = match press_schedule(args) {
+        Ok(p) => p,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let native = native_export(args, arduboy);
+    let (fw, fh) = screen_dims(arduboy);
+    let (mut sw, mut sh) = (fw * scale, fh * scale);
+    let mut ghost = vec![(0.0f32, 0.0f32, 0.0f32); fw * fh];
+    let mut lcd_buf = vec![0u32; sw * sh];
+    for frame in 0..frames {
+        apply_presses(arduboy, &presses, frame);
+        arduboy.run_frame();
+        print_core_messages(arduboy);
+        arduboy.breakpoint_hit = false;
+        if lcd && frame + SCREENSHOT_GHOST_FRAMES >= frames {
+            let is_pcd = matches!(arduboy.display_type, DisplayType::Pcd8544);
+            render_lcd_effect(&screen_pixels(arduboy).0, fw, scale, is_pcd, &mut ghost, &mut lcd_buf, sw);
+        }
+    }
+
+    let result = if let Some((px, w, h)) = native_pixels(arduboy).filter(|_| native) {
+        // 84×48 in LCD colors, without the framebuffer border
+        (sw, sh) = (w * scale, h * scale);
+        let scaled: Vec<bool> = (0..sw * sh).map(|i| px[(i / sw / scale) * w + (i % sw) / scale]).collect();
+        let [off, on] = arduboy_core::pcd8544::PALETTE;
+        fs::write(out, arduboy_core::png::encode_png_palette(sw as u32, sh as u32, &scaled, off, on))
+            .map_err(|e| format!("{}: {}", out, e))
+    } else if lcd {
+        let (lcd_buf, sw, sh) = arduboy.rotation.rotate(&lcd_buf, sw, sh);
+        let rgba: Vec<u8> = lcd_buf.iter()
+            .flat_map(|&c| [(c >> 16) as u8, (c >> 8) as u8, c as u8, 0xFF])
+            .collect();
+        fs::write(out, arduboy_core::png::encode_png(sw as u32, sh as u32, &rgba))
+            .map_err(|e| format!("{}: {}", out, e))
+    } else {
+        save_screenshot_png(arduboy, out, scale)
+    };
+    match result {
+        Ok(()) => {
+            println!("Screenshot: {} (frame {}, {}x{})", out, frames, sw, sh);
+            0
+        }
+        Err(e) => {
+            eprintln!("Screenshot error: {}", e);
+            1
+        }
+    }
+}
+
+/// Measure input latency (`--latency`); exit code 0 if every trial got a response.
+fn run_latency(args: &[String], arduboy: &mut Arduboy) -> i32 {
+    use arduboy_core::latency::{self, LatencyConfig};
+    let opt = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1));
+    let mut cfg = LatencyConfig::default();
+    if let Some(n) = opt("--latency").and_then(|s| s.parse().ok()) {
+        cfg.trials = n;
+    }
+    if let Some(b) = opt("--latency-button") {
+        match arduboy_core::scenario::parse_button(b) {
+            Ok(b) => cfg.button = b,
+            Err(e) => {
+                eprintln!("--latency-button: {}", e);
+                return 1;
+            }
+        }
+    }
+    cfg.marker = opt("--latency-marker").map(|s| s.as_bytes().to_vec());
+    let warmup: u32 = opt("--latency-warmup").and_then(|s| s.parse().ok()).unwrap_or(180);
+
+    for _ in 0..warmup {
+        arduboy.run_frame();
+    }
+    match latency::measure(arduboy, &cfg) {
+        Ok(report) => {
+            println!("{}", report.format());
+            if report.missed() == 0 { 0 } else { 1 }
+        }
+        Err(e) => {
+            eprintln!("Latency: {}", e);
+            1
+        }
+    }
+}
+
+/// `--remote`: run in real time with no window, streaming every frame to
+/// remote viewers and taking their buttons (see `arduboy_core::remote`).
+/// Runs until `--frames N` or a serial RPC quit.
+fn run_remote(args: &[String], arduboy: &mut Arduboy, serial: &mut SerialIo, addr: &str) -> Option<i32> {
+    use arduboy_core::remote::RemoteServer;
+    use arduboy_core::{RealtimeSync, FRAME_CYCLES};
+
+    let mut server = match RemoteServer::bind(addr) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("--remote {}: {}", addr, e);
+            return Some(1);
+        }
+    };
+    let bound = server.local_addr().map(|a| a.to_string()).unwrap_or_else(|_| addr.to_string());
+    eprintln!("Remote display: http://{}/ (raw TCP clients send \"watch\")", bound);
+    let frames: Option<u32> = args.iter()
+        .position(|a| a == "--frames")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|s| s.parse().ok());
+    let mut sync = RealtimeSync::at_frame_rate(60);
+    let mut ran = 0u32;
+    loop {
+        server.service(arduboy);
+        for m in server.take_messages() {
+            eprintln!("{}", m);
+        }
+        for _ in 0..sync.frames_due(Instant::now()).max(1) {
+            arduboy.run_frame();
+            print_core_messages(arduboy);
+            arduboy.breakpoint_hit = false;
+            sync.ran(FRAME_CYCLES);
+            server.publish(arduboy, arduboy.frame_count());
+            ran += 1;
+            if frames.is_some_and(|n| ran >= n) {
+                return None;
+            }
+        }
+        for cmd in serial.service(arduboy) {
+            match cmd {
+                RpcCommand::Quit(code) => return Some(code),
+                RpcCommand::Checkpoint(name) => println!("Checkpoint: {} (frame {})", name, arduboy.frame_count()),
+                RpcCommand::Screenshot(_) | RpcCommand::SetTitle(_) => {}
+            }
+        }
+        std::thread::sleep(sync.sleep_time(Instant::now()));
+    }
+}
+
+fn run_headless(args: &[String], arduboy: &mut Arduboy, serial: &mut SerialIo) -> Option<i32> {
     let frames: usize = args.iter()
         .position(|a| a == "--frames")
         .and_then(|i| args.get(i + 1))
@@ -1853,21 +4590,35 @@ fn run_headless(args: &[String], arduboy: &mut Arduboy, serial_enabled: bool) {
             } else { i += 1; }
         }
     }
+    // Headless frame N is scheduler frame start + N
+    let start = arduboy.frame_count();
+    if let Some(pf) = press_frame {
+        arduboy.schedule(At::Frame(start + pf as u32), Action::Button(Button::A, true));
+        arduboy.schedule(At::Frame(start + pf as u32 + 5), Action::Button(Button::A, false));
+    }
+    for (i, _) in args.iter().enumerate().filter(|(_, a)| *a == "--at") {
+        let spec = args.get(i + 1).map(|s| s.as_str()).unwrap_or("");
+        match schedule::parse(spec) {
+            Ok((At::Frame(n), action)) => arduboy.schedule(At::Frame(start + n), action),
+            Ok((at, action)) => arduboy.schedule(at, action),
+            Err(e) => {
+                eprintln!("--at: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
     if debug {
         if let Some(pf) = press_frame { println!("Press A on frame {}", pf); }
         println!("Running {} frames...", frames);
     }
     for frame in 0..frames {
-        if let Some(pf) = press_frame {
-            if frame == pf { arduboy.set_button(Button::A, true); if debug { println!("  >> A pressed"); } }
-            else if frame == pf + 5 { arduboy.set_button(Button::A, false); if debug { println!("  >> A released"); } }
-        }
         arduboy.display.dbg_reset_counters();
         arduboy.pcd8544.dbg_reset_counters();
         arduboy.timer0.dbg_reset_counters();
         let t0 = arduboy.cpu.tick;
         let px0 = pixel_count(arduboy);
         arduboy.run_frame();
+        print_core_messages(arduboy);
         let t1 = arduboy.cpu.tick;
         if arduboy.breakpoint_hit {
             println!("*** Break: {} (frame {}) ***\n{}", arduboy.disasm_at_pc(), frame+1, arduboy.dump_regs());
@@ -1883,9 +4634,34 @@ fn run_headless(args: &[String], arduboy: &mut Arduboy, serial_enabled: bool) {
                     wh.old_val, wh.new_val);
             }
         }
-        if serial_enabled {
-            let out = arduboy.take_serial_output();
-            if !out.is_empty() { let _ = std::io::stderr().write_all(&out); let _ = std::io::stderr().flush(); }
+        for marker in arduboy.take_markers() {
+            if let Some(file) = marker.name.strip_prefix("screenshot") {
+                let f = file.strip_prefix('=').map(str::to_string)
+                    .unwrap_or_else(|| format!("screenshot_f{:06}.png", frame + 1));
+                match save_screenshot_png(arduboy, &f, 1) {
+                    Ok(()) => println!("Screenshot: {} (frame {})", f, frame + 1),
+                    Err(e) => eprintln!("Screenshot error: {}", e),
+                }
+            } else {
+                println!("Marker: {} (frame {}, tick {})", marker.name, frame + 1, marker.tick);
+            }
+        }
+        for cmd in serial.service(arduboy) {
+            match cmd {
+                RpcCommand::Quit(code) => {
+                    if debug { println!("  >> quit ({}) at frame {}", code, frame + 1); }
+                    return Some(code);
+                }
+                RpcCommand::Screenshot(name) => {
+                    let f = name.unwrap_or_else(|| format!("screenshot_f{:06}.png", frame + 1));
+                    match save_screenshot_png(arduboy, &f, 1) {
+                        Ok(()) => println!("Screenshot: {} (frame {})", f, frame + 1),
+                        Err(e) => eprintln!("Screenshot error: {}", e),
+                    }
+                }
+                RpcCommand::SetTitle(t) => println!("Title: {}", t),
+                RpcCommand::Checkpoint(name) => println!("Checkpoint: {} (frame {})", name, frame + 1),
+            }
         }
         if debug {
             let lit = pixel_count(arduboy);
@@ -1911,6 +4687,7 @@ fn run_headless(args: &[String], arduboy: &mut Arduboy, serial_enabled: bool) {
         }
     }
     if debug { println!("\nDone. {} cycles.", arduboy.cpu.tick); }
+    None
 }
 
 fn pixel_count(arduboy: &Arduboy) -> usize {
@@ -1921,7 +4698,7 @@ fn pixel_count(arduboy: &Arduboy) -> usize {
 fn print_display(arduboy: &Arduboy) {
     let fb = arduboy.framebuffer_rgba();
     let lit = (0..SCREEN_WIDTH * SCREEN_HEIGHT).filter(|&i| fb[i * 4] > 0).count();
-    println!("  ({} px lit)", lit);
+    println!("  ({} px lit, fb_hash 0x{:016x})", lit, arduboy.framebuffer_hash());
     for y in (0..SCREEN_HEIGHT).step_by(2) {
         let mut l = String::with_capacity(SCREEN_WIDTH + 4);
         l.push_str("  |");
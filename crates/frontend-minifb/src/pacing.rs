@@ -0,0 +1,122 @@
+//! Frame pacing between the host display and the emulated game clock.
+//!
+//! The game always advances at [`EMU_FPS`] frames per second of wall-clock
+//! time, while the window is presented at the host refresh rate given with
+//! `--refresh` (e.g. 75, 120 or 144 Hz). Each presented frame asks the pacer
+//! how many emulated frames are due:
+//!
+//! | Host rate | Emulated frames per presented frame |
+//! |-----------|-------------------------------------|
+//! | 60 Hz     | 1 (lockstep, as before)             |
+//! | 120 Hz    | 0, 1, 0, 1, ...                     |
+//! | 144 Hz    | 0, 0, 1, 0, 1, ... (5 per 12)       |
+//! | 50 Hz     | 1, 1, 1, 1, 2, ...                  |
+//!
+//! The cadence follows the nominal host period so it stays regular, and a
+//! small correction pulls it back toward real time (kept by the core's
+//! [`RealtimeSync`]) when the host's actual rate differs from the one
+//! requested. [`FramePacer::phase`] tells how far the display is into the
+//! next emulated frame, for optional blending of the last two frames
+//! (`--frame-blend`).
+//!
+//! When the host cannot keep up, the [`OverrunPolicy`] (`--overrun`)
+//! decides whether the game slows down (one emulated frame per presented
+//! frame at most, the default at 60 Hz) or catches up with extra frames
+//! (the default at other rates). Free-running starts in unlimited mode.
+
+use arduboy_core::{OverrunPolicy, RealtimeSync, FRAME_CYCLES};
+use std::time::Instant;
+
+/// Emulated frames per second of game time
+pub const EMU_FPS: u32 = 60;
+
+/// Most emulated frames run for one presented frame
+const MAX_CATCH_UP: f64 = 4.0;
+
+/// Fraction of the real-time drift corrected per presented frame
+const DRIFT_GAIN: f64 = 0.02;
+
+/// Decides how many emulated frames to run per presented host frame.
+pub struct FramePacer {
+    host_hz: u32,
+    policy: OverrunPolicy,
+    /// Real-time reference for drift correction
+    sync: RealtimeSync,
+    /// Fractional frames owed, in `[0, 1)` after [`FramePacer::advance`]
+    acc: f64,
+}
+
+impl FramePacer {
+    /// `policy` defaults to slowdown in lockstep and catch-up otherwise.
+    pub fn new(host_hz: u32, policy: Option<OverrunPolicy>) -> Self {
+        let host_hz = host_hz.max(1);
+        let policy = policy.unwrap_or(match host_hz {
+            EMU_FPS => OverrunPolicy::Slowdown,
+            _ => OverrunPolicy::default(),
+        });
+        let mut sync = RealtimeSync::at_frame_rate(EMU_FPS);
+        sync.policy = policy;
+        FramePacer { host_hz, policy, sync, acc: 0.0 }
+    }
+
+    pub fn policy(&self) -> OverrunPolicy {
+        self.policy
+    }
+
+    /// Refresh rate to request from the window.
+    pub fn host_fps(&self) -> usize {
+        self.host_hz as usize
+    }
+
+    /// True when the host presents exactly one emulated frame per refresh.
+    pub fn lockstep(&self) -> bool {
+        self.host_hz == EMU_FPS
+    }
+
+    /// Emulated frames due for the host frame presented at `now`.
+    pub fn advance(&mut self, now: Instant) -> u32 {
+        if self.lockstep() {
+            if self.policy == OverrunPolicy::Slowdown {
+                return 1;
+            }
+            // Catch-up: one frame per refresh plus whole frames still owed
+            let due = self.sync.frames_due(now).clamp(1, MAX_CATCH_UP as u32);
+            self.sync.ran(due as u64 * FRAME_CYCLES);
+            return due;
+        }
+        self.acc += EMU_FPS as f64 / self.host_hz as f64;
+        // Stalls (window drag, slow host) make the sync restart its clock
+        // and report no lag, so the backlog is dropped rather than replayed
+        let drift = self.sync.lag(now) / FRAME_CYCLES as f64 - self.acc;
+        self.acc = (self.acc + drift * DRIFT_GAIN).max(0.0);
+        let most = if self.policy == OverrunPolicy::Slowdown { 1.0 } else { MAX_CATCH_UP };
+        let due = self.acc.floor().min(most);
+        self.acc -= self.acc.floor();
+        self.sync.ran(due as u64 * FRAME_CYCLES);
+        due as u32
+    }
+
+    /// Progress toward the next emulated frame, 0.0–1.0.
+    pub fn phase(&self) -> f32 {
+        self.acc as f32
+    }
+
+    /// Forget timing history, e.g. after a pause or fast-forward.
+    pub fn reset(&mut self) {
+        self.sync.reset();
+        self.acc = 0.0;
+    }
+}
+
+/// Blend two frames of 0x00RRGGBB pixels: `t` = 0.0 gives `prev`, 1.0 `cur`.
+pub fn blend_frames(prev: &[u32], cur: &[u32], t: f32) -> Vec<u32> {
+    let w = (t.clamp(0.0, 1.0) * 256.0) as u32;
+    prev.iter().zip(cur).map(|(&p, &c)| {
+        let mix = |shift: u32| {
+            let a = (p >> shift) & 0xFF;
+            let b = (c >> shift) & 0xFF;
+            ((a * (256 - w) + b * w) >> 8) << shift
+        };
+        mix(16) | mix(8) | mix(0)
+    }).collect()
+}
@@ -0,0 +1,130 @@
+//! Host serial-port bridge for the emulated USB CDC / USART.
+//!
+//! Connects the game's serial port to a host device so external tools
+//! (Arduino Serial Monitor, companion apps, terminal programs) can talk to
+//! the emulated game as they would to real hardware:
+//!
+//! - `--serial-port pty` — create a fresh pseudo-terminal (Linux/macOS) and
+//!   print its slave path (e.g. `/dev/pts/7`) for the host tool to open
+//! - `--serial-port <path>` — open an existing device, e.g. one end of a
+//!   `socat` pty pair or a com0com virtual COM port (`COM10` on Windows)
+//!
+//! Baud rate settings are ignored: bytes are passed through unchanged.
+//! Host input is read on a background thread and handed to the core once
+//! per frame via [`Arduboy::push_serial_input`].
+
+use arduboy_core::Arduboy;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+/// An open bridge between the emulated serial port and a host device.
+pub struct SerialBridge {
+    writer: File,
+    rx: Receiver<Vec<u8>>,
+    /// Device path the host tool should open (pty slave or the given path)
+    pub name: String,
+}
+
+impl SerialBridge {
+    /// Open a bridge. `spec` is `pty` or a device path.
+    pub fn open(spec: &str) -> Result<Self, String> {
+        let (file, name) = if spec.eq_ignore_ascii_case("pty") {
+            open_pty()?
+        } else {
+            let path = device_path(spec);
+            let file = OpenOptions::new().read(true).write(true).open(&path)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            (file, path)
+        };
+        let mut reader = file.try_clone().map_err(|e| format!("{}: {}", name, e))?;
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 256];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(n) if n > 0 => {
+                        if tx.send(buf[..n].to_vec()).is_err() { break; }
+                    }
+                    // No data yet, or no client attached to the pty (EIO):
+                    // poll again shortly.
+                    Ok(_) | Err(_) => std::thread::sleep(Duration::from_millis(5)),
+                }
+            }
+        });
+        Ok(SerialBridge { writer: file, rx, name })
+    }
+
+    /// Deliver bytes received from the host to the emulated serial port.
+    pub fn poll_input(&mut self, arduboy: &mut Arduboy) {
+        while let Ok(data) = self.rx.try_recv() {
+            arduboy.push_serial_input(&data);
+        }
+    }
+
+    /// Send game output to the host.
+    ///
+    /// A full pty buffer (nobody reading) drops output rather than stalling.
+    pub fn send(&mut self, data: &[u8]) {
+        if !data.is_empty() && self.writer.write_all(data).is_ok() {
+            let _ = self.writer.flush();
+        }
+    }
+}
+
+/// Windows needs the `\\.\` prefix for COM10 and above; harmless for COM1–9.
+#[cfg(windows)]
+fn device_path(spec: &str) -> String {
+    if spec.len() > 3 && spec[..3].eq_ignore_ascii_case("COM") && !spec.starts_with('\\') {
+        format!("\\\\.\\{}", spec)
+    } else {
+        spec.to_string()
+    }
+}
+
+#[cfg(not(windows))]
+fn device_path(spec: &str) -> String {
+    spec.to_string()
+}
+
+/// Create a raw, non-blocking pseudo-terminal master. Returns the master
+/// file and the slave device path.
+#[cfg(unix)]
+fn open_pty() -> Result<(File, String), String> {
+    use std::os::unix::io::FromRawFd;
+    // Safety: plain libc calls on a descriptor we own; `ptsname` is only
+    // called here, at startup, before any other pty is opened.
+    unsafe {
+        let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if fd < 0 {
+            return Err(format!("posix_openpt: {}", std::io::Error::last_os_error()));
+        }
+        if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+            let e = std::io::Error::last_os_error();
+            libc::close(fd);
+            return Err(format!("pty setup: {}", e));
+        }
+        let name_ptr = libc::ptsname(fd);
+        if name_ptr.is_null() {
+            let e = std::io::Error::last_os_error();
+            libc::close(fd);
+            return Err(format!("ptsname: {}", e));
+        }
+        let name = std::ffi::CStr::from_ptr(name_ptr).to_string_lossy().into_owned();
+        // Raw mode: no echo, no line discipline, 8-bit clean
+        let mut tio: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut tio) == 0 {
+            libc::cfmakeraw(&mut tio);
+            libc::tcsetattr(fd, libc::TCSANOW, &tio);
+        }
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK);
+        Ok((File::from_raw_fd(fd), name))
+    }
+}
+
+#[cfg(not(unix))]
+fn open_pty() -> Result<(File, String), String> {
+    Err("pty is not available on this platform; pass a COM port instead (e.g. COM10)".into())
+}
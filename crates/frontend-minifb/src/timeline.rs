@@ -0,0 +1,114 @@
+//! Rewind timeline scrubber.
+//!
+//! Tab opens a strip of rewind snapshot thumbnails along the bottom of the
+//! window and pauses the game. The selected snapshot is shown full size:
+//!
+//! | Key | Action |
+//! |-----|--------|
+//! | Left / Right | Step one snapshot (Shift: 10) |
+//! | Enter | Resume from the selected snapshot, dropping newer ones |
+//! | Tab | Close and return to where the game was |
+//!
+//! The rightmost position is "now", the state when the timeline opened.
+
+use arduboy_core::snapshot::{RewindBuffer, Snapshot};
+
+/// Colors (`0xRRGGBB`), matching the input viewer
+const BG: u32 = 0x101418;
+const TRACK: u32 = 0x283038;
+const MARK: u32 = 0xF0E040;
+
+/// Open timeline: selected position and the state to return to.
+pub struct Timeline {
+    /// 0 = oldest snapshot, `rewind.len()` = now
+    pub cursor: usize,
+    resume: Snapshot,
+}
+
+impl Timeline {
+    /// Open at "now", remembering `resume` for cancel.
+    pub fn open(rewind: &RewindBuffer, resume: Snapshot) -> Self {
+        Timeline { cursor: rewind.len(), resume }
+    }
+
+    /// Move the cursor by `delta` positions; returns the snapshot to show.
+    pub fn step(&mut self, rewind: &RewindBuffer, delta: isize) -> Snapshot {
+        self.cursor = self.cursor.saturating_add_signed(delta).min(rewind.len());
+        self.selected(rewind)
+    }
+
+    /// Snapshot at the cursor ("now" when past the newest entry).
+    pub fn selected(&self, rewind: &RewindBuffer) -> Snapshot {
+        rewind.get(self.cursor).unwrap_or_else(|| self.resume.clone())
+    }
+
+    /// State when the timeline was opened.
+    pub fn resume(&self) -> &Snapshot {
+        &self.resume
+    }
+
+    /// Seconds between the cursor and "now" at 60 fps.
+    pub fn seconds_back(&self, rewind: &RewindBuffer) -> f32 {
+        let frame = rewind.frame_at(self.cursor).unwrap_or(self.resume.frame);
+        self.resume.frame.saturating_sub(frame) as f32 / 60.0
+    }
+
+    /// Draw the thumbnail strip and position bar at the bottom of a
+    /// `buf_w`×`buf_h` buffer, `unit` pixels per thumbnail pixel.
+    pub fn render(&self, rewind: &RewindBuffer, buf: &mut [u32], buf_w: usize, buf_h: usize, unit: usize) {
+        let unit = unit.max(1);
+        let Some(thumb) = rewind.thumbnail(0) else { return };
+        let (tw, th) = (thumb.width * unit, thumb.height * unit);
+        let strip_h = th + unit * 5;
+        if tw == 0 || strip_h > buf_h {
+            return;
+        }
+        let y0 = buf_h - strip_h;
+        let mut rect = |x: usize, y: usize, w: usize, h: usize, color: u32| {
+            for py in y..(y + h).min(buf_h) {
+                for px in x..(x + w).min(buf_w) {
+                    buf[py * buf_w + px] = color;
+                }
+            }
+        };
+        rect(0, y0, buf_w, strip_h, BG);
+
+        // Thumbnails centered on the cursor; "now" is an empty slot
+        let pitch = tw + unit * 2;
+        let slots = buf_w / pitch;
+        if slots == 0 {
+            return;
+        }
+        let slots = slots - (1 - slots % 2);
+        let first = self.cursor as isize - (slots / 2) as isize;
+        let x_start = (buf_w - slots * pitch) / 2 + unit;
+        let ty = y0 + unit;
+        for slot in 0..slots {
+            let idx = first + slot as isize;
+            if idx < 0 || idx as usize > rewind.len() {
+                continue;
+            }
+            let x = x_start + slot * pitch;
+            if idx as usize == self.cursor {
+                rect(x - unit, ty - unit, tw + unit * 2, th + unit * 2, MARK);
+            }
+            match rewind.thumbnail(idx as usize) {
+                Some(t) => {
+                    for (i, &l) in t.pixels.iter().enumerate() {
+                        let (px, py) = (i % t.width, i / t.width);
+                        let l = l as u32;
+                        rect(x + px * unit, ty + py * unit, unit, unit, (l << 16) | (l << 8) | l);
+                    }
+                }
+                None => rect(x, ty, tw, th, TRACK),
+            }
+        }
+
+        // Position of the cursor within the whole buffer
+        let bar_y = ty + th + unit * 2;
+        rect(unit, bar_y, buf_w - unit * 2, unit, TRACK);
+        let span = (buf_w - unit * 3) as f32;
+        let pos = self.cursor as f32 / rewind.len().max(1) as f32;
+        rect(unit + (pos * span) as usize, bar_y, unit, unit, MARK);
+    }
+}